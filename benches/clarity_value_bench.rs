@@ -0,0 +1,63 @@
+#[macro_use]
+extern crate criterion;
+extern crate blockstack_lib;
+
+use blockstack_lib::vm::database::{ClaritySerializable, MarfedKV, SideStore, SqliteConnection};
+use blockstack_lib::vm::representations::ClarityName;
+use blockstack_lib::vm::types::{PrincipalData, StandardPrincipalData, TupleData, TypeSignature, Value};
+
+use criterion::Criterion;
+use std::convert::TryFrom;
+
+/// A map value like the ones SIMPLE_TOKENS and the boot contracts store
+fn tuple_value() -> Value {
+    let fields = vec![
+        ("balance", Value::UInt(1000000)),
+        ("owner", Value::Principal(PrincipalData::Standard(StandardPrincipalData(26, [7u8; 20])))),
+        ("memo", Value::buff_from(vec![0xab; 32]).unwrap()),
+        ("locked", Value::Bool(false)),
+    ];
+    Value::Tuple(TupleData::from_data(fields.into_iter()
+                                      .map(|(name, value)| (ClarityName::try_from(name.to_string()).unwrap(), value))
+                                      .collect()).unwrap())
+}
+
+fn list_value() -> Value {
+    Value::list_from((0..100).map(|i| Value::Int(i)).collect()).unwrap()
+}
+
+/// Put a value into the side store and read it back, the way a `map-set` followed by a
+///   `map-get?` does.  `encode` and `decode` turn the value into and out of its stored bytes.
+fn side_store_put_get<E, D>(store: &mut SqliteConnection, value: &Value, encode: E, decode: D) -> Value
+where E: Fn(&Value) -> Vec<u8>, D: Fn(&[u8]) -> Value {
+    let stored = encode(value);
+    let key = MarfedKV::make_marf_value(&stored).to_hex();
+    store.put(&key, &stored);
+    let fetched = store.get(&key).unwrap();
+    decode(&fetched)
+}
+
+pub fn side_store_value_benchmark(c: &mut Criterion) {
+    for (name, value) in vec![("uint", Value::UInt(1000000)), ("tuple", tuple_value()), ("list", list_value())].into_iter() {
+        let expected = TypeSignature::type_of(&value);
+        {
+            // what the side store did before values were stored as bytes: hex encode on every
+            //   put, and hex decode on every get
+            let value = value.clone();
+            let expected = expected.clone();
+            let mut store = SqliteConnection::memory().unwrap();
+            c.bench_function(&format!("side_store_{}_hex", name),
+                             move |b| b.iter(|| side_store_put_get(&mut store, &value,
+                                                                    |v| v.serialize().into_bytes(),
+                                                                    |bytes| Value::deserialize(std::str::from_utf8(bytes).unwrap(), &expected))));
+        }
+        let mut store = SqliteConnection::memory().unwrap();
+        c.bench_function(&format!("side_store_{}_bytes", name),
+                         move |b| b.iter(|| side_store_put_get(&mut store, &value,
+                                                                |v| v.serialize_to_store(),
+                                                                |bytes| Value::deserialize_from_store(bytes, &expected))));
+    }
+}
+
+criterion_group!(benches, side_store_value_benchmark);
+criterion_main!(benches);
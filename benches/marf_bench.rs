@@ -4,7 +4,7 @@ extern crate blockstack_lib;
 extern crate rand;
 
 use blockstack_lib::chainstate::burn::BlockHeaderHash;
-use blockstack_lib::chainstate::stacks::index::{marf::MARF, storage::TrieFileStorage, MARFValue};
+use blockstack_lib::chainstate::stacks::index::{marf::MARF, storage::{TrieFileStorage, TrieStorageStats}, MARFValue};
 
 use criterion::Criterion;
 use rand::prelude::*;
@@ -71,6 +71,67 @@ fn benchmark_marf_usage(filename: &str, blocks: u32, writes_per_block: u32, read
     }
 }
 
+/// Build a chain of blocks, and at each block, mix writes with reads of keys written in earlier
+/// blocks -- most of which are only reachable through back-pointers into older tries.  Returns the
+/// storage counters accumulated over the whole run.
+fn benchmark_marf_read_write_mix(filename: &str, blocks: u32, writes_per_block: u32, reads_per_block: u32, cache_capacity: usize) -> TrieStorageStats {
+    let mut f = new_overwrite(filename);
+    f.set_node_cache_capacity(cache_capacity);
+
+    let mut block_header = BlockHeaderHash::from_bytes(&[0u8; 32]).unwrap();
+    let mut marf = MARF::from_storage(f);
+    marf.begin(&TrieFileStorage::block_sentinel(), &block_header).unwrap();
+
+    let mut rng = rand::thread_rng();
+    let mut values = vec![];
+    let mut stats = TrieStorageStats::default();
+
+    for i in 0..blocks {
+        for _k in 0..writes_per_block {
+            let key: u64 = rng.gen();
+            let key = key.to_string();
+            let mut value = [0u8; 40];
+            rng.fill_bytes(&mut value);
+            marf.insert(&key, MARFValue(value.clone())).unwrap();
+            values.push((key, MARFValue(value)));
+        }
+
+        for _k in 0..reads_per_block {
+            let (key, value) = values.as_slice().choose(&mut rng).unwrap();
+            assert_eq!(marf.get(&block_header, key).unwrap().unwrap(), *value);
+        }
+
+        let mut next_block_header = (i+1).to_le_bytes().to_vec();
+        next_block_header.resize(32, 0);
+        let next_block_header = BlockHeaderHash::from_bytes(next_block_header.as_slice()).unwrap();
+
+        marf.commit().unwrap();
+        marf.begin(&block_header, &next_block_header).unwrap();
+        block_header = next_block_header;
+
+        let block_stats = marf.borrow_storage_backend().stats();
+        stats.reads += block_stats.reads;
+        stats.writes += block_stats.writes;
+        stats.cache_hits += block_stats.cache_hits;
+        stats.cache_misses += block_stats.cache_misses;
+    }
+    stats
+}
+
+pub fn cache_hit_rate_benchmark(c: &mut Criterion) {
+    for cache_capacity in [0, 1024, 32768].iter() {
+        let cache_capacity = *cache_capacity;
+
+        // report the hit rate once, outside of the timing loop
+        let stats = benchmark_marf_read_write_mix("/tmp/foo.bar.z", 20, 1000, 5000, cache_capacity);
+        println!("marf_mix_20b_1kW_5kR cache capacity {}: {} reads, {} cache hits, {} cache misses, hit rate {:.3}",
+                 cache_capacity, stats.reads, stats.cache_hits, stats.cache_misses, stats.cache_hit_rate());
+
+        c.bench_function(&format!("marf_mix_20b_1kW_5kR_cache_{}", cache_capacity),
+                         move |b| b.iter(|| benchmark_marf_read_write_mix("/tmp/foo.bar.z", 20, 1000, 5000, cache_capacity)));
+    }
+}
+
 pub fn basic_usage_benchmark(c: &mut Criterion) {
     c.bench_function("marf_usage_1b_10kW_0kR", |b| b.iter(|| benchmark_marf_usage("/tmp/foo.bar.z", 1, 10000, 0, false)));
     c.bench_function("marf_usage_10b_1kW_2kR", |b| b.iter(|| benchmark_marf_usage("/tmp/foo.bar.z", 10, 1000, 2000, false)));
@@ -81,5 +142,5 @@ pub fn basic_usage_benchmark(c: &mut Criterion) {
 pub fn scaling_read_ratio(_c: &mut Criterion) {
 }
 
-criterion_group!(benches, basic_usage_benchmark);
+criterion_group!(benches, basic_usage_benchmark, cache_hit_rate_benchmark);
 criterion_main!(benches);
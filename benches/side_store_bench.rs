@@ -0,0 +1,122 @@
+#[macro_use]
+extern crate criterion;
+extern crate blockstack_lib;
+
+use blockstack_lib::chainstate::burn::BlockHeaderHash;
+use blockstack_lib::chainstate::stacks::index::storage::{TrieFileStorage, TrieStorageLayout};
+use blockstack_lib::vm::ast;
+use blockstack_lib::vm::clarity::ClarityInstance;
+use blockstack_lib::vm::database::{MarfedKV, SideStoreType, NULL_HEADER_DB};
+use blockstack_lib::vm::types::{PrincipalData, QualifiedContractIdentifier, StandardPrincipalData, Value};
+
+use criterion::Criterion;
+use std::fs;
+
+// the token contract from vm::tests::contracts
+const SIMPLE_TOKENS: &str = "(define-map tokens ((account principal)) ((balance uint)))
+         (define-read-only (my-get-token-balance (account principal))
+            (default-to u0 (get balance (map-get? tokens (tuple (account account))))))
+         (define-private (token-credit! (account principal) (amount uint))
+            (if (<= amount u0)
+                (err \"must be positive\")
+                (let ((current-amount (my-get-token-balance account)))
+                  (begin
+                    (map-set tokens (tuple (account account))
+                                       (tuple (balance (+ amount current-amount))))
+                    (ok 0)))))
+         (define-public (token-transfer (to principal) (amount uint))
+          (let ((balance (my-get-token-balance tx-sender)))
+             (if (or (> amount balance) (<= amount u0))
+                 (err \"not enough balance\")
+                 (begin
+                   (map-set tokens (tuple (account tx-sender))
+                                      (tuple (balance (- balance amount))))
+                   (token-credit! to amount)))))
+         (begin (token-credit! 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR u1000000)
+                (token-credit! 'SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G u200))";
+
+fn new_overwrite(dir_path: &str, side_store_type: SideStoreType) -> ClarityInstance {
+    match fs::metadata(dir_path) {
+        Ok(_) => {
+            fs::remove_dir_all(dir_path).unwrap();
+        },
+        Err(_e) => {}
+    };
+    ClarityInstance::new(MarfedKV::open_with_side_store(dir_path, None, side_store_type, TrieStorageLayout::FilePerBlock).unwrap())
+}
+
+fn block_header(i: u32) -> BlockHeaderHash {
+    let mut bytes = (i + 1).to_le_bytes().to_vec();
+    bytes.resize(32, 0);
+    BlockHeaderHash::from_bytes(bytes.as_slice()).unwrap()
+}
+
+/// Deploy the token contract, and then over a chain of blocks, send tokens from the funded
+///   account to a rotating set of recipients.  Each transfer writes two map entries.
+fn benchmark_token_transfers(dir_path: &str, side_store_type: SideStoreType, blocks: u32, transfers_per_block: u32) {
+    let mut clarity = new_overwrite(dir_path, side_store_type);
+    let sender = PrincipalData::from(PrincipalData::parse_standard_principal("SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR").unwrap());
+    let contract_identifier = QualifiedContractIdentifier::local("tokens").unwrap();
+
+    let mut parent = TrieFileStorage::block_sentinel();
+    for i in 0..blocks {
+        let next = block_header(i);
+        let mut block = clarity.begin_block(&parent, &next, &NULL_HEADER_DB);
+
+        if i == 0 {
+            let contract_ast = ast::build_ast(&contract_identifier, SIMPLE_TOKENS, &mut ()).unwrap();
+            block.initialize_smart_contract(&contract_identifier, &contract_ast, SIMPLE_TOKENS, |_, _| false).unwrap();
+        }
+
+        for k in 0..transfers_per_block {
+            let recipient = PrincipalData::Standard(StandardPrincipalData(26, [(k % 251) as u8; 20]));
+            block.run_contract_call(&sender, &contract_identifier, "token-transfer",
+                                    &[Value::Principal(recipient), Value::UInt(1)], |_, _| false).unwrap();
+        }
+
+        block.commit_to_block(&next);
+        parent = next;
+    }
+}
+
+/// Over a chain of blocks, deploy many small contracts.  Contract analysis and the contract
+///   itself are stored as side-store metadata.
+fn benchmark_contract_deploys(dir_path: &str, side_store_type: SideStoreType, blocks: u32, contracts_per_block: u32) {
+    let mut clarity = new_overwrite(dir_path, side_store_type);
+    let mut parent = TrieFileStorage::block_sentinel();
+    for i in 0..blocks {
+        let next = block_header(i);
+        let mut block = clarity.begin_block(&parent, &next, &NULL_HEADER_DB);
+
+        for k in 0..contracts_per_block {
+            let contract_identifier = QualifiedContractIdentifier::local(&format!("counter-{}-{}", i, k)).unwrap();
+            let contract = "(define-data-var count int 0)
+                            (define-public (incr) (begin (var-set count (+ 1 (var-get count))) (ok (var-get count))))";
+            let (contract_ast, contract_analysis) = block.analyze_smart_contract(&contract_identifier, contract).unwrap();
+            block.initialize_smart_contract(&contract_identifier, &contract_ast, contract, |_, _| false).unwrap();
+            block.save_analysis(&contract_identifier, &contract_analysis).unwrap();
+        }
+
+        block.commit_to_block(&next);
+        parent = next;
+    }
+}
+
+pub fn token_transfer_benchmark(c: &mut Criterion) {
+    for (name, side_store_type) in [("sqlite", SideStoreType::Sqlite), ("log", SideStoreType::Log)].iter() {
+        let side_store_type = *side_store_type;
+        c.bench_function(&format!("side_store_{}_tokens_10b_500tx", name),
+                         move |b| b.iter(|| benchmark_token_transfers("/tmp/side_store_bench", side_store_type, 10, 500)));
+    }
+}
+
+pub fn contract_deploy_benchmark(c: &mut Criterion) {
+    for (name, side_store_type) in [("sqlite", SideStoreType::Sqlite), ("log", SideStoreType::Log)].iter() {
+        let side_store_type = *side_store_type;
+        c.bench_function(&format!("side_store_{}_contracts_10b_50c", name),
+                         move |b| b.iter(|| benchmark_contract_deploys("/tmp/side_store_bench", side_store_type, 10, 50)));
+    }
+}
+
+criterion_group!(benches, token_transfer_benchmark, contract_deploy_benchmark);
+criterion_main!(benches);
@@ -5,6 +5,9 @@
 #![allow(non_upper_case_globals)]
 
 extern crate blockstack_lib;
+extern crate serde;
+#[macro_use] extern crate serde_derive;
+extern crate serde_json;
 
 use std::{io, fs, env};
 use std::io::prelude::*;
@@ -16,12 +19,19 @@ use blockstack_lib::vm::{
     Value, ClarityName, ContractName, errors::RuntimeErrorType, errors::Error as ClarityError };
 use blockstack_lib::chainstate::stacks::{
     C32_ADDRESS_VERSION_MAINNET_SINGLESIG, C32_ADDRESS_VERSION_TESTNET_SINGLESIG,
+    C32_ADDRESS_VERSION_MAINNET_MULTISIG, C32_ADDRESS_VERSION_TESTNET_MULTISIG,
     StacksPrivateKey, TransactionSpendingCondition, TransactionAuth, TransactionVersion,
     StacksPublicKey, TransactionPayload, StacksTransactionSigner,
-    StacksTransaction, TransactionSmartContract, TransactionContractCall, StacksAddress, TokenTransferMemo };
+    StacksTransaction, TransactionSmartContract, TransactionContractCall, StacksAddress, TokenTransferMemo,
+    TransactionPostCondition, TransactionPostConditionMode, PostConditionPrincipal, AssetInfo,
+    FungibleConditionCode, NonfungibleConditionCode, TransactionAnchorMode, SinglesigHashMode, MultisigHashMode };
 use blockstack_lib::burnchains::Address;
 use blockstack_lib::address::AddressHashMode;
-use blockstack_lib::net::{Error as NetError, StacksMessageCodec};
+use blockstack_lib::net::{Error as NetError, StacksMessageCodec, TransactionSimulationData};
+use blockstack_lib::chainstate::burn::BlockHeaderHash;
+use blockstack_lib::chainstate::stacks::db::StacksChainState;
+use blockstack_lib::chainstate::stacks::Error as ChainstateError;
+use blockstack_lib::util::secp256k1::MessageSignature;
 
 const USAGE: &str = "blockstack-cli (options) [method] [args...]
 
@@ -34,12 +44,42 @@ This CLI has these methods:
   contract-call    used to generate and sign a contract-call transaction
   generate-sk      used to generate a secret key for transaction signing
   token-transfer   used to generate and sign a transfer transaction
+  simulate         used to dry-run a (possibly unsigned) transaction against a local chainstate
+  sponsor          used to sign an origin-signed sponsored transaction as its sponsor
+  multisig-address used to generate an M-of-N multisig address from N public keys
+  append-signature used to add the next co-signer's signature or public key to a multisig transaction
+  decode-tx        used to describe a (possibly unsigned) transaction in words
+  build            used to generate an unsigned transaction in a signing envelope, for offline signing
+  sign             used to sign a transaction in a signing envelope with a secret key file, offline
+  finalize         used to turn a fully-signed signing envelope into a broadcastable transaction
 
 For usage information on those methods, call `blockstack-cli [method] -h`
 
 `blockstack-cli` accepts flag options as well:
 
    --testnet       instruct the transaction generator to use a testnet version byte instead of MAINNET (default)
+   --sponsored     instruct the transaction generator to make a sponsored transaction, signed only by
+                   the origin. The sponsor must then sign it with `blockstack-cli sponsor`.
+   --multisig M    instruct the transaction generator to make an unsigned transaction whose origin is an
+                   M-of-N multisig address. The origin secret key argument is replaced by the N
+                   comma-separated public keys, in signing order. Each co-signer then adds their
+                   signature or public key, in that order, with `blockstack-cli append-signature`.
+   --p2wsh         use a P2WSH multisig address instead of P2SH (the default)
+   --post-condition PC
+                   attach the post-condition PC to the transaction. May be given more than once.
+                   PC is one of:
+                      stx:PRINCIPAL:CODE:AMOUNT      -- PRINCIPAL sends CODE AMOUNT microSTX
+                      ft:PRINCIPAL:ASSET:CODE:AMOUNT -- PRINCIPAL sends CODE AMOUNT units of ASSET
+                      nft:PRINCIPAL:ASSET:CODE:VALUE -- PRINCIPAL sends (or does not send) ASSET VALUE
+                   where PRINCIPAL is `origin`, a standard address, or ADDRESS.CONTRACT-NAME;
+                   ASSET is ADDRESS.CONTRACT-NAME::ASSET-NAME; CODE is one of eq, gt, gte, lt or
+                   lte for STX and fungible tokens, and sent or not-sent for non-fungible tokens;
+                   and VALUE is a Clarity expression (e.g. u1), or a serialized Clarity value if
+                   it starts with 0x. e.g. `--post-condition stx:origin:lte:1000`
+   --deny-mode     instruct the transaction generator to make a transaction that aborts if it moves
+                   any assets that its post-conditions do not mention. This is the default.
+   --allow-mode    instruct the transaction generator to make a transaction that may move assets
+                   that its post-conditions do not mention.
 
 ";
 
@@ -77,6 +117,87 @@ The transfer command generates and signs a STX transfer transaction. If successf
 this command outputs the hex string encoding of the transaction to stdout, and exits with
 code 0";
 
+const SIMULATE_USAGE: &str = "blockstack-cli (options) simulate [chainstate-dir] [chain-id] [index-block-hash] [transaction-hex]
+
+The simulate command runs a transaction on top of the given block in the chainstate at
+chainstate-dir, without keeping anything it does. The transaction does not need to be signed.
+If successful, this command outputs a JSON description of the transaction's result, events,
+asset movements, execution cost and fee, as well as whether or not its post-conditions would
+pass, and exits with code 0.
+
+If transaction-hex is `-`, the transaction hex is read from stdin.";
+
+const SPONSOR_USAGE: &str = "blockstack-cli (options) sponsor [sponsor-secret-key-hex] [fee-rate] [nonce] [transaction-hex]
+
+The sponsor command takes a sponsored transaction that its origin has already signed (i.e.
+one generated with --sponsored), sets the sponsor's spending condition, fee rate and nonce,
+and signs it as the sponsor. The sponsor pays the transaction's fee at the given fee rate.
+If successful, this command outputs the hex string encoding of the transaction to stdout,
+and exits with code 0.
+
+If transaction-hex is `-`, the transaction hex is read from stdin.";
+
+const MULTISIG_ADDRESS_USAGE: &str = "blockstack-cli (options) multisig-address [threshold] [public-key-hex...]
+
+The multisig-address command generates the address of the multisig account that requires
+threshold of the given public keys' signatures. The order of the public keys matters -- it is the
+order in which co-signers must sign. Use --p2wsh for a P2WSH address (P2SH is the default).";
+
+const APPEND_SIGNATURE_USAGE: &str = "blockstack-cli (options) append-signature [transaction-hex] [-s secret-key-hex | -p public-key-hex]
+
+The append-signature command adds the next field to a multisig transaction's origin -- either
+a signature from the next co-signer's secret key (-s), or the next co-signer's public key if
+they are not signing (-p). Fields must be added in the same order as the public keys that make
+up the multisig address. If successful, this command outputs the hex string encoding of the
+transaction to stdout, and exits with code 0. Once every field has been added, the transaction
+is complete.
+
+If transaction-hex is `-`, the transaction hex is read from stdin.";
+
+const DECODE_TX_USAGE: &str = "blockstack-cli (options) decode-tx [transaction-hex]
+
+The decode-tx command describes a transaction in words: its version, anchor mode, how it is
+authorized and by whom, its payload and its post-conditions. The transaction does not need to
+be (fully) signed. If successful, this command outputs the description to stdout, and exits
+with code 0.
+
+If transaction-hex is `-`, the transaction hex is read from stdin.";
+
+const BUILD_USAGE: &str = "blockstack-cli (options) build [publish | contract-call | token-transfer] [origin-public-key-hex] [args...]
+
+The build command generates an unsigned publish, contract-call or token-transfer transaction,
+taking the same arguments as those commands -- except that the origin's secret key is replaced by
+its public key (or by its comma-separated public keys, with --multisig). If successful, this
+command outputs a JSON signing envelope to stdout, and exits with code 0. The envelope contains
+the unsigned transaction, along with the hash its next signer will sign (sighashPresign), the
+address that must sign it (signerAddress), its fee rate and nonce, and, for a multisig origin, its
+public keys in the order its co-signers sign (signerPublicKeys).
+
+Sign the envelope offline with `blockstack-cli sign`, and make a broadcastable transaction from
+it with `blockstack-cli finalize`.";
+
+const SIGN_USAGE: &str = "blockstack-cli (options) sign [envelope-file] [secret-key-file]
+
+The sign command signs the transaction in a signing envelope made by `blockstack-cli build`,
+using the hex-encoded secret key in secret-key-file. It needs no network access. Before
+signing, it checks that the envelope's sighashPresign, signerAddress, fee rate and nonce all
+match the transaction bytes it contains, and refuses to sign if they do not. If successful, this
+command outputs the signing envelope with the signed transaction to stdout, and exits with code 0.
+Multisig co-signers each sign the envelope in turn, in the order of the envelope's
+signerPublicKeys; the sign command refuses a secret key that is not the next co-signer's.
+
+If envelope-file is `-`, the envelope is read from stdin.";
+
+const FINALIZE_USAGE: &str = "blockstack-cli (options) finalize [envelope-file]
+
+The finalize command checks that the transaction in a signing envelope is completely and
+correctly signed by its origin. If it is, this command outputs the hex string encoding of the
+transaction to stdout, and exits with code 0. A multisig transaction's co-signers who did not
+sign have their public keys (from signerPublicKeys) appended to it first. A sponsored
+transaction still needs to be signed by its sponsor with `blockstack-cli sponsor`.
+
+If envelope-file is `-`, the envelope is read from stdin.";
+
 const GENERATE_USAGE: &str = "blockstack-cli (options) generate-sk
 
 This method generates a secret key, outputting the hex encoding of the
@@ -136,6 +257,18 @@ impl From<NetError> for CliError {
     }
 }
 
+impl From<ChainstateError> for CliError {
+    fn from(value: ChainstateError) -> Self {
+        CliError::Message(format!("Stacks chainstate error: {}", value))
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(value: serde_json::Error) -> Self {
+        CliError::Message(format!("Failed to parse JSON: {}", value))
+    }
+}
+
 impl From<std::num::ParseIntError> for CliError {
     fn from(value: std::num::ParseIntError) -> Self {
         CliError::Message(format!("Failed to parse integer: {}", value))
@@ -179,34 +312,410 @@ fn make_contract_call(contract_address: String, contract_name: String, function_
 }
 
 
-fn make_standard_single_sig_tx(version: TransactionVersion, payload: TransactionPayload,
-                               publicKey: &StacksPublicKey, nonce: u64, fee_rate: u64) -> StacksTransaction {
-    let mut spending_condition = TransactionSpendingCondition::new_singlesig_p2pkh(publicKey.clone())
-        .expect("Failed to create p2pkh spending condition from public key.");
+/// Who authorizes a new transaction.  A single-sig origin signs it right away, unless it is
+/// being built for offline signing; a multisig origin's co-signers each sign it later with
+/// append-signature or sign.
+enum TxOrigin {
+    SingleSig(StacksPrivateKey),
+    OfflineSingleSig(StacksPublicKey),
+    MultiSig(AddressHashMode, u16, Vec<StacksPublicKey>)
+}
+
+/// Transaction-construction options given as flags
+struct TxOptions {
+    build: bool,
+    sponsored: bool,
+    multisig_threshold: Option<u16>,
+    multisig_hash_mode: AddressHashMode,
+    post_condition_mode: TransactionPostConditionMode,
+    post_conditions: Vec<TransactionPostCondition>
+}
+
+fn parse_origin(origin_arg: &str, options: &TxOptions) -> Result<TxOrigin, CliError> {
+    match options.multisig_threshold {
+        Some(threshold) => {
+            let mut public_keys = vec![];
+            for public_key_hex in origin_arg.split(',') {
+                public_keys.push(StacksPublicKey::from_hex(public_key_hex)?);
+            }
+            Ok(TxOrigin::MultiSig(options.multisig_hash_mode, threshold, public_keys))
+        },
+        None if options.build => {
+            Ok(TxOrigin::OfflineSingleSig(StacksPublicKey::from_hex(origin_arg)?))
+        },
+        None => {
+            Ok(TxOrigin::SingleSig(StacksPrivateKey::from_hex(origin_arg)?))
+        }
+    }
+}
+
+fn make_origin_spending_condition(origin: &TxOrigin, nonce: u64, fee_rate: u64) -> Result<TransactionSpendingCondition, CliError> {
+    let mut spending_condition = match origin {
+        TxOrigin::SingleSig(secret_key) => {
+            TransactionSpendingCondition::new_singlesig_p2pkh(StacksPublicKey::from_private(secret_key))
+                .expect("Failed to create p2pkh spending condition from public key.")
+        },
+        TxOrigin::OfflineSingleSig(public_key) => {
+            TransactionSpendingCondition::new_singlesig_p2pkh(public_key.clone())
+                .expect("Failed to create p2pkh spending condition from public key.")
+        },
+        TxOrigin::MultiSig(AddressHashMode::SerializeP2WSH, threshold, public_keys) => {
+            TransactionSpendingCondition::new_multisig_p2wsh(*threshold, public_keys.clone())
+                .ok_or("Failed to create p2wsh spending condition from public keys -- are they all compressed?")?
+        },
+        TxOrigin::MultiSig(_, threshold, public_keys) => {
+            TransactionSpendingCondition::new_multisig_p2sh(*threshold, public_keys.clone())
+                .ok_or("Failed to create p2sh spending condition from public keys")?
+        }
+    };
     spending_condition.set_nonce(nonce);
     spending_condition.set_fee_rate(fee_rate);
-    let auth = TransactionAuth::Standard(spending_condition);
-    StacksTransaction::new(version, auth, payload)
+    Ok(spending_condition)
+}
+
+/// Make an unsigned transaction.  If it's sponsored, then the sponsor is not yet known -- the
+/// origin only commits to the fact that the transaction is sponsored, so the sponsor's spending
+/// condition is a placeholder until the sponsor signs it.
+fn make_unsigned_tx(version: TransactionVersion, payload: TransactionPayload,
+                    origin_condition: TransactionSpendingCondition, options: &TxOptions) -> StacksTransaction {
+    let auth = if options.sponsored {
+        TransactionAuth::Sponsored(origin_condition, TransactionSpendingCondition::new_initial_sighash())
+    } else {
+        TransactionAuth::Standard(origin_condition)
+    };
+    let mut tx = StacksTransaction::new(version, auth, payload);
+    tx.set_post_condition_mode(options.post_condition_mode);
+    for post_condition in options.post_conditions.iter() {
+        tx.add_post_condition(post_condition.clone());
+    }
+    tx
+}
+
+/// Split a post-condition into at most max_parts `:`-separated parts.  The `::` in an asset
+/// name is not a separator, and the last part gets whatever is left over.
+fn split_post_condition(post_condition: &str, max_parts: usize) -> Vec<&str> {
+    let bytes = post_condition.as_bytes();
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() && parts.len() + 1 < max_parts {
+        if bytes[i] == b':' {
+            if i + 1 < bytes.len() && bytes[i + 1] == b':' {
+                i += 2;
+                continue;
+            }
+            parts.push(&post_condition[start..i]);
+            start = i + 1;
+        }
+        i += 1;
+    }
+    parts.push(&post_condition[start..]);
+    parts
+}
+
+fn parse_post_condition_principal(principal: &str) -> Result<PostConditionPrincipal, CliError> {
+    if principal == "origin" {
+        return Ok(PostConditionPrincipal::Origin);
+    }
+    match principal.find('.') {
+        Some(ix) => {
+            let address = StacksAddress::from_string(&principal[..ix].to_string())
+                .ok_or("Failed to parse post-condition principal address")?;
+            let contract_name = ContractName::try_from(principal[ix+1..].to_string())?;
+            Ok(PostConditionPrincipal::Contract(address, contract_name))
+        },
+        None => {
+            let address = StacksAddress::from_string(&principal.to_string())
+                .ok_or("Failed to parse post-condition principal address")?;
+            Ok(PostConditionPrincipal::Standard(address))
+        }
+    }
+}
+
+fn parse_post_condition_asset(asset: &str) -> Result<AssetInfo, CliError> {
+    let (contract_id, asset_name) = match asset.find("::") {
+        Some(ix) => (&asset[..ix], &asset[ix+2..]),
+        None => {
+            return Err("Post-condition asset must be given as ADDRESS.CONTRACT-NAME::ASSET-NAME".into())
+        }
+    };
+    let (contract_address, contract_name) = match parse_post_condition_principal(contract_id)? {
+        PostConditionPrincipal::Contract(address, contract_name) => (address, contract_name),
+        _ => {
+            return Err("Post-condition asset must be given as ADDRESS.CONTRACT-NAME::ASSET-NAME".into())
+        }
+    };
+    Ok(AssetInfo {
+        contract_address: contract_address,
+        contract_name: contract_name,
+        asset_name: ClarityName::try_from(asset_name.to_string())?
+    })
+}
+
+fn parse_fungible_condition_code(code: &str) -> Result<FungibleConditionCode, CliError> {
+    match code {
+        "eq" => Ok(FungibleConditionCode::SentEq),
+        "gt" => Ok(FungibleConditionCode::SentGt),
+        "gte" => Ok(FungibleConditionCode::SentGe),
+        "lt" => Ok(FungibleConditionCode::SentLt),
+        "lte" => Ok(FungibleConditionCode::SentLe),
+        _ => Err(CliError::Message(format!("Unrecognized fungible post-condition code `{}` -- expected one of eq, gt, gte, lt or lte", code)))
+    }
+}
+
+fn parse_nonfungible_condition_code(code: &str) -> Result<NonfungibleConditionCode, CliError> {
+    match code {
+        "sent" => Ok(NonfungibleConditionCode::Sent),
+        "not-sent" => Ok(NonfungibleConditionCode::NotSent),
+        _ => Err(CliError::Message(format!("Unrecognized non-fungible post-condition code `{}` -- expected sent or not-sent", code)))
+    }
+}
+
+/// Parse a post-condition given as a --post-condition argument (see USAGE)
+fn parse_post_condition(post_condition: &str) -> Result<TransactionPostCondition, CliError> {
+    let kind = post_condition.split(':').next().unwrap_or("");
+    let num_parts = match kind {
+        "stx" => 4,
+        "ft" | "nft" => 5,
+        _ => {
+            return Err(CliError::Message(format!("Unrecognized post-condition `{}` -- expected it to start with stx:, ft: or nft:", post_condition)))
+        }
+    };
+    let parts = split_post_condition(post_condition, num_parts);
+    if parts.len() != num_parts {
+        return Err(CliError::Message(format!("Post-condition `{}` has too few parts", post_condition)))
+    }
+
+    let principal = parse_post_condition_principal(parts[1])?;
+    match kind {
+        "stx" => {
+            let code = parse_fungible_condition_code(parts[2])?;
+            Ok(TransactionPostCondition::STX(principal, code, parts[3].parse()?))
+        },
+        "ft" => {
+            let asset = parse_post_condition_asset(parts[2])?;
+            let code = parse_fungible_condition_code(parts[3])?;
+            Ok(TransactionPostCondition::Fungible(principal, asset, code, parts[4].parse()?))
+        },
+        _ => {
+            let asset = parse_post_condition_asset(parts[2])?;
+            let code = parse_nonfungible_condition_code(parts[3])?;
+            let value = if parts[4].starts_with("0x") {
+                Value::try_deserialize_hex_untyped(&parts[4][2..])?
+            } else {
+                vm::execute(parts[4])?
+                    .ok_or("Post-condition value did not evaluate to a Value")?
+            };
+            Ok(TransactionPostCondition::Nonfungible(principal, asset, value, code))
+        }
+    }
+}
+
+fn describe_post_condition_principal(principal: &PostConditionPrincipal) -> String {
+    match *principal {
+        PostConditionPrincipal::Origin => "the origin account".to_string(),
+        PostConditionPrincipal::Standard(ref address) => address.to_string(),
+        PostConditionPrincipal::Contract(ref address, ref contract_name) => format!("{}.{}", address.to_string(), contract_name.as_str())
+    }
+}
+
+fn describe_asset(asset: &AssetInfo) -> String {
+    format!("{}.{}::{}", asset.contract_address.to_string(), asset.contract_name.as_str(), asset.asset_name.as_str())
+}
+
+fn describe_fungible_condition_code(code: &FungibleConditionCode) -> &'static str {
+    match *code {
+        FungibleConditionCode::SentEq => "exactly",
+        FungibleConditionCode::SentGt => "more than",
+        FungibleConditionCode::SentGe => "at least",
+        FungibleConditionCode::SentLt => "less than",
+        FungibleConditionCode::SentLe => "at most"
+    }
+}
+
+fn describe_post_condition(post_condition: &TransactionPostCondition) -> String {
+    match *post_condition {
+        TransactionPostCondition::STX(ref principal, ref code, amount) => {
+            format!("{} must send {} {} microSTX",
+                    describe_post_condition_principal(principal), describe_fungible_condition_code(code), amount)
+        },
+        TransactionPostCondition::Fungible(ref principal, ref asset, ref code, amount) => {
+            format!("{} must send {} {} of {}",
+                    describe_post_condition_principal(principal), describe_fungible_condition_code(code), amount, describe_asset(asset))
+        },
+        TransactionPostCondition::Nonfungible(ref principal, ref asset, ref value, ref code) => {
+            let must = match *code {
+                NonfungibleConditionCode::Sent => "must send",
+                NonfungibleConditionCode::NotSent => "must not send"
+            };
+            format!("{} {} {} {}", describe_post_condition_principal(principal), must, describe_asset(asset), value)
+        }
+    }
 }
 
+/// Sign a new transaction if its origin can, and hex-encode it.  If it's being built for
+/// offline signing, then wrap it in a signing envelope instead.
+fn finish_origin_tx(unsigned_tx: &StacksTransaction, origin: &TxOrigin, options: &TxOptions) -> Result<String, CliError> {
+    if options.build {
+        let signer_public_keys = match origin {
+            TxOrigin::MultiSig(_, _, public_keys) => public_keys.clone(),
+            _ => vec![]
+        };
+        let envelope = SigningEnvelope::from_tx(unsigned_tx, &signer_public_keys)?;
+        return Ok(serde_json::to_string_pretty(&envelope)?);
+    }
+
+    let mut unsigned_tx_bytes = vec![];
+    unsigned_tx.consensus_serialize(&mut unsigned_tx_bytes).expect("FATAL: invalid transaction");
+
+    match origin {
+        TxOrigin::SingleSig(secret_key) => {
+            let signed_tx = sign_transaction_single_sig_standard(&to_hex(&unsigned_tx_bytes), secret_key)?;
+
+            let mut signed_tx_bytes = vec![];
+            signed_tx.consensus_serialize(&mut signed_tx_bytes).expect("FATAL: invalid signed transaction");
+            Ok(to_hex(&signed_tx_bytes))
+        },
+        TxOrigin::OfflineSingleSig(..) | TxOrigin::MultiSig(..) => {
+            // signed later (e.g. by co-signers with append-signature)
+            Ok(to_hex(&unsigned_tx_bytes))
+        }
+    }
+}
+
+/// An unsigned or partially-signed transaction, along with what its next signer needs to
+/// check before signing it offline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SigningEnvelope {
+    transaction: String,
+    #[serde(rename = "sighashPresign")]
+    sighash_presign: String,
+    #[serde(rename = "signerAddress")]
+    signer_address: String,
+    #[serde(rename = "feeRate")]
+    fee_rate: u64,
+    nonce: u64,
+    #[serde(rename = "signaturesRequired")]
+    signatures_required: u16,
+    signatures: u16,
+    // a multisig origin's public keys, in the order its co-signers sign
+    #[serde(rename = "signerPublicKeys", default)]
+    signer_public_keys: Vec<String>
+}
+
+/// Make a signer that picks up where the transaction's origin signers left off
+fn make_origin_signer(tx: &StacksTransaction) -> Result<StacksTransactionSigner, CliError> {
+    match tx.auth.origin() {
+        TransactionSpendingCondition::Singlesig(_) => Ok(StacksTransactionSigner::new(tx)),
+        TransactionSpendingCondition::Multisig(_) => Ok(StacksTransactionSigner::new_partially_signed(tx)?)
+    }
+}
+
+impl SigningEnvelope {
+    fn from_tx(tx: &StacksTransaction, signer_public_keys: &[StacksPublicKey]) -> Result<SigningEnvelope, CliError> {
+        let signer = make_origin_signer(tx)?;
+        let origin = tx.auth.origin();
+
+        let mut tx_bytes = vec![];
+        tx.consensus_serialize(&mut tx_bytes).expect("FATAL: invalid transaction");
+
+        Ok(SigningEnvelope {
+            transaction: to_hex(&tx_bytes),
+            sighash_presign: signer.origin_sighash_presign().to_hex(),
+            signer_address: tx.origin_address().to_string(),
+            fee_rate: origin.fee_rate(),
+            nonce: origin.nonce(),
+            signatures_required: origin.signatures_required(),
+            signatures: origin.num_signatures(),
+            signer_public_keys: signer_public_keys.iter().map(|public_key| public_key.to_hex()).collect()
+        })
+    }
+
+    fn decode_signer_public_keys(&self) -> Result<Vec<StacksPublicKey>, CliError> {
+        let mut public_keys = vec![];
+        for public_key_hex in self.signer_public_keys.iter() {
+            public_keys.push(StacksPublicKey::from_hex(public_key_hex)?);
+        }
+        Ok(public_keys)
+    }
+
+    fn read(arg: &str) -> Result<SigningEnvelope, CliError> {
+        let envelope_json = if arg == "-" {
+            let mut buffer = String::new();
+            io::stdin().read_to_string(&mut buffer)?;
+            buffer
+        } else {
+            fs::read_to_string(arg)?
+        };
+        Ok(serde_json::from_str(&envelope_json)?)
+    }
+
+    /// Decode the envelope's transaction, and confirm that everything else in the envelope
+    /// was derived from it -- including that a multisig origin's public keys make up its address.
+    fn decode_tx(&self) -> Result<StacksTransaction, CliError> {
+        let tx = StacksTransaction::deserialize_partially_signed(&mut io::Cursor::new(&hex_bytes(&self.transaction)?))?;
+        let signer_public_keys = self.decode_signer_public_keys()?;
+        match tx.auth.origin() {
+            TransactionSpendingCondition::Singlesig(_) => {
+                if signer_public_keys.len() > 0 {
+                    return Err("Envelope lists signer public keys, but its transaction has a single-sig origin".into())
+                }
+            },
+            TransactionSpendingCondition::Multisig(data) => {
+                let address = StacksAddress::from_public_keys(tx.origin_address().version, &data.hash_mode.to_address_hash_mode(),
+                                                              data.signatures_required as usize, &signer_public_keys);
+                if address != Some(tx.origin_address()) {
+                    return Err("Envelope signer public keys do not make up its transaction's signer address".into())
+                }
+            }
+        }
+
+        let expected = SigningEnvelope::from_tx(&tx, &signer_public_keys)?;
+        if expected.sighash_presign != self.sighash_presign {
+            return Err(CliError::Message(format!("Envelope sighash {} does not match the transaction's sighash {}",
+                                                 self.sighash_presign, expected.sighash_presign)))
+        }
+        if expected != *self {
+            return Err("Envelope signer address, fee rate, nonce or signature counts do not match its transaction".into())
+        }
+        Ok(tx)
+    }
+}
+
+fn read_tx_hex_arg(arg: &str) -> Result<String, CliError> {
+    if arg == "-" {
+        let mut buffer = String::new();
+        io::stdin().read_to_string(&mut buffer)?;
+        Ok(buffer.trim().to_string())
+    } else {
+        Ok(arg.to_string())
+    }
+}
+
+/// Sign a transaction as its (single-sig) origin.  A sponsored transaction is not complete
+/// until its sponsor signs it too, so it's returned as-is.
 fn sign_transaction_single_sig_standard(transaction: &str, secret_key: &StacksPrivateKey) -> Result<StacksTransaction, CliError> {
     let transaction = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(transaction)?))?;
 
     let mut tx_signer = StacksTransactionSigner::new(&transaction);
     tx_signer.sign_origin(secret_key)?;
 
+    if transaction.auth.is_sponsored() {
+        return Ok(tx_signer.get_tx_incomplete());
+    }
+
     Ok(tx_signer.get_tx()
        .ok_or("TX did not finish signing -- was this a standard single signature transaction?")?)
 }
 
-fn handle_contract_publish(args: &[String], version: TransactionVersion) -> Result<String, CliError> {
+fn handle_contract_publish(args: &[String], version: TransactionVersion, options: &TxOptions) -> Result<String, CliError> {
     if args.len() >= 1 && args[0] == "-h" {
         return Err(CliError::Message(format!("USAGE:\n {}", PUBLISH_USAGE)))
     }
     if args.len() != 5 {
         return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", PUBLISH_USAGE)))
     }
-    let sk_publisher = &args[0];
+    let origin = parse_origin(&args[0], options)?;
     let fee_rate = args[1].parse()?;
     let nonce = args[2].parse()?;
     let contract_name = &args[3];
@@ -220,29 +729,20 @@ fn handle_contract_publish(args: &[String], version: TransactionVersion) -> Resu
         fs::read_to_string(contract_file)?
     };
 
-    let sk_publisher = StacksPrivateKey::from_hex(sk_publisher)?;
-
     let payload = make_contract_publish(contract_name.clone(), contract_contents)?;
-    let unsigned_tx = make_standard_single_sig_tx(version, payload.into(), &StacksPublicKey::from_private(&sk_publisher),
-                                                  nonce, fee_rate);
-    let mut unsigned_tx_bytes = vec![];
-    unsigned_tx.consensus_serialize(&mut unsigned_tx_bytes).expect("FATAL: invalid transaction");
-    let signed_tx = sign_transaction_single_sig_standard(
-        &to_hex(&unsigned_tx_bytes), &sk_publisher)?;
-
-    let mut signed_tx_bytes = vec![];
-    signed_tx.consensus_serialize(&mut signed_tx_bytes).expect("FATAL: invalid signed transaction");
-    Ok(to_hex(&signed_tx_bytes))
+    let unsigned_tx = make_unsigned_tx(version, payload.into(), make_origin_spending_condition(&origin, nonce, fee_rate)?,
+                                       options);
+    finish_origin_tx(&unsigned_tx, &origin, options)
 }
 
-fn handle_contract_call(args: &[String], version: TransactionVersion) -> Result<String, CliError> {
+fn handle_contract_call(args: &[String], version: TransactionVersion, options: &TxOptions) -> Result<String, CliError> {
     if args.len() >= 1 && args[0] == "-h" {
         return Err(CliError::Message(format!("USAGE:\n {}", CALL_USAGE)))
     }
     if args.len() < 6 {
         return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", CALL_USAGE)))
     }
-    let sk_origin = &args[0];
+    let origin = parse_origin(&args[0], options)?;
     let fee_rate = args[1].parse()?;
     let nonce = args[2].parse()?;
     let contract_address = &args[3];
@@ -277,30 +777,24 @@ fn handle_contract_call(args: &[String], version: TransactionVersion) -> Result<
         arg_iterator += 2;
     }
 
-    let sk_origin = StacksPrivateKey::from_hex(sk_origin)?;
-
     let payload = make_contract_call(contract_address.clone(), contract_name.clone(), function_name.clone(), values)?;
-    let unsigned_tx = make_standard_single_sig_tx(version, payload.into(), &StacksPublicKey::from_private(&sk_origin),
-                                                  nonce, fee_rate);
-    
-    let mut unsigned_tx_bytes = vec![];
-    unsigned_tx.consensus_serialize(&mut unsigned_tx_bytes).expect("FATAL: invalid transaction");
-    let signed_tx = sign_transaction_single_sig_standard(
-        &to_hex(&unsigned_tx_bytes), &sk_origin)?;
-
-    let mut signed_tx_bytes = vec![];
-    signed_tx.consensus_serialize(&mut signed_tx_bytes).expect("FATAL: invalid signed transaction");
-    Ok(to_hex(&signed_tx_bytes))
+    let unsigned_tx = make_unsigned_tx(version, payload.into(), make_origin_spending_condition(&origin, nonce, fee_rate)?,
+                                       options);
+    finish_origin_tx(&unsigned_tx, &origin, options)
 }
 
-fn handle_token_transfer(args: &[String], version: TransactionVersion) -> Result<String, CliError> {
+fn handle_token_transfer(args: &[String], version: TransactionVersion, options: &TxOptions) -> Result<String, CliError> {
     if args.len() >= 1 && args[0] == "-h" {
         return Err(CliError::Message(format!("USAGE:\n {}", TOKEN_TRANSFER_USAGE)))
     }
     if args.len() < 5 {
         return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", CALL_USAGE)))
     }
-    let sk_origin = StacksPrivateKey::from_hex(&args[0])?;
+    if options.post_conditions.len() > 0 {
+        return Err("Token transfer transactions do not support post-conditions".into())
+    }
+
+    let origin = parse_origin(&args[0], options)?;
     let fee_rate = args[1].parse()?;
     let nonce = args[2].parse()?;
     let recipient_address = StacksAddress::from_string(&args[3]).ok_or("Failed to parse contract address")?;
@@ -314,18 +808,350 @@ fn handle_token_transfer(args: &[String], version: TransactionVersion) -> Result
     };
 
     let payload = TransactionPayload::TokenTransfer(recipient_address, *amount, memo);
-    let unsigned_tx = make_standard_single_sig_tx(version, payload, &StacksPublicKey::from_private(&sk_origin),
-                                                  nonce, fee_rate);
-    let mut unsigned_tx_bytes = vec![];
-    unsigned_tx.consensus_serialize(&mut unsigned_tx_bytes).expect("FATAL: invalid transaction");
-    let signed_tx = sign_transaction_single_sig_standard(
-        &to_hex(&unsigned_tx_bytes), &sk_origin)?;
+    let unsigned_tx = make_unsigned_tx(version, payload, make_origin_spending_condition(&origin, nonce, fee_rate)?,
+                                       options);
+    finish_origin_tx(&unsigned_tx, &origin, options)
+}
+
+fn handle_sponsor(args: &[String], _version: TransactionVersion) -> Result<String, CliError> {
+    if args.len() >= 1 && args[0] == "-h" {
+        return Err(CliError::Message(format!("USAGE:\n {}", SPONSOR_USAGE)))
+    }
+    if args.len() != 4 {
+        return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", SPONSOR_USAGE)))
+    }
+    let sk_sponsor = StacksPrivateKey::from_hex(&args[0])?;
+    let fee_rate = args[1].parse()?;
+    let nonce = args[2].parse()?;
+
+    let tx_hex = read_tx_hex_arg(&args[3])?;
+    let transaction = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex)?))?;
+
+    if !transaction.auth.is_sponsored() {
+        return Err("Transaction is not sponsored -- was it generated with --sponsored?".into())
+    }
+
+    let mut sponsor_condition = TransactionSpendingCondition::new_singlesig_p2pkh(StacksPublicKey::from_private(&sk_sponsor))
+        .expect("Failed to create p2pkh spending condition from public key.");
+    sponsor_condition.set_nonce(nonce);
+    sponsor_condition.set_fee_rate(fee_rate);
+
+    // this also checks that the origin has signed the transaction
+    let mut tx_signer = StacksTransactionSigner::new_sponsor(&transaction, sponsor_condition)?;
+    tx_signer.sign_sponsor(&sk_sponsor)?;
+
+    let signed_tx = tx_signer.get_tx()
+        .ok_or("TX did not finish signing -- was this a single signature sponsor?")?;
 
     let mut signed_tx_bytes = vec![];
     signed_tx.consensus_serialize(&mut signed_tx_bytes).expect("FATAL: invalid signed transaction");
     Ok(to_hex(&signed_tx_bytes))
 }
 
+fn handle_append_signature(args: &[String], _version: TransactionVersion) -> Result<String, CliError> {
+    if args.len() >= 1 && args[0] == "-h" {
+        return Err(CliError::Message(format!("USAGE:\n {}", APPEND_SIGNATURE_USAGE)))
+    }
+    if args.len() != 3 {
+        return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", APPEND_SIGNATURE_USAGE)))
+    }
+    let tx_hex = read_tx_hex_arg(&args[0])?;
+    let transaction = StacksTransaction::deserialize_partially_signed(&mut io::Cursor::new(&hex_bytes(&tx_hex)?))?;
+
+    let mut tx_signer = StacksTransactionSigner::new_partially_signed(&transaction)?;
+    match args[1].as_str() {
+        "-s" => {
+            tx_signer.sign_origin(&StacksPrivateKey::from_hex(&args[2])?)?;
+        },
+        "-p" => {
+            tx_signer.append_origin(&StacksPublicKey::from_hex(&args[2])?)?;
+        },
+        _ => {
+            return Err("append-signature takes either `-s [secret-key-hex]` or `-p [public-key-hex]`".into())
+        }
+    }
+
+    let mut tx_bytes = vec![];
+    tx_signer.get_tx_incomplete().consensus_serialize(&mut tx_bytes).expect("FATAL: invalid transaction");
+    Ok(to_hex(&tx_bytes))
+}
+
+fn describe_spending_condition(condition: &TransactionSpendingCondition, version: &TransactionVersion) -> String {
+    let address = match *version {
+        TransactionVersion::Mainnet => condition.address_mainnet(),
+        TransactionVersion::Testnet => condition.address_testnet()
+    };
+    match *condition {
+        TransactionSpendingCondition::Singlesig(ref data) => {
+            let hash_mode = match data.hash_mode {
+                SinglesigHashMode::P2PKH => "p2pkh",
+                SinglesigHashMode::P2WPKH => "p2wpkh"
+            };
+            let signed = if data.signature == MessageSignature::empty() { "not signed" } else { "signed" };
+            format!("{} ({} single-sig), nonce {}, fee rate {}, {}",
+                    address.to_string(), hash_mode, data.nonce, data.fee_rate, signed)
+        },
+        TransactionSpendingCondition::Multisig(ref data) => {
+            let hash_mode = match data.hash_mode {
+                MultisigHashMode::P2SH => "p2sh",
+                MultisigHashMode::P2WSH => "p2wsh"
+            };
+            let num_sigs = data.fields.iter().filter(|field| field.is_signature()).count();
+            format!("{} ({} multisig), nonce {}, fee rate {}, {} of {} required signatures ({} signer fields so far)",
+                    address.to_string(), hash_mode, data.nonce, data.fee_rate,
+                    num_sigs, data.signatures_required, data.fields.len())
+        }
+    }
+}
+
+fn describe_payload(payload: &TransactionPayload) -> String {
+    match *payload {
+        TransactionPayload::TokenTransfer(ref recipient, amount, ref memo) => {
+            let memo_bytes : Vec<u8> = memo.0.iter().cloned().take_while(|b| *b != 0).collect();
+            let memo_str = match String::from_utf8(memo_bytes) {
+                Ok(memo_str) => format!("{:?}", memo_str),
+                Err(_) => format!("0x{}", to_hex(&memo.0))
+            };
+            format!("token transfer of {} microSTX to {} (memo {})", amount, recipient.to_string(), memo_str)
+        },
+        TransactionPayload::ContractCall(ref cc) => {
+            let args : Vec<String> = cc.function_args.iter().map(|arg| format!("{}", arg)).collect();
+            format!("contract call: ({} {}) on {}.{}", cc.function_name.as_str(), args.join(" "), cc.address.to_string(), cc.contract_name.as_str())
+        },
+        TransactionPayload::SmartContract(ref sc) => {
+            format!("smart contract: {} ({} bytes of code)", sc.name.as_str(), sc.code_body.len())
+        },
+        TransactionPayload::PoisonMicroblock(ref h1, ref h2) => {
+            format!("poison microblock: {} and {} both have sequence {} and parent {}",
+                    h1.block_hash().to_hex(), h2.block_hash().to_hex(), h1.sequence, h1.prev_block.to_hex())
+        },
+        TransactionPayload::Coinbase(ref payload) => {
+            format!("coinbase: 0x{}", to_hex(&payload.0))
+        }
+    }
+}
+
+fn handle_decode_tx(args: &[String], _version: TransactionVersion) -> Result<String, CliError> {
+    if args.len() >= 1 && args[0] == "-h" {
+        return Err(CliError::Message(format!("USAGE:\n {}", DECODE_TX_USAGE)))
+    }
+    if args.len() != 1 {
+        return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", DECODE_TX_USAGE)))
+    }
+    let tx_hex = read_tx_hex_arg(&args[0])?;
+    let tx = StacksTransaction::deserialize_partially_signed(&mut io::Cursor::new(&hex_bytes(&tx_hex)?))?;
+
+    let mut lines = vec![];
+    lines.push(format!("Transaction ID: {}", tx.txid().to_hex()));
+    lines.push(format!("Version: {}", match tx.version {
+        TransactionVersion::Mainnet => "mainnet",
+        TransactionVersion::Testnet => "testnet"
+    }));
+    lines.push(format!("Chain ID: 0x{:08x}", tx.chain_id));
+    lines.push(format!("Anchor mode: {}", match tx.anchor_mode {
+        TransactionAnchorMode::OnChainOnly => "on-chain only (anchored blocks)",
+        TransactionAnchorMode::OffChainOnly => "off-chain only (microblocks)",
+        TransactionAnchorMode::Any => "any (anchored blocks or microblocks)"
+    }));
+    match tx.auth {
+        TransactionAuth::Standard(ref origin) => {
+            lines.push("Authorization: standard".to_string());
+            lines.push(format!("  Origin: {}", describe_spending_condition(origin, &tx.version)));
+        },
+        TransactionAuth::Sponsored(ref origin, ref sponsor) => {
+            lines.push("Authorization: sponsored".to_string());
+            lines.push(format!("  Origin: {}", describe_spending_condition(origin, &tx.version)));
+            lines.push(format!("  Sponsor: {}", describe_spending_condition(sponsor, &tx.version)));
+        }
+    }
+    lines.push(format!("Payload: {}", describe_payload(&tx.payload)));
+    lines.push(format!("Post-condition mode: {}", match tx.post_condition_mode {
+        TransactionPostConditionMode::Allow => "allow (asset movements not covered by a post-condition are allowed)",
+        TransactionPostConditionMode::Deny => "deny (asset movements not covered by a post-condition abort the transaction)"
+    }));
+    if tx.post_conditions.len() == 0 {
+        lines.push("Post-conditions: none".to_string());
+    } else {
+        lines.push("Post-conditions:".to_string());
+        for (i, post_condition) in tx.post_conditions.iter().enumerate() {
+            lines.push(format!("  {}. {}", i + 1, describe_post_condition(post_condition)));
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+fn handle_build(args: &[String], version: TransactionVersion, mut options: TxOptions) -> Result<String, CliError> {
+    if args.len() == 0 || args[0] == "-h" {
+        return Err(CliError::Message(format!("USAGE:\n {}", BUILD_USAGE)))
+    }
+    options.build = true;
+
+    let method_args = &args[1..];
+    match args[0].as_str() {
+        "publish" => handle_contract_publish(method_args, version, &options),
+        "contract-call" => handle_contract_call(method_args, version, &options),
+        "token-transfer" => handle_token_transfer(method_args, version, &options),
+        _ => Err(CliError::Message(format!("Cannot build a `{}` transaction\n\nUSAGE:\n {}", args[0], BUILD_USAGE)))
+    }
+}
+
+fn handle_sign(args: &[String], _version: TransactionVersion) -> Result<String, CliError> {
+    if args.len() >= 1 && args[0] == "-h" {
+        return Err(CliError::Message(format!("USAGE:\n {}", SIGN_USAGE)))
+    }
+    if args.len() != 2 {
+        return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", SIGN_USAGE)))
+    }
+    let envelope = SigningEnvelope::read(&args[0])?;
+    let secret_key = StacksPrivateKey::from_hex(fs::read_to_string(&args[1])?.trim())?;
+
+    let tx = envelope.decode_tx()?;
+    let signer_public_keys = envelope.decode_signer_public_keys()?;
+    let public_key = StacksPublicKey::from_private(&secret_key);
+    match tx.auth.origin() {
+        TransactionSpendingCondition::Singlesig(_) => {
+            let signer_address = StacksAddress::from_public_keys(tx.origin_address().version, &AddressHashMode::SerializeP2PKH,
+                                                                 1, &vec![public_key])
+                .expect("Failed to generate address from public key");
+            if signer_address.to_string() != envelope.signer_address {
+                return Err(CliError::Message(format!("Secret key is for {}, but the transaction must be signed by {}",
+                                                     signer_address.to_string(), envelope.signer_address)))
+            }
+        },
+        TransactionSpendingCondition::Multisig(data) => {
+            // co-signers sign in the order their public keys make up the address
+            match signer_public_keys.get(data.fields.len()) {
+                Some(next_public_key) => {
+                    if *next_public_key != public_key {
+                        return Err(CliError::Message(format!("Secret key is for public key {}, but the next co-signer is {}",
+                                                             public_key.to_hex(), next_public_key.to_hex())))
+                    }
+                },
+                None => {
+                    return Err("Every co-signer has already signed or appended its public key".into())
+                }
+            }
+        }
+    }
+
+    let mut tx_signer = make_origin_signer(&tx)?;
+    tx_signer.sign_origin(&secret_key)?;
+
+    let signed_envelope = SigningEnvelope::from_tx(&tx_signer.get_tx_incomplete(), &signer_public_keys)?;
+    Ok(serde_json::to_string_pretty(&signed_envelope)?)
+}
+
+fn handle_finalize(args: &[String], _version: TransactionVersion) -> Result<String, CliError> {
+    if args.len() >= 1 && args[0] == "-h" {
+        return Err(CliError::Message(format!("USAGE:\n {}", FINALIZE_USAGE)))
+    }
+    if args.len() != 1 {
+        return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", FINALIZE_USAGE)))
+    }
+    let envelope = SigningEnvelope::read(&args[0])?;
+    let mut tx = envelope.decode_tx()?;
+
+    if envelope.signatures < envelope.signatures_required {
+        return Err(CliError::Message(format!("Transaction has {} of its {} required signatures",
+                                             envelope.signatures, envelope.signatures_required)))
+    }
+
+    // co-signers who didn't sign a multisig transaction still contribute their public keys
+    let num_fields = match tx.auth.origin() {
+        TransactionSpendingCondition::Multisig(data) => data.fields.len(),
+        TransactionSpendingCondition::Singlesig(_) => 0
+    };
+    let signer_public_keys = envelope.decode_signer_public_keys()?;
+    if num_fields < signer_public_keys.len() {
+        let mut tx_signer = StacksTransactionSigner::new_partially_signed(&tx)?;
+        for public_key in signer_public_keys[num_fields..].iter() {
+            tx_signer.append_origin(public_key)?;
+        }
+        tx = tx_signer.get_tx_incomplete();
+    }
+
+    // must be decodable under consensus rules
+    let mut tx_bytes = vec![];
+    tx.consensus_serialize(&mut tx_bytes).expect("FATAL: invalid transaction");
+    StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&tx_bytes))?;
+
+    if tx.auth.is_sponsored() {
+        tx.verify_origin()?;
+    } else {
+        tx.verify()?;
+    }
+    Ok(to_hex(&tx_bytes))
+}
+
+fn handle_simulate(args: &[String], version: TransactionVersion) -> Result<String, CliError> {
+    if args.len() >= 1 && args[0] == "-h" {
+        return Err(CliError::Message(format!("USAGE:\n {}", SIMULATE_USAGE)))
+    }
+    if args.len() != 4 {
+        return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", SIMULATE_USAGE)))
+    }
+    let chainstate_path = &args[0];
+    let chain_id = if args[1].starts_with("0x") {
+        u32::from_str_radix(&args[1][2..], 16)?
+    } else {
+        args[1].parse()?
+    };
+    let index_block_hash = BlockHeaderHash::from_hex(&args[2])?;
+
+    let tx_hex = read_tx_hex_arg(&args[3])?;
+    let tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex)?))?;
+
+    let mainnet = version == TransactionVersion::Mainnet;
+    let mut chainstate = StacksChainState::open(mainnet, chain_id, chainstate_path)?;
+    let outcome = chainstate.simulate_transaction(&index_block_hash, &tx)?;
+
+    let simulation = TransactionSimulationData::from_outcome(&index_block_hash, outcome);
+    Ok(serde_json::to_string_pretty(&simulation)
+       .map_err(|e| CliError::Message(format!("Failed to serialize simulation: {}", e)))?)
+}
+
+fn generate_multisig_address(args: &[String], version: TransactionVersion, options: &TxOptions) -> Result<String, CliError> {
+    if args.len() >= 1 && args[0] == "-h" {
+        return Err(CliError::Message(format!("USAGE:\n {}", MULTISIG_ADDRESS_USAGE)))
+    }
+    if args.len() < 2 {
+        return Err(CliError::Message(format!("Incorrect argument count supplied \n\nUSAGE:\n {}", MULTISIG_ADDRESS_USAGE)))
+    }
+    let threshold : usize = args[0].parse()?;
+    let mut public_keys = vec![];
+    for public_key_hex in args[1..].iter() {
+        public_keys.push(StacksPublicKey::from_hex(public_key_hex)?);
+    }
+
+    if threshold == 0 || threshold > public_keys.len() {
+        return Err("Threshold must be between 1 and the number of public keys".into())
+    }
+
+    let hash_mode = options.multisig_hash_mode;
+    if hash_mode == AddressHashMode::SerializeP2WSH && public_keys.iter().any(|pk| !pk.compressed()) {
+        return Err("P2WSH multisig addresses require compressed public keys".into())
+    }
+
+    let version = match version {
+        TransactionVersion::Mainnet => C32_ADDRESS_VERSION_MAINNET_MULTISIG,
+        TransactionVersion::Testnet => C32_ADDRESS_VERSION_TESTNET_MULTISIG,
+    };
+
+    let address = StacksAddress::from_public_keys(version, &hash_mode, threshold, &public_keys)
+        .expect("Failed to generate address from public keys");
+
+    let public_keys_json : Vec<String> = public_keys.iter().map(|pk| format!("\"{}\"", pk.to_hex())).collect();
+    Ok(format!("{{
+  \"stacksAddress\": \"{}\",
+  \"hashMode\": \"{}\",
+  \"threshold\": {},
+  \"publicKeys\": [{}]
+}}",
+             address.to_string(),
+             if hash_mode == AddressHashMode::SerializeP2WSH { "p2wsh" } else { "p2sh" },
+             threshold,
+             public_keys_json.join(", ")))
+}
 
 fn generate_secret_key(args: &[String], version: TransactionVersion) -> Result<String, CliError> {
     if args.len() >= 1 && args[0] == "-h" {
@@ -377,12 +1203,80 @@ fn main_handler(mut argv: Vec<String>) -> Result<String, CliError> {
         TransactionVersion::Mainnet
     };
 
+    let sponsored = if let Some(ix) = argv.iter().position(|x| x == "--sponsored") {
+        argv.remove(ix);
+        true
+    } else {
+        false
+    };
+
+    let multisig_hash_mode = if let Some(ix) = argv.iter().position(|x| x == "--p2wsh") {
+        argv.remove(ix);
+        AddressHashMode::SerializeP2WSH
+    } else {
+        AddressHashMode::SerializeP2SH
+    };
+
+    let multisig_threshold = if let Some(ix) = argv.iter().position(|x| x == "--multisig") {
+        if ix + 1 >= argv.len() {
+            return Err("--multisig requires a signature threshold".into())
+        }
+        let threshold : u16 = argv[ix + 1].parse()?;
+        argv.drain(ix..ix + 2);
+        Some(threshold)
+    } else {
+        None
+    };
+
+    let deny_mode = if let Some(ix) = argv.iter().position(|x| x == "--deny-mode") {
+        argv.remove(ix);
+        true
+    } else {
+        false
+    };
+
+    let post_condition_mode = if let Some(ix) = argv.iter().position(|x| x == "--allow-mode") {
+        if deny_mode {
+            return Err("--deny-mode and --allow-mode cannot both be given".into())
+        }
+        argv.remove(ix);
+        TransactionPostConditionMode::Allow
+    } else {
+        TransactionPostConditionMode::Deny
+    };
+
+    let mut post_conditions = vec![];
+    while let Some(ix) = argv.iter().position(|x| x == "--post-condition") {
+        if ix + 1 >= argv.len() {
+            return Err("--post-condition requires a post-condition".into())
+        }
+        post_conditions.push(parse_post_condition(&argv[ix + 1])?);
+        argv.drain(ix..ix + 2);
+    }
+
+    let options = TxOptions {
+        build: false,
+        sponsored: sponsored,
+        multisig_threshold: multisig_threshold,
+        multisig_hash_mode: multisig_hash_mode,
+        post_condition_mode: post_condition_mode,
+        post_conditions: post_conditions
+    };
+
     if let Some((method, args)) = argv.split_first() {
         match method.as_str() {
-            "contract-call" => handle_contract_call(args, tx_version),
-            "publish" => handle_contract_publish(args, tx_version),
-            "token-transfer" => handle_token_transfer(args, tx_version),
+            "contract-call" => handle_contract_call(args, tx_version, &options),
+            "publish" => handle_contract_publish(args, tx_version, &options),
+            "token-transfer" => handle_token_transfer(args, tx_version, &options),
+            "multisig-address" => generate_multisig_address(args, tx_version, &options),
+            "append-signature" => handle_append_signature(args, tx_version),
+            "decode-tx" => handle_decode_tx(args, tx_version),
+            "build" => handle_build(args, tx_version, options),
+            "sign" => handle_sign(args, tx_version),
+            "finalize" => handle_finalize(args, tx_version),
+            "sponsor" => handle_sponsor(args, tx_version),
             "generate-sk" => generate_secret_key(args, tx_version),
+            "simulate" => handle_simulate(args, tx_version),
             _ => Err(CliError::Usage)
         }
     } else {
@@ -393,6 +1287,20 @@ fn main_handler(mut argv: Vec<String>) -> Result<String, CliError> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::process;
+    use std::path::PathBuf;
+
+    /// Make an empty directory for a test's files
+    fn make_test_dir(test_name: &str) -> PathBuf {
+        let mut path = env::temp_dir();
+        path.push(format!("blockstack-cli-test-{}-{}", test_name, process::id()));
+        if fs::metadata(&path).is_ok() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
     #[test]
     fn generate_should_work() {
         assert!(main_handler(vec!["generate-sk".into(), "--testnet".into()]).is_ok());
@@ -429,6 +1337,35 @@ mod test {
 
     }
 
+    #[test]
+    fn simulate_bad_args() {
+        assert!(format!("{}", main_handler(to_string_vec(&["simulate", "-h"])).unwrap_err())
+                .contains("USAGE"));
+
+        assert!(format!("{}", main_handler(to_string_vec(&["simulate", "/tmp/nonexistent-chainstate", "0x80000000"])).unwrap_err())
+                .contains("Incorrect argument count"));
+
+        let sim_args = [
+            "simulate",
+            "/tmp/nonexistent-chainstate",
+            "not-a-chain-id",
+            "1111111111111111111111111111111111111111111111111111111111111111",
+            "00"];
+
+        assert!(format!("{}", main_handler(to_string_vec(&sim_args)).unwrap_err())
+                .contains("Failed to parse integer"));
+
+        let sim_args = [
+            "simulate",
+            "/tmp/nonexistent-chainstate",
+            "0x80000000",
+            "1111111111111111111111111111111111111111111111111111111111111111",
+            "zz"];
+
+        assert!(format!("{}", main_handler(to_string_vec(&sim_args)).unwrap_err())
+                .contains("Bad hex string"));
+    }
+
     #[test]
     fn simple_token_transfer() {
         let tt_args = [
@@ -476,6 +1413,378 @@ mod test {
                 .contains("Failed to parse contract address"));        
     }
 
+    #[test]
+    fn sponsored_token_transfer() {
+        let tt_args = [
+            "--sponsored",
+            "token-transfer",
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "0",
+            "3",
+            "ST1A14RBKJ289E3DP89QAZE2RRHDPWP5RHMYFRCHV",
+            "10"];
+
+        let origin_signed_hex = main_handler(to_string_vec(&tt_args)).unwrap();
+        let origin_signed_tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&origin_signed_hex).unwrap())).unwrap();
+        assert!(origin_signed_tx.auth.is_sponsored());
+        assert_eq!(origin_signed_tx.get_origin_nonce(), 3);
+        assert!(origin_signed_tx.verify_origin().is_ok());
+        assert!(origin_signed_tx.verify().is_err());
+
+        let sponsor_args = [
+            "sponsor",
+            "a2e1ba1aac6b96dfdfd80a26ba2a2ba45b3aabaa0d0e1d8a4cba82b57cb8d6a401",
+            "2",
+            "7",
+            origin_signed_hex.as_str()];
+
+        let sponsored_hex = main_handler(to_string_vec(&sponsor_args)).unwrap();
+        let sponsored_tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&sponsored_hex).unwrap())).unwrap();
+        assert!(sponsored_tx.verify().is_ok());
+        assert_eq!(sponsored_tx.get_origin_nonce(), 3);
+        assert_eq!(sponsored_tx.get_sponsor_nonce(), Some(7));
+        assert_eq!(sponsored_tx.get_fee_rate(), 2);
+        assert_eq!(sponsored_tx.payload, origin_signed_tx.payload);
+
+        // can't sponsor a standard transaction
+        let tt_args = [
+            "token-transfer",
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "1",
+            "0",
+            "ST1A14RBKJ289E3DP89QAZE2RRHDPWP5RHMYFRCHV",
+            "10"];
+
+        let standard_hex = main_handler(to_string_vec(&tt_args)).unwrap();
+        let sponsor_args = [
+            "sponsor",
+            "a2e1ba1aac6b96dfdfd80a26ba2a2ba45b3aabaa0d0e1d8a4cba82b57cb8d6a401",
+            "2",
+            "7",
+            standard_hex.as_str()];
+
+        assert!(format!("{}", main_handler(to_string_vec(&sponsor_args)).unwrap_err())
+                .contains("not sponsored"));
+    }
+
+    #[test]
+    fn multisig_token_transfer() {
+        let secret_keys : Vec<StacksPrivateKey> = [
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "a2e1ba1aac6b96dfdfd80a26ba2a2ba45b3aabaa0d0e1d8a4cba82b57cb8d6a401",
+            "6d430bb91222408e7706c9001cfaeb91b08c2be6d5ac95779ab52c6b431950e001"]
+            .iter().map(|sk| StacksPrivateKey::from_hex(sk).unwrap()).collect();
+        let public_keys : Vec<String> = secret_keys.iter()
+            .map(|sk| StacksPublicKey::from_private(sk).to_hex()).collect();
+
+        let addr_args = [
+            "--testnet",
+            "multisig-address",
+            "2",
+            public_keys[0].as_str(),
+            public_keys[1].as_str(),
+            public_keys[2].as_str()];
+
+        let addr_json : serde_json::Value = serde_json::from_str(&main_handler(to_string_vec(&addr_args)).unwrap()).unwrap();
+        assert_eq!(addr_json["hashMode"], "p2sh");
+        assert_eq!(addr_json["threshold"], 2);
+
+        let origin_public_keys = public_keys.join(",");
+        let tt_args = [
+            "--testnet",
+            "--multisig",
+            "2",
+            "token-transfer",
+            origin_public_keys.as_str(),
+            "1",
+            "4",
+            "ST1A14RBKJ289E3DP89QAZE2RRHDPWP5RHMYFRCHV",
+            "10"];
+
+        let mut tx_hex = main_handler(to_string_vec(&tt_args)).unwrap();
+
+        // not signed at all yet
+        assert!(StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex).unwrap())).is_err());
+
+        let fields = [
+            ("-s", secret_keys[0].to_hex()),
+            ("-s", secret_keys[1].to_hex()),
+            ("-p", public_keys[2].clone())];
+
+        for (i, &(flag, ref field)) in fields.iter().enumerate() {
+            let append_args = [
+                "append-signature",
+                tx_hex.as_str(),
+                flag,
+                field.as_str()];
+
+            tx_hex = main_handler(to_string_vec(&append_args)).unwrap();
+
+            // only decodable by consensus rules once all signatures are in
+            let decoded = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex).unwrap()));
+            assert_eq!(decoded.is_ok(), i >= 1);
+        }
+
+        let tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex).unwrap())).unwrap();
+        assert!(tx.verify().is_ok());
+        assert_eq!(tx.get_origin_nonce(), 4);
+        assert_eq!(tx.origin_address().to_string(), addr_json["stacksAddress"].as_str().unwrap());
+
+        // a third signature is one too many
+        let append_args = [
+            "append-signature",
+            tx_hex.as_str(),
+            "-s",
+            "6d430bb91222408e7706c9001cfaeb91b08c2be6d5ac95779ab52c6b431950e001"];
+
+        assert!(main_handler(to_string_vec(&append_args)).is_err());
+    }
+
+    #[test]
+    fn post_conditions() {
+        let addr = StacksAddress::from_string(&"SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4".to_string()).unwrap();
+        let asset = AssetInfo {
+            contract_address: addr.clone(),
+            contract_name: ContractName::try_from("foo-contract".to_string()).unwrap(),
+            asset_name: ClarityName::try_from("fookens".to_string()).unwrap()
+        };
+
+        assert_eq!(parse_post_condition("stx:origin:lte:1000").unwrap(),
+                   TransactionPostCondition::STX(PostConditionPrincipal::Origin, FungibleConditionCode::SentLe, 1000));
+        assert_eq!(parse_post_condition("ft:SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4:SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract::fookens:gt:12").unwrap(),
+                   TransactionPostCondition::Fungible(PostConditionPrincipal::Standard(addr.clone()), asset.clone(), FungibleConditionCode::SentGt, 12));
+        assert_eq!(parse_post_condition("nft:SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract:SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract::fookens:not-sent:u1").unwrap(),
+                   TransactionPostCondition::Nonfungible(PostConditionPrincipal::Contract(addr.clone(), ContractName::try_from("foo-contract".to_string()).unwrap()),
+                                                         asset.clone(), Value::UInt(1), NonfungibleConditionCode::NotSent));
+        assert_eq!(parse_post_condition("nft:origin:SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract::fookens:sent:0x0100000000000000000000000000000001").unwrap(),
+                   TransactionPostCondition::Nonfungible(PostConditionPrincipal::Origin, asset.clone(), Value::UInt(1), NonfungibleConditionCode::Sent));
+
+        assert!(format!("{}", parse_post_condition("btc:origin:lte:1000").unwrap_err()).contains("Unrecognized post-condition"));
+        assert!(format!("{}", parse_post_condition("stx:origin:lte").unwrap_err()).contains("too few parts"));
+        assert!(format!("{}", parse_post_condition("stx:origin:le:1000").unwrap_err()).contains("Unrecognized fungible post-condition code"));
+        assert!(format!("{}", parse_post_condition("nft:origin:SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract::fookens:lte:u1").unwrap_err())
+                .contains("Unrecognized non-fungible post-condition code"));
+        assert!(format!("{}", parse_post_condition("ft:origin:SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract:lte:1").unwrap_err())
+                .contains("ADDRESS.CONTRACT-NAME::ASSET-NAME"));
+
+        let cc_args = [
+            "--post-condition",
+            "stx:origin:lte:1000",
+            "--post-condition",
+            "ft:SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract:SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract::fookens:eq:2",
+            "contract-call",
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "1",
+            "0",
+            "SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4",
+            "foo-contract",
+            "transfer-fookens",
+            "-e",
+            "u2"];
+
+        let tx_hex = main_handler(to_string_vec(&cc_args)).unwrap();
+        let tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex).unwrap())).unwrap();
+        assert_eq!(tx.post_condition_mode, TransactionPostConditionMode::Deny);
+        assert_eq!(tx.post_conditions.len(), 2);
+
+        let description = main_handler(to_string_vec(&["decode-tx", tx_hex.as_str()])).unwrap();
+        assert!(description.contains("Payload: contract call: (transfer-fookens u2) on SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract"));
+        assert!(description.contains("Post-condition mode: deny"));
+        assert!(description.contains("1. the origin account must send at most 1000 microSTX"));
+        assert!(description.contains("2. SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract must send exactly 2 of SPJT598WY1RJN792HRKRHRQYFB7RJ5ZCG6J6GEZ4.foo-contract::fookens"));
+
+        // deny mode is the default, even without post-conditions
+        let tx_hex = main_handler(to_string_vec(&cc_args[4..])).unwrap();
+        let tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex).unwrap())).unwrap();
+        assert_eq!(tx.post_condition_mode, TransactionPostConditionMode::Deny);
+        assert_eq!(tx.post_conditions.len(), 0);
+        assert!(main_handler(to_string_vec(&["decode-tx", tx_hex.as_str()])).unwrap().contains("Post-conditions: none"));
+
+        // ...and can be asked for explicitly
+        let mut deny_args = vec!["--deny-mode"];
+        deny_args.extend_from_slice(&cc_args);
+        let tx_hex = main_handler(to_string_vec(&deny_args)).unwrap();
+        let tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex).unwrap())).unwrap();
+        assert_eq!(tx.post_condition_mode, TransactionPostConditionMode::Deny);
+        assert_eq!(tx.post_conditions.len(), 2);
+
+        let mut both_args = vec!["--allow-mode", "--deny-mode"];
+        both_args.extend_from_slice(&cc_args[4..]);
+        assert!(format!("{}", main_handler(to_string_vec(&both_args)).unwrap_err())
+                .contains("cannot both be given"));
+
+        // allow mode must be asked for
+        let mut allow_args = vec!["--allow-mode"];
+        allow_args.extend_from_slice(&cc_args[4..]);
+        let tx_hex = main_handler(to_string_vec(&allow_args)).unwrap();
+        let tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex).unwrap())).unwrap();
+        assert_eq!(tx.post_condition_mode, TransactionPostConditionMode::Allow);
+        assert!(main_handler(to_string_vec(&["decode-tx", tx_hex.as_str()])).unwrap().contains("Post-condition mode: allow"));
+
+        let tt_args = [
+            "--post-condition",
+            "stx:origin:lte:1000",
+            "token-transfer",
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "1",
+            "0",
+            "ST1A14RBKJ289E3DP89QAZE2RRHDPWP5RHMYFRCHV",
+            "10"];
+
+        assert!(format!("{}", main_handler(to_string_vec(&tt_args)).unwrap_err())
+                .contains("do not support post-conditions"));
+    }
+
+    #[test]
+    fn offline_signing() {
+        let secret_key_hex = "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3";
+        let public_key = StacksPublicKey::from_private(&StacksPrivateKey::from_hex(secret_key_hex).unwrap());
+
+        let test_dir = make_test_dir("offline-signing");
+        let envelope_path = test_dir.join("envelope.json");
+        let envelope_path = envelope_path.to_str().unwrap();
+        let secret_key_path = test_dir.join("offline-signing.key");
+        let secret_key_path = secret_key_path.to_str().unwrap();
+        let other_secret_key_path = test_dir.join("offline-signing-other.key");
+        let other_secret_key_path = other_secret_key_path.to_str().unwrap();
+        fs::write(secret_key_path, format!("{}\n", secret_key_hex)).unwrap();
+        fs::write(other_secret_key_path, "a2e1ba1aac6b96dfdfd80a26ba2a2ba45b3aabaa0d0e1d8a4cba82b57cb8d6a401").unwrap();
+
+        let public_key_hex = public_key.to_hex();
+        let build_args = [
+            "build",
+            "token-transfer",
+            public_key_hex.as_str(),
+            "1",
+            "5",
+            "ST1A14RBKJ289E3DP89QAZE2RRHDPWP5RHMYFRCHV",
+            "10"];
+
+        let unsigned_json = main_handler(to_string_vec(&build_args)).unwrap();
+        let unsigned_envelope : SigningEnvelope = serde_json::from_str(&unsigned_json).unwrap();
+        assert_eq!(unsigned_envelope.nonce, 5);
+        assert_eq!(unsigned_envelope.fee_rate, 1);
+        assert_eq!(unsigned_envelope.signatures, 0);
+        assert_eq!(unsigned_envelope.signatures_required, 1);
+        fs::write(envelope_path, &unsigned_json).unwrap();
+
+        // not signed yet
+        assert!(format!("{}", main_handler(to_string_vec(&["finalize", envelope_path])).unwrap_err())
+                .contains("0 of its 1 required signatures"));
+
+        // wrong key
+        assert!(format!("{}", main_handler(to_string_vec(&["sign", envelope_path, other_secret_key_path])).unwrap_err())
+                .contains("but the transaction must be signed by"));
+
+        // envelope doesn't match its transaction
+        let mut bad_envelope = unsigned_envelope.clone();
+        bad_envelope.sighash_presign = "00".repeat(32);
+        fs::write(envelope_path, serde_json::to_string(&bad_envelope).unwrap()).unwrap();
+        assert!(format!("{}", main_handler(to_string_vec(&["sign", envelope_path, secret_key_path])).unwrap_err())
+                .contains("does not match the transaction's sighash"));
+
+        let mut bad_envelope = unsigned_envelope.clone();
+        bad_envelope.nonce = 6;
+        fs::write(envelope_path, serde_json::to_string(&bad_envelope).unwrap()).unwrap();
+        assert!(format!("{}", main_handler(to_string_vec(&["sign", envelope_path, secret_key_path])).unwrap_err())
+                .contains("do not match its transaction"));
+
+        fs::write(envelope_path, &unsigned_json).unwrap();
+        let signed_json = main_handler(to_string_vec(&["sign", envelope_path, secret_key_path])).unwrap();
+        let signed_envelope : SigningEnvelope = serde_json::from_str(&signed_json).unwrap();
+        assert_eq!(signed_envelope.signatures, 1);
+        assert_eq!(signed_envelope.signer_address, unsigned_envelope.signer_address);
+
+        fs::write(envelope_path, &signed_json).unwrap();
+        let tx_hex = main_handler(to_string_vec(&["finalize", envelope_path])).unwrap();
+        let tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex).unwrap())).unwrap();
+        assert!(tx.verify().is_ok());
+
+        // same as signing it online
+        let tt_args = [
+            "token-transfer",
+            secret_key_hex,
+            "1",
+            "5",
+            "ST1A14RBKJ289E3DP89QAZE2RRHDPWP5RHMYFRCHV",
+            "10"];
+        assert_eq!(tx_hex, main_handler(to_string_vec(&tt_args)).unwrap());
+
+        assert!(format!("{}", main_handler(to_string_vec(&["build", "sponsor"])).unwrap_err())
+                .contains("Cannot build"));
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn offline_signing_multisig() {
+        let secret_keys = [
+            "043ff5004e3d695060fa48ac94c96049b8c14ef441c50a184a6a3875d2a000f3",
+            "a2e1ba1aac6b96dfdfd80a26ba2a2ba45b3aabaa0d0e1d8a4cba82b57cb8d6a401",
+            "6d430bb91222408e7706c9001cfaeb91b08c2be6d5ac95779ab52c6b431950e001"];
+        let public_keys : Vec<String> = secret_keys.iter()
+            .map(|sk| StacksPublicKey::from_private(&StacksPrivateKey::from_hex(sk).unwrap()).to_hex()).collect();
+
+        let test_dir = make_test_dir("offline-signing-multisig");
+        let envelope_path = test_dir.join("envelope.json");
+        let envelope_path = envelope_path.to_str().unwrap();
+        let secret_key_paths : Vec<String> = secret_keys.iter().enumerate()
+            .map(|(i, sk)| {
+                let path = test_dir.join(format!("co-signer-{}.key", i));
+                fs::write(&path, sk).unwrap();
+                path.to_str().unwrap().to_string()
+            })
+            .collect();
+
+        let origin_public_keys = public_keys.join(",");
+        let build_args = [
+            "--testnet",
+            "--multisig",
+            "2",
+            "build",
+            "token-transfer",
+            origin_public_keys.as_str(),
+            "1",
+            "4",
+            "ST1A14RBKJ289E3DP89QAZE2RRHDPWP5RHMYFRCHV",
+            "10"];
+
+        let unsigned_json = main_handler(to_string_vec(&build_args)).unwrap();
+        let unsigned_envelope : SigningEnvelope = serde_json::from_str(&unsigned_json).unwrap();
+        assert_eq!(unsigned_envelope.signatures_required, 2);
+        assert_eq!(unsigned_envelope.signer_public_keys, public_keys);
+
+        // the public keys must make up the signer address
+        let mut bad_envelope = unsigned_envelope.clone();
+        bad_envelope.signer_public_keys.swap(0, 1);
+        fs::write(envelope_path, serde_json::to_string(&bad_envelope).unwrap()).unwrap();
+        assert!(format!("{}", main_handler(to_string_vec(&["sign", envelope_path, secret_key_paths[0].as_str()])).unwrap_err())
+                .contains("do not make up its transaction's signer address"));
+
+        // co-signers sign in order
+        fs::write(envelope_path, &unsigned_json).unwrap();
+        assert!(format!("{}", main_handler(to_string_vec(&["sign", envelope_path, secret_key_paths[1].as_str()])).unwrap_err())
+                .contains("but the next co-signer is"));
+
+        let mut envelope_json = unsigned_json;
+        for (i, secret_key_path) in secret_key_paths[0..2].iter().enumerate() {
+            fs::write(envelope_path, &envelope_json).unwrap();
+            envelope_json = main_handler(to_string_vec(&["sign", envelope_path, secret_key_path])).unwrap();
+
+            let envelope : SigningEnvelope = serde_json::from_str(&envelope_json).unwrap();
+            assert_eq!(envelope.signatures, (i + 1) as u16);
+            assert_eq!(envelope.signer_public_keys, public_keys);
+        }
+
+        fs::write(envelope_path, &envelope_json).unwrap();
+        let tx_hex = main_handler(to_string_vec(&["finalize", envelope_path])).unwrap();
+        let tx = StacksTransaction::consensus_deserialize(&mut io::Cursor::new(&hex_bytes(&tx_hex).unwrap())).unwrap();
+        assert!(tx.verify().is_ok());
+        assert_eq!(tx.get_origin_nonce(), 4);
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
     #[test]
     fn simple_cc() {
         let cc_args = [
@@ -18,6 +18,7 @@
 */
 
 use std::ops::Deref;
+use std::collections::HashMap;
 
 use deps;
 use deps::bitcoin::blockdata::block::{LoneBlockHeader, Block};
@@ -28,7 +29,7 @@ use deps::bitcoin::blockdata::script::{Script, Instruction};
 use deps::bitcoin::network::serialize::BitcoinHash;
 use deps::bitcoin::network::message as btc_message;
 
-use deps::bitcoin::util::hash::bitcoin_merkle_root;
+use deps::bitcoin::util::hash::{bitcoin_merkle_root, Sha256dHash};
 
 use burnchains::bitcoin::indexer::BitcoinIndexer;
 use burnchains::bitcoin::Error as btc_error;
@@ -106,8 +107,8 @@ impl BurnBlockIPC for BitcoinBlockIPC {
 }
 
 pub struct BitcoinBlockDownloader {
-    cur_request: Option<BitcoinHeaderIPC>,
-    cur_block: Option<BitcoinBlockIPC>,
+    cur_requests: Vec<BitcoinHeaderIPC>,
+    cur_blocks: HashMap<Sha256dHash, BitcoinBlockIPC>,
     indexer: Option<BitcoinIndexer>
 }
 
@@ -119,26 +120,57 @@ pub struct BitcoinBlockParser {
 impl BitcoinBlockDownloader {
     pub fn new(indexer: BitcoinIndexer) -> BitcoinBlockDownloader {
         BitcoinBlockDownloader {
-            cur_request: None,
-            cur_block: None,
+            cur_requests: vec![],
+            cur_blocks: HashMap::new(),
             indexer: Some(indexer)
         }
     }
 
     pub fn run(&mut self, header: &BitcoinHeaderIPC) -> Result<BitcoinBlockIPC, btc_error> {
-        self.cur_request = Some((*header).clone());
+        let mut ipc_blocks = self.run_batch(&vec![(*header).clone()])?;
+        assert_eq!(ipc_blocks.len(), 1);
+        Ok(ipc_blocks.pop().unwrap())
+    }
+
+    /// Download a batch of blocks with pipelined getdata requests.
+    /// If the peer stalls or sends us a bad block, we fail over to another peer and ask it for
+    /// whatever blocks we're still missing.
+    /// Returns the blocks in the same order as the headers.
+    pub fn run_batch(&mut self, headers: &Vec<BitcoinHeaderIPC>) -> Result<Vec<BitcoinBlockIPC>, btc_error> {
+        if headers.len() == 0 {
+            return Ok(vec![]);
+        }
+
+        self.cur_requests = headers.clone();
+        self.cur_blocks.clear();
         
         // should always work, since at most one thread can call this method at once
         // due to &mut self.
         let mut indexer = self.indexer.take().unwrap();
         
-        indexer.peer_communicate(self, false)?; 
+        let res = indexer.peer_communicate(self, false); 
         
         self.indexer = Some(indexer);
+        res?;
+
+        let mut ipc_blocks = Vec::with_capacity(headers.len());
+        for header in self.cur_requests.iter() {
+            let block_hash = header.block_header.header.bitcoin_hash();
+            let ipc_block = self.cur_blocks.remove(&block_hash).expect("BUG: finished downloading without all blocks");
+            ipc_blocks.push(ipc_block);
+        }
 
-        assert!(self.cur_block.is_some());
-        let ipc_block = self.cur_block.take().unwrap();
-        Ok(ipc_block)
+        self.cur_requests.clear();
+        Ok(ipc_blocks)
+    }
+
+    /// Hashes of the requested blocks we don't have yet
+    fn missing_block_hashes(&self) -> Vec<Sha256dHash> {
+        self.cur_requests
+            .iter()
+            .map(|h| h.block_header.header.bitcoin_hash())
+            .filter(|h| !self.cur_blocks.contains_key(h))
+            .collect()
     }
 }
 
@@ -150,72 +182,76 @@ impl BurnchainBlockDownloader for BitcoinBlockDownloader {
         self.run(header)
             .map_err(burnchain_error::DownloadError)
     }
+
+    fn download_batch(&mut self, headers: &Vec<BitcoinHeaderIPC>) -> Result<Vec<BitcoinBlockIPC>, burnchain_error> {
+        self.run_batch(headers)
+            .map_err(burnchain_error::DownloadError)
+    }
 }
 
 impl BitcoinMessageHandler for BitcoinBlockDownloader {
     /// Trait message handler 
-    /// initiate the conversation with the bitcoin peer
+    /// initiate the conversation with the bitcoin peer -- ask for all the blocks we still need
     fn begin_session(&mut self, indexer: &mut BitcoinIndexer) -> Result<bool, btc_error> {
-        match self.cur_request {
-            None => panic!("No block header set"),
-            Some(ref ipc_header) => {
-                let block_hash = ipc_header.block_header.header.bitcoin_hash().clone();
-                indexer.send_getdata(&vec![block_hash])
-                    .and_then(|_r| Ok(true))
-            }
+        if self.cur_requests.len() == 0 {
+            panic!("No block header set");
         }
-    }
 
-    /// Trait message handler
-    /// Wait for a block to arrive that matches self.cur_request
-    fn handle_message(&mut self, indexer: &mut BitcoinIndexer, msg: PeerMessage) -> Result<bool, btc_error> {
-        // send to our consumer thread for parsing
-        if self.cur_block.is_some() {
-            debug!("Already have a block");
+        let block_hashes = self.missing_block_hashes();
+        if block_hashes.len() == 0 {
             return Ok(false);
         }
 
-        if self.cur_request.is_none() {
+        indexer.send_getdata(&block_hashes)
+            .and_then(|_r| Ok(true))
+    }
+
+    /// Trait message handler
+    /// Collect blocks that match our requests, until we have all of them
+    fn handle_message(&mut self, _indexer: &mut BitcoinIndexer, msg: PeerMessage) -> Result<bool, btc_error> {
+        if self.cur_requests.len() == 0 {
             panic!("No block header set");
         }
 
-        let ipc_header = self.cur_request.clone().unwrap();
-
-        let height;
-        let header;
-        let block_hash;
-
         match msg.deref() {
             btc_message::NetworkMessage::Block(block) => {
+                let block_hash = block.bitcoin_hash();
+                let ipc_header = match self.cur_requests.iter().find(|h| h.block_header.header.bitcoin_hash() == block_hash) {
+                    Some(h) => h.clone(),
+                    None => {
+                        debug!("Got unrequested block {}", &to_hex(block_hash.as_bytes()));
+                        return Ok(true);
+                    }
+                };
+
+                if self.cur_blocks.contains_key(&block_hash) {
+                    debug!("Already have block {}", &to_hex(block_hash.as_bytes()));
+                    return Ok(true);
+                }
+
                 // make sure this block matches
                 if !BitcoinBlockParser::check_block(&block, &ipc_header.block_header) {
-                    debug!("Requested block {}, got block {}", &to_hex(ipc_header.block_header.header.bitcoin_hash().as_bytes()), &to_hex(block.bitcoin_hash().as_bytes()));
-                    
-                    // try again 
-                    indexer.send_getdata(&vec![ipc_header.block_header.header.bitcoin_hash()])?;
-                    return Ok(true);
+                    // this peer is feeding us bad data.  Drop it and try another.
+                    warn!("Block {} does not match its header", &to_hex(block_hash.as_bytes()));
+                    return Err(btc_error::ConnectionBroken);
                 }
 
-                // got valid data!
-                height = ipc_header.block_height;
-                header = ipc_header.clone();
-                block_hash = ipc_header.block_header.header.bitcoin_hash();
+                debug!("Got block {}: {}", ipc_header.block_height, &to_hex(BurnchainHeaderHash::from_bitcoin_hash(&block_hash).as_bytes()));
+
+                let ipc_block = BitcoinBlockIPC {
+                    header_data: ipc_header,
+                    block_message: msg.clone()
+                };
+
+                self.cur_blocks.insert(block_hash, ipc_block);
+                
+                // keep going until we have all of them
+                Ok(self.missing_block_hashes().len() > 0)
             },
             _ => { 
-                return Err(btc_error::UnhandledMessage(msg.clone()));
+                Err(btc_error::UnhandledMessage(msg.clone()))
             }
         }
-
-        debug!("Got block {}: {}", height, &to_hex(BurnchainHeaderHash::from_bitcoin_hash(&block_hash).as_bytes()));
-
-        // store response. we're done.
-        let ipc_block = BitcoinBlockIPC {
-            header_data: header,
-            block_message: msg
-        };
-
-        self.cur_block = Some(ipc_block);
-        Ok(false)
     }
 }
 
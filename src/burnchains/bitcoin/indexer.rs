@@ -18,11 +18,14 @@
 */
 
 use std::fs;
+use std::cmp;
 use std::path;
 use std::net;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::sync::{Arc, Mutex, LockResult, MutexGuard};
+use std::collections::HashMap;
+use std::time;
 use rand::{Rng, thread_rng};
 use std::path::{PathBuf};
 
@@ -49,6 +52,7 @@ use deps::bitcoin::network::message::NetworkMessage;
 use deps::bitcoin::network::serialize::BitcoinHash;
 
 use util::log;
+use util::get_epoch_time_secs;
 
 pub const USER_AGENT: &'static str = "Blockstack Core v21";
 
@@ -88,7 +92,18 @@ pub struct BitcoinIndexerConfig {
     pub timeout: u32,
     pub spv_headers_path: String,
     pub first_block: u64,
-    pub magic_bytes: MagicBytes
+    pub magic_bytes: MagicBytes,
+    pub checkpoints: Vec<SpvCheckpoint>,
+    // fallback peers, tried after peer_host:peer_port
+    pub peers: Vec<(String, u16)>
+}
+
+/// How reliable a bitcoin peer has been
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitcoinPeerHealth {
+    pub consecutive_failures: u64,
+    pub total_failures: u64,
+    pub last_failure: u64
 }
 
 #[derive(Debug, Clone)]
@@ -99,6 +114,9 @@ pub struct BitcoinIndexerRuntime {
     pub version_nonce: u64,
     pub network_id: BitcoinNetworkType,
     pub block_height: u64,
+    pub peer_host: String,
+    pub peer_port: u16,
+    pub peer_health: HashMap<(String, u16), BitcoinPeerHealth>,
 }
 
 pub struct BitcoinIndexer {
@@ -119,7 +137,9 @@ impl BitcoinIndexerConfig {
             timeout: 30,
             spv_headers_path: "./spv-headers.dat".to_string(),
             first_block: FIRST_BLOCK_MAINNET,
-            magic_bytes: BLOCKSTACK_MAGIC_MAINNET.clone()
+            magic_bytes: BLOCKSTACK_MAGIC_MAINNET.clone(),
+            checkpoints: vec![],
+            peers: vec![]
         }
     }
 
@@ -128,6 +148,16 @@ impl BitcoinIndexerConfig {
 
        let username = self.username.clone().unwrap_or("".to_string());
        let password = self.password.clone().unwrap_or("".to_string());
+       let checkpoints = self.checkpoints
+           .iter()
+           .map(|cp| cp.to_config_str())
+           .collect::<Vec<String>>()
+           .join(",");
+       let peers = self.peers
+           .iter()
+           .map(|&(ref host, ref port)| format!("{}:{}", host, port))
+           .collect::<Vec<String>>()
+           .join(",");
 
        conf.with_section(Some("bitcoin".to_owned()))
            .set("server", self.peer_host.as_str())
@@ -138,7 +168,9 @@ impl BitcoinIndexerConfig {
            .set("password", password.as_str())
            .set("timeout", format!("{}", self.timeout).as_str())
            .set("spv_path", self.spv_headers_path.as_str())
-           .set("first_block", format!("{}", self.first_block).as_str());
+           .set("first_block", format!("{}", self.first_block).as_str())
+           .set("checkpoints", checkpoints.as_str())
+           .set("peers", peers.as_str());
 
        conf.with_section(Some("blockstack".to_owned()))
            .set("network_id", format!("{}{}", self.magic_bytes.as_bytes()[0] as char, self.magic_bytes.as_bytes()[1] as char).as_str());
@@ -219,6 +251,40 @@ impl BitcoinIndexerConfig {
                
                let rpc_ssl = rpc_ssl_str == "1" || rpc_ssl_str == "true";
 
+               // comma-separated list of height:hash:bits:timestamp
+               let mut checkpoints = vec![];
+               if let Some(checkpoints_str) = bitcoin_section.get("checkpoints") {
+                   for checkpoint_str in checkpoints_str.split(',') {
+                       if checkpoint_str.trim().len() == 0 {
+                           continue;
+                       }
+                       checkpoints.push(SpvCheckpoint::from_config_str(checkpoint_str)?);
+                   }
+               }
+
+               // comma-separated list of host:port
+               let mut peers = vec![];
+               if let Some(peers_str) = bitcoin_section.get("peers") {
+                   for peer_str in peers_str.split(',') {
+                       let peer_str = peer_str.trim();
+                       if peer_str.len() == 0 {
+                           continue;
+                       }
+
+                       let sep = peer_str.rfind(':')
+                           .ok_or(btc_error::ConfigError(format!("Invalid bitcoin:peers entry '{}': expected host:port", peer_str)))?;
+
+                       let host = peer_str[0..sep].to_string();
+                       let port : u16 = peer_str[(sep+1)..].parse()
+                           .map_err(|_e| btc_error::ConfigError(format!("Invalid bitcoin:peers port in '{}'", peer_str)))?;
+
+                       if host.len() == 0 || port <= 1024 || port >= 65535 {
+                           return Err(btc_error::ConfigError(format!("Invalid bitcoin:peers entry '{}'", peer_str)));
+                       }
+                       peers.push((host, port));
+                   }
+               }
+
                // [blockstack]
                let blockstack_section_opt = ini_file.section(Some("blockstack").to_owned());
                if None == blockstack_section_opt {
@@ -247,7 +313,9 @@ impl BitcoinIndexerConfig {
                    timeout: timeout,
                    spv_headers_path: spv_headers_path.to_string(),
                    first_block: first_block,
-                   magic_bytes: blockstack_magic
+                   magic_bytes: blockstack_magic,
+                   checkpoints: checkpoints,
+                   peers: peers
                };
                
                Ok(cfg)
@@ -270,6 +338,19 @@ impl BitcoinIndexerRuntime {
             version_nonce: rng.gen(),
             network_id: network_id,
             block_height: 0,
+            peer_host: "".to_string(),
+            peer_port: 0,
+            peer_health: HashMap::new(),
+        }
+    }
+}
+
+impl BitcoinPeerHealth {
+    pub fn new() -> BitcoinPeerHealth {
+        BitcoinPeerHealth {
+            consecutive_failures: 0,
+            total_failures: 0,
+            last_failure: 0
         }
     }
 }
@@ -286,24 +367,82 @@ impl BitcoinIndexer {
     }
 
     pub fn dup(&self) -> BitcoinIndexer {
+        let mut runtime = BitcoinIndexerRuntime::new(self.runtime.network_id);
+        runtime.peer_health = self.runtime.peer_health.clone();
         BitcoinIndexer {
             config: self.config.clone(),
-            runtime: BitcoinIndexerRuntime::new(self.runtime.network_id)
+            runtime: runtime
+        }
+    }
+
+    /// Get all of our configured peers, starting with the primary one
+    pub fn get_peers(&self) -> Vec<(String, u16)> {
+        let mut peers = vec![(self.config.peer_host.clone(), self.config.peer_port)];
+        for peer in self.config.peers.iter() {
+            if !peers.contains(peer) {
+                peers.push(peer.clone());
+            }
         }
+        peers
     }
 
-    /// (re)connect to our configured network peer.
-    /// Sets self.runtime.sock to a new socket referring to our configured
+    /// Choose the peer to connect to -- the one with the fewest consecutive failures.
+    /// Ties go to the peer that failed longest ago, and then to the earliest-configured peer.
+    pub fn select_peer(&self) -> (String, u16) {
+        let peers = self.get_peers();
+        let mut best = 0;
+        let mut best_health = BitcoinPeerHealth::new();
+        for i in 0..peers.len() {
+            let health = self.runtime.peer_health.get(&peers[i]).cloned().unwrap_or(BitcoinPeerHealth::new());
+            if i == 0 || (health.consecutive_failures, health.last_failure) < (best_health.consecutive_failures, best_health.last_failure) {
+                best = i;
+                best_health = health;
+            }
+        }
+        peers[best].clone()
+    }
+
+    /// Record that the current peer failed us (i.e. we couldn't connect, it stalled, or it sent
+    /// us bad data).  We'll prefer other peers the next time we connect.
+    pub fn peer_failed(&mut self) -> () {
+        let peer = (self.runtime.peer_host.clone(), self.runtime.peer_port);
+        let health = self.runtime.peer_health.entry(peer).or_insert(BitcoinPeerHealth::new());
+        health.consecutive_failures += 1;
+        health.total_failures += 1;
+        health.last_failure = get_epoch_time_secs();
+
+        warn!("Bitcoin peer {}:{} failed ({} consecutive failures)", self.runtime.peer_host, self.runtime.peer_port, health.consecutive_failures);
+    }
+
+    /// Record that we successfully talked to the current peer
+    pub fn peer_succeeded(&mut self) -> () {
+        let peer = (self.runtime.peer_host.clone(), self.runtime.peer_port);
+        let health = self.runtime.peer_health.entry(peer).or_insert(BitcoinPeerHealth::new());
+        health.consecutive_failures = 0;
+    }
+
+    /// (re)connect to the healthiest configured network peer.
+    /// Sets self.runtime.sock to a new socket referring to the chosen
     /// Bitcoin peer.  If we fail to connect, this method sets the socket
-    /// to None.
+    /// to None and marks the peer as failed.
     fn reconnect_peer(&mut self) -> Result<(), btc_error> {
-        match net::TcpStream::connect((self.config.peer_host.as_str(), self.config.peer_port)) {
+        let (peer_host, peer_port) = self.select_peer();
+        self.runtime.peer_host = peer_host;
+        self.runtime.peer_port = peer_port;
+
+        match net::TcpStream::connect((self.runtime.peer_host.as_str(), self.runtime.peer_port)) {
             Ok(s) => {
+                // a peer that goes quiet mid-conversation has stalled
+                if self.config.timeout > 0 {
+                    s.set_read_timeout(Some(time::Duration::from_secs(self.config.timeout as u64)))
+                        .map_err(btc_error::Io)?;
+                }
                 self.runtime.sock = Arc::new(Mutex::new(Some(s)));
                 Ok(())
             },
             Err(_e) => {
                 self.runtime.sock = Arc::new(Mutex::new(None));
+                self.peer_failed();
                 Err(btc_error::ConnectionError)
             }
         }
@@ -374,6 +513,7 @@ impl BitcoinIndexer {
                     }
                     Err(btc_error::ConnectionBroken) => {
                         debug!("Re-establish peer connection");
+                        self.peer_failed();
                         do_handshake = true;
                     }
                     Err(e) => {
@@ -407,6 +547,7 @@ impl BitcoinIndexer {
                         }
                         Err(btc_error::ConnectionBroken) => {
                             debug!("Re-establish peer connection");
+                            self.peer_failed();
                             do_handshake = true;
                         }
                         Err(e) => {
@@ -416,10 +557,13 @@ impl BitcoinIndexer {
                     }
                 }
                 Err(btc_error::ConnectionBroken) => {
+                    self.peer_failed();
                     do_handshake = true;
                 }
                 Err(e) => {
+                    // includes timeouts from a stalled peer
                     warn!("Unhandled error while receiving a message: {:?}", e);
+                    self.peer_failed();
                     do_handshake = true;
                 }
             }
@@ -427,12 +571,43 @@ impl BitcoinIndexer {
         Ok(())
     }
 
+    /// Get the hard-coded checkpoints for our network, plus any configured ones
+    pub fn get_checkpoints(&self) -> Result<Vec<SpvCheckpoint>, btc_error> {
+        let mut checkpoints = SpvCheckpoint::defaults(self.runtime.network_id)?;
+        checkpoints.extend_from_slice(&self.config.checkpoints);
+        checkpoints.sort_by_key(|cp| cp.block_height);
+        Ok(checkpoints)
+    }
+
+    /// Get the height of the first header we keep -- the nearest checkpoint at or below the
+    /// first block height.  Headers below this are never fetched.
+    pub fn get_spv_start_height(&self) -> Result<u64, btc_error> {
+        let checkpoints = self.get_checkpoints()?;
+        let first_block_height = self.get_first_block_height();
+        Ok(SpvCheckpoint::find_nearest(&checkpoints, first_block_height)
+           .map(|cp| cp.block_height)
+           .unwrap_or(0))
+    }
+
     /// Synchronize a range of headers from bitcoin to a specific file.
     /// If last_block is None, then sync as many headers as the remote peer has to offer.
+    /// If the file doesn't exist yet, then start from the nearest checkpoint at or below
+    /// start_block.
     /// Returns the height of the last block fetched
     pub fn sync_last_headers(&mut self, path: &String, start_block: u64, last_block: Option<u64>) -> Result<u64, btc_error> {
+        let checkpoints = self.get_checkpoints()?;
+        let start_block = 
+            if !PathBuf::from(path).exists() {
+                SpvCheckpoint::find_nearest(&checkpoints, start_block)
+                    .map(|cp| cp.block_height)
+                    .unwrap_or(0)
+            }
+            else {
+                start_block
+            };
+
         debug!("Sync all headers starting at block {}", start_block);
-        let mut spv_client = SpvClient::new(&path, start_block, last_block, self.runtime.network_id);
+        let mut spv_client = SpvClient::new(&path, start_block, last_block, self.runtime.network_id, &checkpoints);
         spv_client.run(self)
                   .and_then(|_r| Ok(spv_client.end_block_height.unwrap()))
     }
@@ -457,21 +632,18 @@ impl BitcoinIndexer {
         Ok(headers)
     }
 
-    /// Search for a bitcoin reorg.  Return the offset into the canonical bitcoin headers where
-    /// the reorg starts.
+    /// Search for a bitcoin reorg.  Return the height of the last header that the canonical
+    /// bitcoin headers and the peer's headers share, up to and including db_height.  If there is
+    /// no reorg, the returned height is db_height; if there is one, it's less than db_height.
     pub fn find_bitcoin_reorg(&mut self, headers_path: &String, db_height: u64) -> Result<u64, btc_error> {
         let reorg_headers_path = format!("{}.reorg", &headers_path);
-        if PathBuf::from(&reorg_headers_path).exists() {
-            fs::remove_file(&reorg_headers_path)
-                .map_err(|e| {
-                    error!("Failed to remove {}", reorg_headers_path);
-                    btc_error::Io(e)
-                })?;
-        }
-
         let mut new_tip = 0;
         let mut found = false;
 
+        // headers below this height aren't stored
+        let checkpoints = self.get_checkpoints()?;
+        let spv_start_height = self.get_spv_start_height()?;
+
         // what's the last header we have from the canonical history?
         let canonical_end_block = SpvClient::get_headers_height(&headers_path)
             .map_err(|e| {
@@ -484,34 +656,41 @@ impl BitcoinIndexer {
             panic!("Headers is at block {}, but database is at block {}", canonical_end_block, db_height);
         }
         
-        let mut start_block = 
-            if db_height < REORG_BATCH_SIZE {
-                0 
-            }
-            else {
-                db_height - REORG_BATCH_SIZE
-            };
+        let mut start_block = cmp::max(db_height.saturating_sub(REORG_BATCH_SIZE), spv_start_height);
 
-        while start_block > 0 && !found {
+        while !found {
             debug!("Search for reorg'ed headers from {} - {}", start_block, start_block + REORG_BATCH_SIZE);
 
-            // copy over the head of the existing headers so we can fetch to the .reorg file
+            if PathBuf::from(&reorg_headers_path).exists() {
+                fs::remove_file(&reorg_headers_path)
+                    .map_err(|e| {
+                        error!("Failed to remove {}", reorg_headers_path);
+                        btc_error::Io(e)
+                    })?;
+            }
+
+            // copy over the head of the existing headers so we can fetch to the .reorg file.
+            // Copy the whole preceding difficulty interval, so the new headers' work can be
+            // checked.  The genesis header gets written when the .reorg file is created.
             let copy_height_start = 
-                if start_block < REORG_BATCH_SIZE - 1 {
-                    0
+                if start_block < BLOCK_DIFFICULTY_CHUNK_SIZE {
+                    1
                 }
                 else {
-                    start_block - REORG_BATCH_SIZE - 1
+                    cmp::max(1, (start_block / BLOCK_DIFFICULTY_CHUNK_SIZE - 1) * BLOCK_DIFFICULTY_CHUNK_SIZE)
                 };
 
-            let existing_headers = self.read_spv_headers(&headers_path, copy_height_start, start_block + 1)?;
+            let copy_height_start = cmp::max(copy_height_start, spv_start_height);
 
-            let mut spv_client = SpvClient::new(&reorg_headers_path, start_block, Some(start_block + REORG_BATCH_SIZE), self.runtime.network_id);
-            spv_client.write_block_headers(copy_height_start - 1, &existing_headers)
-                .map_err(|e| {
-                    error!("Failed to write block header {} to {}", start_block, &reorg_headers_path);
-                    e
-                })?;
+            let mut spv_client = SpvClient::new(&reorg_headers_path, start_block, Some(start_block + REORG_BATCH_SIZE), self.runtime.network_id, &checkpoints);
+            if copy_height_start <= start_block {
+                let existing_headers = self.read_spv_headers(&headers_path, copy_height_start, start_block + 1)?;
+                spv_client.write_block_headers(copy_height_start - 1, &existing_headers)
+                    .map_err(|e| {
+                        error!("Failed to write block headers {}-{} to {}", copy_height_start, start_block, &reorg_headers_path);
+                        e
+                    })?;
+            }
            
             // get new headers, starting off of this one.
             spv_client.run(self)
@@ -520,29 +699,41 @@ impl BitcoinIndexer {
                     e
                 })?;
 
-            // check for reorg 
-            let canonical_headers = self.read_spv_headers(&headers_path, start_block, start_block + REORG_BATCH_SIZE)
+            // check for reorg, up to and including the last header the db has processed
+            let end_block = cmp::min(start_block + REORG_BATCH_SIZE, db_height) + 1;
+            let canonical_headers = self.read_spv_headers(&headers_path, start_block, end_block)
                 .map_err(|e| {
-                    error!("Failed to read canonical headers from {} to {}", start_block, start_block + REORG_BATCH_SIZE);
+                    error!("Failed to read canonical headers from {} to {}", start_block, end_block);
                     e
                 })?;
 
-            let reorg_headers = self.read_spv_headers(&reorg_headers_path, start_block, start_block + REORG_BATCH_SIZE)
+            let reorg_headers = self.read_spv_headers(&reorg_headers_path, start_block, end_block)
                 .map_err(|e| {
-                    error!("Failed to read reorg headers from {} to {}", start_block, start_block + REORG_BATCH_SIZE);
+                    error!("Failed to read reorg headers from {} to {}", start_block, end_block);
                     e
                 })?;
-              
-            for i in (start_block..(start_block + REORG_BATCH_SIZE)).rev() {
+
+            // headers are hash-linked, so the highest header the two chains share marks the
+            // end of their shared history.
+            let num_headers = cmp::min(canonical_headers.len(), reorg_headers.len()) as u64;
+            for i in (start_block..(start_block + num_headers)).rev() {
                 if canonical_headers[(i - start_block) as usize] == reorg_headers[(i - start_block) as usize] {
                     // shared history 
-                    new_tip = i + 1;
+                    new_tip = i;
                     found = true;
                     break;
                 }
             }
 
-            start_block -= REORG_BATCH_SIZE;
+            if !found {
+                if start_block <= spv_start_height {
+                    // first stored headers differ -- should never happen, since they're checkpointed
+                    error!("No shared history between {} and {}", &headers_path, &reorg_headers_path);
+                    return Err(btc_error::NoncontiguousHeader);
+                }
+
+                start_block = cmp::max(start_block.saturating_sub(REORG_BATCH_SIZE), spv_start_height);
+            }
         }
 
         debug!("Chain history is consistent up to {}", new_tip);
@@ -695,3 +886,893 @@ impl BurnchainIndexer for BitcoinIndexer {
         BitcoinBlockParser::new(self.runtime.network_id, self.config.magic_bytes) 
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use burnchains::bitcoin::blocks::BitcoinBlockIPC;
+
+    use deps::bitcoin::blockdata::block::{Block, BlockHeader, LoneBlockHeader};
+    use deps::bitcoin::blockdata::transaction::{Transaction, TxIn, TxOut, OutPoint};
+    use deps::bitcoin::blockdata::script::{Script, Builder};
+    use deps::bitcoin::blockdata::opcodes::All as btc_opcodes;
+    use deps::bitcoin::network::address as btc_network_address;
+    use deps::bitcoin::network::constants as btc_constants;
+    use deps::bitcoin::network::encodable::{ConsensusEncodable, ConsensusDecodable, VarInt};
+    use deps::bitcoin::network::message as btc_message;
+    use deps::bitcoin::network::message_network as btc_message_network;
+    use deps::bitcoin::network::serialize::{RawEncoder, RawDecoder, BitcoinHash};
+    use deps::bitcoin::util::hash::{Sha256dHash, bitcoin_merkle_root};
+
+    use chainstate::burn::{BlockSnapshot, BlockHeaderHash, ConsensusHash, Opcodes};
+    use chainstate::burn::db::burndb::BurnDB;
+
+    use burnchains::Txid;
+
+    use util::hash::{Hash160, Sha256Sum};
+    use util::secp256k1::{Secp256k1PrivateKey, Secp256k1PublicKey};
+    use util::vrf::{VRFPrivateKey, VRFPublicKey};
+    use util::log;
+
+    /// A Bitcoin chain that a MockBitcoinPeer serves.
+    /// blocks[0] is the genesis block; only its header is ever served.
+    #[derive(Debug, Clone)]
+    pub struct MockBitcoinChain {
+        pub blocks: Vec<Block>,
+        /// the Stacks block committed to in each block, if any
+        pub stacks_blocks: Vec<Option<BlockHeaderHash>>
+    }
+
+    /// Leader keys are registered with the first snapshot's (all-0's) consensus hash, so they
+    /// can only be registered while it's fresh.
+    const MOCK_LAST_KEY_HEIGHT: u64 = 25;
+
+    impl MockBitcoinChain {
+        pub fn new(network_id: BitcoinNetworkType) -> MockBitcoinChain {
+            let genesis_header = SpvClient::genesis_block_header(network_id).unwrap();
+            MockBitcoinChain {
+                blocks: vec![Block {
+                    header: genesis_header.header,
+                    txdata: vec![]
+                }],
+                stacks_blocks: vec![None]
+            }
+        }
+
+        pub fn height(&self) -> u64 {
+            (self.blocks.len() - 1) as u64
+        }
+
+        pub fn tip_hash(&self) -> BurnchainHeaderHash {
+            BurnchainHeaderHash::from_bitcoin_hash(&self.blocks[self.blocks.len() - 1].bitcoin_hash())
+        }
+
+        /// Make a competing chain that shares this chain's blocks up to and including the given height
+        pub fn fork(&self, height: u64) -> MockBitcoinChain {
+            assert!(height <= self.height());
+            MockBitcoinChain {
+                blocks: self.blocks[0..((height + 1) as usize)].to_vec(),
+                stacks_blocks: self.stacks_blocks[0..((height + 1) as usize)].to_vec()
+            }
+        }
+
+        /// Deterministic leader keys for the key registered in the block with the given coinbase
+        fn leader_keys(coinbase_txid: &Sha256dHash) -> (Secp256k1PrivateKey, VRFPrivateKey) {
+            let mut seed = coinbase_txid.as_bytes().to_vec();
+            let privk = Secp256k1PrivateKey::from_slice(Sha256Sum::from_data(&seed).as_bytes()).unwrap();
+            seed.push(0xff);
+            let vrf_privk = VRFPrivateKey::from_bytes(Sha256Sum::from_data(&seed).as_bytes()).unwrap();
+            (privk, vrf_privk)
+        }
+
+        /// Make a burnchain transaction spent by the given key, with an OP_RETURN <magic> <op>
+        /// <payload> output followed by the given (p2pkh hash, amount) outputs.
+        fn make_op_tx(privk: &Secp256k1PrivateKey, opcode: Opcodes, payload: &Vec<u8>, outputs: &Vec<(Hash160, u64)>) -> Transaction {
+            let pubk = Secp256k1PublicKey::from_private(privk);
+
+            let mut data = vec![];
+            data.extend_from_slice(BLOCKSTACK_MAGIC_MAINNET.as_bytes());
+            data.push(opcode as u8);
+            data.extend_from_slice(payload);
+
+            let mut tx_outputs = vec![TxOut {
+                value: 0,
+                script_pubkey: Builder::new().push_opcode(btc_opcodes::OP_RETURN).push_slice(&data).into_script()
+            }];
+            for (hash, amount) in outputs.iter() {
+                tx_outputs.push(TxOut {
+                    value: *amount,
+                    script_pubkey: Builder::new()
+                        .push_opcode(btc_opcodes::OP_DUP)
+                        .push_opcode(btc_opcodes::OP_HASH160)
+                        .push_slice(hash.as_bytes())
+                        .push_opcode(btc_opcodes::OP_EQUALVERIFY)
+                        .push_opcode(btc_opcodes::OP_CHECKSIG)
+                        .into_script()
+                });
+            }
+
+            // the parser doesn't check signatures
+            Transaction {
+                version: 1,
+                lock_time: 0,
+                input: vec![TxIn {
+                    previous_output: OutPoint {
+                        txid: Sha256dHash::from_data(&data),
+                        vout: 1
+                    },
+                    script_sig: Builder::new().push_slice(&[0x30; 71]).push_slice(&pubk.to_bytes_compressed()).into_script(),
+                    sequence: 0xffffffff,
+                    witness: vec![]
+                }],
+                output: tx_outputs
+            }
+        }
+
+        /// Append blocks to this chain.  Each block has a coinbase-like transaction that encodes
+        /// the fork ID and height, so blocks on different forks have different hashes.  While
+        /// leader keys can still be registered, each block also registers a key, and commits to
+        /// a Stacks block (on top of the last block's commit) with the key registered in the
+        /// last block.
+        pub fn mine(&mut self, num_blocks: u64, fork_id: u64) -> () {
+            for _i in 0..num_blocks {
+                let height = self.height() + 1;
+                let parent_header = self.blocks[self.blocks.len() - 1].header.clone();
+
+                let mut script_sig = vec![];
+                script_sig.extend_from_slice(&fork_id.to_le_bytes());
+                script_sig.extend_from_slice(&height.to_le_bytes());
+
+                let coinbase = Transaction {
+                    version: 1,
+                    lock_time: 0,
+                    input: vec![TxIn {
+                        previous_output: OutPoint::null(),
+                        script_sig: Script::from(script_sig),
+                        sequence: 0xffffffff,
+                        witness: vec![]
+                    }],
+                    output: vec![TxOut {
+                        value: 5000000000,
+                        script_pubkey: Script::from(vec![])
+                    }]
+                };
+
+                let mut txdata = vec![coinbase];
+                let mut stacks_block = None;
+
+                if height <= MOCK_LAST_KEY_HEIGHT {
+                    let (privk, vrf_privk) = MockBitcoinChain::leader_keys(&txdata[0].txid());
+                    let pubk = Secp256k1PublicKey::from_private(&privk);
+
+                    let mut payload = vec![];
+                    payload.extend_from_slice(ConsensusHash([0u8; 20]).as_bytes());
+                    payload.extend_from_slice(VRFPublicKey::from_private(&vrf_privk).as_bytes());
+
+                    txdata.push(MockBitcoinChain::make_op_tx(&privk, Opcodes::LeaderKeyRegister, &payload, &vec![(Hash160::from_data(&pubk.to_bytes_compressed()), 5500)]));
+                }
+
+                if height >= 2 && height <= MOCK_LAST_KEY_HEIGHT + 1 {
+                    // use the key registered in the last block
+                    let (privk, _) = MockBitcoinChain::leader_keys(&self.blocks[(height - 1) as usize].txdata[0].txid());
+
+                    let block_hash = BlockHeaderHash(Sha256Sum::from_data(&txdata[0].txid().as_bytes()[..]).0);
+                    let (parent_block_ptr, parent_vtxindex) = match self.stacks_blocks[(height - 1) as usize] {
+                        Some(_) => ((height - 1) as u32, 2u16),
+                        None => (0, 0)
+                    };
+
+                    let mut payload = vec![];
+                    payload.extend_from_slice(block_hash.as_bytes());
+                    payload.extend_from_slice(Sha256Sum::from_data(block_hash.as_bytes()).as_bytes());
+                    payload.extend_from_slice(&parent_block_ptr.to_be_bytes());
+                    payload.extend_from_slice(&parent_vtxindex.to_be_bytes());
+                    payload.extend_from_slice(&((height - 1) as u32).to_be_bytes());
+                    payload.extend_from_slice(&1u16.to_be_bytes());
+                    payload.push(0);
+
+                    txdata.push(MockBitcoinChain::make_op_tx(&privk, Opcodes::LeaderBlockCommit, &payload, &vec![(Hash160([0u8; 20]), 10000 + height)]));
+                    stacks_block = Some(block_hash);
+                }
+
+                let mut header = BlockHeader {
+                    version: 1,
+                    prev_blockhash: parent_header.bitcoin_hash(),
+                    merkle_root: bitcoin_merkle_root(txdata.iter().map(|tx| tx.txid()).collect()),
+                    time: parent_header.time + 600,
+                    bits: parent_header.bits,
+                    nonce: 0
+                };
+
+                // regtest difficulty is low enough that this only takes a few tries
+                while header.bitcoin_hash().into_le() > header.target() {
+                    header.nonce += 1;
+                }
+
+                self.blocks.push(Block {
+                    header: header,
+                    txdata: txdata
+                });
+                self.stacks_blocks.push(stacks_block);
+            }
+        }
+
+        /// Get the txid of the block commit in the block at the given height, if it has one
+        pub fn commit_txid(&self, height: u64) -> Option<Txid> {
+            match self.stacks_blocks[height as usize] {
+                Some(_) => {
+                    // the commit is always the block's last tx
+                    let txdata = &self.blocks[height as usize].txdata;
+                    Some(Txid::from_vec_be(&txdata[txdata.len() - 1].txid().as_bytes().to_vec()).unwrap())
+                },
+                None => None
+            }
+        }
+
+        /// Get up to 2,000 headers that follow the block with the given hash.
+        /// Like bitcoind, start from block 1 if the hash isn't on this chain.
+        pub fn headers_after(&self, block_hash: &Sha256dHash) -> Vec<LoneBlockHeader> {
+            let start = match self.blocks.iter().position(|b| b.bitcoin_hash() == *block_hash) {
+                Some(i) => i + 1,
+                None => 1
+            };
+            let end = cmp::min(self.blocks.len(), start + 2000);
+            if start >= end {
+                return vec![];
+            }
+
+            self.blocks[start..end]
+                .iter()
+                .map(|b| LoneBlockHeader { header: b.header.clone(), tx_count: VarInt(0) })
+                .collect()
+        }
+
+        pub fn get_block(&self, block_hash: &Sha256dHash) -> Option<Block> {
+            self.blocks.iter().find(|b| b.bitcoin_hash() == *block_hash).map(|b| b.clone())
+        }
+
+        pub fn checkpoint(&self, block_height: u64) -> SpvCheckpoint {
+            let header = &self.blocks[block_height as usize].header;
+            SpvCheckpoint {
+                block_height: block_height,
+                block_hash: header.bitcoin_hash(),
+                bits: header.bits,
+                timestamp: header.time
+            }
+        }
+    }
+
+    /// How a MockBitcoinPeer answers requests for blocks
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum MockPeerBehavior {
+        Honest,
+        // send blocks whose transactions don't match their headers
+        CorruptBlocks,
+        // never answer getdata
+        StallBlocks
+    }
+
+    /// A local Bitcoin peer that serves headers and blocks from a MockBitcoinChain.
+    /// The chain can be swapped out between syncs to simulate a reorg.
+    pub struct MockBitcoinPeer {
+        pub port: u16,
+        pub network_id: BitcoinNetworkType,
+        chain: Arc<Mutex<MockBitcoinChain>>
+    }
+
+    impl MockBitcoinPeer {
+        pub fn new(port: u16, network_id: BitcoinNetworkType, chain: MockBitcoinChain) -> MockBitcoinPeer {
+            MockBitcoinPeer::new_with_behavior(port, network_id, chain, MockPeerBehavior::Honest)
+        }
+
+        pub fn new_with_behavior(port: u16, network_id: BitcoinNetworkType, chain: MockBitcoinChain, behavior: MockPeerBehavior) -> MockBitcoinPeer {
+            let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+            let chain = Arc::new(Mutex::new(chain));
+            let server_chain = chain.clone();
+
+            thread::spawn(move || {
+                for sock_res in listener.incoming() {
+                    let sock = match sock_res {
+                        Ok(s) => s,
+                        Err(_e) => {
+                            continue;
+                        }
+                    };
+                    let conn_chain = server_chain.clone();
+                    thread::spawn(move || {
+                        MockBitcoinPeer::serve(sock, network_id, conn_chain, behavior);
+                    });
+                }
+            });
+
+            MockBitcoinPeer {
+                port: port,
+                network_id: network_id,
+                chain: chain
+            }
+        }
+
+        pub fn set_chain(&self, chain: MockBitcoinChain) -> () {
+            let mut cur_chain = self.chain.lock().unwrap();
+            *cur_chain = chain;
+        }
+
+        /// Answer one message from the client
+        fn reply(sock: &TcpStream, chain: &MockBitcoinChain, behavior: MockPeerBehavior, msg: btc_message::NetworkMessage) -> Vec<btc_message::NetworkMessage> {
+            match msg {
+                btc_message::NetworkMessage::Version(_) => {
+                    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+                    let version = btc_message_network::VersionMessage {
+                        version: btc_constants::PROTOCOL_VERSION,
+                        services: 0,
+                        timestamp: timestamp,
+                        receiver: btc_network_address::Address::new(&sock.peer_addr().unwrap(), 0),
+                        sender: btc_network_address::Address::new(&sock.local_addr().unwrap(), 0),
+                        nonce: 0,
+                        user_agent: "mock-bitcoin-peer".to_string(),
+                        start_height: chain.height() as i32,
+                        relay: false
+                    };
+                    vec![btc_message::NetworkMessage::Version(version), btc_message::NetworkMessage::Verack]
+                },
+                btc_message::NetworkMessage::GetHeaders(getheaders) => {
+                    let headers = match getheaders.locator_hashes.first() {
+                        Some(h) => chain.headers_after(h),
+                        None => vec![]
+                    };
+                    vec![btc_message::NetworkMessage::Headers(headers)]
+                },
+                btc_message::NetworkMessage::GetData(invs) => {
+                    match behavior {
+                        MockPeerBehavior::Honest => {
+                            invs.iter()
+                                .filter_map(|inv| chain.get_block(&inv.hash))
+                                .map(|b| btc_message::NetworkMessage::Block(b))
+                                .collect()
+                        },
+                        MockPeerBehavior::CorruptBlocks => {
+                            invs.iter()
+                                .filter_map(|inv| chain.get_block(&inv.hash))
+                                .map(|mut b| {
+                                    // same header, different transactions
+                                    b.txdata[0].lock_time += 1;
+                                    btc_message::NetworkMessage::Block(b)
+                                })
+                                .collect()
+                        },
+                        MockPeerBehavior::StallBlocks => {
+                            vec![]
+                        }
+                    }
+                },
+                btc_message::NetworkMessage::Ping(n) => {
+                    vec![btc_message::NetworkMessage::Pong(n)]
+                },
+                _ => {
+                    vec![]
+                }
+            }
+        }
+
+        /// Serve a client until it hangs up
+        fn serve(mut sock: TcpStream, network_id: BitcoinNetworkType, chain: Arc<Mutex<MockBitcoinChain>>, behavior: MockPeerBehavior) -> () {
+            let magic = network_id_to_bytes(network_id);
+            loop {
+                let msg : btc_message::RawNetworkMessage = match ConsensusDecodable::consensus_decode(&mut RawDecoder::new(&mut sock)) {
+                    Ok(m) => m,
+                    Err(_e) => {
+                        return;
+                    }
+                };
+
+                assert_eq!(msg.magic, magic);
+
+                let replies = {
+                    let cur_chain = chain.lock().unwrap();
+                    MockBitcoinPeer::reply(&sock, &cur_chain, behavior, msg.payload)
+                };
+
+                for reply in replies.into_iter() {
+                    let raw_reply = btc_message::RawNetworkMessage {
+                        magic: magic,
+                        payload: reply
+                    };
+                    if raw_reply.consensus_encode(&mut RawEncoder::new(&mut sock)).is_err() {
+                        return;
+                    }
+                }
+                if sock.flush().is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Set up a fresh regtest node in working_dir that talks to a mock peer on the given port
+    fn setup_node(working_dir: &str, port: u16) -> Burnchain {
+        if fs::metadata(working_dir).is_ok() {
+            fs::remove_dir_all(working_dir).unwrap();
+        }
+
+        let working_dir = working_dir.to_string();
+        let chain_name = "bitcoin".to_string();
+        let network_name = BITCOIN_REGTEST_NAME.to_string();
+
+        Burnchain::setup_chainstate_dirs(&working_dir, &chain_name, &network_name).unwrap();
+
+        let mut config = BitcoinIndexerConfig::default();
+        config.peer_host = "127.0.0.1".to_string();
+        config.peer_port = port;
+        config.spv_headers_path = format!("{}/spv-headers.dat", Burnchain::get_chainstate_path(&working_dir, &chain_name, &network_name));
+        config.first_block = FIRST_BLOCK_REGTEST;
+        config.to_file(&Burnchain::get_chainstate_config_path(&working_dir, &chain_name, &network_name)).unwrap();
+
+        Burnchain::new(&working_dir, &chain_name, &network_name).unwrap()
+    }
+
+    /// Get all snapshots on the node's canonical burn chain fork, in height order
+    fn get_canonical_snapshots(burnchain: &Burnchain) -> Vec<BlockSnapshot> {
+        let mut burndb = burnchain.open_db(true).unwrap();
+        let tip = BurnDB::get_canonical_burn_chain_tip(burndb.conn()).unwrap();
+        let mut tx = burndb.tx_begin().unwrap();
+
+        let mut ret = vec![];
+        for i in 0..(tip.block_height + 1) {
+            let sn = BurnDB::get_block_snapshot_in_fork(&mut tx, i, &tip.burn_header_hash).unwrap().unwrap();
+            ret.push(sn);
+        }
+        ret
+    }
+
+    /// Get the snapshot with the last sortition on the node's canonical burn chain fork
+    fn get_stacks_chain_tip(burnchain: &Burnchain) -> BlockSnapshot {
+        let mut burndb = burnchain.open_db(true).unwrap();
+        let tip = BurnDB::get_canonical_burn_chain_tip(burndb.conn()).unwrap();
+        if tip.sortition {
+            return tip;
+        }
+
+        let mut tx = burndb.tx_begin().unwrap();
+        BurnDB::get_last_snapshot_with_sortition(&mut tx, tip.block_height, &tip.burn_header_hash).unwrap()
+    }
+
+    /// The node's canonical fork must be exactly the given chain, and must match a reference
+    /// node that only ever saw that chain.
+    fn check_converged(burnchain: &Burnchain, reference: &Burnchain, chain: &MockBitcoinChain) -> () {
+        let snapshots = get_canonical_snapshots(burnchain);
+        let reference_snapshots = get_canonical_snapshots(reference);
+
+        assert_eq!(snapshots.len() as u64, chain.height() + 1);
+        for i in 0..snapshots.len() {
+            assert_eq!(snapshots[i].block_height, i as u64);
+            assert_eq!(snapshots[i].burn_header_hash, BurnchainHeaderHash::from_bitcoin_hash(&chain.blocks[i].bitcoin_hash()));
+
+            // each block's commit won its sortition
+            match chain.stacks_blocks[i] {
+                Some(ref block_hash) => {
+                    assert!(snapshots[i].sortition);
+                    assert_eq!(snapshots[i].winning_stacks_block_hash, *block_hash);
+                    assert_eq!(Some(snapshots[i].winning_block_txid.clone()), chain.commit_txid(i as u64));
+                },
+                None => {
+                    // the first snapshot is the initial sortition, which always "wins"
+                    assert_eq!(snapshots[i].sortition, i == 0);
+                }
+            }
+        }
+
+        assert_eq!(snapshots, reference_snapshots);
+        assert_eq!(get_stacks_chain_tip(burnchain), get_stacks_chain_tip(reference));
+    }
+
+    #[test]
+    fn test_mock_chain_fork() {
+        let mut chain_a = MockBitcoinChain::new(BitcoinNetworkType::Regtest);
+        chain_a.mine(10, 1);
+
+        let mut chain_b = chain_a.fork(4);
+        chain_b.mine(10, 2);
+
+        assert_eq!(chain_a.height(), 10);
+        assert_eq!(chain_b.height(), 14);
+
+        for i in 0..5 {
+            assert_eq!(chain_a.blocks[i].bitcoin_hash(), chain_b.blocks[i].bitcoin_hash());
+        }
+        for i in 5..11 {
+            assert!(chain_a.blocks[i].bitcoin_hash() != chain_b.blocks[i].bitcoin_hash());
+            assert!(chain_a.stacks_blocks[i].is_some());
+            assert!(chain_a.stacks_blocks[i] != chain_b.stacks_blocks[i]);
+            assert_eq!(chain_b.blocks[i].header.prev_blockhash, chain_b.blocks[i - 1].bitcoin_hash());
+            assert!(BitcoinBlockParser::check_block(&chain_b.blocks[i], &LoneBlockHeader { header: chain_b.blocks[i].header.clone(), tx_count: VarInt(0) }));
+        }
+
+        // headers from a known block
+        let headers = chain_b.headers_after(&chain_b.blocks[4].bitcoin_hash());
+        assert_eq!(headers.len(), 10);
+        assert_eq!(headers[0].header, chain_b.blocks[5].header);
+
+        // headers from an unknown block start at block 1
+        let headers = chain_b.headers_after(&chain_a.blocks[10].bitcoin_hash());
+        assert_eq!(headers.len(), 14);
+        assert_eq!(headers[0].header, chain_b.blocks[1].header);
+
+        // nothing after the tip
+        assert_eq!(chain_b.headers_after(&chain_b.blocks[14].bitcoin_hash()).len(), 0);
+    }
+
+    #[test]
+    fn test_bitcoin_reorg_converges() {
+        let network_id = BitcoinNetworkType::Regtest;
+
+        let mut chain_a = MockBitcoinChain::new(network_id);
+        chain_a.mine(20, 1);
+
+        let peer = MockBitcoinPeer::new(20130, network_id, chain_a.clone());
+        let mut burnchain = setup_node("/tmp/test_bitcoin_reorg_converges", peer.port);
+
+        // initial sync
+        let end_block = burnchain.sync::<BitcoinIndexer>().unwrap();
+        assert_eq!(end_block, 20);
+
+        let mut reference_a = setup_node("/tmp/test_bitcoin_reorg_converges.reference_a", peer.port);
+        reference_a.sync::<BitcoinIndexer>().unwrap();
+        check_converged(&burnchain, &reference_a, &chain_a);
+
+        // syncing again with no new blocks is a no-op
+        let end_block = burnchain.sync::<BitcoinIndexer>().unwrap();
+        assert_eq!(end_block, 20);
+        check_converged(&burnchain, &reference_a, &chain_a);
+
+        // deep reorg: 12 blocks get orphaned by a longer competing fork
+        let mut chain_b = chain_a.fork(8);
+        chain_b.mine(24, 2);
+        peer.set_chain(chain_b.clone());
+
+        let end_block = burnchain.sync::<BitcoinIndexer>().unwrap();
+        assert_eq!(end_block, 32);
+
+        let mut reference_b = setup_node("/tmp/test_bitcoin_reorg_converges.reference_b", peer.port);
+        reference_b.sync::<BitcoinIndexer>().unwrap();
+        check_converged(&burnchain, &reference_b, &chain_b);
+
+        // the Stacks chain tip is now the new fork's last sortition
+        let stacks_tip = get_stacks_chain_tip(&burnchain);
+        assert_eq!(stacks_tip.block_height, 26);
+        assert_eq!(stacks_tip.burn_header_hash, BurnchainHeaderHash::from_bitcoin_hash(&chain_b.blocks[26].bitcoin_hash()));
+        assert_eq!(Some(stacks_tip.winning_stacks_block_hash), chain_b.stacks_blocks[26]);
+
+        // the orphaned fork is still in the db, sortitions and all, but is no longer canonical
+        {
+            let burndb = burnchain.open_db(false).unwrap();
+            let orphan = BurnDB::get_block_snapshot(burndb.conn(), &BurnchainHeaderHash::from_bitcoin_hash(&chain_a.blocks[20].bitcoin_hash())).unwrap().unwrap();
+            assert!(orphan.sortition);
+            assert_eq!(Some(orphan.winning_stacks_block_hash), chain_a.stacks_blocks[20]);
+            assert_eq!(BurnDB::get_canonical_burn_chain_tip(burndb.conn()).unwrap().burn_header_hash, chain_b.tip_hash());
+        }
+
+        // the original fork comes back and overtakes.  Its first 20 blocks are replayed from
+        // what's already in the db.
+        let mut chain_a2 = chain_a.clone();
+        chain_a2.mine(20, 1);
+        peer.set_chain(chain_a2.clone());
+
+        let end_block = burnchain.sync::<BitcoinIndexer>().unwrap();
+        assert_eq!(end_block, 40);
+
+        let mut reference_a2 = setup_node("/tmp/test_bitcoin_reorg_converges.reference_a2", peer.port);
+        reference_a2.sync::<BitcoinIndexer>().unwrap();
+        check_converged(&burnchain, &reference_a2, &chain_a2);
+
+        // reorg all the way back to the block after genesis
+        let mut chain_c = chain_a2.fork(0);
+        chain_c.mine(45, 3);
+        peer.set_chain(chain_c.clone());
+
+        let end_block = burnchain.sync::<BitcoinIndexer>().unwrap();
+        assert_eq!(end_block, 45);
+
+        let mut reference_c = setup_node("/tmp/test_bitcoin_reorg_converges.reference_c", peer.port);
+        reference_c.sync::<BitcoinIndexer>().unwrap();
+        check_converged(&burnchain, &reference_c, &chain_c);
+
+        let stacks_tip = get_stacks_chain_tip(&burnchain);
+        assert_eq!(stacks_tip.burn_header_hash, BurnchainHeaderHash::from_bitcoin_hash(&chain_c.blocks[26].bitcoin_hash()));
+        assert_eq!(Some(stacks_tip.winning_stacks_block_hash), chain_c.stacks_blocks[26]);
+    }
+
+    fn make_spv_indexer(port: u16, headers_path: &str, checkpoints: Vec<SpvCheckpoint>) -> BitcoinIndexer {
+        if fs::metadata(headers_path).is_ok() {
+            fs::remove_file(headers_path).unwrap();
+        }
+
+        let mut config = BitcoinIndexerConfig::default();
+        config.peer_host = "127.0.0.1".to_string();
+        config.peer_port = port;
+        config.spv_headers_path = headers_path.to_string();
+        config.first_block = FIRST_BLOCK_REGTEST;
+        config.checkpoints = checkpoints;
+
+        BitcoinIndexer {
+            config: config,
+            runtime: BitcoinIndexerRuntime::new(BitcoinNetworkType::Regtest)
+        }
+    }
+
+    #[test]
+    fn test_indexer_config_checkpoints() {
+        let path = "/tmp/test_indexer_config_checkpoints.ini".to_string();
+        if fs::metadata(&path).is_ok() {
+            fs::remove_file(&path).unwrap();
+        }
+
+        let mut chain = MockBitcoinChain::new(BitcoinNetworkType::Regtest);
+        chain.mine(4033, 1);
+
+        let mut config = BitcoinIndexerConfig::default();
+        config.spv_headers_path = "/tmp/test_indexer_config_checkpoints.dat".to_string();
+        config.checkpoints = vec![chain.checkpoint(2016), chain.checkpoint(4032)];
+        config.to_file(&path).unwrap();
+
+        let loaded_config = BitcoinIndexerConfig::from_file(&path).unwrap();
+        assert_eq!(loaded_config.checkpoints, config.checkpoints);
+
+        // no checkpoints
+        config.checkpoints = vec![];
+        config.to_file(&path).unwrap();
+
+        let loaded_config = BitcoinIndexerConfig::from_file(&path).unwrap();
+        assert_eq!(loaded_config.checkpoints.len(), 0);
+
+        // hard-coded checkpoints always come first
+        let indexer = make_spv_indexer(20140, "/tmp/test_indexer_config_checkpoints.dat", vec![chain.checkpoint(2016)]);
+        let checkpoints = indexer.get_checkpoints().unwrap();
+        assert_eq!(checkpoints, vec![SpvCheckpoint::genesis(BitcoinNetworkType::Regtest).unwrap(), chain.checkpoint(2016)]);
+        assert_eq!(indexer.get_spv_start_height().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_spv_bootstrap_from_checkpoint() {
+        let mut chain = MockBitcoinChain::new(BitcoinNetworkType::Regtest);
+        chain.mine(2100, 1);
+
+        let peer = MockBitcoinPeer::new(20150, BitcoinNetworkType::Regtest, chain.clone());
+        let path = "/tmp/test_spv_bootstrap_from_checkpoint.dat".to_string();
+        let mut indexer = make_spv_indexer(peer.port, &path, vec![chain.checkpoint(2016)]);
+
+        let end_block = indexer.sync_last_headers(&path, 2050, None).unwrap();
+        assert_eq!(end_block, 2100);
+        assert_eq!(SpvClient::get_headers_height(&path).unwrap(), 2100);
+
+        // only the genesis header and the headers from the checkpoint on are stored
+        assert_eq!(SpvClient::read_block_header(&path, 0).unwrap().unwrap().header, chain.blocks[0].header);
+        assert!(SpvClient::read_block_header(&path, 1).unwrap().is_none());
+        assert!(SpvClient::read_block_header(&path, 2015).unwrap().is_none());
+
+        let headers = indexer.read_spv_headers(&path, 2016, 2101).unwrap();
+        assert_eq!(headers.len(), 85);
+        for i in 0..headers.len() {
+            assert_eq!(headers[i].header, chain.blocks[2016 + i].header);
+        }
+
+        // new blocks arrive
+        chain.mine(10, 1);
+        peer.set_chain(chain.clone());
+
+        let end_block = indexer.sync_last_headers(&path, 2100, None).unwrap();
+        assert_eq!(end_block, 2110);
+        assert_eq!(SpvClient::read_block_header(&path, 2110).unwrap().unwrap().header, chain.blocks[2110].header);
+    }
+
+    #[test]
+    fn test_spv_reject_checkpoint_mismatch() {
+        let mut chain = MockBitcoinChain::new(BitcoinNetworkType::Regtest);
+        chain.mine(2100, 1);
+
+        let mut other_chain = chain.fork(1000);
+        other_chain.mine(1100, 2);
+
+        let peer = MockBitcoinPeer::new(20160, BitcoinNetworkType::Regtest, chain.clone());
+
+        // syncing from genesis stops at a header that contradicts a checkpoint
+        let path = "/tmp/test_spv_reject_checkpoint_mismatch.dat".to_string();
+        let mut indexer = make_spv_indexer(peer.port, &path, vec![other_chain.checkpoint(2016)]);
+        match indexer.sync_last_headers(&path, 0, None) {
+            Err(btc_error::CheckpointMismatch) => {},
+            res => panic!("Expected checkpoint mismatch, got {:?}", res)
+        }
+        assert!(SpvClient::get_headers_height(&path).unwrap() < 2016);
+
+        // bootstrapping from a checkpoint that doesn't match the peer's block fails
+        let path = "/tmp/test_spv_reject_checkpoint_mismatch.bootstrap.dat".to_string();
+        let mut bad_checkpoint = chain.checkpoint(2016);
+        bad_checkpoint.timestamp += 1;
+
+        let mut indexer = make_spv_indexer(peer.port, &path, vec![bad_checkpoint]);
+        match indexer.sync_last_headers(&path, 2016, None) {
+            Err(btc_error::CheckpointMismatch) => {},
+            res => panic!("Expected checkpoint mismatch, got {:?}", res)
+        }
+        assert!(fs::metadata(&path).is_err());
+    }
+
+    #[test]
+    fn test_sync_reorg_past_batch_size() {
+        let mut chain = MockBitcoinChain::new(BitcoinNetworkType::Regtest);
+        chain.mine(2010, 1);
+
+        let peer = MockBitcoinPeer::new(20165, BitcoinNetworkType::Regtest, chain.clone());
+        let path = "/tmp/test_sync_reorg_past_batch_size.dat".to_string();
+        let mut indexer = make_spv_indexer(peer.port, &path, vec![]);
+        indexer.sync_last_headers(&path, 0, None).unwrap();
+        assert_eq!(SpvClient::get_headers_height(&path).unwrap(), 2010);
+
+        // the db has processed more than a reorg batch's worth of blocks (but stays within the
+        // first difficulty interval, since the mock chain never retargets), and nothing changed
+        let db_tip = BlockSnapshot::initial(2005, &BurnchainHeaderHash::from_bitcoin_hash(&chain.blocks[2005].bitcoin_hash()), 0);
+        assert_eq!(indexer.find_bitcoin_reorg(&path, 2005).unwrap(), 2005);
+        assert_eq!(Burnchain::sync_reorg(&mut indexer, &db_tip).unwrap(), 2005);
+
+        // no headers were dropped
+        assert_eq!(SpvClient::get_headers_height(&path).unwrap(), 2010);
+
+        // only the db's tip gets reorged out
+        let mut other_chain = chain.fork(2004);
+        other_chain.mine(8, 2);
+        peer.set_chain(other_chain.clone());
+
+        assert_eq!(indexer.find_bitcoin_reorg(&path, 2005).unwrap(), 2004);
+        assert_eq!(Burnchain::sync_reorg(&mut indexer, &db_tip).unwrap(), 2004);
+
+        // headers after the last shared one are gone
+        assert_eq!(SpvClient::get_headers_height(&path).unwrap(), 2004);
+        assert_eq!(SpvClient::read_block_header(&path, 2004).unwrap().unwrap().header, chain.blocks[2004].header);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&format!("{}.reorg", &path)).unwrap();
+    }
+
+    fn make_download_headers(chain: &MockBitcoinChain, start_height: u64, end_height: u64) -> Vec<BitcoinHeaderIPC> {
+        (start_height..end_height)
+            .map(|h| BitcoinHeaderIPC {
+                block_header: LoneBlockHeader { header: chain.blocks[h as usize].header.clone(), tx_count: VarInt(0) },
+                block_height: h
+            })
+            .collect()
+    }
+
+    fn check_downloaded_blocks(chain: &MockBitcoinChain, headers: &Vec<BitcoinHeaderIPC>, blocks: &Vec<BitcoinBlockIPC>) -> () {
+        assert_eq!(headers.len(), blocks.len());
+        for i in 0..blocks.len() {
+            assert_eq!(blocks[i].header_data, headers[i]);
+            match blocks[i].block_message.deref() {
+                btc_message::NetworkMessage::Block(ref block) => {
+                    assert_eq!(*block, chain.blocks[headers[i].block_height as usize]);
+                },
+                _ => panic!("Not a block")
+            }
+        }
+    }
+
+    #[test]
+    fn test_indexer_config_peers() {
+        let path = "/tmp/test_indexer_config_peers.ini".to_string();
+        if fs::metadata(&path).is_ok() {
+            fs::remove_file(&path).unwrap();
+        }
+
+        let mut config = BitcoinIndexerConfig::default();
+        config.spv_headers_path = "/tmp/test_indexer_config_peers.dat".to_string();
+        config.peers = vec![("bitcoin-2.example.com".to_string(), 8333), ("127.0.0.1".to_string(), 18444)];
+        config.to_file(&path).unwrap();
+
+        let loaded_config = BitcoinIndexerConfig::from_file(&path).unwrap();
+        assert_eq!(loaded_config.peers, config.peers);
+
+        config.peers = vec![];
+        config.to_file(&path).unwrap();
+
+        let loaded_config = BitcoinIndexerConfig::from_file(&path).unwrap();
+        assert_eq!(loaded_config.peers.len(), 0);
+    }
+
+    #[test]
+    fn test_select_peer() {
+        let mut indexer = make_spv_indexer(20170, "/tmp/test_select_peer.dat", vec![]);
+        indexer.config.peers = vec![("127.0.0.1".to_string(), 20171), ("127.0.0.1".to_string(), 20172), ("127.0.0.1".to_string(), 20170)];
+
+        // duplicates are ignored, and the primary peer comes first
+        assert_eq!(indexer.get_peers(), vec![("127.0.0.1".to_string(), 20170), ("127.0.0.1".to_string(), 20171), ("127.0.0.1".to_string(), 20172)]);
+        assert_eq!(indexer.select_peer(), ("127.0.0.1".to_string(), 20170));
+
+        // fail over to the next peer
+        indexer.runtime.peer_host = "127.0.0.1".to_string();
+        indexer.runtime.peer_port = 20170;
+        indexer.peer_failed();
+        assert_eq!(indexer.select_peer(), ("127.0.0.1".to_string(), 20171));
+
+        indexer.runtime.peer_port = 20171;
+        indexer.peer_failed();
+        assert_eq!(indexer.select_peer(), ("127.0.0.1".to_string(), 20172));
+
+        // everyone has failed once; prefer the one that failed longest ago
+        indexer.runtime.peer_port = 20172;
+        indexer.peer_failed();
+        indexer.runtime.peer_health.get_mut(&("127.0.0.1".to_string(), 20171)).unwrap().last_failure -= 100;
+        assert_eq!(indexer.select_peer(), ("127.0.0.1".to_string(), 20171));
+
+        // a peer that works again is preferred
+        indexer.runtime.peer_port = 20172;
+        indexer.peer_succeeded();
+        assert_eq!(indexer.select_peer(), ("127.0.0.1".to_string(), 20172));
+        assert_eq!(indexer.runtime.peer_health.get(&("127.0.0.1".to_string(), 20172)).unwrap().total_failures, 1);
+
+        // health carries over to duplicated indexers
+        let dup_indexer = indexer.dup();
+        assert_eq!(dup_indexer.runtime.peer_health, indexer.runtime.peer_health);
+    }
+
+    #[test]
+    fn test_download_batch_failover() {
+        let mut chain = MockBitcoinChain::new(BitcoinNetworkType::Regtest);
+        chain.mine(40, 1);
+
+        let _corrupt_peer = MockBitcoinPeer::new_with_behavior(20180, BitcoinNetworkType::Regtest, chain.clone(), MockPeerBehavior::CorruptBlocks);
+        let _stalled_peer = MockBitcoinPeer::new_with_behavior(20181, BitcoinNetworkType::Regtest, chain.clone(), MockPeerBehavior::StallBlocks);
+        let _honest_peer = MockBitcoinPeer::new(20182, BitcoinNetworkType::Regtest, chain.clone());
+
+        // nothing is listening on 20183
+        let mut indexer = make_spv_indexer(20183, "/tmp/test_download_batch_failover.dat", vec![]);
+        indexer.config.timeout = 1;
+        indexer.config.peers = vec![("127.0.0.1".to_string(), 20180), ("127.0.0.1".to_string(), 20181), ("127.0.0.1".to_string(), 20182)];
+
+        let mut downloader = BitcoinBlockDownloader::new(indexer);
+
+        // the dead peer, the corrupt peer, and the stalled peer all get skipped
+        let headers = make_download_headers(&chain, 1, 17);
+        let blocks = downloader.run_batch(&headers).unwrap();
+        check_downloaded_blocks(&chain, &headers, &blocks);
+
+        // the honest peer is kept for the next batch
+        let headers = make_download_headers(&chain, 17, 41);
+        let blocks = downloader.run_batch(&headers).unwrap();
+        check_downloaded_blocks(&chain, &headers, &blocks);
+
+        // single-block downloads still work
+        let headers = make_download_headers(&chain, 5, 6);
+        let block = downloader.run(&headers[0]).unwrap();
+        check_downloaded_blocks(&chain, &headers, &vec![block]);
+    }
+
+    #[test]
+    fn test_sync_with_failover() {
+        let network_id = BitcoinNetworkType::Regtest;
+
+        let mut chain = MockBitcoinChain::new(network_id);
+        chain.mine(50, 1);
+
+        let _corrupt_peer = MockBitcoinPeer::new_with_behavior(20190, network_id, chain.clone(), MockPeerBehavior::CorruptBlocks);
+        let honest_peer = MockBitcoinPeer::new(20191, network_id, chain.clone());
+
+        let mut burnchain = setup_node("/tmp/test_sync_with_failover", 20190);
+
+        // add the honest peer as a fallback
+        let conf_path = Burnchain::get_chainstate_config_path(&burnchain.working_dir, &burnchain.chain_name, &burnchain.network_name);
+        let mut config = BitcoinIndexerConfig::from_file(&conf_path).unwrap();
+        config.peers = vec![("127.0.0.1".to_string(), honest_peer.port)];
+        config.to_file(&conf_path).unwrap();
+
+        let end_block = burnchain.sync::<BitcoinIndexer>().unwrap();
+        assert_eq!(end_block, 50);
+
+        let mut reference = setup_node("/tmp/test_sync_with_failover.reference", honest_peer.port);
+        reference.sync::<BitcoinIndexer>().unwrap();
+        check_converged(&burnchain, &reference, &chain);
+    }
+}
@@ -92,6 +92,8 @@ pub enum Error {
     ConfigError(String),
     /// Tried to synchronize to a point above the chain tip
     BlockchainHeight,
+    /// Header chain contradicts a checkpoint
+    CheckpointMismatch,
 }
 
 impl fmt::Display for Error {
@@ -115,6 +117,7 @@ impl fmt::Display for Error {
             Error::InvalidByteSequence => write!(f, "Invalid sequence of bytes"),
             Error::ConfigError(ref e_str) => fmt::Display::fmt(e_str, f),
             Error::BlockchainHeight => write!(f, "Value is beyond the end of the blockchain"),
+            Error::CheckpointMismatch => write!(f, "Header chain contradicts a checkpoint"),
         }
     }
 }
@@ -139,6 +142,7 @@ impl error::Error for Error {
             Error::InvalidByteSequence => None,
             Error::ConfigError(ref _e_str) => None,
             Error::BlockchainHeight => None,
+            Error::CheckpointMismatch => None,
         }
     }
 }
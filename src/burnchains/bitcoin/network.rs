@@ -190,7 +190,7 @@ impl BitcoinIndexer {
     /// Do the initial handshake to the remote peer.
     /// Returns the remote peer's block height
     pub fn peer_handshake(&mut self) -> Result<u64, btc_error> {
-        debug!("Begin peer handshake to {}:{}", self.config.peer_host, self.config.peer_port);
+        debug!("Begin peer handshake to {}:{}", self.runtime.peer_host, self.runtime.peer_port);
         self.send_version()?;
         let version_reply = self.recv_message()?;
         self.handle_version(version_reply)?;
@@ -198,18 +198,20 @@ impl BitcoinIndexer {
         let verack_reply = self.recv_message()?;
         self.handle_verack(verack_reply)?;
 
-        debug!("Established connection to {}:{}, who has {} blocks", self.config.peer_host, self.config.peer_port, self.runtime.block_height);
+        debug!("Established connection to {}:{}, who has {} blocks", self.runtime.peer_host, self.runtime.peer_port, self.runtime.block_height);
         Ok(self.runtime.block_height)
     }
 
 
     /// Connect to a remote peer, do a handshake with the remote peer, and use exponential backoff until we
-    /// succeed in establishing a connection.
+    /// succeed in establishing a connection.  Each failed attempt fails over to the next-healthiest
+    /// peer; we only back off once every peer has been tried.
     /// This method masks ConnectionBroken errors, but does not mask other network errors.
     /// Returns the remote peer's block height on success
     pub fn connect_handshake_backoff(&mut self) -> Result<u64, btc_error> {
         let mut backoff: f64 = 1.0;
         let mut rng = thread_rng();
+        let mut num_attempts = 0;
 
         loop {
             let connection_result = self.connect();
@@ -220,24 +222,34 @@ impl BitcoinIndexer {
                     match handshake_result {
                         Ok(block_height) => {
                             // connected!
+                            self.peer_succeeded();
                             return Ok(block_height);
                         }
                         Err(btc_error::ConnectionBroken) => {
                             // need to try again
-                            backoff = 2.0 * backoff + (backoff * rng.gen_range(0.0, 1.0));
+                            self.peer_failed();
                         }
                         Err(_) => {
                             // propagate other network error
+                            self.peer_failed();
                             return handshake_result;
                         }
                     }
                 }
                 Err(err_msg) => {
-                    error!("Failed to connect to peer: {}", err_msg);
-                    backoff = 2.0 * backoff + (backoff * rng.gen_range(0.0, 1.0));
+                    error!("Failed to connect to peer {}:{}: {}", self.runtime.peer_host, self.runtime.peer_port, err_msg);
                 }
             }
 
+            // try the other peers before backing off
+            num_attempts += 1;
+            if num_attempts < self.get_peers().len() {
+                continue;
+            }
+
+            num_attempts = 0;
+            backoff = 2.0 * backoff + (backoff * rng.gen_range(0.0, 1.0));
+
             // don't sleep more than 60 seconds
             if backoff > 60.0 {
                 backoff = 60.0;
@@ -277,7 +289,7 @@ impl BitcoinIndexer {
             relay: false
         };
 
-        debug!("Send version (nonce={}) to {}:{}", self.runtime.version_nonce, self.config.peer_host, self.config.peer_port);
+        debug!("Send version (nonce={}) to {}:{}", self.runtime.version_nonce, self.runtime.peer_host, self.runtime.peer_port);
         self.send_message(btc_message::NetworkMessage::Version(payload))
     }
 
@@ -362,7 +374,7 @@ impl BitcoinIndexer {
         let getheaders = btc_message_blockdata::GetHeadersMessage::new(vec![prev_block_hash], prev_block_hash);
         let payload = btc_message::NetworkMessage::GetHeaders(getheaders);
 
-        debug!("Send GetHeaders {} for 2000 headers to {}:{}", prev_block_hash.be_hex_string(), self.config.peer_host, self.config.peer_port);
+        debug!("Send GetHeaders {} for 2000 headers to {}:{}", prev_block_hash.be_hex_string(), self.runtime.peer_host, self.runtime.peer_port);
         self.send_message(payload)
     }
 
@@ -381,7 +393,7 @@ impl BitcoinIndexer {
 
         let getdata = btc_message::NetworkMessage::GetData(getdata_invs);
 
-        debug!("Send GetData {}-{} to {}:{}", block_hashes[0].be_hex_string(), block_hashes[block_hashes.len() - 1].be_hex_string(), self.config.peer_host, self.config.peer_port);
+        debug!("Send GetData {}-{} to {}:{}", block_hashes[0].be_hex_string(), block_hashes[block_hashes.len() - 1].be_hex_string(), self.runtime.peer_host, self.runtime.peer_port);
         self.send_message(getdata)
     }
 }
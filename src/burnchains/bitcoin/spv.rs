@@ -49,6 +49,18 @@ const GENESIS_BLOCK_MERKLE_ROOT_TESTNET: &'static str = "4a5e1e4baab89f3a32518a8
 pub const BLOCK_DIFFICULTY_CHUNK_SIZE: u64 = 2016;
 const BLOCK_DIFFICULTY_INTERVAL: u32 = 14 * 24 * 60 * 60;   // two weeks, in seconds
 
+/// A trusted block in the header chain.  A new headers file can start from a checkpoint instead
+/// of from the genesis block, and any header chain that disagrees with a checkpoint is rejected.
+/// Checkpoints must be the first block in a difficulty adjustment interval, so the difficulty of
+/// the headers after it can be checked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpvCheckpoint {
+    pub block_height: u64,
+    pub block_hash: Sha256dHash,
+    pub bits: u32,
+    pub timestamp: u32
+}
+
 // TODO: write all headers to a sqlite db, since reliable file I/O can be perilous
 pub struct SpvClient {
     pub headers_path: String,
@@ -56,16 +68,137 @@ pub struct SpvClient {
     pub end_block_height: Option<u64>,
     pub cur_block_height: u64,
     pub network_id: BitcoinNetworkType,
+    pub checkpoints: Vec<SpvCheckpoint>,
+}
+
+/// Fetches the header of a checkpointed block, so a new headers file can start from it.
+struct SpvCheckpointFetcher {
+    checkpoint: SpvCheckpoint,
+    header: Option<LoneBlockHeader>
+}
+
+impl SpvCheckpoint {
+    /// The genesis block is always a checkpoint
+    pub fn genesis(network_id: BitcoinNetworkType) -> Result<SpvCheckpoint, btc_error> {
+        let genesis_header = SpvClient::genesis_block_header(network_id)?;
+        Ok(SpvCheckpoint {
+            block_height: 0,
+            block_hash: genesis_header.header.bitcoin_hash(),
+            bits: genesis_header.header.bits,
+            timestamp: genesis_header.header.time
+        })
+    }
+
+    /// Hard-coded checkpoints for a network
+    pub fn defaults(network_id: BitcoinNetworkType) -> Result<Vec<SpvCheckpoint>, btc_error> {
+        let mut checkpoints = vec![SpvCheckpoint::genesis(network_id)?];
+        for (block_height, block_hash, bits, timestamp) in SpvCheckpoint::hard_coded(network_id).iter() {
+            checkpoints.push(SpvCheckpoint {
+                block_height: *block_height,
+                block_hash: Sha256dHash::from_hex(block_hash).map_err(btc_error::HashError)?,
+                bits: *bits,
+                timestamp: *timestamp
+            });
+        }
+        Ok(checkpoints)
+    }
+
+    /// (height, block hash, bits, timestamp) of the hard-coded checkpoints past genesis
+    fn hard_coded(network_id: BitcoinNetworkType) -> Vec<(u64, &'static str, u32, u32)> {
+        match network_id {
+            BitcoinNetworkType::Mainnet => vec![
+                // segwit activation
+                (481824, "0000000000000000001c8018d9cb3b742ef25114f27563e3fc4a1902167f9893", 0x18013ce9, 1503539857),
+            ],
+            BitcoinNetworkType::Testnet => vec![],
+            BitcoinNetworkType::Regtest => vec![]
+        }
+    }
+
+    /// Parse a checkpoint from a config file string, formatted as
+    /// "height:block hash:bits (hex):timestamp"
+    pub fn from_config_str(s: &str) -> Result<SpvCheckpoint, btc_error> {
+        let parts : Vec<&str> = s.trim().split(':').collect();
+        if parts.len() != 4 {
+            return Err(btc_error::ConfigError(format!("Invalid checkpoint '{}': expected height:hash:bits:timestamp", s)));
+        }
+
+        let block_height : u64 = parts[0].parse()
+            .map_err(|_e| btc_error::ConfigError(format!("Invalid checkpoint height '{}'", parts[0])))?;
+
+        let block_hash = Sha256dHash::from_hex(parts[1])
+            .map_err(|_e| btc_error::ConfigError(format!("Invalid checkpoint hash '{}'", parts[1])))?;
+
+        let bits = u32::from_str_radix(parts[2].trim_start_matches("0x"), 16)
+            .map_err(|_e| btc_error::ConfigError(format!("Invalid checkpoint bits '{}'", parts[2])))?;
+
+        let timestamp : u32 = parts[3].parse()
+            .map_err(|_e| btc_error::ConfigError(format!("Invalid checkpoint timestamp '{}'", parts[3])))?;
+
+        if block_height % BLOCK_DIFFICULTY_CHUNK_SIZE != 0 {
+            return Err(btc_error::ConfigError(format!("Invalid checkpoint height {}: must be a multiple of {}", block_height, BLOCK_DIFFICULTY_CHUNK_SIZE)));
+        }
+
+        Ok(SpvCheckpoint {
+            block_height,
+            block_hash,
+            bits,
+            timestamp
+        })
+    }
+
+    pub fn to_config_str(&self) -> String {
+        format!("{}:{}:{:08x}:{}", self.block_height, self.block_hash.be_hex_string(), self.bits, self.timestamp)
+    }
+
+    /// The difficulty target encoded by this checkpoint's bits
+    pub fn target(&self) -> Uint256 {
+        let header = BlockHeader {
+            version: 0,
+            prev_blockhash: Default::default(),
+            merkle_root: Default::default(),
+            time: self.timestamp,
+            bits: self.bits,
+            nonce: 0
+        };
+        header.target()
+    }
+
+    /// Verify that a header is the checkpointed block
+    pub fn check_header(&self, header: &BlockHeader) -> Result<(), btc_error> {
+        if header.bitcoin_hash() != self.block_hash || header.bits != self.bits || header.time != self.timestamp {
+            error!("Header {} (bits {:08x}, time {}) contradicts checkpoint at {}: {} (bits {:08x}, time {})",
+                   header.bitcoin_hash(), header.bits, header.time, self.block_height, self.block_hash, self.bits, self.timestamp);
+            return Err(btc_error::CheckpointMismatch);
+        }
+        Ok(())
+    }
+
+    /// Find the highest checkpoint at or below the given height
+    pub fn find_nearest(checkpoints: &Vec<SpvCheckpoint>, block_height: u64) -> Option<SpvCheckpoint> {
+        checkpoints.iter()
+            .filter(|cp| cp.block_height <= block_height)
+            .max_by_key(|cp| cp.block_height)
+            .map(|cp| cp.clone())
+    }
+
+    /// Find the checkpoint at the given height, if there is one
+    pub fn find_at(checkpoints: &Vec<SpvCheckpoint>, block_height: u64) -> Option<SpvCheckpoint> {
+        checkpoints.iter()
+            .find(|cp| cp.block_height == block_height)
+            .map(|cp| cp.clone())
+    }
 }
 
 impl SpvClient {
-    pub fn new(headers_path: &str, start_block: u64, end_block: Option<u64>, network_id: BitcoinNetworkType) -> SpvClient {
+    pub fn new(headers_path: &str, start_block: u64, end_block: Option<u64>, network_id: BitcoinNetworkType, checkpoints: &Vec<SpvCheckpoint>) -> SpvClient {
         SpvClient {
             headers_path: headers_path.to_owned(),
             start_block_height: start_block,
             end_block_height: end_block,
             cur_block_height: start_block,
-            network_id: network_id
+            network_id: network_id,
+            checkpoints: checkpoints.clone()
         }
     }
 
@@ -82,7 +215,16 @@ impl SpvClient {
         let network_id = self.network_id;
 
         if !self.is_initialized().is_ok() {
-            match SpvClient::init_block_headers(&self.headers_path, network_id) {
+            // start from the nearest checkpoint, if we have one
+            let init_res = match SpvCheckpoint::find_nearest(&self.checkpoints, self.start_block_height) {
+                Some(ref checkpoint) if checkpoint.block_height > 0 => {
+                    self.init_block_headers_from_checkpoint(indexer, checkpoint)
+                },
+                _ => {
+                    SpvClient::init_block_headers(&self.headers_path, network_id)
+                }
+            };
+            match init_res {
                 Ok(()) => {},
                 Err(e) => {
                     debug!("Failed to initialize block headers file at {}: {:?}", &self.headers_path, e);
@@ -123,18 +265,22 @@ impl SpvClient {
     }
 
     /// Verify that the given headers have the correct amount of work to be appended to our
-    /// local header chain.  Checks the difficulty between [interval, interval+1]
-    fn validate_header_work(headers_path: &str, interval_start: u64, interval_end: u64) -> Result<(), btc_error> {
+    /// local header chain.  Checks the difficulty between [interval, interval+1].
+    /// An interval that starts with a checkpoint uses the checkpoint's difficulty.
+    fn validate_header_work(headers_path: &str, checkpoints: &Vec<SpvCheckpoint>, interval_start: u64, interval_end: u64) -> Result<(), btc_error> {
         assert!(interval_start <= interval_end);
         if interval_start == 0 {
             return Ok(());
         }
 
         for i in interval_start..interval_end {
-            let target_opt = SpvClient::get_target(headers_path, i)?;
+            let target_opt = match SpvCheckpoint::find_at(checkpoints, i * BLOCK_DIFFICULTY_CHUNK_SIZE) {
+                Some(checkpoint) => Some((checkpoint.bits, checkpoint.target())),
+                None => SpvClient::get_target(headers_path, i)?
+            };
             if target_opt.is_none() {
-                // out of headers 
-                return Ok(());
+                // out of headers, or this interval precedes the checkpoint the headers start from
+                continue;
             }
 
             let (bits, difficulty) = target_opt.unwrap();
@@ -175,7 +321,8 @@ impl SpvClient {
     }
 
     /// Read the block header at a particular height 
-    /// Returns None if the requested block height is beyond the end of the headers file
+    /// Returns None if the requested block height is beyond the end of the headers file, or
+    /// if the headers file starts from a later checkpoint.
     pub fn read_block_header(headers_path: &str, block_height: u64) -> Result<Option<LoneBlockHeader>, btc_error> {
         let headers_height = SpvClient::get_headers_height(headers_path)?;
         if headers_height < block_height {
@@ -192,14 +339,19 @@ impl SpvClient {
         headers_file.read(&mut serialized_header)
                             .map_err(btc_error::FilesystemError)?;
 
+        if block_height > 0 && serialized_header.iter().all(|b| *b == 0) {
+            // skipped -- this file starts from a checkpoint
+            return Ok(None);
+        }
+
         let header : LoneBlockHeader = deserialize(&serialized_header)
                             .map_err(btc_error::SerializationError)?;
 
         return Ok(Some(header));
     }
 
-    /// Initialize the block headers file with the genesis block hash 
-    fn init_block_headers(headers_path: &str, network_id: BitcoinNetworkType) -> Result<(), btc_error> {
+    /// Get the genesis block header for the given network
+    pub fn genesis_block_header(network_id: BitcoinNetworkType) -> Result<LoneBlockHeader, btc_error> {
         let genesis_merkle_root_str = match network_id {
             BitcoinNetworkType::Mainnet => GENESIS_BLOCK_MERKLE_ROOT_MAINNET,
             BitcoinNetworkType::Testnet => GENESIS_BLOCK_MERKLE_ROOT_TESTNET,
@@ -212,6 +364,12 @@ impl SpvClient {
             BitcoinNetworkType::Regtest => GENESIS_BLOCK_HASH_TESTNET,
         };
 
+        // (time, bits, nonce)
+        let (genesis_time, genesis_bits, genesis_nonce) = match network_id {
+            BitcoinNetworkType::Mainnet => (1231006505, 0x1d00ffff, 2083236893),
+            BitcoinNetworkType::Testnet | BitcoinNetworkType::Regtest => (1296688602, 0x207fffff, 2)
+        };
+
         let genesis_prev_blockhash = Sha256dHash::from_hex("0000000000000000000000000000000000000000000000000000000000000000")
                 .map_err(btc_error::HashError)?;
 
@@ -226,14 +384,20 @@ impl SpvClient {
                 version: 1,
                 prev_blockhash: genesis_prev_blockhash,
                 merkle_root: genesis_merkle_root,
-                time: 1231006505,
-                bits: 0x1d00ffff,
-                nonce: 2083236893
+                time: genesis_time,
+                bits: genesis_bits,
+                nonce: genesis_nonce
             },
             tx_count: VarInt(0)
         };
 
         assert_eq!(genesis_header.header.bitcoin_hash(), genesis_block_hash);
+        Ok(genesis_header)
+    }
+
+    /// Initialize the block headers file with the genesis block hash 
+    fn init_block_headers(headers_path: &str, network_id: BitcoinNetworkType) -> Result<(), btc_error> {
+        let genesis_header = SpvClient::genesis_block_header(network_id)?;
 
         let genesis_header_vec = serialize(&genesis_header)
                 .map_err(btc_error::SerializationError)?;
@@ -255,6 +419,45 @@ impl SpvClient {
         return Ok(());
     }
 
+    /// Start a new headers file from a checkpoint, instead of from the genesis block.
+    /// The checkpointed block's header is fetched from the peer and checked against the checkpoint.
+    fn init_block_headers_from_checkpoint(&mut self, indexer: &mut BitcoinIndexer, checkpoint: &SpvCheckpoint) -> Result<(), btc_error> {
+        debug!("Fetch checkpoint header {} at {}", checkpoint.block_hash, checkpoint.block_height);
+
+        let mut fetcher = SpvCheckpointFetcher {
+            checkpoint: checkpoint.clone(),
+            header: None
+        };
+
+        indexer.peer_communicate(&mut fetcher, true)?;
+
+        let header = fetcher.header.take().ok_or(btc_error::MissingHeader)?;
+        self.init_block_headers_at_checkpoint(checkpoint, &header)
+    }
+
+    /// Start a new headers file with the given header of a checkpointed block.  The headers
+    /// between genesis and the checkpoint are left zeroed.
+    fn init_block_headers_at_checkpoint(&mut self, checkpoint: &SpvCheckpoint, header: &LoneBlockHeader) -> Result<(), btc_error> {
+        checkpoint.check_header(&header.header)?;
+
+        SpvClient::init_block_headers(&self.headers_path, self.network_id)?;
+        self.write_block_headers(checkpoint.block_height - 1, &vec![header.clone()])?;
+
+        debug!("Initialized block headers at {} from checkpoint {}", &self.headers_path, checkpoint.block_height);
+        Ok(())
+    }
+
+    /// Verify that none of the given headers contradict a checkpoint
+    fn validate_header_checkpoints(&self, insert_height: u64, block_headers: &Vec<LoneBlockHeader>) -> Result<(), btc_error> {
+        for i in 0..block_headers.len() {
+            let block_height = insert_height + 1 + (i as u64);
+            if let Some(checkpoint) = SpvCheckpoint::find_at(&self.checkpoints, block_height) {
+                checkpoint.check_header(&block_headers[i].header)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Handle a Headers message
     /// -- validate them
     /// -- store them
@@ -265,11 +468,17 @@ impl SpvClient {
             return valid_check;
         }
 
+        let checkpoint_check = self.validate_header_checkpoints(insert_height, block_headers);
+        if checkpoint_check.is_err() {
+            error!("Received headers that contradict a checkpoint");
+            return checkpoint_check;
+        }
+
         self.insert_block_headers(insert_height, block_headers)?;
 
         // check work 
         let chain_tip = SpvClient::get_headers_height(&self.headers_path)?;
-        let work_check = SpvClient::validate_header_work(&self.headers_path, (chain_tip - 1) / BLOCK_DIFFICULTY_CHUNK_SIZE, chain_tip / BLOCK_DIFFICULTY_CHUNK_SIZE + 1);
+        let work_check = SpvClient::validate_header_work(&self.headers_path, &self.checkpoints, (chain_tip - 1) / BLOCK_DIFFICULTY_CHUNK_SIZE, chain_tip / BLOCK_DIFFICULTY_CHUNK_SIZE + 1);
         if work_check.is_err() {
             error!("Received headers with bad target or difficulty");
             return work_check;
@@ -349,7 +558,9 @@ impl SpvClient {
     /// Drop headers after a block height (i.e. due to a reorg).
     /// DANGEROUS -- don't use if there's another SPV client running on this header path!
     pub fn drop_headers(header_path: &str, new_size: u64) -> Result<(), btc_error> {
-        let headers_file = fs::File::open(header_path)
+        let headers_file = fs::OpenOptions::new()
+                             .write(true)
+                             .open(header_path)
                              .map_err(btc_error::FilesystemError)?;
 
         headers_file.set_len(new_size * BLOCK_HEADER_SIZE)
@@ -443,7 +654,7 @@ impl BitcoinMessageHandler for SpvClient {
                 // only handle headers we asked for 
                 let header_range = 
                     if end_block_height - self.cur_block_height < block_headers.len() as u64 {
-                        block_headers.len() as u64 - (end_block_height - self.cur_block_height)
+                        end_block_height - self.cur_block_height
                     }
                     else {
                         block_headers.len() as u64
@@ -476,6 +687,38 @@ impl BitcoinMessageHandler for SpvClient {
     }
 }
 
+impl BitcoinMessageHandler for SpvCheckpointFetcher {
+
+    /// Trait message handler 
+    /// ask for the checkpointed block 
+    fn begin_session(&mut self, indexer: &mut BitcoinIndexer) -> Result<bool, btc_error> {
+        indexer.send_getdata(&vec![self.checkpoint.block_hash.clone()]).and_then(|_r| Ok(true))
+    }
+
+    /// Trait message handler
+    /// Take the checkpointed block's header
+    fn handle_message(&mut self, _indexer: &mut BitcoinIndexer, msg: PeerMessage) -> Result<bool, btc_error> {
+        match msg.deref() {
+            btc_message::NetworkMessage::Block(block) => {
+                if block.bitcoin_hash() != self.checkpoint.block_hash {
+                    debug!("Requested checkpoint block {}, got block {}", self.checkpoint.block_hash, block.bitcoin_hash());
+                    return Ok(true);
+                }
+
+                self.checkpoint.check_header(&block.header)?;
+                self.header = Some(LoneBlockHeader {
+                    header: block.header.clone(),
+                    tx_count: VarInt(0)
+                });
+                return Ok(false);
+            }
+            _ => {}
+        };
+
+        Err(btc_error::UnhandledMessage(msg))
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -512,4 +755,148 @@ mod test {
 
         assert_eq!(genesis_header.header.bitcoin_hash(), genesis_block_hash);
     }
+
+    #[test]
+    fn genesis_block_headers() {
+        for network_id in [BitcoinNetworkType::Mainnet, BitcoinNetworkType::Testnet, BitcoinNetworkType::Regtest].iter() {
+            let genesis_header = SpvClient::genesis_block_header(*network_id).unwrap();
+            assert_eq!(genesis_header.header.prev_blockhash, Sha256dHash::from_hex("0000000000000000000000000000000000000000000000000000000000000000").unwrap());
+        }
+    }
+
+    #[test]
+    fn checkpoint_config_str() {
+        let checkpoint = SpvCheckpoint {
+            block_height: 2016 * 200,
+            block_hash: Sha256dHash::from_hex("000000000000000000b2c8c1e3a1f6cd2fc4e35cf0e8fbc89ab7e3ec5e8e0b25").unwrap(),
+            bits: 0x1802a3b1,
+            timestamp: 1570000000
+        };
+
+        let checkpoint_str = checkpoint.to_config_str();
+        assert_eq!(checkpoint_str, "403200:000000000000000000b2c8c1e3a1f6cd2fc4e35cf0e8fbc89ab7e3ec5e8e0b25:1802a3b1:1570000000");
+        assert_eq!(SpvCheckpoint::from_config_str(&checkpoint_str).unwrap(), checkpoint);
+        assert_eq!(SpvCheckpoint::from_config_str("403200:000000000000000000b2c8c1e3a1f6cd2fc4e35cf0e8fbc89ab7e3ec5e8e0b25:0x1802a3b1:1570000000").unwrap(), checkpoint);
+
+        // not the start of a difficulty interval
+        assert!(SpvCheckpoint::from_config_str("403201:000000000000000000b2c8c1e3a1f6cd2fc4e35cf0e8fbc89ab7e3ec5e8e0b25:1802a3b1:1570000000").is_err());
+
+        // malformed
+        assert!(SpvCheckpoint::from_config_str("403200:000000000000000000b2c8c1e3a1f6cd2fc4e35cf0e8fbc89ab7e3ec5e8e0b25:1802a3b1").is_err());
+        assert!(SpvCheckpoint::from_config_str("403200:00000000000000:1802a3b1:1570000000").is_err());
+        assert!(SpvCheckpoint::from_config_str("403200:000000000000000000b2c8c1e3a1f6cd2fc4e35cf0e8fbc89ab7e3ec5e8e0b25:xyz:1570000000").is_err());
+    }
+
+    #[test]
+    fn checkpoint_find() {
+        let genesis = SpvCheckpoint::genesis(BitcoinNetworkType::Mainnet).unwrap();
+        let mut checkpoint = genesis.clone();
+        checkpoint.block_height = 4032;
+
+        let checkpoints = vec![genesis.clone(), checkpoint.clone()];
+        assert_eq!(SpvCheckpoint::find_nearest(&checkpoints, 0), Some(genesis.clone()));
+        assert_eq!(SpvCheckpoint::find_nearest(&checkpoints, 4031), Some(genesis.clone()));
+        assert_eq!(SpvCheckpoint::find_nearest(&checkpoints, 4032), Some(checkpoint.clone()));
+        assert_eq!(SpvCheckpoint::find_nearest(&checkpoints, 100000), Some(checkpoint.clone()));
+
+        assert_eq!(SpvCheckpoint::find_at(&checkpoints, 4032), Some(checkpoint.clone()));
+        assert_eq!(SpvCheckpoint::find_at(&checkpoints, 2016), None);
+
+        let genesis_header = SpvClient::genesis_block_header(BitcoinNetworkType::Mainnet).unwrap();
+        assert!(genesis.check_header(&genesis_header.header).is_ok());
+        assert_eq!(genesis.target(), genesis_header.header.target());
+
+        let mut bad_header = genesis_header.header.clone();
+        bad_header.nonce += 1;
+        assert!(genesis.check_header(&bad_header).is_err());
+    }
+
+    #[test]
+    fn checkpoint_defaults() {
+        for network_id in [BitcoinNetworkType::Mainnet, BitcoinNetworkType::Testnet, BitcoinNetworkType::Regtest].iter() {
+            let checkpoints = SpvCheckpoint::defaults(*network_id).unwrap();
+            assert_eq!(checkpoints[0], SpvCheckpoint::genesis(*network_id).unwrap());
+            for i in 1..checkpoints.len() {
+                assert!(checkpoints[i-1].block_height < checkpoints[i].block_height);
+                assert_eq!(checkpoints[i].block_height % BLOCK_DIFFICULTY_CHUNK_SIZE, 0);
+
+                // the checkpointed block meets its own difficulty
+                assert!(checkpoints[i].block_hash.into_le() <= checkpoints[i].target());
+            }
+        }
+
+        let mainnet = SpvCheckpoint::defaults(BitcoinNetworkType::Mainnet).unwrap();
+        assert!(mainnet.len() > 1);
+        assert_eq!(SpvCheckpoint::find_nearest(&mainnet, 500000).unwrap().block_height, 481824);
+    }
+
+    /// Make a header on top of prev_blockhash that meets the difficulty in bits
+    fn make_header(prev_blockhash: &Sha256dHash, time: u32, bits: u32) -> LoneBlockHeader {
+        let mut header = BlockHeader {
+            version: 0x20000000,
+            prev_blockhash: prev_blockhash.clone(),
+            merkle_root: Sha256dHash::from_data(&time.to_be_bytes()),
+            time: time,
+            bits: bits,
+            nonce: 0
+        };
+        while header.bitcoin_hash().into_le() > header.target() {
+            header.nonce += 1;
+        }
+        LoneBlockHeader {
+            header: header,
+            tx_count: VarInt(0)
+        }
+    }
+
+    #[test]
+    fn checkpoint_start_headers() {
+        let path = "/tmp/test_spv_checkpoint_start_headers.dat".to_string();
+        if fs::metadata(&path).is_ok() {
+            fs::remove_file(&path).unwrap();
+        }
+
+        let bits = 0x207fffff;
+        let checkpoint_header = make_header(&Sha256dHash::from_data(&[1u8; 32]), 1500000000, bits);
+        let checkpoint = SpvCheckpoint {
+            block_height: BLOCK_DIFFICULTY_CHUNK_SIZE,
+            block_hash: checkpoint_header.header.bitcoin_hash(),
+            bits: bits,
+            timestamp: 1500000000
+        };
+        let checkpoints = vec![SpvCheckpoint::genesis(BitcoinNetworkType::Regtest).unwrap(), checkpoint.clone()];
+
+        let mut client = SpvClient::new(&path, BLOCK_DIFFICULTY_CHUNK_SIZE, None, BitcoinNetworkType::Regtest, &checkpoints);
+
+        // a header that isn't the checkpointed block is refused
+        let other_header = make_header(&Sha256dHash::from_data(&[2u8; 32]), 1500000000, bits);
+        match client.init_block_headers_at_checkpoint(&checkpoint, &other_header) {
+            Err(btc_error::CheckpointMismatch) => {},
+            x => panic!("Expected CheckpointMismatch, got {:?}", x)
+        }
+        assert!(fs::metadata(&path).is_err());
+
+        // the new file starts at the checkpoint
+        client.init_block_headers_at_checkpoint(&checkpoint, &checkpoint_header).unwrap();
+        assert_eq!(SpvClient::get_headers_height(&path).unwrap(), BLOCK_DIFFICULTY_CHUNK_SIZE);
+        assert_eq!(SpvClient::read_block_header(&path, BLOCK_DIFFICULTY_CHUNK_SIZE).unwrap(), Some(checkpoint_header.clone()));
+        assert_eq!(SpvClient::read_block_header(&path, 0).unwrap(), Some(SpvClient::genesis_block_header(BitcoinNetworkType::Regtest).unwrap()));
+        assert_eq!(SpvClient::read_block_header(&path, 1).unwrap(), None);
+        assert_eq!(SpvClient::read_block_header(&path, BLOCK_DIFFICULTY_CHUNK_SIZE - 1).unwrap(), None);
+
+        // the next header is checked against the checkpoint's difficulty
+        let next_header = make_header(&checkpoint.block_hash, 1500000600, bits);
+        client.handle_headers(BLOCK_DIFFICULTY_CHUNK_SIZE, &vec![next_header.clone()]).unwrap();
+        assert_eq!(SpvClient::get_headers_height(&path).unwrap(), BLOCK_DIFFICULTY_CHUNK_SIZE + 1);
+        assert_eq!(SpvClient::read_block_header(&path, BLOCK_DIFFICULTY_CHUNK_SIZE + 1).unwrap(), Some(next_header.clone()));
+
+        // ...and one with a different difficulty is rejected
+        let bad_header = make_header(&next_header.header.bitcoin_hash(), 1500001200, 0x207ffffe);
+        match client.handle_headers(BLOCK_DIFFICULTY_CHUNK_SIZE + 1, &vec![bad_header]) {
+            Err(btc_error::InvalidPoW) => {},
+            x => panic!("Expected InvalidPoW, got {:?}", x)
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
 }
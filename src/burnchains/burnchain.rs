@@ -58,6 +58,8 @@ use chainstate::burn::operations::{
     LeaderBlockCommitOp,
     LeaderKeyRegisterOp,
     UserBurnSupportOp,
+    TransferStxOp,
+    PreStxOp,
     BlockstackOperation,
     BlockstackOperationType,
 };
@@ -89,6 +91,9 @@ use burnchains::bitcoin::indexer::FIRST_BLOCK_MAINNET as BITCOIN_FIRST_BLOCK_MAI
 use burnchains::bitcoin::indexer::FIRST_BLOCK_TESTNET as BITCOIN_FIRST_BLOCK_TESTNET;
 use burnchains::bitcoin::indexer::FIRST_BLOCK_REGTEST as BITCOIN_FIRST_BLOCK_REGTEST;
 
+// number of blocks to ask for at once when downloading
+const BLOCK_DOWNLOAD_BATCH_SIZE: usize = 16;
+
 impl BurnchainStateTransition {
     pub fn from_block_ops<'a>(tx: &mut BurnDBTx<'a>, parent_snapshot: &BlockSnapshot, block_ops: &Vec<BlockstackOperationType>) -> Result<BurnchainStateTransition, burnchain_error> {
         // block commits and support burns discovered in this block.
@@ -102,13 +107,17 @@ impl BurnchainStateTransition {
         let mut all_user_burns : HashMap<Txid, UserBurnSupportOp> = HashMap::new();
         let mut all_block_commits : HashMap<Txid, LeaderBlockCommitOp> = HashMap::new();
 
-        // accept all leader keys we found.
+        // accept all leader keys and STX operations we found.
         // don't treat block commits and user burn supports just yet.
         for i in 0..block_ops.len() {
             match block_ops[i] {
                 BlockstackOperationType::LeaderKeyRegister(_) => {
                     accepted_ops.push(block_ops[i].clone());
                 },
+                BlockstackOperationType::TransferStx(_) | BlockstackOperationType::PreStx(_) => {
+                    // these don't participate in sortition -- the Stacks chain state applies them
+                    accepted_ops.push(block_ops[i].clone());
+                },
                 BlockstackOperationType::LeaderBlockCommit(ref op) => {
                     // we don't yet know which block commits are going to be accepted until we have
                     // the burn distribution, so just account for them for now.
@@ -220,6 +229,20 @@ impl BurnchainSigner {
         let h = public_keys_to_address_hash(&self.hash_mode, self.num_sigs, &self.public_keys);
         h.as_bytes().to_vec()
     }
+
+    /// The Stacks address whose hash is derived from this signer's public keys
+    pub fn to_stacks_address(&self, mainnet: bool) -> StacksAddress {
+        let version = 
+            if mainnet {
+                self.hash_mode.to_version_mainnet()
+            }
+            else {
+                self.hash_mode.to_version_testnet()
+            };
+
+        let h = public_keys_to_address_hash(&self.hash_mode, self.num_sigs, &self.public_keys);
+        StacksAddress::new(version, h)
+    }
 }
 
 impl BurnchainRecipient {
@@ -367,8 +390,17 @@ impl Burnchain {
             };
 
         if !headers_pathbuf.exists() || headers_height < indexer.get_first_block_height() {
-            debug!("Fetch initial headers");
-            indexer.sync_headers(&headers_path, headers_height, None)
+            // a new headers file can start from a checkpoint near the first block
+            let start_height = 
+                if !headers_pathbuf.exists() {
+                    indexer.get_first_block_height()
+                }
+                else {
+                    headers_height
+                };
+
+            debug!("Fetch initial headers from {}", start_height);
+            indexer.sync_headers(&headers_path, start_height, None)
                 .map_err(|e| {
                     error!("Failed to sync initial headers");
                     e
@@ -449,6 +481,28 @@ impl Burnchain {
                     }
                 }
             },
+            x if x == Opcodes::TransferStx as u8 => {
+                match TransferStxOp::from_tx(block_header, burn_tx) {
+                    Ok(op) => {
+                        Some(BlockstackOperationType::TransferStx(op))
+                    },
+                    Err(e) => {
+                        warn!("Failed to parse STX transfer tx {} data {}: {:?}", &burn_tx.txid(), &to_hex(&burn_tx.data()[..]), e);
+                        None
+                    }
+                }
+            },
+            x if x == Opcodes::PreStx as u8 => {
+                match PreStxOp::from_tx(block_header, burn_tx) {
+                    Ok(op) => {
+                        Some(BlockstackOperationType::PreStx(op))
+                    },
+                    Err(e) => {
+                        warn!("Failed to parse stacking pre-registration tx {} data {}: {:?}", &burn_tx.txid(), &to_hex(&burn_tx.data()[..]), e);
+                        None
+                    }
+                }
+            },
             _ => {
                 None
             }
@@ -478,6 +532,20 @@ impl Burnchain {
                         warn!("REJECTED({}) user burn support {} at {},{}: {:?}", op.block_height, &op.txid, op.block_height, op.vtxindex, &e);
                         burnchain_error::OpError(e)
                     })
+            },
+            BlockstackOperationType::TransferStx(ref op) => {
+                op.check(burnchain, block_header, tx)
+                    .map_err(|e| {
+                        warn!("REJECTED({}) STX transfer {} at {},{}: {:?}", op.block_height, &op.txid, op.block_height, op.vtxindex, &e);
+                        burnchain_error::OpError(e)
+                    })
+            },
+            BlockstackOperationType::PreStx(ref op) => {
+                op.check(burnchain, block_header, tx)
+                    .map_err(|e| {
+                        warn!("REJECTED({}) stacking pre-registration {} at {},{}: {:?}", op.block_height, &op.txid, op.block_height, op.vtxindex, &e);
+                        burnchain_error::OpError(e)
+                    })
             }
         }
     }
@@ -693,7 +761,9 @@ impl Burnchain {
         Ok(new_snapshot)
     }
 
-    fn sync_reorg<I: BurnchainIndexer>(indexer: &mut I, chain_tip: &BlockSnapshot) -> Result<u64, burnchain_error> {
+    /// Check for a burnchain reorg, and drop any headers that are no longer canonical.
+    /// Returns the height of the last processed block that is still canonical.
+    pub fn sync_reorg<I: BurnchainIndexer>(indexer: &mut I, chain_tip: &BlockSnapshot) -> Result<u64, burnchain_error> {
         let headers_path = indexer.get_headers_path();
         let sync_height;
         
@@ -720,10 +790,12 @@ impl Burnchain {
             })?;
         
         if new_height < db_height {
-            warn!("Detected burnchain reorg at height {}. Re-sync'ing...", new_height);
+            warn!("Detected burnchain reorg after height {}. Re-sync'ing...", new_height);
 
-            // drop associated headers as well 
-            indexer.drop_headers(&headers_path, new_height)?;
+            // drop associated headers as well, keeping the last block both forks share
+            indexer.drop_headers(&headers_path, new_height + 1)?;
+
+            // resume from the last block both forks share
             sync_height = new_height;
         }
         else {
@@ -769,7 +841,7 @@ impl Burnchain {
 
         // initial inputs
         // TODO: stream this -- don't need to load them all into RAM
-        let input_headers = indexer.read_headers(&headers_path, sync_height, end_block)?;
+        let input_headers = indexer.read_headers(&headers_path, sync_height + 1, end_block + 1)?;
 
         // synchronize 
         let (downloader_send, downloader_recv) = sync_channel(1);
@@ -781,30 +853,42 @@ impl Burnchain {
 
         let burnchain_config = self.clone();
 
-        // TODO: don't re-process blocks.  See if the block hash is already present in the burn db,
-        // and if so, do nothing.
+        // each stage runs until its upstream stage hangs up
         let download_thread : thread::JoinHandle<Result<(), burnchain_error>> = thread::spawn(move || {
             loop {
-                debug!("Try recv next header");
-                let ipc_header = downloader_recv.recv()
-                    .map_err(|_e| burnchain_error::ThreadChannelError)?;
+                debug!("Try recv next batch of headers");
+                let ipc_headers = match downloader_recv.recv() {
+                    Ok(h) => h,
+                    Err(_e) => {
+                        debug!("No more headers to download");
+                        break;
+                    }
+                };
 
                 let download_start = get_epoch_time_ms();
-                let ipc_block = downloader.download(&ipc_header)?;
+                let ipc_blocks = downloader.download_batch(&ipc_headers)?;
                 let download_end = get_epoch_time_ms();
 
-                debug!("Downloaded block {} in {}ms", ipc_block.height(), download_end - download_start);
+                debug!("Downloaded {} blocks in {}ms", ipc_blocks.len(), download_end - download_start);
 
-                parser_send.send(ipc_block)
-                    .map_err(|_e| burnchain_error::ThreadChannelError)?;
+                for ipc_block in ipc_blocks.into_iter() {
+                    parser_send.send(ipc_block)
+                        .map_err(|_e| burnchain_error::ThreadChannelError)?;
+                }
             }
+            Ok(())
         });
 
         let parse_thread : thread::JoinHandle<Result<(), burnchain_error>> = thread::spawn(move || {
             loop {
                 debug!("Try recv next block");
-                let ipc_block = parser_recv.recv()
-                    .map_err(|_e| burnchain_error::ThreadChannelError)?;
+                let ipc_block = match parser_recv.recv() {
+                    Ok(b) => b,
+                    Err(_e) => {
+                        debug!("No more blocks to parse");
+                        break;
+                    }
+                };
 
                 let parse_start = get_epoch_time_ms();
                 let burnchain_block = parser.parse(&ipc_block)?;
@@ -815,14 +899,30 @@ impl Burnchain {
                 db_send.send(burnchain_block)
                     .map_err(|_e| burnchain_error::ThreadChannelError)?;
             }
+            Ok(())
         });
 
         let db_thread : thread::JoinHandle<Result<(), burnchain_error>> = thread::spawn(move || {
             loop {
                 debug!("Try recv next parsed block");
 
-                let burnchain_block = db_recv.recv()
-                    .map_err(|_e| burnchain_error::ThreadChannelError)?;
+                let burnchain_block = match db_recv.recv() {
+                    Ok(b) => b,
+                    Err(_e) => {
+                        debug!("No more blocks to insert");
+                        break;
+                    }
+                };
+
+                // blocks on a fork we already processed (e.g. a fork we are switching back to)
+                // are already snapshotted
+                let existing_snapshot = BurnDB::get_block_snapshot(burndb.conn(), &burnchain_block.block_hash())
+                    .map_err(burnchain_error::DBError)?;
+
+                if existing_snapshot.is_some() {
+                    debug!("Already processed block {} {}", burnchain_block.block_height(), &burnchain_block.block_hash());
+                    continue;
+                }
 
                 let insert_start = get_epoch_time_ms();
                 Burnchain::process_block(&mut burndb, &burnchain_config, &burnchain_block)?;
@@ -830,18 +930,22 @@ impl Burnchain {
 
                 debug!("Inserted block {} in {}ms", burnchain_block.block_height(), insert_end - insert_start);
             }
+            Ok(())
         });
 
         // feed the pipeline!
-        for i in 0..input_headers.len() {
-            downloader_send.send(input_headers[i].clone())
+        for batch in input_headers.chunks(BLOCK_DOWNLOAD_BATCH_SIZE) {
+            downloader_send.send(batch.to_vec())
                 .map_err(|_e| burnchain_error::ThreadChannelError)?;
         }
 
+        // no more headers -- let the pipeline drain
+        drop(downloader_send);
+
         // join up 
-        download_thread.join().unwrap().unwrap();
-        parse_thread.join().unwrap().unwrap();
-        db_thread.join().unwrap().unwrap();
+        download_thread.join().unwrap()?;
+        parse_thread.join().unwrap()?;
+        db_thread.join().unwrap()?;
         
         Ok(end_block)
     }
@@ -1294,6 +1398,12 @@ pub mod tests {
                     },
                     BlockstackOperationType::UserBurnSupport(ref mut op) => {
                         op.burn_header_hash = block_124_hash.clone();
+                    },
+                    BlockstackOperationType::TransferStx(ref mut op) => {
+                        op.burn_header_hash = block_124_hash.clone();
+                    },
+                    BlockstackOperationType::PreStx(ref mut op) => {
+                        op.burn_header_hash = block_124_hash.clone();
                     }
                 }
             }
@@ -1307,7 +1417,9 @@ pub mod tests {
                     match bo {
                         BlockstackOperationType::LeaderBlockCommit(ref op) => op.txid.clone(),
                         BlockstackOperationType::LeaderKeyRegister(ref op) => op.txid.clone(),
-                        BlockstackOperationType::UserBurnSupport(ref op) => op.txid.clone()
+                        BlockstackOperationType::UserBurnSupport(ref op) => op.txid.clone(),
+                        BlockstackOperationType::TransferStx(ref op) => op.txid.clone(),
+                        BlockstackOperationType::PreStx(ref op) => op.txid.clone()
                     }
                 })
                 .collect()
@@ -44,6 +44,15 @@ pub trait BurnchainBlockDownloader {
     type B: BurnBlockIPC + Sync + Send + Clone;
 
     fn download(&mut self, header: &Self::H) -> Result<Self::B, burnchain_error>;
+
+    /// Download a batch of blocks.  Blocks are returned in the same order as their headers.
+    fn download_batch(&mut self, headers: &Vec<Self::H>) -> Result<Vec<Self::B>, burnchain_error> {
+        let mut blocks = Vec::with_capacity(headers.len());
+        for header in headers.iter() {
+            blocks.push(self.download(header)?);
+        }
+        Ok(blocks)
+    }
 }
 
 pub trait BurnchainBlockParser {
@@ -39,6 +39,7 @@ use chainstate::ChainstateDB;
 
 use chainstate::burn::Opcodes;
 use chainstate::burn::{ConsensusHash, VRFSeed, BlockHeaderHash, OpsHash, BlockSnapshot, SortitionHash};
+use chainstate::burn::distribution::BurnSamplePoint;
 
 use core::CHAINSTATE_VERSION;
 
@@ -46,6 +47,8 @@ use chainstate::burn::operations::{
     LeaderBlockCommitOp,
     LeaderKeyRegisterOp,
     UserBurnSupportOp,
+    TransferStxOp,
+    PreStxOp,
     BlockstackOperation,
     BlockstackOperationType
 };
@@ -64,7 +67,7 @@ use chainstate::stacks::StacksAddress;
 use chainstate::stacks::StacksPublicKey;
 use chainstate::stacks::*;
 use chainstate::stacks::index::TrieHash;
-use chainstate::stacks::index::storage::TrieFileStorage;
+use chainstate::stacks::index::storage::{TrieFileStorage, TrieStorageLayout};
 use chainstate::stacks::index::marf::MARF;
 use chainstate::stacks::index::MARFValue;
 use chainstate::stacks::index::Error as MARFError;
@@ -282,6 +285,75 @@ impl FromRow<UserBurnSupportOp> for UserBurnSupportOp {
     }
 }
 
+impl FromRow<TransferStxOp> for TransferStxOp {
+    fn from_row<'a>(row: &'a Row) -> Result<TransferStxOp, db_error> {
+        let txid = Txid::from_column(row, "txid")?;
+        let vtxindex : u32 = row.get("vtxindex");
+        let block_height : i64 = row.get("block_height");
+        let burn_header_hash = BurnchainHeaderHash::from_column(row, "burn_header_hash")?;
+
+        let sender = StacksAddress::from_column(row, "sender")?;
+        let recipient = StacksAddress::from_column(row, "recipient")?;
+        let transfered_ustx_str : String = row.get("transfered_ustx");
+        let memo_hex : String = row.get("memo");
+
+        let transfered_ustx = transfered_ustx_str.parse::<u64>()
+            .map_err(|_e| db_error::ParseError)?;
+
+        let memo = hex_bytes(&memo_hex)
+            .map_err(|_e| db_error::ParseError)?;
+
+        if block_height < 0 {
+            return Err(db_error::ParseError);
+        }
+
+        let transfer_stx = TransferStxOp {
+            sender: sender,
+            recipient: recipient,
+            transfered_ustx: transfered_ustx,
+            memo: memo,
+
+            txid: txid,
+            vtxindex: vtxindex,
+            block_height: block_height as u64,
+            burn_header_hash: burn_header_hash
+        };
+        Ok(transfer_stx)
+    }
+}
+
+impl FromRow<PreStxOp> for PreStxOp {
+    fn from_row<'a>(row: &'a Row) -> Result<PreStxOp, db_error> {
+        let txid = Txid::from_column(row, "txid")?;
+        let vtxindex : u32 = row.get("vtxindex");
+        let block_height : i64 = row.get("block_height");
+        let burn_header_hash = BurnchainHeaderHash::from_column(row, "burn_header_hash")?;
+
+        let stacker = StacksAddress::from_column(row, "stacker")?;
+        let reward_address = StacksAddress::from_column(row, "reward_address")?;
+        let stacked_ustx_str : String = row.get("stacked_ustx");
+
+        let stacked_ustx = stacked_ustx_str.parse::<u64>()
+            .map_err(|_e| db_error::ParseError)?;
+
+        if block_height < 0 {
+            return Err(db_error::ParseError);
+        }
+
+        let pre_stx = PreStxOp {
+            stacker: stacker,
+            reward_address: reward_address,
+            stacked_ustx: stacked_ustx,
+
+            txid: txid,
+            vtxindex: vtxindex,
+            block_height: block_height as u64,
+            burn_header_hash: burn_header_hash
+        };
+        Ok(pre_stx)
+    }
+}
+
 const BURNDB_SETUP : &'static [&'static str]= &[
     r#"
     PRAGMA foreign_keys = ON;
@@ -375,6 +447,42 @@ const BURNDB_SETUP : &'static [&'static str]= &[
     "#
 ];
 
+/// Tables added after the burn database schema was first released.  These are applied to new
+/// databases and to existing ones whenever they are opened read/write, so each must be idempotent.
+const BURNDB_MIGRATIONS : &'static [&'static str] = &[
+    r#"
+    -- STX transfers sent from the burn chain.
+    CREATE TABLE IF NOT EXISTS transfer_stx(
+        txid TEXT NOT NULL,
+        vtxindex INTEGER NOT NULL,
+        block_height INTEGER NOT NULL,
+        burn_header_hash TEXT NOT NULL,
+
+        sender TEXT NOT NULL,
+        recipient TEXT NOT NULL,
+        transfered_ustx TEXT NOT NULL,      -- use text to encode really big numbers
+        memo TEXT,
+
+        PRIMARY KEY(txid,burn_header_hash),
+        FOREIGN KEY(burn_header_hash) REFERENCES snapshots(burn_header_hash)
+    );"#,
+    r#"
+    -- stacking pre-registrations sent from the burn chain.
+    CREATE TABLE IF NOT EXISTS pre_stx(
+        txid TEXT NOT NULL,
+        vtxindex INTEGER NOT NULL,
+        block_height INTEGER NOT NULL,
+        burn_header_hash TEXT NOT NULL,
+
+        stacker TEXT NOT NULL,
+        reward_address TEXT NOT NULL,
+        stacked_ustx TEXT NOT NULL,
+
+        PRIMARY KEY(txid,burn_header_hash),
+        FOREIGN KEY(burn_header_hash) REFERENCES snapshots(burn_header_hash)
+    );"#
+];
+
 pub struct BurnDB {
     pub conn: Connection,
     pub readwrite: bool,
@@ -420,6 +528,10 @@ impl BurnDB {
             tx.execute(row_text, NO_PARAMS).map_err(db_error::SqliteError)?;
         }
 
+        for row_text in BURNDB_MIGRATIONS {
+            tx.execute(row_text, NO_PARAMS).map_err(db_error::SqliteError)?;
+        }
+
         tx.execute("INSERT INTO db_config (version) VALUES (?1)", &[&CHAINSTATE_VERSION]).map_err(db_error::SqliteError)?;
         
         let mut marf = BurnDB::open_index(index_path)?;
@@ -495,6 +607,10 @@ impl BurnDB {
                     }
                 }
             };
+
+            if readwrite {
+                BurnDB::migrate(&mut conn)?;
+            }
         }
 
         let marf = BurnDB::open_index(&index_path)?;
@@ -550,7 +666,11 @@ impl BurnDB {
             };
 
         let (db_path, index_path) = db_mkdirs(path)?;
-        let conn = Connection::open_with_flags(&db_path, open_flags).map_err(db_error::SqliteError)?;
+        let mut conn = Connection::open_with_flags(&db_path, open_flags).map_err(db_error::SqliteError)?;
+        if readwrite {
+            BurnDB::migrate(&mut conn)?;
+        }
+
         let marf = BurnDB::open_index(&index_path)?;
         let first_snapshot = BurnDB::get_first_block_snapshot(&conn)?;
 
@@ -564,13 +684,25 @@ impl BurnDB {
         Ok(db)
     }
 
+    /// Bring an existing burn database up to the current schema
+    fn migrate(conn: &mut Connection) -> Result<(), db_error> {
+        let tx = conn.transaction().map_err(db_error::SqliteError)?;
+
+        for row_text in BURNDB_MIGRATIONS {
+            tx.execute(row_text, NO_PARAMS).map_err(db_error::SqliteError)?;
+        }
+
+        tx.commit().map_err(db_error::SqliteError)?;
+        Ok(())
+    }
+
     pub fn conn(&self) -> &Connection {
         &self.conn
     }
 
     pub fn open_index(index_path: &str) -> Result<MARF, db_error> {
         test_debug!("Open index at {}", index_path);
-        let marf = MARF::from_path(index_path, None).map_err(|_e| db_error::Corruption)?;
+        let marf = MARF::from_path(index_path, None, TrieStorageLayout::FilePerBlock).map_err(|_e| db_error::Corruption)?;
         Ok(marf)
     }
 
@@ -615,6 +747,16 @@ impl BurnDB {
                 debug!("ACCEPTED({}) user burn support {} at {},{}", op.block_height, &op.txid, op.block_height, op.vtxindex);
                 BurnDB::insert_user_burn(tx, op)
                     .expect("FATAL: failed to store user burn support to Sqlite");
+            },
+            BlockstackOperationType::TransferStx(ref op) => {
+                debug!("ACCEPTED({}) STX transfer {} at {},{}", op.block_height, &op.txid, op.block_height, op.vtxindex);
+                BurnDB::insert_transfer_stx(tx, op)
+                    .expect("FATAL: failed to store STX transfer to Sqlite");
+            },
+            BlockstackOperationType::PreStx(ref op) => {
+                debug!("ACCEPTED({}) stacking pre-registration {} at {},{}", op.block_height, &op.txid, op.block_height, op.vtxindex);
+                BurnDB::insert_pre_stx(tx, op)
+                    .expect("FATAL: failed to store stacking pre-registration to Sqlite");
             }
         }
         Ok(())
@@ -665,6 +807,34 @@ impl BurnDB {
             }
         }
 
+        // STX transfer?
+        let transfer_stx_sql = "SELECT * FROM transfer_stx WHERE txid = ?1 LIMIT 1".to_string();
+
+        let transfer_stx_rows = query_rows::<TransferStxOp, _>(conn, &transfer_stx_sql, &args)?;
+        match transfer_stx_rows.len() {
+            0 => {},
+            1 => {
+                return Ok(Some(BlockstackOperationType::TransferStx(transfer_stx_rows[0].clone())));
+            },
+            _ => {
+                panic!("Multiple STX transfers with the same txid");
+            }
+        }
+
+        // stacking pre-registration?
+        let pre_stx_sql = "SELECT * FROM pre_stx WHERE txid = ?1 LIMIT 1".to_string();
+
+        let pre_stx_rows = query_rows::<PreStxOp, _>(conn, &pre_stx_sql, &args)?;
+        match pre_stx_rows.len() {
+            0 => {},
+            1 => {
+                return Ok(Some(BlockstackOperationType::PreStx(pre_stx_rows[0].clone())));
+            },
+            _ => {
+                panic!("Multiple stacking pre-registrations with the same txid");
+            }
+        }
+
         Ok(None)
     }
 
@@ -877,6 +1047,42 @@ impl BurnDB {
 
         Ok(())
     }
+
+    /// Insert a burnchain STX transfer.
+    /// No validity checking will be done -- the Stacks chain state decides whether or not the
+    /// sender can afford it.
+    /// The corresponding snapshot must already be inserted
+    pub fn insert_transfer_stx<'a>(tx: &mut BurnDBTx<'a>, transfer_stx: &TransferStxOp) -> Result<(), db_error> {
+        assert!(transfer_stx.block_height < BLOCK_HEIGHT_MAX);
+
+        let transfered_ustx_str = format!("{}", transfer_stx.transfered_ustx);
+
+        tx.execute("INSERT INTO transfer_stx (txid, vtxindex, block_height, burn_header_hash, sender, recipient, transfered_ustx, memo) \
+                   VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                   &[&transfer_stx.txid, &transfer_stx.vtxindex as &dyn ToSql, &(transfer_stx.block_height as i64), &transfer_stx.burn_header_hash,
+                   &transfer_stx.sender.to_string(), &transfer_stx.recipient.to_string(), &transfered_ustx_str, &to_hex(&transfer_stx.memo[..])])
+            .map_err(db_error::SqliteError)?;
+
+        Ok(())
+    }
+
+    /// Insert a burnchain stacking pre-registration.
+    /// No validity checking will be done -- the Stacks chain state decides whether or not the
+    /// stacker can afford it.
+    /// The corresponding snapshot must already be inserted
+    pub fn insert_pre_stx<'a>(tx: &mut BurnDBTx<'a>, pre_stx: &PreStxOp) -> Result<(), db_error> {
+        assert!(pre_stx.block_height < BLOCK_HEIGHT_MAX);
+
+        let stacked_ustx_str = format!("{}", pre_stx.stacked_ustx);
+
+        tx.execute("INSERT INTO pre_stx (txid, vtxindex, block_height, burn_header_hash, stacker, reward_address, stacked_ustx) \
+                   VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                   &[&pre_stx.txid, &pre_stx.vtxindex as &dyn ToSql, &(pre_stx.block_height as i64), &pre_stx.burn_header_hash,
+                   &pre_stx.stacker.to_string(), &pre_stx.reward_address.to_string(), &stacked_ustx_str])
+            .map_err(db_error::SqliteError)?;
+
+        Ok(())
+    }
     
     /// Get the first snapshot 
     pub fn get_first_block_snapshot(conn: &Connection) -> Result<BlockSnapshot, db_error> {
@@ -1063,6 +1269,46 @@ impl BurnDB {
 
         query_rows::<UserBurnSupportOp, _>(tx, &qry.to_string(), &args)
     }
+
+    /// Get all STX transfers sent in a block on this fork.
+    /// Returns list of STX transfers in order by vtxindex.
+    pub fn get_transfer_stx_ops_by_block<'a>(tx: &mut BurnDBTx<'a>, block_height: u64, tip_block_hash: &BurnchainHeaderHash) -> Result<Vec<TransferStxOp>, db_error> {
+        assert!(block_height < BLOCK_HEIGHT_MAX);
+        let ancestor_snapshot = match BurnDB::get_ancestor_snapshot(tx, block_height, tip_block_hash)? {
+            Some(sn) => {
+                sn
+            },
+            None => {
+                error!("No ancestor snapshot at {} from {}", block_height, tip_block_hash);
+                return Err(db_error::NotFoundError);
+            }
+        };
+
+        let qry = "SELECT * FROM transfer_stx WHERE burn_header_hash = ?1 AND block_height = ?2 ORDER BY vtxindex ASC".to_string();
+        let args: [&dyn ToSql; 2] = [&ancestor_snapshot.burn_header_hash, &(block_height as i64)];
+
+        query_rows::<TransferStxOp, _>(tx, &qry.to_string(), &args)
+    }
+
+    /// Get all stacking pre-registrations sent in a block on this fork.
+    /// Returns list of pre-registrations in order by vtxindex.
+    pub fn get_pre_stx_ops_by_block<'a>(tx: &mut BurnDBTx<'a>, block_height: u64, tip_block_hash: &BurnchainHeaderHash) -> Result<Vec<PreStxOp>, db_error> {
+        assert!(block_height < BLOCK_HEIGHT_MAX);
+        let ancestor_snapshot = match BurnDB::get_ancestor_snapshot(tx, block_height, tip_block_hash)? {
+            Some(sn) => {
+                sn
+            },
+            None => {
+                error!("No ancestor snapshot at {} from {}", block_height, tip_block_hash);
+                return Err(db_error::NotFoundError);
+            }
+        };
+
+        let qry = "SELECT * FROM pre_stx WHERE burn_header_hash = ?1 AND block_height = ?2 ORDER BY vtxindex ASC".to_string();
+        let args: [&dyn ToSql; 2] = [&ancestor_snapshot.burn_header_hash, &(block_height as i64)];
+
+        query_rows::<PreStxOp, _>(tx, &qry.to_string(), &args)
+    }
     
     /// Get all user burns that burned for a particular block in a fork.
     /// Returns list of user burns in order by vtxindex.
@@ -1105,6 +1351,29 @@ impl BurnDB {
         Ok(burn_total)
     }
     
+    /// Reconstruct the burn distribution of a block in a fork from its accepted block commits, its
+    /// accepted user burns, and the leader keys those block commits consumed.
+    /// Only accepted operations are stored, so this is the same distribution the sortition sampled.
+    /// Returns the distribution's sample points in order by block commit vtxindex.
+    pub fn get_burn_distribution<'a>(tx: &mut BurnDBTx<'a>, block_height: u64, tip_block_hash: &BurnchainHeaderHash) -> Result<Vec<BurnSamplePoint>, db_error> {
+        assert!(block_height < BLOCK_HEIGHT_MAX);
+        let snapshot = match BurnDB::get_ancestor_snapshot(tx, block_height, tip_block_hash)? {
+            Some(sn) => {
+                sn
+            },
+            None => {
+                error!("No ancestor snapshot at {} from {}", block_height, tip_block_hash);
+                return Err(db_error::NotFoundError);
+            }
+        };
+
+        let block_commits = BurnDB::get_block_commits_by_block(tx, block_height, tip_block_hash)?;
+        let user_burns = BurnDB::get_user_burns_by_block(tx, block_height, tip_block_hash)?;
+        let consumed_leader_keys = BurnDB::get_consumed_leader_keys(tx, &snapshot.parent_burn_header_hash, &block_commits)?;
+
+        Ok(BurnSamplePoint::make_distribution(block_commits, consumed_leader_keys, user_burns))
+    }
+    
     /// Get a parent block commit at a specific location in the burn chain on a particular fork.
     /// Returns None if there is no block commit at this location.
     pub fn get_block_commit_parent<'a>(tx: &mut BurnDBTx<'a>, block_height: u64, vtxindex: u32, tip_block_hash: &BurnchainHeaderHash) -> Result<Option<LeaderBlockCommitOp>, db_error> {
@@ -1491,6 +1760,8 @@ mod tests {
         LeaderBlockCommitOp,
         LeaderKeyRegisterOp,
         UserBurnSupportOp,
+        TransferStxOp,
+        PreStxOp,
         BlockstackOperation,
         BlockstackOperationType
     };
@@ -1502,6 +1773,7 @@ mod tests {
     use burnchains::{Txid, BurnchainHeaderHash};
     use chainstate::burn::{ConsensusHash, VRFSeed, BlockHeaderHash};
     use util::hash::{hex_bytes, Hash160};
+    use util::uint::{Uint256, BitArray};
     use util::vrf::*;
 
     use chainstate::stacks::StacksAddress;
@@ -1697,6 +1969,22 @@ mod tests {
             let no_block_commits = BurnDB::get_block_commits_by_block(&mut tx, block_height+1, &snapshot_consumed.burn_header_hash).unwrap();
             assert_eq!(no_block_commits.len(), 0);
         }
+
+        // test get_burn_distribution()
+        {
+            let mut tx = db.tx_begin().unwrap();
+            let burn_dist = BurnDB::get_burn_distribution(&mut tx, block_height+2, &snapshot_consumed.burn_header_hash).unwrap();
+            assert_eq!(burn_dist.len(), 1);
+            assert_eq!(burn_dist[0].candidate, block_commit);
+            assert_eq!(burn_dist[0].key, leader_key);
+            assert_eq!(burn_dist[0].burns, block_commit.burn_fee as u128);
+            assert_eq!(burn_dist[0].range_start, Uint256::zero());
+            assert_eq!(burn_dist[0].range_end, Uint256::max());
+            assert_eq!(burn_dist[0].user_burns.len(), 0);
+
+            let no_burn_dist = BurnDB::get_burn_distribution(&mut tx, block_height+1, &snapshot_consumed.burn_header_hash).unwrap();
+            assert_eq!(no_burn_dist.len(), 0);
+        }
         
         // test is_leader_key_consumed() now that the commit exists
         {
@@ -1877,6 +2165,75 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_insert_stx_ops() {
+        let block_height = 123;
+        let first_burn_hash = BurnchainHeaderHash::from_hex("0000000000000000000000000000000000000000000000000000000000000000").unwrap();
+
+        let transfer_stx = TransferStxOp {
+            sender: StacksAddress::new(26, Hash160([1u8; 20])),
+            recipient: StacksAddress::new(26, Hash160([2u8; 20])),
+            transfered_ustx: 12345,
+            memo: vec![01, 02, 03, 04, 05],
+
+            txid: Txid::from_bytes_be(&hex_bytes("1bfa831b5fc56c858198acb8e77e5863c1e9d8ac26d49ddb914e24d8d4083562").unwrap()).unwrap(),
+            vtxindex: 1,
+            block_height: block_height + 1,
+            burn_header_hash: BurnchainHeaderHash([0x01; 32])
+        };
+
+        let pre_stx = PreStxOp {
+            stacker: StacksAddress::new(26, Hash160([1u8; 20])),
+            reward_address: StacksAddress::new(26, Hash160([3u8; 20])),
+            stacked_ustx: 67890,
+
+            txid: Txid::from_bytes_be(&hex_bytes("1d5cbdd276495b07f0e0bf0181fa57c175b217bc35531b078d62fc20986c716c").unwrap()).unwrap(),
+            vtxindex: 2,
+            block_height: block_height + 1,
+            burn_header_hash: BurnchainHeaderHash([0x01; 32])
+        };
+
+        let mut db = BurnDB::connect_memory(block_height, &first_burn_hash).unwrap();
+
+        let snapshot = {
+            let mut tx = db.tx_begin().unwrap();
+            let mut sn = BurnDB::get_canonical_burn_chain_tip(&mut tx).unwrap();
+
+            let sn_parent = sn.clone();
+            sn.parent_burn_header_hash = sn.burn_header_hash.clone();
+            sn.burn_header_hash = BurnchainHeaderHash([0x01; 32]);
+            sn.block_height += 1;
+            sn.num_sortitions += 1;
+
+            let index_root = BurnDB::append_chain_tip_snapshot(&mut tx, &sn_parent, &sn, &vec![BlockstackOperationType::TransferStx(transfer_stx.clone()), BlockstackOperationType::PreStx(pre_stx.clone())], &vec![]).unwrap();
+            sn.index_root = index_root;
+
+            tx.commit().unwrap();
+            sn
+        };
+
+        {
+            let mut tx = db.tx_begin().unwrap();
+            let res_transfers = BurnDB::get_transfer_stx_ops_by_block(&mut tx, block_height+1, &snapshot.burn_header_hash).unwrap();
+            assert_eq!(res_transfers, vec![transfer_stx.clone()]);
+
+            let res_pre_stx = BurnDB::get_pre_stx_ops_by_block(&mut tx, block_height+1, &snapshot.burn_header_hash).unwrap();
+            assert_eq!(res_pre_stx, vec![pre_stx.clone()]);
+
+            let no_transfers = BurnDB::get_transfer_stx_ops_by_block(&mut tx, block_height, &snapshot.burn_header_hash).unwrap();
+            assert_eq!(no_transfers.len(), 0);
+        }
+
+        match BurnDB::get_burnchain_transaction(db.conn(), &transfer_stx.txid).unwrap() {
+            Some(BlockstackOperationType::TransferStx(op)) => assert_eq!(op, transfer_stx),
+            x => panic!("Expected STX transfer, got {:?}", &x)
+        }
+        match BurnDB::get_burnchain_transaction(db.conn(), &pre_stx.txid).unwrap() {
+            Some(BlockstackOperationType::PreStx(op)) => assert_eq!(op, pre_stx),
+            x => panic!("Expected stacking pre-registration, got {:?}", &x)
+        }
+    }
+
     #[test]
     fn has_VRF_public_key() {
         let public_key = VRFPublicKey::from_bytes(&hex_bytes("a366b51292bef4edd64063d9145c617fec373bceb0758e98cd72becd84d54c7a").unwrap()).unwrap();
@@ -106,7 +106,9 @@ impl_byte_array_newtype!(SortitionHash, u8, 32);
 pub enum Opcodes {
     LeaderBlockCommit = '[' as u8,
     LeaderKeyRegister = '^' as u8,
-    UserBurnSupport = '_' as u8
+    UserBurnSupport = '_' as u8,
+    TransferStx = '$' as u8,
+    PreStx = 'p' as u8
 }
 
 // a burnchain block snapshot
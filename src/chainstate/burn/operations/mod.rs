@@ -22,6 +22,8 @@
 pub mod leader_key_register;
 pub mod leader_block_commit;
 pub mod user_burn_support;
+pub mod transfer_stx;
+pub mod pre_stx;
 
 use std::fmt;
 use std::error;
@@ -78,6 +80,13 @@ pub enum Error {
     // all the things that can go wrong with user burn supports
     UserBurnSupportBadConsensusHash,
     UserBurnSupportNoLeaderKey,
+
+    // all the things that can go wrong with STX transfers
+    TransferStxMustBePositive,
+    TransferStxSelfSend,
+
+    // all the things that can go wrong with stacking pre-registrations
+    PreStxMustBePositive,
 }
 
 impl fmt::Display for Error {
@@ -98,6 +107,11 @@ impl fmt::Display for Error {
 
             Error::UserBurnSupportBadConsensusHash => write!(f, "User burn support has an invalid consensus hash"),
             Error::UserBurnSupportNoLeaderKey => write!(f, "User burn support does not match a registered leader key"),
+
+            Error::TransferStxMustBePositive => write!(f, "STX transfer must send a positive amount"),
+            Error::TransferStxSelfSend => write!(f, "STX transfer sender and recipient are the same"),
+
+            Error::PreStxMustBePositive => write!(f, "Stacking pre-registration must stack a positive amount"),
         }
     }
 }
@@ -120,6 +134,11 @@ impl error::Error for Error {
 
             Error::UserBurnSupportBadConsensusHash => None,
             Error::UserBurnSupportNoLeaderKey => None,
+
+            Error::TransferStxMustBePositive => None,
+            Error::TransferStxSelfSend => None,
+
+            Error::PreStxMustBePositive => None,
         }
     }
 }
@@ -175,6 +194,33 @@ pub struct UserBurnSupportOp {
     pub burn_header_hash: BurnchainHeaderHash,   // hash of burnchain block with this tx
 }
 
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct TransferStxOp {
+    pub sender: StacksAddress,              // derived from the burnchain tx's first input
+    pub recipient: StacksAddress,           // the burnchain tx's first output
+    pub transfered_ustx: u64,               // how many microSTX to send
+    pub memo: Vec<u8>,                      // extra bytes in the op-return
+
+    // common to all transactions
+    pub txid: Txid,                         // transaction ID
+    pub vtxindex: u32,                      // index in the block where this tx occurs
+    pub block_height: u64,                  // block height at which this tx occurs
+    pub burn_header_hash: BurnchainHeaderHash,   // hash of burnchain block with this tx
+}
+
+#[derive(Debug, PartialEq, Clone, Eq)]
+pub struct PreStxOp {
+    pub stacker: StacksAddress,             // derived from the burnchain tx's first input
+    pub reward_address: StacksAddress,      // the burnchain tx's first output
+    pub stacked_ustx: u64,                  // how many microSTX the stacker intends to stack
+
+    // common to all transactions
+    pub txid: Txid,                         // transaction ID
+    pub vtxindex: u32,                      // index in the block where this tx occurs
+    pub block_height: u64,                  // block height at which this tx occurs
+    pub burn_header_hash: BurnchainHeaderHash,   // hash of burnchain block with this tx
+}
+
 pub trait BlockstackOperation {
     fn check<'a>(&self, burnchain: &Burnchain, block_header: &BurnchainBlockHeader, tx: &mut BurnDBTx<'a>) -> Result<(), Error>;
     fn from_tx(block_header: &BurnchainBlockHeader, tx: &BurnchainTransaction) -> Result<Self, Error>
@@ -185,7 +231,9 @@ pub trait BlockstackOperation {
 pub enum BlockstackOperationType {
     LeaderKeyRegister(LeaderKeyRegisterOp),
     LeaderBlockCommit(LeaderBlockCommitOp),
-    UserBurnSupport(UserBurnSupportOp)
+    UserBurnSupport(UserBurnSupportOp),
+    TransferStx(TransferStxOp),
+    PreStx(PreStxOp)
 }
 
 impl BlockstackOperationType {
@@ -193,7 +241,9 @@ impl BlockstackOperationType {
         match *self {
             BlockstackOperationType::LeaderKeyRegister(_) => Opcodes::LeaderKeyRegister,
             BlockstackOperationType::LeaderBlockCommit(_) => Opcodes::LeaderBlockCommit,
-            BlockstackOperationType::UserBurnSupport(_) => Opcodes::UserBurnSupport
+            BlockstackOperationType::UserBurnSupport(_) => Opcodes::UserBurnSupport,
+            BlockstackOperationType::TransferStx(_) => Opcodes::TransferStx,
+            BlockstackOperationType::PreStx(_) => Opcodes::PreStx
         }
     }
 
@@ -201,7 +251,9 @@ impl BlockstackOperationType {
         match *self {
             BlockstackOperationType::LeaderKeyRegister(ref data) => data.txid.clone(),
             BlockstackOperationType::LeaderBlockCommit(ref data) => data.txid.clone(),
-            BlockstackOperationType::UserBurnSupport(ref data) => data.txid.clone()
+            BlockstackOperationType::UserBurnSupport(ref data) => data.txid.clone(),
+            BlockstackOperationType::TransferStx(ref data) => data.txid.clone(),
+            BlockstackOperationType::PreStx(ref data) => data.txid.clone()
         }
     }
 
@@ -210,6 +262,8 @@ impl BlockstackOperationType {
             BlockstackOperationType::LeaderKeyRegister(ref data) => data.vtxindex,
             BlockstackOperationType::LeaderBlockCommit(ref data) => data.vtxindex,
             BlockstackOperationType::UserBurnSupport(ref data) => data.vtxindex,
+            BlockstackOperationType::TransferStx(ref data) => data.vtxindex,
+            BlockstackOperationType::PreStx(ref data) => data.vtxindex,
         }
     }
 
@@ -217,7 +271,9 @@ impl BlockstackOperationType {
         match *self {
             BlockstackOperationType::LeaderKeyRegister(ref data) => data.block_height,
             BlockstackOperationType::LeaderBlockCommit(ref data) => data.block_height,
-            BlockstackOperationType::UserBurnSupport(ref data) => data.block_height
+            BlockstackOperationType::UserBurnSupport(ref data) => data.block_height,
+            BlockstackOperationType::TransferStx(ref data) => data.block_height,
+            BlockstackOperationType::PreStx(ref data) => data.block_height
         }
     }
 
@@ -225,7 +281,9 @@ impl BlockstackOperationType {
         match *self {
             BlockstackOperationType::LeaderKeyRegister(ref data) => data.burn_header_hash.clone(),
             BlockstackOperationType::LeaderBlockCommit(ref data) => data.burn_header_hash.clone(),
-            BlockstackOperationType::UserBurnSupport(ref data) => data.burn_header_hash.clone()
+            BlockstackOperationType::UserBurnSupport(ref data) => data.burn_header_hash.clone(),
+            BlockstackOperationType::TransferStx(ref data) => data.burn_header_hash.clone(),
+            BlockstackOperationType::PreStx(ref data) => data.burn_header_hash.clone()
         }
     }
 }
@@ -236,7 +294,9 @@ impl fmt::Display for BlockstackOperationType {
         match *self {
             BlockstackOperationType::LeaderKeyRegister(ref leader_key_register) => fmt::Display::fmt(&format!("{:?}", leader_key_register), f),
             BlockstackOperationType::LeaderBlockCommit(ref leader_block_commit) => fmt::Display::fmt(&format!("{:?}", leader_block_commit), f),
-            BlockstackOperationType::UserBurnSupport(ref user_burn_support) => fmt::Display::fmt(&format!("{:?}", user_burn_support), f)
+            BlockstackOperationType::UserBurnSupport(ref user_burn_support) => fmt::Display::fmt(&format!("{:?}", user_burn_support), f),
+            BlockstackOperationType::TransferStx(ref transfer_stx) => fmt::Display::fmt(&format!("{:?}", transfer_stx), f),
+            BlockstackOperationType::PreStx(ref pre_stx) => fmt::Display::fmt(&format!("{:?}", pre_stx), f)
         }
     }
 }
@@ -250,3 +310,7 @@ pub fn parse_u32_from_be(bytes: &[u8]) -> Option<u32> {
 pub fn parse_u16_from_be(bytes: &[u8]) -> Option<u16> {
     bytes.try_into().ok().map(u16::from_be_bytes)
 }
+
+pub fn parse_u64_from_be(bytes: &[u8]) -> Option<u64> {
+    bytes.try_into().ok().map(u64::from_be_bytes)
+}
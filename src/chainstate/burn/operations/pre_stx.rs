@@ -0,0 +1,252 @@
+/*
+ copyright: (c) 2013-2020 by Blockstack PBC, a public benefit corporation.
+
+ This file is part of Blockstack.
+
+ Blockstack is free software. You may redistribute or modify
+ it under the terms of the GNU General Public License as published by
+ the Free Software Foundation, either version 3 of the License or
+ (at your option) any later version.
+
+ Blockstack is distributed in the hope that it will be useful,
+ but WITHOUT ANY WARRANTY, including without the implied warranty of
+ MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ GNU General Public License for more details.
+
+ You should have received a copy of the GNU General Public License
+ along with Blockstack. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use chainstate::burn::operations::Error as op_error;
+use chainstate::burn::Opcodes;
+use chainstate::burn::db::burndb::BurnDBTx;
+
+use chainstate::burn::operations::{
+    PreStxOp,
+    BlockstackOperation,
+    parse_u64_from_be
+};
+
+use chainstate::stacks::StacksAddress;
+
+use burnchains::BurnchainBlockHeader;
+use burnchains::BurnchainTransaction;
+use burnchains::BurnchainHeaderHash;
+use burnchains::Burnchain;
+use burnchains::Txid;
+use burnchains::Address;
+
+use util::log;
+
+// return type for parse_data (below)
+struct ParsedData {
+    pub stacked_ustx: u64
+}
+
+impl PreStxOp {
+    #[cfg(test)]
+    pub fn new(stacker: &StacksAddress, reward_address: &StacksAddress, stacked_ustx: u64) -> PreStxOp {
+        PreStxOp {
+            stacker: stacker.clone(),
+            reward_address: reward_address.clone(),
+            stacked_ustx: stacked_ustx,
+
+            // will be filled in
+            txid: Txid([0u8; 32]),
+            vtxindex: 0,
+            block_height: 0,
+            burn_header_hash: BurnchainHeaderHash([0u8; 32]),
+        }
+    }
+
+    fn parse_data(data: &Vec<u8>) -> Option<ParsedData> {
+        /*
+            Wire format:
+
+            0      2  3                             11
+            |------|--|-----------------------------|
+             magic  op     uSTX to stack (u64)
+
+
+             Note that `data` is missing the first 3 bytes -- the magic and op have been stripped
+        */
+        if data.len() < 8 {
+            warn!("PRE_STX payload is malformed ({} bytes)", data.len());
+            return None;
+        }
+
+        let stacked_ustx = parse_u64_from_be(&data[0..8]).unwrap();
+
+        Some(ParsedData {
+            stacked_ustx
+        })
+    }
+
+    fn parse_from_tx(block_height: u64, block_hash: &BurnchainHeaderHash, tx: &BurnchainTransaction) -> Result<PreStxOp, op_error> {
+        // can't be too careful...
+        let inputs = tx.get_signers();
+        let outputs = tx.get_recipients();
+
+        if inputs.len() == 0 || outputs.len() == 0 {
+            test_debug!("Invalid tx: inputs: {}, outputs: {}", inputs.len(), outputs.len());
+            return Err(op_error::InvalidInput);
+        }
+
+        if tx.opcode() != Opcodes::PreStx as u8 {
+            test_debug!("Invalid tx: invalid opcode {}", tx.opcode());
+            return Err(op_error::InvalidInput);
+        }
+
+        // outputs[0] is the reward address
+        if outputs[0].address.is_burn() {
+            test_debug!("Invalid tx: reward address is the burn address");
+            return Err(op_error::ParseError);
+        }
+
+        let data = match PreStxOp::parse_data(&tx.data()) {
+            None => {
+                test_debug!("Invalid tx data");
+                return Err(op_error::ParseError);
+            },
+            Some(d) => d
+        };
+
+        // the stacker is whoever owns the first input, on the same network as the reward address
+        let reward_address = outputs[0].address.clone();
+        let stacker = inputs[0].to_stacks_address(reward_address.is_mainnet());
+
+        Ok(PreStxOp {
+            stacker: stacker,
+            reward_address: reward_address,
+            stacked_ustx: data.stacked_ustx,
+
+            txid: tx.txid(),
+            vtxindex: tx.vtxindex(),
+            block_height: block_height,
+            burn_header_hash: block_hash.clone(),
+        })
+    }
+}
+
+impl BlockstackOperation for PreStxOp {
+    fn from_tx(block_header: &BurnchainBlockHeader, tx: &BurnchainTransaction) -> Result<PreStxOp, op_error> {
+        PreStxOp::parse_from_tx(block_header.block_height, &block_header.block_hash, tx)
+    }
+
+    fn check<'a>(&self, _burnchain: &Burnchain, _block_header: &BurnchainBlockHeader, _tx: &mut BurnDBTx<'a>) -> Result<(), op_error> {
+        // like STX transfers, the stacker's balance is only known to the Stacks chain state
+        if self.stacked_ustx == 0 {
+            warn!("Invalid stacking pre-registration: no uSTX stacked");
+            return Err(op_error::PreStxMustBePositive);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burnchains::bitcoin::BitcoinNetworkType;
+    use burnchains::bitcoin::{BitcoinTransaction, BitcoinTxInput, BitcoinTxOutput, BitcoinInputType};
+    use burnchains::bitcoin::keys::BitcoinPublicKey;
+    use burnchains::bitcoin::address::{BitcoinAddress, BitcoinAddressType};
+
+    use chainstate::burn::db::burndb::BurnDB;
+    use chainstate::stacks::C32_ADDRESS_VERSION_TESTNET_SINGLESIG;
+    use chainstate::stacks::index::TrieHash;
+
+    use util::hash::{hex_bytes, Hash160};
+    use util::get_epoch_time_secs;
+
+    fn make_tx(opcode: u8, data: &Vec<u8>, num_inputs: usize, reward_address: &Vec<u8>) -> BurnchainTransaction {
+        let pubk = BitcoinPublicKey::from_hex("02d8015134d9db8178ac93acbc43170a2f20febba5087a5b0437058765ad5133d0").unwrap();
+        let inputs = (0..num_inputs).map(|_| BitcoinTxInput {
+            keys: vec![pubk.clone()],
+            num_required: 1,
+            in_type: BitcoinInputType::Standard
+        }).collect();
+
+        BurnchainTransaction::Bitcoin(BitcoinTransaction {
+            txid: Txid([0x33; 32]),
+            vtxindex: 4,
+            opcode: opcode,
+            data: data.clone(),
+            inputs: inputs,
+            outputs: vec![
+                BitcoinTxOutput {
+                    address: BitcoinAddress::from_bytes(BitcoinNetworkType::Testnet, BitcoinAddressType::PublicKeyHash, reward_address).unwrap(),
+                    units: 5500
+                }
+            ]
+        })
+    }
+
+    fn make_header(block_height: u64) -> BurnchainBlockHeader {
+        BurnchainBlockHeader {
+            block_height: block_height,
+            block_hash: BurnchainHeaderHash([0x22; 32]),
+            parent_block_hash: BurnchainHeaderHash([0x21; 32]),
+            num_txs: 1,
+            parent_index_root: TrieHash::from_empty_data(),
+            timestamp: get_epoch_time_secs()
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let reward_bytes = hex_bytes("0be3e286a15ea85882761618e366586b5574100d").unwrap();
+        let stacker_bytes = Hash160::from_data(&hex_bytes("02d8015134d9db8178ac93acbc43170a2f20febba5087a5b0437058765ad5133d0").unwrap());
+        let header = make_header(123);
+
+        // 1000000 uSTX; trailing bytes are ignored
+        let data = vec![0, 0, 0, 0, 0, 0x0f, 0x42, 0x40, 0xff];
+
+        let op = PreStxOp::from_tx(&header, &make_tx(Opcodes::PreStx as u8, &data, 1, &reward_bytes)).unwrap();
+        assert_eq!(op, PreStxOp {
+            stacker: StacksAddress::new(C32_ADDRESS_VERSION_TESTNET_SINGLESIG, stacker_bytes),
+            reward_address: StacksAddress::new(C32_ADDRESS_VERSION_TESTNET_SINGLESIG, Hash160::from_bytes(&reward_bytes).unwrap()),
+            stacked_ustx: 1000000,
+
+            txid: Txid([0x33; 32]),
+            vtxindex: 4,
+            block_height: 123,
+            burn_header_hash: BurnchainHeaderHash([0x22; 32])
+        });
+
+        // too short
+        assert!(PreStxOp::from_tx(&header, &make_tx(Opcodes::PreStx as u8, &data[0..7].to_vec(), 1, &reward_bytes)).is_err());
+
+        // wrong opcode
+        assert!(PreStxOp::from_tx(&header, &make_tx(Opcodes::TransferStx as u8, &data, 1, &reward_bytes)).is_err());
+
+        // no inputs
+        assert!(PreStxOp::from_tx(&header, &make_tx(Opcodes::PreStx as u8, &data, 0, &reward_bytes)).is_err());
+
+        // can't be rewarded at the burn address
+        assert!(PreStxOp::from_tx(&header, &make_tx(Opcodes::PreStx as u8, &data, 1, &vec![0u8; 20])).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let first_burn_hash = BurnchainHeaderHash([0x01; 32]);
+        let burnchain = Burnchain::default_unittest(100, &first_burn_hash);
+        let mut db = BurnDB::connect_memory(100, &first_burn_hash).unwrap();
+        let header = make_header(101);
+
+        let stacker = StacksAddress::new(C32_ADDRESS_VERSION_TESTNET_SINGLESIG, Hash160([0x01; 20]));
+        let reward_address = StacksAddress::new(C32_ADDRESS_VERSION_TESTNET_SINGLESIG, Hash160([0x02; 20]));
+
+        let mut tx = db.tx_begin().unwrap();
+
+        let op = PreStxOp::new(&stacker, &reward_address, 123);
+        assert_eq!(op.check(&burnchain, &header, &mut tx), Ok(()));
+
+        // stacking to yourself is fine
+        let op = PreStxOp::new(&stacker, &stacker, 123);
+        assert_eq!(op.check(&burnchain, &header, &mut tx), Ok(()));
+
+        let op = PreStxOp::new(&stacker, &reward_address, 0);
+        assert_eq!(op.check(&burnchain, &header, &mut tx), Err(op_error::PreStxMustBePositive));
+    }
+}
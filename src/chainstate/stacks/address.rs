@@ -32,6 +32,10 @@ use address::public_keys_to_address_hash;
 use chainstate::stacks::StacksPublicKey;
 use chainstate::stacks::StacksAddress;
 use chainstate::stacks::STACKS_ADDRESS_ENCODED_SIZE;
+use chainstate::stacks::{
+    C32_ADDRESS_VERSION_MAINNET_SINGLESIG,
+    C32_ADDRESS_VERSION_MAINNET_MULTISIG
+};
 
 use util::hash::Hash160;
 use util::hash::HASH160_ENCODED_SIZE;
@@ -125,6 +129,11 @@ impl StacksAddress {
         }
     }
 
+    /// Is this a mainnet address?
+    pub fn is_mainnet(&self) -> bool {
+        self.version == C32_ADDRESS_VERSION_MAINNET_SINGLESIG || self.version == C32_ADDRESS_VERSION_MAINNET_MULTISIG
+    }
+
     /// Convert to PrincipalData::Standard(StandardPrincipalData)
     pub fn to_account_principal(&self) -> PrincipalData {
         PrincipalData::Standard(StandardPrincipalData(self.version, self.bytes.as_bytes().clone()))
@@ -140,6 +140,14 @@ impl StacksMessageCodec for MultisigSpendingCondition {
     }
 
     fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<MultisigSpendingCondition, net_error> {
+        MultisigSpendingCondition::deserialize_inner(fd, false)
+    }
+}
+
+impl MultisigSpendingCondition {
+    /// Decode a multisig spending condition.  If partially_signed is true, then the condition may
+    /// have fewer signatures than it requires (but never more).
+    fn deserialize_inner<R: Read>(fd: &mut R, partially_signed: bool) -> Result<MultisigSpendingCondition, net_error> {
         let hash_mode_u8 : u8 = read_next(fd)?;
         let hash_mode = MultisigHashMode::from_u8(hash_mode_u8)
             .ok_or(net_error::DeserializeError(format!("Failed to parse multisig spending condition: unknown hash mode {}", hash_mode_u8)))?;
@@ -174,7 +182,7 @@ impl StacksMessageCodec for MultisigSpendingCondition {
         }
 
         // must be given the right number of signatures
-        if num_sigs_given != signatures_required {
+        if num_sigs_given > signatures_required || (num_sigs_given < signatures_required && !partially_signed) {
             test_debug!("Failed to deserialize multisig spending condition: got {} sigs, expected {}", num_sigs_given, signatures_required);
             return Err(net_error::DeserializeError(format!("Failed to parse multisig spending condition: got {} sigs, expected {}", num_sigs_given, signatures_required)));
         }
@@ -194,9 +202,7 @@ impl StacksMessageCodec for MultisigSpendingCondition {
             signatures_required
         })
     }
-}
 
-impl MultisigSpendingCondition {
     pub fn push_signature(&mut self, key_encoding: TransactionPublicKeyEncoding, signature: MessageSignature) -> () {
         self.fields.push(TransactionAuthField::Signature(key_encoding, signature));
     }
@@ -209,6 +215,19 @@ impl MultisigSpendingCondition {
         self.fields.pop()
     }
 
+    /// Get the sighash that the next signer must sign, given the signatures present so far.
+    /// Unlike verify(), this does not require the condition to be completely signed.
+    pub fn next_sighash(&self, initial_sighash: &Txid, cond_code: &TransactionAuthFlags) -> Result<Txid, net_error> {
+        let mut cur_sighash = initial_sighash.clone();
+        for field in self.fields.iter() {
+            if let TransactionAuthField::Signature(ref pubkey_encoding, ref sigbuf) = field {
+                let (_, next_sighash) = TransactionSpendingCondition::next_verification(&cur_sighash, cond_code, self.fee_rate, self.nonce, pubkey_encoding, sigbuf)?;
+                cur_sighash = next_sighash;
+            }
+        }
+        Ok(cur_sighash)
+    }
+
     pub fn address_mainnet(&self) -> StacksAddress {
         StacksAddress {
             version: C32_ADDRESS_VERSION_MAINNET_MULTISIG,
@@ -396,6 +415,14 @@ impl StacksMessageCodec for TransactionSpendingCondition {
     }
 
     fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<TransactionSpendingCondition, net_error> {
+        TransactionSpendingCondition::deserialize_inner(fd, false)
+    }
+}
+
+impl TransactionSpendingCondition {
+    /// Decode a spending condition.  If partially_signed is true, then a multisig condition may
+    /// have fewer signatures than it requires.
+    fn deserialize_inner<R: Read>(fd: &mut R, partially_signed: bool) -> Result<TransactionSpendingCondition, net_error> {
         // peek the hash mode byte
         let hash_mode_u8 : u8 = read_next(fd)?;
         let peek_buf = [hash_mode_u8];
@@ -406,7 +433,7 @@ impl StacksMessageCodec for TransactionSpendingCondition {
                 TransactionSpendingCondition::Singlesig(cond)
             }
             else if MultisigHashMode::from_u8(hash_mode_u8).is_some() {
-                let cond = MultisigSpendingCondition::consensus_deserialize(&mut rrd)?;
+                let cond = MultisigSpendingCondition::deserialize_inner(&mut rrd, partially_signed)?;
                 TransactionSpendingCondition::Multisig(cond)
             }
             else {
@@ -417,9 +444,7 @@ impl StacksMessageCodec for TransactionSpendingCondition {
 
         Ok(cond)
     }
-}
 
-impl TransactionSpendingCondition {
     pub fn new_singlesig_p2pkh(pubkey: StacksPublicKey) -> Option<TransactionSpendingCondition> {
         let key_encoding = if pubkey.compressed() { TransactionPublicKeyEncoding::Compressed } else { TransactionPublicKeyEncoding::Uncompressed };
         let signer_addr = StacksAddress::from_public_keys(0, &AddressHashMode::SerializeP2PKH, 1, &vec![pubkey])?;
@@ -707,15 +732,23 @@ impl StacksMessageCodec for TransactionAuth {
     }
 
     fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<TransactionAuth, net_error> {
+        TransactionAuth::deserialize_inner(fd, false)
+    }
+}
+
+impl TransactionAuth {
+    /// Decode a transaction authorization.  If partially_signed is true, then its multisig
+    /// spending conditions may have fewer signatures than they require.
+    pub fn deserialize_inner<R: Read>(fd: &mut R, partially_signed: bool) -> Result<TransactionAuth, net_error> {
         let type_id : u8 = read_next(fd)?;
         let auth = match type_id {
             x if x == TransactionAuthFlags::AuthStandard as u8 => {
-                let origin_auth : TransactionSpendingCondition = read_next(fd)?;
+                let origin_auth = TransactionSpendingCondition::deserialize_inner(fd, partially_signed)?;
                 TransactionAuth::Standard(origin_auth)
             },
             x if x == TransactionAuthFlags::AuthSponsored as u8 => {
-                let origin_auth : TransactionSpendingCondition = read_next(fd)?;
-                let sponsor_auth : TransactionSpendingCondition = read_next(fd)?;
+                let origin_auth = TransactionSpendingCondition::deserialize_inner(fd, partially_signed)?;
+                let sponsor_auth = TransactionSpendingCondition::deserialize_inner(fd, partially_signed)?;
                 TransactionAuth::Sponsored(origin_auth, sponsor_auth)
             },
             _ => {
@@ -725,9 +758,7 @@ impl StacksMessageCodec for TransactionAuth {
         };
         Ok(auth)
     }
-}
 
-impl TransactionAuth {
     pub fn from_p2pkh(privk: &StacksPrivateKey) -> Option<TransactionAuth> {
         match TransactionSpendingCondition::new_singlesig_p2pkh(StacksPublicKey::from_private(privk)) {
             Some(auth) => Some(TransactionAuth::Standard(auth)),
@@ -42,7 +42,8 @@ pub struct MinerReward {
     pub tx_fees_anchored_exclusive: u128,
     pub tx_fees_streamed_produced: u128,
     pub tx_fees_streamed_confirmed: u128,
-    pub vtxindex: u32       // will be 0 for the reward to the miner, and >0 for user burn supports
+    pub vtxindex: u32,      // will be 0 for the reward to the miner, and >0 for user burn supports
+    pub microblock_pubkey_hash: Option<Hash160>     // the matured block's microblock key, if this is the reward to its miner.  The reward is withheld if that microblock stream was poisoned.
 }
 
 impl FromRow<MinerPaymentSchedule> for MinerPaymentSchedule {
@@ -107,7 +108,8 @@ impl MinerReward {
             tx_fees_anchored_exclusive: 0,
             tx_fees_streamed_produced: 0,
             tx_fees_streamed_confirmed: 0,
-            vtxindex: 0
+            vtxindex: 0,
+            microblock_pubkey_hash: None
         }
     }
     
@@ -119,7 +121,8 @@ impl MinerReward {
             tx_fees_anchored_exclusive: 0,
             tx_fees_streamed_produced: 0,
             tx_fees_streamed_confirmed: 0,
-            vtxindex: vtxindex
+            vtxindex: vtxindex,
+            microblock_pubkey_hash: None
         }
     }
 
@@ -356,7 +359,8 @@ impl StacksChainState {
             tx_fees_anchored_exclusive: 0,
             tx_fees_streamed_produced: 0,
             tx_fees_streamed_confirmed: 0,
-            vtxindex: miner.vtxindex
+            vtxindex: miner.vtxindex,
+            microblock_pubkey_hash: None
         };
         miner_reward
     }
@@ -407,7 +411,11 @@ impl StacksChainState {
 
         let mut rewards = vec![];
         for matured_miner in matured_miners {
-            let reward = StacksChainState::calculate_miner_reward(&matured_miner, &scheduled_payments);
+            let mut reward = StacksChainState::calculate_miner_reward(&matured_miner, &scheduled_payments);
+            if matured_miner.miner {
+                let header_info_opt = StacksChainState::get_anchored_block_header_info(tx, &matured_miner.burn_header_hash, &matured_miner.block_hash)?;
+                reward.microblock_pubkey_hash = header_info_opt.map(|header_info| header_info.anchored_header.microblock_pubkey_hash);
+            }
             rewards.push(reward);
         }
         Ok(Some(rewards))
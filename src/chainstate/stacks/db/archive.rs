@@ -0,0 +1,209 @@
+/*
+ copyright: (c) 2013-2019 by Blockstack PBC, a public benefit corporation.
+
+ This file is part of Blockstack.
+
+ Blockstack is free software. You may redistribute or modify
+ it under the terms of the GNU General Public License as published by
+ the Free Software Foundation, either version 3 of the License or
+ (at your option) any later version.
+
+ Blockstack is distributed in the hope that it will be useful,
+ but WITHOUT ANY WARRANTY, including without the implied warranty of
+ MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ GNU General Public License for more details.
+
+ You should have received a copy of the GNU General Public License
+ along with Blockstack. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/// Read-only queries against the Clarity state as of any processed Stacks block.
+///
+/// The Clarity MARF keeps a trie for every processed block, so the state as of a past block can
+/// be read back by pointing a read-only connection at that block's index block hash (just like
+/// `at-block` does inside the VM).  A node in archive mode never prunes, so it can answer these
+/// queries for every block it ever processed.  Other nodes can answer them for any block that
+/// has not yet been pruned.
+///
+/// The MARF reads a missing trie as an empty one, so every query first checks that the block
+/// was processed and not pruned.  Otherwise, a query against a pruned block would silently
+/// return default values.
+
+use rusqlite::types::ToSql;
+
+use chainstate::stacks::Error;
+use chainstate::stacks::*;
+use chainstate::stacks::db::*;
+
+use util::db::query_count;
+
+use vm::clarity::ClarityConnection;
+use vm::types::{PrincipalData, QualifiedContractIdentifier, Value};
+
+impl StacksChainState {
+    /// Turn archive mode on or off.  In archive mode, the state of every processed block is kept,
+    /// and pruning is refused.
+    pub fn set_archive_mode(&mut self, archive_mode: bool) -> () {
+        self.archive_mode = archive_mode;
+    }
+
+    pub fn is_archive_mode(&self) -> bool {
+        self.archive_mode
+    }
+
+    /// Was this block processed, and then pruned?
+    pub fn is_pruned_block(conn: &DBConn, index_block_hash: &BlockHeaderHash) -> Result<bool, Error> {
+        let args : &[&dyn ToSql] = &[index_block_hash];
+        let count = query_count(conn, &"SELECT COUNT(*) FROM pruned_blocks WHERE index_block_hash = ?1".to_string(), args)
+            .map_err(Error::DBError)?;
+        Ok(count > 0)
+    }
+
+    /// Make sure that the state as of the given block can be queried
+    pub fn check_historical_block(&self, index_block_hash: &BlockHeaderHash) -> Result<(), Error> {
+        if StacksChainState::is_pruned_block(&self.headers_db, index_block_hash)? {
+            warn!("Refusing to query pruned block {}", index_block_hash);
+            return Err(Error::PrunedBlockError);
+        }
+
+        match StacksChainState::get_stacks_block_header_info_by_index_block_hash(&self.headers_db, index_block_hash)? {
+            Some(_) => Ok(()),
+            None => Err(Error::NoSuchBlockError)
+        }
+    }
+
+    /// Run a read-only Clarity connection against the state as of the given processed block
+    pub fn with_historical_clarity_tx<F, R>(&mut self, index_block_hash: &BlockHeaderHash, to_do: F) -> Result<R, Error>
+    where F: FnOnce(&mut ClarityReadOnlyConnection) -> R {
+        self.check_historical_block(index_block_hash)?;
+
+        let mut conn = self.clarity_state.read_only_connection(index_block_hash, &self.headers_db);
+        let result = to_do(&mut conn);
+        conn.done();
+        Ok(result)
+    }
+
+    /// Get an account's STX balance and nonce as of the given block
+    pub fn get_account_at(&mut self, index_block_hash: &BlockHeaderHash, principal: &PrincipalData) -> Result<StacksAccount, Error> {
+        self.with_historical_clarity_tx(index_block_hash, |clarity_tx| StacksChainState::get_account(clarity_tx, principal))
+    }
+
+    /// Get the value of a contract's data var as of the given block
+    pub fn get_data_var_at(&mut self, index_block_hash: &BlockHeaderHash, contract_id: &QualifiedContractIdentifier, var_name: &str) -> Result<Value, Error> {
+        self.with_historical_clarity_tx(index_block_hash, |clarity_tx| {
+            clarity_tx.with_clarity_db_readonly(|ref mut db| db.lookup_variable(contract_id, var_name))
+        })?
+        .map_err(|e| Error::ClarityError(clarity_error::Interpreter(e)))
+    }
+
+    /// Get a contract's map entry as of the given block.  Returns an optional, like `map-get?`.
+    pub fn get_map_entry_at(&mut self, index_block_hash: &BlockHeaderHash, contract_id: &QualifiedContractIdentifier, map_name: &str, key: &Value) -> Result<Value, Error> {
+        self.with_historical_clarity_tx(index_block_hash, |clarity_tx| {
+            clarity_tx.with_clarity_db_readonly(|ref mut db| db.fetch_entry(contract_id, map_name, key))
+        })?
+        .map_err(|e| Error::ClarityError(clarity_error::Interpreter(e)))
+    }
+
+    /// Evaluate read-only Clarity code in the context of a contract, as of the given block
+    pub fn eval_read_only_at(&mut self, index_block_hash: &BlockHeaderHash, contract_id: &QualifiedContractIdentifier, code: &str) -> Result<Value, Error> {
+        self.with_historical_clarity_tx(index_block_hash, |clarity_tx| clarity_tx.eval_read_only(contract_id, code))?
+            .map_err(Error::ClarityError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chainstate::stacks::db::snapshot::test::*;
+
+    use rusqlite::NO_PARAMS;
+
+    use vm::types::{StandardPrincipalData, TupleData};
+    use vm::representations::ClarityName;
+
+    #[test]
+    fn historical_queries() {
+        let mut chainstate = ::chainstate::stacks::db::test::instantiate_chainstate(false, 0x80000000, "historical-queries");
+
+        // heights are 2..=5, and the block at height h credits 100 + h - 2 to its own recipient
+        let mut headers = vec![append_block_at(&mut chainstate, None, &BurnchainHeaderHash([2u8; 32]), 100)];
+        for i in 1..4 {
+            let parent = headers.last().unwrap().clone();
+            let burn_header_hash = BurnchainHeaderHash([(parent.block_height + 1) as u8; 32]);
+            headers.push(append_block_at(&mut chainstate, Some(&parent), &burn_header_hash, 100 + i));
+        }
+        let tip = headers.last().unwrap().clone();
+        assert_eq!(tip.block_height, 5);
+        let at_height = |h: u64| headers.iter().find(|c| c.block_height == h).unwrap().index_block_hash();
+
+        let recipient = PrincipalData::Standard(StandardPrincipalData(0x16, [102u8; 20]));
+        assert_eq!(chainstate.get_account_at(&at_height(3), &recipient).unwrap().stx_balance, 0);
+        assert_eq!(chainstate.get_account_at(&at_height(4), &recipient).unwrap().stx_balance, 102);
+        assert_eq!(chainstate.get_account_at(&at_height(5), &recipient).unwrap().stx_balance, 102);
+
+        let boot_contract = QualifiedContractIdentifier::parse(&format!("{}.{}", STACKS_BOOT_CODE_CONTRACT_ADDRESS, BOOT_CODE_MINER_CONTRACT_NAME)).unwrap();
+        let height_at_3 = chainstate.eval_read_only_at(&at_height(3), &boot_contract, "block-height").unwrap();
+        let height_at_5 = chainstate.eval_read_only_at(&at_height(5), &boot_contract, "block-height").unwrap();
+        match (height_at_3, height_at_5) {
+            (Value::UInt(h3), Value::UInt(h5)) => assert_eq!(h3 + 2, h5),
+            (h3, h5) => panic!("Expected uints, got {:?} and {:?}", h3, h5)
+        }
+
+        let key = Value::Tuple(TupleData::from_data(vec![(ClarityName::try_from(BOOT_CODE_MINER_REWARDS_PARTICIPANT.to_string()).unwrap(), Value::Principal(recipient.clone()))]).unwrap());
+        assert_eq!(chainstate.get_map_entry_at(&at_height(4), &boot_contract, BOOT_CODE_MINER_REWARDS_MAP, &key).unwrap(), Value::none());
+
+        // no such data var
+        assert!(chainstate.get_data_var_at(&at_height(4), &boot_contract, "no-such-var").is_err());
+
+        // no such block
+        match chainstate.get_account_at(&BlockHeaderHash([0xff; 32]), &recipient) {
+            Err(Error::NoSuchBlockError) => {},
+            x => panic!("Expected NoSuchBlockError, got {:?}", x)
+        }
+
+        // a dead fork off of height 2
+        let fork_parent = StacksChainState::get_stacks_block_header_info_by_index_block_hash(&chainstate.headers_db, &at_height(2)).unwrap().unwrap();
+        let dead = append_block_at(&mut chainstate, Some(&fork_parent), &BurnchainHeaderHash([0xa1; 32]), 102);
+        assert_eq!(chainstate.get_account_at(&dead.index_block_hash(), &recipient).unwrap().stx_balance, 102);
+
+        // archive mode keeps it
+        chainstate.set_archive_mode(true);
+        match chainstate.prune_dead_forks(&tip.burn_header_hash, &tip.anchored_header.block_hash(), 2) {
+            Err(Error::ArchiveModeError) => {},
+            x => panic!("Expected ArchiveModeError, got {:?}", x)
+        }
+        assert_eq!(chainstate.get_account_at(&dead.index_block_hash(), &recipient).unwrap().stx_balance, 102);
+
+        // once pruned, it can't be queried
+        chainstate.set_archive_mode(false);
+        let stats = chainstate.prune_dead_forks(&tip.burn_header_hash, &tip.anchored_header.block_hash(), 2).unwrap();
+        assert_eq!(stats.tries, 1);
+
+        assert!(StacksChainState::is_pruned_block(&chainstate.headers_db, &dead.index_block_hash()).unwrap());
+        match chainstate.get_account_at(&dead.index_block_hash(), &recipient) {
+            Err(Error::PrunedBlockError) => {},
+            x => panic!("Expected PrunedBlockError, got {:?}", x)
+        }
+
+        // the canonical fork is unaffected
+        assert_eq!(chainstate.get_account_at(&at_height(4), &recipient).unwrap().stx_balance, 102);
+    }
+
+    #[test]
+    fn historical_queries_on_migrated_chainstate() {
+        let (chainstate_path, _burndb_path, tip) = make_snapshot_source("historical-queries-migrated", 2);
+
+        // make it look like a chain state from before pruned blocks were tracked
+        {
+            let chainstate = StacksChainState::open(false, 0x80000000, &chainstate_path).unwrap();
+            chainstate.headers_db.execute("DROP TABLE pruned_blocks", NO_PARAMS).unwrap();
+        }
+
+        let mut chainstate = StacksChainState::open(false, 0x80000000, &chainstate_path).unwrap();
+        assert!(!StacksChainState::is_pruned_block(&chainstate.headers_db, &tip.index_block_hash()).unwrap());
+
+        let recipient = PrincipalData::Standard(StandardPrincipalData(0x16, [101u8; 20]));
+        assert_eq!(chainstate.get_account_at(&tip.index_block_hash(), &recipient).unwrap().stx_balance, 101);
+    }
+}
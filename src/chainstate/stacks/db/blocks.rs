@@ -31,6 +31,7 @@ use rusqlite::DatabaseName;
 use core::*;
 
 use chainstate::burn::operations::*;
+use chainstate::burn::Opcodes;
 
 use chainstate::stacks::Error;
 use chainstate::stacks::db::StacksChainState;
@@ -40,6 +41,8 @@ use chainstate::stacks::db::*;
 
 use std::path::{Path, PathBuf};
 
+use burnchains::Txid;
+
 use util::db::Error as db_error;
 use util::db::{
     DBConn,
@@ -53,7 +56,7 @@ use util::db::{
 
 use util::strings::StacksString;
 use util::get_epoch_time_secs;
-use util::hash::to_hex;
+use util::hash::{to_hex, hex_bytes};
 
 use util::retry::BoundReader;
 
@@ -92,6 +95,8 @@ use vm::database::ClarityDatabase;
 
 use vm::contracts::Contract;
 
+use vm::costs::ExecutionCost;
+
 use rand::RngCore;
 use rand::thread_rng;
 
@@ -138,6 +143,13 @@ pub struct StagingUserBurnSupport {
     pub vtxindex: u32,
 }
 
+/// An STX operation sent from the burn chain, which an anchored block applies to the chain state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BurnchainStxOp {
+    TransferStx(TransferStxOp),
+    PreStx(PreStxOp)
+}
+
 #[derive(Debug)]
 pub enum MemPoolRejection {
     DeserializationFailure(net_error),
@@ -151,6 +163,7 @@ pub enum MemPoolRejection {
     ContractAlreadyExists(QualifiedContractIdentifier),
     PoisonMicroblocksDoNotConflict,
     NoAnchorBlockWithPubkeyHash(Hash160),
+    PoisonMicroblockAlreadyReported(Hash160),
     InvalidMicroblocks,
     BadAddressVersionByte,
     NoCoinbaseViaMempool
@@ -277,6 +290,84 @@ impl FromRow<StagingUserBurnSupport> for StagingUserBurnSupport {
     }
 }
 
+impl FromRow<BurnchainStxOp> for BurnchainStxOp {
+    fn from_row<'a>(row: &'a Row) -> Result<BurnchainStxOp, db_error> {
+        let txid = Txid::from_column(row, "txid")?;
+        let op_burn_header_hash = BurnchainHeaderHash::from_column(row, "op_burn_header_hash")?;
+        let op_block_height_i64 : i64 = row.get("op_block_height");
+        let vtxindex : u32 = row.get("vtxindex");
+        let opcode : u8 = row.get("opcode");
+        let sender = StacksAddress::from_column(row, "sender")?;
+        let recipient = StacksAddress::from_column(row, "recipient")?;
+        let amount_ustx_str : String = row.get("amount_ustx");
+        let memo_hex : String = row.get("memo");
+
+        if op_block_height_i64 < 0 {
+            return Err(db_error::ParseError);
+        }
+
+        let amount_ustx = amount_ustx_str.parse::<u64>()
+            .map_err(|_e| db_error::ParseError)?;
+
+        let memo = hex_bytes(&memo_hex)
+            .map_err(|_e| db_error::ParseError)?;
+
+        match opcode {
+            x if x == Opcodes::TransferStx as u8 => {
+                Ok(BurnchainStxOp::TransferStx(TransferStxOp {
+                    sender: sender,
+                    recipient: recipient,
+                    transfered_ustx: amount_ustx,
+                    memo: memo,
+
+                    txid: txid,
+                    vtxindex: vtxindex,
+                    block_height: op_block_height_i64 as u64,
+                    burn_header_hash: op_burn_header_hash
+                }))
+            },
+            x if x == Opcodes::PreStx as u8 => {
+                Ok(BurnchainStxOp::PreStx(PreStxOp {
+                    stacker: sender,
+                    reward_address: recipient,
+                    stacked_ustx: amount_ustx,
+
+                    txid: txid,
+                    vtxindex: vtxindex,
+                    block_height: op_block_height_i64 as u64,
+                    burn_header_hash: op_burn_header_hash
+                }))
+            },
+            _ => {
+                Err(db_error::ParseError)
+            }
+        }
+    }
+}
+
+impl BurnchainStxOp {
+    pub fn txid(&self) -> Txid {
+        match *self {
+            BurnchainStxOp::TransferStx(ref op) => op.txid.clone(),
+            BurnchainStxOp::PreStx(ref op) => op.txid.clone()
+        }
+    }
+
+    pub fn vtxindex(&self) -> u32 {
+        match *self {
+            BurnchainStxOp::TransferStx(ref op) => op.vtxindex,
+            BurnchainStxOp::PreStx(ref op) => op.vtxindex
+        }
+    }
+
+    pub fn block_height(&self) -> u64 {
+        match *self {
+            BurnchainStxOp::TransferStx(ref op) => op.block_height,
+            BurnchainStxOp::PreStx(ref op) => op.block_height
+        }
+    }
+}
+
 impl StagingMicroblock {
     #[cfg(test)]
     pub fn try_into_microblock(self) -> Result<StacksMicroblock, StagingMicroblock> {
@@ -387,6 +478,28 @@ const STACKS_BLOCK_INDEX_SQL : &'static [&'static str]= &[
     "#,
 ];
 
+/// Tables added after the block database schema was first released.  These are applied to new
+/// databases and to existing ones whenever they are opened, so each must be idempotent.
+const STACKS_BLOCK_INDEX_MIGRATIONS : &'static [&'static str] = &[
+    r#"
+    -- STX operations sent from the burn chain that a block will apply.
+    -- sender and recipient are the stacker and reward address for stacking pre-registrations.
+    CREATE TABLE IF NOT EXISTS staging_burnchain_stx_ops(anchored_block_hash TEXT NOT NULL,
+                                                         burn_header_hash TEXT NOT NULL,
+                                                         txid TEXT NOT NULL,
+                                                         op_burn_header_hash TEXT NOT NULL,    -- burnchain block that contains the operation
+                                                         op_block_height INT NOT NULL,
+                                                         vtxindex INT NOT NULL,
+                                                         opcode INT NOT NULL,
+                                                         sender TEXT NOT NULL,
+                                                         recipient TEXT NOT NULL,
+                                                         amount_ustx TEXT NOT NULL,
+                                                         memo TEXT NOT NULL,
+                                                         PRIMARY KEY(anchored_block_hash,burn_header_hash,txid,op_burn_header_hash)
+    );
+    "#,
+];
+
 
 impl StacksChainState {
     fn instantiate_blocks_db(conn: &mut DBConn) -> Result<(), Error> {
@@ -396,6 +509,22 @@ impl StacksChainState {
             tx.execute(cmd, NO_PARAMS).map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
         }
 
+        for cmd in STACKS_BLOCK_INDEX_MIGRATIONS {
+            tx.execute(cmd, NO_PARAMS).map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+
+        tx.commit().map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        Ok(())
+    }
+
+    /// Bring an existing block database up to the current schema
+    fn migrate_blocks_db(conn: &mut DBConn) -> Result<(), Error> {
+        let tx = conn.transaction().map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+        for cmd in STACKS_BLOCK_INDEX_MIGRATIONS {
+            tx.execute(cmd, NO_PARAMS).map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+
         tx.commit().map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
         Ok(())
     }
@@ -419,6 +548,9 @@ impl StacksChainState {
             // instantiate!
             StacksChainState::instantiate_blocks_db(&mut conn)?;
         }
+        else {
+            StacksChainState::migrate_blocks_db(&mut conn)?;
+        }
         
         Ok(conn)
     }
@@ -892,6 +1024,15 @@ impl StacksChainState {
         Ok(rows)
     }
     
+    /// Load up the burnchain STX operations an unprocessed block will apply, in the order they
+    /// were sent on the burn chain.
+    fn load_staging_block_burnchain_stx_ops(block_conn: &DBConn, burn_header_hash: &BurnchainHeaderHash, block_hash: &BlockHeaderHash) -> Result<Vec<BurnchainStxOp>, Error> {
+        let sql = "SELECT * FROM staging_burnchain_stx_ops WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2 ORDER BY op_block_height ASC, vtxindex ASC".to_string();
+        let args: &[&dyn ToSql] = &[&block_hash, &burn_header_hash];
+        let rows = query_rows::<BurnchainStxOp, _>(block_conn, &sql, args).map_err(Error::DBError)?;
+        Ok(rows)
+    }
+
     /// Load up a queued block's queued pubkey hash
     fn load_staging_block_pubkey_hash(block_conn: &DBConn, burn_header_hash: &BurnchainHeaderHash, block_hash: &BlockHeaderHash) -> Result<Option<Hash160>, Error> {
         let sql = format!("SELECT microblock_pubkey_hash FROM staging_blocks WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2 AND processed = 0 AND orphaned = 0");
@@ -1142,6 +1283,30 @@ impl StacksChainState {
         Ok(())
     }
 
+    /// Store the burnchain STX operations a block will apply
+    fn store_staging_block_burnchain_stx_ops<'a>(tx: &mut BlocksDBTx<'a>, burn_hash: &BurnchainHeaderHash, block_hash: &BlockHeaderHash, stx_ops: &Vec<BurnchainStxOp>) -> Result<(), Error> {
+        for stx_op in stx_ops.iter() {
+            assert!(stx_op.block_height() < i64::max_value() as u64);
+        }
+
+        for stx_op in stx_ops.iter() {
+            let (opcode, sender, recipient, amount_ustx, memo, op_burn_header_hash) = match *stx_op {
+                BurnchainStxOp::TransferStx(ref op) => (Opcodes::TransferStx, &op.sender, &op.recipient, op.transfered_ustx, to_hex(&op.memo[..]), &op.burn_header_hash),
+                BurnchainStxOp::PreStx(ref op) => (Opcodes::PreStx, &op.stacker, &op.reward_address, op.stacked_ustx, "".to_string(), &op.burn_header_hash)
+            };
+
+            let sql = "INSERT OR REPLACE INTO staging_burnchain_stx_ops (anchored_block_hash, burn_header_hash, txid, op_burn_header_hash, op_block_height, vtxindex, opcode, sender, recipient, amount_ustx, memo) \
+                       VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)";
+            let args: &[&dyn ToSql] = &[&block_hash, &burn_hash, &stx_op.txid(), op_burn_header_hash, &(stx_op.block_height() as i64), &stx_op.vtxindex(),
+                                       &(opcode as u8), &sender.to_string(), &recipient.to_string(), &format!("{}", amount_ustx), &memo];
+
+            tx.execute(&sql, args)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+
+        Ok(())
+    }
+
     /// Read all the i64 values from a query (possibly none).
     fn read_i64s(conn: &DBConn, query: &str, args: &[&dyn ToSql]) -> Result<Vec<i64>, Error> {
         let mut stmt = conn.prepare(query).map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
@@ -2015,12 +2180,25 @@ impl StacksChainState {
             }
         };
      
+        // find all burnchain STX operations this block will apply.  Its miner could only have
+        // seen the ones sent before this block's sortition.
+        let burnchain_stx_ops = match BurnDB::get_block_snapshot(burn_tx, burn_header_hash).map_err(Error::DBError)? {
+            Some(sn) => StacksChainState::get_burnchain_stx_ops(burn_tx, parent_burn_header_hash, &sn.parent_burn_header_hash)?,
+            None => {
+                // validate_anchored_block_burnchain() already checked this
+                return Err(Error::DBError(db_error::NotFoundError));
+            }
+        };
+
         // queue block up for processing
         StacksChainState::store_staging_block(&mut block_tx, burn_header_hash, burn_header_timestamp, &block, parent_burn_header_hash, commit_burn, sortition_burn)?;
 
         // store users who burned for this block so they'll get rewarded if we process it
         StacksChainState::store_staging_block_user_burn_supports(&mut block_tx, burn_header_hash, &block.block_hash(), &user_burns)?;
 
+        // store burnchain STX operations so they'll get applied if we process it
+        StacksChainState::store_staging_block_burnchain_stx_ops(&mut block_tx, burn_header_hash, &block.block_hash(), &burnchain_stx_ops)?;
+
         block_tx.commit().map_err(Error::DBError)?;
 
         // ready to go
@@ -2404,7 +2582,9 @@ impl StacksChainState {
         Ok(())
     }
 
-    /// Process matured miner rewards for this block
+    /// Process matured miner rewards for this block.
+    /// If the block's miner was caught equivocating in its microblock stream, its reward is
+    /// withheld, and whoever reported it gets a bounty out of it instead.
     pub fn process_matured_miner_rewards<'a>(clarity_tx: &mut ClarityTx<'a>, miner_rewards: &Vec<MinerReward>) -> Result<(), Error> {
         // must all be in order by vtxindex, and the first reward (the miner's) must have vtxindex 0
         assert!(miner_rewards.len() > 0);
@@ -2415,11 +2595,185 @@ impl StacksChainState {
 
         // store each reward into the miner trust fund contract in the chain boot code
         for reward in miner_rewards.iter() {
+            if let Some(ref microblock_pubkey_hash) = reward.microblock_pubkey_hash {
+                if let Some((reporter, sequence)) = StacksChainState::get_poison_microblock_report(clarity_tx, microblock_pubkey_hash)? {
+                    let bounty = reward.total().checked_mul(POISON_MICROBLOCK_BOUNTY_PCT).expect("FATAL: STX reward overflow") / 100;
+                    warn!("Miner {} equivocated at microblock sequence {}: withhold its reward of {} STX, and grant {} {} STX", 
+                          reward.address.to_string(), sequence, reward.total(), reporter.to_string(), bounty);
+
+                    let mut bounty_reward = MinerReward::empty_user(&reporter, reward.vtxindex);
+                    bounty_reward.coinbase = bounty;
+                    StacksChainState::process_matured_miner_reward(clarity_tx, &bounty_reward)?;
+                    continue;
+                }
+            }
             StacksChainState::process_matured_miner_reward(clarity_tx, reward)?;
         }
         Ok(())
     }
 
+    fn make_poison_microblock_key(microblock_pubkey_hash: &Hash160) -> Value {
+        let pubkey_hash_name = ClarityName::try_from(BOOT_CODE_MINER_POISON_PUBKEY_HASH.to_string()).unwrap();
+        Value::Tuple(TupleData::from_data(vec![
+                (pubkey_hash_name, Value::buff_from(microblock_pubkey_hash.as_bytes().to_vec()).expect("FATAL: failed to construct microblock public key hash"))])
+            .expect("FATAL: failed to construct poisoned microblock key"))
+    }
+
+    /// Find out whether or not a PoisonMicroblock transaction reported the leader with the given
+    /// microblock public key hash.  Returns who reported it, and the sequence number at which the
+    /// leader equivocated.
+    pub fn get_poison_microblock_report<T: ClarityConnection>(clarity_tx: &mut T, microblock_pubkey_hash: &Hash160) -> Result<Option<(StacksAddress, u16)>, Error> {
+        let boot_code_address = StacksAddress::from_string(&STACKS_BOOT_CODE_CONTRACT_ADDRESS.to_string()).unwrap();
+        let miner_contract_id = QualifiedContractIdentifier::new(StandardPrincipalData::from(boot_code_address.clone()), ContractName::try_from(BOOT_CODE_MINER_CONTRACT_NAME.to_string()).unwrap());
+
+        let reporter_name = ClarityName::try_from(BOOT_CODE_MINER_POISON_REPORTER.to_string()).unwrap();
+        let sequence_name = ClarityName::try_from(BOOT_CODE_MINER_POISON_SEQUENCE.to_string()).unwrap();
+        let poison_key = StacksChainState::make_poison_microblock_key(microblock_pubkey_hash);
+
+        let report_opt = clarity_tx.with_clarity_db_readonly(|ref mut db| db.fetch_entry(&miner_contract_id, BOOT_CODE_MINER_POISON_MAP, &poison_key))
+            .map_err(|e| Error::ClarityError(clarity_error::Interpreter(e)))?;
+
+        match report_opt {
+            Value::Optional(ref optional_data) => {
+                match optional_data.data {
+                    None => Ok(None),
+                    Some(ref report) => {
+                        match **report {
+                            Value::Tuple(ref tuple) => {
+                                let reporter = match tuple.get(&reporter_name).expect("FATAL: no reporter in poisoned microblock tuple") {
+                                    Value::Principal(PrincipalData::Standard(StandardPrincipalData(version, bytes))) => StacksAddress { version: *version, bytes: Hash160(bytes.clone()) },
+                                    x => panic!("FATAL: poisoned microblock reporter is not a standard principal: {:?}", x)
+                                };
+                                let sequence = match tuple.get(&sequence_name).expect("FATAL: no sequence in poisoned microblock tuple") {
+                                    Value::UInt(sequence) => *sequence as u16,
+                                    x => panic!("FATAL: poisoned microblock sequence is not a uint: {:?}", x)
+                                };
+                                Ok(Some((reporter, sequence)))
+                            },
+                            ref x => {
+                                panic!("FATAL: poisoned microblock report is not a tuple: {:?}", &x);
+                            }
+                        }
+                    }
+                }
+            },
+            ref x => {
+                panic!("FATAL: fetched poisoned microblock report is not an optional: {:?}", &x);
+            }
+        }
+    }
+
+    /// Record that a leader equivocated in its microblock stream, so its reward will be withheld
+    /// when it matures.
+    pub fn insert_poison_microblock_report<'a>(clarity_tx: &mut ClarityTx<'a>, microblock_pubkey_hash: &Hash160, reporter: &StacksAddress, sequence: u16) -> Result<(), Error> {
+        let boot_code_address = StacksAddress::from_string(&STACKS_BOOT_CODE_CONTRACT_ADDRESS.to_string()).unwrap();
+        let miner_contract_id = QualifiedContractIdentifier::new(StandardPrincipalData::from(boot_code_address.clone()), ContractName::try_from(BOOT_CODE_MINER_CONTRACT_NAME.to_string()).unwrap());
+
+        let reporter_name = ClarityName::try_from(BOOT_CODE_MINER_POISON_REPORTER.to_string()).unwrap();
+        let sequence_name = ClarityName::try_from(BOOT_CODE_MINER_POISON_SEQUENCE.to_string()).unwrap();
+        let poison_key = StacksChainState::make_poison_microblock_key(microblock_pubkey_hash);
+        let report = Value::Tuple(TupleData::from_data(vec![
+                (reporter_name, Value::Principal(PrincipalData::Standard(StandardPrincipalData::from(reporter.clone())))),
+                (sequence_name, Value::UInt(sequence as u128))])
+            .expect("FATAL: failed to construct poisoned microblock report"));
+
+        clarity_tx.connection().with_clarity_db(|ref mut db| {
+            db.set_entry(&miner_contract_id, BOOT_CODE_MINER_POISON_MAP, poison_key, report)?;
+            Ok(())
+        }).map_err(Error::ClarityError)?;
+        Ok(())
+    }
+
+    /// Find the STX operations sent on the burn chain that an anchored block must apply.
+    /// A block applies the operations in each burnchain block on its fork, starting at its parent's
+    /// sortition and ending just before its own sortition.  These are exactly the operations its
+    /// miner could have seen, and each burnchain block's operations get applied once per Stacks fork.
+    /// * parent_burn_header_hash is the burnchain block that elected the parent (or
+    /// FIRST_BURNCHAIN_BLOCK_HASH if there is no parent).
+    /// * tip_burn_header_hash is the burnchain block just before this block's sortition.
+    /// Operations are returned in the order they were sent.
+    pub fn get_burnchain_stx_ops<'a>(burn_tx: &mut BurnDBTx<'a>, parent_burn_header_hash: &BurnchainHeaderHash, tip_burn_header_hash: &BurnchainHeaderHash) -> Result<Vec<BurnchainStxOp>, Error> {
+        let tip_snapshot = match BurnDB::get_block_snapshot(burn_tx, tip_burn_header_hash).map_err(Error::DBError)? {
+            Some(sn) => sn,
+            None => {
+                return Err(Error::DBError(db_error::NotFoundError));
+            }
+        };
+
+        let start_height = match BurnDB::get_block_snapshot(burn_tx, parent_burn_header_hash).map_err(Error::DBError)? {
+            Some(sn) => sn.block_height,
+            None => {
+                // no parent, so start at the beginning of the burn chain
+                BurnDB::get_first_block_snapshot(burn_tx).map_err(Error::DBError)?.block_height
+            }
+        };
+
+        let mut stx_ops = vec![];
+        for block_height in start_height..(tip_snapshot.block_height + 1) {
+            let transfers = BurnDB::get_transfer_stx_ops_by_block(burn_tx, block_height, tip_burn_header_hash).map_err(Error::DBError)?;
+            let pre_stxs = BurnDB::get_pre_stx_ops_by_block(burn_tx, block_height, tip_burn_header_hash).map_err(Error::DBError)?;
+
+            let mut block_stx_ops : Vec<BurnchainStxOp> = transfers
+                .into_iter()
+                .map(BurnchainStxOp::TransferStx)
+                .chain(pre_stxs.into_iter().map(BurnchainStxOp::PreStx))
+                .collect();
+
+            block_stx_ops.sort_by_key(|op| op.vtxindex());
+            stx_ops.append(&mut block_stx_ops);
+        }
+
+        Ok(stx_ops)
+    }
+
+    /// Process a single burnchain STX transfer.
+    /// The sender's account was never checked on the burn chain, so skip the transfer if it can't
+    /// afford it.
+    fn process_transfer_stx_op<'a>(clarity_tx: &mut ClarityTx<'a>, op: &TransferStxOp) -> () {
+        let sender = op.sender.to_account_principal();
+        let recipient = op.recipient.to_account_principal();
+
+        let sender_account = StacksChainState::get_account(clarity_tx, &sender);
+        if sender_account.stx_balance < (op.transfered_ustx as u128) {
+            warn!("Skip STX transfer {} at {},{}: {} has {} uSTX, but tried to send {}", &op.txid, op.block_height, op.vtxindex, &op.sender.to_string(), sender_account.stx_balance, op.transfered_ustx);
+            return;
+        }
+
+        debug!("Transfer {} uSTX from {} to {} ({} at {},{})", op.transfered_ustx, &op.sender.to_string(), &op.recipient.to_string(), &op.txid, op.block_height, op.vtxindex);
+        StacksChainState::account_debit(clarity_tx, &sender, op.transfered_ustx);
+        StacksChainState::account_credit(clarity_tx, &recipient, op.transfered_ustx);
+    }
+
+    /// Process a single burnchain stacking pre-registration.
+    /// There is no stacking contract in the boot code yet, so this doesn't change the chain state;
+    /// the pre-registration stays queryable from the burn database.
+    fn process_pre_stx_op<'a>(clarity_tx: &mut ClarityTx<'a>, op: &PreStxOp) -> () {
+        let stacker = op.stacker.to_account_principal();
+        let stacker_account = StacksChainState::get_account(clarity_tx, &stacker);
+        if stacker_account.stx_balance < (op.stacked_ustx as u128) {
+            warn!("Skip stacking pre-registration {} at {},{}: {} has {} uSTX, but tried to stack {}", &op.txid, op.block_height, op.vtxindex, &op.stacker.to_string(), stacker_account.stx_balance, op.stacked_ustx);
+            return;
+        }
+
+        debug!("Pre-register {} to stack {} uSTX for {} ({} at {},{})", &op.stacker.to_string(), op.stacked_ustx, &op.reward_address.to_string(), &op.txid, op.block_height, op.vtxindex);
+    }
+
+    /// Process the STX operations sent on the burn chain that this block confirms.
+    /// They must be in the order they were sent.
+    pub fn process_burnchain_stx_ops<'a>(clarity_tx: &mut ClarityTx<'a>, stx_ops: &Vec<BurnchainStxOp>) -> Result<(), Error> {
+        for stx_op in stx_ops.iter() {
+            match *stx_op {
+                BurnchainStxOp::TransferStx(ref op) => {
+                    StacksChainState::process_transfer_stx_op(clarity_tx, op);
+                },
+                BurnchainStxOp::PreStx(ref op) => {
+                    StacksChainState::process_pre_stx_op(clarity_tx, op);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Process the next pre-processed staging block.
     /// We've already processed parent_chain_tip.  chain_tip refers to a block we have _not_
     /// processed yet.
@@ -2434,7 +2788,8 @@ impl StacksChainState {
                         microblocks: &Vec<StacksMicroblock>,  // parent microblocks 
                         burnchain_commit_burn: u64, 
                         burnchain_sortition_burn: u64, 
-                        user_burns: &Vec<StagingUserBurnSupport>) -> Result<(StacksHeaderInfo, Vec<StacksTransactionReceipt>), Error>
+                        user_burns: &Vec<StagingUserBurnSupport>,
+                        burnchain_stx_ops: &Vec<BurnchainStxOp>) -> Result<(StacksHeaderInfo, Vec<StacksTransactionReceipt>), Error>
     {
 
         debug!("Process block {:?} with {} transactions", &block.block_hash().to_hex(), block.txs.len());
@@ -2479,7 +2834,8 @@ impl StacksChainState {
             
             let mut clarity_tx = StacksChainState::chainstate_block_begin(chainstate_tx, clarity_instance, &parent_burn_header_hash, &parent_block_hash, &MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH);
 
-            // process microblock stream
+            // process microblock stream, within its own execution budget
+            clarity_tx.reset_cost(MICROBLOCK_STREAM_COST_LIMIT);
             let (microblock_fees, _microblock_burns, mut microblock_txs_receipts) = match StacksChainState::process_microblocks_transactions(&mut clarity_tx, &microblocks) {
                 Err((e, offending_mblock_header_hash)) => {
                     let msg = format!("Invalid Stacks microblocks {},{} (offender {}): {:?}", block.header.parent_microblock, block.header.parent_microblock_sequence, offending_mblock_header_hash, &e);
@@ -2498,7 +2854,8 @@ impl StacksChainState {
                         block.header.total_work.work, block.header.total_work.burn,
                         last_microblock_hash, last_microblock_seq, microblocks.len());
 
-            // process anchored block
+            // process anchored block, within its own execution budget
+            clarity_tx.reset_cost(ANCHORED_BLOCK_COST_LIMIT);
             let (block_fees, block_burns, mut txs_receipts) = match StacksChainState::process_block_transactions(&mut clarity_tx, &block) {
                 Err(e) => {
                     let msg = format!("Invalid Stacks block {}: {:?}", block.block_hash(), &e);
@@ -2516,6 +2873,9 @@ impl StacksChainState {
                 StacksChainState::process_matured_miner_rewards(&mut clarity_tx, &mature_miner_rewards)?;
             }
 
+            // apply STX operations sent on the burn chain
+            StacksChainState::process_burnchain_stx_ops(&mut clarity_tx, burnchain_stx_ops)?;
+
             let root_hash = clarity_tx.get_root_hash();
             if root_hash != block.header.state_index_root {
                 let msg = format!("Block {} state root mismatch: expected {}, got {}", block.block_hash(), root_hash, block.header.state_index_root);
@@ -2691,6 +3051,9 @@ impl StacksChainState {
         // find users that burned in support of this block, so we can calculate the miner reward
         let user_supports = StacksChainState::load_staging_block_user_supports(&chainstate_tx.blocks_tx, &next_staging_block.burn_header_hash, &next_staging_block.anchored_block_hash)?;
 
+        // find the STX operations sent on the burn chain that this block applies
+        let burnchain_stx_ops = StacksChainState::load_staging_block_burnchain_stx_ops(&chainstate_tx.blocks_tx, &next_staging_block.burn_header_hash, &next_staging_block.anchored_block_hash)?;
+
         // attach the block to the chain state and calculate the next chain tip.
         // Execute the confirmed microblocks' transactions against the chain state, and then
         // execute the anchored block's transactions against the chain state.
@@ -2703,7 +3066,8 @@ impl StacksChainState {
                                                                   &next_microblocks, 
                                                                   next_staging_block.commit_burn, 
                                                                   next_staging_block.sortition_burn, 
-                                                                  &user_supports) {
+                                                                  &user_supports,
+                                                                  &burnchain_stx_ops) {
             Ok(next_chain_tip) => next_chain_tip,
             Err(e) => {
                 // something's wrong with this epoch -- either a microblock was invalid, or the
@@ -2839,11 +3203,13 @@ impl StacksChainState {
         StacksChainState::process_transaction_precheck(&self.config(), &tx)
             .map_err(|e| MemPoolRejection::FailedToValidate(e))?;
 
-        // 3: it must pay a tx fee
-        let fee = tx.get_fee_rate();
+        // 3: it must pay a tx fee.  We don't know the execution cost yet, so the least it will
+        // pay is its fee rate times its length.
+        let fee_rate = tx.get_fee_rate();
+        let fee = StacksChainState::get_tx_fee(&tx, tx_size, &ExecutionCost::zero()).unwrap_or(u64::max_value());
 
-        if fee < MINIMUM_TX_FEE || 
-           fee / tx_size < MINIMUM_TX_FEE_RATE_PER_BYTE {
+        if fee_rate < MINIMUM_TX_FEE_RATE_PER_BYTE ||
+           fee < MINIMUM_TX_FEE {
             return Err(MemPoolRejection::FeeTooLow(fee, cmp::max(MINIMUM_TX_FEE, tx_size * MINIMUM_TX_FEE_RATE_PER_BYTE)))
         }
 
@@ -2914,8 +3280,7 @@ impl StacksChainState {
             },
             TransactionPayload::PoisonMicroblock(microblock_header_1, microblock_header_2) => {
                 if microblock_header_1.sequence != microblock_header_2.sequence ||
-                    microblock_header_1.prev_block != microblock_header_2.prev_block ||
-                    microblock_header_1.version != microblock_header_2.version {
+                    microblock_header_1.block_hash() == microblock_header_2.block_hash() {
                     return Err(MemPoolRejection::PoisonMicroblocksDoNotConflict)
                 }
 
@@ -2931,6 +3296,13 @@ impl StacksChainState {
                 if !self.have_any_blocks_with_microblock_pubkh(&microblock_pkh_1, 0) {
                     return Err(MemPoolRejection::NoAnchorBlockWithPubkeyHash(microblock_pkh_1))
                 }
+
+                let report_opt = self.with_read_only_clarity_tx(current_burn, current_block, |conn| {
+                    StacksChainState::get_poison_microblock_report(conn, &microblock_pkh_1) })
+                    .map_err(|e| MemPoolRejection::FailedToValidate(e))?;
+                if report_opt.is_some() {
+                    return Err(MemPoolRejection::PoisonMicroblockAlreadyReported(microblock_pkh_1))
+                }
             },
             TransactionPayload::Coinbase(_) => {
                 return Err(MemPoolRejection::NoCoinbaseViaMempool)
@@ -4414,6 +4786,36 @@ pub mod test {
             }
         }
     }
+
+    #[test]
+    fn stacks_db_process_burnchain_stx_ops() {
+        let mut chainstate = instantiate_chainstate(false, 0x80000000, "stacks_db_process_burnchain_stx_ops");
+
+        let alice = StacksAddress::new(C32_ADDRESS_VERSION_TESTNET_SINGLESIG, Hash160([0x01; 20]));
+        let bob = StacksAddress::new(C32_ADDRESS_VERSION_TESTNET_SINGLESIG, Hash160([0x02; 20]));
+        let charlie = StacksAddress::new(C32_ADDRESS_VERSION_TESTNET_SINGLESIG, Hash160([0x03; 20]));
+
+        let stx_ops = vec![
+            // alice can afford this
+            BurnchainStxOp::TransferStx(TransferStxOp::new(&alice, &bob, 100)),
+            // bob can't afford this
+            BurnchainStxOp::TransferStx(TransferStxOp::new(&bob, &charlie, 101)),
+            // pre-registrations don't move any STX
+            BurnchainStxOp::PreStx(PreStxOp::new(&alice, &charlie, 24)),
+            BurnchainStxOp::PreStx(PreStxOp::new(&alice, &charlie, 23)),
+        ];
+
+        let mut conn = chainstate.block_begin(&FIRST_BURNCHAIN_BLOCK_HASH, &FIRST_STACKS_BLOCK_HASH, &BurnchainHeaderHash([1u8; 32]), &BlockHeaderHash([1u8; 32]));
+        StacksChainState::account_credit(&mut conn, &alice.to_account_principal(), 123);
+
+        StacksChainState::process_burnchain_stx_ops(&mut conn, &stx_ops).unwrap();
+
+        assert_eq!(StacksChainState::get_account(&mut conn, &alice.to_account_principal()).stx_balance, 23);
+        assert_eq!(StacksChainState::get_account(&mut conn, &bob.to_account_principal()).stx_balance, 100);
+        assert_eq!(StacksChainState::get_account(&mut conn, &charlie.to_account_principal()).stx_balance, 0);
+
+        conn.commit_block();
+    }
    
     // TODO: test multiple anchored blocks confirming the same microblock stream (in the same
     // place, and different places, with/without orphans)
@@ -0,0 +1,502 @@
+/*
+ copyright: (c) 2013-2019 by Blockstack PBC, a public benefit corporation.
+
+ This file is part of Blockstack.
+
+ Blockstack is free software. You may redistribute or modify
+ it under the terms of the GNU General Public License as published by
+ the Free Software Foundation, either version 3 of the License or
+ (at your option) any later version.
+
+ Blockstack is distributed in the hope that it will be useful,
+ but WITHOUT ANY WARRANTY, including without the implied warranty of
+ MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ GNU General Public License for more details.
+
+ You should have received a copy of the GNU General Public License
+ along with Blockstack. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/// Offline chainstate integrity checks.
+///
+/// These walk a stopped node's on-disk state and cross-check the stores against each other:
+/// Stacks headers against the headers and Clarity MARFs, processed staging blocks against the
+/// chunk store, Clarity contract metadata in the side-store against the contract commitments in
+/// the Clarity MARF, and burn snapshots against their parents and the burn DB's MARF.  Every
+/// inconsistency found is reported, rather than stopping at the first one.
+
+use std::fs;
+use std::fmt;
+use std::collections::{HashSet, HashMap};
+use std::path::PathBuf;
+
+use rusqlite::Connection;
+use rusqlite::OpenFlags;
+use rusqlite::NO_PARAMS;
+
+use burnchains::BurnchainHeaderHash;
+
+use chainstate::burn::{BlockSnapshot, ConsensusHash};
+use chainstate::burn::db::burndb::BurnDB;
+
+use chainstate::stacks::Error;
+use chainstate::stacks::*;
+use chainstate::stacks::db::*;
+use chainstate::stacks::db::blocks::StagingBlock;
+use chainstate::stacks::index::TrieHash;
+use chainstate::stacks::index::marf::MARF;
+
+use core::BOOT_BLOCK_HASH;
+
+use util::db::Error as db_error;
+use util::db::query_rows;
+
+use vm::database::{ClarityDatabase, StoreType, ContractCommitment, SideStoreType, MarfedKV, try_stored_value_text};
+
+/// Which store an inconsistency was found in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChainstateStore {
+    Headers,
+    StagingBlocks,
+    ClaritySideStore,
+    BurnDB,
+}
+
+impl fmt::Display for ChainstateStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChainstateStore::Headers => write!(f, "headers"),
+            ChainstateStore::StagingBlocks => write!(f, "staging-blocks"),
+            ChainstateStore::ClaritySideStore => write!(f, "clarity-side-store"),
+            ChainstateStore::BurnDB => write!(f, "burndb"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainstateInconsistency {
+    pub store: ChainstateStore,
+    pub location: String,
+    pub problem: String,
+}
+
+impl fmt::Display for ChainstateInconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}: {}", &self.store, &self.location, &self.problem)
+    }
+}
+
+struct ChainstateChecker {
+    issues: Vec<ChainstateInconsistency>,
+}
+
+impl ChainstateChecker {
+    fn report(&mut self, store: ChainstateStore, location: &str, problem: String) {
+        warn!("[{}] {}: {}", &store, location, &problem);
+        self.issues.push(ChainstateInconsistency {
+            store: store,
+            location: location.to_string(),
+            problem: problem
+        });
+    }
+
+    /// Compare the root hash of a MARF at a given block to the root hash we expect
+    fn check_root(&mut self, store: ChainstateStore, location: &str, what: &str, marf: &mut MARF, block_hash: &BlockHeaderHash, expected: &TrieHash) {
+        match marf.get_root_hash_at(block_hash) {
+            Ok(root_hash) => {
+                if root_hash != *expected {
+                    self.report(store, location, format!("{} root is {}, but {} was expected", what, &root_hash, expected));
+                }
+            },
+            Err(e) => {
+                self.report(store, location, format!("no {} root for {}: {:?}", what, block_hash, &e));
+            }
+        }
+    }
+
+    /// Every processed Stacks block's state root and fork index root must match the Clarity and
+    /// headers MARFs.
+    fn check_headers(&mut self, chainstate: &mut StacksChainState, clarity_marf: &mut MARF) -> Result<(), Error> {
+        let headers = query_rows::<StacksHeaderInfo, _>(&chainstate.headers_db, &"SELECT * FROM block_headers ORDER BY block_height".to_string(), NO_PARAMS)
+            .map_err(Error::DBError)?;
+
+        for header in headers.iter() {
+            if header.anchored_header.parent_block == BOOT_BLOCK_HASH {
+                // the boot block's header is not indexed under its own hash
+                continue;
+            }
+
+            let location = format!("Stacks block {}/{} (height {})", &header.burn_header_hash, header.anchored_header.block_hash(), header.block_height);
+            let index_block_hash = header.index_block_hash();
+
+            self.check_root(ChainstateStore::Headers, &location, "headers index", &mut chainstate.headers_state_index, &index_block_hash, &header.index_root);
+            self.check_root(ChainstateStore::Headers, &location, "Clarity state", clarity_marf, &index_block_hash, &header.anchored_header.state_index_root);
+        }
+
+        debug!("Checked {} Stacks headers", headers.len());
+        Ok(())
+    }
+
+    /// Every processed, non-orphaned staging block must be in the chunk store and have a header
+    fn check_staging_blocks(&mut self, chainstate: &StacksChainState) -> Result<(), Error> {
+        let staging_blocks = query_rows::<StagingBlock, _>(&chainstate.blocks_db, &"SELECT * FROM staging_blocks WHERE processed = 1 AND orphaned = 0".to_string(), NO_PARAMS)
+            .map_err(Error::DBError)?;
+
+        for staging_block in staging_blocks.iter() {
+            let location = format!("staging block {}/{}", &staging_block.burn_header_hash, &staging_block.anchored_block_hash);
+
+            match StacksChainState::load_block(&chainstate.blocks_path, &staging_block.burn_header_hash, &staging_block.anchored_block_hash) {
+                Ok(Some(block)) => {
+                    if block.block_hash() != staging_block.anchored_block_hash {
+                        self.report(ChainstateStore::StagingBlocks, &location, format!("stored block has hash {}", block.block_hash()));
+                    }
+                },
+                Ok(None) => {
+                    self.report(ChainstateStore::StagingBlocks, &location, "processed block's data was erased".to_string());
+                },
+                Err(Error::DBError(db_error::NotFoundError)) => {
+                    let path = StacksChainState::get_block_path(&chainstate.blocks_path, &staging_block.burn_header_hash, &staging_block.anchored_block_hash)?;
+                    self.report(ChainstateStore::StagingBlocks, &location, format!("processed block is missing from {}", &path));
+                },
+                Err(e) => {
+                    self.report(ChainstateStore::StagingBlocks, &location, format!("failed to load processed block: {:?}", &e));
+                }
+            }
+
+            if StacksChainState::get_anchored_block_header_info(&chainstate.headers_db, &staging_block.burn_header_hash, &staging_block.anchored_block_hash)?.is_none() {
+                self.report(ChainstateStore::StagingBlocks, &location, "processed block has no header".to_string());
+            }
+        }
+
+        debug!("Checked {} processed staging blocks", staging_blocks.len());
+        Ok(())
+    }
+
+    /// The side-store's values must hash to their keys, and each contract's metadata must be
+    /// stored under the block its MARF commitment points to.
+    fn check_side_store(&mut self, side_store: &Connection, clarity_marf: &mut MARF) -> Result<(), Error> {
+        let mut num_values = 0;
+        {
+            // databases that have not yet been opened since values were stored as bytes still hold text
+            let mut stmt = side_store.prepare("SELECT key, CAST(value AS BLOB) FROM data_table").map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            let mut rows = stmt.query(NO_PARAMS).map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            while let Some(row_res) = rows.next() {
+                let row = row_res.map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+                let key : String = row.get(0);
+                let value : Vec<u8> = row.get(1);
+                let value_hash = MarfedKV::make_marf_value(&value).to_hex();
+                if value_hash != key {
+                    self.report(ChainstateStore::ClaritySideStore, &format!("value {}", &key), format!("value hashes to {}", &value_hash));
+                }
+                num_values += 1;
+            }
+        }
+
+        // (block hash, contract) pairs, and the metadata keys each one has
+        let mut contract_metadata : Vec<(String, String, HashSet<String>)> = vec![];
+        {
+            let mut stmt = side_store.prepare("SELECT blockhash, key FROM metadata_table ORDER BY blockhash, key").map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            let mut rows = stmt.query(NO_PARAMS).map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            while let Some(row_res) = rows.next() {
+                let row = row_res.map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+                let blockhash : String = row.get(0);
+                let key : String = row.get(1);
+
+                let (contract, metadata_key) = match ChainstateChecker::parse_metadata_key(&key) {
+                    Some(x) => x,
+                    None => {
+                        self.report(ChainstateStore::ClaritySideStore, &format!("metadata {} at {}", &key, &blockhash), "unrecognized metadata key".to_string());
+                        continue;
+                    }
+                };
+
+                let is_new = match contract_metadata.last() {
+                    Some((last_blockhash, last_contract, _)) => *last_blockhash != blockhash || *last_contract != contract,
+                    None => true
+                };
+                if is_new {
+                    contract_metadata.push((blockhash, contract, HashSet::new()));
+                }
+                if let Some((_, _, keys)) = contract_metadata.last_mut() {
+                    keys.insert(metadata_key);
+                }
+            }
+        }
+
+        let required_keys = vec![
+            ClarityDatabase::make_metadata_key(StoreType::Contract, "contract"),
+            ClarityDatabase::make_metadata_key(StoreType::Contract, "contract-size"),
+        ];
+
+        for (blockhash, contract, keys) in contract_metadata.iter() {
+            let block_hash = match BlockHeaderHash::from_hex(blockhash) {
+                Ok(bhh) => bhh,
+                Err(_) => {
+                    // left behind by a miner that discarded its block
+                    test_debug!("Skip metadata for {} at {}", contract, blockhash);
+                    continue;
+                }
+            };
+
+            let location = format!("contract {} at {}", contract, blockhash);
+            for required_key in required_keys.iter() {
+                if !keys.contains(required_key) {
+                    self.report(ChainstateStore::ClaritySideStore, &location, format!("missing metadata {}", required_key));
+                }
+            }
+
+            let commitment_key = format!("clarity-contract::{}", contract);
+            let marf_value = match clarity_marf.get(&block_hash, &commitment_key) {
+                Ok(Some(marf_value)) => marf_value,
+                Ok(None) => {
+                    self.report(ChainstateStore::ClaritySideStore, &location, "metadata has no contract commitment in the Clarity MARF".to_string());
+                    continue;
+                },
+                Err(e) => {
+                    self.report(ChainstateStore::ClaritySideStore, &location, format!("failed to read contract commitment: {:?}", &e));
+                    continue;
+                }
+            };
+
+            let commitment_str : Option<String> = side_store.query_row("SELECT CAST(value AS BLOB) FROM data_table WHERE key = ?1", &[&marf_value.to_hex()], |row| {
+                    let value : Vec<u8> = row.get(0);
+                    try_stored_value_text(&value)
+                })
+                .ok()
+                .and_then(|value| value);
+
+            let commitment = match commitment_str.as_ref().and_then(|s| ContractCommitment::try_deserialize(s)) {
+                Some(commitment) => commitment,
+                None => {
+                    self.report(ChainstateStore::ClaritySideStore, &location, format!("contract commitment {} is missing or malformed", &marf_value.to_hex()));
+                    continue;
+                }
+            };
+
+            match clarity_marf.get_bhh_at_height(&block_hash, commitment.block_height) {
+                Ok(Some(commitment_block_hash)) => {
+                    if commitment_block_hash != block_hash {
+                        self.report(ChainstateStore::ClaritySideStore, &location, format!("contract is committed at {} (height {})", &commitment_block_hash, commitment.block_height));
+                    }
+                },
+                Ok(None) => {
+                    self.report(ChainstateStore::ClaritySideStore, &location, format!("contract commitment height {} is not in this fork", commitment.block_height));
+                },
+                Err(e) => {
+                    self.report(ChainstateStore::ClaritySideStore, &location, format!("failed to resolve contract commitment height {}: {:?}", commitment.block_height, &e));
+                }
+            }
+        }
+
+        debug!("Checked {} side-store values and metadata for {} contracts", num_values, contract_metadata.len());
+        Ok(())
+    }
+
+    /// Split "clr-meta::{contract}::{key}" into the contract and key
+    fn parse_metadata_key(key: &str) -> Option<(String, String)> {
+        let prefix = "clr-meta::";
+        if !key.starts_with(prefix) {
+            return None;
+        }
+        let rest = &key[prefix.len()..];
+        let idx = rest.find("::")?;
+        Some((rest[0..idx].to_string(), rest[idx+2..].to_string()))
+    }
+
+    /// Every burn snapshot must follow from its parent, and its index root must match the burn
+    /// DB's MARF.
+    /// Get the consensus hashes a child of the given block is built from, like
+    /// ConsensusHash::get_prev_consensus_hashes(), but without a burn DB transaction.
+    fn get_prev_consensus_hashes(marf: &mut MARF, consensus_hashes: &HashMap<BurnchainHeaderHash, ConsensusHash>, block_height: u64, first_block_height: u64, tip_block_hash: &BurnchainHeaderHash) -> Result<Vec<ConsensusHash>, Error> {
+        let tip_bhh = BlockHeaderHash::from(tip_block_hash.clone());
+        let mut i = 0;
+        let mut prev_chs = vec![];
+        while i < 64 && block_height - (((1 as u64) << i) - 1) >= first_block_height {
+            let prev_block : u64 = block_height - (((1 as u64) << i) - 1);
+            let ancestor_opt = MARF::get_block_at_height(marf.borrow_storage_backend(), (prev_block - first_block_height) as u32, &tip_bhh)
+                .map_err(|e| Error::DBError(db_error::IndexError(e)))?;
+
+            let prev_ch = match ancestor_opt {
+                Some(ancestor) => consensus_hashes.get(&BurnchainHeaderHash::from(ancestor)).cloned().unwrap_or(ConsensusHash::empty()),
+                None => ConsensusHash::empty()
+            };
+
+            prev_chs.push(prev_ch);
+            i += 1;
+
+            if block_height < (((1 as u64) << i) - 1) {
+                break;
+            }
+        }
+        Ok(prev_chs)
+    }
+
+    fn check_burn_snapshots(&mut self, burndb: &mut BurnDB) -> Result<(), Error> {
+        let first_block_height = burndb.first_block_height;
+        let snapshots = query_rows::<BlockSnapshot, _>(burndb.conn(), &"SELECT * FROM snapshots ORDER BY block_height".to_string(), NO_PARAMS)
+            .map_err(Error::DBError)?;
+
+        let consensus_hashes : HashMap<BurnchainHeaderHash, ConsensusHash> = snapshots.iter()
+            .map(|sn| (sn.burn_header_hash.clone(), sn.consensus_hash.clone()))
+            .collect();
+
+        for snapshot in snapshots.iter() {
+            let location = format!("burn block {} (height {})", &snapshot.burn_header_hash, snapshot.block_height);
+
+            self.check_root(ChainstateStore::BurnDB, &location, "burn index", &mut burndb.marf, &BlockHeaderHash::from(snapshot.burn_header_hash.clone()), &snapshot.index_root);
+
+            if snapshot.block_height == first_block_height {
+                // the first snapshot has no parent to check against
+                continue;
+            }
+
+            let parent = match BurnDB::get_block_snapshot(burndb.conn(), &snapshot.parent_burn_header_hash).map_err(Error::DBError)? {
+                Some(parent) => parent,
+                None => {
+                    self.report(ChainstateStore::BurnDB, &location, format!("parent snapshot {} is missing", &snapshot.parent_burn_header_hash));
+                    continue;
+                }
+            };
+
+            if parent.block_height + 1 != snapshot.block_height {
+                self.report(ChainstateStore::BurnDB, &location, format!("parent snapshot {} is at height {}", &parent.burn_header_hash, parent.block_height));
+                continue;
+            }
+
+            let expected_sortitions = parent.num_sortitions + if snapshot.sortition { 1 } else { 0 };
+            if snapshot.num_sortitions != expected_sortitions {
+                self.report(ChainstateStore::BurnDB, &location, format!("{} sortitions, but its parent implies {}", snapshot.num_sortitions, expected_sortitions));
+            }
+
+            if snapshot.total_burn < parent.total_burn {
+                self.report(ChainstateStore::BurnDB, &location, format!("total burn {} is less than its parent's {}", snapshot.total_burn, parent.total_burn));
+            }
+
+            let prev_consensus_hashes = ChainstateChecker::get_prev_consensus_hashes(&mut burndb.marf, &consensus_hashes, parent.block_height, first_block_height, &parent.burn_header_hash)?;
+            let consensus_hash = ConsensusHash::from_ops(&snapshot.ops_hash, snapshot.total_burn, &prev_consensus_hashes);
+
+            if consensus_hash != snapshot.consensus_hash {
+                self.report(ChainstateStore::BurnDB, &location, format!("consensus hash is {}, but its parent's consensus hashes give {}", &snapshot.consensus_hash, &consensus_hash));
+            }
+        }
+
+        debug!("Checked {} burn snapshots", snapshots.len());
+        Ok(())
+    }
+}
+
+impl StacksChainState {
+    /// Check a stopped node's chainstate and burn database for consistency.  Returns every
+    /// inconsistency found -- an empty list means the state is consistent.  An Err is only returned
+    /// if the stores cannot be opened or read at all.
+    pub fn check_chainstate(mainnet: bool, chain_id: u32, chainstate_path: &str, burndb_path: &str) -> Result<Vec<ChainstateInconsistency>, Error> {
+        // don't instantiate a fresh chainstate just to check it
+        let mut clarity_marf_path = PathBuf::from(chainstate_path);
+        clarity_marf_path.push(StacksChainState::chain_dir_name(mainnet, chain_id));
+        clarity_marf_path.push("vm");
+        clarity_marf_path.push("clarity");
+        clarity_marf_path.push("marf");
+        if fs::metadata(&clarity_marf_path).is_err() {
+            error!("No chainstate at {}", chainstate_path);
+            return Err(Error::DBError(db_error::NoDBError));
+        }
+
+        let mut chainstate = StacksChainState::open(mainnet, chain_id, chainstate_path)?;
+        let mut clarity_marf = StacksChainState::open_index(&chainstate.clarity_state_index_path, None, chainstate.index_layout)?;
+
+        // only the SQLite side store can be walked without loading it
+        let mut side_store_path = PathBuf::from(&chainstate.clarity_state_index_path);
+        side_store_path.pop();
+        side_store_path.push(SideStoreType::Sqlite.file_name());
+        let side_store_opt =
+            if fs::metadata(&side_store_path).is_ok() {
+                Some(Connection::open_with_flags(&side_store_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                     .map_err(|e| Error::DBError(db_error::SqliteError(e)))?)
+            }
+            else {
+                warn!("No SQLite side store at {:?}; not checking the side store", &side_store_path);
+                None
+            };
+
+        let mut burndb = BurnDB::open(&burndb_path.to_string(), false).map_err(Error::DBError)?;
+
+        let mut checker = ChainstateChecker {
+            issues: vec![]
+        };
+
+        checker.check_headers(&mut chainstate, &mut clarity_marf)?;
+        checker.check_staging_blocks(&chainstate)?;
+        if let Some(side_store) = side_store_opt {
+            checker.check_side_store(&side_store, &mut clarity_marf)?;
+        }
+        checker.check_burn_snapshots(&mut burndb)?;
+
+        Ok(checker.issues)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use burnchains::BurnchainHeaderHash;
+    use chainstate::stacks::db::snapshot::test::*;
+
+    #[test]
+    fn check_chainstate_finds_inconsistencies() {
+        let (chainstate_path, burndb_path, tip) = make_snapshot_source("check-chainstate", 4);
+
+        let issues = StacksChainState::check_chainstate(false, 0x80000000, &chainstate_path, &burndb_path).unwrap();
+        assert_eq!(issues, vec![]);
+
+        let chain_dir = format!("{}/{}", &chainstate_path, StacksChainState::chain_dir_name(false, 0x80000000));
+
+        // a header whose fork index root doesn't match the headers MARF (the state root is part
+        // of the block hash, so it can't be changed without making the row unreadable)
+        {
+            let conn = Connection::open(&format!("{}/vm/headers.db", &chain_dir)).unwrap();
+            conn.execute("UPDATE block_headers SET index_root = ?1 WHERE block_height = 3", &[&TrieHash([0x22; 32]).to_hex()]).unwrap();
+        }
+
+        // a processed block whose data is missing
+        {
+            let conn = Connection::open(&format!("{}/blocks/staging.db", &chain_dir)).unwrap();
+            conn.execute("INSERT INTO staging_blocks \
+                         (anchored_block_hash, parent_anchored_block_hash, burn_header_hash, burn_header_timestamp, parent_burn_header_hash, parent_microblock_hash, parent_microblock_seq, \
+                          microblock_pubkey_hash, attacheable, orphaned, processed, commit_burn, sortition_burn, index_block_hash) \
+                         VALUES (?1, ?2, ?3, 0, ?4, ?5, 0, ?6, 1, 0, 1, 0, 0, ?7)",
+                         &[&tip.anchored_header.block_hash().to_hex(), &tip.anchored_header.parent_block.to_hex(), &tip.burn_header_hash.to_hex(), &BurnchainHeaderHash([0u8; 32]).to_hex(),
+                           &BlockHeaderHash([0u8; 32]).to_hex(), &tip.anchored_header.microblock_pubkey_hash.to_hex(), &tip.index_block_hash().to_hex()]).unwrap();
+        }
+
+        // a boot contract that lost some of its metadata
+        {
+            let conn = Connection::open(&format!("{}/vm/clarity/data.sqlite", &chain_dir)).unwrap();
+            conn.execute("DELETE FROM metadata_table WHERE rowid = (SELECT rowid FROM metadata_table WHERE key LIKE '%::vm-metadata::9::contract-size' LIMIT 1)", NO_PARAMS).unwrap();
+        }
+
+        // a burn snapshot with the wrong consensus hash
+        {
+            let conn = Connection::open(&format!("{}/data.db", &burndb_path)).unwrap();
+            conn.execute("UPDATE snapshots SET consensus_hash = ?1 WHERE block_height = 2", &[&ConsensusHash([0x33; 20]).to_hex()]).unwrap();
+        }
+
+        let issues = StacksChainState::check_chainstate(false, 0x80000000, &chainstate_path, &burndb_path).unwrap();
+        for issue in issues.iter() {
+            test_debug!("{}", issue);
+        }
+
+        let count = |store: ChainstateStore| issues.iter().filter(|issue| issue.store == store).count();
+        assert_eq!(count(ChainstateStore::Headers), 1);
+        assert_eq!(count(ChainstateStore::StagingBlocks), 1);
+        assert_eq!(count(ChainstateStore::ClaritySideStore), 1);
+
+        // the snapshot itself, and the children whose consensus hashes were derived from it
+        assert!(count(ChainstateStore::BurnDB) >= 1);
+        assert!(issues.iter().any(|issue| issue.store == ChainstateStore::BurnDB && issue.location.contains("(height 2)")));
+
+        assert!(issues.iter().any(|issue| issue.store == ChainstateStore::Headers && issue.location.contains("(height 3)")));
+        assert!(issues.iter().any(|issue| issue.store == ChainstateStore::StagingBlocks && issue.problem.contains("missing")));
+        assert!(issues.iter().any(|issue| issue.store == ChainstateStore::ClaritySideStore && issue.problem.contains("contract-size")));
+    }
+}
@@ -18,9 +18,13 @@
 */
 
 pub mod accounts;
+pub mod archive;
 pub mod blocks;
 pub mod contracts;
+pub mod fsck;
 pub mod headers;
+pub mod prune;
+pub mod snapshot;
 pub mod transactions;
 
 use rusqlite::Transaction;
@@ -54,7 +58,10 @@ use chainstate::stacks::index::marf::{
     BLOCK_HEIGHT_TO_HASH_MAPPING_KEY
 };
 
-use chainstate::stacks::index::storage::TrieFileStorage;
+use chainstate::stacks::index::storage::{
+    TrieFileStorage,
+    TrieStorageLayout
+};
 
 use std::path::{Path, PathBuf};
 
@@ -94,6 +101,7 @@ use vm::clarity::{
 };
 use vm::representations::ClarityName;
 use vm::representations::ContractName;
+use vm::costs::ExecutionCost;
 
 use core::CHAINSTATE_VERSION;
 
@@ -105,7 +113,9 @@ pub struct StacksChainState {
     pub blocks_db: DBConn,
     pub headers_state_index: MARF,
     pub blocks_path: String,
-    pub clarity_state_index_path: String
+    pub clarity_state_index_path: String,
+    pub index_layout: TrieStorageLayout,
+    archive_mode: bool
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -276,6 +286,27 @@ impl<'a> ClarityTx<'a> {
         self.block.get_root_hash()
     }
 
+    pub fn cost_so_far(&self) -> ExecutionCost {
+        self.block.cost_so_far()
+    }
+
+    /// Count execution cost against a fresh budget, starting from zero
+    pub fn reset_cost(&mut self, limit: ExecutionCost) {
+        self.block.reset_cost(ExecutionCost::zero(), limit)
+    }
+
+    pub fn hold_writes(&mut self) {
+        self.block.hold_writes()
+    }
+
+    pub fn release_writes(&mut self) {
+        self.block.release_writes()
+    }
+
+    pub fn discard_writes(&mut self) {
+        self.block.discard_writes()
+    }
+
     #[cfg(test)]
     pub fn commit_block(self) -> () {
         self.block.commit_block();
@@ -302,6 +333,10 @@ impl<'a> ClarityTx<'a> {
         let index_block_hash = StacksBlockHeader::make_index_block_hash(burn_hash, block_hash);
         self.block.get_marf().get_block_path(&index_block_hash)
     }
+
+    pub fn get_index_layout(&mut self) -> TrieStorageLayout {
+        self.block.get_marf().borrow_storage_backend().get_layout()
+    }
 }
 
 pub struct ChainstateTx<'a> {
@@ -336,8 +371,8 @@ pub struct BlockStreamData {
 }
 
 // TODO: write code to populate the microblock_headers table too!
-// TODO: keep track of when microblock equivocations occur (maybe in the MARF?), so that once we
-// process a PoisonMicroblock transaction, no further blocks may build off of any descendent fork.
+// TODO: once a PoisonMicroblock transaction is processed (it is recorded in the miner-rewards boot
+// contract's poisoned-microblocks map), no further blocks may build off of any descendent fork.
 const STACKS_CHAIN_STATE_SQL : &'static [&'static str]= &[
     "PRAGMA foreign_keys = ON;",
     r#"
@@ -438,6 +473,19 @@ const STACKS_CHAIN_STATE_SQL : &'static [&'static str]= &[
     )"#
 ];
 
+/// Tables added after the chain state database schema was first released.  These are applied to
+/// new databases and to existing ones whenever they are opened, so each must be idempotent.
+const STACKS_CHAIN_STATE_MIGRATIONS : &'static [&'static str] = &[
+    r#"
+    -- processed blocks whose state was pruned away
+    CREATE TABLE IF NOT EXISTS pruned_blocks(
+        index_block_hash TEXT NOT NULL,
+        block_height INTEGER NOT NULL,
+        PRIMARY KEY(index_block_hash)
+    );
+    "#
+];
+
 /// Built-in "system-level" smart contracts that are there from the beginning.
 /// Includes BNS and the miner trust fund.
 #[cfg(test)]
@@ -463,6 +511,16 @@ const STACKS_BOOT_CODE : &'static [&'static str] = &[
     (define-public (is-participant-authorized? (participant principal))
         (ok (get authorized (get-participant-info participant))))
 
+    ;; leaders caught equivocating in their microblock streams, by microblock public key hash.
+    ;; written only by the chain state when it processes a PoisonMicroblock transaction.
+    (define-map poisoned-microblocks
+        ((pubkey-hash (buff 20)))
+        ((reporter principal) (sequence uint))
+    )
+
+    (define-public (get-poisoned-microblock (pubkey-hash (buff 20)))
+        (ok (map-get? poisoned-microblocks ((pubkey-hash pubkey-hash)))))
+
     ;; TODO: authorize STX withdrawals
     ;; TODO: withdraw STX
     "#
@@ -479,6 +537,11 @@ pub const BOOT_CODE_MINER_REWARDS_MAP : &'static str = "rewards";
 pub const BOOT_CODE_MINER_REWARDS_PARTICIPANT : &'static str = "participant";
 pub const BOOT_CODE_MINER_REWARDS_AVAILABLE : &'static str = "available";
 pub const BOOT_CODE_MINER_REWARDS_AUTHORIZED : &'static str = "authorized";
+pub const BOOT_CODE_MINER_POISON_MAP : &'static str = "poisoned-microblocks";
+pub const BOOT_CODE_MINER_POISON_PUBKEY_HASH : &'static str = "pubkey-hash";
+pub const BOOT_CODE_MINER_POISON_REPORTER : &'static str = "reporter";
+pub const BOOT_CODE_MINER_POISON_SEQUENCE : &'static str = "sequence";
+
 
 #[cfg(test)]
 pub const MINER_REWARD_MATURITY : u64 = 2;       // small for testing purposes
@@ -496,18 +559,24 @@ pub const MINER_FEE_MINIMUM_BLOCK_USAGE : u64 = 80;         // miner must share
 
 pub const MINER_FEE_WINDOW : u64 = 24;                      // number of blocks (B) used to smooth over the fraction of tx fees they share from anchored blocks
 
+pub const POISON_MICROBLOCK_BOUNTY_PCT : u128 = 5;          // a leader caught equivocating forfeits its matured reward, and whoever reported it gets this percent of it
+
 impl StacksChainState {
-    fn instantiate_headers_db(conn: &mut DBConn, mainnet: bool, chain_id: u32, marf_path: &str) -> Result<(), Error> {
+    fn instantiate_headers_db(conn: &mut DBConn, mainnet: bool, chain_id: u32, marf_path: &str, layout: TrieStorageLayout) -> Result<(), Error> {
         let tx = conn.transaction().map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
         
         for cmd in STACKS_CHAIN_STATE_SQL {
             tx.execute(cmd, NO_PARAMS).map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
         }
 
+        for cmd in STACKS_CHAIN_STATE_MIGRATIONS {
+            tx.execute(cmd, NO_PARAMS).map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+
         tx.execute("INSERT INTO db_config (version,mainnet,chain_id) VALUES (?1,?2,?3)", &[&CHAINSTATE_VERSION, &(if mainnet { 1 } else { 0 }) as &dyn ToSql, &chain_id as &dyn ToSql])
             .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
 
-        let mut marf = StacksChainState::open_index(marf_path, None)?;
+        let mut marf = StacksChainState::open_index(marf_path, None, layout)?;
         let mut dbtx = StacksDBTx::new(tx, &mut marf, ());
         
         dbtx.instantiate_index().map_err(Error::DBError)?;
@@ -515,7 +584,7 @@ impl StacksChainState {
         Ok(())
     }
     
-    fn open_headers_db(mainnet: bool, chain_id: u32, headers_path: &str, index_path: &str) -> Result<DBConn, Error> {
+    fn open_headers_db(mainnet: bool, chain_id: u32, headers_path: &str, index_path: &str, layout: TrieStorageLayout) -> Result<DBConn, Error> {
         let mut create_flag = false;
         let open_flags =
             if fs::metadata(headers_path).is_err() {
@@ -532,7 +601,7 @@ impl StacksChainState {
 
         if create_flag {
             // instantiate!
-            StacksChainState::instantiate_headers_db(&mut conn, mainnet, chain_id, index_path)?;
+            StacksChainState::instantiate_headers_db(&mut conn, mainnet, chain_id, index_path, layout)?;
         }
         else {
             // sanity check
@@ -555,14 +624,28 @@ impl StacksChainState {
                 error!("Invalid chain ID: expected {}, got {}", chain_id, db_config.chain_id);
                 return Err(Error::InvalidChainstateDB);
             }
+
+            StacksChainState::migrate_headers_db(&mut conn)?;
         }
 
         Ok(conn)
     }
+
+    /// Bring an existing headers database up to the current schema
+    fn migrate_headers_db(conn: &mut DBConn) -> Result<(), Error> {
+        let tx = conn.transaction().map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+        for cmd in STACKS_CHAIN_STATE_MIGRATIONS {
+            tx.execute(cmd, NO_PARAMS).map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+
+        tx.commit().map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        Ok(())
+    }
     
-    pub fn open_index(marf_path: &str, miner_tip: Option<&BlockHeaderHash>) -> Result<MARF, Error> {
+    pub fn open_index(marf_path: &str, miner_tip: Option<&BlockHeaderHash>, layout: TrieStorageLayout) -> Result<MARF, Error> {
         test_debug!("Open MARF index at {}, set miner tip = {:?}", marf_path, miner_tip);
-        let marf = MARF::from_path(marf_path, miner_tip).map_err(|e| Error::DBError(db_error::IndexError(e)))?;
+        let marf = MARF::from_path(marf_path, miner_tip, layout).map_err(|e| Error::DBError(db_error::IndexError(e)))?;
         Ok(marf)
     }
    
@@ -706,28 +789,36 @@ impl StacksChainState {
         Ok(())
     }
 
+    /// Name of the directory under the chainstate path that holds a given chain's state
+    pub fn chain_dir_name(mainnet: bool, chain_id: u32) -> String {
+        if mainnet {
+            format!("chain-{}-mainnet", &to_hex(&chain_id.to_le_bytes()))
+        }
+        else {
+            format!("chain-{}-testnet", &to_hex(&chain_id.to_le_bytes()))
+        }
+    }
+
     pub fn open(mainnet: bool, chain_id: u32, path_str: &str) -> Result<StacksChainState, Error> {
-        StacksChainState::open_and_exec(mainnet, chain_id, path_str, None, |_| {})
+        StacksChainState::open_with_layout(mainnet, chain_id, path_str, TrieStorageLayout::FilePerBlock)
+    }
+
+    /// Open the chain state, creating any new MARFs with the given storage layout.  MARFs that
+    /// already exist keep the layout they were created with.
+    pub fn open_with_layout(mainnet: bool, chain_id: u32, path_str: &str, layout: TrieStorageLayout) -> Result<StacksChainState, Error> {
+        StacksChainState::open_and_exec(mainnet, chain_id, path_str, layout, None, |_| {})
     }
 
     pub fn open_testnet<F>(chain_id: u32, path_str: &str, initial_balances: Option<Vec<(PrincipalData, u64)>>, in_boot_block: F) -> Result<StacksChainState, Error> 
     where F: FnOnce(&mut ClarityTx) -> () {        
-        StacksChainState::open_and_exec(false, chain_id, path_str, initial_balances, in_boot_block)
+        StacksChainState::open_and_exec(false, chain_id, path_str, TrieStorageLayout::FilePerBlock, initial_balances, in_boot_block)
     }
 
-    pub fn open_and_exec<F>(mainnet: bool, chain_id: u32, path_str: &str, initial_balances: Option<Vec<(PrincipalData, u64)>>, in_boot_block: F) -> Result<StacksChainState, Error> 
+    pub fn open_and_exec<F>(mainnet: bool, chain_id: u32, path_str: &str, layout: TrieStorageLayout, initial_balances: Option<Vec<(PrincipalData, u64)>>, in_boot_block: F) -> Result<StacksChainState, Error> 
     where F: FnOnce(&mut ClarityTx) -> () {
         let mut path = PathBuf::from(path_str);
 
-        let chain_id_str = 
-            if mainnet {
-                format!("chain-{}-mainnet", &to_hex(&chain_id.to_le_bytes()))
-            }
-            else {
-                format!("chain-{}-testnet", &to_hex(&chain_id.to_le_bytes()))
-            };
-
-        path.push(chain_id_str);
+        path.push(StacksChainState::chain_dir_name(mainnet, chain_id));
         StacksChainState::mkdirs(&path)?;
 
         let mut blocks_path = path.clone();
@@ -766,14 +857,17 @@ impl StacksChainState {
             Err(_) => false
         };
 
-        let headers_db = StacksChainState::open_headers_db(mainnet, chain_id, &headers_db_path, &header_index_root)?;
+        let headers_db = StacksChainState::open_headers_db(mainnet, chain_id, &headers_db_path, &header_index_root, layout)?;
         let blocks_db = StacksChainState::open_blocks_db(&blocks_db_path)?;
 
-        let headers_state_index = StacksChainState::open_index(&header_index_root, None)?;
+        let headers_state_index = StacksChainState::open_index(&header_index_root, None, layout)?;
 
-        let vm_state = MarfedKV::open(&clarity_state_index_root, Some(&StacksBlockHeader::make_index_block_hash(&MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH)))
+        let vm_state = MarfedKV::open(&clarity_state_index_root, Some(&StacksBlockHeader::make_index_block_hash(&MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH)), layout)
             .map_err(|e| Error::ClarityError(e.into()))?;
 
+        // an existing MARF keeps whatever layout it was created with
+        let index_layout = TrieStorageLayout::detect(&clarity_state_index_marf).unwrap_or(layout);
+
         let clarity_state = ClarityInstance::new(vm_state);
         
         let mut chainstate = StacksChainState {
@@ -784,7 +878,9 @@ impl StacksChainState {
             blocks_db: blocks_db,
             headers_state_index: headers_state_index,
             blocks_path: blocks_path_root,
-            clarity_state_index_path: clarity_state_index_marf
+            clarity_state_index_path: clarity_state_index_marf,
+            index_layout: index_layout,
+            archive_mode: false
         };
 
         if !index_exists {
@@ -1034,5 +1130,24 @@ pub mod test {
             assert!(contract_res.is_some());
         }
     }
-}
 
+    #[test]
+    fn test_instantiate_chainstate_packed() {
+        let path = chainstate_path("instantiate-chainstate-packed");
+        if fs::metadata(&path).is_ok() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+
+        let chainstate = StacksChainState::open_with_layout(false, 0x80000000, &path, TrieStorageLayout::Packed).unwrap();
+        assert_eq!(chainstate.index_layout, TrieStorageLayout::Packed);
+        drop(chainstate);
+
+        // the layout sticks, whatever is asked for on reopen
+        let mut chainstate = StacksChainState::open(false, 0x80000000, &path).unwrap();
+        assert_eq!(chainstate.index_layout, TrieStorageLayout::Packed);
+
+        let mut conn = chainstate.block_begin(&FIRST_BURNCHAIN_BLOCK_HASH, &FIRST_STACKS_BLOCK_HASH, &MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH);
+        assert_eq!(conn.get_index_layout(), TrieStorageLayout::Packed);
+        conn.rollback_block();
+    }
+}
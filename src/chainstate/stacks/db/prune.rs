@@ -0,0 +1,603 @@
+/*
+ copyright: (c) 2013-2019 by Blockstack PBC, a public benefit corporation.
+
+ This file is part of Blockstack.
+
+ Blockstack is free software. You may redistribute or modify
+ it under the terms of the GNU General Public License as published by
+ the Free Software Foundation, either version 3 of the License or
+ (at your option) any later version.
+
+ Blockstack is distributed in the hope that it will be useful,
+ but WITHOUT ANY WARRANTY, including without the implied warranty of
+ MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ GNU General Public License for more details.
+
+ You should have received a copy of the GNU General Public License
+ along with Blockstack. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/// Pruning of dead Stacks forks.
+///
+/// Once the canonical Stacks chain tip is more than a given finality depth above a block, any
+/// block that forks off of the canonical chain at or below that height can never become canonical.
+/// Pruning deletes such blocks -- their staging rows, chunk store files, microblock streams,
+/// headers, MARF tries, and Clarity contract metadata -- along with orphaned (invalid) staging
+/// blocks and microblocks at or below the same height.
+///
+/// Every block on the canonical fork is kept, so `at-block` queries and MARF lookups on the
+/// canonical fork are unaffected.  Blocks on forks that branch off above the finality height are
+/// kept too, since they may still overtake the canonical tip.
+///
+/// Pruned blocks are remembered, so historical state queries against them can be told apart
+/// from queries against blocks that never existed.  Pruning is refused in archive mode.
+
+use std::fs;
+use std::io;
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::NO_PARAMS;
+use rusqlite::types::ToSql;
+
+use burnchains::BurnchainHeaderHash;
+
+use chainstate::stacks::Error;
+use chainstate::stacks::*;
+use chainstate::stacks::db::*;
+use chainstate::stacks::db::blocks::StagingMicroblock;
+use chainstate::stacks::index::Error as marf_error;
+
+use util::db::Error as db_error;
+use util::db::{
+    FromColumn,
+    query_rows,
+    query_row_columns
+};
+
+/// What a prune pass deleted
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PruneStats {
+    /// anchored blocks removed from the staging DB and chunk store
+    pub blocks: u64,
+    /// microblocks removed from the staging DB
+    pub microblocks: u64,
+    /// processed blocks whose headers and MARF tries were removed
+    pub tries: u64,
+    /// Clarity contract metadata rows removed
+    pub metadata: u64,
+}
+
+/// A block known to either the staging DB or the headers DB
+struct BlockNode {
+    burn_header_hash: BurnchainHeaderHash,
+    block_hash: BlockHeaderHash,
+    parent: Option<BlockHeaderHash>,
+    height: Option<u64>,
+    processed: bool,
+    orphaned: bool,
+    staged: bool,
+}
+
+impl StacksChainState {
+    /// Load every block the chainstate knows about, keyed by index block hash.  Processed blocks'
+    /// parents come from their miner payment rows; staged blocks' parents from their staging rows.
+    fn load_block_nodes(&self) -> Result<HashMap<BlockHeaderHash, BlockNode>, Error> {
+        let mut nodes = HashMap::new();
+
+        let headers = query_rows::<StacksHeaderInfo, _>(&self.headers_db, &"SELECT * FROM block_headers".to_string(), NO_PARAMS)
+            .map_err(Error::DBError)?;
+
+        for header in headers.into_iter() {
+            nodes.insert(header.index_block_hash(), BlockNode {
+                block_hash: header.anchored_header.block_hash(),
+                burn_header_hash: header.burn_header_hash,
+                parent: None,
+                height: Some(header.block_height),
+                processed: true,
+                orphaned: false,
+                staged: false
+            });
+        }
+
+        {
+            let mut stmt = self.headers_db.prepare("SELECT index_block_hash, parent_burn_header_hash, parent_block_hash FROM payments WHERE miner = 1")
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            let mut rows = stmt.query(NO_PARAMS)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+            while let Some(row_res) = rows.next() {
+                let row = row_res.map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+                let index_block_hash = BlockHeaderHash::from_column(&row, "index_block_hash").map_err(Error::DBError)?;
+                let parent_burn_header_hash = BurnchainHeaderHash::from_column(&row, "parent_burn_header_hash").map_err(Error::DBError)?;
+                let parent_block_hash = BlockHeaderHash::from_column(&row, "parent_block_hash").map_err(Error::DBError)?;
+
+                if let Some(node) = nodes.get_mut(&index_block_hash) {
+                    node.parent = Some(StacksBlockHeader::make_index_block_hash(&parent_burn_header_hash, &parent_block_hash));
+                }
+            }
+        }
+
+        {
+            let mut stmt = self.blocks_db.prepare("SELECT burn_header_hash, anchored_block_hash, parent_burn_header_hash, parent_anchored_block_hash, orphaned FROM staging_blocks")
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            let mut rows = stmt.query(NO_PARAMS)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+            while let Some(row_res) = rows.next() {
+                let row = row_res.map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+                let burn_header_hash = BurnchainHeaderHash::from_column(&row, "burn_header_hash").map_err(Error::DBError)?;
+                let block_hash = BlockHeaderHash::from_column(&row, "anchored_block_hash").map_err(Error::DBError)?;
+                let parent_burn_header_hash = BurnchainHeaderHash::from_column(&row, "parent_burn_header_hash").map_err(Error::DBError)?;
+                let parent_block_hash = BlockHeaderHash::from_column(&row, "parent_anchored_block_hash").map_err(Error::DBError)?;
+                let orphaned_i64 : i64 = row.get("orphaned");
+
+                let index_block_hash = StacksBlockHeader::make_index_block_hash(&burn_header_hash, &block_hash);
+                let parent = StacksBlockHeader::make_index_block_hash(&parent_burn_header_hash, &parent_block_hash);
+
+                let node = nodes.entry(index_block_hash).or_insert(BlockNode {
+                    burn_header_hash: burn_header_hash,
+                    block_hash: block_hash,
+                    parent: None,
+                    height: None,
+                    processed: false,
+                    orphaned: false,
+                    staged: false
+                });
+
+                node.staged = true;
+                node.orphaned = orphaned_i64 != 0;
+                if node.parent.is_none() {
+                    node.parent = Some(parent);
+                }
+            }
+        }
+
+        Ok(nodes)
+    }
+
+    /// Walk up from a block to the first block whose value is already known, and fill in the value
+    /// for every block along the way.  `base` gives the value of a block with no known parent;
+    /// `step` derives a block's value from its parent's.
+    fn fill_from_ancestors<T, B, S>(nodes: &HashMap<BlockHeaderHash, BlockNode>, values: &mut HashMap<BlockHeaderHash, Option<T>>, start: &BlockHeaderHash, base: B, step: S)
+        where T: Clone,
+              B: Fn(&BlockNode) -> Option<T>,
+              S: Fn(&BlockNode, &T) -> Option<T>
+    {
+        let mut path = vec![];
+        let mut cur = start.clone();
+        let mut value = loop {
+            if let Some(value) = values.get(&cur) {
+                break value.clone();
+            }
+            let node = nodes.get(&cur).expect("BUG: walked off of the block graph");
+            match node.parent {
+                Some(ref parent) if nodes.contains_key(parent) && !path.contains(parent) => {
+                    path.push(cur.clone());
+                    cur = parent.clone();
+                },
+                _ => {
+                    let value = base(node);
+                    values.insert(cur.clone(), value.clone());
+                    break value;
+                }
+            }
+        };
+
+        for index_block_hash in path.iter().rev() {
+            let node = nodes.get(index_block_hash).expect("BUG: walked off of the block graph");
+            value = match value {
+                Some(ref parent_value) => step(node, parent_value),
+                None => None
+            };
+            values.insert(index_block_hash.clone(), value.clone());
+        }
+    }
+
+    /// Find the blocks that can be pruned, given the canonical tip and the finality depth
+    fn find_prunable_blocks(nodes: &HashMap<BlockHeaderHash, BlockNode>, tip_index_block_hash: &BlockHeaderHash, finality_height: u64) -> HashSet<BlockHeaderHash> {
+        // the canonical fork
+        let mut canonical = HashSet::new();
+        let mut cur = tip_index_block_hash.clone();
+        while let Some(node) = nodes.get(&cur) {
+            canonical.insert(cur.clone());
+            match node.parent {
+                Some(ref parent) if !canonical.contains(parent) => {
+                    cur = parent.clone();
+                },
+                _ => {
+                    break;
+                }
+            }
+        }
+
+        // heights of staged blocks that haven't been processed
+        let mut heights : HashMap<BlockHeaderHash, Option<u64>> = HashMap::new();
+        for (index_block_hash, node) in nodes.iter() {
+            if let Some(height) = node.height {
+                heights.insert(index_block_hash.clone(), Some(height));
+            }
+        }
+        for index_block_hash in nodes.keys() {
+            StacksChainState::fill_from_ancestors(nodes, &mut heights, index_block_hash,
+                                                  |node| node.height,
+                                                  |_node, parent_height| Some(parent_height + 1));
+        }
+
+        // height of the highest canonical ancestor of each block.  A block that forks off below
+        // the oldest known block forks off of the boot block.
+        let mut fork_heights : HashMap<BlockHeaderHash, Option<u64>> = HashMap::new();
+        for index_block_hash in canonical.iter() {
+            fork_heights.insert(index_block_hash.clone(), heights.get(index_block_hash).cloned().unwrap_or(None));
+        }
+        for index_block_hash in nodes.keys() {
+            let height = heights.get(index_block_hash).cloned().unwrap_or(None);
+            if height.is_none() {
+                continue;
+            }
+            StacksChainState::fill_from_ancestors(nodes, &mut fork_heights, index_block_hash,
+                                                  |node| node.height.and_then(|h| h.checked_sub(1)),
+                                                  |_node, fork_height| Some(*fork_height));
+        }
+
+        let mut prunable = HashSet::new();
+        for (index_block_hash, node) in nodes.iter() {
+            if canonical.contains(index_block_hash) {
+                continue;
+            }
+
+            let height = heights.get(index_block_hash).cloned().unwrap_or(None);
+            let fork_height = fork_heights.get(index_block_hash).cloned().unwrap_or(None);
+
+            // forked off of the canonical chain below the finality height
+            let dead = match fork_height {
+                Some(fork_height) => fork_height < finality_height,
+                None => false
+            };
+
+            // invalid, and old enough that no one will ask for it again
+            let stale = node.orphaned && match height {
+                Some(height) => height <= finality_height,
+                None => false
+            };
+
+            if dead || stale {
+                prunable.insert(index_block_hash.clone());
+            }
+        }
+
+        prunable
+    }
+
+    /// Delete the staging state of anchored blocks that will never be processed or built on
+    /// again, as well as the microblock streams built on them.  Returns the (burn header hash,
+    /// microblock hash) pairs of the streams, so their chunk store files can be removed.
+    fn delete_staging_blocks<'a>(tx: &mut BlocksDBTx<'a>, blocks: &Vec<(BurnchainHeaderHash, BlockHeaderHash)>, stats: &mut PruneStats) -> Result<Vec<(BurnchainHeaderHash, BlockHeaderHash)>, Error> {
+        let mut streams = vec![];
+        for (burn_header_hash, block_hash) in blocks.iter() {
+            let args : &[&dyn ToSql] = &[block_hash, burn_header_hash];
+
+            let stream_head_sql = "SELECT microblock_hash FROM staging_microblocks WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2 AND sequence = 0".to_string();
+            let stream_heads = query_row_columns::<BlockHeaderHash, _>(tx, &stream_head_sql, args, "microblock_hash")
+                .map_err(Error::DBError)?;
+            for stream_head in stream_heads.into_iter() {
+                streams.push((burn_header_hash.clone(), stream_head));
+            }
+
+            let microblocks_sql = "SELECT microblock_hash FROM staging_microblocks WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2".to_string();
+            let microblock_hashes = query_row_columns::<BlockHeaderHash, _>(tx, &microblocks_sql, args, "microblock_hash")
+                .map_err(Error::DBError)?;
+            for microblock_hash in microblock_hashes.iter() {
+                tx.execute("DELETE FROM staging_microblocks_data WHERE block_hash = ?1", &[microblock_hash])
+                    .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            }
+            stats.microblocks += microblock_hashes.len() as u64;
+
+            for sql in ["DELETE FROM staging_microblocks WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2",
+                        "DELETE FROM staging_user_burn_support WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2",
+                        "DELETE FROM staging_burnchain_stx_ops WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2",
+                        "DELETE FROM staging_blocks WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2"].iter() {
+                tx.execute(sql, args)
+                    .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            }
+        }
+        Ok(streams)
+    }
+
+    /// Delete orphaned microblocks built on blocks at or below the finality height
+    fn delete_orphaned_microblocks<'a>(tx: &mut BlocksDBTx<'a>, nodes: &HashMap<BlockHeaderHash, BlockNode>, finality_height: u64, stats: &mut PruneStats) -> Result<(), Error> {
+        let orphaned_sql = "SELECT * FROM staging_microblocks WHERE orphaned = 1".to_string();
+        let orphaned = query_rows::<StagingMicroblock, _>(tx, &orphaned_sql, NO_PARAMS)
+            .map_err(Error::DBError)?;
+
+        for microblock in orphaned.iter() {
+            let index_block_hash = StacksBlockHeader::make_index_block_hash(&microblock.burn_header_hash, &microblock.anchored_block_hash);
+            let final_parent = match nodes.get(&index_block_hash).and_then(|node| node.height) {
+                Some(height) => height <= finality_height,
+                None => false
+            };
+            if !final_parent {
+                continue;
+            }
+
+            tx.execute("DELETE FROM staging_microblocks_data WHERE block_hash = ?1", &[&microblock.microblock_hash])
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+            let args : &[&dyn ToSql] = &[&microblock.anchored_block_hash, &microblock.burn_header_hash, &microblock.microblock_hash];
+            tx.execute("DELETE FROM staging_microblocks WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2 AND microblock_hash = ?3", args)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+            stats.microblocks += 1;
+        }
+        Ok(())
+    }
+
+    /// Delete the header state of processed blocks on dead forks
+    fn delete_headers<'a>(tx: &mut StacksDBTx<'a>, blocks: &Vec<(BlockHeaderHash, BurnchainHeaderHash, BlockHeaderHash, u64)>) -> Result<(), Error> {
+        for (index_block_hash, burn_header_hash, block_hash, block_height) in blocks.iter() {
+            let pruned_args : &[&dyn ToSql] = &[index_block_hash, &(*block_height as i64)];
+            tx.execute("INSERT OR REPLACE INTO pruned_blocks (index_block_hash, block_height) VALUES (?1, ?2)", pruned_args)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+            let index_args : &[&dyn ToSql] = &[index_block_hash];
+            tx.execute("DELETE FROM block_headers WHERE index_block_hash = ?1", index_args)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            tx.execute("DELETE FROM payments WHERE index_block_hash = ?1", index_args)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+
+            let args : &[&dyn ToSql] = &[block_hash, burn_header_hash];
+            tx.execute("DELETE FROM user_supporters WHERE block_hash = ?1 AND burn_header_hash = ?2", args)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+            tx.execute("DELETE FROM microblock_headers WHERE parent_block_hash = ?1 AND parent_burn_header_hash = ?2", args)
+                .map_err(|e| Error::DBError(db_error::SqliteError(e)))?;
+        }
+        Ok(())
+    }
+
+    /// Remove a file from the chunk store, if it's there
+    fn remove_chunk(blocks_path: &String, burn_header_hash: &BurnchainHeaderHash, block_hash: &BlockHeaderHash) -> Result<(), Error> {
+        let path = StacksChainState::get_block_path(blocks_path, burn_header_hash, block_hash)?;
+        match fs::remove_file(&path) {
+            Ok(_) => {
+                debug!("Removed {}", &path);
+                Ok(())
+            },
+            Err(e) => {
+                if e.kind() == io::ErrorKind::NotFound {
+                    Ok(())
+                }
+                else {
+                    Err(Error::WriteError(e))
+                }
+            }
+        }
+    }
+
+    /// Prune blocks that can never become canonical, given the canonical Stacks chain tip.  Blocks
+    /// on forks that branch off of the canonical chain more than `finality_depth` blocks below the
+    /// tip are deleted, as are orphaned blocks and microblocks at or below that height.
+    ///
+    /// MARF tries are dropped before the database rows that refer to them, so if pruning is
+    /// interrupted, running it again finishes the job.
+    pub fn prune_dead_forks(&mut self, tip_burn_header_hash: &BurnchainHeaderHash, tip_block_hash: &BlockHeaderHash, finality_depth: u64) -> Result<PruneStats, Error> {
+        if self.archive_mode {
+            warn!("Will not prune dead forks in archive mode");
+            return Err(Error::ArchiveModeError);
+        }
+
+        let tip = match StacksChainState::get_anchored_block_header_info(&self.headers_db, tip_burn_header_hash, tip_block_hash)? {
+            Some(tip) => tip,
+            None => {
+                error!("No such block {}/{}", tip_burn_header_hash, tip_block_hash);
+                return Err(Error::NoSuchBlockError);
+            }
+        };
+
+        let mut stats = PruneStats::default();
+        let finality_height = match tip.block_height.checked_sub(finality_depth) {
+            Some(height) => height,
+            None => {
+                return Ok(stats);
+            }
+        };
+
+        let nodes = self.load_block_nodes()?;
+        let prunable = StacksChainState::find_prunable_blocks(&nodes, &tip.index_block_hash(), finality_height);
+
+        let mut processed = vec![];
+        let mut staged = vec![];
+        for index_block_hash in prunable.iter() {
+            let node = nodes.get(index_block_hash).expect("BUG: prunable block is not in the block graph");
+            if node.processed {
+                let block_height = node.height.expect("BUG: processed block has no height");
+                processed.push((index_block_hash.clone(), node.burn_header_hash.clone(), node.block_hash.clone(), block_height));
+            }
+            if node.staged || node.processed {
+                staged.push((node.burn_header_hash.clone(), node.block_hash.clone()));
+            }
+        }
+
+        debug!("Prune {} processed and {} staged blocks below height {} (tip {}/{} at {})",
+               processed.len(), staged.len(), finality_height, tip_burn_header_hash, tip_block_hash, tip.block_height);
+
+        // tries first -- nothing that survives descends from them
+        let processed_index_block_hashes : Vec<BlockHeaderHash> = processed.iter().map(|(index_block_hash, _, _, _)| index_block_hash.clone()).collect();
+        for index_block_hash in processed_index_block_hashes.iter() {
+            match self.headers_state_index.delete_block(index_block_hash) {
+                Ok(_) | Err(marf_error::NotFoundError) => {},
+                Err(e) => {
+                    return Err(Error::MARFError(e));
+                }
+            }
+        }
+        stats.metadata = self.clarity_state.drop_blocks(&processed_index_block_hashes)
+            .map_err(|e| Error::ClarityError(clarity_error::Interpreter(e)))? as u64;
+        stats.tries = processed.len() as u64;
+
+        {
+            let mut headers_tx = self.headers_tx_begin()?;
+            StacksChainState::delete_headers(&mut headers_tx, &processed)?;
+            headers_tx.commit().map_err(Error::DBError)?;
+        }
+
+        let streams = {
+            let mut blocks_tx = self.blocks_tx_begin()?;
+            let streams = StacksChainState::delete_staging_blocks(&mut blocks_tx, &staged, &mut stats)?;
+            StacksChainState::delete_orphaned_microblocks(&mut blocks_tx, &nodes, finality_height, &mut stats)?;
+            blocks_tx.commit().map_err(Error::DBError)?;
+            streams
+        };
+
+        for (burn_header_hash, block_hash) in staged.iter() {
+            StacksChainState::remove_chunk(&self.blocks_path, burn_header_hash, block_hash)?;
+        }
+        for (burn_header_hash, microblock_hash) in streams.iter() {
+            StacksChainState::remove_chunk(&self.blocks_path, burn_header_hash, microblock_hash)?;
+        }
+        stats.blocks = staged.len() as u64;
+
+        debug!("Pruned {:?}", &stats);
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chainstate::stacks::db::fsck::*;
+    use chainstate::stacks::db::snapshot::test::*;
+
+    use rusqlite::Connection;
+
+    /// Is there a staging row for this block, processed or not?
+    fn has_staging_row(blocks_conn: &DBConn, burn_header_hash: &BurnchainHeaderHash, block_hash: &BlockHeaderHash) -> bool {
+        let args : &[&dyn ToSql] = &[block_hash, burn_header_hash];
+        let count : i64 = blocks_conn.query_row("SELECT COUNT(*) FROM staging_blocks WHERE anchored_block_hash = ?1 AND burn_header_hash = ?2", args, |row| row.get(0)).unwrap();
+        count > 0
+    }
+
+    #[test]
+    fn prune_dead_forks() {
+        let (chainstate_path, burndb_path, tip) = make_snapshot_source("prune-dead-forks", 6);
+        let mut chainstate = StacksChainState::open(false, 0x80000000, &chainstate_path).unwrap();
+
+        let canonical = StacksChainState::get_stacks_block_headers(&mut chainstate.headers_tx_begin().unwrap(), tip.block_height + 1, &tip.burn_header_hash, &tip.anchored_header.block_hash())
+            .unwrap()
+            .into_iter()
+            .filter_map(|h| h)
+            .filter(|h| !h.is_genesis() && h.anchored_header.parent_block != BOOT_BLOCK_HASH)
+            .chain(vec![tip.clone()].into_iter())
+            .collect::<Vec<StacksHeaderInfo>>();
+
+        // canonical heights are 2..=7
+        assert_eq!(tip.block_height, 7);
+        let at_height = |h: u64| canonical.iter().find(|c| c.block_height == h).unwrap().clone();
+
+        // a dead fork off of height 3, with a staged-but-unprocessed child
+        let dead_1 = append_block_at(&mut chainstate, Some(&at_height(3)), &BurnchainHeaderHash([0xa1; 32]), 200);
+        let dead_2 = append_block_at(&mut chainstate, Some(&dead_1), &BurnchainHeaderHash([0xa2; 32]), 201);
+
+        // a live fork off of height 6
+        let live = append_block_at(&mut chainstate, Some(&at_height(6)), &BurnchainHeaderHash([0xb1; 32]), 300);
+
+        let chain_dir = format!("{}/{}", &chainstate_path, StacksChainState::chain_dir_name(false, 0x80000000));
+        let blocks_path = chainstate.blocks_path.clone();
+        let insert_staging = |burn_header_hash: &BurnchainHeaderHash, block_hash: &BlockHeaderHash, parent: &StacksHeaderInfo, processed: bool, orphaned: bool| {
+            let conn = Connection::open(&format!("{}/blocks/staging.db", &chain_dir)).unwrap();
+            let args : &[&dyn ToSql] = &[block_hash, &parent.anchored_header.block_hash(), burn_header_hash, &parent.burn_header_hash, &BlockHeaderHash([0u8; 32]),
+                                         &Hash160([0u8; 20]), &(if orphaned { 1 } else { 0 }), &(if processed { 1 } else { 0 }),
+                                         &StacksBlockHeader::make_index_block_hash(burn_header_hash, block_hash)];
+            conn.execute("INSERT INTO staging_blocks \
+                         (anchored_block_hash, parent_anchored_block_hash, burn_header_hash, burn_header_timestamp, parent_burn_header_hash, parent_microblock_hash, parent_microblock_seq, \
+                          microblock_pubkey_hash, attacheable, orphaned, processed, commit_burn, sortition_burn, index_block_hash) \
+                         VALUES (?1, ?2, ?3, 0, ?4, ?5, 0, ?6, 1, ?7, ?8, 0, 0, ?9)", args).unwrap();
+            StacksChainState::store_empty_block(&blocks_path, burn_header_hash, block_hash).unwrap();
+        };
+
+        insert_staging(&dead_2.burn_header_hash, &dead_2.anchored_header.block_hash(), &dead_1, true, false);
+        let dead_staged_burn = BurnchainHeaderHash([0xa3; 32]);
+        let dead_staged_block = BlockHeaderHash([0xa3; 32]);
+        insert_staging(&dead_staged_burn, &dead_staged_block, &dead_2, false, false);
+
+        // an invalid block at height 4, and another at height 7
+        let old_orphan_burn = BurnchainHeaderHash([0xc1; 32]);
+        let old_orphan_block = BlockHeaderHash([0xc1; 32]);
+        insert_staging(&old_orphan_burn, &old_orphan_block, &at_height(3), true, true);
+
+        let new_orphan_burn = BurnchainHeaderHash([0xc2; 32]);
+        let new_orphan_block = BlockHeaderHash([0xc2; 32]);
+        insert_staging(&new_orphan_burn, &new_orphan_block, &at_height(6), true, true);
+
+        // contract metadata stored at the dead fork, and set aside by a miner
+        {
+            let conn = Connection::open(&format!("{}/vm/clarity/data.sqlite", &chain_dir)).unwrap();
+            conn.execute("INSERT INTO metadata_table (key, blockhash, value) VALUES ('clr-meta::S1G2081040G2081040G2081040G208105NK8PE5.dead::vm-metadata::9::contract', ?1, 'x')",
+                         &[&dead_1.index_block_hash()]).unwrap();
+            conn.execute("INSERT INTO metadata_table (key, blockhash, value) VALUES ('clr-meta::S1G2081040G2081040G2081040G208105NK8PE5.mined::vm-metadata::9::contract', ?1, 'y')",
+                         &[&format!("{}.mined", &dead_2.index_block_hash())]).unwrap();
+        }
+
+        // nothing is final at depth 10
+        let stats = chainstate.prune_dead_forks(&tip.burn_header_hash, &tip.anchored_header.block_hash(), 10).unwrap();
+        assert_eq!(stats, PruneStats::default());
+
+        // height 5 is final at depth 2
+        let stats = chainstate.prune_dead_forks(&tip.burn_header_hash, &tip.anchored_header.block_hash(), 2).unwrap();
+        assert_eq!(stats.tries, 2);
+        assert_eq!(stats.blocks, 4);
+        assert_eq!(stats.metadata, 2);
+
+        for dead in [&dead_1, &dead_2].iter() {
+            assert!(StacksChainState::get_anchored_block_header_info(&chainstate.headers_db, &dead.burn_header_hash, &dead.anchored_header.block_hash()).unwrap().is_none());
+            assert!(chainstate.headers_state_index.get_root_hash_at(&dead.index_block_hash()).is_err());
+        }
+        for (burn_header_hash, block_hash) in [(&dead_2.burn_header_hash, &dead_2.anchored_header.block_hash()), (&dead_staged_burn, &dead_staged_block), (&old_orphan_burn, &old_orphan_block)].iter() {
+            assert!(!has_staging_row(&chainstate.blocks_db, burn_header_hash, block_hash));
+            let path = StacksChainState::get_block_path(&chainstate.blocks_path, burn_header_hash, block_hash).unwrap();
+            assert!(fs::metadata(&path).is_err());
+        }
+
+        // the canonical fork, the live fork, and the recent orphan survive
+        for header in canonical.iter().chain([live.clone()].iter()) {
+            assert!(StacksChainState::get_anchored_block_header_info(&chainstate.headers_db, &header.burn_header_hash, &header.anchored_header.block_hash()).unwrap().is_some());
+            assert_eq!(chainstate.headers_state_index.get_root_hash_at(&header.index_block_hash()).unwrap(), header.index_root);
+        }
+        assert!(has_staging_row(&chainstate.blocks_db, &new_orphan_burn, &new_orphan_block));
+
+        // a second pass finds nothing more to do
+        let stats = chainstate.prune_dead_forks(&tip.burn_header_hash, &tip.anchored_header.block_hash(), 2).unwrap();
+        assert_eq!(stats.tries, 0);
+        assert_eq!(stats.blocks, 0);
+
+        // the remaining MARF tries still match their headers
+        drop(chainstate);
+        let issues = StacksChainState::check_chainstate(false, 0x80000000, &chainstate_path, &burndb_path).unwrap();
+        let header_issues : Vec<&ChainstateInconsistency> = issues.iter().filter(|i| i.store == ChainstateStore::Headers).collect();
+        assert_eq!(header_issues.len(), 0);
+    }
+
+    #[test]
+    fn prune_dead_forks_on_migrated_chainstate() {
+        let (chainstate_path, _burndb_path, tip) = make_snapshot_source("prune-dead-forks-migrated", 5);
+
+        // make it look like a chain state from before pruned blocks were tracked
+        {
+            let chainstate = StacksChainState::open(false, 0x80000000, &chainstate_path).unwrap();
+            chainstate.headers_db.execute("DROP TABLE pruned_blocks", NO_PARAMS).unwrap();
+        }
+
+        let mut chainstate = StacksChainState::open(false, 0x80000000, &chainstate_path).unwrap();
+        let fork_base = StacksChainState::get_stacks_block_headers(&mut chainstate.headers_tx_begin().unwrap(), tip.block_height + 1, &tip.burn_header_hash, &tip.anchored_header.block_hash())
+            .unwrap()
+            .into_iter()
+            .filter_map(|h| h)
+            .find(|h| h.block_height == 3)
+            .unwrap();
+
+        let dead = append_block_at(&mut chainstate, Some(&fork_base), &BurnchainHeaderHash([0xa1; 32]), 200);
+
+        let stats = chainstate.prune_dead_forks(&tip.burn_header_hash, &tip.anchored_header.block_hash(), 2).unwrap();
+        assert_eq!(stats.tries, 1);
+        assert!(StacksChainState::is_pruned_block(&chainstate.headers_db, &dead.index_block_hash()).unwrap());
+    }
+}
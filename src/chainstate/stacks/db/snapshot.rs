@@ -0,0 +1,793 @@
+/*
+ copyright: (c) 2013-2019 by Blockstack PBC, a public benefit corporation.
+
+ This file is part of Blockstack.
+
+ Blockstack is free software. You may redistribute or modify
+ it under the terms of the GNU General Public License as published by
+ the Free Software Foundation, either version 3 of the License or
+ (at your option) any later version.
+
+ Blockstack is distributed in the hope that it will be useful,
+ but WITHOUT ANY WARRANTY, including without the implied warranty of
+ MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ GNU General Public License for more details.
+
+ You should have received a copy of the GNU General Public License
+ along with Blockstack. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/// Chainstate snapshots.
+///
+/// A snapshot archive packages everything a node needs to resume from a given Stacks chain tip
+/// without replaying the chain: the chainstate directory (staging blocks, the headers DB and its
+/// MARF, and the Clarity MARF and side-store) and the burn database.  The archive is a single
+/// file laid out as:
+///
+///    magic (4 bytes) | version (u32) | manifest length (u32) | manifest (JSON) | file data...
+///
+/// where the file data is every file listed in the manifest, concatenated in manifest order.
+/// Integers are big-endian.  Each manifest entry carries the file's size and sha512/256 hash.
+///
+/// Snapshots must be taken while the node is stopped.
+
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::fmt;
+use std::error;
+use std::path::{Path, PathBuf};
+
+use rusqlite::NO_PARAMS;
+
+use sha2::{Sha512Trunc256, Digest};
+
+use serde_json;
+
+use burnchains::BurnchainHeaderHash;
+
+use chainstate::burn::BlockSnapshot;
+use chainstate::burn::db::burndb::BurnDB;
+
+use chainstate::stacks::Error as chainstate_error;
+use chainstate::stacks::*;
+use chainstate::stacks::db::*;
+use chainstate::stacks::index::TrieHash;
+
+use core::BOOT_BLOCK_HASH;
+
+use util::db::Error as db_error;
+use util::db::query_rows;
+use util::hash::Sha512Trunc256Sum;
+
+pub const SNAPSHOT_MAGIC : &'static [u8; 4] = b"STSN";
+pub const SNAPSHOT_VERSION : u32 = 1;
+
+/// Upper bound on the size of an encoded manifest
+pub const SNAPSHOT_MANIFEST_MAX_LEN : u32 = 64 * 1024 * 1024;
+
+/// Archive path prefixes for the two stores in a snapshot
+const CHAINSTATE_PREFIX : &'static str = "chainstate";
+const BURNDB_PREFIX : &'static str = "burndb";
+
+#[derive(Debug)]
+pub enum Error {
+    /// The archive is truncated, has a bad header, or has a malformed manifest
+    InvalidArchive(String),
+    /// A file's contents do not match its manifest entry
+    FileMismatch(String),
+    /// A MARF root hash does not match the root hash committed to by a header or snapshot
+    RootHashMismatch(String),
+    /// The snapshot tip is missing, or disagrees with the manifest
+    InvalidTip(String),
+    /// Refusing to overwrite something
+    ExistsError(String),
+    ChainstateError(chainstate_error),
+    DBError(db_error),
+    IOError(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidArchive(ref s) => write!(f, "Invalid snapshot archive: {}", s),
+            Error::FileMismatch(ref s) => write!(f, "Snapshot file mismatch: {}", s),
+            Error::RootHashMismatch(ref s) => write!(f, "Snapshot root hash mismatch: {}", s),
+            Error::InvalidTip(ref s) => write!(f, "Invalid snapshot tip: {}", s),
+            Error::ExistsError(ref s) => write!(f, "Already exists: {}", s),
+            Error::ChainstateError(ref e) => fmt::Display::fmt(e, f),
+            Error::DBError(ref e) => fmt::Display::fmt(e, f),
+            Error::IOError(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            Error::InvalidArchive(ref _s) => None,
+            Error::FileMismatch(ref _s) => None,
+            Error::RootHashMismatch(ref _s) => None,
+            Error::InvalidTip(ref _s) => None,
+            Error::ExistsError(ref _s) => None,
+            Error::ChainstateError(ref e) => Some(e),
+            Error::DBError(ref e) => Some(e),
+            Error::IOError(ref e) => Some(e),
+        }
+    }
+}
+
+/// A file packaged in a snapshot.  The path is relative and '/'-separated, and starts with
+/// either "chainstate/" or "burndb/".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    pub path: String,
+    pub size: u64,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    pub mainnet: bool,
+    pub chain_id: u32,
+    pub tip_burn_header_hash: String,
+    pub tip_block_hash: String,
+    pub tip_index_block_hash: String,
+    pub tip_block_height: u64,
+    pub tip_state_index_root: String,
+    pub tip_index_root: String,
+    pub files: Vec<SnapshotFile>,
+}
+
+/// Copy exactly len bytes from r to w, and return the sha512/256 hash of what was copied.
+fn copy_hashed<R: Read, W: Write>(r: &mut R, w: &mut W, len: u64) -> Result<Sha512Trunc256Sum, io::Error> {
+    let mut hasher = Sha512Trunc256::new();
+    let mut buf = [0u8; 65536];
+    let mut remaining = len;
+    while remaining > 0 {
+        let to_read = if remaining < (buf.len() as u64) { remaining as usize } else { buf.len() };
+        let nr = r.read(&mut buf[0..to_read])?;
+        if nr == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Unexpected end of stream"));
+        }
+        hasher.input(&buf[0..nr]);
+        w.write_all(&buf[0..nr])?;
+        remaining -= nr as u64;
+    }
+    Ok(Sha512Trunc256Sum::from(hasher.result().as_slice()))
+}
+
+/// Recursively list all files under dir, sorted by path.  Each entry is (archive path, local path).
+fn list_files(dir: &Path, prefix: &str, ret: &mut Vec<(String, PathBuf)>) -> Result<(), io::Error> {
+    let mut entries = vec![];
+    for entry_res in fs::read_dir(dir)? {
+        let entry = entry_res?;
+        entries.push(entry.path());
+    }
+    entries.sort();
+
+    for path in entries.into_iter() {
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unrepresentable file name in {:?}", dir)));
+            }
+        };
+        let archive_path = format!("{}/{}", prefix, name);
+        let md = fs::symlink_metadata(&path)?;
+        if md.is_dir() {
+            list_files(&path, &archive_path, ret)?;
+        }
+        else if md.is_file() {
+            ret.push((archive_path, path));
+        }
+        else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Not a regular file: {:?}", &path)));
+        }
+    }
+    Ok(())
+}
+
+/// Map an archive path to a local path under the chainstate or burn DB directory.
+/// Rejects anything that could escape the destination directory.
+fn local_path(archive_path: &str, chainstate_path: &str, burndb_path: &str) -> Result<PathBuf, Error> {
+    let mut parts = archive_path.split('/');
+    let mut path = match parts.next() {
+        Some(CHAINSTATE_PREFIX) => PathBuf::from(chainstate_path),
+        Some(BURNDB_PREFIX) => PathBuf::from(burndb_path),
+        _ => {
+            return Err(Error::InvalidArchive(format!("Unrecognized file path {}", archive_path)));
+        }
+    };
+
+    let mut num_parts = 0;
+    for part in parts {
+        if part.len() == 0 || part == "." || part == ".." || part.contains('\\') {
+            return Err(Error::InvalidArchive(format!("Invalid file path {}", archive_path)));
+        }
+        path.push(part);
+        num_parts += 1;
+    }
+
+    if num_parts == 0 {
+        return Err(Error::InvalidArchive(format!("Invalid file path {}", archive_path)));
+    }
+    Ok(path)
+}
+
+/// A directory we can unpack into must not exist yet, or be empty.
+fn check_empty_dir(path: &str) -> Result<(), Error> {
+    match fs::read_dir(path) {
+        Ok(mut entries) => {
+            if entries.next().is_some() {
+                return Err(Error::ExistsError(path.to_string()));
+            }
+            Ok(())
+        },
+        Err(e) => {
+            if e.kind() == io::ErrorKind::NotFound {
+                Ok(())
+            }
+            else {
+                Err(Error::IOError(e))
+            }
+        }
+    }
+}
+
+fn parse_hex<T, F>(hex: &str, what: &str, parser: F) -> Result<T, Error>
+where F: FnOnce(&str) -> Result<T, ::util::HexError> {
+    parser(hex).map_err(|_e| Error::InvalidArchive(format!("Invalid {} {}", what, hex)))
+}
+
+impl SnapshotManifest {
+    pub fn tip_burn_header_hash(&self) -> Result<BurnchainHeaderHash, Error> {
+        parse_hex(&self.tip_burn_header_hash, "tip burn header hash", BurnchainHeaderHash::from_hex)
+    }
+
+    pub fn tip_block_hash(&self) -> Result<BlockHeaderHash, Error> {
+        parse_hex(&self.tip_block_hash, "tip block hash", BlockHeaderHash::from_hex)
+    }
+
+    /// Write the archive header and manifest
+    fn write_header<W: Write>(&self, fd: &mut W) -> Result<(), Error> {
+        let manifest_bytes = serde_json::to_vec(self)
+            .map_err(|e| Error::InvalidArchive(format!("Failed to encode manifest: {:?}", &e)))?;
+
+        if manifest_bytes.len() > (SNAPSHOT_MANIFEST_MAX_LEN as usize) {
+            return Err(Error::InvalidArchive("Manifest is too big".to_string()));
+        }
+
+        fd.write_all(SNAPSHOT_MAGIC).map_err(Error::IOError)?;
+        fd.write_all(&SNAPSHOT_VERSION.to_be_bytes()).map_err(Error::IOError)?;
+        fd.write_all(&(manifest_bytes.len() as u32).to_be_bytes()).map_err(Error::IOError)?;
+        fd.write_all(&manifest_bytes).map_err(Error::IOError)?;
+        Ok(())
+    }
+
+    /// Read the archive header and manifest
+    fn read_header<R: Read>(fd: &mut R) -> Result<SnapshotManifest, Error> {
+        let mut magic = [0u8; 4];
+        let mut version_bytes = [0u8; 4];
+        let mut len_bytes = [0u8; 4];
+
+        fd.read_exact(&mut magic).map_err(|_e| Error::InvalidArchive("Truncated header".to_string()))?;
+        if magic != *SNAPSHOT_MAGIC {
+            return Err(Error::InvalidArchive("Not a snapshot archive".to_string()));
+        }
+
+        fd.read_exact(&mut version_bytes).map_err(|_e| Error::InvalidArchive("Truncated header".to_string()))?;
+        let version = u32::from_be_bytes(version_bytes);
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::InvalidArchive(format!("Unsupported snapshot version {}", version)));
+        }
+
+        fd.read_exact(&mut len_bytes).map_err(|_e| Error::InvalidArchive("Truncated header".to_string()))?;
+        let len = u32::from_be_bytes(len_bytes);
+        if len > SNAPSHOT_MANIFEST_MAX_LEN {
+            return Err(Error::InvalidArchive(format!("Manifest is too big ({} bytes)", len)));
+        }
+
+        let mut manifest_bytes = vec![0u8; len as usize];
+        fd.read_exact(&mut manifest_bytes).map_err(|_e| Error::InvalidArchive("Truncated manifest".to_string()))?;
+
+        let manifest : SnapshotManifest = serde_json::from_slice(&manifest_bytes)
+            .map_err(|e| Error::InvalidArchive(format!("Failed to decode manifest: {:?}", &e)))?;
+
+        if manifest.version != version {
+            return Err(Error::InvalidArchive(format!("Manifest version {} does not match archive version {}", manifest.version, version)));
+        }
+        Ok(manifest)
+    }
+}
+
+impl StacksChainState {
+    /// Package the chainstate at chainstate_path and the burn database at burndb_path into a
+    /// snapshot archive at archive_path, recording the given Stacks chain tip in the manifest.
+    /// The node must not be running.
+    pub fn export_snapshot(mainnet: bool, chain_id: u32, chainstate_path: &str, burndb_path: &str,
+                           tip_burn_header_hash: &BurnchainHeaderHash, tip_block_hash: &BlockHeaderHash, archive_path: &str) -> Result<SnapshotManifest, Error> {
+        match fs::metadata(archive_path) {
+            Ok(_) => {
+                return Err(Error::ExistsError(archive_path.to_string()));
+            },
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(Error::IOError(e));
+                }
+            }
+        }
+
+        let mut chain_dir = PathBuf::from(chainstate_path);
+        let chain_dir_name = StacksChainState::chain_dir_name(mainnet, chain_id);
+        chain_dir.push(&chain_dir_name);
+
+        // don't instantiate a fresh chainstate just to export it
+        fs::metadata(&chain_dir).map_err(Error::IOError)?;
+
+        // the tip must be known to both stores
+        let tip = {
+            let chainstate = StacksChainState::open(mainnet, chain_id, chainstate_path).map_err(Error::ChainstateError)?;
+            match StacksChainState::get_anchored_block_header_info(&chainstate.headers_db, tip_burn_header_hash, tip_block_hash).map_err(Error::ChainstateError)? {
+                Some(tip) => tip,
+                None => {
+                    return Err(Error::InvalidTip(format!("No such Stacks block {}/{}", tip_burn_header_hash, tip_block_hash)));
+                }
+            }
+        };
+
+        {
+            let burndb = BurnDB::open(&burndb_path.to_string(), false).map_err(Error::DBError)?;
+            if BurnDB::get_block_snapshot(burndb.conn(), tip_burn_header_hash).map_err(Error::DBError)?.is_none() {
+                return Err(Error::InvalidTip(format!("No such burn block {}", tip_burn_header_hash)));
+            }
+        }
+
+        let mut local_files = vec![];
+        list_files(&chain_dir, &format!("{}/{}", CHAINSTATE_PREFIX, &chain_dir_name), &mut local_files).map_err(Error::IOError)?;
+        list_files(Path::new(burndb_path), BURNDB_PREFIX, &mut local_files).map_err(Error::IOError)?;
+
+        let mut files = vec![];
+        for (file_path, path) in local_files.iter() {
+            let mut fd = fs::File::open(path).map_err(Error::IOError)?;
+            let size = fd.metadata().map_err(Error::IOError)?.len();
+            let hash = copy_hashed(&mut fd, &mut io::sink(), size).map_err(Error::IOError)?;
+            files.push(SnapshotFile {
+                path: file_path.clone(),
+                size: size,
+                hash: hash.to_hex()
+            });
+        }
+
+        let manifest = SnapshotManifest {
+            version: SNAPSHOT_VERSION,
+            mainnet: mainnet,
+            chain_id: chain_id,
+            tip_burn_header_hash: tip.burn_header_hash.to_hex(),
+            tip_block_hash: tip.anchored_header.block_hash().to_hex(),
+            tip_index_block_hash: tip.index_block_hash().to_hex(),
+            tip_block_height: tip.block_height,
+            tip_state_index_root: tip.anchored_header.state_index_root.to_hex(),
+            tip_index_root: tip.index_root.to_hex(),
+            files: files
+        };
+
+        // write to a temporary file, so a failed export doesn't leave a partial archive behind
+        let tmp_path = format!("{}.tmp", archive_path);
+        let res = StacksChainState::write_snapshot(&manifest, &local_files, &tmp_path);
+        match res {
+            Ok(_) => {
+                fs::rename(&tmp_path, archive_path).map_err(Error::IOError)?;
+            },
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path);
+                return Err(e);
+            }
+        }
+
+        debug!("Exported snapshot of {}/{} ({} files) to {}", tip_burn_header_hash, tip_block_hash, manifest.files.len(), archive_path);
+        Ok(manifest)
+    }
+
+    fn write_snapshot(manifest: &SnapshotManifest, local_files: &Vec<(String, PathBuf)>, archive_path: &str) -> Result<(), Error> {
+        let archive_fd = fs::OpenOptions::new().write(true).create_new(true).open(archive_path).map_err(Error::IOError)?;
+        let mut writer = BufWriter::new(archive_fd);
+
+        manifest.write_header(&mut writer)?;
+
+        for (file, (_, path)) in manifest.files.iter().zip(local_files.iter()) {
+            let mut fd = fs::File::open(path).map_err(Error::IOError)?;
+            let hash = copy_hashed(&mut fd, &mut writer, file.size).map_err(Error::IOError)?;
+            if hash.to_hex() != file.hash {
+                return Err(Error::FileMismatch(format!("{} changed during export", &file.path)));
+            }
+        }
+
+        writer.flush().map_err(Error::IOError)?;
+        writer.get_ref().sync_all().map_err(Error::IOError)?;
+        Ok(())
+    }
+
+    /// Unpack a snapshot archive into chainstate_path and burndb_path, and verify it.  Both
+    /// destinations must be empty or absent.  If the archive is corrupt or fails verification,
+    /// nothing is left behind.
+    pub fn import_snapshot(archive_path: &str, chainstate_path: &str, burndb_path: &str) -> Result<SnapshotManifest, Error> {
+        check_empty_dir(chainstate_path)?;
+        check_empty_dir(burndb_path)?;
+
+        let res = StacksChainState::unpack_snapshot(archive_path, chainstate_path, burndb_path)
+            .and_then(|manifest| {
+                StacksChainState::verify_snapshot(&manifest, chainstate_path, burndb_path)?;
+                Ok(manifest)
+            });
+
+        match res {
+            Ok(manifest) => {
+                debug!("Imported snapshot of {}/{} from {}", &manifest.tip_burn_header_hash, &manifest.tip_block_hash, archive_path);
+                Ok(manifest)
+            },
+            Err(e) => {
+                warn!("Failed to import snapshot {}: {:?}", archive_path, &e);
+                let _ = fs::remove_dir_all(chainstate_path);
+                let _ = fs::remove_dir_all(burndb_path);
+                Err(e)
+            }
+        }
+    }
+
+    fn unpack_snapshot(archive_path: &str, chainstate_path: &str, burndb_path: &str) -> Result<SnapshotManifest, Error> {
+        let archive_fd = fs::File::open(archive_path).map_err(Error::IOError)?;
+        let mut reader = BufReader::new(archive_fd);
+
+        let manifest = SnapshotManifest::read_header(&mut reader)?;
+
+        // the headers DB and both MARFs must be present, or opening the chainstate would
+        // silently instantiate a fresh one
+        let chain_prefix = format!("{}/{}/", CHAINSTATE_PREFIX, StacksChainState::chain_dir_name(manifest.mainnet, manifest.chain_id));
+        for required in ["vm/headers.db", "vm/index", "vm/clarity/marf"].iter() {
+            let required_path = format!("{}{}", &chain_prefix, required);
+            let required_dir = format!("{}/", &required_path);
+            if !manifest.files.iter().any(|f| f.path == required_path || f.path.starts_with(&required_dir)) {
+                return Err(Error::InvalidArchive(format!("Missing {}", &required_path)));
+            }
+        }
+
+        fs::create_dir_all(chainstate_path).map_err(Error::IOError)?;
+        fs::create_dir_all(burndb_path).map_err(Error::IOError)?;
+
+        for file in manifest.files.iter() {
+            let path = local_path(&file.path, chainstate_path, burndb_path)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(Error::IOError)?;
+            }
+
+            let fd = fs::OpenOptions::new().write(true).create_new(true).open(&path)
+                .map_err(|e| {
+                    if e.kind() == io::ErrorKind::AlreadyExists {
+                        Error::InvalidArchive(format!("Duplicate file {}", &file.path))
+                    }
+                    else {
+                        Error::IOError(e)
+                    }
+                })?;
+
+            let mut writer = BufWriter::new(fd);
+            let hash = copy_hashed(&mut reader, &mut writer, file.size)
+                .map_err(|e| {
+                    if e.kind() == io::ErrorKind::UnexpectedEof {
+                        Error::InvalidArchive(format!("Truncated data for {}", &file.path))
+                    }
+                    else {
+                        Error::IOError(e)
+                    }
+                })?;
+
+            if hash.to_hex() != file.hash {
+                return Err(Error::FileMismatch(format!("{}: expected hash {}, got {}", &file.path, &file.hash, hash.to_hex())));
+            }
+
+            writer.flush().map_err(Error::IOError)?;
+        }
+
+        let mut trailing = [0u8; 1];
+        if reader.read(&mut trailing).map_err(Error::IOError)? != 0 {
+            return Err(Error::InvalidArchive("Trailing data after the last file".to_string()));
+        }
+
+        Ok(manifest)
+    }
+
+    /// Verify an unpacked snapshot: the manifest's tip must match its header, every Stacks
+    /// header's state_index_root and index_root must match the Clarity and headers MARFs, and
+    /// every burn snapshot's index_root must match the burn DB's MARF.
+    pub fn verify_snapshot(manifest: &SnapshotManifest, chainstate_path: &str, burndb_path: &str) -> Result<(), Error> {
+        let tip_burn_header_hash = manifest.tip_burn_header_hash()?;
+        let tip_block_hash = manifest.tip_block_hash()?;
+
+        let mut chainstate = StacksChainState::open(manifest.mainnet, manifest.chain_id, chainstate_path).map_err(Error::ChainstateError)?;
+
+        let tip = match StacksChainState::get_anchored_block_header_info(&chainstate.headers_db, &tip_burn_header_hash, &tip_block_hash).map_err(Error::ChainstateError)? {
+            Some(tip) => tip,
+            None => {
+                return Err(Error::InvalidTip(format!("No such Stacks block {}/{}", &tip_burn_header_hash, &tip_block_hash)));
+            }
+        };
+
+        if tip.index_block_hash().to_hex() != manifest.tip_index_block_hash
+            || tip.block_height != manifest.tip_block_height
+            || tip.anchored_header.state_index_root.to_hex() != manifest.tip_state_index_root
+            || tip.index_root.to_hex() != manifest.tip_index_root {
+            return Err(Error::InvalidTip(format!("Header for {}/{} does not match the manifest", &tip_burn_header_hash, &tip_block_hash)));
+        }
+
+        let headers = query_rows::<StacksHeaderInfo, _>(&chainstate.headers_db, &"SELECT * FROM block_headers".to_string(), NO_PARAMS)
+            .map_err(Error::DBError)?;
+
+        let mut clarity_marf = StacksChainState::open_index(&chainstate.clarity_state_index_path, None, chainstate.index_layout).map_err(Error::ChainstateError)?;
+
+        for header in headers.iter() {
+            if header.anchored_header.parent_block == BOOT_BLOCK_HASH {
+                // the boot block's header is not indexed under its own hash
+                continue;
+            }
+
+            let index_block_hash = header.index_block_hash();
+
+            let index_root = chainstate.headers_state_index.get_root_hash_at(&index_block_hash)
+                .map_err(|e| Error::RootHashMismatch(format!("No headers index root for {}/{} ({:?})", &header.burn_header_hash, header.anchored_header.block_hash(), &e)))?;
+            if index_root != header.index_root {
+                return Err(Error::RootHashMismatch(format!("Headers index root for {}/{} is {}, but the header has {}",
+                                                           &header.burn_header_hash, header.anchored_header.block_hash(), &index_root, &header.index_root)));
+            }
+
+            let state_root = clarity_marf.get_root_hash_at(&index_block_hash)
+                .map_err(|e| Error::RootHashMismatch(format!("No state root for {}/{} ({:?})", &header.burn_header_hash, header.anchored_header.block_hash(), &e)))?;
+            if state_root != header.anchored_header.state_index_root {
+                return Err(Error::RootHashMismatch(format!("State root for {}/{} is {}, but the header has {}",
+                                                           &header.burn_header_hash, header.anchored_header.block_hash(), &state_root, &header.anchored_header.state_index_root)));
+            }
+        }
+
+        let mut burndb = BurnDB::open(&burndb_path.to_string(), false).map_err(Error::DBError)?;
+        let snapshots = query_rows::<BlockSnapshot, _>(burndb.conn(), &"SELECT * FROM snapshots".to_string(), NO_PARAMS)
+            .map_err(Error::DBError)?;
+
+        if !snapshots.iter().any(|sn| sn.burn_header_hash == tip_burn_header_hash) {
+            return Err(Error::InvalidTip(format!("No such burn block {}", &tip_burn_header_hash)));
+        }
+
+        for snapshot in snapshots.iter() {
+            let index_root : TrieHash = burndb.marf.get_root_hash_at(&BlockHeaderHash::from(snapshot.burn_header_hash.clone()))
+                .map_err(|e| Error::RootHashMismatch(format!("No burn index root for {} ({:?})", &snapshot.burn_header_hash, &e)))?;
+            if index_root != snapshot.index_root {
+                return Err(Error::RootHashMismatch(format!("Burn index root for {} is {}, but the snapshot has {}",
+                                                           &snapshot.burn_header_hash, &index_root, &snapshot.index_root)));
+            }
+        }
+
+        debug!("Verified snapshot of {}/{}: {} Stacks headers, {} burn snapshots", &tip_burn_header_hash, &tip_block_hash, headers.len(), snapshots.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    use chainstate::stacks::db::test::*;
+    use chainstate::stacks::db::blocks::StagingUserBurnSupport;
+
+    use chainstate::burn::ConsensusHash;
+
+    use core::FIRST_STACKS_BLOCK_HASH;
+
+    use rusqlite::Connection;
+
+    use util::get_epoch_time_secs;
+    use util::hash::Hash160;
+
+    use vm::types::{PrincipalData, StandardPrincipalData};
+
+    pub fn remove_if_exists(path: &str) {
+        match fs::metadata(path) {
+            Ok(md) => {
+                if md.is_dir() {
+                    fs::remove_dir_all(path).unwrap();
+                }
+                else {
+                    fs::remove_file(path).unwrap();
+                }
+            },
+            Err(_) => {}
+        }
+    }
+
+    /// Process a block that credits an account, without touching the burn DB.  The block is
+    /// committed under the given burn header hash, so this can build forks.
+    pub fn append_block_at(chainstate: &mut StacksChainState, parent_opt: Option<&StacksHeaderInfo>, burn_header_hash: &BurnchainHeaderHash, amount: u64) -> StacksHeaderInfo {
+        let recipient = PrincipalData::Standard(StandardPrincipalData(0x16, [amount as u8; 20]));
+
+        let (parent, parent_block_hash) = match parent_opt {
+            Some(parent) => (parent.clone(), parent.anchored_header.block_hash()),
+            None => (StacksHeaderInfo::genesis(), FIRST_STACKS_BLOCK_HASH.clone())
+        };
+        let parent_burn_header_hash = parent.burn_header_hash.clone();
+
+        let mut header = parent.anchored_header.clone();
+        header.parent_block = parent_block_hash.clone();
+        header.total_work.work = parent.block_height + 1;
+
+        let mut clarity_tx = chainstate.block_begin(&parent_burn_header_hash, &parent_block_hash, &MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH);
+        StacksChainState::account_credit(&mut clarity_tx, &recipient, amount);
+        header.state_index_root = clarity_tx.get_root_hash();
+        clarity_tx.commit_to_block(burn_header_hash, &header.block_hash());
+
+        let reward = MinerPaymentSchedule {
+            address: StacksAddress { version: 0, bytes: Hash160([0u8; 20]) },
+            block_hash: header.block_hash(),
+            burn_header_hash: burn_header_hash.clone(),
+            parent_block_hash: parent_block_hash.clone(),
+            parent_burn_header_hash: parent_burn_header_hash.clone(),
+            coinbase: 0,
+            tx_fees_anchored: 0,
+            tx_fees_streamed: 0,
+            stx_burns: 0,
+            burnchain_commit_burn: 0,
+            burnchain_sortition_burn: 0,
+            fill: 0,
+            miner: true,
+            stacks_block_height: header.total_work.work,
+            vtxindex: 0
+        };
+        let user_burns : Vec<StagingUserBurnSupport> = vec![];
+
+        let mut headers_tx = chainstate.headers_tx_begin().unwrap();
+        let tip = StacksChainState::advance_tip(&mut headers_tx, &parent.anchored_header, &parent.burn_header_hash, &header, burn_header_hash,
+                                                get_epoch_time_secs(), None, &reward, &user_burns).unwrap();
+        headers_tx.commit().unwrap();
+        tip
+    }
+
+    /// Process a block that credits an account, and add a burn snapshot for it.  A parent of
+    /// None means this is the first-ever block.  Returns the new tip.
+    pub fn append_block(chainstate: &mut StacksChainState, burndb: &mut BurnDB, parent_opt: Option<&StacksHeaderInfo>, amount: u64) -> StacksHeaderInfo {
+        let parent_height = parent_opt.map(|p| p.block_height).unwrap_or(StacksHeaderInfo::genesis().block_height);
+        let burn_header_hash = BurnchainHeaderHash([(parent_height + 1) as u8; 32]);
+        let tip = append_block_at(chainstate, parent_opt, &burn_header_hash, amount);
+
+        let first_block_height = burndb.first_block_height;
+        let mut burn_tx = burndb.tx_begin().unwrap();
+        let mut sn = BurnDB::get_canonical_burn_chain_tip(&mut burn_tx).unwrap();
+        let sn_parent = sn.clone();
+        sn.parent_burn_header_hash = sn.burn_header_hash.clone();
+        sn.burn_header_hash = burn_header_hash.clone();
+        sn.block_height += 1;
+        sn.num_sortitions += 1;
+        sn.consensus_hash = ConsensusHash::from_parent_block_data(&mut burn_tx, &sn.ops_hash, sn_parent.block_height, first_block_height,
+                                                                  &sn_parent.burn_header_hash, sn.total_burn).unwrap();
+        BurnDB::append_chain_tip_snapshot(&mut burn_tx, &sn_parent, &sn, &vec![], &vec![]).unwrap();
+        burn_tx.commit().unwrap();
+
+        tip
+    }
+
+    pub fn make_snapshot_source(test_name: &str, num_blocks: u64) -> (String, String, StacksHeaderInfo) {
+        let mut chainstate = instantiate_chainstate(false, 0x80000000, test_name);
+        let chainstate_path = chainstate_path(test_name);
+
+        let burndb_path = format!("/tmp/blockstack-test-snapshot-burndb-{}", test_name);
+        remove_if_exists(&burndb_path);
+        let mut burndb = BurnDB::connect(&burndb_path, 0, &BurnchainHeaderHash([0xfe; 32]), get_epoch_time_secs(), true).unwrap();
+
+        let mut tip = append_block(&mut chainstate, &mut burndb, None, 100);
+        for i in 1..num_blocks {
+            tip = append_block(&mut chainstate, &mut burndb, Some(&tip), 100 + i);
+        }
+
+        (chainstate_path, burndb_path, tip)
+    }
+
+    #[test]
+    fn snapshot_export_import() {
+        let (src_chainstate_path, src_burndb_path, tip) = make_snapshot_source("snapshot-export-import", 5);
+
+        let archive_path = "/tmp/blockstack-test-snapshot-export-import.snap";
+        let dest_chainstate_path = "/tmp/blockstack-test-snapshot-export-import-dest-chainstate";
+        let dest_burndb_path = "/tmp/blockstack-test-snapshot-export-import-dest-burndb";
+        remove_if_exists(archive_path);
+        remove_if_exists(dest_chainstate_path);
+        remove_if_exists(dest_burndb_path);
+
+        let manifest = StacksChainState::export_snapshot(false, 0x80000000, &src_chainstate_path, &src_burndb_path,
+                                                         &tip.burn_header_hash, &tip.anchored_header.block_hash(), archive_path).unwrap();
+        assert_eq!(manifest.tip_block_height, tip.block_height);
+        assert_eq!(manifest.tip_index_root, tip.index_root.to_hex());
+        assert_eq!(manifest.tip_state_index_root, tip.anchored_header.state_index_root.to_hex());
+
+        // won't clobber an existing archive
+        match StacksChainState::export_snapshot(false, 0x80000000, &src_chainstate_path, &src_burndb_path,
+                                                &tip.burn_header_hash, &tip.anchored_header.block_hash(), archive_path) {
+            Err(Error::ExistsError(_)) => {},
+            x => panic!("Expected ExistsError, got {:?}", &x)
+        }
+
+        let imported = StacksChainState::import_snapshot(archive_path, dest_chainstate_path, dest_burndb_path).unwrap();
+        assert_eq!(imported, manifest);
+
+        // imported state is usable at the tip
+        let mut chainstate = StacksChainState::open(false, 0x80000000, dest_chainstate_path).unwrap();
+        let imported_tip = StacksChainState::get_anchored_block_header_info(&chainstate.headers_db, &tip.burn_header_hash, &tip.anchored_header.block_hash()).unwrap().unwrap();
+        assert_eq!(imported_tip, tip);
+
+        let recipient = PrincipalData::Standard(StandardPrincipalData(0x16, [104u8; 20]));
+        let account = chainstate.with_read_only_clarity_tx(&tip.burn_header_hash, &tip.anchored_header.block_hash(), |clarity_tx| {
+            StacksChainState::get_account(clarity_tx, &recipient)
+        });
+        assert_eq!(account.stx_balance, 104);
+
+        // won't import over existing state
+        match StacksChainState::import_snapshot(archive_path, dest_chainstate_path, dest_burndb_path) {
+            Err(Error::ExistsError(_)) => {},
+            x => panic!("Expected ExistsError, got {:?}", &x)
+        }
+    }
+
+    #[test]
+    fn snapshot_import_rejects_corruption() {
+        let (src_chainstate_path, src_burndb_path, tip) = make_snapshot_source("snapshot-import-corrupt", 3);
+
+        let archive_path = "/tmp/blockstack-test-snapshot-import-corrupt.snap";
+        let dest_chainstate_path = "/tmp/blockstack-test-snapshot-import-corrupt-dest-chainstate";
+        let dest_burndb_path = "/tmp/blockstack-test-snapshot-import-corrupt-dest-burndb";
+        remove_if_exists(archive_path);
+        remove_if_exists(dest_chainstate_path);
+        remove_if_exists(dest_burndb_path);
+
+        StacksChainState::export_snapshot(false, 0x80000000, &src_chainstate_path, &src_burndb_path,
+                                          &tip.burn_header_hash, &tip.anchored_header.block_hash(), archive_path).unwrap();
+        let archive = fs::read(archive_path).unwrap();
+
+        // flip a byte in the file data
+        let mut corrupt = archive.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+        fs::write(archive_path, &corrupt).unwrap();
+
+        match StacksChainState::import_snapshot(archive_path, dest_chainstate_path, dest_burndb_path) {
+            Err(Error::FileMismatch(_)) => {},
+            x => panic!("Expected FileMismatch, got {:?}", &x)
+        }
+
+        // nothing left behind
+        assert!(fs::metadata(dest_chainstate_path).is_err());
+        assert!(fs::metadata(dest_burndb_path).is_err());
+
+        // truncated
+        fs::write(archive_path, &archive[0..archive.len() - 1]).unwrap();
+        match StacksChainState::import_snapshot(archive_path, dest_chainstate_path, dest_burndb_path) {
+            Err(Error::InvalidArchive(_)) => {},
+            x => panic!("Expected InvalidArchive, got {:?}", &x)
+        }
+
+        // a header whose index root disagrees with the headers MARF is caught, even though the
+        // archive itself is intact
+        {
+            let headers_db_path = format!("{}/{}/vm/headers.db", &src_chainstate_path, StacksChainState::chain_dir_name(false, 0x80000000));
+            let conn = Connection::open(&headers_db_path).unwrap();
+            conn.execute("UPDATE block_headers SET index_root = ?1 WHERE block_height = 3", &[&TrieHash([0x11; 32])]).unwrap();
+        }
+
+        remove_if_exists(archive_path);
+        StacksChainState::export_snapshot(false, 0x80000000, &src_chainstate_path, &src_burndb_path,
+                                          &tip.burn_header_hash, &tip.anchored_header.block_hash(), archive_path).unwrap();
+
+        match StacksChainState::import_snapshot(archive_path, dest_chainstate_path, dest_burndb_path) {
+            Err(Error::RootHashMismatch(_)) => {},
+            x => panic!("Expected RootHashMismatch, got {:?}", &x)
+        }
+        assert!(fs::metadata(dest_chainstate_path).is_err());
+        assert!(fs::metadata(dest_burndb_path).is_err());
+    }
+}
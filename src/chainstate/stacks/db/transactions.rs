@@ -23,6 +23,7 @@ use std::io::{Read, Write};
 
 use std::fmt;
 use std::fs;
+use std::cmp;
 use std::collections::{HashSet, HashMap};
 
 use chainstate::stacks::Error;
@@ -79,6 +80,8 @@ use vm::database::ClarityDatabase;
 
 use vm::contracts::Contract;
 
+use vm::costs::ExecutionCost;
+
 // make it possible to have a set of Values
 impl std::hash::Hash for Value {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
@@ -88,6 +91,17 @@ impl std::hash::Hash for Value {
     }
 }
 
+/// Everything that processing a transaction produced.  If the post-conditions did not pass, then
+/// the payload's changes were rolled back (but the fee was still paid and the nonces still bumped).
+#[derive(Debug, Clone)]
+pub struct StacksTransactionOutcome {
+    pub fee: u64,
+    pub execution_cost: ExecutionCost,
+    pub receipt: StacksTransactionReceipt,
+    pub asset_map: AssetMap,
+    pub post_conditions_passed: bool
+}
+
 impl StacksChainState {
     /// Check the account nonces for the supplied stacks transaction,
     ///   returning the origin and payer accounts if valid.
@@ -123,16 +137,49 @@ impl StacksChainState {
         Ok((origin_account, payer_account))
     }
 
-    /// Pay the transaction fee (but don't credit it to the miner yet).
-    /// Does not touch the account nonce
-    /// TODO: the fee paid here isn't the bare fee in the transaction, but is instead the
-    /// block-wide STX/compute-unit rate, times the compute units used by this tx.
-    fn pay_transaction_fee<'a>(clarity_tx: &mut ClarityTx<'a>, tx: &StacksTransaction, payer_account: &StacksAccount) -> Result<u64, Error> {
-        if payer_account.stx_balance < tx.get_fee_rate() as u128 {
+    /// Calculate the number of fee units a transaction consumes.  A transaction is charged one unit
+    /// per byte of its serialized form, plus one unit for each 1/$MAX_EPOCH_SIZE-th of each
+    /// dimension of the anchored block's execution budget that it used (rounded up).  This way, a
+    /// transaction that uses up a whole block's worth of some resource is charged as much as one
+    /// that uses up the whole epoch's worth of bandwidth.
+    pub fn get_tx_fee_units(tx_len: u64, cost: &ExecutionCost) -> u64 {
+        let scale = |used: u64, budget: u64| -> u128 {
+            let budget = cmp::max(budget, 1) as u128;
+            ((used as u128) * (MAX_EPOCH_SIZE as u128) + budget - 1) / budget
+        };
+
+        let units = (tx_len as u128)
+            + scale(cost.runtime, ANCHORED_BLOCK_COST_LIMIT.runtime)
+            + scale(cost.read_count, ANCHORED_BLOCK_COST_LIMIT.read_count)
+            + scale(cost.read_length, ANCHORED_BLOCK_COST_LIMIT.read_length)
+            + scale(cost.write_count, ANCHORED_BLOCK_COST_LIMIT.write_count)
+            + scale(cost.write_length, ANCHORED_BLOCK_COST_LIMIT.write_length);
+
+        if units > (u64::max_value() as u128) {
+            u64::max_value()
+        }
+        else {
+            units as u64
+        }
+    }
+
+    /// Calculate the fee a transaction of the given length and execution cost pays:  its fee
+    /// rate times the number of fee units it consumes.
+    /// Returns None if the fee would overflow.
+    pub fn get_tx_fee(tx: &StacksTransaction, tx_len: u64, cost: &ExecutionCost) -> Option<u64> {
+        tx.get_fee_rate().checked_mul(StacksChainState::get_tx_fee_units(tx_len, cost))
+    }
+
+    /// Pay (part of) the transaction fee (but don't credit it to the miner yet).
+    /// Does not touch the account nonce.
+    /// The payer's balance is re-read, since the transaction may have already spent some of it.
+    fn pay_transaction_fee<'a>(clarity_tx: &mut ClarityTx<'a>, payer: &PrincipalData, fee: u64) -> Result<(), Error> {
+        let payer_account = StacksChainState::get_account(clarity_tx, payer);
+        if payer_account.stx_balance < fee as u128 {
             return Err(Error::InvalidFee);
         }
-        StacksChainState::account_debit(clarity_tx, &payer_account.principal, tx.get_fee_rate());
-        Ok(tx.get_fee_rate())
+        StacksChainState::account_debit(clarity_tx, &payer_account.principal, fee);
+        Ok(())
     }
 
     /// Pre-check a transaction -- make sure it's well-formed
@@ -364,9 +411,48 @@ impl StacksChainState {
         })
     }
 
+    /// Check that two microblock headers prove that a leader equivocated: they must share a
+    /// sequence number, differ in content, and both be signed by the same microblock key.
+    /// Returns the hash of that key.
+    pub fn check_poison_microblock_headers(mblock_header_1: &StacksMicroblockHeader, mblock_header_2: &StacksMicroblockHeader) -> Result<Hash160, Error> {
+        if mblock_header_1.sequence != mblock_header_2.sequence {
+            let msg = format!("Invalid PoisonMicroblock: sequence numbers {} and {} differ", mblock_header_1.sequence, mblock_header_2.sequence);
+            warn!("{}", &msg);
+            return Err(Error::InvalidStacksTransaction(msg));
+        }
+
+        if mblock_header_1.block_hash() == mblock_header_2.block_hash() {
+            let msg = format!("Invalid PoisonMicroblock: both headers are microblock {}", mblock_header_1.block_hash());
+            warn!("{}", &msg);
+            return Err(Error::InvalidStacksTransaction(msg));
+        }
+
+        let pubkey_hash_1 = mblock_header_1.check_recover_pubkey()
+            .map_err(|e| Error::InvalidStacksTransaction(format!("Invalid PoisonMicroblock: {:?}", &e)))?;
+        let pubkey_hash_2 = mblock_header_2.check_recover_pubkey()
+            .map_err(|e| Error::InvalidStacksTransaction(format!("Invalid PoisonMicroblock: {:?}", &e)))?;
+
+        if pubkey_hash_1 != pubkey_hash_2 {
+            let msg = format!("Invalid PoisonMicroblock: headers are signed by different keys ({} and {})", pubkey_hash_1.to_hex(), pubkey_hash_2.to_hex());
+            warn!("{}", &msg);
+            return Err(Error::InvalidStacksTransaction(msg));
+        }
+
+        Ok(pubkey_hash_1)
+    }
+
     /// Process the transaction's payload, and run the post-conditions against the resulting state.
-    /// Returns the number of STX burned.
+    /// Returns the receipt.
     pub fn process_transaction_payload<'a>(clarity_tx: &mut ClarityTx<'a>, tx: &StacksTransaction, origin_account: &StacksAccount) -> Result<StacksTransactionReceipt, Error> {
+        let (receipt, _asset_map, _post_conditions_passed) = StacksChainState::run_transaction_payload(clarity_tx, tx, origin_account)?;
+        Ok(receipt)
+    }
+
+    /// Process the transaction's payload, and run the post-conditions against the resulting state.
+    /// Returns the receipt, the assets the payload moved, and whether or not the post-conditions
+    /// passed.
+    fn run_transaction_payload<'a>(clarity_tx: &mut ClarityTx<'a>, tx: &StacksTransaction, origin_account: &StacksAccount) -> Result<(StacksTransactionReceipt, AssetMap, bool), Error> {
+        let mut post_conditions_passed = true;
         match tx.payload {
             TransactionPayload::TokenTransfer(ref addr, ref amount, ref _memo) => {
                 // post-conditions are not allowed for this variant, since they're non-sensical.
@@ -383,6 +469,10 @@ impl StacksChainState {
                 let sender = origin_account.principal.clone();
                 let recipient = addr.to_account_principal();
                 let amount = u128::try_from(*amount).unwrap();
+
+                let mut asset_map = AssetMap::new();
+                asset_map.add_stx_transfer(&sender, amount).expect("FATAL: overflow in a new asset map");
+
                 let event_data = STXTransferEventData { sender, recipient, amount };
                 let receipt = StacksTransactionReceipt {
                     transaction: tx.clone(),
@@ -393,7 +483,7 @@ impl StacksChainState {
                 };
 
                 // no burns
-                Ok(receipt)
+                Ok((receipt, asset_map, post_conditions_passed))
             },
             TransactionPayload::ContractCall(ref contract_call) => {
                 // if this calls a function that doesn't exist or is syntactically invalid, then the
@@ -403,7 +493,10 @@ impl StacksChainState {
                 // tx fee.
                 let contract_id = contract_call.to_clarity_contract_id();
                 let (result, asset_map, events) = match clarity_tx.connection().run_contract_call(&origin_account.principal, &contract_id, &contract_call.function_name, &contract_call.function_args,
-                                                                                |asset_map, _| { !StacksChainState::check_transaction_postconditions(&tx.post_conditions, &tx.post_condition_mode, origin_account, asset_map) }) {
+                                                                                |asset_map, _| {
+                                                                                    post_conditions_passed = StacksChainState::check_transaction_postconditions(&tx.post_conditions, &tx.post_condition_mode, origin_account, asset_map);
+                                                                                    !post_conditions_passed
+                                                                                }) {
                     Ok((return_value, asset_map, events)) => {
                         info!("Contract-call to {}.{:?} args {:?} returned {:?}", &contract_id, &contract_call.function_name, &contract_call.function_args, &return_value);
                         Ok((return_value, asset_map, events))
@@ -431,7 +524,7 @@ impl StacksChainState {
                     contract_analysis: None,
                 };
 
-                Ok(receipt)
+                Ok((receipt, asset_map, post_conditions_passed))
             },
             TransactionPayload::SmartContract(ref smart_contract) => {
                 let issuer_principal = match origin_account.principal {
@@ -460,6 +553,12 @@ impl StacksChainState {
                 // operation, and the paying account will need to be debited the fee regardless.
                 let (contract_ast, contract_analysis) = match clarity_tx.connection().analyze_smart_contract(&contract_id, &contract_code_str) {
                     Ok((ast, analysis)) => (ast, analysis),
+                    Err(clarity_error::CostError(total, limit)) => {
+                        // running out of block budget isn't the sender's fault -- the miner
+                        // shouldn't have included it
+                        info!("Smart-contract transaction {} exceeds the block execution budget", &tx.txid());
+                        return Err(Error::ClarityError(clarity_error::CostError(total, limit)));
+                    },
                     Err(e) => {
                         // this analysis isn't free -- convert to runtime error
                         error!("Runtime error in contract analysis for {}: {}", &contract_id, &e);
@@ -472,7 +571,7 @@ impl StacksChainState {
                         };
                 
                         // abort now -- no burns
-                        return Ok(receipt);
+                        return Ok((receipt, AssetMap::new(), post_conditions_passed));
                     }
                 };
 
@@ -480,7 +579,10 @@ impl StacksChainState {
                 // accepted, but the contract does not materialize (but the sender is out their fee).
                 let (asset_map, events) = match clarity_tx.connection().initialize_smart_contract(
                     &contract_id, &contract_ast, &contract_code_str,
-                    |asset_map, _| { !StacksChainState::check_transaction_postconditions(&tx.post_conditions, &tx.post_condition_mode, origin_account, asset_map) }) {
+                    |asset_map, _| {
+                        post_conditions_passed = StacksChainState::check_transaction_postconditions(&tx.post_conditions, &tx.post_condition_mode, origin_account, asset_map);
+                        !post_conditions_passed
+                    }) {
                     Ok((asset_map, events)) => {
                         Ok((asset_map, events))
                     },
@@ -511,9 +613,9 @@ impl StacksChainState {
                     contract_analysis: Some(contract_analysis),
                 };
 
-                Ok(receipt)
+                Ok((receipt, asset_map, post_conditions_passed))
             },
-            TransactionPayload::PoisonMicroblock(ref _mblock_header_1, ref _mblock_header_2) => {
+            TransactionPayload::PoisonMicroblock(ref mblock_header_1, ref mblock_header_2) => {
                 // post-conditions are not allowed for this variant, since they're non-sensical.
                 // Their presence in this variant makes the transaction invalid.
                 if tx.post_conditions.len() > 0 {
@@ -523,8 +625,27 @@ impl StacksChainState {
                     return Err(Error::InvalidStacksTransaction(msg));
                 }
 
-                // TODO: actually implement this, but not necessarily for this PR
-                panic!("Not implemented yet");
+                let microblock_pubkey_hash = StacksChainState::check_poison_microblock_headers(mblock_header_1, mblock_header_2)?;
+
+                // only the first report counts
+                if let Some((reporter, _)) = StacksChainState::get_poison_microblock_report(clarity_tx, &microblock_pubkey_hash)? {
+                    let msg = format!("Invalid Stacks transaction: microblock public key hash {} was already reported by {}", microblock_pubkey_hash.to_hex(), reporter.to_string());
+                    warn!("{}", &msg);
+
+                    return Err(Error::InvalidStacksTransaction(msg));
+                }
+
+                StacksChainState::insert_poison_microblock_report(clarity_tx, &microblock_pubkey_hash, &tx.origin_address(), mblock_header_1.sequence)?;
+
+                let receipt = StacksTransactionReceipt {
+                    transaction: tx.clone(),
+                    events: vec![],
+                    result: Value::okay_true(),
+                    stx_burned: 0,
+                    contract_analysis: None,
+                };
+
+                Ok((receipt, AssetMap::new(), post_conditions_passed))
             },
             TransactionPayload::Coinbase(_) => {
                 // no-op; not handled here
@@ -537,25 +658,37 @@ impl StacksChainState {
                     contract_analysis: None,
                 };
 
-                Ok(receipt)
+                Ok((receipt, AssetMap::new(), post_conditions_passed))
             }
         }
     }
 
-    /// Process a transaction.  Return the fee, the amount of STX destroyed and the events emitted
-    pub fn process_transaction<'a>(clarity_tx: &mut ClarityTx<'a>, tx: &StacksTransaction) -> Result<(u64, StacksTransactionReceipt), Error> {
-        debug!("Process transaction {}", tx.txid());
+    /// Charge a transaction's fee, run its payload, and bump its nonces -- i.e. everything
+    /// process_transaction() does besides checking that the transaction is well-formed.
+    /// Return the outcome -- the fee, the transaction's execution cost, its receipt, and what the
+    /// payload did to assets.
+    fn apply_transaction<'a>(clarity_tx: &mut ClarityTx<'a>, tx: &StacksTransaction) -> Result<StacksTransactionOutcome, Error> {
+        let (origin_account, payer_account) = StacksChainState::check_transaction_nonces(clarity_tx, tx)?;
 
-        StacksChainState::process_transaction_precheck(&clarity_tx.config, tx)?;
+        let mut tx_bytes = vec![];
+        tx.consensus_serialize(&mut tx_bytes).map_err(Error::NetError)?;
+        let tx_len = tx_bytes.len() as u64;
 
-        let (origin_account, payer_account) = StacksChainState::check_transaction_nonces(clarity_tx, tx)?;
+        // pay the length-proportional part of the fee up front, so a transaction that can't even
+        // afford its own bytes doesn't get to run
+        let len_fee = StacksChainState::get_tx_fee(tx, tx_len, &ExecutionCost::zero()).ok_or(Error::InvalidFee)?;
+        StacksChainState::pay_transaction_fee(clarity_tx, &payer_account.principal, len_fee)?;
 
-        // pay fee
-        // TODO: don't do this here; do it when we know what the STX/compute rate will be, and then
-        // debit the account (aborting the _whole block_ if the balance would go negative)
-        let fee = StacksChainState::pay_transaction_fee(clarity_tx, tx, &payer_account)?;
-    
-        let tx_receipt = StacksChainState::process_transaction_payload(clarity_tx, tx, &origin_account)?;
+        let cost_before = clarity_tx.cost_so_far();
+        let (receipt, asset_map, post_conditions_passed) = StacksChainState::run_transaction_payload(clarity_tx, tx, &origin_account)?;
+
+        // pay the cost-proportional part of the fee, now that we know what the payload consumed.
+        // If the payer can't afford it, the transaction is invalid.
+        let mut tx_cost = clarity_tx.cost_so_far();
+        tx_cost.sub(&cost_before).expect("BUG: execution cost decreased while processing a transaction");
+
+        let fee = StacksChainState::get_tx_fee(tx, tx_len, &tx_cost).ok_or(Error::InvalidFee)?;
+        StacksChainState::pay_transaction_fee(clarity_tx, &payer_account.principal, fee - len_fee)?;
 
         // update the account nonces
         StacksChainState::update_account_nonce(clarity_tx, &origin_account);
@@ -563,7 +696,51 @@ impl StacksChainState {
             StacksChainState::update_account_nonce(clarity_tx, &payer_account);
         }
 
-        Ok((fee, tx_receipt))
+        Ok(StacksTransactionOutcome {
+            fee: fee,
+            execution_cost: tx_cost,
+            receipt: receipt,
+            asset_map: asset_map,
+            post_conditions_passed: post_conditions_passed
+        })
+    }
+
+    /// Process a transaction.  Return the fee, the amount of STX destroyed and the events emitted.
+    /// The fee is the transaction's fee rate times the fee units it consumed (see
+    /// get_tx_fee_units()).
+    pub fn process_transaction<'a>(clarity_tx: &mut ClarityTx<'a>, tx: &StacksTransaction) -> Result<(u64, StacksTransactionReceipt), Error> {
+        debug!("Process transaction {}", tx.txid());
+
+        StacksChainState::process_transaction_precheck(&clarity_tx.config, tx)?;
+
+        let outcome = StacksChainState::apply_transaction(clarity_tx, tx)?;
+        Ok((outcome.fee, outcome.receipt))
+    }
+
+    /// Dry-run a transaction as if it were mined on top of the given block.  Its signatures are
+    /// not checked, so it can be unsigned.  Nothing it does is kept.
+    /// Return everything processing it produced -- the fee, execution cost, receipt, asset
+    /// movements, and whether or not its post-conditions passed.
+    pub fn simulate_transaction(&mut self, index_block_hash: &BlockHeaderHash, tx: &StacksTransaction) -> Result<StacksTransactionOutcome, Error> {
+        self.check_historical_block(index_block_hash)?;
+
+        let parent_header_info = StacksChainState::get_stacks_block_header_info_by_index_block_hash(&self.headers_db, index_block_hash)?
+            .ok_or(Error::NoSuchBlockError)?;
+
+        let mut clarity_tx = self.block_begin(&parent_header_info.burn_header_hash, &parent_header_info.anchored_header.block_hash(), &MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH);
+        clarity_tx.reset_cost(ANCHORED_BLOCK_COST_LIMIT);
+
+        let result = StacksChainState::apply_transaction(&mut clarity_tx, tx);
+        clarity_tx.rollback_block();
+        result
+    }
+
+    /// Estimate the fee a transaction would pay if it were mined on top of the given block, by
+    /// dry-running it (see simulate_transaction()).
+    /// Return the fee and the transaction's execution cost.
+    pub fn estimate_transaction_fee(&mut self, index_block_hash: &BlockHeaderHash, tx: &StacksTransaction) -> Result<(u64, ExecutionCost), Error> {
+        let outcome = self.simulate_transaction(index_block_hash, tx)?;
+        Ok((outcome.fee, outcome.execution_cost))
     }
 }
 
@@ -574,6 +751,7 @@ pub mod test {
     use chainstate::stacks::*;
     use chainstate::stacks::Error;
     use chainstate::stacks::db::test::*;
+    use chainstate::stacks::db::accounts::MinerReward;
     use chainstate::stacks::index::*;
     use chainstate::stacks::index::storage::*;
     use burnchains::Address;
@@ -861,6 +1039,160 @@ pub mod test {
         assert!(contract_res.is_ok());
     }
 
+    #[test]
+    fn get_tx_fee_units() {
+        // no execution cost: just the length
+        assert_eq!(StacksChainState::get_tx_fee_units(123, &ExecutionCost::zero()), 123);
+
+        // any use of a dimension rounds up to at least one unit
+        assert_eq!(StacksChainState::get_tx_fee_units(123, &ExecutionCost::runtime(1)), 124);
+
+        // using up a whole dimension of the block budget costs as much as a whole epoch's worth of bytes
+        assert_eq!(StacksChainState::get_tx_fee_units(123, &ExecutionCost::runtime(ANCHORED_BLOCK_COST_LIMIT.runtime)), 123 + (MAX_EPOCH_SIZE as u64));
+        assert_eq!(StacksChainState::get_tx_fee_units(0, &ANCHORED_BLOCK_COST_LIMIT), 5 * (MAX_EPOCH_SIZE as u64));
+
+        // half of the write count budget
+        let cost = ExecutionCost {
+            write_length: 0,
+            write_count: ANCHORED_BLOCK_COST_LIMIT.write_count / 2,
+            read_length: 0,
+            read_count: 0,
+            runtime: 0
+        };
+        assert_eq!(StacksChainState::get_tx_fee_units(0, &cost), (MAX_EPOCH_SIZE as u64) / 2);
+
+        // saturates instead of overflowing
+        assert_eq!(StacksChainState::get_tx_fee_units(u64::max_value(), &ExecutionCost::max_value()), u64::max_value());
+    }
+
+    #[test]
+    fn process_smart_contract_transaction_fee() {
+        let contract = "
+        (define-data-var bar int 0)
+        (define-public (get-bar) (ok (var-get bar)))";
+
+        let mut chainstate = instantiate_chainstate(false, 0x80000000, "process-smart-contract-transaction-fee");
+
+        let privk = StacksPrivateKey::from_hex("6d430bb91222408e7706c9001cfaeb91b08c2be6d5ac95779ab52c6b431950e001").unwrap();
+        let auth = TransactionAuth::from_p2pkh(&privk).unwrap();
+        let addr = auth.origin().address_testnet();
+
+        let privk_poor = StacksPrivateKey::from_hex("7e3af4db6af6b3c67e2c6c6d7d5983b519f4d9b3a6e00580ae96dcace3bde8bc01").unwrap();
+        let auth_poor = TransactionAuth::from_p2pkh(&privk_poor).unwrap();
+        let addr_poor = auth_poor.origin().address_testnet();
+
+        let make_signed_tx = |auth: &TransactionAuth, privk: &StacksPrivateKey| {
+            let mut tx_contract = StacksTransaction::new(TransactionVersion::Testnet,
+                                                         auth.clone(),
+                                                         TransactionPayload::new_smart_contract(&"hello-world".to_string(), &contract.to_string()).unwrap());
+
+            tx_contract.chain_id = 0x80000000;
+            tx_contract.set_fee_rate(2);
+
+            let mut signer = StacksTransactionSigner::new(&tx_contract);
+            signer.sign_origin(privk).unwrap();
+            signer.get_tx().unwrap()
+        };
+
+        let signed_tx = make_signed_tx(&auth, &privk);
+        let signed_tx_poor = make_signed_tx(&auth_poor, &privk_poor);
+
+        let mut tx_bytes = vec![];
+        signed_tx.consensus_serialize(&mut tx_bytes).unwrap();
+        let tx_len = tx_bytes.len() as u64;
+
+        let mut conn = chainstate.block_begin(&FIRST_BURNCHAIN_BLOCK_HASH, &FIRST_STACKS_BLOCK_HASH, &BurnchainHeaderHash([1u8; 32]), &BlockHeaderHash([1u8; 32]));
+
+        // can afford the bytes, but not the execution cost
+        StacksChainState::account_credit(&mut conn, &addr_poor.to_account_principal(), 2 * tx_len);
+        let res = StacksChainState::process_transaction(&mut conn, &signed_tx_poor);
+        assert!(if let Err(Error::InvalidFee) = res { true } else { false });
+
+        // can afford both
+        StacksChainState::account_credit(&mut conn, &addr.to_account_principal(), 1_000_000);
+        let cost_before = conn.cost_so_far();
+        let (fee, _) = StacksChainState::process_transaction(&mut conn, &signed_tx).unwrap();
+
+        let mut tx_cost = conn.cost_so_far();
+        tx_cost.sub(&cost_before).unwrap();
+
+        let account = StacksChainState::get_account(&mut conn, &addr.to_account_principal());
+        assert_eq!(account.nonce, 1);
+        assert_eq!(account.stx_balance, 1_000_000 - (fee as u128));
+
+        conn.commit_block();
+
+        // charged for both the bytes and the execution cost
+        assert!(fee > 2 * tx_len);
+        assert_eq!(fee, 2 * StacksChainState::get_tx_fee_units(tx_len, &tx_cost));
+    }
+
+    #[test]
+    fn apply_transaction_outcome_postconditions() {
+        let contract = "
+        (define-public (burn (amount uint)) (stx-burn? amount tx-sender))";
+
+        let mut chainstate = instantiate_chainstate(false, 0x80000000, "apply-transaction-outcome-postconditions");
+
+        let privk = StacksPrivateKey::from_hex("6d430bb91222408e7706c9001cfaeb91b08c2be6d5ac95779ab52c6b431950e001").unwrap();
+        let auth = TransactionAuth::from_p2pkh(&privk).unwrap();
+        let addr = auth.origin().address_testnet();
+
+        let mut tx_contract = StacksTransaction::new(TransactionVersion::Testnet,
+                                                     auth.clone(),
+                                                     TransactionPayload::new_smart_contract(&"hello-world".to_string(), &contract.to_string()).unwrap());
+
+        tx_contract.chain_id = 0x80000000;
+        tx_contract.set_fee_rate(0);
+
+        // unsigned -- apply_transaction() doesn't check signatures
+        let mut tx_burn = StacksTransaction::new(TransactionVersion::Testnet,
+                                                 auth.clone(),
+                                                 TransactionPayload::new_contract_call(addr.clone(), "hello-world", "burn", vec![Value::UInt(100)]).unwrap());
+
+        tx_burn.chain_id = 0x80000000;
+        tx_burn.set_fee_rate(0);
+        tx_burn.set_origin_nonce(1);
+
+        let mut tx_burn_pass = tx_burn.clone();
+        tx_burn_pass.add_post_condition(TransactionPostCondition::STX(PostConditionPrincipal::Origin, FungibleConditionCode::SentEq, 100));
+
+        let mut tx_burn_fail = tx_burn.clone();
+        tx_burn_fail.add_post_condition(TransactionPostCondition::STX(PostConditionPrincipal::Origin, FungibleConditionCode::SentLt, 100));
+
+        let mut conn = chainstate.block_begin(&FIRST_BURNCHAIN_BLOCK_HASH, &FIRST_STACKS_BLOCK_HASH, &BurnchainHeaderHash([1u8; 32]), &BlockHeaderHash([1u8; 32]));
+        StacksChainState::account_credit(&mut conn, &addr.to_account_principal(), 1000);
+
+        let outcome = StacksChainState::apply_transaction(&mut conn, &tx_contract).unwrap();
+        assert!(outcome.post_conditions_passed);
+        assert!(outcome.receipt.contract_analysis.is_some());
+
+        // post-condition fails, so the burn is reported but not kept (the nonce still gets bumped,
+        // so discard that afterwards)
+        conn.hold_writes();
+        let outcome = StacksChainState::apply_transaction(&mut conn, &tx_burn_fail).unwrap();
+        assert!(!outcome.post_conditions_passed);
+        assert_eq!(outcome.asset_map.get_stx_burned(&addr.to_account_principal()), Some(100));
+
+        let account = StacksChainState::get_account(&mut conn, &addr.to_account_principal());
+        assert_eq!(account.stx_balance, 1000);
+        assert_eq!(account.nonce, 2);
+        conn.discard_writes();
+
+        // post-condition passes, so the burn is kept
+        let outcome = StacksChainState::apply_transaction(&mut conn, &tx_burn_pass).unwrap();
+        assert!(outcome.post_conditions_passed);
+        assert_eq!(outcome.asset_map.get_stx_burned(&addr.to_account_principal()), Some(100));
+        assert_eq!(outcome.receipt.stx_burned, 100);
+        assert_eq!(outcome.fee, 0);
+
+        let account = StacksChainState::get_account(&mut conn, &addr.to_account_principal());
+        assert_eq!(account.stx_balance, 900);
+        assert_eq!(account.nonce, 2);
+
+        conn.commit_block();
+    }
+
     #[test]
     fn process_smart_contract_transaction_invalid() {
         let contract_correct = "
@@ -2296,6 +2628,117 @@ pub mod test {
         conn.commit_block();
     }
 
+    fn make_poison_microblock_tx(privk: &StacksPrivateKey, nonce: u64, mblock_header_1: &StacksMicroblockHeader, mblock_header_2: &StacksMicroblockHeader) -> StacksTransaction {
+        let mut auth = TransactionAuth::from_p2pkh(privk).unwrap();
+        auth.set_origin_nonce(nonce);
+
+        let mut tx_poison = StacksTransaction::new(TransactionVersion::Testnet,
+                                                   auth,
+                                                   TransactionPayload::PoisonMicroblock(mblock_header_1.clone(), mblock_header_2.clone()));
+        tx_poison.chain_id = 0x80000000;
+        tx_poison.set_fee_rate(0);
+
+        let mut signer = StacksTransactionSigner::new(&tx_poison);
+        signer.sign_origin(privk).unwrap();
+        signer.get_tx().unwrap()
+    }
+
+    fn get_miner_rewards_available<T: ClarityConnection>(conn: &mut T, addr: &StacksAddress) -> u128 {
+        let miner_contract_id = QualifiedContractIdentifier::parse(&format!("{}.{}", STACKS_BOOT_CODE_CONTRACT_ADDRESS, BOOT_CODE_MINER_CONTRACT_NAME)).unwrap();
+        let key = Value::Tuple(TupleData::from_data(vec![
+                (ClarityName::try_from(BOOT_CODE_MINER_REWARDS_PARTICIPANT.to_string()).unwrap(), Value::Principal(addr.to_account_principal()))]).unwrap());
+        let entry = conn.with_clarity_db_readonly(|ref mut db| db.fetch_entry(&miner_contract_id, BOOT_CODE_MINER_REWARDS_MAP, &key)).unwrap();
+        match entry {
+            Value::Optional(OptionalData { data: Some(ref status) }) => {
+                match **status {
+                    Value::Tuple(ref tuple) => match tuple.get(&ClarityName::try_from(BOOT_CODE_MINER_REWARDS_AVAILABLE.to_string()).unwrap()).unwrap() {
+                        Value::UInt(available) => *available,
+                        x => panic!("Unexpected available amount {:?}", x)
+                    },
+                    ref x => panic!("Unexpected miner status {:?}", x)
+                }
+            },
+            _ => 0
+        }
+    }
+
+    #[test]
+    fn process_poison_microblock_transaction() {
+        let mut chainstate = instantiate_chainstate(false, 0x80000000, "process-poison-microblock-transaction");
+
+        let privk = StacksPrivateKey::from_hex("6d430bb91222408e7706c9001cfaeb91b08c2be6d5ac95779ab52c6b431950e001").unwrap();
+        let addr = TransactionAuth::from_p2pkh(&privk).unwrap().origin().address_testnet();
+
+        let leader_privk = StacksPrivateKey::from_hex("59e4d5e18351d6027a37920efe53c2f1cbadc50dca7d77169b7291dff936ed6d01").unwrap();
+        let other_privk = StacksPrivateKey::from_hex("eb05c83546fdd2c79f10f5ad5434a90dd28f7e3acb7c092157aa1bc3656b012c01").unwrap();
+        let leader_pubkey_hash = StacksBlockHeader::pubkey_hash(&StacksPublicKey::from_private(&leader_privk));
+
+        let make_header = |sequence: u16, merkle_data: &[u8], signer: &StacksPrivateKey| {
+            let mut header = StacksMicroblockHeader {
+                version: 0,
+                sequence: sequence,
+                prev_block: BlockHeaderHash([0x11; 32]),
+                tx_merkle_root: Sha512Trunc256Sum::from_data(merkle_data),
+                signature: MessageSignature::empty()
+            };
+            header.sign(signer).unwrap();
+            header
+        };
+
+        let mblock_1 = make_header(3, &[1], &leader_privk);
+        let mblock_2 = make_header(3, &[2], &leader_privk);
+
+        let mut conn = chainstate.block_begin(&FIRST_BURNCHAIN_BLOCK_HASH, &FIRST_STACKS_BLOCK_HASH, &BurnchainHeaderHash([1u8; 32]), &BlockHeaderHash([1u8; 32]));
+
+        // not equivocations
+        let error_frags = vec![
+            (make_header(3, &[1], &leader_privk), make_header(4, &[2], &leader_privk), "sequence numbers 3 and 4 differ"),
+            (mblock_1.clone(), mblock_1.clone(), "both headers are microblock"),
+            (mblock_1.clone(), make_header(3, &[2], &other_privk), "signed by different keys"),
+        ];
+        for (h1, h2, err_frag) in error_frags.into_iter() {
+            match StacksChainState::process_transaction(&mut conn, &make_poison_microblock_tx(&privk, 0, &h1, &h2)) {
+                Err(Error::InvalidStacksTransaction(msg)) => assert!(msg.contains(err_frag), "{}", msg),
+                res => panic!("Expected InvalidStacksTransaction, got {:?}", res)
+            }
+        }
+
+        assert_eq!(StacksChainState::get_poison_microblock_report(&mut conn, &leader_pubkey_hash).unwrap(), None);
+
+        StacksChainState::process_transaction(&mut conn, &make_poison_microblock_tx(&privk, 0, &mblock_1, &mblock_2)).unwrap();
+        assert_eq!(StacksChainState::get_poison_microblock_report(&mut conn, &leader_pubkey_hash).unwrap(), Some((addr.clone(), 3)));
+
+        // only the first report counts
+        match StacksChainState::process_transaction(&mut conn, &make_poison_microblock_tx(&privk, 1, &mblock_2, &mblock_1)) {
+            Err(Error::InvalidStacksTransaction(msg)) => assert!(msg.contains("already reported"), "{}", msg),
+            res => panic!("Expected InvalidStacksTransaction, got {:?}", res)
+        }
+
+        // the leader's reward is withheld when it matures, and the reporter gets a bounty
+        let leader_addr = StacksAddress { version: 26, bytes: Hash160([0x22; 20]) };
+        let honest_addr = StacksAddress { version: 26, bytes: Hash160([0x33; 20]) };
+        let supporter_addr = StacksAddress { version: 26, bytes: Hash160([0x44; 20]) };
+
+        let mut leader_reward = MinerReward::empty_miner(&leader_addr);
+        leader_reward.coinbase = 1000;
+        leader_reward.microblock_pubkey_hash = Some(leader_pubkey_hash.clone());
+        let mut supporter_reward = MinerReward::empty_user(&supporter_addr, 1);
+        supporter_reward.coinbase = 500;
+        StacksChainState::process_matured_miner_rewards(&mut conn, &vec![leader_reward, supporter_reward]).unwrap();
+
+        let mut honest_reward = MinerReward::empty_miner(&honest_addr);
+        honest_reward.coinbase = 1000;
+        honest_reward.microblock_pubkey_hash = Some(Hash160([0x55; 20]));
+        StacksChainState::process_matured_miner_rewards(&mut conn, &vec![honest_reward]).unwrap();
+
+        assert_eq!(get_miner_rewards_available(&mut conn, &leader_addr), 0);
+        assert_eq!(get_miner_rewards_available(&mut conn, &addr), 1000 * POISON_MICROBLOCK_BOUNTY_PCT / 100);
+        assert_eq!(get_miner_rewards_available(&mut conn, &supporter_addr), 500);
+        assert_eq!(get_miner_rewards_available(&mut conn, &honest_addr), 1000);
+
+        conn.commit_block();
+    }
+
     fn make_account(principal: &PrincipalData, nonce: u64, balance: u128) -> StacksAccount {
         StacksAccount {
             principal: principal.clone(),
@@ -0,0 +1,338 @@
+/*
+ copyright: (c) 2013-2019 by Blockstack PBC, a public benefit corporation.
+
+ This file is part of Blockstack.
+
+ Blockstack is free software. You may redistribute or modify
+ it under the terms of the GNU General Public License as published by
+ the Free Software Foundation, either version 3 of the License or
+ (at your option) any later version.
+
+ Blockstack is distributed in the hope that it will be useful,
+ but WITHOUT ANY WARRANTY, including without the implied warranty of
+ MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ GNU General Public License for more details.
+
+ You should have received a copy of the GNU General Public License
+ along with Blockstack. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use chainstate::stacks::index::TrieHash;
+use chainstate::stacks::index::node::TrieNodeType;
+
+/// Default number of nodes (or node hashes) kept in a TrieFileStorage's node cache.
+/// Most cached nodes are leaves and small nodes, but a Node256 is a few KB, so keep this modest.
+pub const TRIE_NODE_CACHE_DEFAULT_CAPACITY : usize = 32768;
+
+const NIL : usize = usize::max_value();
+
+struct LruEntry<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+/// Bounded least-recently-used map.  Entries live in a slab and are threaded onto a doubly-linked
+/// recency list by index, so lookups, inserts and evictions are all O(1).
+pub struct LruCache<K: Hash + Eq + Clone, V> {
+    capacity: usize,
+    index: HashMap<K, usize>,
+    entries: Vec<LruEntry<K, V>>,
+    head: usize,    // most-recently used
+    tail: usize,    // least-recently used
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache {
+            capacity: capacity,
+            index: HashMap::new(),
+            entries: vec![],
+            head: NIL,
+            tail: NIL
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.entries.clear();
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    /// Change the capacity.  Shrinking it discards everything cached.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        if capacity < self.len() {
+            self.clear();
+        }
+        self.capacity = capacity;
+    }
+
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = (self.entries[idx].prev, self.entries[idx].next);
+        if prev != NIL {
+            self.entries[prev].next = next;
+        }
+        else {
+            self.head = next;
+        }
+        if next != NIL {
+            self.entries[next].prev = prev;
+        }
+        else {
+            self.tail = prev;
+        }
+    }
+
+    fn push_front(&mut self, idx: usize) {
+        self.entries[idx].prev = NIL;
+        self.entries[idx].next = self.head;
+        if self.head != NIL {
+            self.entries[self.head].prev = idx;
+        }
+        self.head = idx;
+        if self.tail == NIL {
+            self.tail = idx;
+        }
+    }
+
+    /// Look up a value, marking it as most-recently used.
+    pub fn get(&mut self, key: &K) -> Option<&mut V> {
+        let idx = match self.index.get(key) {
+            Some(idx) => *idx,
+            None => {
+                return None;
+            }
+        };
+        if idx != self.head {
+            self.unlink(idx);
+            self.push_front(idx);
+        }
+        Some(&mut self.entries[idx].value)
+    }
+
+    /// Insert or replace a value, evicting the least-recently used value if the cache is full.
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if let Some(idx) = self.index.get(&key).cloned() {
+            if idx != self.head {
+                self.unlink(idx);
+                self.push_front(idx);
+            }
+            self.entries[idx].value = value;
+            return;
+        }
+
+        let idx =
+            if self.index.len() >= self.capacity {
+                // reuse the least-recently used slot
+                let idx = self.tail;
+                self.unlink(idx);
+                self.index.remove(&self.entries[idx].key);
+                self.entries[idx].key = key.clone();
+                self.entries[idx].value = value;
+                idx
+            }
+            else {
+                self.entries.push(LruEntry {
+                    key: key.clone(),
+                    value: value,
+                    prev: NIL,
+                    next: NIL
+                });
+                self.entries.len() - 1
+            };
+
+        self.push_front(idx);
+        self.index.insert(key, idx);
+    }
+}
+
+/// Cache of decoded nodes and node hashes from sealed tries, keyed by (block identifier, node
+/// pointer).  Sealed tries never change, so entries never need to be invalidated -- only evicted.
+pub struct TrieNodeCache {
+    cache: LruCache<(u32, u32), (Option<TrieNodeType>, TrieHash)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl TrieNodeCache {
+    pub fn new(capacity: usize) -> TrieNodeCache {
+        TrieNodeCache {
+            cache: LruCache::new(capacity),
+            hits: 0,
+            misses: 0
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cache.capacity()
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.cache.set_capacity(capacity);
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    fn count(&mut self, hit: bool) {
+        if self.cache.capacity() == 0 {
+            return;
+        }
+        if hit {
+            self.hits += 1;
+        }
+        else {
+            self.misses += 1;
+        }
+    }
+
+    /// Get a decoded node and its hash.  node_id is the node type the caller expects to find.
+    pub fn get_node(&mut self, block_id: u32, ptr: u32, node_id: u8) -> Option<(TrieNodeType, TrieHash)> {
+        let res = match self.cache.get(&(block_id, ptr)) {
+            Some((Some(node), hash)) => {
+                if node.id() == node_id {
+                    Some((node.clone(), hash.clone()))
+                }
+                else {
+                    None
+                }
+            },
+            _ => None
+        };
+        self.count(res.is_some());
+        res
+    }
+
+    /// Get a node's hash
+    pub fn get_hash(&mut self, block_id: u32, ptr: u32) -> Option<TrieHash> {
+        let res = match self.cache.get(&(block_id, ptr)) {
+            Some((_, hash)) => Some(hash.clone()),
+            None => None
+        };
+        self.count(res.is_some());
+        res
+    }
+
+    pub fn store_node(&mut self, block_id: u32, ptr: u32, node: TrieNodeType, hash: TrieHash) {
+        self.cache.insert((block_id, ptr), (Some(node), hash));
+    }
+
+    pub fn store_hash(&mut self, block_id: u32, ptr: u32, hash: TrieHash) {
+        // don't clobber a cached node with just its hash
+        if let Some((_, cached_hash)) = self.cache.get(&(block_id, ptr)) {
+            *cached_hash = hash;
+            return;
+        }
+        self.cache.insert((block_id, ptr), (None, hash));
+    }
+
+    /// Get and reset the (hits, misses) counters
+    pub fn stats(&mut self) -> (u64, u64) {
+        let ret = (self.hits, self.misses);
+        self.hits = 0;
+        self.misses = 0;
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chainstate::stacks::index::node::*;
+
+    #[test]
+    fn lru_cache_evicts_least_recently_used() {
+        let mut cache = LruCache::new(3);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        cache.insert(3, "c");
+
+        // 1 becomes the most-recently used
+        assert_eq!(cache.get(&1).cloned(), Some("a"));
+
+        // evicts 2
+        cache.insert(4, "d");
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get(&2).is_none());
+
+        // replacing promotes, and doesn't evict
+        cache.insert(3, "C");
+        assert_eq!(cache.len(), 3);
+
+        // evicts 1, then 4
+        cache.insert(5, "e");
+        cache.insert(6, "f");
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&4).is_none());
+        assert_eq!(cache.get(&3).cloned(), Some("C"));
+        assert_eq!(cache.get(&5).cloned(), Some("e"));
+        assert_eq!(cache.get(&6).cloned(), Some("f"));
+
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 0);
+        cache.insert(7, "g");
+        cache.insert(8, "h");
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&8).cloned(), Some("h"));
+
+        let mut disabled = LruCache::new(0);
+        disabled.insert(1, "a");
+        assert_eq!(disabled.len(), 0);
+    }
+
+    #[test]
+    fn trie_node_cache_stats() {
+        let mut cache = TrieNodeCache::new(16);
+        let leaf = TrieNodeType::Leaf(TrieLeaf::new(&vec![], &[1u8; 40].to_vec()));
+        let hash = TrieHash([2u8; 32]);
+
+        assert!(cache.get_node(1, 100, TrieNodeID::Leaf).is_none());
+        cache.store_hash(1, 100, hash.clone());
+
+        // only the hash is known
+        assert!(cache.get_node(1, 100, TrieNodeID::Leaf).is_none());
+        assert_eq!(cache.get_hash(1, 100), Some(hash.clone()));
+
+        cache.store_node(1, 100, leaf.clone(), hash.clone());
+        assert_eq!(cache.get_node(1, 100, TrieNodeID::Leaf), Some((leaf.clone(), hash.clone())));
+
+        // wrong node type, or different block
+        assert!(cache.get_node(1, 100, TrieNodeID::Node4).is_none());
+        assert!(cache.get_hash(2, 100).is_none());
+
+        // storing a hash doesn't forget the node
+        cache.store_hash(1, 100, hash.clone());
+        assert_eq!(cache.get_node(1, 100, TrieNodeID::Leaf), Some((leaf.clone(), hash.clone())));
+
+        assert_eq!(cache.stats(), (3, 4));
+        assert_eq!(cache.stats(), (0, 0));
+
+        // a disabled cache counts nothing
+        let mut cache = TrieNodeCache::new(0);
+        cache.store_node(1, 100, leaf.clone(), hash.clone());
+        assert!(cache.get_node(1, 100, TrieNodeID::Leaf).is_none());
+        assert_eq!(cache.stats(), (0, 0));
+    }
+}
@@ -65,7 +65,8 @@ use chainstate::stacks::index::node::{
 };
 
 use chainstate::stacks::index::storage::{
-    TrieFileStorage
+    TrieFileStorage,
+    TrieStorageLayout
 };
 
 use chainstate::stacks::index::{
@@ -483,8 +484,10 @@ impl MARF {
     /// Instantiate the MARF using a TrieFileStorage instance, from the given path on disk.
     /// This will have the side-effect of instantiating a new fork table from the tries encoded on
     /// disk. Performant code should call this method sparingly.
-    pub fn from_path(path: &str, miner_tip: Option<&BlockHeaderHash>) -> Result<MARF, Error> {
-        let mut file_storage = TrieFileStorage::new(path)?;
+    /// A new MARF is created with the given storage layout; an existing MARF keeps the layout it
+    /// was created with (use TrieFileStorage::migrate_to_packed() to convert it).
+    pub fn from_path(path: &str, miner_tip: Option<&BlockHeaderHash>, layout: TrieStorageLayout) -> Result<MARF, Error> {
+        let mut file_storage = TrieFileStorage::open(path, layout)?;
         match fs::metadata(path) {
             Ok(_) => {},
             Err(e) => {
@@ -793,6 +796,15 @@ impl MARF {
             .map(|x| x.height)
     }
 
+    /// Delete a block's trie from the MARF.  No other block's trie may descend from it, and no
+    /// block may be open for writing.
+    pub fn delete_block(&mut self, block_hash: &BlockHeaderHash) -> Result<(), Error> {
+        if self.open_chain_tip.is_some() {
+            return Err(Error::InProgressError);
+        }
+        self.storage.delete_block(block_hash)
+    }
+
     /// Get all known chain tips
     pub fn chain_tips(&mut self) -> Vec<BlockHeaderHash> {
         self.storage.chain_tips()
@@ -1806,7 +1818,8 @@ mod test {
             // merkle_test_marf(&mut f, &block_header, &path.to_vec(), &value.data.to_vec(), None);
             if i % 128 == 0 {
                 let end_time = get_epoch_time_ms();
-                let (read_count, write_count) = f.stats();
+                let stats = f.stats();
+                let (read_count, write_count) = (stats.reads, stats.writes);
                 let (node_reads, backptr_reads, node_writes) = f.node_stats();
                 let (leaf_reads, leaf_writes) = f.leaf_stats();
                 test_debug!("Got {} in {} (1 get = {} ms).  Read = {}, Write = {}, Node Reads = {}, Node Writes = {}, Backptr Reads = {}, Leaf Reads = {}, Leaf Writes = {}",
@@ -1911,7 +1924,7 @@ mod test {
             Err(_) => {}
         };
 
-        let mut m = MARF::from_path(&path, None).unwrap();
+        let mut m = MARF::from_path(&path, None, TrieStorageLayout::FilePerBlock).unwrap();
         let mut fork_headers = vec![];
         
         let mut pattern = 0u8;
@@ -2092,5 +2105,159 @@ mod test {
                 merkle_test_marf(marf.borrow_storage_backend(), &read_from_block, &path.to_vec(), &value.data.to_vec(), root_table_cache));
         }
     }
-}
 
+    fn populate_layout_test_marf(m: &mut MARF, num_blocks: u8) -> Vec<(BlockHeaderHash, TrieHash)> {
+        let mut blocks = vec![];
+        let mut parent = TrieFileStorage::block_sentinel();
+        for i in 0..num_blocks {
+            let bhh = BlockHeaderHash([i; 32]);
+            if i % 4 == 3 {
+                // exercise retargeting, like a block validator does
+                m.begin(&parent, &BlockHeaderHash([0xfe; 32])).unwrap();
+            }
+            else {
+                m.begin(&parent, &bhh).unwrap();
+            }
+
+            m.insert(&format!("key-{}", i), MARFValue::from_value(&format!("value-{}", i))).unwrap();
+            m.insert("shared", MARFValue::from_value(&format!("shared-{}", i))).unwrap();
+
+            if i % 4 == 3 {
+                m.commit_to(&bhh).unwrap();
+            }
+            else {
+                m.commit().unwrap();
+            }
+
+            let root_hash = m.get_root_hash_at(&bhh).unwrap();
+            blocks.push((bhh.clone(), root_hash));
+            parent = bhh;
+        }
+        blocks
+    }
+
+    fn check_layout_test_marf(m: &mut MARF, blocks: &Vec<(BlockHeaderHash, TrieHash)>) {
+        for (i, (bhh, root_hash)) in blocks.iter().enumerate() {
+            assert_eq!(m.get_root_hash_at(bhh).unwrap(), *root_hash);
+            assert_eq!(m.get(bhh, "shared").unwrap(), Some(MARFValue::from_value(&format!("shared-{}", i))));
+            for j in 0..blocks.len() {
+                let expected = if j <= i { Some(MARFValue::from_value(&format!("value-{}", j))) } else { None };
+                assert_eq!(m.get(bhh, &format!("key-{}", j)).unwrap(), expected);
+            }
+        }
+        assert_eq!(m.chain_tips(), vec![blocks[blocks.len() - 1].0.clone()]);
+    }
+
+    #[test]
+    fn marf_packed_layout() {
+        let packed_path = "/tmp/rust_marf_packed_layout".to_string();
+        let file_path = "/tmp/rust_marf_packed_layout_file_per_block".to_string();
+        for path in [&packed_path, &file_path].iter() {
+            if fs::metadata(path).is_ok() {
+                fs::remove_dir_all(path).unwrap();
+            }
+        }
+
+        let (packed_blocks, file_blocks) = {
+            let mut packed_marf = MARF::from_path(&packed_path, None, TrieStorageLayout::Packed).unwrap();
+            let mut file_marf = MARF::from_path(&file_path, None, TrieStorageLayout::FilePerBlock).unwrap();
+            assert_eq!(packed_marf.borrow_storage_backend().get_layout(), TrieStorageLayout::Packed);
+            assert_eq!(file_marf.borrow_storage_backend().get_layout(), TrieStorageLayout::FilePerBlock);
+
+            (populate_layout_test_marf(&mut packed_marf, 16), populate_layout_test_marf(&mut file_marf, 16))
+        };
+
+        // same tries, regardless of layout
+        assert_eq!(packed_blocks, file_blocks);
+
+        // no per-block files were written
+        for entry in fs::read_dir(&packed_path).unwrap() {
+            assert!(!entry.unwrap().path().is_dir());
+        }
+
+        // the layout is picked up on reopen, even if another one is requested
+        let mut packed_marf = MARF::from_path(&packed_path, None, TrieStorageLayout::FilePerBlock).unwrap();
+        assert_eq!(packed_marf.borrow_storage_backend().get_layout(), TrieStorageLayout::Packed);
+        check_layout_test_marf(&mut packed_marf, &packed_blocks);
+
+        // can't extend to a block that's already stored
+        match packed_marf.begin(&packed_blocks[15].0, &packed_blocks[3].0) {
+            Err(Error::ExistsError) => {},
+            x => panic!("Expected ExistsError, got {:?}", x)
+        }
+
+        // migrate the file-per-block MARF
+        assert_eq!(TrieFileStorage::migrate_to_packed(&file_path).unwrap(), 16);
+        match TrieFileStorage::migrate_to_packed(&file_path) {
+            Err(Error::ExistsError) => {},
+            x => panic!("Expected ExistsError, got {:?}", x)
+        }
+        for entry in fs::read_dir(&file_path).unwrap() {
+            assert!(!entry.unwrap().path().is_dir());
+        }
+
+        let mut migrated_marf = MARF::from_path(&file_path, None, TrieStorageLayout::FilePerBlock).unwrap();
+        assert_eq!(migrated_marf.borrow_storage_backend().get_layout(), TrieStorageLayout::Packed);
+        check_layout_test_marf(&mut migrated_marf, &file_blocks);
+
+        // the migrated MARF can keep growing
+        let next_bhh = BlockHeaderHash([0x10; 32]);
+        migrated_marf.begin(&file_blocks[15].0, &next_bhh).unwrap();
+        migrated_marf.insert("shared", MARFValue::from_value("shared-16")).unwrap();
+        migrated_marf.commit().unwrap();
+        assert_eq!(migrated_marf.get(&next_bhh, "shared").unwrap(), Some(MARFValue::from_value("shared-16")));
+        assert_eq!(migrated_marf.get(&next_bhh, "key-15").unwrap(), Some(MARFValue::from_value("value-15")));
+    }
+
+    #[test]
+    fn marf_node_cache() {
+        let path = "/tmp/rust_marf_node_cache".to_string();
+        if fs::metadata(&path).is_ok() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+
+        let mut m = MARF::from_path(&path, None, TrieStorageLayout::FilePerBlock).unwrap();
+        let mut parent = TrieFileStorage::block_sentinel();
+        let mut keys = vec![];
+        for i in 0..8u8 {
+            let bhh = BlockHeaderHash([i; 32]);
+            m.begin(&parent, &bhh).unwrap();
+            for j in 0..64 {
+                let key = format!("{}-{}", i, j);
+                m.insert(&key, MARFValue::from_value(&key)).unwrap();
+                keys.push(key);
+            }
+            m.commit().unwrap();
+            parent = bhh;
+        }
+
+        m.borrow_storage_backend().stats();
+
+        // nothing is cached from the in-RAM trie, so the first pass mostly misses
+        for key in keys.iter() {
+            assert_eq!(m.get(&parent, key).unwrap(), Some(MARFValue::from_value(key)));
+        }
+        let first_pass = m.borrow_storage_backend().stats();
+        assert!(first_pass.cache_misses > 0);
+
+        // ...and the second pass mostly hits
+        for key in keys.iter() {
+            assert_eq!(m.get(&parent, key).unwrap(), Some(MARFValue::from_value(key)));
+        }
+        let second_pass = m.borrow_storage_backend().stats();
+        assert_eq!(second_pass.reads, first_pass.reads);
+        assert!(second_pass.cache_hits > first_pass.cache_hits);
+        assert!(second_pass.cache_hit_rate() > 0.9);
+
+        // same answers, and no cache traffic, with the cache disabled
+        let mut m = MARF::from_path(&path, None, TrieStorageLayout::FilePerBlock).unwrap();
+        m.borrow_storage_backend().set_node_cache_capacity(0);
+        for key in keys.iter() {
+            assert_eq!(m.get(&parent, key).unwrap(), Some(MARFValue::from_value(key)));
+        }
+        let uncached = m.borrow_storage_backend().stats();
+        assert_eq!(uncached.reads, first_pass.reads);
+        assert_eq!(uncached.cache_hits, 0);
+        assert_eq!(uncached.cache_misses, 0);
+    }
+}
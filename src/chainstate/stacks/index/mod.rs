@@ -18,8 +18,10 @@
 */
 
 pub mod bits;
+pub mod cache;
 pub mod marf;
 pub mod node;
+pub mod packed;
 pub mod proofs;
 pub mod storage;
 pub mod trie;
@@ -39,6 +41,8 @@ use sha2::Digest;
 use std::hash::Hash;
 use chainstate::burn::BlockHeaderHash;
 
+use rusqlite::Error as SqliteError;
+
 use util::log;
 use util::hash::to_hex;
 
@@ -204,6 +208,36 @@ impl MARFValue {
         MARFValue::from_value_hash_bytes(&tmp)
     }
 
+    /// Construct from the bytes of a value inserted into the underlying data store
+    pub fn from_value_bytes(bytes: &[u8]) -> MARFValue {
+        let mut tmp = [0u8; 32];
+
+        let mut hasher = TrieHasher::new();
+        hasher.input(bytes);
+        tmp.copy_from_slice(hasher.result().as_slice());
+
+        MARFValue::from_value_hash_bytes(&tmp)
+    }
+
+    /// Same as from_value(&to_hex(bytes)), but without building the hex string
+    pub fn from_value_hex(bytes: &[u8]) -> MARFValue {
+        const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+        let mut tmp = [0u8; 32];
+        let mut buf = [0u8; 512];
+
+        let mut hasher = TrieHasher::new();
+        for chunk in bytes.chunks(buf.len() / 2) {
+            for (i, b) in chunk.iter().enumerate() {
+                buf[2*i] = HEX_CHARS[(b >> 4) as usize];
+                buf[2*i + 1] = HEX_CHARS[(b & 0x0f) as usize];
+            }
+            hasher.input(&buf[0..2*chunk.len()]);
+        }
+        tmp.copy_from_slice(hasher.result().as_slice());
+
+        MARFValue::from_value_hash_bytes(&tmp)
+    }
+
     /// Convert to a byte vector
     pub fn to_vec(&self) -> Vec<u8> {
         self.as_bytes().to_vec()
@@ -234,7 +268,8 @@ pub enum Error {
     WriteNotBegunError,
     CursorError(node::CursorError),
     RestoreMarfBlockError(Box<Error>),
-    NonMatchingForks(BlockHeaderHash, BlockHeaderHash)
+    NonMatchingForks(BlockHeaderHash, BlockHeaderHash),
+    SQLError(SqliteError)
 }
 
 impl From<io::Error> for Error {
@@ -249,6 +284,7 @@ impl fmt::Display for Error {
             Error::IOError(ref e) => fmt::Display::fmt(e, f),
             Error::CorruptionError(ref s) => fmt::Display::fmt(s, f),
             Error::CursorError(ref e) => fmt::Display::fmt(e, f),
+            Error::SQLError(ref e) => fmt::Display::fmt(e, f),
             Error::BlockHashMapCorruptionError(ref opt_e) => {
                 f.write_str("Corrupted MARF BlockHashMap")?;
                 match opt_e {
@@ -277,6 +313,7 @@ impl error::Error for Error {
         match *self {
             Error::IOError(ref e) => Some(e),
             Error::RestoreMarfBlockError(ref e) => Some(e),
+            Error::SQLError(ref e) => Some(e),
             Error::BlockHashMapCorruptionError(ref opt_e) => match opt_e {
                 Some(ref e) => Some(e),
                 None => None
@@ -0,0 +1,475 @@
+/*
+ copyright: (c) 2013-2019 by Blockstack PBC, a public benefit corporation.
+
+ This file is part of Blockstack.
+
+ Blockstack is free software. You may redistribute or modify
+ it under the terms of the GNU General Public License as published by
+ the Free Software Foundation, either version 3 of the License or
+ (at your option) any later version.
+
+ Blockstack is distributed in the hope that it will be useful,
+ but WITHOUT ANY WARRANTY, including without the implied warranty of
+ MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ GNU General Public License for more details.
+
+ You should have received a copy of the GNU General Public License
+ along with Blockstack. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/// Packed trie storage.
+///
+/// Instead of writing each sealed trie to its own file, the packed layout appends sealed tries
+/// back-to-back into a small number of large blob files (`tries.<n>.blob`), and records where
+/// each trie lives in a SQLite index (`tries.sqlite`).  A trie's bytes are identical to what would
+/// have been written to its own file in the file-per-block layout -- node pointers are relative to
+/// the start of the trie, not the start of the blob.
+///
+/// Appends are crash-safe:  the trie bytes are written and fsync'ed to the end of the current blob
+/// before the index row is committed, and every append first truncates the blob back to the end
+/// of the last indexed trie.  A crash between the two steps therefore leaves an unindexed tail
+/// which is never read and which the next append overwrites.
+
+use std::fs;
+use std::io;
+use std::io::{
+    Write,
+    Seek,
+    SeekFrom,
+};
+use std::path::{
+    Path,
+    PathBuf
+};
+
+use rusqlite::Connection;
+use rusqlite::OpenFlags;
+use rusqlite::OptionalExtension;
+use rusqlite::TransactionBehavior;
+use rusqlite::NO_PARAMS;
+use rusqlite::types::ToSql;
+
+use chainstate::burn::BlockHeaderHash;
+use chainstate::burn::BLOCK_HEADER_HASH_ENCODED_SIZE;
+
+use chainstate::stacks::index::storage::TrieBlockFile;
+use chainstate::stacks::index::Error as Error;
+
+use util::log;
+
+pub const PACKED_TRIE_DB_NAME : &'static str = "tries.sqlite";
+pub const PACKED_TRIE_MIGRATION_DB_NAME : &'static str = "tries.sqlite.migrating";
+
+/// Once a blob grows past this size, subsequent tries go into a new blob.
+pub const PACKED_TRIE_MAX_BLOB_SIZE : u64 = 1024 * 1024 * 1024;
+
+const PACKED_TRIE_SCHEMA : &'static [&'static str] = &[
+    r#"
+    CREATE TABLE IF NOT EXISTS marf_tries(
+        block_hash TEXT PRIMARY KEY NOT NULL,
+        identifier INTEGER NOT NULL,
+        parent_hash TEXT NOT NULL,
+        blob_id INTEGER NOT NULL,
+        offset INTEGER NOT NULL,
+        length INTEGER NOT NULL
+    );
+    "#,
+    r#"
+    CREATE INDEX IF NOT EXISTS marf_tries_by_blob ON marf_tries(blob_id,offset);
+    "#,
+];
+
+/// Where a sealed trie lives in the packed storage
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedTrieInfo {
+    pub block_hash: BlockHeaderHash,
+    pub identifier: u32,
+    pub parent: BlockHeaderHash,
+    pub blob_id: u32,
+    pub offset: u64,
+    pub length: u64,
+}
+
+pub struct TriePackedStore {
+    dir_path: String,
+    conn: Connection,
+    max_blob_size: u64,
+}
+
+impl TriePackedStore {
+    pub fn db_path(dir_path: &String) -> PathBuf {
+        Path::new(dir_path).join(PACKED_TRIE_DB_NAME)
+    }
+
+    pub fn blob_path(dir_path: &String, blob_id: u32) -> PathBuf {
+        Path::new(dir_path).join(format!("tries.{:08}.blob", blob_id))
+    }
+
+    /// Does the given MARF directory use the packed layout?
+    pub fn exists(dir_path: &String) -> bool {
+        fs::metadata(TriePackedStore::db_path(dir_path)).is_ok()
+    }
+
+    /// Open (and instantiate, if need be) the packed trie store in the given directory.
+    pub fn open(dir_path: &String) -> Result<TriePackedStore, Error> {
+        let db_path = TriePackedStore::db_path(dir_path);
+        TriePackedStore::open_db_at(dir_path, &db_path)
+    }
+
+    /// Instantiate an empty packed trie store that will be put into place with
+    /// commit_migration().  Any leftover state from an interrupted migration is discarded.
+    pub fn create_for_migration(dir_path: &String) -> Result<TriePackedStore, Error> {
+        let db_path = Path::new(dir_path).join(PACKED_TRIE_MIGRATION_DB_NAME);
+        match fs::remove_file(&db_path) {
+            Ok(_) => {
+                debug!("Removed stale migration index {:?}", &db_path);
+            },
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    return Err(Error::IOError(e));
+                }
+            }
+        }
+        TriePackedStore::open_db_at(dir_path, &db_path)
+    }
+
+    /// Atomically put a store created by create_for_migration() into place.
+    pub fn commit_migration(self) -> Result<(), Error> {
+        let dir_path = self.dir_path.clone();
+
+        // close the connection before renaming the database
+        drop(self);
+
+        let migration_path = Path::new(&dir_path).join(PACKED_TRIE_MIGRATION_DB_NAME);
+        let db_path = TriePackedStore::db_path(&dir_path);
+
+        debug!("Rename {:?} to {:?}", &migration_path, &db_path);
+        fs::rename(&migration_path, &db_path)
+            .map_err(Error::IOError)
+    }
+
+    fn open_db_at(dir_path: &String, db_path: &PathBuf) -> Result<TriePackedStore, Error> {
+        let open_flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE;
+        let conn = Connection::open_with_flags(db_path, open_flags)
+            .map_err(Error::SQLError)?;
+
+        for row_text in PACKED_TRIE_SCHEMA {
+            conn.execute(row_text, NO_PARAMS)
+                .map_err(Error::SQLError)?;
+        }
+
+        test_debug!("Opened packed trie store {:?}", db_path);
+        Ok(TriePackedStore {
+            dir_path: dir_path.clone(),
+            conn: conn,
+            max_blob_size: PACKED_TRIE_MAX_BLOB_SIZE
+        })
+    }
+
+    #[cfg(test)]
+    pub fn set_max_blob_size(&mut self, max_blob_size: u64) {
+        self.max_blob_size = max_blob_size;
+    }
+
+    fn parse_hash(hex_str: &String) -> Result<BlockHeaderHash, Error> {
+        BlockHeaderHash::from_hex(hex_str)
+            .map_err(|_e| Error::CorruptionError(format!("Invalid block hash in packed trie index: {}", hex_str)))
+    }
+
+    fn get_info(conn: &Connection, bhh: &BlockHeaderHash) -> Result<Option<PackedTrieInfo>, Error> {
+        let args : &[&dyn ToSql] = &[bhh];
+        let row_opt = conn.query_row("SELECT identifier, parent_hash, blob_id, offset, length FROM marf_tries WHERE block_hash = ?1",
+                                     args,
+                                     |row| {
+                                         let identifier : i64 = row.get(0);
+                                         let parent_hash : String = row.get(1);
+                                         let blob_id : i64 = row.get(2);
+                                         let offset : i64 = row.get(3);
+                                         let length : i64 = row.get(4);
+                                         (identifier, parent_hash, blob_id, offset, length)
+                                     })
+            .optional()
+            .map_err(Error::SQLError)?;
+
+        match row_opt {
+            Some((identifier, parent_hash, blob_id, offset, length)) => {
+                Ok(Some(PackedTrieInfo {
+                    block_hash: bhh.clone(),
+                    identifier: identifier as u32,
+                    parent: TriePackedStore::parse_hash(&parent_hash)?,
+                    blob_id: blob_id as u32,
+                    offset: offset as u64,
+                    length: length as u64
+                }))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Find out where a block's trie is stored
+    pub fn get(&self, bhh: &BlockHeaderHash) -> Result<Option<PackedTrieInfo>, Error> {
+        TriePackedStore::get_info(&self.conn, bhh)
+    }
+
+    pub fn has_block(&self, bhh: &BlockHeaderHash) -> Result<bool, Error> {
+        Ok(self.get(bhh)?.is_some())
+    }
+
+    /// Open a read handle on a block's trie.  Offsets in the handle are relative to the start of
+    /// the trie.
+    pub fn open_trie(&self, bhh: &BlockHeaderHash) -> Result<TrieBlockFile, Error> {
+        let info = self.get(bhh)?
+            .ok_or_else(|| {
+                debug!("Trie not found in packed store: {:?}", bhh);
+                Error::NotFoundError
+            })?;
+
+        let blob_path = TriePackedStore::blob_path(&self.dir_path, info.blob_id);
+        let fd = fs::OpenOptions::new()
+                    .read(true)
+                    .write(false)
+                    .open(&blob_path)
+                    .map_err(|e| {
+                        if e.kind() == io::ErrorKind::NotFound {
+                            error!("Blob file not found: {:?}", &blob_path);
+                            Error::CorruptionError(format!("Missing trie blob {:?}", &blob_path))
+                        }
+                        else {
+                            Error::IOError(e)
+                        }
+                    })?;
+
+        TrieBlockFile::new(fd, info.offset, info.offset + info.length)
+    }
+
+    /// Read out the raw bytes of a block's trie
+    pub fn read_trie_bytes(&self, bhh: &BlockHeaderHash) -> Result<Vec<u8>, Error> {
+        let info = self.get(bhh)?.ok_or(Error::NotFoundError)?;
+        let mut fd = self.open_trie(bhh)?;
+        let mut buf = vec![0u8; info.length as usize];
+        io::Read::read_exact(&mut fd, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// List the (block hash, identifier, parent hash) triples of all stored tries
+    pub fn list_blocks(&self) -> Result<Vec<(BlockHeaderHash, u32, BlockHeaderHash)>, Error> {
+        let mut stmt = self.conn.prepare("SELECT block_hash, identifier, parent_hash FROM marf_tries")
+            .map_err(Error::SQLError)?;
+
+        let mut rows = stmt.query(NO_PARAMS)
+            .map_err(Error::SQLError)?;
+
+        let mut ret = vec![];
+        while let Some(row_res) = rows.next() {
+            let row = row_res.map_err(Error::SQLError)?;
+            let block_hash : String = row.get(0);
+            let identifier : i64 = row.get(1);
+            let parent_hash : String = row.get(2);
+
+            ret.push((TriePackedStore::parse_hash(&block_hash)?, identifier as u32, TriePackedStore::parse_hash(&parent_hash)?));
+        }
+        Ok(ret)
+    }
+
+    /// Append a sealed trie, as produced by TrieRAM::dump().
+    /// The trie's parent hash and identifier are read from its header.
+    /// Fails with ExistsError if the block is already stored.
+    pub fn append(&mut self, bhh: &BlockHeaderHash, trie_bytes: &[u8]) -> Result<(), Error> {
+        let header_len = BLOCK_HEADER_HASH_ENCODED_SIZE as usize + 4;
+        if trie_bytes.len() < header_len {
+            return Err(Error::CorruptionError(format!("Trie for {} is too short ({} bytes)", bhh, trie_bytes.len())));
+        }
+
+        let mut parent_bytes = [0u8; BLOCK_HEADER_HASH_ENCODED_SIZE as usize];
+        parent_bytes.copy_from_slice(&trie_bytes[0..(BLOCK_HEADER_HASH_ENCODED_SIZE as usize)]);
+        let parent = BlockHeaderHash(parent_bytes);
+
+        let mut identifier_bytes = [0u8; 4];
+        identifier_bytes.copy_from_slice(&trie_bytes[(BLOCK_HEADER_HASH_ENCODED_SIZE as usize)..header_len]);
+        let identifier = u32::from_le_bytes(identifier_bytes);
+
+        let dir_path = self.dir_path.clone();
+        let max_blob_size = self.max_blob_size;
+
+        // an immediate transaction holds the write lock, so concurrent appenders in other handles
+        // can't interleave between the blob write and the index insert.
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(Error::SQLError)?;
+
+        if TriePackedStore::get_info(&tx, bhh)?.is_some() {
+            error!("Trie for {} is already stored", bhh);
+            return Err(Error::ExistsError);
+        }
+
+        // find the end of the last indexed trie
+        let last_opt = tx.query_row("SELECT blob_id, offset + length FROM marf_tries ORDER BY blob_id DESC, offset DESC LIMIT 1",
+                                    NO_PARAMS,
+                                    |row| {
+                                        let blob_id : i64 = row.get(0);
+                                        let end : i64 = row.get(1);
+                                        (blob_id as u32, end as u64)
+                                    })
+            .optional()
+            .map_err(Error::SQLError)?;
+
+        let (blob_id, offset) = match last_opt {
+            Some((blob_id, end)) => {
+                if end > 0 && end + (trie_bytes.len() as u64) > max_blob_size {
+                    (blob_id + 1, 0)
+                }
+                else {
+                    (blob_id, end)
+                }
+            },
+            None => (0, 0)
+        };
+
+        let blob_path = TriePackedStore::blob_path(&dir_path, blob_id);
+        let mut fd = fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .open(&blob_path)
+                    .map_err(Error::IOError)?;
+
+        // discard any unindexed tail left over from an interrupted append
+        let blob_len = fd.metadata().map_err(Error::IOError)?.len();
+        if blob_len > offset {
+            warn!("Discard {} unindexed bytes at the end of {:?}", blob_len - offset, &blob_path);
+            fd.set_len(offset).map_err(Error::IOError)?;
+        }
+        else if blob_len < offset {
+            return Err(Error::CorruptionError(format!("Trie blob {:?} is truncated: {} < {}", &blob_path, blob_len, offset)));
+        }
+
+        fd.seek(SeekFrom::Start(offset)).map_err(Error::IOError)?;
+        fd.write_all(trie_bytes).map_err(Error::IOError)?;
+        fd.sync_all().map_err(Error::IOError)?;
+
+        let args : &[&dyn ToSql] = &[bhh, &(identifier as i64), &parent, &(blob_id as i64), &(offset as i64), &(trie_bytes.len() as i64)];
+        tx.execute("INSERT INTO marf_tries (block_hash, identifier, parent_hash, blob_id, offset, length) VALUES (?1, ?2, ?3, ?4, ?5, ?6)", args)
+            .map_err(Error::SQLError)?;
+
+        tx.commit()
+            .map_err(Error::SQLError)?;
+
+        debug!("Appended trie for {} ({} bytes) to {:?} at {}", bhh, trie_bytes.len(), &blob_path, offset);
+        Ok(())
+    }
+
+    /// Remove a trie from the index.  Its bytes are left in the blob; if it was the last trie
+    /// appended, the next append overwrites them.
+    pub fn delete(&mut self, bhh: &BlockHeaderHash) -> Result<(), Error> {
+        let args : &[&dyn ToSql] = &[bhh];
+        let count = self.conn.execute("DELETE FROM marf_tries WHERE block_hash = ?1", args)
+            .map_err(Error::SQLError)?;
+
+        if count == 0 {
+            debug!("Trie not found in packed store: {:?}", bhh);
+            return Err(Error::NotFoundError);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+    use chainstate::stacks::index::storage::fseek;
+
+    fn make_trie_bytes(parent: &BlockHeaderHash, identifier: u32, body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(parent.as_bytes());
+        bytes.extend_from_slice(&identifier.to_le_bytes());
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    fn setup(test_name: &str) -> String {
+        let path = format!("/tmp/test-packed-trie-store-{}", test_name);
+        if fs::metadata(&path).is_ok() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn packed_append_and_read() {
+        let path = setup("append-and-read");
+        let mut store = TriePackedStore::open(&path).unwrap();
+        store.set_max_blob_size(100);
+
+        let mut parent = BlockHeaderHash([0xff; 32]);
+        for i in 0..10u32 {
+            let bhh = BlockHeaderHash([i as u8; 32]);
+            let body = vec![i as u8; 20];
+            let bytes = make_trie_bytes(&parent, i, &body);
+            store.append(&bhh, &bytes).unwrap();
+
+            // can't append twice
+            match store.append(&bhh, &bytes) {
+                Err(Error::ExistsError) => {},
+                x => panic!("expected ExistsError, got {:?}", x)
+            }
+            parent = bhh;
+        }
+
+        let mut parent = BlockHeaderHash([0xff; 32]);
+        for i in 0..10u32 {
+            let bhh = BlockHeaderHash([i as u8; 32]);
+            let info = store.get(&bhh).unwrap().unwrap();
+            assert_eq!(info.identifier, i);
+            assert_eq!(info.parent, parent);
+            assert_eq!(info.length, 56);
+
+            // at most one 56-byte trie fits into a 100-byte blob
+            assert_eq!(info.blob_id, i);
+            assert_eq!(info.offset, 0);
+
+            let bytes = store.read_trie_bytes(&bhh).unwrap();
+            assert_eq!(bytes, make_trie_bytes(&parent, i, &vec![i as u8; 20]));
+
+            // offsets are relative to the start of the trie
+            let mut fd = store.open_trie(&bhh).unwrap();
+            fseek(&mut fd, 36).unwrap();
+            let mut body = [0u8; 20];
+            fd.read_exact(&mut body).unwrap();
+            assert_eq!(body.to_vec(), vec![i as u8; 20]);
+
+            parent = bhh;
+        }
+
+        assert_eq!(store.list_blocks().unwrap().len(), 10);
+        assert!(store.get(&BlockHeaderHash([0xee; 32])).unwrap().is_none());
+    }
+
+    #[test]
+    fn packed_append_discards_unindexed_tail() {
+        let path = setup("discard-tail");
+        let mut store = TriePackedStore::open(&path).unwrap();
+
+        let parent = BlockHeaderHash([0xff; 32]);
+        let bhh_1 = BlockHeaderHash([1u8; 32]);
+        let bhh_2 = BlockHeaderHash([2u8; 32]);
+
+        store.append(&bhh_1, &make_trie_bytes(&parent, 0, &[1u8; 10])).unwrap();
+
+        // simulate a crash after writing a trie but before indexing it
+        {
+            let mut fd = fs::OpenOptions::new().write(true).open(TriePackedStore::blob_path(&path, 0)).unwrap();
+            fd.seek(SeekFrom::End(0)).unwrap();
+            fd.write_all(&[0xaa; 123]).unwrap();
+        }
+
+        let mut store = TriePackedStore::open(&path).unwrap();
+        store.append(&bhh_2, &make_trie_bytes(&bhh_1, 1, &[2u8; 10])).unwrap();
+
+        let info = store.get(&bhh_2).unwrap().unwrap();
+        assert_eq!(info.offset, 46);
+        assert_eq!(fs::metadata(TriePackedStore::blob_path(&path, 0)).unwrap().len(), 92);
+        assert_eq!(store.read_trie_bytes(&bhh_2).unwrap(), make_trie_bytes(&bhh_1, 1, &[2u8; 10]));
+        assert_eq!(store.read_trie_bytes(&bhh_1).unwrap(), make_trie_bytes(&parent, 0, &[1u8; 10]));
+    }
+}
@@ -54,6 +54,8 @@ use chainstate::stacks::index::bits::{
     get_node_hash,
     get_leaf_hash,
     write_path_to_bytes,
+    check_node_id,
+    node_id_to_ptr_count,
 };
 
 use chainstate::stacks::index::node::{
@@ -73,6 +75,7 @@ use chainstate::stacks::index::node::{
     TriePtr,
     TriePath,
     ConsensusSerializable,
+    TRIEPATH_MAX_LEN,
 };
 
 use chainstate::stacks::index::{
@@ -83,7 +86,7 @@ use chainstate::stacks::index::{
 };
 
 use chainstate::stacks::index::storage::{
-    TrieFileStorage, BlockHashMap
+    TrieFileStorage, TrieStorageLayout, BlockHashMap
 };
 
 use chainstate::stacks::index::trie::{
@@ -92,6 +95,10 @@ use chainstate::stacks::index::trie::{
 
 use chainstate::stacks::index::Error as Error;
 
+use net::StacksMessageCodec;
+use net::codec::{read_next, read_next_exact, write_next};
+use net::Error as net_error;
+
 use util::log;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -160,7 +167,8 @@ pub enum TrieMerkleProofType {
     Node48((u8, ProofTrieNode, [TrieHash; 47])),
     Node256((u8, ProofTrieNode, [TrieHash; 255])),
     Leaf((u8, TrieLeaf)),
-    Shunt((i64, Vec<TrieHash>))
+    Shunt((i64, Vec<TrieHash>)),
+    Diverged((ProofTrieNode, Vec<TrieHash>))     // node at which a path stops resolving, with all of its children's hashes
 }
 
 pub fn hashes_fmt(hashes: &[TrieHash]) -> String {
@@ -187,7 +195,8 @@ impl fmt::Debug for TrieMerkleProofType {
             TrieMerkleProofType::Node48((ref chr, ref node, ref hashes)) => write!(f, "TrieMerkleProofType::Node48(0x{:02x}, node={:?}, hashes={})", chr, node, hashes_fmt(hashes)),
             TrieMerkleProofType::Node256((ref chr, ref node, ref hashes)) => write!(f, "TrieMerkleProofType::Node256(0x{:02x}, node={:?}, hashes={})", chr, node, hashes_fmt(hashes)),
             TrieMerkleProofType::Leaf((ref chr, ref node)) => write!(f, "TrieMerkleProofType::Leaf(0x{:02x}, node={:?})", chr, node),
-            TrieMerkleProofType::Shunt((ref idx, ref hashes)) => write!(f, "TrieMerkleProofType::Shunt(idx={}, hashes={:?})", idx, hashes)
+            TrieMerkleProofType::Shunt((ref idx, ref hashes)) => write!(f, "TrieMerkleProofType::Shunt(idx={}, hashes={:?})", idx, hashes),
+            TrieMerkleProofType::Diverged((ref node, ref hashes)) => write!(f, "TrieMerkleProofType::Diverged(node={:?}, hashes={})", node, hashes_fmt(hashes))
         }
     }
 }
@@ -213,6 +222,9 @@ impl PartialEq for TrieMerkleProofType {
             (TrieMerkleProofType::Shunt((ref idx_1, ref hashes_1)), TrieMerkleProofType::Shunt((ref idx_2, ref hashes_2))) => {
                 idx_1 == idx_2 && hashes_1 == hashes_2
             },
+            (TrieMerkleProofType::Diverged((ref node, ref hashes)), TrieMerkleProofType::Diverged((ref other_node, ref other_hashes))) => {
+                node == other_node && hashes == other_hashes
+            },
             (_, _) => false
         }
     }
@@ -622,6 +634,58 @@ impl TrieMerkleProof {
         Ok(proof_segment)
     }
 
+    /// Given a TriePtr to the node at which a path stops resolving, calculate the proof node that
+    /// shows it.  A leaf is included as-is (its path is not the path being proven absent); any other
+    /// node is included with _all_ of its children's hashes, so the verifier can see both its
+    /// compressed path and which children it has.
+    fn ptr_to_exclusion_proof_node(storage: &mut TrieFileStorage, ptr: &TriePtr) -> Result<TrieMerkleProofType, Error> {
+        trace!("ptr_to_exclusion_proof_node: ptr={:?}", ptr);
+        let (node, _) = storage.read_nodetype(ptr)?;
+        if let TrieNodeType::Leaf(ref data) = node {
+            return Ok(TrieMerkleProofType::Leaf((ptr.chr(), data.clone())));
+        }
+
+        let all_hashes = Trie::get_children_hashes(storage, &node)?;
+        let mut hashes = Vec::with_capacity(all_hashes.len());
+        for i in 0..node.ptrs().len() {
+            if node.ptrs()[i].id() == TrieNodeID::Empty {
+                hashes.push(TrieHash::from_data(&[]));
+            }
+            else {
+                hashes.push(all_hashes[i].clone());
+            }
+        }
+
+        let block_map = &storage.block_map;
+        let proof_node = match node {
+            TrieNodeType::Node4(ref data) => ProofTrieNode::try_from_trie_node(data, block_map)?,
+            TrieNodeType::Node16(ref data) => ProofTrieNode::try_from_trie_node(data, block_map)?,
+            TrieNodeType::Node48(ref data) => ProofTrieNode::try_from_trie_node(data, block_map)?,
+            TrieNodeType::Node256(ref data) => ProofTrieNode::try_from_trie_node(data, block_map)?,
+            TrieNodeType::Leaf(_) => unreachable!()
+        };
+        Ok(TrieMerkleProofType::Diverged((proof_node, hashes)))
+    }
+
+    /// Given the list of non-backptr ptrs walked to the node at which a path stops resolving,
+    /// calculate the segment proof from that node up to the trie root.
+    fn make_exclusion_segment_proof(storage: &mut TrieFileStorage, ptrs: &Vec<TriePtr>) -> Result<Vec<TrieMerkleProofType>, Error> {
+        trace!("make_exclusion_segment_proof: ptrs = {:?}", &ptrs);
+        assert!(ptrs.len() > 0);
+
+        let terminal_ptr = ptrs[ptrs.len() - 1].clone();
+        let mut proof_segment = vec![TrieMerkleProof::ptr_to_exclusion_proof_node(storage, &terminal_ptr)?];
+
+        if ptrs.len() > 1 {
+            // the terminal node's ancestors, up to the root
+            let ancestor_ptrs = ptrs[0..ptrs.len() - 1].to_vec();
+            let mut ancestors = TrieMerkleProof::make_segment_proof(storage, &ancestor_ptrs, terminal_ptr.chr())?;
+            proof_segment.append(&mut ancestors);
+        }
+
+        Ok(proof_segment)
+    }
+
     /// Given a segment proof, the deepest node's hash, and the hash of the trie root, verify that
     /// the segment proof is well-formed.
     /// If so, calculate the root hash of the segment and return it.
@@ -738,9 +802,11 @@ impl TrieMerkleProof {
     /// Verify that a proof is well-formed:
     /// * it must have the same number of segment and shunt proofs
     /// * segment proof i+1 must be a prefix of segment proof i
-    /// * segment proof 0 must end in a leaf
+    /// * segment proof 0 must end in a leaf (or, for an exclusion proof, a leaf or a diverged node)
     /// * all segment proofs must end in a Node256 (a root)
-    fn is_proof_well_formed(proof: &Vec<TrieMerkleProofType>, expected_path: &TriePath) -> bool {
+    /// For an exclusion proof, segment proof 0's path is the path to the diverged node, which
+    /// must be a strict prefix of the expected path.
+    fn is_proof_well_formed(proof: &Vec<TrieMerkleProofType>, expected_path: &TriePath, exclusion: bool) -> bool {
         if proof.len() == 0 {
             trace!("Proof is empty");
             return false;
@@ -748,6 +814,7 @@ impl TrieMerkleProof {
 
         match proof[0] {
             TrieMerkleProofType::Leaf(_) => {},
+            TrieMerkleProofType::Diverged(_) if exclusion => {},
             _ => {
                 trace!("First proof node is not a leaf");
                 return false;
@@ -774,7 +841,33 @@ impl TrieMerkleProof {
 
             let segment_proof = &proof[i..j];
             
-            if i == 0 {
+            if i == 0 && exclusion {
+                // detect the path to the diverged node.  Everything above it must be an
+                // intermediate node.
+                for proof_node in segment_proof[1..].iter() {
+                    if let TrieMerkleProofType::Leaf(_) = proof_node {
+                        trace!("Invalid exclusion proof -- leaf above the diverged node");
+                        return false;
+                    }
+                }
+
+                path_bytes = match TrieMerkleProof::get_segment_proof_path_prefix(&segment_proof[1..]) {
+                    Some(bytes) => {
+                        bytes
+                    },
+                    None => {
+                        trace!("Failed to get the path prefix from the proof");
+                        return false;
+                    }
+                };
+
+                let expected_bytes = expected_path.as_bytes();
+                if path_bytes.len() >= expected_bytes.len() || path_bytes[..] != expected_bytes[0..path_bytes.len()] {
+                    trace!("Invalid proof -- path bytes {:?} are not a prefix of the expected path {:?}", &path_bytes, expected_path);
+                    return false;
+                }
+            }
+            else if i == 0 {
                 // detect the path
                 path_bytes = match TrieMerkleProof::get_segment_proof_path_prefix(segment_proof) {
                     Some(bytes) => {
@@ -853,11 +946,11 @@ impl TrieMerkleProof {
     /// headers.
     /// NOTE: Trie root hashes are globally unique by design, even if they represent the same contents, so the root_to_block map is bijective with high probability.
     pub fn verify_proof(proof: &Vec<TrieMerkleProofType>, path: &TriePath, value: &MARFValue, root_hash: &TrieHash, root_to_block: &HashMap<TrieHash, BlockHeaderHash>) -> bool {
-        if !TrieMerkleProof::is_proof_well_formed(&proof, path) {
+        if !TrieMerkleProof::is_proof_well_formed(&proof, path, false) {
             return false;
         }
 
-        let (mut node_hash, node_data) = match proof[0] {
+        let (node_hash, node_data) = match proof[0] {
             TrieMerkleProofType::Leaf((_, ref node)) => {
                 (get_leaf_hash(node), node.data.clone())
             },
@@ -872,7 +965,7 @@ impl TrieMerkleProof {
             return false;
         }
 
-        let mut i = 0;
+        let i = 0;
 
         // verify the very first segment proof 
         let mut j = i + 1;
@@ -897,7 +990,17 @@ impl TrieMerkleProof {
             }
         };
         
-        i = j;
+        TrieMerkleProof::verify_proof_from_segment_root(proof, j, &node_root_hash, root_hash, root_to_block)
+    }
+
+    /// Given the root hash of a proof's first segment proof (i.e. the one ending in a leaf or
+    /// diverged node), verify the remaining shunt and segment proofs, starting from the
+    /// first shunt proof at proof[first_shunt].  Returns true if they lead to root_hash.
+    fn verify_proof_from_segment_root(proof: &Vec<TrieMerkleProofType>, first_shunt: usize, node_root_hash: &TrieHash, root_hash: &TrieHash, root_to_block: &HashMap<TrieHash, BlockHeaderHash>) -> bool {
+        let mut i = first_shunt;
+        let mut j;
+        let mut node_hash;
+
         if i >= proof.len() {
             trace!("Proof is too short -- needed at least one shunt proof for the first segment");
             return false;
@@ -905,7 +1008,7 @@ impl TrieMerkleProof {
 
         // verify the very first shunt proof head.
         trace!("verify shunt proof head at {}: {:?}", i, &proof[i]);
-        let mut trie_hash = match TrieMerkleProof::verify_shunt_proof_head(&node_root_hash, &proof[i]) {
+        let mut trie_hash = match TrieMerkleProof::verify_shunt_proof_head(node_root_hash, &proof[i]) {
             Some(h) => {
                 h
             },
@@ -918,7 +1021,8 @@ impl TrieMerkleProof {
         i += 1;
         if i >= proof.len() {
             // done -- no further shunts
-            return true;
+            trace!("Verify proof: {:?} =?= {:?}", root_hash, &trie_hash);
+            return *root_hash == trie_hash;
         }
 
         // next node hash is the hash of the block from which its root came
@@ -1057,8 +1161,145 @@ impl TrieMerkleProof {
         TrieMerkleProof::verify_proof(&self.0, &path, &marf_value, root_hash, root_to_block)
     }
 
+    /// Verify that the first node of an exclusion proof shows that the path does not resolve past
+    /// it.  path_prefix is the path up to this node (including the chr that leads to it).
+    /// If so, return the node's hash.
+    fn verify_exclusion_terminal(terminal: &TrieMerkleProofType, path_prefix: &[u8], path: &TriePath) -> Option<TrieHash> {
+        let path_bytes = path.as_bytes();
+
+        // guaranteed by is_proof_well_formed()
+        assert!(path_prefix.len() < path_bytes.len());
+        let path_suffix = &path_bytes[path_prefix.len()..];
+
+        match terminal {
+            TrieMerkleProofType::Leaf((_, ref leaf)) => {
+                // the leaf sits where the path would have to be, but is for a different path
+                if leaf.path.len() != path_suffix.len() {
+                    trace!("Invalid exclusion proof -- leaf path {:?} is not a path suffix", &leaf.path);
+                    return None;
+                }
+                if leaf.path[..] == path_suffix[..] {
+                    trace!("Invalid exclusion proof -- leaf is for {:?}", path);
+                    return None;
+                }
+                Some(get_leaf_hash(leaf))
+            },
+            TrieMerkleProofType::Diverged((ref node, ref hashes)) => {
+                let count = match node.id {
+                    TrieNodeID::Node4 => 4,
+                    TrieNodeID::Node16 => 16,
+                    TrieNodeID::Node48 => 48,
+                    TrieNodeID::Node256 => 256,
+                    _ => {
+                        trace!("Invalid exclusion proof -- diverged node has id {}", node.id);
+                        return None;
+                    }
+                };
+                if node.ptrs().len() != count || hashes.len() != count {
+                    trace!("Invalid exclusion proof -- expected {} children, got {} ptrs and {} hashes", count, node.ptrs().len(), hashes.len());
+                    return None;
+                }
+
+                // an intermediate node always leaves at least one more chr of the path to walk
+                if node.path.len() >= path_suffix.len() {
+                    trace!("Invalid exclusion proof -- diverged node path {:?} is too long", &node.path);
+                    return None;
+                }
+
+                if node.path[..] == path_suffix[0..node.path.len()] {
+                    // the path follows the node's compressed path, so the node must not have a
+                    // child for the next chr
+                    let chr = path_suffix[node.path.len()];
+                    for ptr in node.ptrs() {
+                        if ptr.id != TrieNodeID::Empty && ptr.chr == chr {
+                            trace!("Invalid exclusion proof -- diverged node has a child for 0x{:02x}", chr);
+                            return None;
+                        }
+                    }
+                }
+
+                Some(get_node_hash(node, hashes, &()))
+            },
+            _ => {
+                trace!("Invalid exclusion proof -- does not start with a leaf or diverged node");
+                None
+            }
+        }
+    }
+
+    /// Given the root hash from which this exclusion proof was (supposedly) generated, verify that
+    /// the path has no value as of that root.  The first segment proof ends at the node where the
+    /// path stops resolving -- an intermediate node whose compressed path diverges from it or that has no
+    /// child for its next chr, or a leaf for a different path.  The rest of the proof is checked
+    /// just like an inclusion proof, so the verifier needs the same root_to_block map.
+    pub fn verify_absence_proof(proof: &Vec<TrieMerkleProofType>, path: &TriePath, root_hash: &TrieHash, root_to_block: &HashMap<TrieHash, BlockHeaderHash>) -> bool {
+        if !TrieMerkleProof::is_proof_well_formed(&proof, path, true) {
+            return false;
+        }
+
+        // find the end of the very first segment proof
+        let mut j = 1;
+        while j < proof.len() {
+            match proof[j] {
+                TrieMerkleProofType::Shunt(_) => {
+                    break;
+                },
+                _ => {
+                    j += 1;
+                }
+            }
+        }
+
+        let path_prefix = match TrieMerkleProof::get_segment_proof_path_prefix(&proof[1..j]) {
+            Some(bytes) => {
+                bytes
+            },
+            None => {
+                return false;
+            }
+        };
+
+        let terminal_hash = match TrieMerkleProof::verify_exclusion_terminal(&proof[0], &path_prefix, path) {
+            Some(h) => {
+                h
+            },
+            None => {
+                return false;
+            }
+        };
+
+        trace!("verify segment proof in range 1..{}", j);
+        let node_root_hash = match TrieMerkleProof::verify_segment_proof(&proof[1..j], &terminal_hash) {
+            Some(h) => {
+                h
+            },
+            None => {
+                return false;
+            }
+        };
+
+        TrieMerkleProof::verify_proof_from_segment_root(proof, j, &node_root_hash, root_hash, root_to_block)
+    }
+
+    /// Verify this proof as an exclusion proof
+    pub fn verify_absent(&self, path: &TriePath, root_hash: &TrieHash, root_to_block: &HashMap<TrieHash, BlockHeaderHash>) -> bool {
+        TrieMerkleProof::verify_absence_proof(&self.0, &path, root_hash, root_to_block)
+    }
+
     /// Walk down the trie pointed to by s until we reach a backptr or a leaf
     fn walk_to_leaf_or_backptr(storage: &mut TrieFileStorage, path: &TriePath) -> Result<(TrieCursor, TrieNodeType, TriePtr), Error> {
+        let (cursor, node, ptr, diverged) = TrieMerkleProof::walk_to_leaf_or_backptr_or_divergence(storage, path)?;
+        if diverged {
+            trace!("Path {:?} does not resolve in {:?}", path, &storage.get_cur_block());
+            return Err(Error::NotFoundError);
+        }
+        Ok((cursor, node, ptr))
+    }
+
+    /// Walk down the trie pointed to by s until we reach a backptr or a leaf, or until the path
+    /// stops resolving.  Returns the cursor, the last node reached, the ptr to the backptr (or to
+    /// the last node reached), and whether or not the path stopped resolving at that node.
+    fn walk_to_leaf_or_backptr_or_divergence(storage: &mut TrieFileStorage, path: &TriePath) -> Result<(TrieCursor, TrieNodeType, TriePtr, bool), Error> {
         trace!("Walk path {:?} from {:?} to the first backptr", path, &storage.get_cur_block());
         
         let mut node_ptr = storage.root_trieptr();
@@ -1079,7 +1320,7 @@ impl TrieMerkleProof {
                         None => {
                             // end of path.
                             trace!("Found leaf {:?}", &node);
-                            return Ok((cursor, node, node_ptr));
+                            return Ok((cursor, node, node_ptr, false));
                         }
                     }
                 },
@@ -1090,12 +1331,12 @@ impl TrieMerkleProof {
                                 CursorError::PathDiverged => {
                                     // we're done -- path diverged.  No backptr-walking can help us.
                                     trace!("Path diverged -- we're done.");
-                                    return Err(Error::NotFoundError);
+                                    return Ok((cursor, node, node_ptr, true));
                                 },
                                 CursorError::ChrNotFound => {
                                     // node isn't present
                                     trace!("Failed to walk from {:?}", &node);
-                                    return Err(Error::NotFoundError);
+                                    return Ok((cursor, node, node_ptr, true));
                                 },
                                 CursorError::BackptrEncountered(ptr) => {
                                     // expect backptr
@@ -1105,7 +1346,7 @@ impl TrieMerkleProof {
 
                                     // we're done -- we found a backptr
                                     trace!("Found backptr {:?}", &ptr);
-                                    return Ok((cursor, node, ptr));
+                                    return Ok((cursor, node, ptr, false));
                                 }
                             }
                         },
@@ -1187,6 +1428,12 @@ impl TrieMerkleProof {
             block_header = storage.get_block_from_local_id(backptr.back_block())?.clone();
         }
 
+        Ok(TrieMerkleProof::join_proofs(segment_proofs, shunt_proofs))
+    }
+
+    /// Assemble a proof from the segment and shunt proofs accumulated while walking back from the
+    /// root block.
+    fn join_proofs(mut segment_proofs: Vec<Vec<TrieMerkleProofType>>, mut shunt_proofs: Vec<Vec<TrieMerkleProofType>>) -> TrieMerkleProof {
         assert_eq!(shunt_proofs.len(), segment_proofs.len());
 
         // leaf proof needs to be first
@@ -1202,7 +1449,7 @@ impl TrieMerkleProof {
             proof.append(&mut shunt_proofs[i]);
         }
         
-        Ok(TrieMerkleProof(proof))
+        TrieMerkleProof(proof)
     }
     
     /// Make a merkle proof of inclusion from a key/value pair.
@@ -1212,6 +1459,517 @@ impl TrieMerkleProof {
         let path = TriePath::from_key(key);
         TrieMerkleProof::from_path(storage, &path, &marf_value, root_block_header)
     }
+
+    /// Make a merkle proof of exclusion from a path -- i.e. that the path has no value as of the
+    /// given block.  The path is followed back through the MARF just as it is for an inclusion
+    /// proof, until it stops resolving.
+    /// If the path does resolve, return an error (ExistsError)
+    pub fn from_absent_path(storage: &mut TrieFileStorage, path: &TriePath, root_block_header: &BlockHeaderHash) -> Result<TrieMerkleProof, Error> {
+        let mut segment_proofs = vec![];
+        let mut shunt_proofs = vec![];
+        let mut block_header = root_block_header.clone();
+
+        loop {
+            storage.open_block(&block_header)?;
+
+            trace!("Walk {:?} path {:?} to divergence or backptr", &storage.get_cur_block(), path);
+            let (cursor, _, ptr, diverged) = TrieMerkleProof::walk_to_leaf_or_backptr_or_divergence(storage, path)?;
+
+            if diverged {
+                // the path stops resolving in this trie.  Prove the node where it does, and
+                // shunt from this trie's root.
+                trace!("Make exclusion segment proof at {:?} from {:?}", &storage.get_cur_block(), &cursor.node_ptrs);
+                let segment_proof = TrieMerkleProof::make_exclusion_segment_proof(storage, &cursor.node_ptrs)?;
+                segment_proofs.push(segment_proof);
+
+                let first_shunt_proof = TrieMerkleProof::make_initial_shunt_proof(storage)?;
+                shunt_proofs.push(first_shunt_proof);
+                break;
+            }
+
+            if !is_backptr(ptr.id()) {
+                trace!("Path {:?} resolves to a leaf in {:?}", path, &storage.get_cur_block());
+                return Err(Error::ExistsError);
+            }
+
+            trace!("Make segment proof at {:?} from {:?}", &storage.get_cur_block(), &cursor.node_ptrs);
+            let segment_proof = TrieMerkleProof::make_segment_proof(storage, &cursor.node_ptrs, cursor.chr().unwrap())?;
+            segment_proofs.push(segment_proof);
+
+            trace!("Make shunt proof {:?} back to the block containing {:?} (cursor ptrs = {:?})", &storage.get_cur_block(), &ptr, &cursor.node_ptrs);
+            let shunt_proof = TrieMerkleProof::make_backptr_shunt_proof(storage, &ptr)?;
+            shunt_proofs.push(shunt_proof);
+
+            storage.open_block(&block_header)?;
+
+            trace!("Walk back for {:?} from {:?}", &ptr, &storage.get_cur_block());
+            block_header = storage.get_block_from_local_id(ptr.back_block())?.clone();
+        }
+
+        Ok(TrieMerkleProof::join_proofs(segment_proofs, shunt_proofs))
+    }
+
+    /// Make a merkle proof of exclusion from a key.
+    /// If the key has a value, return an error (ExistsError)
+    pub fn from_absent_key(storage: &mut TrieFileStorage, key: &String, root_block_header: &BlockHeaderHash) -> Result<TrieMerkleProof, Error> {
+        let path = TriePath::from_key(key);
+        TrieMerkleProof::from_absent_path(storage, &path, root_block_header)
+    }
+}
+
+/// One entry in a single key's proof within a TrieMerkleMultiProof.  Intermediate nodes and shunt
+/// proofs are often shared between keys, so they are stored once and referred to by index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrieMerkleMultiProofEntry {
+    Node((u8, u32)),        // (chr walked, index into nodes)
+    Diverged(u32),          // index into nodes
+    Leaf((u8, TrieLeaf)),
+    Shunt(u32)              // index into shunts
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum TrieMerkleMultiProofEntryID {
+    Node = 0x00,
+    Diverged = 0x01,
+    Leaf = 0x02,
+    Shunt = 0x03
+}
+
+/// A batch of Merkle proofs for different keys, all from the same block.  Each intermediate node
+/// is stored once, with all of its children's hashes, so it can stand in for a proof node on any
+/// path through it.  Shunt proofs are likewise stored once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrieMerkleMultiProof {
+    pub nodes: Vec<(ProofTrieNode, Vec<TrieHash>)>,
+    pub shunts: Vec<(i64, Vec<TrieHash>)>,
+    pub proofs: Vec<Vec<TrieMerkleMultiProofEntry>>
+}
+
+impl TrieMerkleMultiProof {
+    /// Given a proof node's excluded children hashes and the hash of the child at chr, calculate
+    /// all of the node's children hashes.
+    fn fill_proof_hashes(node: &ProofTrieNode, chr: u8, child_hash: &TrieHash, hashes: &[TrieHash]) -> Option<Vec<TrieHash>> {
+        let mut all_hashes = Vec::with_capacity(node.ptrs().len());
+        let mut ih = 0;
+        for child_ptr in node.ptrs() {
+            if child_ptr.id != TrieNodeID::Empty && child_ptr.chr == chr {
+                all_hashes.push(child_hash.clone());
+            }
+            else {
+                if ih >= hashes.len() {
+                    return None;
+                }
+                all_hashes.push(hashes[ih].clone());
+                ih += 1;
+            }
+        }
+        if ih != hashes.len() || all_hashes.len() != node.ptrs().len() {
+            return None;
+        }
+        Some(all_hashes)
+    }
+
+    /// Given a node and all of its children hashes, make the proof node for walking chr.
+    fn expand_proof_node(node: &ProofTrieNode, all_hashes: &Vec<TrieHash>, chr: u8) -> Option<TrieMerkleProofType> {
+        if all_hashes.len() != node.ptrs().len() {
+            return None;
+        }
+
+        let mut hashes = Vec::with_capacity(all_hashes.len());
+        let mut found = false;
+        for i in 0..node.ptrs().len() {
+            let child_ptr = &node.ptrs()[i];
+            if !found && child_ptr.id != TrieNodeID::Empty && child_ptr.chr == chr {
+                found = true;
+            }
+            else {
+                hashes.push(all_hashes[i].clone());
+            }
+        }
+        if !found {
+            trace!("Char 0x{:02x} does not appear in {:?}", chr, node);
+            return None;
+        }
+
+        let proof_node = match node.id {
+            TrieNodeID::Node4 if hashes.len() == 3 => {
+                let mut hash_slice = [TrieHash::from_data(&[]); 3];
+                hash_slice.copy_from_slice(&hashes[0..3]);
+                TrieMerkleProofType::Node4((chr, node.clone(), hash_slice))
+            },
+            TrieNodeID::Node16 if hashes.len() == 15 => {
+                let mut hash_slice = [TrieHash::from_data(&[]); 15];
+                hash_slice.copy_from_slice(&hashes[0..15]);
+                TrieMerkleProofType::Node16((chr, node.clone(), hash_slice))
+            },
+            TrieNodeID::Node48 if hashes.len() == 47 => {
+                let mut hash_slice = [TrieHash::from_data(&[]); 47];
+                hash_slice.copy_from_slice(&hashes[0..47]);
+                TrieMerkleProofType::Node48((chr, node.clone(), hash_slice))
+            },
+            TrieNodeID::Node256 if hashes.len() == 255 => {
+                let mut hash_slice = [TrieHash::from_data(&[]); 255];
+                hash_slice.copy_from_slice(&hashes[0..255]);
+                TrieMerkleProofType::Node256((chr, node.clone(), hash_slice))
+            },
+            _ => {
+                trace!("Node {:?} has the wrong number of children hashes ({})", node, all_hashes.len());
+                return None;
+            }
+        };
+        Some(proof_node)
+    }
+
+    /// Combine proofs for several keys from the same block, deduplicating the nodes and shunt
+    /// proofs they share.
+    pub fn from_proofs(proofs: &[TrieMerkleProof]) -> Result<TrieMerkleMultiProof, Error> {
+        let mut nodes = vec![];
+        let mut node_index : HashMap<TrieHash, u32> = HashMap::new();
+        let mut shunts = vec![];
+        let mut shunt_index : HashMap<(i64, Vec<TrieHash>), u32> = HashMap::new();
+        let mut multi_proofs = Vec::with_capacity(proofs.len());
+
+        for proof in proofs.iter() {
+            let mut entries = Vec::with_capacity(proof.len());
+
+            // hash of the node below the current proof node.  Not known at the bottom of a
+            // segment proof that ends in a backptr -- its hash is that of the block it points to.
+            let mut child_hash : Option<TrieHash> = None;
+
+            for proof_node in proof.iter() {
+                let (node, all_hashes, chr) = match proof_node {
+                    TrieMerkleProofType::Leaf((ref chr, ref leaf)) => {
+                        child_hash = Some(get_leaf_hash(leaf));
+                        entries.push(TrieMerkleMultiProofEntry::Leaf((*chr, leaf.clone())));
+                        continue;
+                    },
+                    TrieMerkleProofType::Shunt((ref idx, ref hashes)) => {
+                        child_hash = None;
+                        let key = (*idx, hashes.clone());
+                        let shunt_idx = match shunt_index.get(&key) {
+                            Some(i) => *i,
+                            None => {
+                                shunts.push(key.clone());
+                                shunt_index.insert(key, (shunts.len() - 1) as u32);
+                                (shunts.len() - 1) as u32
+                            }
+                        };
+                        entries.push(TrieMerkleMultiProofEntry::Shunt(shunt_idx));
+                        continue;
+                    },
+                    TrieMerkleProofType::Diverged((ref node, ref hashes)) => {
+                        (node, hashes.clone(), None)
+                    },
+                    TrieMerkleProofType::Node4((ref chr, ref node, ref hashes)) => {
+                        (node, TrieMerkleMultiProof::fill_node_hashes(node, *chr, &child_hash, hashes)?, Some(*chr))
+                    },
+                    TrieMerkleProofType::Node16((ref chr, ref node, ref hashes)) => {
+                        (node, TrieMerkleMultiProof::fill_node_hashes(node, *chr, &child_hash, hashes)?, Some(*chr))
+                    },
+                    TrieMerkleProofType::Node48((ref chr, ref node, ref hashes)) => {
+                        (node, TrieMerkleMultiProof::fill_node_hashes(node, *chr, &child_hash, hashes)?, Some(*chr))
+                    },
+                    TrieMerkleProofType::Node256((ref chr, ref node, ref hashes)) => {
+                        (node, TrieMerkleMultiProof::fill_node_hashes(node, *chr, &child_hash, hashes)?, Some(*chr))
+                    }
+                };
+
+                let node_hash = get_node_hash(node, &all_hashes, &());
+                let node_idx = match node_index.get(&node_hash) {
+                    Some(i) => *i,
+                    None => {
+                        nodes.push((node.clone(), all_hashes));
+                        node_index.insert(node_hash.clone(), (nodes.len() - 1) as u32);
+                        (nodes.len() - 1) as u32
+                    }
+                };
+
+                match chr {
+                    Some(chr) => entries.push(TrieMerkleMultiProofEntry::Node((chr, node_idx))),
+                    None => entries.push(TrieMerkleMultiProofEntry::Diverged(node_idx))
+                }
+                child_hash = Some(node_hash);
+            }
+
+            multi_proofs.push(entries);
+        }
+
+        Ok(TrieMerkleMultiProof {
+            nodes: nodes,
+            shunts: shunts,
+            proofs: multi_proofs
+        })
+    }
+
+    /// Recover all of a segment proof node's children hashes.  If the hash of the child at chr
+    /// isn't known, then the child must be a backptr, whose hash is the hash of the block it
+    /// points to.
+    fn fill_node_hashes(node: &ProofTrieNode, chr: u8, child_hash: &Option<TrieHash>, hashes: &[TrieHash]) -> Result<Vec<TrieHash>, Error> {
+        let child_hash = match child_hash {
+            Some(h) => h.clone(),
+            None => {
+                let mut backptr_hash = None;
+                for child_ptr in node.ptrs() {
+                    if child_ptr.id != TrieNodeID::Empty && child_ptr.chr == chr && is_backptr(child_ptr.id) {
+                        backptr_hash = Some(TrieHash(child_ptr.back_block.0));
+                        break;
+                    }
+                }
+                backptr_hash.ok_or_else(|| Error::CorruptionError(format!("Malformed proof: no backptr for 0x{:02x} in {:?}", chr, node)))?
+            }
+        };
+
+        TrieMerkleMultiProof::fill_proof_hashes(node, chr, &child_hash, hashes)
+            .ok_or_else(|| Error::CorruptionError(format!("Malformed proof: wrong number of hashes for {:?}", node)))
+    }
+
+    /// Make a multi-proof for a batch of keys at the given block.  Each key is paired with the
+    /// value it is expected to have, or None if it is expected to have no value.
+    /// If a key does not have its expected value, return an error (NotFoundError or ExistsError)
+    pub fn from_entries(storage: &mut TrieFileStorage, entries: &[(String, Option<String>)], root_block_header: &BlockHeaderHash) -> Result<TrieMerkleMultiProof, Error> {
+        let mut proofs = Vec::with_capacity(entries.len());
+        for (key, value_opt) in entries.iter() {
+            let proof = match value_opt {
+                Some(value) => TrieMerkleProof::from_entry(storage, key, value, root_block_header)?,
+                None => TrieMerkleProof::from_absent_key(storage, key, root_block_header)?
+            };
+            proofs.push(proof);
+        }
+        TrieMerkleMultiProof::from_proofs(&proofs)
+    }
+
+    /// Expand into one proof per key.  Returns None if the multi-proof is malformed.
+    pub fn to_proofs(&self) -> Option<Vec<TrieMerkleProof>> {
+        let mut proofs = Vec::with_capacity(self.proofs.len());
+        for entries in self.proofs.iter() {
+            let mut proof = Vec::with_capacity(entries.len());
+            for entry in entries.iter() {
+                let proof_node = match entry {
+                    TrieMerkleMultiProofEntry::Node((chr, idx)) => {
+                        let (node, all_hashes) = self.nodes.get(*idx as usize)?;
+                        TrieMerkleMultiProof::expand_proof_node(node, all_hashes, *chr)?
+                    },
+                    TrieMerkleMultiProofEntry::Diverged(idx) => {
+                        let (node, all_hashes) = self.nodes.get(*idx as usize)?;
+                        TrieMerkleProofType::Diverged((node.clone(), all_hashes.clone()))
+                    },
+                    TrieMerkleMultiProofEntry::Leaf((chr, leaf)) => {
+                        TrieMerkleProofType::Leaf((*chr, leaf.clone()))
+                    },
+                    TrieMerkleMultiProofEntry::Shunt(idx) => {
+                        let (shunt_idx, hashes) = self.shunts.get(*idx as usize)?;
+                        TrieMerkleProofType::Shunt((*shunt_idx, hashes.clone()))
+                    }
+                };
+                proof.push(proof_node);
+            }
+            proofs.push(TrieMerkleProof(proof));
+        }
+        Some(proofs)
+    }
+
+    /// Verify a multi-proof for a batch of paths, given the root hash of the block it was
+    /// (supposedly) generated from.  values[i] is the value paths[i] should have, or None if it
+    /// should have no value.  Every path's proof must verify.
+    pub fn verify(&self, paths: &[TriePath], values: &[Option<MARFValue>], root_hash: &TrieHash, root_to_block: &HashMap<TrieHash, BlockHeaderHash>) -> bool {
+        if paths.len() != values.len() || paths.len() != self.proofs.len() {
+            trace!("Multi-proof has {} proofs for {} paths and {} values", self.proofs.len(), paths.len(), values.len());
+            return false;
+        }
+
+        let proofs = match self.to_proofs() {
+            Some(proofs) => proofs,
+            None => {
+                trace!("Malformed multi-proof");
+                return false;
+            }
+        };
+
+        for i in 0..proofs.len() {
+            let valid = match values[i] {
+                Some(ref value) => proofs[i].verify(&paths[i], value, root_hash, root_to_block),
+                None => proofs[i].verify_absent(&paths[i], root_hash, root_to_block)
+            };
+            if !valid {
+                trace!("Multi-proof does not verify for {:?}", &paths[i]);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn write_proof_path<W: Write>(fd: &mut W, path: &Vec<u8>) -> Result<(), net_error> {
+    if path.len() > TRIEPATH_MAX_LEN {
+        return Err(net_error::SerializeError(format!("Node path is longer than {} bytes", TRIEPATH_MAX_LEN)));
+    }
+    write_next(fd, &(path.len() as u8))?;
+    fd.write_all(path).map_err(net_error::WriteError)
+}
+
+fn read_proof_path<R: Read>(fd: &mut R) -> Result<Vec<u8>, net_error> {
+    let len : u8 = read_next(fd)?;
+    if (len as usize) > TRIEPATH_MAX_LEN {
+        return Err(net_error::DeserializeError(format!("Node path is longer than {} bytes (got {})", TRIEPATH_MAX_LEN, len)));
+    }
+    let mut path = vec![0u8; len as usize];
+    fd.read_exact(&mut path).map_err(net_error::ReadError)?;
+    Ok(path)
+}
+
+impl StacksMessageCodec for ProofTriePtr {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        write_next(fd, &self.id)?;
+        write_next(fd, &self.chr)?;
+        write_next(fd, &self.back_block)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<ProofTriePtr, net_error> {
+        let id : u8                         = read_next(fd)?;
+        let chr : u8                        = read_next(fd)?;
+        let back_block : BlockHeaderHash    = read_next(fd)?;
+        Ok(ProofTriePtr {
+            id,
+            chr,
+            back_block
+        })
+    }
+}
+
+/// Intermediate nodes only -- the number of ptrs is determined by the node ID.
+impl StacksMessageCodec for ProofTrieNode {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        if self.id == TrieNodeID::Leaf || is_backptr(self.id) || !check_node_id(self.id) || node_id_to_ptr_count(self.id) != self.ptrs.len() {
+            return Err(net_error::SerializeError(format!("Invalid proof node {:?}", self)));
+        }
+        write_next(fd, &self.id)?;
+        write_proof_path(fd, &self.path)?;
+        write_next(fd, &self.ptrs)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<ProofTrieNode, net_error> {
+        let id : u8 = read_next(fd)?;
+        if id == TrieNodeID::Leaf || is_backptr(id) || !check_node_id(id) {
+            return Err(net_error::DeserializeError(format!("Invalid proof node ID {}", id)));
+        }
+        let path = read_proof_path(fd)?;
+        let ptrs : Vec<ProofTriePtr> = read_next_exact(fd, node_id_to_ptr_count(id) as u32)?;
+        Ok(ProofTrieNode {
+            id,
+            path,
+            ptrs
+        })
+    }
+}
+
+impl StacksMessageCodec for TrieMerkleMultiProofEntry {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        match *self {
+            TrieMerkleMultiProofEntry::Node((ref chr, ref idx)) => {
+                write_next(fd, &(TrieMerkleMultiProofEntryID::Node as u8))?;
+                write_next(fd, chr)?;
+                write_next(fd, idx)?;
+            },
+            TrieMerkleMultiProofEntry::Diverged(ref idx) => {
+                write_next(fd, &(TrieMerkleMultiProofEntryID::Diverged as u8))?;
+                write_next(fd, idx)?;
+            },
+            TrieMerkleMultiProofEntry::Leaf((ref chr, ref leaf)) => {
+                write_next(fd, &(TrieMerkleMultiProofEntryID::Leaf as u8))?;
+                write_next(fd, chr)?;
+                write_proof_path(fd, &leaf.path)?;
+                write_next(fd, &leaf.data)?;
+            },
+            TrieMerkleMultiProofEntry::Shunt(ref idx) => {
+                write_next(fd, &(TrieMerkleMultiProofEntryID::Shunt as u8))?;
+                write_next(fd, idx)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<TrieMerkleMultiProofEntry, net_error> {
+        let entry_id : u8 = read_next(fd)?;
+        let entry = match entry_id {
+            x if x == TrieMerkleMultiProofEntryID::Node as u8 => {
+                let chr : u8 = read_next(fd)?;
+                let idx : u32 = read_next(fd)?;
+                TrieMerkleMultiProofEntry::Node((chr, idx))
+            },
+            x if x == TrieMerkleMultiProofEntryID::Diverged as u8 => {
+                let idx : u32 = read_next(fd)?;
+                TrieMerkleMultiProofEntry::Diverged(idx)
+            },
+            x if x == TrieMerkleMultiProofEntryID::Leaf as u8 => {
+                let chr : u8 = read_next(fd)?;
+                let path = read_proof_path(fd)?;
+                let data : MARFValue = read_next(fd)?;
+                TrieMerkleMultiProofEntry::Leaf((chr, TrieLeaf::from_value(&path, data)))
+            },
+            x if x == TrieMerkleMultiProofEntryID::Shunt as u8 => {
+                let idx : u32 = read_next(fd)?;
+                TrieMerkleMultiProofEntry::Shunt(idx)
+            },
+            _ => {
+                return Err(net_error::DeserializeError(format!("Failed to parse multi-proof entry: unknown entry ID {}", entry_id)));
+            }
+        };
+        Ok(entry)
+    }
+}
+
+impl StacksMessageCodec for TrieMerkleMultiProof {
+    fn consensus_serialize<W: Write>(&self, fd: &mut W) -> Result<(), net_error> {
+        write_next(fd, &(self.nodes.len() as u32))?;
+        for (node, hashes) in self.nodes.iter() {
+            if hashes.len() != node.ptrs.len() {
+                return Err(net_error::SerializeError(format!("Node {:?} has {} children hashes", node, hashes.len())));
+            }
+            write_next(fd, node)?;
+            write_next(fd, hashes)?;
+        }
+
+        write_next(fd, &(self.shunts.len() as u32))?;
+        for (idx, hashes) in self.shunts.iter() {
+            if *idx < 0 || *idx > (u32::max_value() as i64) {
+                return Err(net_error::SerializeError(format!("Invalid shunt proof index {}", idx)));
+            }
+            write_next(fd, &(*idx as u32))?;
+            write_next(fd, hashes)?;
+        }
+
+        write_next(fd, &self.proofs)?;
+        Ok(())
+    }
+
+    fn consensus_deserialize<R: Read>(fd: &mut R) -> Result<TrieMerkleMultiProof, net_error> {
+        // don't pre-allocate anything based on the counts -- a short input just fails to decode
+        let num_nodes : u32 = read_next(fd)?;
+        let mut nodes = vec![];
+        for _ in 0..num_nodes {
+            let node : ProofTrieNode = read_next(fd)?;
+            let hashes : Vec<TrieHash> = read_next_exact(fd, node.ptrs.len() as u32)?;
+            nodes.push((node, hashes));
+        }
+
+        let num_shunts : u32 = read_next(fd)?;
+        let mut shunts = vec![];
+        for _ in 0..num_shunts {
+            let idx : u32 = read_next(fd)?;
+            let hashes : Vec<TrieHash> = read_next(fd)?;
+            shunts.push((idx as i64, hashes));
+        }
+
+        let proofs : Vec<Vec<TrieMerkleMultiProofEntry>> = read_next(fd)?;
+        Ok(TrieMerkleMultiProof {
+            nodes,
+            shunts,
+            proofs
+        })
+    }
 }
 
 #[cfg(test)]
@@ -1220,6 +1978,7 @@ mod test {
     use chainstate::stacks::index::test::*;
     use chainstate::stacks::index::*;
     use chainstate::stacks::index::marf::*;
+    use util::hash::to_hex;
 
     #[test]
     fn verifier_catches_stale_proof() {
@@ -1234,7 +1993,7 @@ mod test {
             Err(_) => {}
         };
 
-        let mut m = MARF::from_path(&path, None).unwrap();
+        let mut m = MARF::from_path(&path, None, TrieStorageLayout::FilePerBlock).unwrap();
 
         let sentinel_block = TrieFileStorage::block_sentinel();
         let block_0 = BlockHeaderHash([0u8; 32]);
@@ -1292,4 +2051,231 @@ mod test {
         let block_map = m.borrow_storage_backend().block_map.clone();
         assert!(proof_1.verify(&triepath_1, &marf_value_1, &root_hash_1, &root_to_block));
     }
+
+    fn make_absence_test_keys(seed: &mut Vec<u8>, count: usize) -> Vec<String> {
+        let mut keys = vec![];
+        for _ in 0..count {
+            let path = TrieHash::from_data(&seed[..]).as_bytes()[0..32].to_vec();
+            *seed = path.clone();
+            keys.push(to_hex(&path));
+        }
+        keys
+    }
+
+    #[test]
+    fn marf_absence_proofs_randomized() {
+        use rand::RngCore;
+        use rand::thread_rng;
+
+        for round in 0..4 {
+            let mut seed_bytes = [0u8; 32];
+            thread_rng().fill_bytes(&mut seed_bytes);
+            test_debug!("Absence proof round {}: seed {}", round, to_hex(&seed_bytes));
+            let mut seed = seed_bytes.to_vec();
+
+            let path = format!("/tmp/rust_marf_absence_proofs_randomized_{}", round);
+            match fs::metadata(&path) {
+                Ok(_) => {
+                    fs::remove_dir_all(&path).unwrap();
+                },
+                Err(_) => {}
+            };
+
+            let mut m = MARF::from_path(&path, None, TrieStorageLayout::FilePerBlock).unwrap();
+
+            // block 0 is empty; each later block inserts a batch of random keys, so later absence
+            // proofs have to walk back through earlier tries.
+            let mut blocks = vec![];
+            let mut num_present = vec![];
+            let mut inserted = vec![];
+            let mut prev_block = TrieFileStorage::block_sentinel();
+            for b in 0..8u8 {
+                let block = BlockHeaderHash([b; 32]);
+                m.begin(&prev_block, &block).unwrap();
+                if b > 0 {
+                    let keys = make_absence_test_keys(&mut seed, 8 * (round + 1));
+                    for key in keys.iter() {
+                        m.insert(key, MARFValue::from_value(key)).unwrap();
+                    }
+                    inserted.extend(keys);
+                }
+                m.commit().unwrap();
+
+                blocks.push(block.clone());
+                num_present.push(inserted.len());
+                prev_block = block;
+            }
+
+            let never_inserted = make_absence_test_keys(&mut seed, 16);
+            let root_to_block = m.borrow_storage_backend().read_root_to_block_table().unwrap();
+
+            for b in 0..blocks.len() {
+                let block = &blocks[b];
+                let root_hash = m.get_root_hash_at(block).unwrap();
+                let other_root_hash = m.get_root_hash_at(&blocks[(b + 1) % blocks.len()]).unwrap();
+                let present = &inserted[0..num_present[b]];
+
+                // keys inserted in later blocks are absent here too
+                let mut absent = never_inserted.clone();
+                absent.extend_from_slice(&inserted[num_present[b]..]);
+
+                for (i, key) in absent.iter().enumerate() {
+                    let triepath = TriePath::from_key(key);
+                    let proof = TrieMerkleProof::from_absent_key(m.borrow_storage_backend(), key, block).unwrap();
+                    assert!(proof.verify_absent(&triepath, &root_hash, &root_to_block));
+
+                    // only valid for this block's root
+                    assert!(!proof.verify_absent(&triepath, &other_root_hash, &root_to_block));
+
+                    // tampering with the node where the path stops resolving invalidates it
+                    let mut tampered = proof.0.clone();
+                    match tampered[0] {
+                        TrieMerkleProofType::Diverged((_, ref mut hashes)) => {
+                            hashes[0] = TrieHash([0xff; 32]);
+                        },
+                        TrieMerkleProofType::Leaf((_, ref mut leaf)) => {
+                            leaf.data = MARFValue([0xff; 40]);
+                        },
+                        _ => {
+                            panic!("Exclusion proof does not start with a leaf or diverged node: {:?}", &proof);
+                        }
+                    }
+                    assert!(!TrieMerkleProof::verify_absence_proof(&tampered, &triepath, &root_hash, &root_to_block));
+
+                    // never valid for a key that is present
+                    if i < never_inserted.len() {
+                        for present_key in present.iter() {
+                            assert!(!proof.verify_absent(&TriePath::from_key(present_key), &root_hash, &root_to_block));
+                        }
+                    }
+                }
+
+                for key in present.iter() {
+                    let triepath = TriePath::from_key(key);
+                    match TrieMerkleProof::from_absent_key(m.borrow_storage_backend(), key, block) {
+                        Err(Error::ExistsError) => {},
+                        x => {
+                            panic!("Expected ExistsError for {}, got {:?}", key, &x);
+                        }
+                    }
+
+                    // an inclusion proof is not an exclusion proof
+                    let proof = TrieMerkleProof::from_entry(m.borrow_storage_backend(), key, key, block).unwrap();
+                    assert!(proof.verify(&triepath, &MARFValue::from_value(key), &root_hash, &root_to_block));
+                    assert!(!proof.verify_absent(&triepath, &root_hash, &root_to_block));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn marf_multi_proof() {
+        let path = "/tmp/rust_marf_multi_proof".to_string();
+        match fs::metadata(&path) {
+            Ok(_) => {
+                fs::remove_dir_all(&path).unwrap();
+            },
+            Err(_) => {}
+        };
+
+        let mut m = MARF::from_path(&path, None, TrieStorageLayout::FilePerBlock).unwrap();
+        let mut seed = TrieHash::from_data(&[]).as_bytes().to_vec();
+
+        let mut inserted = vec![];
+        let mut prev_block = TrieFileStorage::block_sentinel();
+        for b in 0..4u8 {
+            let block = BlockHeaderHash([b; 32]);
+            m.begin(&prev_block, &block).unwrap();
+            let keys = make_absence_test_keys(&mut seed, 64);
+            for key in keys.iter() {
+                m.insert(key, MARFValue::from_value(key)).unwrap();
+            }
+            inserted.extend(keys);
+            m.commit().unwrap();
+            prev_block = block;
+        }
+
+        let tip = prev_block.clone();
+        let root_hash = m.get_root_hash_at(&tip).unwrap();
+        let root_to_block = m.borrow_storage_backend().read_root_to_block_table().unwrap();
+
+        // keys from every block, plus a few that were never inserted
+        let mut entries = vec![];
+        for i in 0..16 {
+            let key = inserted[i * 16].clone();
+            entries.push((key.clone(), Some(key)));
+        }
+        for key in make_absence_test_keys(&mut seed, 4) {
+            entries.push((key, None));
+        }
+
+        let paths : Vec<TriePath> = entries.iter().map(|(key, _)| TriePath::from_key(key)).collect();
+        let values : Vec<Option<MARFValue>> = entries.iter().map(|(_, value)| value.as_ref().map(|v| MARFValue::from_value(v))).collect();
+
+        let multi_proof = TrieMerkleMultiProof::from_entries(m.borrow_storage_backend(), &entries, &tip).unwrap();
+        assert!(multi_proof.verify(&paths, &values, &root_hash, &root_to_block));
+
+        // shared nodes (at least the tip's root) and shunt proofs are only stored once
+        let mut num_nodes = 0;
+        let mut num_shunts = 0;
+        for proof in multi_proof.to_proofs().unwrap().iter() {
+            for proof_node in proof.iter() {
+                match proof_node {
+                    TrieMerkleProofType::Shunt(_) => num_shunts += 1,
+                    TrieMerkleProofType::Leaf(_) => {},
+                    _ => num_nodes += 1
+                }
+            }
+        }
+        assert!(multi_proof.nodes.len() < num_nodes);
+        assert!(multi_proof.shunts.len() < num_shunts);
+
+        // the expanded proofs are the single-key proofs
+        let proofs = multi_proof.to_proofs().unwrap();
+        for i in 0..entries.len() {
+            let single_proof = match entries[i].1 {
+                Some(ref value) => TrieMerkleProof::from_entry(m.borrow_storage_backend(), &entries[i].0, value, &tip).unwrap(),
+                None => TrieMerkleProof::from_absent_key(m.borrow_storage_backend(), &entries[i].0, &tip).unwrap()
+            };
+            assert_eq!(proofs[i].0, single_proof.0);
+        }
+
+        // wire encoding round-trips
+        let mut bytes = vec![];
+        multi_proof.consensus_serialize(&mut bytes).unwrap();
+        let decoded = TrieMerkleMultiProof::consensus_deserialize(&mut io::Cursor::new(&bytes)).unwrap();
+        assert_eq!(decoded, multi_proof);
+        assert!(decoded.verify(&paths, &values, &root_hash, &root_to_block));
+
+        // truncated encodings don't decode
+        for len in [0, 1, bytes.len() / 2, bytes.len() - 1].iter() {
+            assert!(TrieMerkleMultiProof::consensus_deserialize(&mut io::Cursor::new(&bytes[0..*len])).is_err());
+        }
+
+        // wrong values, a wrong root, or a missing path don't verify
+        let mut wrong_values = values.clone();
+        wrong_values.swap(0, 1);
+        assert!(!multi_proof.verify(&paths, &wrong_values, &root_hash, &root_to_block));
+
+        let mut wrong_values = values.clone();
+        wrong_values[0] = None;
+        assert!(!multi_proof.verify(&paths, &wrong_values, &root_hash, &root_to_block));
+
+        let old_root_hash = m.get_root_hash_at(&BlockHeaderHash([2u8; 32])).unwrap();
+        assert!(!multi_proof.verify(&paths, &values, &old_root_hash, &root_to_block));
+
+        assert!(!multi_proof.verify(&paths[1..], &values[1..], &root_hash, &root_to_block));
+
+        // tampering with a shared node breaks it
+        let mut tampered = multi_proof.clone();
+        for hash in tampered.nodes[0].1.iter_mut() {
+            *hash = TrieHash([0xff; 32]);
+        }
+        assert!(!tampered.verify(&paths, &values, &root_hash, &root_to_block));
+
+        // so does a dangling index
+        let mut tampered = multi_proof.clone();
+        tampered.proofs[0].push(TrieMerkleMultiProofEntry::Shunt(multi_proof.shunts.len() as u32));
+        assert!(!tampered.verify(&paths, &values, &root_hash, &root_to_block));
+    }
 }
@@ -79,6 +79,12 @@ use chainstate::stacks::index::node::{
     TrieNode
 };
 
+use chainstate::stacks::index::cache::{
+    TrieNodeCache,
+    TRIE_NODE_CACHE_DEFAULT_CAPACITY
+};
+use chainstate::stacks::index::packed::TriePackedStore;
+
 use chainstate::stacks::index::Error as Error;
 
 use util::log;
@@ -454,13 +460,141 @@ impl NodeHashReader for TrieRAM {
     }
 }
 
-impl NodeHashReader for fs::File {
+/// Read handle on a single sealed trie.  The trie is either a whole block file, or a slice of a
+/// packed blob -- either way, seek offsets are relative to the start of the trie.
+pub struct TrieBlockFile {
+    fd: fs::File,
+    start: u64,
+    end: u64,
+}
+
+impl TrieBlockFile {
+    pub fn new(mut fd: fs::File, start: u64, end: u64) -> Result<TrieBlockFile, Error> {
+        fd.seek(SeekFrom::Start(start))
+            .map_err(Error::IOError)?;
+
+        Ok(TrieBlockFile {
+            fd: fd,
+            start: start,
+            end: end
+        })
+    }
+
+    pub fn from_file(fd: fs::File) -> Result<TrieBlockFile, Error> {
+        let end = fd.metadata()
+            .map_err(Error::IOError)?
+            .len();
+        TrieBlockFile::new(fd, 0, end)
+    }
+}
+
+impl Read for TrieBlockFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fd.read(buf)
+    }
+}
+
+impl Seek for TrieBlockFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let abs_pos = match pos {
+            SeekFrom::Start(off) => self.fd.seek(SeekFrom::Start(self.start + off))?,
+            SeekFrom::Current(off) => self.fd.seek(SeekFrom::Current(off))?,
+            SeekFrom::End(off) => {
+                let end = (self.end as i64).checked_add(off)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek offset overflow"))?;
+                if end < 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start of trie"));
+                }
+                self.fd.seek(SeekFrom::Start(end as u64))?
+            }
+        };
+
+        if abs_pos < self.start {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start of trie"));
+        }
+        Ok(abs_pos - self.start)
+    }
+}
+
+impl NodeHashReader for TrieBlockFile {
     fn read_node_hash_bytes<W: Write>(&mut self, ptr: &TriePtr, w: &mut W) -> Result<(), Error> {
         w.write_all(&read_node_hash_bytes(self, ptr)?)?;
         Ok(())
     }
 }
 
+/// Reads node hashes from a sealed trie, going through the node cache first.
+struct CachedNodeHashReader<'a> {
+    fd: &'a mut TrieBlockFile,
+    cache: &'a mut TrieNodeCache,
+    block_id: u32,
+}
+
+impl<'a> NodeHashReader for CachedNodeHashReader<'a> {
+    fn read_node_hash_bytes<W: Write>(&mut self, ptr: &TriePtr, w: &mut W) -> Result<(), Error> {
+        if let Some(hash) = self.cache.get_hash(self.block_id, ptr.ptr()) {
+            w.write_all(hash.as_bytes())?;
+            return Ok(());
+        }
+
+        let hash_bytes = read_node_hash_bytes(&mut *self.fd, ptr)?;
+        self.cache.store_hash(self.block_id, ptr.ptr(), TrieHash(hash_bytes));
+        w.write_all(&hash_bytes)?;
+        Ok(())
+    }
+}
+
+/// I/O counters for a TrieFileStorage
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrieStorageStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+impl TrieStorageStats {
+    /// Fraction of node cache lookups that were hits
+    pub fn cache_hit_rate(&self) -> f64 {
+        let total = self.cache_hits + self.cache_misses;
+        if total == 0 {
+            0.0
+        }
+        else {
+            (self.cache_hits as f64) / (total as f64)
+        }
+    }
+}
+
+/// How sealed tries are laid out on disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrieStorageLayout {
+    /// one file per block, under a directory tree keyed by the block hash
+    FilePerBlock,
+    /// appended into large blob files, indexed by a SQLite database
+    Packed,
+}
+
+impl TrieStorageLayout {
+    /// Determine the layout of an existing MARF directory, if it has one.
+    pub fn detect(dir_path: &String) -> Option<TrieStorageLayout> {
+        if TriePackedStore::exists(dir_path) {
+            return Some(TrieStorageLayout::Packed);
+        }
+        match fs::read_dir(dir_path) {
+            Ok(mut entries) => {
+                if entries.next().is_some() {
+                    Some(TrieStorageLayout::FilePerBlock)
+                }
+                else {
+                    None
+                }
+            },
+            Err(_) => None
+        }
+    }
+}
+
 // disk-backed Trie.
 // Keeps the last-extended Trie in-RAM and flushes it to disk on either a call to flush() or a call
 // to extend_to_block() with a different block header hash.
@@ -470,7 +604,11 @@ pub struct TrieFileStorage {
     last_extended: Option<(BlockHeaderHash, TrieRAM)>,
     
     cur_block: BlockHeaderHash,
-    cur_block_fd: Option<fs::File>,
+    cur_block_fd: Option<TrieBlockFile>,
+    cur_block_id: Option<u32>,
+
+    layout: TrieStorageLayout,
+    packed: Option<TriePackedStore>,
     
     read_count: u64,
     read_backptr_count: u64,
@@ -488,6 +626,9 @@ pub struct TrieFileStorage {
     // cache of block paths (they're surprisingly expensive to generate)
     block_path_cache: HashMap<BlockHeaderHash, PathBuf>,
 
+    // cache of nodes and hashes read from sealed tries
+    node_cache: TrieNodeCache,
+
     pub trie_ancestor_hash_bytes_cache: Option<(BlockHeaderHash, Vec<TrieHash>)>,
 
     miner_tip: Option<BlockHeaderHash>,
@@ -499,7 +640,15 @@ pub struct TrieFileStorage {
 }
 
 impl TrieFileStorage {
+    /// Open the Trie forest in the given directory, using whatever layout it already has.  New
+    /// directories get the file-per-block layout.
     pub fn new(dir_path: &str) -> Result<TrieFileStorage, Error> {
+        TrieFileStorage::open(dir_path, TrieStorageLayout::FilePerBlock)
+    }
+
+    /// Open the Trie forest in the given directory.  If the directory is new or empty, it will be
+    /// given the requested layout; otherwise, the layout already on disk is used.
+    pub fn open(dir_path: &str, layout: TrieStorageLayout) -> Result<TrieFileStorage, Error> {
         match fs::metadata(dir_path) {
             Ok(md) => {
                 if !md.is_dir() {
@@ -517,13 +666,26 @@ impl TrieFileStorage {
         }
 
         let dir_path = dir_path.to_string();
-        let partially_written_state = TrieFileStorage::scan_tmp_blocks(&dir_path)?;
-        if partially_written_state.len() > 0 {
-            return Err(Error::PartialWriteError);
-        }
+        let layout = TrieStorageLayout::detect(&dir_path).unwrap_or(layout);
 
-        let (block_map, chain_tips) = TrieFileStorage::read_block_hash_map(&dir_path)?;
-        test_debug!("Opened TrieFileStorage {}; {} blocks", dir_path, block_map.len());
+        let (packed, block_map, chain_tips) = match layout {
+            TrieStorageLayout::FilePerBlock => {
+                let partially_written_state = TrieFileStorage::scan_tmp_blocks(&dir_path)?;
+                if partially_written_state.len() > 0 {
+                    return Err(Error::PartialWriteError);
+                }
+
+                let (block_map, chain_tips) = TrieFileStorage::read_block_hash_map(&dir_path)?;
+                (None, block_map, chain_tips)
+            },
+            TrieStorageLayout::Packed => {
+                let packed = TriePackedStore::open(&dir_path)?;
+                let (block_map, chain_tips) = TrieFileStorage::read_packed_block_hash_map(&packed)?;
+                (Some(packed), block_map, chain_tips)
+            }
+        };
+
+        test_debug!("Opened TrieFileStorage {} ({:?}); {} blocks", dir_path, layout, block_map.len());
 
         let ret = TrieFileStorage {
             dir_path,
@@ -532,6 +694,10 @@ impl TrieFileStorage {
 
             cur_block: TrieFileStorage::block_sentinel(),
             cur_block_fd: None,
+            cur_block_id: None,
+
+            layout: layout,
+            packed: packed,
             
             read_count: 0,
             read_backptr_count: 0,
@@ -546,6 +712,7 @@ impl TrieFileStorage {
             chain_tips: chain_tips,
 
             block_path_cache: HashMap::new(),
+            node_cache: TrieNodeCache::new(TRIE_NODE_CACHE_DEFAULT_CAPACITY),
             trie_ancestor_hash_bytes_cache: None,
   
             miner_tip: None,
@@ -559,6 +726,10 @@ impl TrieFileStorage {
         Ok(ret)
     }
 
+    pub fn get_layout(&self) -> TrieStorageLayout {
+        self.layout
+    }
+
     pub fn set_miner_tip(&mut self, miner_tip: BlockHeaderHash) {
         self.miner_tip = Some(miner_tip)
     }
@@ -599,13 +770,27 @@ impl TrieFileStorage {
         BlockHeaderHash([255u8; BLOCK_HEADER_HASH_ENCODED_SIZE as usize])
     }
 
-    #[cfg(test)]
-    pub fn stats(&mut self) -> (u64, u64) {
-        let r = self.read_count;
-        let w = self.write_count;
+    /// Get and reset the read/write and node cache counters
+    pub fn stats(&mut self) -> TrieStorageStats {
+        let (cache_hits, cache_misses) = self.node_cache.stats();
+        let stats = TrieStorageStats {
+            reads: self.read_count,
+            writes: self.write_count,
+            cache_hits: cache_hits,
+            cache_misses: cache_misses
+        };
         self.read_count = 0;
         self.write_count = 0;
-        (r, w)
+        stats
+    }
+
+    /// Set the maximum number of nodes and node hashes to cache.  0 disables the cache.
+    pub fn set_node_cache_capacity(&mut self, capacity: usize) {
+        self.node_cache.set_capacity(capacity);
+    }
+
+    pub fn get_node_cache_capacity(&self) -> usize {
+        self.node_cache.capacity()
     }
     
     #[cfg(test)]
@@ -726,8 +911,16 @@ impl TrieFileStorage {
         where F: FnMut(String, PathBuf) -> Result<(), Error> {
         for dir_1_res in fs::read_dir(dir_path).map_err(Error::IOError)? {
             let dir_1_entry = dir_1_res.map_err(Error::IOError)?;
+            if !dir_1_entry.path().is_dir() {
+                trace!("Skip {:?}", &dir_1_entry.path());
+                continue;
+            }
             for dir_2_res in fs::read_dir(&dir_1_entry.path()).map_err(Error::IOError)? {
                 let dir_2_entry = dir_2_res.map_err(Error::IOError)?;
+                if !dir_2_entry.path().is_dir() {
+                    trace!("Skip {:?}", &dir_2_entry.path());
+                    continue;
+                }
                 for block_file_res in fs::read_dir(&dir_2_entry.path()).map_err(Error::IOError)? {
                     let block_file = block_file_res.map_err(Error::IOError)?;
                     if !block_file.path().is_file() {
@@ -790,7 +983,6 @@ impl TrieFileStorage {
 
     fn read_block_hash_map(dir_path: &String) -> Result<(BlockHashMap, HashSet<BlockHeaderHash>), Error> {
         let mut blocks = vec![];
-
         let mut parents = HashSet::new();
 
         TrieFileStorage::scan_blocks(dir_path, |block_name, block_path| {
@@ -807,11 +999,34 @@ impl TrieFileStorage {
             Ok(())
         })?;
 
+        TrieFileStorage::build_block_hash_map(blocks, parents)
+    }
+
+    fn read_packed_block_hash_map(packed: &TriePackedStore) -> Result<(BlockHashMap, HashSet<BlockHeaderHash>), Error> {
+        let mut blocks = vec![];
+        let mut parents = HashSet::new();
+
+        for (bhh, identifier, parent) in packed.list_blocks()?.drain(..) {
+            blocks.push((bhh, identifier));
+            parents.insert(parent);
+        }
+
+        TrieFileStorage::build_block_hash_map(blocks, parents)
+    }
+
+    fn build_block_hash_map(mut blocks: Vec<(BlockHeaderHash, u32)>, parents: HashSet<BlockHeaderHash>) -> Result<(BlockHashMap, HashSet<BlockHeaderHash>), Error> {
         if blocks.len() > (u32::max_value() as usize) {
             return Err(Error::CorruptionError("Too many blocks have been found.".to_string()));
         }
 
-        let mut block_hash_map = BlockHashMap::new(Some(blocks.len() as u32));
+        // identifiers of pruned tries leave gaps, which are filled with the sentinel hash
+        let map_size = match blocks.iter().map(|(_, identifier)| *identifier).max() {
+            Some(max_identifier) => max_identifier.checked_add(1)
+                .ok_or_else(|| Error::CorruptionError("Block identifier overflow.".to_string()))?,
+            None => 0
+        };
+
+        let mut block_hash_map = BlockHashMap::new(Some(map_size));
         let mut chain_tips = HashSet::new();
 
         for (bhh, identifier) in blocks.drain(..) {
@@ -841,21 +1056,34 @@ impl TrieFileStorage {
                         }
                     })?;
 
+        TrieFileStorage::read_root_hash_from_fd(&mut fd)
+    }
+
+    #[cfg(test)]
+    fn read_root_hash_from_fd<F: Read + Seek>(fd: &mut F) -> Result<TrieHash, Error> {
         let root_hash_ptr =
             TriePtr::new(TrieNodeID::Node256, 0, TrieFileStorage::root_ptr_disk());
-        let hash = read_node_hash_bytes(&mut fd, &root_hash_ptr)?;
+        let hash = read_node_hash_bytes(fd, &root_hash_ptr)?;
 
         Ok(TrieHash(hash))
     }
 
     #[cfg(test)]
     pub fn read_block_root_hash(&self, bhh: &BlockHeaderHash) -> Result<TrieHash, Error> {
+        if let Some(ref packed) = self.packed {
+            let mut fd = packed.open_trie(bhh)?;
+            return TrieFileStorage::read_root_hash_from_fd(&mut fd);
+        }
         let path = TrieFileStorage::block_path(&self.dir_path, bhh);
         self.read_block_root_hash_by_path(&path)
     }
     
     #[cfg(test)]
     pub fn read_tmp_block_root_hash(&self, bhh: &BlockHeaderHash) -> Result<TrieHash, Error> {
+        if self.packed.is_some() {
+            // packed storage never has partially-written tries
+            return Err(Error::NotFoundError);
+        }
         let path = TrieFileStorage::block_path_tmp(&self.dir_path, bhh);
         self.read_block_root_hash_by_path(&path)
     }
@@ -932,6 +1160,22 @@ impl TrieFileStorage {
 
         let trie_buf = TrieRAM::new(bhh, size_hint, identifier, &self.cur_block);
 
+        if let Some(ref packed) = self.packed {
+            // nothing is written to packed storage until the trie is sealed
+            if packed.has_block(bhh)? {
+                trace!("Block exists in packed storage: {:?}", bhh);
+                return Err(Error::ExistsError);
+            }
+
+            trace!("Extend from {:?} to {:?} in packed storage", &self.cur_block, bhh);
+
+            self.cur_block = bhh.clone();
+            self.cur_block_fd = None;
+            self.cur_block_id = None;
+            self.last_extended = Some((bhh.clone(), trie_buf));
+            return Ok(());
+        }
+
         // create an empty file for this block, so we can't extend to it again
         let block_dir = TrieFileStorage::block_dir(&self.dir_path, bhh);
         let block_path = TrieFileStorage::block_path(&self.dir_path, bhh);
@@ -990,6 +1234,7 @@ impl TrieFileStorage {
         // update internal structures
         self.cur_block = bhh.clone();
         self.cur_block_fd = None;
+        self.cur_block_id = None;
 
         self.last_extended = Some((bhh.clone(), trie_buf));
 
@@ -1007,6 +1252,7 @@ impl TrieFileStorage {
         if *bhh == sentinel {
             // just reset to newly opened state
             self.cur_block_fd = None;
+            self.cur_block_id = None;
             self.cur_block = sentinel;
             return Ok(());
         }
@@ -1016,12 +1262,22 @@ impl TrieFileStorage {
                 // nothing to do -- we're already ready.
                 // just clear out.
                 self.cur_block_fd = None;
+                self.cur_block_id = None;
                 self.cur_block = bhh.clone();
                 return Ok(());
             }
         }
 
         // opening a different Trie than the one we're extending
+        if let Some(ref packed) = self.packed {
+            let mut fd = packed.open_trie(bhh)?;
+            let block_id = TrieFileStorage::read_block_identifier_from_fd(&mut fd)?;
+            self.cur_block = bhh.clone();
+            self.cur_block_fd = Some(fd);
+            self.cur_block_id = Some(block_id);
+            return Ok(());
+        }
+
         let block_path = self.cached_block_path(bhh);
         let fd = fs::OpenOptions::new()
                     .read(true)
@@ -1037,8 +1293,12 @@ impl TrieFileStorage {
                         }
                     })?;
 
+        let mut fd = TrieBlockFile::from_file(fd)?;
+        let block_id = TrieFileStorage::read_block_identifier_from_fd(&mut fd)?;
+
         self.cur_block = bhh.clone();
         self.cur_block_fd = Some(fd);
+        self.cur_block_id = Some(block_id);
 
         Ok(())
     }
@@ -1050,10 +1310,9 @@ impl TrieFileStorage {
             }
         }
 
-        if let Some(ref mut cur_block_fd) = self.cur_block_fd {
-            TrieFileStorage::read_block_identifier_from_fd(cur_block_fd)
-        } else {
-            Err(Error::NotOpenedError)
+        match (self.cur_block_fd.is_some(), self.cur_block_id) {
+            (true, Some(block_id)) => Ok(block_id),
+            _ => Err(Error::NotOpenedError)
         }
     }
     
@@ -1092,6 +1351,12 @@ impl TrieFileStorage {
     pub fn format(&mut self) -> Result<(), Error> {
         debug!("Format TrieFileStorage {}", &self.dir_path);
 
+        // close the packed index before blowing it away
+        self.packed = None;
+        self.cur_block_fd = None;
+        self.cur_block_id = None;
+        self.node_cache.clear();
+
         // blow away and recreate the Trie directory
         fs::remove_dir_all(self.dir_path.clone())
             .map_err(Error::IOError)?;
@@ -1099,6 +1364,10 @@ impl TrieFileStorage {
         fs::create_dir_all(self.dir_path.clone())
             .map_err(Error::IOError)?;
 
+        if self.layout == TrieStorageLayout::Packed {
+            self.packed = Some(TriePackedStore::open(&self.dir_path)?);
+        }
+
         match self.last_extended {
             Some((_, ref mut trie_storage)) => trie_storage.format()?,
             None => {}
@@ -1146,13 +1415,20 @@ impl TrieFileStorage {
         }
 
         // otherwise, the current block is open as an FD
-        let hash_reader = self.cur_block_fd.as_mut()
+        let fd = self.cur_block_fd.as_mut()
             .ok_or_else(|| {
                 error!("Failed to get cur block fd as hash reader");
                 Error::NotFoundError
             })?;
 
-        TrieFileStorage::inner_write_children_hashes(hash_reader, block_map, node, w)
+        let block_id = self.cur_block_id.ok_or(Error::NotOpenedError)?;
+        let mut hash_reader = CachedNodeHashReader {
+            fd: fd,
+            cache: &mut self.node_cache,
+            block_id: block_id
+        };
+
+        TrieFileStorage::inner_write_children_hashes(&mut hash_reader, block_map, node, w)
     }
 
     fn inner_write_children_hashes<W: Write, H: NodeHashReader>(
@@ -1194,8 +1470,15 @@ impl TrieFileStorage {
         // some other block or ptr, or cache miss
         match self.cur_block_fd {
             Some(ref mut f) => {
-                read_node_hash_bytes(f, ptr)
-                    .map(TrieHash)
+                let block_id = self.cur_block_id.ok_or(Error::NotOpenedError)?;
+                if let Some(hash) = self.node_cache.get_hash(block_id, ptr.ptr()) {
+                    return Ok(hash);
+                }
+
+                let hash = read_node_hash_bytes(f, ptr)
+                    .map(TrieHash)?;
+                self.node_cache.store_hash(block_id, ptr.ptr(), hash.clone());
+                Ok(hash)
             },
             None => {
                 error!("Not found (no file is open)");
@@ -1230,7 +1513,16 @@ impl TrieFileStorage {
 
         // some other block
         match self.cur_block_fd {
-            Some(ref mut f) => read_nodetype(f, &clear_ptr),
+            Some(ref mut f) => {
+                let block_id = self.cur_block_id.ok_or(Error::NotOpenedError)?;
+                if let Some(node_and_hash) = self.node_cache.get_node(block_id, clear_ptr.ptr(), clear_ptr.id()) {
+                    return Ok(node_and_hash);
+                }
+
+                let (node, hash) = read_nodetype(f, &clear_ptr)?;
+                self.node_cache.store_node(block_id, clear_ptr.ptr(), node.clone(), hash.clone());
+                Ok((node, hash))
+            },
             None => {
                 error!("Not found (no file is open)");
                 Err(Error::NotFoundError)
@@ -1276,9 +1568,11 @@ impl TrieFileStorage {
         debug!("Retarget block {} to {}", cur_bhh, new_bhh);
 
         // switch over state
-        let block_dir = TrieFileStorage::block_dir(&self.dir_path, new_bhh);
-        fs::create_dir_all(block_dir)
-            .map_err(Error::IOError)?;
+        if self.packed.is_none() {
+            let block_dir = TrieFileStorage::block_dir(&self.dir_path, new_bhh);
+            fs::create_dir_all(block_dir)
+                .map_err(Error::IOError)?;
+        }
 
         // make it as if we had inserted this block the whole time
         if self.chain_tips.contains(cur_bhh) {
@@ -1310,6 +1604,10 @@ impl TrieFileStorage {
         // after.  Turns out rename(2) isn't crash-consistent, and turns out syscalls can get
         // reordered.
         if let Some((ref bhh, ref mut trie_ram)) = self.last_extended.take() {
+            if self.packed.is_some() {
+                return self.flush_packed(bhh, trie_ram, final_bhh);
+            }
+
             let block_path_tmp = TrieFileStorage::block_path_tmp(&self.dir_path, bhh);
             let (block_path, real_bhh) = match final_bhh {
                 Some(real_bhh) => {
@@ -1361,12 +1659,97 @@ impl TrieFileStorage {
         Ok(())
     }
 
+    /// Seal the currently-bufferred Trie and append it to packed storage.  The append is durable
+    /// once this returns; see TriePackedStore::append().
+    fn flush_packed(&mut self, bhh: &BlockHeaderHash, trie_ram: &mut TrieRAM, final_bhh: Option<&BlockHeaderHash>) -> Result<(), Error> {
+        let real_bhh = match final_bhh {
+            Some(real_bhh) => {
+                if *real_bhh != *bhh {
+                    self.block_retarget(bhh, real_bhh)?;
+                    assert_eq!(self.block_map.find_id(real_bhh), Some(trie_ram.identifier));
+                }
+                real_bhh.clone()
+            },
+            None => bhh.clone()
+        };
+
+        debug!("Flush {:?} to packed storage as {:?}", bhh, &real_bhh);
+
+        let mut buf = Cursor::new(Vec::with_capacity(trie_ram.size_hint()));
+        trie_ram.dump(&mut buf, bhh)?;
+
+        debug!("Flush: identifier of {:?} is {:?}", real_bhh, trie_ram.identifier);
+        match self.packed {
+            Some(ref mut packed) => packed.append(&real_bhh, buf.get_ref()),
+            None => {
+                panic!("Packed storage is not open");
+            }
+        }
+    }
+
     pub fn flush(&mut self) -> Result<(), Error> {
         self.flush_to(None)
     }
 
+    /// Convert a MARF directory from the file-per-block layout to the packed layout.  Every
+    /// block's trie is copied verbatim into the packed blobs, the packed index is atomically put
+    /// into place, and then the per-block files are removed.  The directory must not have any
+    /// partially-written tries, and must not be open.  Returns the number of tries migrated.
+    pub fn migrate_to_packed(dir_path: &str) -> Result<u64, Error> {
+        let dir_path = dir_path.to_string();
+        if TriePackedStore::exists(&dir_path) {
+            error!("{} already uses the packed layout", &dir_path);
+            return Err(Error::ExistsError);
+        }
+
+        let partially_written_state = TrieFileStorage::scan_tmp_blocks(&dir_path)?;
+        if partially_written_state.len() > 0 {
+            return Err(Error::PartialWriteError);
+        }
+
+        let mut packed = TriePackedStore::create_for_migration(&dir_path)?;
+        let mut count = 0;
+
+        TrieFileStorage::scan_blocks(&dir_path, |block_name, block_path| {
+            let bhh = match BlockHeaderHash::from_hex(&block_name) {
+                Ok(h) => h,
+                Err(_) => {
+                    trace!("Skip {:?}", &block_path);
+                    return Ok(());
+                }
+            };
+
+            let trie_bytes = fs::read(&block_path)
+                .map_err(Error::IOError)?;
+
+            packed.append(&bhh, &trie_bytes)?;
+            count += 1;
+            Ok(())
+        })?;
+
+        packed.commit_migration()?;
+        debug!("Migrated {} tries in {} to the packed layout", count, &dir_path);
+
+        // the packed index is in place, so the per-block files are now garbage
+        for dir_res in fs::read_dir(&dir_path).map_err(Error::IOError)? {
+            let dir_entry = dir_res.map_err(Error::IOError)?;
+            if !dir_entry.path().is_dir() {
+                continue;
+            }
+            debug!("Remove per-block trie directory {:?}", &dir_entry.path());
+            fs::remove_dir_all(dir_entry.path())
+                .map_err(Error::IOError)?;
+        }
+
+        Ok(count)
+    }
+
     pub fn drop_extending_trie(&mut self) {
         if let Some((ref bhh, _)) = self.last_extended.take() {
+            if self.packed.is_some() {
+                // nothing was written for this trie
+                return;
+            }
             let block_path_tmp = TrieFileStorage::block_path_tmp(&self.dir_path, bhh);
             match fs::metadata(&block_path_tmp) {
                 Ok(_md) => {
@@ -1385,6 +1768,58 @@ impl TrieFileStorage {
         self.last_extended = None;
     }
 
+    /// Delete a sealed trie.  Only tries that no other stored trie descends from may be deleted,
+    /// since descendants' back-pointers would otherwise dangle.  The trie's identifier is not
+    /// reused while the storage is open.  In the packed layout, the trie's bytes stay in their
+    /// blob unless it was the last trie appended.
+    pub fn delete_block(&mut self, bhh: &BlockHeaderHash) -> Result<(), Error> {
+        if let Some((ref last_extended, _)) = self.last_extended {
+            if last_extended == bhh {
+                error!("Cannot delete trie {:?} while it is being extended", bhh);
+                return Err(Error::InProgressError);
+            }
+        }
+
+        let identifier = self.block_map.find_id(bhh)
+            .ok_or(Error::NotFoundError)?;
+
+        match self.packed {
+            Some(ref mut packed) => {
+                packed.delete(bhh)?;
+            },
+            None => {
+                let block_path = TrieFileStorage::block_path(&self.dir_path, bhh);
+                fs::remove_file(&block_path)
+                    .map_err(|e| {
+                        if e.kind() == io::ErrorKind::NotFound {
+                            error!("File not found: {:?}", &block_path);
+                            Error::NotFoundError
+                        }
+                        else {
+                            Error::IOError(e)
+                        }
+                    })?;
+            }
+        }
+
+        debug!("Deleted trie {:?} (identifier {})", bhh, identifier);
+
+        if self.cur_block == *bhh {
+            self.cur_block = TrieFileStorage::block_sentinel();
+            self.cur_block_fd = None;
+            self.cur_block_id = None;
+        }
+
+        self.block_map.set_block(TrieFileStorage::block_sentinel(), identifier);
+        self.chain_tips.remove(bhh);
+        self.block_path_cache.remove(bhh);
+        self.trie_ancestor_hash_bytes_cache = None;
+
+        // cached nodes are keyed by identifier, and this one is gone
+        self.node_cache.clear();
+        Ok(())
+    }
+
     pub fn last_ptr(&mut self) -> Result<u32, Error> {
         if let Some((_, ref mut trie_ram)) = self.last_extended {
             trie_ram.last_ptr()
@@ -18,14 +18,19 @@
 */
 
 use std::fs;
+use std::collections::{HashMap, VecDeque, BinaryHeap};
+use std::cmp::Reverse;
 use chainstate::stacks::Error;
 use chainstate::stacks::*;
 use chainstate::stacks::db::{
     ClarityTx,
     StacksChainState,
-    blocks::MemPoolRejection
+    blocks::MemPoolRejection,
+    blocks::BurnchainStxOp
 };
+use chainstate::burn::db::burndb::BurnDBTx;
 use chainstate::stacks::index::TrieHash;
+use chainstate::stacks::index::storage::TrieStorageLayout;
 use chainstate::burn::BlockHeaderHash;
 
 use net::StacksMessageCodec;
@@ -38,6 +43,8 @@ use util::secp256k1::MessageSignature;
 
 use net::StacksPublicKeyBuffer;
 
+use vm::clarity::Error as clarity_error;
+
 use chainstate::burn::*;
 use chainstate::burn::operations::*;
 
@@ -71,6 +78,7 @@ impl StacksBlockBuilder {
             prev_microblock_header: StacksMicroblockHeader::first_unsigned(&EMPTY_MICROBLOCK_PARENT_HASH, &Sha512Trunc256Sum([0u8; 32])),       // will be updated
             miner_privkey: microblock_privkey.clone(),
             miner_payouts: None,
+            burnchain_stx_ops: vec![],
             miner_id: miner_id
         }
     }
@@ -101,7 +109,31 @@ impl StacksBlockBuilder {
         self.header.parent_microblock_sequence = parent_mblock_seq;
     }
 
-    /// Append a transaction if doing so won't exceed the epoch data size.
+    /// Process a transaction that we might not mine.  If it fails -- including by exceeding what
+    /// is left of the block's execution budget -- then none of its writes (not even its fee
+    /// debit) are kept, and its execution cost is not counted.
+    fn try_process_tx<'a>(clarity_tx: &mut ClarityTx<'a>, tx: &StacksTransaction) -> Result<(), Error> {
+        clarity_tx.hold_writes();
+        match StacksChainState::process_transaction(clarity_tx, tx) {
+            Ok(_) => {
+                clarity_tx.release_writes();
+                Ok(())
+            },
+            Err(e) => {
+                clarity_tx.discard_writes();
+                match e {
+                    Error::ClarityError(clarity_error::CostError(ref total, ref limit)) => {
+                        debug!("Transaction {} would exceed the block execution budget ({} > {})", &tx.txid(), total, limit);
+                        Err(Error::BlockCostExceeded)
+                    },
+                    _ => Err(e)
+                }
+            }
+        }
+    }
+
+    /// Append a transaction if doing so won't exceed the epoch data size or the block's execution
+    /// budget.
     pub fn try_mine_tx<'a>(&mut self, clarity_tx: &mut ClarityTx<'a>, tx: &StacksTransaction) -> Result<(), Error> {
         let mut tx_bytes = vec![];
         tx.consensus_serialize(&mut tx_bytes).map_err(Error::NetError)?;
@@ -117,7 +149,7 @@ impl StacksBlockBuilder {
                 return Err(Error::InvalidStacksTransaction("Invalid transaction anchor mode for anchored data".to_string()));
             }
 
-            StacksChainState::process_transaction(clarity_tx, tx)?;
+            StacksBlockBuilder::try_process_tx(clarity_tx, tx)?;
 
             // save
             self.txs.push(tx.clone());
@@ -128,7 +160,7 @@ impl StacksBlockBuilder {
                 return Err(Error::InvalidStacksTransaction("Invalid transaction anchor mode for streamed data".to_string()));
             }
             
-            StacksChainState::process_transaction(clarity_tx, tx)?;
+            StacksBlockBuilder::try_process_tx(clarity_tx, tx)?;
 
             self.micro_txs.push(tx.clone());
         }
@@ -184,6 +216,10 @@ impl StacksBlockBuilder {
                 .expect("FATAL: failed to process miner rewards");
         }
 
+        // apply STX operations sent on the burn chain
+        StacksChainState::process_burnchain_stx_ops(clarity_tx, &self.burnchain_stx_ops)
+            .expect("FATAL: failed to process burnchain STX operations");
+
         let txid_vecs = self.txs
             .iter()
             .map(|tx| tx.txid().as_bytes().to_vec())
@@ -206,6 +242,9 @@ impl StacksBlockBuilder {
         self.prev_microblock_header.prev_block = block.block_hash();
         self.anchored_done = true;
 
+        // our microblock stream gets its own execution budget
+        clarity_tx.reset_cost(MICROBLOCK_STREAM_COST_LIMIT);
+
         test_debug!("\n\nMiner {}: Mined anchored block {}, {} transactions, state root is {}\n", self.miner_id, block.block_hash(), block.txs.len(), state_root_hash);
 
         block
@@ -251,12 +290,115 @@ impl StacksBlockBuilder {
     pub fn will_admit_mempool_tx<R: Read>(&self, chainstate: &mut StacksChainState, fd: &mut R) -> Result<StacksTransaction, MemPoolRejection> {
         chainstate.will_admit_mempool_tx(&self.chain_tip.burn_header_hash, &self.header.parent_block, fd)
     }
-    
+
+    /// Can a transaction with this anchor mode go into the block or microblock we're building?
+    fn is_anchor_mode_allowed(&self, tx: &StacksTransaction) -> bool {
+        match tx.anchor_mode {
+            TransactionAnchorMode::Any => true,
+            TransactionAnchorMode::OnChainOnly => !self.anchored_done,
+            TransactionAnchorMode::OffChainOnly => self.anchored_done
+        }
+    }
+
+    /// Pack as many of the candidate transactions as will fit into the anchored block or
+    /// microblock being built.  Returns the transactions mined, in order, and the transactions
+    /// that should go back to the mempool.
+    ///
+    /// Candidates are grouped by origin and each origin's transactions are tried in nonce order,
+    /// starting from the origin account's next nonce.  At each step, the origin whose next
+    /// transaction pays the highest fee rate goes next, so the block is packed greedily by fee per
+    /// byte and per cost unit until it runs out of room (MAX_EPOCH_SIZE) or execution budget.
+    ///
+    /// A transaction that can't be mined now but could be later goes back to the mempool, along
+    /// with the rest of its origin's transactions:  one after a nonce gap, one that doesn't fit,
+    /// or one whose anchor mode doesn't match what's being built.  Transactions whose nonces were
+    /// already used are dropped, as are transactions that fail to process (their origin's later
+    /// transactions now have a nonce gap, so they go back to the mempool).
+    pub fn mine_txs_by_fee_rate<'a>(&mut self, clarity_tx: &mut ClarityTx<'a>, txs: Vec<StacksTransaction>) -> (Vec<StacksTransaction>, Vec<StacksTransaction>) {
+        let mut mined = vec![];
+        let mut requeued = vec![];
+
+        let mut origin_index : HashMap<StacksAddress, usize> = HashMap::new();
+        let mut origin_txs : Vec<Vec<StacksTransaction>> = vec![];
+        for tx in txs.into_iter() {
+            if !self.is_anchor_mode_allowed(&tx) {
+                requeued.push(tx);
+                continue;
+            }
+            let next_index = origin_txs.len();
+            let index = *origin_index.entry(tx.origin_address()).or_insert(next_index);
+            if index == next_index {
+                origin_txs.push(vec![]);
+            }
+            origin_txs[index].push(tx);
+        }
+
+        // each origin's minable transactions, in nonce order
+        let mut origin_queues : Vec<VecDeque<StacksTransaction>> = Vec::with_capacity(origin_txs.len());
+        let mut candidates = BinaryHeap::new();
+        for (index, mut txs) in origin_txs.into_iter().enumerate() {
+            // lowest nonce first, and highest fee rate first among transactions with the same nonce
+            txs.sort_by(|tx1, tx2| tx1.get_origin_nonce().cmp(&tx2.get_origin_nonce()).then(tx2.get_fee_rate().cmp(&tx1.get_fee_rate())));
+
+            let origin = txs[0].origin_address();
+            let mut next_nonce = StacksChainState::get_account(clarity_tx, &origin.to_account_principal()).nonce;
+
+            let mut queue = VecDeque::with_capacity(txs.len());
+            let mut gap = false;
+            for tx in txs.into_iter() {
+                let nonce = tx.get_origin_nonce();
+                if nonce < next_nonce {
+                    debug!("Drop transaction {} from {:?}: nonce {} is already used", &tx.txid(), &origin, nonce);
+                }
+                else if nonce == next_nonce && !gap {
+                    queue.push_back(tx);
+                    next_nonce += 1;
+                }
+                else {
+                    gap = true;
+                    debug!("Return transaction {} from {:?} to the mempool: expected nonce {}, got {}", &tx.txid(), &origin, next_nonce, nonce);
+                    requeued.push(tx);
+                }
+            }
+
+            if let Some(tx) = queue.front() {
+                candidates.push((tx.get_fee_rate(), Reverse(index)));
+            }
+            origin_queues.push(queue);
+        }
+
+        while let Some((_, Reverse(index))) = candidates.pop() {
+            let tx = origin_queues[index].pop_front().expect("BUG: origin has no next transaction");
+            match self.try_mine_tx(clarity_tx, &tx) {
+                Ok(_) => {
+                    mined.push(tx);
+                    if let Some(next_tx) = origin_queues[index].front() {
+                        candidates.push((next_tx.get_fee_rate(), Reverse(index)));
+                    }
+                },
+                Err(Error::BlockTooBigError) | Err(Error::BlockCostExceeded) => {
+                    // might fit into a later block
+                    requeued.push(tx);
+                    requeued.extend(origin_queues[index].drain(..));
+                },
+                Err(e) => {
+                    warn!("Failed to mine transaction {}: {:?}", &tx.txid(), &e);
+                    requeued.extend(origin_queues[index].drain(..));
+                }
+            }
+        }
+
+        (mined, requeued)
+    }
+
     /// Begin mining an epoch's transactions.
     /// NOTE: even though we don't yet know the block hash, the Clarity VM ensures that a
     /// transaction can't query information about the _current_ block (i.e. information that is not
     /// yet known).
-    pub fn epoch_begin<'a>(&mut self, chainstate: &'a mut StacksChainState) -> Result<ClarityTx<'a>, Error> {
+    /// * burn_tip is the burnchain block this miner is building on (i.e. the one just before the
+    /// sortition this block will be a candidate in).  All STX operations sent on the burn chain
+    /// since the parent's sortition up to and including burn_tip get applied in this block.
+    pub fn epoch_begin<'a, 'b>(&mut self, chainstate: &'a mut StacksChainState, burn_tx: &mut BurnDBTx<'b>, burn_tip: &BurnchainHeaderHash) -> Result<ClarityTx<'a>, Error> {
         // find matured miner rewards, so we can grant them within the Clarity DB tx.
         let matured_miner_rewards_opt = {
             let mut tx = chainstate.headers_tx_begin()?;
@@ -264,6 +406,17 @@ impl StacksBlockBuilder {
         };
 
         self.miner_payouts = matured_miner_rewards_opt;
+
+        // find the STX operations sent on the burn chain that this block must apply
+        let parent_sortition_burn_header_hash = 
+            if self.chain_tip.is_genesis() {
+                FIRST_BURNCHAIN_BLOCK_HASH.clone()
+            }
+            else {
+                self.chain_tip.burn_header_hash.clone()
+            };
+
+        self.burnchain_stx_ops = StacksChainState::get_burnchain_stx_ops(burn_tx, &parent_sortition_burn_header_hash, burn_tip)?;
         
         // there's no way the miner can learn either the burn block hash or the stacks block hash,
         // so use a sentinel hash value for each that will never occur in practice.
@@ -286,6 +439,7 @@ impl StacksBlockBuilder {
         };
 
         let mut tx = chainstate.block_begin(&parent_burn_header_hash, &parent_header_hash, &new_burn_hash, &new_block_hash);
+        tx.reset_cost(MICROBLOCK_STREAM_COST_LIMIT);
 
         test_debug!("Miner {}: Apply {} parent microblocks", self.miner_id, parent_microblocks.len());
 
@@ -309,6 +463,7 @@ impl StacksBlockBuilder {
         
         test_debug!("Miner {}: Finished applying {} parent microblocks\n", self.miner_id, parent_microblocks.len());
 
+        tx.reset_cost(ANCHORED_BLOCK_COST_LIMIT);
         Ok(tx)
     }
 
@@ -321,6 +476,13 @@ impl StacksBlockBuilder {
 
         // clear out the block trie we just created, so the block validator logic doesn't step all
         // over it.
+        if tx.get_index_layout() == TrieStorageLayout::Packed {
+            // packed tries can't be set aside under another name, and nothing reads them back.
+            tx.rollback_block();
+            test_debug!("\n\nMiner {}: Finished mining child of {}/{}. Discarded trie for {}\n", self.miner_id, self.chain_tip.burn_header_hash, self.chain_tip.anchored_header.block_hash(), &index_block_hash);
+            return;
+        }
+
         let moved_filename = format!("{}.mined", index_block_hash);
         let block_pathbuf = tx.get_block_path(&new_burn_hash, &new_block_hash);
         let mut mined_block_pathbuf = block_pathbuf.clone();
@@ -364,6 +526,7 @@ pub mod test {
     use util::vrf::*;
 
     use vm::types::*;
+    use vm::costs::ExecutionCost;
 
     use rand::Rng;
     use rand::thread_rng;
@@ -770,12 +933,12 @@ pub mod test {
                                 burn_amount: u64,
                                 block_assembler: F) -> (StacksBlock, Vec<StacksMicroblock>, LeaderBlockCommitOp) 
         where
-            F: FnOnce(StacksBlockBuilder, &mut TestMiner) -> (StacksBlock, Vec<StacksMicroblock>)
+            F: FnOnce(StacksBlockBuilder, &mut TestMiner, &mut BurnDBTx, &BurnchainHeaderHash) -> (StacksBlock, Vec<StacksMicroblock>)
         {
             let proof = miner.make_proof(&miner_key.public_key, &burn_block.parent_snapshot.sortition_hash)
                 .expect(&format!("FATAL: no private key for {}", miner_key.public_key.to_hex()));
 
-            let (builder, parent_block_snapshot_opt) = match parent_stacks_block {
+            let (mut builder, parent_block_snapshot_opt) = match parent_stacks_block {
                 None => {
                     // first stacks block
                     let builder = StacksBlockBuilder::first(miner.id, &burn_block.parent_snapshot.burn_header_hash, burn_block.parent_snapshot.burn_header_timestamp, &proof, &miner.next_microblock_privkey());
@@ -805,7 +968,10 @@ pub mod test {
 
             test_debug!("Miner {}: Assemble stacks block from {}", miner.id, miner.origin_address().unwrap().to_string());
 
-            let (stacks_block, microblocks) = block_assembler(builder, miner);
+            let (stacks_block, microblocks) = {
+                let mut burn_tx = burn_node.burndb.tx_begin().unwrap();
+                block_assembler(builder, miner, &mut burn_tx, &burn_block.parent_snapshot.burn_header_hash)
+            };
             self.anchored_blocks.push(stacks_block.clone());
             self.microblocks.push(microblocks.clone());
             
@@ -864,7 +1030,7 @@ pub mod test {
     /// Verify that the stacks block's state root matches the state root in the chain state
     fn check_block_state_index_root(chainstate: &mut StacksChainState, burn_header_hash: &BurnchainHeaderHash, stacks_header: &StacksBlockHeader) -> bool {
         let index_block_hash = StacksBlockHeader::make_index_block_hash(burn_header_hash, &stacks_header.block_hash());
-        let mut state_root_index = StacksChainState::open_index(&chainstate.clarity_state_index_path, Some(&StacksBlockHeader::make_index_block_hash(&MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH))).unwrap();
+        let mut state_root_index = StacksChainState::open_index(&chainstate.clarity_state_index_path, Some(&StacksBlockHeader::make_index_block_hash(&MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH)), chainstate.index_layout).unwrap();
         let state_root = state_root_index.borrow_storage_backend().read_block_root_hash(&index_block_hash).unwrap();
         state_root == stacks_header.state_index_root
     }
@@ -1001,13 +1167,13 @@ pub mod test {
             // next key
             node.add_key_register(&mut burn_block, &mut miner);
 
-            let (stacks_block, microblocks, block_commit_op) = node.mine_stacks_block(&mut burn_node, &mut miner, &mut burn_block, &last_key, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block, microblocks, block_commit_op) = node.mine_stacks_block(&mut burn_node, &mut miner, &mut burn_block, &last_key, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block");
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1098,13 +1264,13 @@ pub mod test {
             node.add_key_register(&mut burn_block, &mut miner_1);
             node.add_key_register(&mut burn_block, &mut miner_2);
 
-            let (stacks_block, microblocks, block_commit_op) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block, microblocks, block_commit_op) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block");
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_1_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1174,13 +1340,13 @@ pub mod test {
             node.add_key_register(&mut burn_block, &mut miner_1);
             node.add_key_register(&mut burn_block, &mut miner_2);
             
-            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block in stacks fork 1 via {}", miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_1_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1189,13 +1355,13 @@ pub mod test {
                 (stacks_block, microblocks)
             });
             
-            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block in stacks fork 2 via {}", miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_2_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1331,13 +1497,13 @@ pub mod test {
             node.add_key_register(&mut burn_block, &mut miner_1);
             node.add_key_register(&mut burn_block, &mut miner_2);
             
-            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block in stacks fork 1 via {}", miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_1_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt.as_ref());
                 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1346,13 +1512,13 @@ pub mod test {
                 (stacks_block, microblocks)
             });
             
-            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block in stacks fork 2 via {}", miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_2_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1469,13 +1635,13 @@ pub mod test {
             node.add_key_register(&mut burn_block, &mut miner_1);
             node_2.add_key_register(&mut burn_block, &mut miner_2);
             
-            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt_1.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt_1.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Miner {}: Produce anchored stacks block in stacks fork 1 via {}", miner.id, miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_1_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt_1.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1484,13 +1650,13 @@ pub mod test {
                 (stacks_block, microblocks)
             });
             
-            let (stacks_block_2, microblocks_2, block_commit_op_2) = node_2.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt_2.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_2, microblocks_2, block_commit_op_2) = node_2.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt_2.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Miner {}: Produce anchored stacks block in stacks fork 2 via {}", miner.id, miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name_2);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_2_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt_2.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1631,13 +1797,13 @@ pub mod test {
             node.add_key_register(&mut burn_block, &mut miner_1);
             node.add_key_register(&mut burn_block, &mut miner_2);
 
-            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block from miner 1");
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_1_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1646,13 +1812,13 @@ pub mod test {
                 (stacks_block, microblocks)
             });
             
-            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block from miner 2");
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_2_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1748,13 +1914,13 @@ pub mod test {
             let last_microblock_header_opt_1 = get_last_microblock_header(&node, &miner_1, parent_block_opt_1.as_ref());
             let last_microblock_header_opt_2 = get_last_microblock_header(&node, &miner_2, parent_block_opt_2.as_ref());
 
-            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block_1, &last_key_1, parent_block_opt_1.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block_1, &last_key_1, parent_block_opt_1.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block in stacks fork 1 via {}", miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_1_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt_1.as_ref());
                 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1763,13 +1929,13 @@ pub mod test {
                 (stacks_block, microblocks)
             });
             
-            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block_2, &last_key_2, parent_block_opt_2.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block_2, &last_key_2, parent_block_opt_2.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block in stacks fork 2 via {}", miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_2_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt_2.as_ref());
                 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1910,13 +2076,13 @@ pub mod test {
             node.add_key_register(&mut burn_block, &mut miner_1);
             node.add_key_register(&mut burn_block, &mut miner_2);
 
-            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt_1.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block, &last_key_1, parent_block_opt_1.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block");
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_1_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt_1.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -1925,13 +2091,13 @@ pub mod test {
                 (stacks_block, microblocks)
             });
             
-            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt_2.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block, &last_key_2, parent_block_opt_2.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block");
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_2_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt_2.as_ref());
 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -2033,13 +2199,13 @@ pub mod test {
             let last_microblock_header_opt_1 = get_last_microblock_header(&node, &miner_1, parent_block_opt_1.as_ref());
             let last_microblock_header_opt_2 = get_last_microblock_header(&node, &miner_2, parent_block_opt_2.as_ref());
 
-            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block_1, &last_key_1, parent_block_opt_1.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_1, microblocks_1, block_commit_op_1) = node.mine_stacks_block(&mut burn_node, &mut miner_1, &mut burn_block_1, &last_key_1, parent_block_opt_1.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block in stacks fork 1 via {}", miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_1_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt_1.as_ref());
                 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -2048,13 +2214,13 @@ pub mod test {
                 (stacks_block, microblocks)
             });
             
-            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block_2, &last_key_2, parent_block_opt_2.as_ref(), 1000, |mut builder, ref mut miner| {
+            let (stacks_block_2, microblocks_2, block_commit_op_2) = node.mine_stacks_block(&mut burn_node, &mut miner_2, &mut burn_block_2, &last_key_2, parent_block_opt_2.as_ref(), 1000, |mut builder, ref mut miner, burn_tx, burn_tip| {
                 test_debug!("Produce anchored stacks block in stacks fork 2 via {}", miner.origin_address().unwrap().to_string());
 
                 let mut miner_chainstate = open_chainstate(false, 0x80000000, &full_test_name);
                 let all_prev_mining_rewards = get_all_mining_rewards(&mut miner_chainstate, &builder.chain_tip, builder.chain_tip.block_height);
 
-                let mut epoch = builder.epoch_begin(&mut miner_chainstate).unwrap();
+                let mut epoch = builder.epoch_begin(&mut miner_chainstate, burn_tx, burn_tip).unwrap();
                 let (stacks_block, microblocks) = miner_2_block_builder(&mut epoch, &mut builder, miner, i, last_microblock_header_opt_2.as_ref());
                 
                 assert!(check_mining_reward(&mut epoch, miner, builder.chain_tip.block_height, &all_prev_mining_rewards));
@@ -2420,6 +2586,37 @@ pub mod test {
         (stacks_block, vec![])
     }
     
+    /// mine a smart contract and a contract-call to it from the mempool, given out of nonce order
+    /// and with a later contract-call that has a nonce gap
+    fn mine_smart_contract_contract_call_packed_block<'a>(clarity_tx: &mut ClarityTx<'a>, builder: &mut StacksBlockBuilder, miner: &mut TestMiner, burnchain_height: usize, parent_microblock_header: Option<&StacksMicroblockHeader>) -> (StacksBlock, Vec<StacksMicroblock>) {
+        let miner_account = StacksChainState::get_account(clarity_tx, &miner.origin_address().unwrap().to_account_principal());
+        miner.set_nonce(miner_account.nonce);
+
+        // make a coinbase for this miner
+        let tx_coinbase_signed = mine_coinbase(clarity_tx, builder, miner, burnchain_height);
+        builder.try_mine_tx(clarity_tx, &tx_coinbase_signed).unwrap();
+
+        let tx_contract_signed = make_smart_contract(clarity_tx, builder, miner, burnchain_height);
+        let tx_contract_call_signed = make_contract_call(clarity_tx, builder, miner, burnchain_height, 6, 2);
+
+        // skip a nonce
+        let next_nonce = miner.get_nonce();
+        miner.set_nonce(next_nonce + 1);
+        let tx_contract_call_gap_signed = make_contract_call(clarity_tx, builder, miner, burnchain_height, 8, 2);
+
+        // the coinbase's nonce is already used, so it gets dropped
+        let candidates = vec![tx_contract_call_gap_signed.clone(), tx_contract_call_signed.clone(), tx_coinbase_signed.clone(), tx_contract_signed.clone()];
+        let (mined, requeued) = builder.mine_txs_by_fee_rate(clarity_tx, candidates);
+
+        assert_eq!(mined, vec![tx_contract_signed, tx_contract_call_signed]);
+        assert_eq!(requeued, vec![tx_contract_call_gap_signed]);
+
+        let stacks_block = builder.mine_anchored_block(clarity_tx);
+
+        test_debug!("Produce anchored stacks block {} with packed smart contract and contract call at burnchain height {} stacks height {}", stacks_block.block_hash(), burnchain_height, stacks_block.header.total_work.work);
+        (stacks_block, vec![])
+    }
+    
     /// mine a smart contract in an anchored block, and mine some contract-calls to it in a microblock tail
     fn mine_smart_contract_block_contract_call_microblock<'a>(clarity_tx: &mut ClarityTx<'a>, builder: &mut StacksBlockBuilder, miner: &mut TestMiner, burnchain_height: usize, parent_microblock_header: Option<&StacksMicroblockHeader>) -> (StacksBlock, Vec<StacksMicroblock>) {
         if burnchain_height > 0 && builder.chain_tip.anchored_header.total_work.work > 0 {
@@ -2521,6 +2718,39 @@ pub mod test {
         (stacks_block, microblocks)
     }
 
+    /// try to mine a smart contract in an anchored block that has no execution budget left for it.
+    /// The miner must leave it out, and the block must still be valid.
+    fn mine_smart_contract_block_over_budget<'a>(clarity_tx: &mut ClarityTx<'a>, builder: &mut StacksBlockBuilder, miner: &mut TestMiner, burnchain_height: usize, parent_microblock_header: Option<&StacksMicroblockHeader>) -> (StacksBlock, Vec<StacksMicroblock>) {
+        let miner_account = StacksChainState::get_account(clarity_tx, &miner.origin_address().unwrap().to_account_principal());
+        miner.set_nonce(miner_account.nonce);
+
+        // make a coinbase for this miner
+        let tx_coinbase_signed = mine_coinbase(clarity_tx, builder, miner, burnchain_height);
+        builder.try_mine_tx(clarity_tx, &tx_coinbase_signed).unwrap();
+
+        let root_before = clarity_tx.get_root_hash();
+
+        // no room for the contract
+        clarity_tx.reset_cost(ExecutionCost::runtime(1));
+        let tx_contract_signed = make_smart_contract(clarity_tx, builder, miner, burnchain_height);
+        match builder.try_mine_tx(clarity_tx, &tx_contract_signed) {
+            Err(::chainstate::stacks::Error::BlockCostExceeded) => {},
+            x => panic!("Mined a transaction over the block execution budget: {:?}", &x)
+        }
+
+        // nothing it did was kept
+        assert_eq!(clarity_tx.cost_so_far(), ExecutionCost::zero());
+        assert_eq!(clarity_tx.get_root_hash(), root_before);
+        assert_eq!(builder.txs.len(), 1);
+
+        clarity_tx.reset_cost(ANCHORED_BLOCK_COST_LIMIT);
+
+        let stacks_block = builder.mine_anchored_block(clarity_tx);
+
+        test_debug!("Produce anchored stacks block {} without an over-budget smart contract at burnchain height {} stacks height {}", stacks_block.block_hash(), burnchain_height, stacks_block.header.total_work.work);
+        (stacks_block, vec![])
+    }
+
     /// make a token transfer
     pub fn make_token_transfer<'a>(clarity_tx: &mut ClarityTx<'a>, builder: &mut StacksBlockBuilder, miner: &mut TestMiner, burnchain_height: usize, nonce: Option<u64>, recipient: &StacksAddress, amount: u64, memo: &TokenTransferMemo) -> StacksTransaction {
         let addr = miner.origin_address().unwrap();
@@ -2629,6 +2859,11 @@ pub mod test {
     }
     */
 
+    #[test]
+    fn mine_anchored_smart_contract_block_over_budget_single() {
+        mine_stacks_blocks_1_fork_1_miner_1_burnchain(&"smart-contract-block-over-budget".to_string(), 10, mine_smart_contract_block_over_budget, |_, _| true);
+    }
+
     #[test]
     fn mine_anchored_empty_blocks_single() {
         mine_stacks_blocks_1_fork_1_miner_1_burnchain(&"empty-anchored-blocks".to_string(), 10, mine_empty_anchored_block, |_, _| true);
@@ -2849,6 +3084,11 @@ pub mod test {
         miner_trace_replay_randomized(&mut miner_trace);
     }
 
+    #[test]
+    fn mine_anchored_smart_contract_contract_call_packed_blocks_single() {
+        mine_stacks_blocks_1_fork_1_miner_1_burnchain(&"smart-contract-contract-call-packed".to_string(), 10, mine_smart_contract_contract_call_packed_block, |_, _| true);
+    }
+
     #[test]
     fn mine_anchored_invalid_token_transfer_blocks_single() {
         let miner_trace = mine_stacks_blocks_1_fork_1_miner_1_burnchain(&"invalid-token-transfers".to_string(), 10, mine_invalid_token_transfers_block, |_, _| false);
@@ -2864,6 +3104,58 @@ pub mod test {
         }
     } 
 
+    #[test]
+    fn mine_txs_by_fee_rate_multiple_origins() {
+        let path = chainstate_path("mine-txs-by-fee-rate-multiple-origins");
+        match fs::metadata(&path) {
+            Ok(_) => {
+                fs::remove_dir_all(&path).unwrap();
+            },
+            Err(_) => {}
+        };
+
+        let privks : Vec<StacksPrivateKey> = (0..3).map(|_| StacksPrivateKey::new()).collect();
+        let addrs : Vec<StacksAddress> = privks.iter()
+            .map(|privk| StacksAddress::from_public_keys(C32_ADDRESS_VERSION_TESTNET_SINGLESIG, &AddressHashMode::SerializeP2PKH, 1, &vec![StacksPublicKey::from_private(privk)]).unwrap())
+            .collect();
+
+        let initial_balances = addrs.iter().map(|addr| (addr.to_account_principal(), 1000000000)).collect();
+        let mut chainstate = StacksChainState::open_testnet(0x80000000, &path, Some(initial_balances), |_| {}).unwrap();
+
+        let recipient = StacksAddress::new(C32_ADDRESS_VERSION_TESTNET_SINGLESIG, Hash160([0xff; 20]));
+        let make_transfer = |privk: &StacksPrivateKey, nonce: u64, fee_rate: u64| {
+            let mut tx_stx_transfer = StacksTransaction::new(TransactionVersion::Testnet,
+                                                             TransactionAuth::from_p2pkh(privk).unwrap(),
+                                                             TransactionPayload::TokenTransfer(recipient.clone(), 1, TokenTransferMemo([0u8; 34])));
+            tx_stx_transfer.chain_id = 0x80000000;
+            tx_stx_transfer.auth.set_origin_nonce(nonce);
+            tx_stx_transfer.set_fee_rate(fee_rate);
+
+            let mut tx_signer = StacksTransactionSigner::new(&tx_stx_transfer);
+            tx_signer.sign_origin(privk).unwrap();
+            tx_signer.get_tx().unwrap()
+        };
+
+        // the first origin's cheap transaction holds up its expensive one, and the third origin's
+        // expensive transaction comes after a nonce gap
+        let tx_a0 = make_transfer(&privks[0], 0, 1);
+        let tx_a1 = make_transfer(&privks[0], 1, 5);
+        let tx_b0 = make_transfer(&privks[1], 0, 3);
+        let tx_c0 = make_transfer(&privks[2], 0, 2);
+        let tx_c2 = make_transfer(&privks[2], 2, 10);
+
+        let mut builder = StacksBlockBuilder::first(0, &FIRST_BURNCHAIN_BLOCK_HASH, 0, &VRFProof::empty(), &StacksPrivateKey::new());
+        let mut clarity_tx = chainstate.block_begin(&FIRST_BURNCHAIN_BLOCK_HASH, &FIRST_STACKS_BLOCK_HASH, &MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH);
+
+        let candidates = vec![tx_c2.clone(), tx_a1.clone(), tx_c0.clone(), tx_a0.clone(), tx_b0.clone()];
+        let (mined, requeued) = builder.mine_txs_by_fee_rate(&mut clarity_tx, candidates);
+
+        assert_eq!(mined, vec![tx_b0, tx_c0, tx_a0, tx_a1]);
+        assert_eq!(requeued, vec![tx_c2]);
+
+        clarity_tx.rollback_block();
+    }
+
     // TODO: (BLOCKED) build off of different points in the same microblock stream
     // TODO; skipped blocks
     // TODO: missing blocks
@@ -53,10 +53,11 @@ use burnchains::BurnchainHeaderHash;
 use chainstate::burn::BlockHeaderHash;
 use chainstate::burn::operations::LeaderBlockCommitOp;
 
-use chainstate::stacks::index::{TrieHash, TRIEHASH_ENCODED_SIZE};
+use chainstate::stacks::index::{TrieHash, TRIEHASH_ENCODED_SIZE, MARFValue, MARF_VALUE_ENCODED_SIZE};
 use chainstate::stacks::index::Error as marf_error;
 use chainstate::stacks::db::StacksHeaderInfo;
 use chainstate::stacks::db::accounts::MinerReward;
+use chainstate::stacks::db::blocks::BurnchainStxOp;
 
 use net::{StacksMessageCodec, MAX_MESSAGE_LEN};
 use net::codec::{read_next, write_next};
@@ -71,11 +72,13 @@ use vm::types::{
 
 use vm::representations::{ContractName, ClarityName};
 use vm::clarity::Error as clarity_error;
+use vm::costs::ExecutionCost;
 
 pub type StacksPublicKey = secp256k1::Secp256k1PublicKey;
 pub type StacksPrivateKey = secp256k1::Secp256k1PrivateKey;
 
 impl_byte_array_message_codec!(TrieHash, TRIEHASH_ENCODED_SIZE as u32);
+impl_byte_array_message_codec!(MARFValue, MARF_VALUE_ENCODED_SIZE);
 impl_byte_array_message_codec!(Sha512Trunc256Sum, 32);
 
 pub const C32_ADDRESS_VERSION_MAINNET_SINGLESIG: u8 = 22;       // P
@@ -125,8 +128,11 @@ pub enum Error {
     InvalidStacksTransaction(String),
     PostConditionFailed(String),
     NoSuchBlockError,
+    PrunedBlockError,
+    ArchiveModeError,
     InvalidChainstateDB,
     BlockTooBigError,
+    BlockCostExceeded,
     MicroblockStreamTooLongError,
     IncompatibleSpendingConditionError,
     ClarityError(clarity_error),
@@ -146,8 +152,11 @@ impl fmt::Display for Error {
             Error::InvalidStacksTransaction(ref s) => fmt::Display::fmt(s, f),
             Error::PostConditionFailed(ref s) => fmt::Display::fmt(s, f),
             Error::NoSuchBlockError => write!(f, "No such Stacks block"),
+            Error::PrunedBlockError => write!(f, "Stacks block state has been pruned"),
+            Error::ArchiveModeError => write!(f, "Operation not permitted in archive mode"),
             Error::InvalidChainstateDB => write!(f, "Invalid chainstate database"),
             Error::BlockTooBigError => write!(f, "Too much data in block"),
+            Error::BlockCostExceeded => write!(f, "Block execution budget exceeded"),
             Error::MicroblockStreamTooLongError => write!(f, "Too many microblocks in stream"),
             Error::IncompatibleSpendingConditionError => write!(f, "Spending condition is incompatible with this operation"),
             Error::ClarityError(ref e) => fmt::Display::fmt(e, f),
@@ -169,8 +178,11 @@ impl error::Error for Error {
             Error::InvalidStacksTransaction(ref _s) => None,
             Error::PostConditionFailed(ref _s) => None,
             Error::NoSuchBlockError => None,
+            Error::PrunedBlockError => None,
+            Error::ArchiveModeError => None,
             Error::InvalidChainstateDB => None,
             Error::BlockTooBigError => None,
+            Error::BlockCostExceeded => None,
             Error::MicroblockStreamTooLongError => None,
             Error::IncompatibleSpendingConditionError => None,
             Error::ClarityError(ref e) => Some(e),
@@ -198,7 +210,7 @@ impl Txid {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy, Serialize, Deserialize)]
 pub struct StacksAddress {
     pub version: u8,
     pub bytes: Hash160
@@ -686,6 +698,7 @@ pub struct StacksBlockBuilder {
     prev_microblock_header: StacksMicroblockHeader,
     miner_privkey: StacksPrivateKey,
     miner_payouts: Option<Vec<MinerReward>>,
+    burnchain_stx_ops: Vec<BurnchainStxOp>,
     miner_id: usize
 }
 
@@ -696,6 +709,25 @@ pub const MAX_EPOCH_SIZE : u32 = 2 * 1024 * 1024;
 // $MAX_EPOCH_SIZE bytes (so the average microblock size needs to be 4kb if there are 256 of them)
 pub const MAX_MICROBLOCK_SIZE : u32 = 65536;
 
+// maximum total execution cost of the transactions in an anchored block
+pub const ANCHORED_BLOCK_COST_LIMIT : ExecutionCost = ExecutionCost {
+    write_length: 15_000_000,
+    write_count: 7_750,
+    read_length: 100_000_000,
+    read_count: 7_750,
+    runtime: 5_000_000_000
+};
+
+// maximum total execution cost of the transactions in a leader's microblock stream.  This is
+// counted separately from the cost of the anchored block that confirms the stream.
+pub const MICROBLOCK_STREAM_COST_LIMIT : ExecutionCost = ExecutionCost {
+    write_length: 15_000_000,
+    write_count: 7_750,
+    read_length: 100_000_000,
+    read_count: 7_750,
+    runtime: 5_000_000_000
+};
+
 #[cfg(test)]
 pub mod test {
     use super::*;
@@ -369,12 +369,24 @@ impl StacksMessageCodec for TransactionPostCondition {
 
 impl StacksTransaction {
     pub fn consensus_deserialize_with_len<R: Read>(fd: &mut R) -> Result<(StacksTransaction, u64), net_error> {
+        StacksTransaction::deserialize_inner(fd, false)
+    }
+
+    /// Decode a transaction whose multisig spending conditions may not have all of their
+    /// signatures yet, so co-signers can pass it between one another.  Such a transaction is not
+    /// valid -- never use this to decode transactions from the network.
+    pub fn deserialize_partially_signed<R: Read>(fd: &mut R) -> Result<StacksTransaction, net_error> {
+        StacksTransaction::deserialize_inner(fd, true)
+            .map(|(result, _)| result)
+    }
+
+    fn deserialize_inner<R: Read>(fd: &mut R, partially_signed: bool) -> Result<(StacksTransaction, u64), net_error> {
         let mut bound_read = BoundReader::from_reader(fd, MAX_TRANSACTION_LEN.into());
         let fd = &mut bound_read;
 
         let version_u8 : u8             = read_next(fd)?;
         let chain_id : u32              = read_next(fd)?;
-        let auth : TransactionAuth      = read_next(fd)?;
+        let auth                        = TransactionAuth::deserialize_inner(fd, partially_signed)?;
         let anchor_mode_u8 : u8         = read_next(fd)?;
         let post_condition_mode_u8 : u8 = read_next(fd)?;
         let post_conditions : Vec<TransactionPostCondition> = read_next(fd)?;
@@ -756,6 +768,26 @@ impl StacksTransactionSigner {
         })
     }
 
+    /// Resume signing a transaction whose origin is a partially-signed multisig spending
+    /// condition (e.g. one decoded with StacksTransaction::deserialize_partially_signed()).
+    pub fn new_partially_signed(tx: &StacksTransaction) -> Result<StacksTransactionSigner, net_error> {
+        let initial_sighash = tx.sign_begin();
+        let sighash = match tx.auth.origin() {
+            TransactionSpendingCondition::Multisig(ref data) => data.next_sighash(&initial_sighash, &TransactionAuthFlags::AuthStandard)?,
+            _ => {
+                return Err(net_error::SigningError("Origin is not a multisig spending condition".to_string()));
+            }
+        };
+
+        Ok(StacksTransactionSigner {
+            tx: tx.clone(),
+            sighash: sighash,
+            origin_done: false,
+            check_oversign: true,
+            check_overlap: true
+        })
+    }
+
     pub fn resume(&mut self, tx: &StacksTransaction) -> () {
         self.tx = tx.clone()
     }
@@ -765,6 +797,13 @@ impl StacksTransactionSigner {
         self.check_overlap = false;
     }
 
+    /// Get the hash that the next origin private key will sign.  Offline signers use this to
+    /// confirm what they are about to sign.
+    pub fn origin_sighash_presign(&self) -> Txid {
+        let origin = self.tx.auth.origin();
+        TransactionSpendingCondition::make_sighash_presign(&self.sighash, &TransactionAuthFlags::AuthStandard, origin.fee_rate(), origin.nonce())
+    }
+
     pub fn sign_origin(&mut self, privk: &StacksPrivateKey) -> Result<(), net_error> {
         if self.check_overlap && self.origin_done {
             // can't sign another origin private key since we started signing sponsors
@@ -2450,6 +2489,64 @@ mod test {
         }
     } 
     
+    #[test]
+    fn tx_stacks_transaction_sign_verify_standard_p2sh_partially_signed() {
+        let privk_1 = StacksPrivateKey::from_hex("6d430bb91222408e7706c9001cfaeb91b08c2be6d5ac95779ab52c6b431950e001").unwrap();
+        let privk_2 = StacksPrivateKey::from_hex("2a584d899fed1d24e26b524f202763c8ab30260167429f157f1c119f550fa6af01").unwrap();
+        let privk_3 = StacksPrivateKey::from_hex("d5200dee706ee53ae98a03fba6cf4fdcc5084c30cfa9e1b3462dcdeaa3e0f1d201").unwrap();
+
+        let pubk_1 = StacksPublicKey::from_private(&privk_1);
+        let pubk_2 = StacksPublicKey::from_private(&privk_2);
+        let pubk_3 = StacksPublicKey::from_private(&privk_3);
+
+        let origin_auth = TransactionAuth::Standard(TransactionSpendingCondition::new_multisig_p2sh(2, vec![pubk_1.clone(), pubk_2.clone(), pubk_3.clone()]).unwrap());
+
+        let txs = tx_stacks_transaction_test_txs(&origin_auth);
+
+        for tx in txs {
+            // each co-signer decodes the partially-signed transaction, adds its field, and
+            // re-encodes it
+            let mut tx_bytes = vec![];
+            tx.consensus_serialize(&mut tx_bytes).unwrap();
+
+            assert!(StacksTransaction::consensus_deserialize(&mut &tx_bytes[..]).is_err());
+            let partial_tx = StacksTransaction::deserialize_partially_signed(&mut &tx_bytes[..]).unwrap();
+            assert_eq!(partial_tx, tx);
+
+            let mut tx_signer = StacksTransactionSigner::new_partially_signed(&partial_tx).unwrap();
+            tx_signer.sign_origin(&privk_1).unwrap();
+            assert!(tx_signer.get_tx().is_none());
+
+            let mut tx_bytes = vec![];
+            tx_signer.get_tx_incomplete().consensus_serialize(&mut tx_bytes).unwrap();
+
+            assert!(StacksTransaction::consensus_deserialize(&mut &tx_bytes[..]).is_err());
+            let partial_tx = StacksTransaction::deserialize_partially_signed(&mut &tx_bytes[..]).unwrap();
+
+            let mut tx_signer = StacksTransactionSigner::new_partially_signed(&partial_tx).unwrap();
+            tx_signer.sign_origin(&privk_2).unwrap();
+            tx_signer.append_origin(&pubk_3).unwrap();
+            let signed_tx = tx_signer.get_tx().unwrap();
+
+            // same as signing it all at once
+            let mut tx_signer = StacksTransactionSigner::new(&tx);
+            tx_signer.sign_origin(&privk_1).unwrap();
+            tx_signer.sign_origin(&privk_2).unwrap();
+            tx_signer.append_origin(&pubk_3).unwrap();
+            assert_eq!(signed_tx, tx_signer.get_tx().unwrap());
+
+            let mut tx_bytes = vec![];
+            signed_tx.consensus_serialize(&mut tx_bytes).unwrap();
+            let decoded_tx = StacksTransaction::consensus_deserialize(&mut &tx_bytes[..]).unwrap();
+            assert_eq!(decoded_tx, signed_tx);
+            decoded_tx.verify().unwrap();
+
+            // can't sign past the threshold
+            let mut tx_signer = StacksTransactionSigner::new_partially_signed(&signed_tx).unwrap();
+            assert!(tx_signer.sign_origin(&privk_3).is_err());
+        }
+    }
+
     #[test]
     fn tx_stacks_transaction_sign_verify_sponsored_p2sh() {
         let origin_privk = StacksPrivateKey::from_hex("807bbe9e471ac976592cc35e3056592ecc0f778ee653fced3b491a122dd8d59701").unwrap();
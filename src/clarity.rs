@@ -10,7 +10,7 @@ use std::path::PathBuf;
 use util::log;
 
 use chainstate::burn::BlockHeaderHash;
-use chainstate::stacks::index::storage::{TrieFileStorage};
+use chainstate::stacks::index::storage::{TrieFileStorage, TrieStorageLayout};
 
 use rusqlite::{Connection, OpenFlags, NO_PARAMS};
 use rusqlite::types::ToSql;
@@ -212,7 +212,7 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) {
                 panic_test!();
             }
 
-            let marf_kv = friendly_expect(MarfedKV::open(&args[1], None), "Failed to open VM database.");
+            let marf_kv = friendly_expect(MarfedKV::open(&args[1], None, TrieStorageLayout::FilePerBlock), "Failed to open VM database.");
             in_block(&args[1], marf_kv, |mut kv| {
                 { let mut db = kv.as_clarity_db(&NULL_HEADER_DB);
                   db.initialize() };
@@ -243,7 +243,7 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) {
             let contract_analysis = {
                 if args.len() >= 3 {
                     // use a persisted marf
-                    let marf_kv = friendly_expect(MarfedKV::open(&args[2], None), "Failed to open VM database.");
+                    let marf_kv = friendly_expect(MarfedKV::open(&args[2], None, TrieStorageLayout::FilePerBlock), "Failed to open VM database.");
                     let result = at_chaintip(
                         &args[2],
                         marf_kv,
@@ -394,7 +394,7 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) {
 
             let contract_identifier = friendly_expect(QualifiedContractIdentifier::parse(&args[1]), "Failed to parse contract identifier.");
 
-            let marf_kv = friendly_expect(MarfedKV::open(vm_filename, None), "Failed to open VM database.");
+            let marf_kv = friendly_expect(MarfedKV::open(vm_filename, None, TrieStorageLayout::FilePerBlock), "Failed to open VM database.");
             let result = in_block(vm_filename, marf_kv, |mut marf| {
                 let result = {
                     let db = marf.as_clarity_db(&NULL_HEADER_DB);
@@ -428,7 +428,7 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) {
                                                            &format!("Error reading file: {}", args[2]));
 
             let mut ast = friendly_expect(parse(&contract_identifier, &contract_content), "Failed to parse program.");
-            let marf_kv = friendly_expect(MarfedKV::open(vm_filename, None), "Failed to open VM database.");
+            let marf_kv = friendly_expect(MarfedKV::open(vm_filename, None, TrieStorageLayout::FilePerBlock), "Failed to open VM database.");
             let result = in_block(
                 vm_filename,
                 marf_kv,
@@ -479,7 +479,7 @@ pub fn invoke_command(invoked_by: &str, args: &[String]) {
                 panic_test!();
             }
             let vm_filename = &args[1];
-            let marf_kv = friendly_expect(MarfedKV::open(vm_filename, None), "Failed to open VM database.");
+            let marf_kv = friendly_expect(MarfedKV::open(vm_filename, None, TrieStorageLayout::FilePerBlock), "Failed to open VM database.");
 
             let contract_identifier = friendly_expect(QualifiedContractIdentifier::parse(&args[2]), "Failed to parse contract identifier.");
 
@@ -104,7 +104,7 @@ mod tests {
         run_loop.apply_on_new_tenures(|round, tenure| {
             let contract_sk = StacksPrivateKey::from_hex(SK_1).unwrap();
             if round == 0 { // block-height = 2
-                let publish_tx = make_contract_publish(&contract_sk, 0, 100, "foo_contract", FOO_CONTRACT);
+                let publish_tx = make_contract_publish(&contract_sk, 0, 1, "foo_contract", FOO_CONTRACT);
                 eprintln!("Tenure in 1 started!");
                 tenure.mem_pool.submit(publish_tx);
             }
@@ -127,13 +127,13 @@ mod tests {
 
                 // let's throw some transactions at it.
                 // first a couple valid ones:
-                let tx = make_contract_publish(&contract_sk, 1, 1000, "bar_contract", FOO_CONTRACT);
+                let tx = make_contract_publish(&contract_sk, 1, 1, "bar_contract", FOO_CONTRACT);
                 chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap();
 
-                let tx = make_contract_call(&contract_sk, 1, 200, &contract_addr, "foo_contract", "bar", &[Value::UInt(1)]);
+                let tx = make_contract_call(&contract_sk, 1, 1, &contract_addr, "foo_contract", "bar", &[Value::UInt(1)]);
                 chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap();
 
-                let tx = make_stacks_transfer(&contract_sk, 1, 200, &other_addr, 1000);
+                let tx = make_stacks_transfer(&contract_sk, 1, 1, &other_addr, 1000);
                 chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap();
 
                 // now invalid ones.
@@ -141,7 +141,7 @@ mod tests {
                 assert!(if let MemPoolRejection::DeserializationFailure(_) = e { true } else { false });
 
                 // bad signature
-                let tx = make_bad_stacks_transfer(&contract_sk, 1, 200, &other_addr, 1000);
+                let tx = make_bad_stacks_transfer(&contract_sk, 1, 1, &other_addr, 1000);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err();
                 eprintln!("Err: {:?}", e);
                 assert!(if let
@@ -154,7 +154,7 @@ mod tests {
                         88, &AddressHashMode::SerializeP2PKH, 1, &vec![StacksPublicKey::from_private(&other_sk)])
                     .unwrap();
 
-                let tx = make_contract_call(&contract_sk, 1, 200, &bad_addr, "foo_contract", "bar", &[Value::UInt(1), Value::Int(2)]);
+                let tx = make_contract_call(&contract_sk, 1, 1, &bad_addr, "foo_contract", "bar", &[Value::UInt(1), Value::Int(2)]);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err();
 
                 assert!(if let MemPoolRejection::BadAddressVersionByte = e { true } else { false });
@@ -165,7 +165,7 @@ mod tests {
                         C32_ADDRESS_VERSION_MAINNET_SINGLESIG, &AddressHashMode::SerializeP2PKH, 1, &vec![StacksPublicKey::from_private(&other_sk)])
                     .unwrap();
 
-                let tx = make_stacks_transfer(&contract_sk, 1, 200, &bad_addr, 1000);
+                let tx = make_stacks_transfer(&contract_sk, 1, 1, &bad_addr, 1000);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err();
                 assert!(if let MemPoolRejection::BadAddressVersionByte = e { true } else { false });
 
@@ -176,38 +176,44 @@ mod tests {
                 assert!(if let MemPoolRejection::FeeTooLow(0, _) = e { true } else { false });
 
                 // bad nonce
-                let tx = make_stacks_transfer(&contract_sk, 0, 200, &other_addr, 1000);
+                let tx = make_stacks_transfer(&contract_sk, 0, 1, &other_addr, 1000);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
                 assert!(if let MemPoolRejection::BadNonces(_) = e { true } else { false });
 
                 // not enough funds
+                let balance = chainstate.with_read_only_clarity_tx(burn_hash, block_hash, |conn| {
+                    StacksChainState::get_account(conn, &contract_addr.to_account_principal()).stx_balance
+                });
+
                 let tx = make_stacks_transfer(&contract_sk, 1, 110000, &other_addr, 1000);
+                let min_fee = 110000 * (tx.len() as u128);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
-                assert!(if let MemPoolRejection::NotEnoughFunds(110000, 99900) = e { true } else { false });
+                assert!(if let MemPoolRejection::NotEnoughFunds(needed, available) = e { needed == min_fee && available == balance } else { false });
 
-                let tx = make_stacks_transfer(&contract_sk, 1, 99900, &other_addr, 1000);
+                let tx = make_stacks_transfer(&contract_sk, 1, 1, &other_addr, balance as u64);
+                let min_fee = tx.len() as u128;
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
-                assert!(if let MemPoolRejection::NotEnoughFunds(100900, 99900) = e { true } else { false });
+                assert!(if let MemPoolRejection::NotEnoughFunds(needed, available) = e { needed == balance + min_fee && available == balance } else { false });
 
-                let tx = make_contract_call(&contract_sk, 1, 200, &contract_addr, "bar_contract", "bar", &[Value::UInt(1)]);
+                let tx = make_contract_call(&contract_sk, 1, 1, &contract_addr, "bar_contract", "bar", &[Value::UInt(1)]);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
                 assert!(if let MemPoolRejection::NoSuchContract = e { true } else { false });
 
-                let tx = make_contract_call(&contract_sk, 1, 200, &contract_addr, "foo_contract", "foobar", &[Value::UInt(1)]);
+                let tx = make_contract_call(&contract_sk, 1, 1, &contract_addr, "foo_contract", "foobar", &[Value::UInt(1)]);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
                 assert!(if let MemPoolRejection::NoSuchPublicFunction = e { true } else { false });
 
-                let tx = make_contract_call(&contract_sk, 1, 200, &contract_addr, "foo_contract", "bar", &[Value::UInt(1), Value::Int(2)]);
+                let tx = make_contract_call(&contract_sk, 1, 1, &contract_addr, "foo_contract", "bar", &[Value::UInt(1), Value::Int(2)]);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
                 assert!(if let MemPoolRejection::BadFunctionArgument(_) = e { true } else { false });
 
-                let tx = make_contract_publish(&contract_sk, 1, 1000, "foo_contract", FOO_CONTRACT);
+                let tx = make_contract_publish(&contract_sk, 1, 1, "foo_contract", FOO_CONTRACT);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
                 assert!(if let MemPoolRejection::ContractAlreadyExists(_) = e { true } else { false });
@@ -228,7 +234,7 @@ mod tests {
                     signature: MessageSignature([0; 65])
                 };
 
-                let tx = make_poison(&contract_sk, 1, 1000, microblock_1, microblock_2);
+                let tx = make_poison(&contract_sk, 1, 1, microblock_1, microblock_2);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
                 assert!(if let MemPoolRejection::PoisonMicroblocksDoNotConflict = e { true } else { false });
@@ -249,7 +255,7 @@ mod tests {
                     signature: MessageSignature([0; 65])
                 };
 
-                let tx = make_poison(&contract_sk, 1, 1000, microblock_1, microblock_2);
+                let tx = make_poison(&contract_sk, 1, 1, microblock_1, microblock_2);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
                 assert!(if let MemPoolRejection::InvalidMicroblocks = e { true } else { false });
@@ -274,12 +280,12 @@ mod tests {
                 microblock_1.sign(&other_sk).unwrap();
                 microblock_2.sign(&other_sk).unwrap();
 
-                let tx = make_poison(&contract_sk, 1, 1000, microblock_1, microblock_2);
+                let tx = make_poison(&contract_sk, 1, 1, microblock_1, microblock_2);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
                 assert!(if let MemPoolRejection::NoAnchorBlockWithPubkeyHash(_) = e { true } else { false });
 
-                let tx = make_coinbase(&contract_sk, 1, 1000);
+                let tx = make_coinbase(&contract_sk, 1, 1);
                 let e = chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap_err(); 
                 eprintln!("Err: {:?}", e);
                 assert!(if let MemPoolRejection::NoCoinbaseViaMempool = e { true } else { false });
@@ -323,7 +329,7 @@ mod tests {
                 microblock_1.sign(&secret_key).unwrap();
                 microblock_2.sign(&secret_key).unwrap();
 
-                let tx = make_poison(&contract_sk, 1, 1000, microblock_1, microblock_2);
+                let tx = make_poison(&contract_sk, 1, 1, microblock_1, microblock_2);
                 chainstate.will_admit_mempool_tx(burn_hash, block_hash, &mut tx.as_slice()).unwrap(); 
             }
         });
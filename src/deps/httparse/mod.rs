@@ -1271,11 +1271,9 @@ mod tests {
         assert_eq!(parse_chunk_size(b"fffffffffffffffff\r\n"), Err(Error::ChunkSize));
     }
 
-    #[cfg(feature = "std")]
     #[test]
     fn test_std_error() {
         use super::Error;
-        use std::error::Error as StdError;
         let err = Error::HeaderName;
         assert_eq!(err.to_string(), err.description_str());
     }
@@ -167,6 +167,271 @@ fn main() {
         process::exit(0);
     }
 
+    if argv[1] == "sortitions" {
+        if argv.len() < 4 {
+            eprintln!("Usage: {} sortitions BURNDB_PATH START_BURN_HEIGHT [END_BURN_HEIGHT]", argv[0]);
+            process::exit(1);
+        }
+
+        use chainstate::burn::db::burndb::BurnDB;
+        use net::SortitionInfoData;
+
+        let burndb_path = &argv[2];
+        let start_height = argv[3].parse::<u64>().expect("Invalid start burn block height");
+        let end_height =
+            if argv.len() >= 5 {
+                argv[4].parse::<u64>().expect("Invalid end burn block height")
+            }
+            else {
+                start_height
+            };
+
+        if end_height < start_height {
+            eprintln!("End burn block height must be at least the start burn block height");
+            process::exit(1);
+        }
+
+        // read/write is needed to open a transaction to walk the fork index, but nothing is written
+        let mut burndb = BurnDB::open(burndb_path, true).map_err(|e| {
+            eprintln!("Failed to open burn database {}: {:?}", burndb_path, &e);
+            process::exit(1);
+        }).unwrap();
+
+        let mut sortitions = vec![];
+        for burn_height in start_height..(end_height + 1) {
+            match SortitionInfoData::from_db(&mut burndb, burn_height) {
+                Ok(Some(sortition_info)) => {
+                    sortitions.push(sortition_info);
+                },
+                Ok(None) => {
+                    break;
+                },
+                Err(e) => {
+                    eprintln!("Failed to load sortition at burn block height {}: {:?}", burn_height, &e);
+                    process::exit(1);
+                }
+            }
+        }
+
+        println!("{}", serde_json::to_string_pretty(&sortitions).unwrap());
+        process::exit(0);
+    }
+
+    if argv[1] == "migrate-marf" {
+        if argv.len() < 3 {
+            eprintln!("Usage: {} migrate-marf MARF_DIR", argv[0]);
+            process::exit(1);
+        }
+
+        use chainstate::stacks::index::storage::TrieFileStorage;
+
+        let marf_path = &argv[2];
+        let count = TrieFileStorage::migrate_to_packed(marf_path).map_err(|e| {
+            eprintln!("Failed to migrate {} to the packed layout: {:?}", marf_path, &e);
+            process::exit(1);
+        }).unwrap();
+
+        println!("Migrated {} tries in {}", count, marf_path);
+        process::exit(0);
+    }
+
+    if argv[1] == "export-snapshot" {
+        if argv.len() < 9 {
+            eprintln!("Usage: {} export-snapshot mainnet|testnet CHAIN_ID CHAINSTATE_DIR BURNDB_DIR TIP_BURN_HEADER_HASH TIP_BLOCK_HASH ARCHIVE_PATH", argv[0]);
+            process::exit(1);
+        }
+
+        use burnchains::BurnchainHeaderHash;
+        use chainstate::burn::BlockHeaderHash;
+        use chainstate::stacks::db::StacksChainState;
+
+        let mainnet = match argv[2].as_str() {
+            "mainnet" => true,
+            "testnet" => false,
+            _ => {
+                eprintln!("Network must be 'mainnet' or 'testnet'");
+                process::exit(1);
+            }
+        };
+
+        let chain_id =
+            if argv[3].starts_with("0x") {
+                u32::from_str_radix(&argv[3][2..], 16)
+            }
+            else {
+                argv[3].parse::<u32>()
+            }
+            .expect("Invalid chain ID");
+
+        let chainstate_path = &argv[4];
+        let burndb_path = &argv[5];
+        let tip_burn_header_hash = BurnchainHeaderHash::from_hex(&argv[6]).expect("Invalid tip burn header hash");
+        let tip_block_hash = BlockHeaderHash::from_hex(&argv[7]).expect("Invalid tip block hash");
+        let archive_path = &argv[8];
+
+        let manifest = StacksChainState::export_snapshot(mainnet, chain_id, chainstate_path, burndb_path, &tip_burn_header_hash, &tip_block_hash, archive_path).map_err(|e| {
+            eprintln!("Failed to export snapshot: {}", &e);
+            process::exit(1);
+        }).unwrap();
+
+        println!("Exported {} files at Stacks block height {} to {}", manifest.files.len(), manifest.tip_block_height, archive_path);
+        process::exit(0);
+    }
+
+    if argv[1] == "import-snapshot" {
+        if argv.len() < 5 {
+            eprintln!("Usage: {} import-snapshot ARCHIVE_PATH CHAINSTATE_DIR BURNDB_DIR", argv[0]);
+            process::exit(1);
+        }
+
+        use chainstate::stacks::db::StacksChainState;
+
+        let archive_path = &argv[2];
+        let chainstate_path = &argv[3];
+        let burndb_path = &argv[4];
+
+        let manifest = StacksChainState::import_snapshot(archive_path, chainstate_path, burndb_path).map_err(|e| {
+            eprintln!("Failed to import snapshot: {}", &e);
+            process::exit(1);
+        }).unwrap();
+
+        println!("Imported and verified snapshot of {}/{} at Stacks block height {}", &manifest.tip_burn_header_hash, &manifest.tip_block_hash, manifest.tip_block_height);
+        process::exit(0);
+    }
+
+    if argv[1] == "check-chainstate" {
+        if argv.len() < 6 {
+            eprintln!("Usage: {} check-chainstate mainnet|testnet CHAIN_ID CHAINSTATE_DIR BURNDB_DIR", argv[0]);
+            process::exit(1);
+        }
+
+        use chainstate::stacks::db::StacksChainState;
+
+        let mainnet = match argv[2].as_str() {
+            "mainnet" => true,
+            "testnet" => false,
+            _ => {
+                eprintln!("Network must be 'mainnet' or 'testnet'");
+                process::exit(1);
+            }
+        };
+
+        let chain_id =
+            if argv[3].starts_with("0x") {
+                u32::from_str_radix(&argv[3][2..], 16)
+            }
+            else {
+                argv[3].parse::<u32>()
+            }
+            .expect("Invalid chain ID");
+
+        let chainstate_path = &argv[4];
+        let burndb_path = &argv[5];
+
+        let issues = StacksChainState::check_chainstate(mainnet, chain_id, chainstate_path, burndb_path).map_err(|e| {
+            eprintln!("Failed to check chainstate: {:?}", &e);
+            process::exit(1);
+        }).unwrap();
+
+        if issues.len() > 0 {
+            for issue in issues.iter() {
+                println!("{}", issue);
+            }
+            eprintln!("Found {} inconsistencies", issues.len());
+            process::exit(1);
+        }
+
+        println!("No inconsistencies found");
+        process::exit(0);
+    }
+
+    if argv[1] == "history" {
+        let usage = format!("Usage: {} history mainnet|testnet CHAIN_ID CHAINSTATE_DIR INDEX_BLOCK_HASH [account PRINCIPAL | data-var CONTRACT_ID VAR_NAME | map-entry CONTRACT_ID MAP_NAME KEY_HEX | eval CONTRACT_ID PROGRAM_FILE]", argv[0]);
+        if argv.len() < 8 {
+            eprintln!("{}", &usage);
+            process::exit(1);
+        }
+
+        use chainstate::burn::BlockHeaderHash;
+        use chainstate::stacks::db::StacksChainState;
+        use net::{HistoricalAccountData, HistoricalValueData};
+        use vm::types::{PrincipalData, QualifiedContractIdentifier, Value};
+
+        let mainnet = match argv[2].as_str() {
+            "mainnet" => true,
+            "testnet" => false,
+            _ => {
+                eprintln!("Network must be 'mainnet' or 'testnet'");
+                process::exit(1);
+            }
+        };
+
+        let chain_id =
+            if argv[3].starts_with("0x") {
+                u32::from_str_radix(&argv[3][2..], 16)
+            }
+            else {
+                argv[3].parse::<u32>()
+            }
+            .expect("Invalid chain ID");
+
+        let chainstate_path = &argv[4];
+        let index_block_hash = BlockHeaderHash::from_hex(&argv[5]).expect("Invalid index block hash");
+
+        let mut chainstate = StacksChainState::open(mainnet, chain_id, chainstate_path).map_err(|e| {
+            eprintln!("Failed to open chainstate: {:?}", &e);
+            process::exit(1);
+        }).unwrap();
+
+        let parse_contract_id = |contract_id: &str| QualifiedContractIdentifier::parse(contract_id).expect("Invalid contract ID");
+
+        let result = match (argv[6].as_str(), argv.len()) {
+            ("account", 8) => {
+                let principal =
+                    if argv[7].contains('.') {
+                        PrincipalData::parse_qualified_contract_principal(&argv[7])
+                    }
+                    else {
+                        PrincipalData::parse_standard_principal(&argv[7]).map(PrincipalData::Standard)
+                    }
+                    .expect("Invalid principal");
+
+                chainstate.get_account_at(&index_block_hash, &principal)
+                    .map(|account| serde_json::to_string_pretty(&HistoricalAccountData::from_account(&index_block_hash, &account)).unwrap())
+            },
+            ("data-var", 9) => {
+                chainstate.get_data_var_at(&index_block_hash, &parse_contract_id(&argv[7]), &argv[8])
+                    .map(|value| serde_json::to_string_pretty(&HistoricalValueData::from_value(&index_block_hash, &value)).unwrap())
+            },
+            ("map-entry", 10) => {
+                let key = Value::try_deserialize_hex_untyped(&argv[9]).expect("Invalid map key");
+                chainstate.get_map_entry_at(&index_block_hash, &parse_contract_id(&argv[7]), &argv[8], &key)
+                    .map(|value| serde_json::to_string_pretty(&HistoricalValueData::from_value(&index_block_hash, &value)).unwrap())
+            },
+            ("eval", 9) => {
+                let program = fs::read_to_string(&argv[8])
+                    .expect(&format!("Error reading file: {}", argv[8]));
+                chainstate.eval_read_only_at(&index_block_hash, &parse_contract_id(&argv[7]), &program)
+                    .map(|value| serde_json::to_string_pretty(&HistoricalValueData::from_value(&index_block_hash, &value)).unwrap())
+            },
+            _ => {
+                eprintln!("{}", &usage);
+                process::exit(1);
+            }
+        };
+
+        match result {
+            Ok(json) => {
+                println!("{}", json);
+                process::exit(0);
+            },
+            Err(e) => {
+                eprintln!("Failed to query state as of {}: {:?}", &index_block_hash.to_hex(), &e);
+                process::exit(1);
+            }
+        }
+    }
+
     if argv[1] == "exec_program" {
         if argv.len() < 3 {
             eprintln!("Usage: {} exec_program [program-file.clar]", argv[0]);
@@ -22,6 +22,7 @@ use std::str;
 use std::fmt;
 use std::net::SocketAddr;
 use std::str::FromStr;
+use std::convert::TryFrom;
 use std::io::prelude::*;
 use std::io;
 use std::io::{Read, Write};
@@ -52,6 +53,7 @@ use net::NeighborAddress;
 use net::HTTP_PREAMBLE_MAX_ENCODED_SIZE;
 use net::MAX_MESSAGE_LEN;
 use net::MAX_MICROBLOCKS_UNCONFIRMED;
+use net::MAX_SORTITIONS_PER_RANGE;
 use net::HTTP_REQUEST_ID_RESERVED;
 
 use chainstate::burn::BlockHeaderHash;
@@ -61,12 +63,16 @@ use chainstate::stacks::StacksBlock;
 use chainstate::stacks::StacksMicroblock;
 use chainstate::stacks::StacksPublicKey;
 
+use vm::types::{PrincipalData, QualifiedContractIdentifier, Value};
+use vm::representations::ClarityName;
+use vm::database::ClaritySerializable;
+
 use util::log;
 use util::hash::hex_bytes;
 use util::retry::RetryReader;
 use util::retry::BoundReader;
 
-use regex::Regex;
+use regex::{Regex, Captures};
 
 use deps::httparse;
 use time;
@@ -1106,13 +1112,21 @@ impl HttpRequestType {
 
     pub fn parse<R: Read>(protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, fd: &mut R) -> Result<HttpRequestType, net_error> {
         // TODO: make this static somehow
-        let REQUEST_METHODS : [(&str, &Regex, &dyn Fn(&mut StacksHttp, &HttpRequestPreamble, &Regex, &mut R) -> Result<HttpRequestType, net_error>); 6] = [
+        let REQUEST_METHODS : [(&str, &Regex, &dyn Fn(&mut StacksHttp, &HttpRequestPreamble, &Regex, &mut R) -> Result<HttpRequestType, net_error>); 14] = [
             ("GET", &PATH_GETINFO, &HttpRequestType::parse_getinfo),
             ("GET", &PATH_GETNEIGHBORS, &HttpRequestType::parse_getneighbors),
             ("GET", &PATH_GETBLOCK, &HttpRequestType::parse_getblock),
             ("GET", &PATH_GETMICROBLOCKS, &HttpRequestType::parse_getmicroblocks),
             ("GET", &PATH_GETMICROBLOCKS_UNCONFIRMED, &HttpRequestType::parse_getmicroblocks_unconfirmed),
-            ("POST", &PATH_POSTTRANSACTION, &HttpRequestType::parse_posttransaction)
+            ("GET", &PATH_GETSORTITION, &HttpRequestType::parse_getsortition),
+            ("GET", &PATH_GETSORTITIONS, &HttpRequestType::parse_getsortitions),
+            ("GET", &PATH_GETHISTORICAL_ACCOUNT, &HttpRequestType::parse_gethistorical_account),
+            ("GET", &PATH_GETHISTORICAL_DATA_VAR, &HttpRequestType::parse_gethistorical_data_var),
+            ("GET", &PATH_GETHISTORICAL_MAP_ENTRY, &HttpRequestType::parse_gethistorical_map_entry),
+            ("POST", &PATH_POSTHISTORICAL_EVAL, &HttpRequestType::parse_posthistorical_eval),
+            ("POST", &PATH_POSTTRANSACTION, &HttpRequestType::parse_posttransaction),
+            ("POST", &PATH_POSTFEE_ESTIMATE, &HttpRequestType::parse_postfee_estimate),
+            ("POST", &PATH_POSTSIMULATE_TRANSACTION, &HttpRequestType::parse_postsimulate_transaction)
         ];
 
         for (verb, regex, parser) in REQUEST_METHODS.iter() {
@@ -1206,6 +1220,178 @@ impl HttpRequestType {
         Ok(HttpRequestType::GetMicroblocksUnconfirmed(HttpRequestMetadata::from_preamble(preamble), block_hash, min_seq))
     }
 
+    fn parse_getsortition<R: Read>(_protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, regex: &Regex, _fd: &mut R) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError("Invalid Http request: expected 0-length body for GetSortition".to_string()));
+        }
+
+        let captures = regex.captures(&preamble.path).ok_or(net_error::DeserializeError("Failed to match path to burn block height".to_string()))?;
+        let burn_height_str = captures
+            .get(1)
+            .ok_or(net_error::DeserializeError("Failed to match path to burn block height group".to_string()))?
+            .as_str();
+
+        let burn_height = burn_height_str.parse::<u64>().map_err(|_e| net_error::DeserializeError("Failed to parse burn block height".to_string()))?;
+
+        Ok(HttpRequestType::GetSortition(HttpRequestMetadata::from_preamble(preamble), burn_height))
+    }
+
+    fn parse_getsortitions<R: Read>(_protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, regex: &Regex, _fd: &mut R) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError("Invalid Http request: expected 0-length body for GetSortitions".to_string()));
+        }
+
+        let captures = regex.captures(&preamble.path).ok_or(net_error::DeserializeError("Failed to match path to burn block height range".to_string()))?;
+        let start_height_str = captures
+            .get(1)
+            .ok_or(net_error::DeserializeError("Failed to match path to start burn block height group".to_string()))?
+            .as_str();
+
+        let end_height_str = captures
+            .get(2)
+            .ok_or(net_error::DeserializeError("Failed to match path to end burn block height group".to_string()))?
+            .as_str();
+
+        let start_height = start_height_str.parse::<u64>().map_err(|_e| net_error::DeserializeError("Failed to parse start burn block height".to_string()))?;
+        let end_height = end_height_str.parse::<u64>().map_err(|_e| net_error::DeserializeError("Failed to parse end burn block height".to_string()))?;
+
+        // the range is inclusive
+        if start_height > end_height || end_height - start_height >= MAX_SORTITIONS_PER_RANGE {
+            return Err(net_error::DeserializeError(format!("Invalid burn block height range: must be non-empty and span at most {} heights", MAX_SORTITIONS_PER_RANGE)));
+        }
+
+        Ok(HttpRequestType::GetSortitions(HttpRequestMetadata::from_preamble(preamble), start_height, end_height))
+    }
+
+    /// Get the index block hash and contract ID at the start of a historical state query path
+    fn parse_historical_prefix(captures: &Captures) -> Result<(BlockHeaderHash, QualifiedContractIdentifier), net_error> {
+        let index_block_hash_str = captures
+            .get(1)
+            .ok_or(net_error::DeserializeError("Failed to match path to index block hash group".to_string()))?
+            .as_str();
+
+        let contract_id_str = captures
+            .get(2)
+            .ok_or(net_error::DeserializeError("Failed to match path to contract ID group".to_string()))?
+            .as_str();
+
+        let index_block_hash = BlockHeaderHash::from_hex(index_block_hash_str)
+            .map_err(|_e| net_error::DeserializeError("Failed to parse index block hash".to_string()))?;
+
+        let contract_id = QualifiedContractIdentifier::parse(contract_id_str)
+            .map_err(|_e| net_error::DeserializeError("Failed to parse contract ID".to_string()))?;
+
+        Ok((index_block_hash, contract_id))
+    }
+
+    fn parse_gethistorical_account<R: Read>(_protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, regex: &Regex, _fd: &mut R) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError("Invalid Http request: expected 0-length body for GetHistoricalAccount".to_string()));
+        }
+
+        let captures = regex.captures(&preamble.path).ok_or(net_error::DeserializeError("Failed to match path to index block hash and principal".to_string()))?;
+        let index_block_hash_str = captures
+            .get(1)
+            .ok_or(net_error::DeserializeError("Failed to match path to index block hash group".to_string()))?
+            .as_str();
+
+        let principal_str = captures
+            .get(2)
+            .ok_or(net_error::DeserializeError("Failed to match path to principal group".to_string()))?
+            .as_str();
+
+        let index_block_hash = BlockHeaderHash::from_hex(index_block_hash_str)
+            .map_err(|_e| net_error::DeserializeError("Failed to parse index block hash".to_string()))?;
+
+        let principal =
+            if principal_str.contains('.') {
+                PrincipalData::parse_qualified_contract_principal(principal_str)
+            }
+            else {
+                PrincipalData::parse_standard_principal(principal_str).map(PrincipalData::Standard)
+            }
+            .map_err(|_e| net_error::DeserializeError("Failed to parse principal".to_string()))?;
+
+        Ok(HttpRequestType::GetHistoricalAccount(HttpRequestMetadata::from_preamble(preamble), index_block_hash, principal))
+    }
+
+    fn parse_gethistorical_data_var<R: Read>(_protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, regex: &Regex, _fd: &mut R) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError("Invalid Http request: expected 0-length body for GetHistoricalDataVar".to_string()));
+        }
+
+        let captures = regex.captures(&preamble.path).ok_or(net_error::DeserializeError("Failed to match path to index block hash, contract, and data var".to_string()))?;
+        let (index_block_hash, contract_id) = HttpRequestType::parse_historical_prefix(&captures)?;
+
+        let var_name_str = captures
+            .get(3)
+            .ok_or(net_error::DeserializeError("Failed to match path to data var group".to_string()))?
+            .as_str();
+
+        let var_name = ClarityName::try_from(var_name_str.to_string())
+            .map_err(|_e| net_error::DeserializeError("Failed to parse data var name".to_string()))?;
+
+        Ok(HttpRequestType::GetHistoricalDataVar(HttpRequestMetadata::from_preamble(preamble), index_block_hash, contract_id, var_name))
+    }
+
+    fn parse_gethistorical_map_entry<R: Read>(_protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, regex: &Regex, _fd: &mut R) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() != 0 {
+            return Err(net_error::DeserializeError("Invalid Http request: expected 0-length body for GetHistoricalMapEntry".to_string()));
+        }
+
+        let captures = regex.captures(&preamble.path).ok_or(net_error::DeserializeError("Failed to match path to index block hash, contract, map, and key".to_string()))?;
+        let (index_block_hash, contract_id) = HttpRequestType::parse_historical_prefix(&captures)?;
+
+        let map_name_str = captures
+            .get(3)
+            .ok_or(net_error::DeserializeError("Failed to match path to map group".to_string()))?
+            .as_str();
+
+        let key_str = captures
+            .get(4)
+            .ok_or(net_error::DeserializeError("Failed to match path to map key group".to_string()))?
+            .as_str();
+
+        let map_name = ClarityName::try_from(map_name_str.to_string())
+            .map_err(|_e| net_error::DeserializeError("Failed to parse map name".to_string()))?;
+
+        let key = Value::try_deserialize_hex_untyped(key_str)
+            .map_err(|_e| net_error::DeserializeError("Failed to parse map key".to_string()))?;
+
+        Ok(HttpRequestType::GetHistoricalMapEntry(HttpRequestMetadata::from_preamble(preamble), index_block_hash, contract_id, map_name, key))
+    }
+
+    fn parse_posthistorical_eval<R: Read>(_protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, regex: &Regex, fd: &mut R) -> Result<HttpRequestType, net_error> {
+        if preamble.get_content_length() == 0 {
+            return Err(net_error::DeserializeError("Invalid Http request: expected non-zero-length body for PostHistoricalEval".to_string()));
+        }
+
+        if preamble.get_content_length() > MAX_MESSAGE_LEN {
+            return Err(net_error::DeserializeError("Invalid Http request: PostHistoricalEval body is too big".to_string()));
+        }
+
+        // content-type must be given, and must be text/plain
+        match preamble.content_type {
+            None => {
+                return Err(net_error::DeserializeError("Missing Content-Type for Clarity code".to_string()));
+            },
+            Some(ref c) => {
+                if *c != HttpContentType::Text {
+                    return Err(net_error::DeserializeError("Wrong Content-Type for Clarity code; expected text/plain".to_string()));
+                }
+            }
+        };
+
+        let captures = regex.captures(&preamble.path).ok_or(net_error::DeserializeError("Failed to match path to index block hash and contract".to_string()))?;
+        let (index_block_hash, contract_id) = HttpRequestType::parse_historical_prefix(&captures)?;
+
+        let mut bound_fd = BoundReader::from_reader(fd, preamble.get_content_length() as u64);
+        let mut code = String::new();
+        bound_fd.read_to_string(&mut code).map_err(net_error::ReadError)?;
+
+        Ok(HttpRequestType::PostHistoricalEval(HttpRequestMetadata::from_preamble(preamble), index_block_hash, contract_id, code))
+    }
+
     fn parse_posttransaction<R: Read>(_protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, _regex: &Regex, fd: &mut R) -> Result<HttpRequestType, net_error> {
         if preamble.get_content_length() == 0 {
             return Err(net_error::DeserializeError("Invalid Http request: expected non-zero-length body for PostTransaction".to_string()));
@@ -1227,6 +1413,52 @@ impl HttpRequestType {
         Ok(HttpRequestType::PostTransaction(HttpRequestMetadata::from_preamble(preamble), tx))
     }
 
+    /// Parse a request whose path carries an index block hash and whose body is a transaction
+    fn parse_index_block_hash_and_tx<R: Read>(request_name: &str, preamble: &HttpRequestPreamble, regex: &Regex, fd: &mut R) -> Result<(BlockHeaderHash, StacksTransaction), net_error> {
+        if preamble.get_content_length() == 0 {
+            return Err(net_error::DeserializeError(format!("Invalid Http request: expected non-zero-length body for {}", request_name)));
+        }
+
+        if preamble.get_content_length() > MAX_MESSAGE_LEN {
+            return Err(net_error::DeserializeError(format!("Invalid Http request: {} body is too big", request_name)));
+        }
+
+        // content-type must be given, and must be application/octet-stream
+        match preamble.content_type {
+            None => {
+                return Err(net_error::DeserializeError("Missing Content-Type for transaction".to_string()));
+            },
+            Some(ref c) => {
+                if *c != HttpContentType::Bytes {
+                    return Err(net_error::DeserializeError("Wrong Content-Type for transaction; expected application/octet-stream".to_string()));
+                }
+            }
+        };
+
+        let captures = regex.captures(&preamble.path).ok_or(net_error::DeserializeError("Failed to match path to index block hash".to_string()))?;
+        let index_block_hash_str = captures
+            .get(1)
+            .ok_or(net_error::DeserializeError("Failed to match path to index block hash group".to_string()))?
+            .as_str();
+
+        let index_block_hash = BlockHeaderHash::from_hex(index_block_hash_str)
+            .map_err(|_e| net_error::DeserializeError("Failed to parse index block hash".to_string()))?;
+
+        let mut bound_fd = BoundReader::from_reader(fd, preamble.get_content_length() as u64);
+        let tx = StacksTransaction::consensus_deserialize(&mut bound_fd)?;
+        Ok((index_block_hash, tx))
+    }
+
+    fn parse_postfee_estimate<R: Read>(_protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, regex: &Regex, fd: &mut R) -> Result<HttpRequestType, net_error> {
+        let (index_block_hash, tx) = HttpRequestType::parse_index_block_hash_and_tx("PostFeeEstimate", preamble, regex, fd)?;
+        Ok(HttpRequestType::PostFeeEstimate(HttpRequestMetadata::from_preamble(preamble), index_block_hash, tx))
+    }
+
+    fn parse_postsimulate_transaction<R: Read>(_protocol: &mut StacksHttp, preamble: &HttpRequestPreamble, regex: &Regex, fd: &mut R) -> Result<HttpRequestType, net_error> {
+        let (index_block_hash, tx) = HttpRequestType::parse_index_block_hash_and_tx("PostSimulateTransaction", preamble, regex, fd)?;
+        Ok(HttpRequestType::PostSimulateTransaction(HttpRequestMetadata::from_preamble(preamble), index_block_hash, tx))
+    }
+
     pub fn metadata(&self) -> &HttpRequestMetadata {
         match *self {
             HttpRequestType::GetInfo(ref md) => md,
@@ -1234,7 +1466,15 @@ impl HttpRequestType {
             HttpRequestType::GetBlock(ref md, _) => md,
             HttpRequestType::GetMicroblocks(ref md, _) => md,
             HttpRequestType::GetMicroblocksUnconfirmed(ref md, _, _) => md,
+            HttpRequestType::GetSortition(ref md, _) => md,
+            HttpRequestType::GetSortitions(ref md, _, _) => md,
+            HttpRequestType::GetHistoricalAccount(ref md, _, _) => md,
+            HttpRequestType::GetHistoricalDataVar(ref md, _, _, _) => md,
+            HttpRequestType::GetHistoricalMapEntry(ref md, _, _, _, _) => md,
+            HttpRequestType::PostHistoricalEval(ref md, _, _, _) => md,
             HttpRequestType::PostTransaction(ref md, _) => md,
+            HttpRequestType::PostFeeEstimate(ref md, _, _) => md,
+            HttpRequestType::PostSimulateTransaction(ref md, _, _) => md,
         }
     }
     
@@ -1245,7 +1485,15 @@ impl HttpRequestType {
             HttpRequestType::GetBlock(ref mut md, _) => md,
             HttpRequestType::GetMicroblocks(ref mut md, _) => md,
             HttpRequestType::GetMicroblocksUnconfirmed(ref mut md, _, _) => md,
+            HttpRequestType::GetSortition(ref mut md, _) => md,
+            HttpRequestType::GetSortitions(ref mut md, _, _) => md,
+            HttpRequestType::GetHistoricalAccount(ref mut md, _, _) => md,
+            HttpRequestType::GetHistoricalDataVar(ref mut md, _, _, _) => md,
+            HttpRequestType::GetHistoricalMapEntry(ref mut md, _, _, _, _) => md,
+            HttpRequestType::PostHistoricalEval(ref mut md, _, _, _) => md,
             HttpRequestType::PostTransaction(ref mut md, _) => md,
+            HttpRequestType::PostFeeEstimate(ref mut md, _, _) => md,
+            HttpRequestType::PostSimulateTransaction(ref mut md, _, _) => md,
         }
     }
 
@@ -1256,7 +1504,15 @@ impl HttpRequestType {
             HttpRequestType::GetBlock(ref _md, ref block_hash) => format!("/v2/blocks/{}", block_hash.to_hex()),
             HttpRequestType::GetMicroblocks(ref _md, ref block_hash) => format!("/v2/microblocks/{}", block_hash.to_hex()),
             HttpRequestType::GetMicroblocksUnconfirmed(ref _md, ref block_hash, ref min_seq) => format!("/v2/microblocks/unconfirmed/{}/{}", block_hash.to_hex(), min_seq),
-            HttpRequestType::PostTransaction(ref _md, ref _tx) => "/v2/transactions".to_string()
+            HttpRequestType::GetSortition(ref _md, ref burn_height) => format!("/v2/sortitions/{}", burn_height),
+            HttpRequestType::GetSortitions(ref _md, ref start_height, ref end_height) => format!("/v2/sortitions/{}/{}", start_height, end_height),
+            HttpRequestType::GetHistoricalAccount(ref _md, ref index_block_hash, ref principal) => format!("/v2/history/{}/accounts/{}", index_block_hash.to_hex(), principal),
+            HttpRequestType::GetHistoricalDataVar(ref _md, ref index_block_hash, ref contract_id, ref var_name) => format!("/v2/history/{}/data_var/{}/{}", index_block_hash.to_hex(), contract_id, var_name.as_str()),
+            HttpRequestType::GetHistoricalMapEntry(ref _md, ref index_block_hash, ref contract_id, ref map_name, ref key) => format!("/v2/history/{}/map_entry/{}/{}/{}", index_block_hash.to_hex(), contract_id, map_name.as_str(), ClaritySerializable::serialize(key)),
+            HttpRequestType::PostHistoricalEval(ref _md, ref index_block_hash, ref contract_id, ref _code) => format!("/v2/history/{}/eval/{}", index_block_hash.to_hex(), contract_id),
+            HttpRequestType::PostTransaction(ref _md, ref _tx) => "/v2/transactions".to_string(),
+            HttpRequestType::PostFeeEstimate(ref _md, ref index_block_hash, ref _tx) => format!("/v2/fees/transaction/{}", index_block_hash.to_hex()),
+            HttpRequestType::PostSimulateTransaction(ref _md, ref index_block_hash, ref _tx) => format!("/v2/transactions/simulate/{}", index_block_hash.to_hex())
         }
     }
 
@@ -1277,10 +1533,43 @@ impl HttpRequestType {
             HttpRequestType::GetMicroblocksUnconfirmed(ref md, ref _block_hash, ref _min_seq) => {
                 HttpRequestPreamble::new_serialized(fd, &md.version, "GET", &self.request_path(), &md.peer, md.keep_alive, None, None, empty_headers)?;
             },
+            HttpRequestType::GetSortition(ref md, ref _burn_height) => {
+                HttpRequestPreamble::new_serialized(fd, &md.version, "GET", &self.request_path(), &md.peer, md.keep_alive, None, None, empty_headers)?;
+            },
+            HttpRequestType::GetSortitions(ref md, ref _start_height, ref _end_height) => {
+                HttpRequestPreamble::new_serialized(fd, &md.version, "GET", &self.request_path(), &md.peer, md.keep_alive, None, None, empty_headers)?;
+            },
+            HttpRequestType::GetHistoricalAccount(ref md, ref _index_block_hash, ref _principal) => {
+                HttpRequestPreamble::new_serialized(fd, &md.version, "GET", &self.request_path(), &md.peer, md.keep_alive, None, None, empty_headers)?;
+            },
+            HttpRequestType::GetHistoricalDataVar(ref md, ref _index_block_hash, ref _contract_id, ref _var_name) => {
+                HttpRequestPreamble::new_serialized(fd, &md.version, "GET", &self.request_path(), &md.peer, md.keep_alive, None, None, empty_headers)?;
+            },
+            HttpRequestType::GetHistoricalMapEntry(ref md, ref _index_block_hash, ref _contract_id, ref _map_name, ref _key) => {
+                HttpRequestPreamble::new_serialized(fd, &md.version, "GET", &self.request_path(), &md.peer, md.keep_alive, None, None, empty_headers)?;
+            },
+            HttpRequestType::PostHistoricalEval(ref md, ref _index_block_hash, ref _contract_id, ref code) => {
+                HttpRequestPreamble::new_serialized(fd, &md.version, "POST", &self.request_path(), &md.peer, md.keep_alive, Some(code.len() as u32), Some(&HttpContentType::Text), empty_headers)?;
+                fd.write_all(code.as_bytes()).map_err(net_error::WriteError)?;
+            },
             HttpRequestType::PostTransaction(ref md, ref tx) => {
                 let mut tx_bytes = vec![];
                 write_next(&mut tx_bytes, tx)?;
 
+                HttpRequestPreamble::new_serialized(fd, &md.version, "POST", &self.request_path(), &md.peer, md.keep_alive, Some(tx_bytes.len() as u32), Some(&HttpContentType::Bytes), empty_headers)?;
+                fd.write_all(&tx_bytes).map_err(net_error::WriteError)?;
+            },
+            HttpRequestType::PostFeeEstimate(ref md, ref _index_block_hash, ref tx) => {
+                let mut tx_bytes = vec![];
+                write_next(&mut tx_bytes, tx)?;
+
+                HttpRequestPreamble::new_serialized(fd, &md.version, "POST", &self.request_path(), &md.peer, md.keep_alive, Some(tx_bytes.len() as u32), Some(&HttpContentType::Bytes), empty_headers)?;
+                fd.write_all(&tx_bytes).map_err(net_error::WriteError)?;
+            },
+            HttpRequestType::PostSimulateTransaction(ref md, ref _index_block_hash, ref tx) => {
+                let mut tx_bytes = vec![];
+                write_next(&mut tx_bytes, tx)?;
+
                 HttpRequestPreamble::new_serialized(fd, &md.version, "POST", &self.request_path(), &md.peer, md.keep_alive, Some(tx_bytes.len() as u32), Some(&HttpContentType::Bytes), empty_headers)?;
                 fd.write_all(&tx_bytes).map_err(net_error::WriteError)?;
             }
@@ -1445,13 +1734,21 @@ impl HttpResponseType {
         }
 
         // TODO: make this static somehow
-        let RESPONSE_METHODS : [(&Regex, &dyn Fn(&mut StacksHttp, HttpVersion, &HttpResponsePreamble, &mut R, Option<usize>) -> Result<HttpResponseType, net_error>); 6] = [
+        let RESPONSE_METHODS : [(&Regex, &dyn Fn(&mut StacksHttp, HttpVersion, &HttpResponsePreamble, &mut R, Option<usize>) -> Result<HttpResponseType, net_error>); 14] = [
             (&PATH_GETINFO, &HttpResponseType::parse_peerinfo),
             (&PATH_GETNEIGHBORS, &HttpResponseType::parse_neighbors),
             (&PATH_GETBLOCK, &HttpResponseType::parse_block),
             (&PATH_GETMICROBLOCKS, &HttpResponseType::parse_microblocks),
             (&PATH_GETMICROBLOCKS_UNCONFIRMED, &HttpResponseType::parse_microblocks_unconfirmed),
-            (&PATH_POSTTRANSACTION, &HttpResponseType::parse_txid)
+            (&PATH_GETSORTITION, &HttpResponseType::parse_sortition_info),
+            (&PATH_GETSORTITIONS, &HttpResponseType::parse_sortition_info_list),
+            (&PATH_GETHISTORICAL_ACCOUNT, &HttpResponseType::parse_historical_account),
+            (&PATH_GETHISTORICAL_DATA_VAR, &HttpResponseType::parse_historical_value),
+            (&PATH_GETHISTORICAL_MAP_ENTRY, &HttpResponseType::parse_historical_value),
+            (&PATH_POSTHISTORICAL_EVAL, &HttpResponseType::parse_historical_value),
+            (&PATH_POSTTRANSACTION, &HttpResponseType::parse_txid),
+            (&PATH_POSTFEE_ESTIMATE, &HttpResponseType::parse_fee_estimate),
+            (&PATH_POSTSIMULATE_TRANSACTION, &HttpResponseType::parse_transaction_simulation)
         ];
 
         for (regex, parser) in RESPONSE_METHODS.iter() {
@@ -1522,6 +1819,26 @@ impl HttpResponseType {
         Ok(HttpResponseType::Microblocks(HttpResponseMetadata::from_preamble(request_version, preamble), microblocks))
     }
 
+    fn parse_sortition_info<R: Read>(_protocol: &mut StacksHttp, request_version: HttpVersion, preamble: &HttpResponsePreamble, fd: &mut R, len_hint: Option<usize>) -> Result<HttpResponseType, net_error> {
+        let sortition_info = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::SortitionInfo(HttpResponseMetadata::from_preamble(request_version, preamble), sortition_info))
+    }
+
+    fn parse_sortition_info_list<R: Read>(_protocol: &mut StacksHttp, request_version: HttpVersion, preamble: &HttpResponsePreamble, fd: &mut R, len_hint: Option<usize>) -> Result<HttpResponseType, net_error> {
+        let sortition_infos = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::SortitionInfoList(HttpResponseMetadata::from_preamble(request_version, preamble), sortition_infos))
+    }
+
+    fn parse_historical_account<R: Read>(_protocol: &mut StacksHttp, request_version: HttpVersion, preamble: &HttpResponsePreamble, fd: &mut R, len_hint: Option<usize>) -> Result<HttpResponseType, net_error> {
+        let account_data = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::HistoricalAccount(HttpResponseMetadata::from_preamble(request_version, preamble), account_data))
+    }
+
+    fn parse_historical_value<R: Read>(_protocol: &mut StacksHttp, request_version: HttpVersion, preamble: &HttpResponsePreamble, fd: &mut R, len_hint: Option<usize>) -> Result<HttpResponseType, net_error> {
+        let value_data = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::HistoricalValue(HttpResponseMetadata::from_preamble(request_version, preamble), value_data))
+    }
+
     fn parse_txid<R: Read>(_protocol: &mut StacksHttp, request_version: HttpVersion, preamble: &HttpResponsePreamble, fd: &mut R, len_hint: Option<usize>) -> Result<HttpResponseType, net_error> {
         let txid_buf = HttpResponseType::parse_text(preamble, fd, len_hint, 64)?;
         if txid_buf.len() != 64 {
@@ -1536,6 +1853,16 @@ impl HttpResponseType {
         Ok(HttpResponseType::TransactionID(HttpResponseMetadata::from_preamble(request_version, preamble), Txid::from_bytes(&txid_bytes).unwrap()))
     }
 
+    fn parse_fee_estimate<R: Read>(_protocol: &mut StacksHttp, request_version: HttpVersion, preamble: &HttpResponsePreamble, fd: &mut R, len_hint: Option<usize>) -> Result<HttpResponseType, net_error> {
+        let fee_estimate = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::FeeEstimate(HttpResponseMetadata::from_preamble(request_version, preamble), fee_estimate))
+    }
+
+    fn parse_transaction_simulation<R: Read>(_protocol: &mut StacksHttp, request_version: HttpVersion, preamble: &HttpResponsePreamble, fd: &mut R, len_hint: Option<usize>) -> Result<HttpResponseType, net_error> {
+        let simulation = HttpResponseType::parse_json(preamble, fd, len_hint, MAX_MESSAGE_LEN as u64)?;
+        Ok(HttpResponseType::TransactionSimulation(HttpResponseMetadata::from_preamble(request_version, preamble), simulation))
+    }
+
     fn error_reason(code: u16) -> &'static str {
         match code {
             400 => "Bad Request",
@@ -1543,6 +1870,7 @@ impl HttpResponseType {
             402 => "Payment Required",
             403 => "Forbidden",
             404 => "Not Found",
+            410 => "Gone",
             500 => "Internal Server Error",
             503 => "Service Temporarily Unavailable",
             _ => "Error"
@@ -1564,7 +1892,13 @@ impl HttpResponseType {
             HttpResponseType::BlockStream(ref md) => md,
             HttpResponseType::Microblocks(ref md, _) => md,
             HttpResponseType::MicroblockStream(ref md) => md,
+            HttpResponseType::SortitionInfo(ref md, _) => md,
+            HttpResponseType::SortitionInfoList(ref md, _) => md,
+            HttpResponseType::HistoricalAccount(ref md, _) => md,
+            HttpResponseType::HistoricalValue(ref md, _) => md,
             HttpResponseType::TransactionID(ref md, _) => md,
+            HttpResponseType::FeeEstimate(ref md, _) => md,
+            HttpResponseType::TransactionSimulation(ref md, _) => md,
             // errors
             HttpResponseType::BadRequest(ref md, _) => md,
             HttpResponseType::Unauthorized(ref md, _) => md,
@@ -1650,11 +1984,35 @@ impl HttpResponseType {
                 // the microblock data itself.
                 HttpResponsePreamble::new_serialized(fd, 200, "OK", None, &HttpContentType::Bytes, md.request_id, |ref mut fd| keep_alive_headers(fd, md))?;
             },
+            HttpResponseType::SortitionInfo(ref md, ref sortition_info) => {
+                HttpResponsePreamble::new_serialized(fd, 200, "OK", md.content_length.clone(), &HttpContentType::JSON, md.request_id, |ref mut fd| keep_alive_headers(fd, md))?;
+                HttpResponseType::send_json(protocol, md, fd, sortition_info)?;
+            },
+            HttpResponseType::SortitionInfoList(ref md, ref sortition_infos) => {
+                HttpResponsePreamble::new_serialized(fd, 200, "OK", md.content_length.clone(), &HttpContentType::JSON, md.request_id, |ref mut fd| keep_alive_headers(fd, md))?;
+                HttpResponseType::send_json(protocol, md, fd, sortition_infos)?;
+            },
+            HttpResponseType::HistoricalAccount(ref md, ref account_data) => {
+                HttpResponsePreamble::new_serialized(fd, 200, "OK", md.content_length.clone(), &HttpContentType::JSON, md.request_id, |ref mut fd| keep_alive_headers(fd, md))?;
+                HttpResponseType::send_json(protocol, md, fd, account_data)?;
+            },
+            HttpResponseType::HistoricalValue(ref md, ref value_data) => {
+                HttpResponsePreamble::new_serialized(fd, 200, "OK", md.content_length.clone(), &HttpContentType::JSON, md.request_id, |ref mut fd| keep_alive_headers(fd, md))?;
+                HttpResponseType::send_json(protocol, md, fd, value_data)?;
+            },
             HttpResponseType::TransactionID(ref md, ref txid) => {
                 let txid_bytes = txid.to_hex().into_bytes();
                 HttpResponsePreamble::new_serialized(fd, 200, "OK", md.content_length.clone(), &HttpContentType::Text, md.request_id, |ref mut fd| keep_alive_headers(fd, md))?;
                 HttpResponseType::send_text(protocol, md, fd, &txid_bytes)?;
             },
+            HttpResponseType::FeeEstimate(ref md, ref fee_estimate) => {
+                HttpResponsePreamble::new_serialized(fd, 200, "OK", md.content_length.clone(), &HttpContentType::JSON, md.request_id, |ref mut fd| keep_alive_headers(fd, md))?;
+                HttpResponseType::send_json(protocol, md, fd, fee_estimate)?;
+            },
+            HttpResponseType::TransactionSimulation(ref md, ref simulation) => {
+                HttpResponsePreamble::new_serialized(fd, 200, "OK", md.content_length.clone(), &HttpContentType::JSON, md.request_id, |ref mut fd| keep_alive_headers(fd, md))?;
+                HttpResponseType::send_json(protocol, md, fd, simulation)?;
+            },
             HttpResponseType::BadRequest(_, ref msg) => self.error_response(fd, 400, msg)?,
             HttpResponseType::Unauthorized(_, ref msg) => self.error_response(fd, 401, msg)?,
             HttpResponseType::PaymentRequired(_, ref msg) => self.error_response(fd, 402, msg)?,
@@ -1674,7 +2032,15 @@ lazy_static! {
     static ref PATH_GETBLOCK : Regex = Regex::new(r#"^/v2/blocks/([0-9a-f]{64})$"#).unwrap();
     static ref PATH_GETMICROBLOCKS : Regex = Regex::new(r#"^/v2/microblocks/([0-9a-f]{64})$"#).unwrap();
     static ref PATH_GETMICROBLOCKS_UNCONFIRMED : Regex = Regex::new(r#"^/v2/microblocks/unconfirmed/([0-9a-f]{64})/([0-9]{1,5})$"#).unwrap();
+    static ref PATH_GETSORTITION : Regex = Regex::new(r#"^/v2/sortitions/([0-9]{1,20})$"#).unwrap();
+    static ref PATH_GETSORTITIONS : Regex = Regex::new(r#"^/v2/sortitions/([0-9]{1,20})/([0-9]{1,20})$"#).unwrap();
+    static ref PATH_GETHISTORICAL_ACCOUNT : Regex = Regex::new(r#"^/v2/history/([0-9a-f]{64})/accounts/([0-9A-Z]{28,41}(\.[a-zA-Z][a-zA-Z0-9_-]*)?)$"#).unwrap();
+    static ref PATH_GETHISTORICAL_DATA_VAR : Regex = Regex::new(r#"^/v2/history/([0-9a-f]{64})/data_var/([0-9A-Z]{28,41}\.[a-zA-Z][a-zA-Z0-9_-]*)/([a-zA-Z][a-zA-Z0-9_!+<>=*-]*)$"#).unwrap();
+    static ref PATH_GETHISTORICAL_MAP_ENTRY : Regex = Regex::new(r#"^/v2/history/([0-9a-f]{64})/map_entry/([0-9A-Z]{28,41}\.[a-zA-Z][a-zA-Z0-9_-]*)/([a-zA-Z][a-zA-Z0-9_!+<>=*-]*)/([0-9a-f]+)$"#).unwrap();
+    static ref PATH_POSTHISTORICAL_EVAL : Regex = Regex::new(r#"^/v2/history/([0-9a-f]{64})/eval/([0-9A-Z]{28,41}\.[a-zA-Z][a-zA-Z0-9_-]*)$"#).unwrap();
     static ref PATH_POSTTRANSACTION : Regex = Regex::new(r#"^/v2/transactions$"#).unwrap();
+    static ref PATH_POSTFEE_ESTIMATE : Regex = Regex::new(r#"^/v2/fees/transaction/([0-9a-f]{64})$"#).unwrap();
+    static ref PATH_POSTSIMULATE_TRANSACTION : Regex = Regex::new(r#"^/v2/transactions/simulate/([0-9a-f]{64})$"#).unwrap();
 }
 
 impl StacksMessageCodec for StacksHttpPreamble {
@@ -1731,7 +2097,15 @@ impl MessageSequence for StacksHttpMessage {
                 HttpRequestType::GetBlock(_, _) => "HTTP(GetBlock)",
                 HttpRequestType::GetMicroblocks(_, _) => "HTTP(GetMicroblocks)",
                 HttpRequestType::GetMicroblocksUnconfirmed(_, _, _) => "HTTP(GetMicroblocksUnconfirmed)",
-                HttpRequestType::PostTransaction(_, _) => "HTTP(PostTransaction)"
+                HttpRequestType::GetSortition(_, _) => "HTTP(GetSortition)",
+                HttpRequestType::GetSortitions(_, _, _) => "HTTP(GetSortitions)",
+                HttpRequestType::GetHistoricalAccount(_, _, _) => "HTTP(GetHistoricalAccount)",
+                HttpRequestType::GetHistoricalDataVar(_, _, _, _) => "HTTP(GetHistoricalDataVar)",
+                HttpRequestType::GetHistoricalMapEntry(_, _, _, _, _) => "HTTP(GetHistoricalMapEntry)",
+                HttpRequestType::PostHistoricalEval(_, _, _, _) => "HTTP(PostHistoricalEval)",
+                HttpRequestType::PostTransaction(_, _) => "HTTP(PostTransaction)",
+                HttpRequestType::PostFeeEstimate(_, _, _) => "HTTP(PostFeeEstimate)",
+                HttpRequestType::PostSimulateTransaction(_, _, _) => "HTTP(PostSimulateTransaction)"
             },
             StacksHttpMessage::Response(ref res) => match res {
                 HttpResponseType::PeerInfo(_, _) => "HTTP(PeerInfo)",
@@ -1740,7 +2114,13 @@ impl MessageSequence for StacksHttpMessage {
                 HttpResponseType::BlockStream(_) => "HTTP(BlockStream)",
                 HttpResponseType::Microblocks(_, _) => "HTTP(Microblocks)",
                 HttpResponseType::MicroblockStream(_) => "HTTP(MicroblockStream)",
+                HttpResponseType::SortitionInfo(_, _) => "HTTP(SortitionInfo)",
+                HttpResponseType::SortitionInfoList(_, _) => "HTTP(SortitionInfoList)",
+                HttpResponseType::HistoricalAccount(_, _) => "HTTP(HistoricalAccount)",
+                HttpResponseType::HistoricalValue(_, _) => "HTTP(HistoricalValue)",
                 HttpResponseType::TransactionID(_, _) => "HTTP(Transaction)",
+                HttpResponseType::FeeEstimate(_, _) => "HTTP(FeeEstimate)",
+                HttpResponseType::TransactionSimulation(_, _) => "HTTP(TransactionSimulation)",
                 HttpResponseType::BadRequest(_, _) => "HTTP(400)",
                 HttpResponseType::Unauthorized(_, _) => "HTTP(401)",
                 HttpResponseType::PaymentRequired(_, _) => "HTTP(402)",
@@ -2180,6 +2560,13 @@ mod test {
     use util::hash::MerkleTree;
     use util::hash::to_hex;
 
+    use vm::types::StandardPrincipalData;
+    use vm::costs::ExecutionCost;
+
+    use net::FeeEstimateData;
+    use net::TransactionSimulationData;
+    use net::AssetMovementData;
+
     use rand;
     use rand::RngCore;
 
@@ -2832,11 +3219,21 @@ mod test {
             keep_alive: true,
         };
 
+        let test_principal = PrincipalData::Standard(StandardPrincipalData(0x1a, [0x22; 20]));
+        let test_contract_id = QualifiedContractIdentifier::new(StandardPrincipalData(0x1a, [0x33; 20]), "hello-world".into());
+        let test_code = "(get-balance tx-sender)";
+
         let tests = vec![
             HttpRequestType::GetNeighbors(http_request_metadata_ip.clone()),
             HttpRequestType::GetBlock(http_request_metadata_dns.clone(), BlockHeaderHash([2u8; 32])),
             HttpRequestType::GetMicroblocks(http_request_metadata_ip.clone(), BlockHeaderHash([3u8; 32])),
-            HttpRequestType::PostTransaction(http_request_metadata_dns.clone(), make_test_transaction())
+            HttpRequestType::GetSortition(http_request_metadata_dns.clone(), 123),
+            HttpRequestType::GetSortitions(http_request_metadata_ip.clone(), 120, 130),
+            HttpRequestType::GetHistoricalAccount(http_request_metadata_ip.clone(), BlockHeaderHash([4u8; 32]), test_principal.clone()),
+            HttpRequestType::PostTransaction(http_request_metadata_dns.clone(), make_test_transaction()),
+            HttpRequestType::PostHistoricalEval(http_request_metadata_dns.clone(), BlockHeaderHash([5u8; 32]), test_contract_id.clone(), test_code.to_string()),
+            HttpRequestType::PostFeeEstimate(http_request_metadata_ip.clone(), BlockHeaderHash([6u8; 32]), make_test_transaction()),
+            HttpRequestType::PostSimulateTransaction(http_request_metadata_dns.clone(), BlockHeaderHash([7u8; 32]), make_test_transaction())
         ];
 
         let mut tx_body = vec![];
@@ -2846,18 +3243,42 @@ mod test {
         post_transaction_preamble.set_content_type(HttpContentType::Bytes);
         post_transaction_preamble.set_content_length(tx_body.len() as u32);
 
+        let mut post_historical_eval_preamble = HttpRequestPreamble::new(HttpVersion::Http11, "POST".to_string(), format!("/v2/history/{}/eval/{}", BlockHeaderHash([5u8; 32]).to_hex(), &test_contract_id), http_request_metadata_dns.peer.hostname(), http_request_metadata_dns.peer.port(), http_request_metadata_dns.keep_alive);
+        post_historical_eval_preamble.set_content_type(HttpContentType::Text);
+        post_historical_eval_preamble.set_content_length(test_code.len() as u32);
+
+        let mut post_fee_estimate_preamble = HttpRequestPreamble::new(HttpVersion::Http11, "POST".to_string(), format!("/v2/fees/transaction/{}", BlockHeaderHash([6u8; 32]).to_hex()), http_request_metadata_ip.peer.hostname(), http_request_metadata_ip.peer.port(), http_request_metadata_ip.keep_alive);
+        post_fee_estimate_preamble.set_content_type(HttpContentType::Bytes);
+        post_fee_estimate_preamble.set_content_length(tx_body.len() as u32);
+
+        let mut post_simulate_transaction_preamble = HttpRequestPreamble::new(HttpVersion::Http11, "POST".to_string(), format!("/v2/transactions/simulate/{}", BlockHeaderHash([7u8; 32]).to_hex()), http_request_metadata_dns.peer.hostname(), http_request_metadata_dns.peer.port(), http_request_metadata_dns.keep_alive);
+        post_simulate_transaction_preamble.set_content_type(HttpContentType::Bytes);
+        post_simulate_transaction_preamble.set_content_length(tx_body.len() as u32);
+
         // all of these should parse
         let expected_http_preambles = vec![
             HttpRequestPreamble::new(HttpVersion::Http11, "GET".to_string(), "/v2/neighbors".to_string(), http_request_metadata_ip.peer.hostname(), http_request_metadata_ip.peer.port(), http_request_metadata_ip.keep_alive),
             HttpRequestPreamble::new(HttpVersion::Http11, "GET".to_string(), format!("/v2/blocks/{}", BlockHeaderHash([2u8; 32]).to_hex()), http_request_metadata_dns.peer.hostname(), http_request_metadata_dns.peer.port(), http_request_metadata_dns.keep_alive),
             HttpRequestPreamble::new(HttpVersion::Http11, "GET".to_string(), format!("/v2/microblocks/{}", BlockHeaderHash([3u8; 32]).to_hex()), http_request_metadata_ip.peer.hostname(), http_request_metadata_ip.peer.port(), http_request_metadata_ip.keep_alive),
+            HttpRequestPreamble::new(HttpVersion::Http11, "GET".to_string(), "/v2/sortitions/123".to_string(), http_request_metadata_dns.peer.hostname(), http_request_metadata_dns.peer.port(), http_request_metadata_dns.keep_alive),
+            HttpRequestPreamble::new(HttpVersion::Http11, "GET".to_string(), "/v2/sortitions/120/130".to_string(), http_request_metadata_ip.peer.hostname(), http_request_metadata_ip.peer.port(), http_request_metadata_ip.keep_alive),
+            HttpRequestPreamble::new(HttpVersion::Http11, "GET".to_string(), format!("/v2/history/{}/accounts/{}", BlockHeaderHash([4u8; 32]).to_hex(), &test_principal), http_request_metadata_ip.peer.hostname(), http_request_metadata_ip.peer.port(), http_request_metadata_ip.keep_alive),
             post_transaction_preamble,
+            post_historical_eval_preamble,
+            post_fee_estimate_preamble,
+            post_simulate_transaction_preamble,
         ];
 
         let expected_http_bodies = vec![
             vec![],
             vec![],
             vec![],
+            vec![],
+            vec![],
+            vec![],
+            tx_body.clone(),
+            test_code.as_bytes().to_vec(),
+            tx_body.clone(),
             tx_body,
         ];
 
@@ -2890,7 +3311,13 @@ mod test {
             "GET /v2/neighbors HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\n\r\nb",
             "GET /v2/blocks/1111111111111111111111111111111111111111111111111111111111111111 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\n\r\nb",
             "GET /v2/microblocks/1111111111111111111111111111111111111111111111111111111111111111 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\n\r\nb",
+            "GET /v2/sortitions/123 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\n\r\nb",
+            "GET /v2/sortitions/120/130 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\n\r\nb",
             "POST /v2/transactions HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 0\r\n\r\n",
+            "GET /v2/history/1111111111111111111111111111111111111111111111111111111111111111/accounts/ST000000000000000000002AMW42H HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\n\r\nb",
+            "POST /v2/history/1111111111111111111111111111111111111111111111111111111111111111/eval/ST000000000000000000002AMW42H.miner-rewards HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 0\r\n\r\n",
+            "POST /v2/fees/transaction/1111111111111111111111111111111111111111111111111111111111111111 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 0\r\n\r\n",
+            "POST /v2/transactions/simulate/1111111111111111111111111111111111111111111111111111111111111111 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 0\r\n\r\n",
         ];
         for bad_content_length in bad_content_lengths {
             let mut http = StacksHttp::new();
@@ -2905,6 +3332,8 @@ mod test {
         let bad_content_types = vec![
             "POST /v2/transactions HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\n\r\nb",
             "POST /v2/transactions HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\nContent-Type: application/json\r\n\r\nb",
+            "POST /v2/fees/transaction/1111111111111111111111111111111111111111111111111111111111111111 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\nContent-Type: text/plain\r\n\r\nb",
+            "POST /v2/transactions/simulate/1111111111111111111111111111111111111111111111111111111111111111 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 1\r\nContent-Type: text/plain\r\n\r\nb",
         ];
         for bad_content_type in bad_content_types {
             let mut http = StacksHttp::new();
@@ -2913,6 +3342,18 @@ mod test {
             assert!(e.is_err());
             assert!(e.unwrap_err().to_string().find("Content-Type").is_some());
         }
+
+        let bad_sortition_ranges = vec![
+            "GET /v2/sortitions/130/120 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 0\r\n\r\n",
+            "GET /v2/sortitions/100/200 HTTP/1.1\r\nUser-Agent: stacks/2.0\r\nHost: bad:123\r\nContent-Length: 0\r\n\r\n",
+        ];
+        for bad_sortition_range in bad_sortition_ranges {
+            let mut http = StacksHttp::new();
+            let (preamble, offset) = http.read_preamble(bad_sortition_range.as_bytes()).unwrap();
+            let e = http.read_payload(&preamble, &bad_sortition_range.as_bytes()[offset..]);
+            assert!(e.is_err());
+            assert!(e.unwrap_err().to_string().find("Invalid burn block height range").is_some());
+        }
     }
 
     #[test]
@@ -2942,18 +3383,52 @@ mod test {
         let mut test_microblock_info_bytes = vec![];
         test_microblock_info.consensus_serialize(&mut test_microblock_info_bytes).unwrap();
 
+        let test_fee_estimate = FeeEstimateData {
+            index_block_hash: BlockHeaderHash([0x6; 32]).to_hex(),
+            fee_rate: 2,
+            tx_len: 100,
+            execution_cost: ExecutionCost { write_length: 1, write_count: 2, read_length: 3, read_count: 4, runtime: 5 },
+            fee_units: 900,
+            fee: 1800
+        };
+
+        let test_simulation = TransactionSimulationData {
+            index_block_hash: BlockHeaderHash([0x7; 32]).to_hex(),
+            txid: Txid([0x1; 32]).to_hex(),
+            result: ClaritySerializable::serialize(&Value::okay(Value::Bool(true)).unwrap()),
+            result_repr: format!("{}", Value::okay(Value::Bool(true)).unwrap()),
+            post_conditions_passed: false,
+            events: vec![json!({"type": "stx_burn_event", "stx_burn_event": {"sender": "ST000000000000000000002AMW42H", "amount": "100"}})],
+            asset_movements: vec![
+                AssetMovementData {
+                    principal: "ST000000000000000000002AMW42H".to_string(),
+                    asset_identifier: "STX".to_string(),
+                    movement_type: "stx_burn".to_string(),
+                    amount: Some("100".to_string()),
+                    values: None
+                }
+            ],
+            stx_burned: "0".to_string(),
+            execution_cost: ExecutionCost { write_length: 1, write_count: 2, read_length: 3, read_count: 4, runtime: 5 },
+            fee: 1800
+        };
+
         let tests = vec![
             // length is known
             (HttpResponseType::Neighbors(HttpResponseMetadata::new(HttpVersion::Http11, 123, Some(serde_json::to_string(&test_neighbors_info).unwrap().len() as u32), true), test_neighbors_info.clone()), "/v2/neighbors".to_string()),
             (HttpResponseType::Block(HttpResponseMetadata::new(HttpVersion::Http11, 123, Some(test_block_info_bytes.len() as u32), true), test_block_info.clone()), format!("/v2/blocks/{}", test_block_info.block_hash().to_hex())),
             (HttpResponseType::Microblocks(HttpResponseMetadata::new(HttpVersion::Http11, 123, Some(test_microblock_info_bytes.len() as u32), true), test_microblock_info.clone()), format!("/v2/microblocks/{}", test_microblock_info[0].block_hash().to_hex())),
             (HttpResponseType::TransactionID(HttpResponseMetadata::new(HttpVersion::Http11, 123, Some(Txid([0x1; 32]).to_hex().len() as u32), true), Txid([0x1; 32])), "/v2/transactions".to_string()),
+            (HttpResponseType::FeeEstimate(HttpResponseMetadata::new(HttpVersion::Http11, 123, Some(serde_json::to_string(&test_fee_estimate).unwrap().len() as u32), true), test_fee_estimate.clone()), format!("/v2/fees/transaction/{}", BlockHeaderHash([0x6; 32]).to_hex())),
+            (HttpResponseType::TransactionSimulation(HttpResponseMetadata::new(HttpVersion::Http11, 123, Some(serde_json::to_string(&test_simulation).unwrap().len() as u32), true), test_simulation.clone()), format!("/v2/transactions/simulate/{}", BlockHeaderHash([0x7; 32]).to_hex())),
             
             // length is unknown
             (HttpResponseType::Neighbors(HttpResponseMetadata::new(HttpVersion::Http11, 123, None, true), test_neighbors_info.clone()), "/v2/neighbors".to_string()),
             (HttpResponseType::Block(HttpResponseMetadata::new(HttpVersion::Http11, 123, None, true), test_block_info.clone()), format!("/v2/blocks/{}", test_block_info.block_hash().to_hex())),
             (HttpResponseType::Microblocks(HttpResponseMetadata::new(HttpVersion::Http11, 123, None, true), test_microblock_info.clone()), format!("/v2/microblocks/{}", test_microblock_info[0].block_hash().to_hex())),
             (HttpResponseType::TransactionID(HttpResponseMetadata::new(HttpVersion::Http11, 123, None, true), Txid([0x1; 32])), "/v2/transactions".to_string()),
+            (HttpResponseType::FeeEstimate(HttpResponseMetadata::new(HttpVersion::Http11, 123, None, true), test_fee_estimate.clone()), format!("/v2/fees/transaction/{}", BlockHeaderHash([0x6; 32]).to_hex())),
+            (HttpResponseType::TransactionSimulation(HttpResponseMetadata::new(HttpVersion::Http11, 123, None, true), test_simulation.clone()), format!("/v2/transactions/simulate/{}", BlockHeaderHash([0x7; 32]).to_hex())),
 
             // errors without error messages
             (HttpResponseType::BadRequest(HttpResponseMetadata::new(HttpVersion::Http11, 123, Some(0), true), "".to_string()), "/v2/neighbors".to_string()),
@@ -2982,12 +3457,16 @@ mod test {
             HttpResponsePreamble::new(200, "OK".to_string(), Some(test_block_info_bytes.len() as u32), HttpContentType::Bytes, true, 123),
             HttpResponsePreamble::new(200, "OK".to_string(), Some(test_microblock_info_bytes.len() as u32), HttpContentType::Bytes, true, 123),
             HttpResponsePreamble::new(200, "OK".to_string(), Some(Txid([0x1; 32]).to_hex().len() as u32), HttpContentType::Text, true, 123),
+            HttpResponsePreamble::new(200, "OK".to_string(), Some(serde_json::to_string(&test_fee_estimate).unwrap().len() as u32), HttpContentType::JSON, true, 123),
+            HttpResponsePreamble::new(200, "OK".to_string(), Some(serde_json::to_string(&test_simulation).unwrap().len() as u32), HttpContentType::JSON, true, 123),
             
             // length is unknown
             HttpResponsePreamble::new(200, "OK".to_string(), None, HttpContentType::JSON, true, 123),
             HttpResponsePreamble::new(200, "OK".to_string(), None, HttpContentType::Bytes, true, 123),
             HttpResponsePreamble::new(200, "OK".to_string(), None, HttpContentType::Bytes, true, 123),
             HttpResponsePreamble::new(200, "OK".to_string(), None, HttpContentType::Text, true, 123),
+            HttpResponsePreamble::new(200, "OK".to_string(), None, HttpContentType::JSON, true, 123),
+            HttpResponsePreamble::new(200, "OK".to_string(), None, HttpContentType::JSON, true, 123),
 
             // errors
             HttpResponsePreamble::new_error(400, 123, None),
@@ -3019,12 +3498,16 @@ mod test {
             test_block_info_bytes.clone(),
             test_microblock_info_bytes.clone(),
             Txid([0x1; 32]).to_hex().as_bytes().to_vec(),
+            serde_json::to_string(&test_fee_estimate).unwrap().as_bytes().to_vec(),
+            serde_json::to_string(&test_simulation).unwrap().as_bytes().to_vec(),
             
             // with transfer-encoding: chunked
             serde_json::to_string(&test_neighbors_info).unwrap().as_bytes().to_vec(),
             test_block_info_bytes,
             test_microblock_info_bytes,
             Txid([0x1; 32]).to_hex().as_bytes().to_vec(),
+            serde_json::to_string(&test_fee_estimate).unwrap().as_bytes().to_vec(),
+            serde_json::to_string(&test_simulation).unwrap().as_bytes().to_vec(),
 
             // errors
             vec![],
@@ -70,6 +70,10 @@ use chainstate::stacks::StacksPublicKey;
 
 use chainstate::stacks::Error as chainstate_error;
 
+use vm::types::{PrincipalData, QualifiedContractIdentifier, Value};
+use vm::representations::ClarityName;
+use vm::costs::ExecutionCost;
+
 use util::hash::Hash160;
 use util::hash::DOUBLE_SHA256_ENCODED_SIZE;
 use util::hash::HASH160_ENCODED_SIZE;
@@ -738,6 +742,111 @@ pub struct PeerInfoData {
     parent_network_id: u32,
 }
 
+/// A user burn that supported a block commit in a sortition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserBurnSupportData {
+    pub txid: String,
+    pub vtxindex: u32,
+    pub burn_fee: u64,
+}
+
+/// A block commit's sample point in a sortition's burn distribution.
+/// The range bounds are 256-bit hex numbers; the sortition picked this block commit if the
+/// sampled index fell in [range_start, range_end).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BurnSampleData {
+    pub txid: String,
+    pub vtxindex: u32,
+    pub block_header_hash: String,
+    pub burn_fee: u64,
+    pub total_burns: String,        // encodes u128
+    pub range_start: String,
+    pub range_end: String,
+    pub leader_key_block_height: u64,
+    pub leader_key_vtxindex: u32,
+    pub leader_public_key: String,
+    pub user_burns: Vec<UserBurnSupportData>,
+    pub winner: bool,
+}
+
+/// The data we return on GET /v2/sortitions/{burn-height}, and (as a list) on
+/// GET /v2/sortitions/{start-burn-height}/{end-burn-height}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SortitionInfoData {
+    pub burn_block_height: u64,
+    pub burn_header_hash: String,
+    pub parent_burn_header_hash: String,
+    pub consensus_hash: ConsensusHash,
+    pub total_burn: u64,
+    pub block_burn: u64,
+    pub sortition: bool,
+    pub sortition_hash: String,
+    pub sortition_index: Option<String>,
+    pub winning_block_txid: Option<String>,
+    pub winning_stacks_block_hash: Option<String>,
+    pub distribution: Vec<BurnSampleData>,
+}
+
+/// The data we return on GET /v2/history/{index-block-hash}/accounts/{principal}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoricalAccountData {
+    pub index_block_hash: String,
+    pub principal: String,
+    pub balance: String,        // encodes u128
+    pub nonce: u64,
+}
+
+/// A Clarity value as of a past block.  Returned by the historical data var, map entry, and
+/// read-only evaluation routes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoricalValueData {
+    pub index_block_hash: String,
+    pub value: String,          // hex-encoded serialized Clarity value
+    pub repr: String,
+}
+
+/// The data we return on POST /v2/fees/transaction/{index-block-hash}.  The fee is the fee rate
+/// times the fee units, which are the transaction's length plus its cost-weighted execution cost.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeEstimateData {
+    pub index_block_hash: String,
+    pub fee_rate: u64,
+    pub tx_len: u64,
+    pub execution_cost: ExecutionCost,
+    pub fee_units: u64,
+    pub fee: u64,
+}
+
+/// One principal's movement of one asset in a simulated transaction.  STX and STX burns have the
+/// asset identifier "STX"; fungible tokens and STX have an amount, and non-fungible tokens have
+/// the hex-encoded values that moved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetMovementData {
+    pub principal: String,
+    pub asset_identifier: String,
+    #[serde(rename = "type")]
+    pub movement_type: String,
+    pub amount: Option<String>,
+    pub values: Option<Vec<String>>,
+}
+
+/// The data we return on POST /v2/transactions/simulate/{index-block-hash}.  If the
+/// post-conditions did not pass, the events and asset movements are what the transaction would
+/// have done had they passed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionSimulationData {
+    pub index_block_hash: String,
+    pub txid: String,
+    pub result: String,
+    pub result_repr: String,
+    pub post_conditions_passed: bool,
+    pub events: Vec<serde_json::Value>,
+    pub asset_movements: Vec<AssetMovementData>,
+    pub stx_burned: String,
+    pub execution_cost: ExecutionCost,
+    pub fee: u64,
+}
+
 #[derive(Debug, Clone, PartialEq, Copy, Hash)]
 #[repr(u8)]
 pub enum HttpVersion {
@@ -792,7 +901,15 @@ pub enum HttpRequestType {
     GetBlock(HttpRequestMetadata, BlockHeaderHash),
     GetMicroblocks(HttpRequestMetadata, BlockHeaderHash),
     GetMicroblocksUnconfirmed(HttpRequestMetadata, BlockHeaderHash, u16),
-    PostTransaction(HttpRequestMetadata, StacksTransaction)
+    GetSortition(HttpRequestMetadata, u64),
+    GetSortitions(HttpRequestMetadata, u64, u64),
+    GetHistoricalAccount(HttpRequestMetadata, BlockHeaderHash, PrincipalData),
+    GetHistoricalDataVar(HttpRequestMetadata, BlockHeaderHash, QualifiedContractIdentifier, ClarityName),
+    GetHistoricalMapEntry(HttpRequestMetadata, BlockHeaderHash, QualifiedContractIdentifier, ClarityName, Value),
+    PostHistoricalEval(HttpRequestMetadata, BlockHeaderHash, QualifiedContractIdentifier, String),
+    PostTransaction(HttpRequestMetadata, StacksTransaction),
+    PostFeeEstimate(HttpRequestMetadata, BlockHeaderHash, StacksTransaction),
+    PostSimulateTransaction(HttpRequestMetadata, BlockHeaderHash, StacksTransaction)
 }
 
 /// The fields that Actually Matter to http responses
@@ -842,7 +959,13 @@ pub enum HttpResponseType {
     BlockStream(HttpResponseMetadata),
     Microblocks(HttpResponseMetadata, Vec<StacksMicroblock>),
     MicroblockStream(HttpResponseMetadata),
+    SortitionInfo(HttpResponseMetadata, SortitionInfoData),
+    SortitionInfoList(HttpResponseMetadata, Vec<SortitionInfoData>),
+    HistoricalAccount(HttpResponseMetadata, HistoricalAccountData),
+    HistoricalValue(HttpResponseMetadata, HistoricalValueData),
     TransactionID(HttpResponseMetadata, Txid),
+    FeeEstimate(HttpResponseMetadata, FeeEstimateData),
+    TransactionSimulation(HttpResponseMetadata, TransactionSimulationData),
     
     // peer-given error responses
     BadRequest(HttpResponseMetadata, String),
@@ -1064,6 +1187,9 @@ pub const NUM_NEIGHBORS : usize = 32;
 // maximum number of unconfirmed microblocks can get streamed to us
 pub const MAX_MICROBLOCKS_UNCONFIRMED : usize = 1024;
 
+// maximum number of sortitions we'll return for a range of burn block heights
+pub const MAX_SORTITIONS_PER_RANGE : u64 = 100;
+
 #[cfg(test)]
 mod test {
     use super::*;
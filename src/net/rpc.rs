@@ -36,6 +36,14 @@ use net::HttpRequestMetadata;
 use net::HttpResponseMetadata;
 use net::PeerAddress;
 use net::PeerInfoData;
+use net::SortitionInfoData;
+use net::BurnSampleData;
+use net::UserBurnSupportData;
+use net::HistoricalAccountData;
+use net::HistoricalValueData;
+use net::FeeEstimateData;
+use net::TransactionSimulationData;
+use net::AssetMovementData;
 use net::NeighborAddress;
 use net::NeighborsData;
 use net::StacksHttp;
@@ -52,8 +60,11 @@ use burnchains::BurnchainHeaderHash;
 
 use chainstate::burn::db::burndb::BurnDB;
 use chainstate::burn::BlockHeaderHash;
+use chainstate::burn::VRFSeed;
 use chainstate::stacks::db::StacksChainState;
+use chainstate::stacks::db::StacksAccount;
 use chainstate::stacks::db::BlockStreamData;
+use chainstate::stacks::db::transactions::StacksTransactionOutcome;
 use chainstate::stacks::Error as chain_error;
 use chainstate::stacks::*;
 use burnchains::*;
@@ -64,6 +75,12 @@ use util::db::Error as db_error;
 use util::db::DBConn;
 use util::get_epoch_time_secs;
 
+use vm::types::{PrincipalData, QualifiedContractIdentifier, Value};
+use vm::representations::ClarityName;
+use vm::database::ClaritySerializable;
+use vm::costs::ExecutionCost;
+use vm::contexts::AssetMapEntry;
+
 use rand::prelude::*;
 use rand::thread_rng;
 
@@ -135,6 +152,195 @@ impl PeerInfoData {
     }
 }
 
+impl SortitionInfoData {
+    /// Load the sortition at the given burn block height on the canonical burn chain fork,
+    /// including its full burn distribution.
+    /// Returns Ok(None) if there is no snapshot at this height.
+    pub fn from_db(burndb: &mut BurnDB, burn_block_height: u64) -> Result<Option<SortitionInfoData>, net_error> {
+        let burnchain_tip = BurnDB::get_canonical_burn_chain_tip(burndb.conn()).map_err(net_error::DBError)?;
+        if burn_block_height < burndb.first_block_height || burn_block_height > burnchain_tip.block_height {
+            return Ok(None);
+        }
+
+        let first_block_height = burndb.first_block_height;
+        let mut tx = burndb.tx_begin().map_err(net_error::DBError)?;
+        let snapshot = match BurnDB::get_block_snapshot_in_fork(&mut tx, burn_block_height, &burnchain_tip.burn_header_hash).map_err(net_error::DBError)? {
+            Some(sn) => sn,
+            None => {
+                return Ok(None);
+            }
+        };
+
+        let burn_dist = BurnDB::get_burn_distribution(&mut tx, burn_block_height, &burnchain_tip.burn_header_hash).map_err(net_error::DBError)?;
+        let block_burn = BurnDB::get_block_burn_amount(&mut tx, burn_block_height, &burnchain_tip.burn_header_hash).map_err(net_error::DBError)?;
+
+        // reconstruct the point in the burn distribution that the sortition sampled, using the
+        // parent's sortition hash mixed with this block's hash, and the VRF seed of the last
+        // sortition winner before this block (see BlockSnapshot::make_snapshot)
+        let sortition_index =
+            if burn_dist.len() > 0 && burn_block_height > first_block_height {
+                let parent_snapshot = BurnDB::get_block_snapshot(&mut tx, &snapshot.parent_burn_header_hash)
+                    .map_err(net_error::DBError)?
+                    .ok_or(net_error::DBError(db_error::NotFoundError))?;
+
+                let last_sortition_snapshot = BurnDB::get_last_snapshot_with_sortition(&mut tx, burn_block_height - 1, &snapshot.parent_burn_header_hash)
+                    .map_err(net_error::DBError)?;
+
+                let VRF_seed =
+                    if last_sortition_snapshot.is_initial() {
+                        VRFSeed::initial()
+                    }
+                    else {
+                        BurnDB::get_block_commit(&mut tx, &last_sortition_snapshot.winning_block_txid, &last_sortition_snapshot.burn_header_hash)
+                            .map_err(net_error::DBError)?
+                            .ok_or(net_error::DBError(db_error::NotFoundError))?
+                            .new_seed
+                    };
+
+                let sortition_hash = parent_snapshot.sortition_hash.mix_burn_header(&snapshot.burn_header_hash);
+                Some(format!("{}", sortition_hash.mix_VRF_seed(&VRF_seed).to_uint256()))
+            }
+            else {
+                None
+            };
+
+        let distribution = burn_dist
+            .iter()
+            .map(|sample| {
+                let user_burns = sample.user_burns
+                    .iter()
+                    .map(|user_burn| UserBurnSupportData {
+                        txid: user_burn.txid.to_hex(),
+                        vtxindex: user_burn.vtxindex,
+                        burn_fee: user_burn.burn_fee
+                    })
+                    .collect();
+
+                BurnSampleData {
+                    txid: sample.candidate.txid.to_hex(),
+                    vtxindex: sample.candidate.vtxindex,
+                    block_header_hash: sample.candidate.block_header_hash.to_hex(),
+                    burn_fee: sample.candidate.burn_fee,
+                    total_burns: format!("{}", sample.burns),
+                    range_start: format!("{}", &sample.range_start),
+                    range_end: format!("{}", &sample.range_end),
+                    leader_key_block_height: sample.key.block_height,
+                    leader_key_vtxindex: sample.key.vtxindex,
+                    leader_public_key: sample.key.public_key.to_hex(),
+                    user_burns: user_burns,
+                    winner: snapshot.sortition && sample.candidate.txid == snapshot.winning_block_txid
+                }
+            })
+            .collect();
+
+        let (winning_block_txid, winning_stacks_block_hash) =
+            if snapshot.sortition && !snapshot.is_initial() {
+                (Some(snapshot.winning_block_txid.to_hex()), Some(snapshot.winning_stacks_block_hash.to_hex()))
+            }
+            else {
+                (None, None)
+            };
+
+        Ok(Some(SortitionInfoData {
+            burn_block_height: snapshot.block_height,
+            burn_header_hash: snapshot.burn_header_hash.to_hex(),
+            parent_burn_header_hash: snapshot.parent_burn_header_hash.to_hex(),
+            consensus_hash: snapshot.consensus_hash.clone(),
+            total_burn: snapshot.total_burn,
+            block_burn: block_burn,
+            sortition: snapshot.sortition,
+            sortition_hash: snapshot.sortition_hash.to_hex(),
+            sortition_index: sortition_index,
+            winning_block_txid: winning_block_txid,
+            winning_stacks_block_hash: winning_stacks_block_hash,
+            distribution: distribution
+        }))
+    }
+}
+
+impl HistoricalAccountData {
+    pub fn from_account(index_block_hash: &BlockHeaderHash, account: &StacksAccount) -> HistoricalAccountData {
+        HistoricalAccountData {
+            index_block_hash: index_block_hash.to_hex(),
+            principal: format!("{}", &account.principal),
+            balance: format!("{}", account.stx_balance),
+            nonce: account.nonce
+        }
+    }
+}
+
+impl HistoricalValueData {
+    pub fn from_value(index_block_hash: &BlockHeaderHash, value: &Value) -> HistoricalValueData {
+        HistoricalValueData {
+            index_block_hash: index_block_hash.to_hex(),
+            value: value.serialize(),
+            repr: format!("{}", value)
+        }
+    }
+}
+
+impl FeeEstimateData {
+    pub fn from_estimate(index_block_hash: &BlockHeaderHash, tx: &StacksTransaction, fee: u64, execution_cost: ExecutionCost) -> FeeEstimateData {
+        let mut tx_bytes = vec![];
+        tx.consensus_serialize(&mut tx_bytes).expect("FATAL: failed to serialize transaction to vec");
+        let tx_len = tx_bytes.len() as u64;
+
+        FeeEstimateData {
+            index_block_hash: index_block_hash.to_hex(),
+            fee_rate: tx.get_fee_rate(),
+            tx_len: tx_len,
+            fee_units: StacksChainState::get_tx_fee_units(tx_len, &execution_cost),
+            execution_cost: execution_cost,
+            fee: fee
+        }
+    }
+}
+
+impl TransactionSimulationData {
+    pub fn from_outcome(index_block_hash: &BlockHeaderHash, outcome: StacksTransactionOutcome) -> TransactionSimulationData {
+        let txid = outcome.receipt.transaction.txid();
+        let events = outcome.receipt.events
+            .iter()
+            .map(|event| event.json_serialize(&txid))
+            .collect();
+
+        let mut asset_movements = vec![];
+        for (principal, assets) in outcome.asset_map.to_table().into_iter() {
+            for (asset_identifier, entry) in assets.into_iter() {
+                let (asset_identifier, movement_type, amount, values) = match entry {
+                    AssetMapEntry::STX(amount) => ("STX".to_string(), "stx", Some(format!("{}", amount)), None),
+                    AssetMapEntry::Burn(amount) => ("STX".to_string(), "stx_burn", Some(format!("{}", amount)), None),
+                    AssetMapEntry::Token(amount) => (format!("{}", asset_identifier), "ft", Some(format!("{}", amount)), None),
+                    AssetMapEntry::Asset(values) => (format!("{}", asset_identifier), "nft", None, Some(values.iter().map(|v| v.serialize()).collect()))
+                };
+                asset_movements.push(AssetMovementData {
+                    principal: format!("{}", principal),
+                    asset_identifier: asset_identifier,
+                    movement_type: movement_type.to_string(),
+                    amount: amount,
+                    values: values
+                });
+            }
+        }
+
+        // the asset table is a hash map, so give the movements a stable order
+        asset_movements.sort_by(|m1, m2| (&m1.principal, &m1.asset_identifier, &m1.movement_type).cmp(&(&m2.principal, &m2.asset_identifier, &m2.movement_type)));
+
+        TransactionSimulationData {
+            index_block_hash: index_block_hash.to_hex(),
+            txid: txid.to_hex(),
+            result: outcome.receipt.result.serialize(),
+            result_repr: format!("{}", &outcome.receipt.result),
+            post_conditions_passed: outcome.post_conditions_passed,
+            events: events,
+            asset_movements: asset_movements,
+            stx_burned: format!("{}", outcome.receipt.stx_burned),
+            execution_cost: outcome.execution_cost,
+            fee: outcome.fee
+        }
+    }
+}
+
 impl ConversationHttp {
     pub fn new(network_id: u32, burnchain: &Burnchain, peer_host: PeerHost, conn_opts: &ConnectionOptions, conn_id: usize) -> ConversationHttp {
         ConversationHttp {
@@ -214,6 +420,149 @@ impl ConversationHttp {
         response.send(http, fd)
     }
 
+    /// Handle a GET sortition.
+    /// The response will be synchronously written to the given fd (so use a fd that can buffer!)
+    fn handle_getsortition<W: Write>(http: &mut StacksHttp, fd: &mut W, req: &HttpRequestType, burn_block_height: u64, burndb: &mut BurnDB) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::new(req.metadata().version, HttpResponseMetadata::make_request_id(), None, req.metadata().keep_alive);
+        match SortitionInfoData::from_db(burndb, burn_block_height) {
+            Ok(Some(sortition_info)) => {
+                let response = HttpResponseType::SortitionInfo(response_metadata, sortition_info);
+                response.send(http, fd)
+            },
+            Ok(None) => {
+                let response = HttpResponseType::NotFound(response_metadata, format!("No sortition at burn block height {}", burn_block_height));
+                response.send(http, fd)
+            },
+            Err(e) => {
+                warn!("Failed to get sortition {:?}: {:?}", req, &e);
+                let response = HttpResponseType::ServerError(response_metadata, format!("Failed to query sortition at burn block height {}", burn_block_height));
+                response.send(http, fd)
+            }
+        }
+    }
+
+    /// Handle a GET sortitions over an inclusive range of burn block heights.
+    /// Heights without a snapshot on the canonical burn chain fork are left out; if none of them
+    /// have one, the response is a 404.
+    /// The response will be synchronously written to the given fd (so use a fd that can buffer!)
+    fn handle_getsortitions<W: Write>(http: &mut StacksHttp, fd: &mut W, req: &HttpRequestType, start_height: u64, end_height: u64, burndb: &mut BurnDB) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::new(req.metadata().version, HttpResponseMetadata::make_request_id(), None, req.metadata().keep_alive);
+        let mut sortition_infos = vec![];
+        for burn_block_height in start_height..(end_height + 1) {
+            match SortitionInfoData::from_db(burndb, burn_block_height) {
+                Ok(Some(sortition_info)) => {
+                    sortition_infos.push(sortition_info);
+                },
+                Ok(None) => {
+                    continue;
+                },
+                Err(e) => {
+                    warn!("Failed to get sortitions {:?}: {:?}", req, &e);
+                    let response = HttpResponseType::ServerError(response_metadata, format!("Failed to query sortition at burn block height {}", burn_block_height));
+                    return response.send(http, fd);
+                }
+            }
+        }
+
+        let response =
+            if sortition_infos.len() == 0 {
+                HttpResponseType::NotFound(response_metadata, format!("No sortitions at burn block heights {} through {}", start_height, end_height))
+            }
+            else {
+                HttpResponseType::SortitionInfoList(response_metadata, sortition_infos)
+            };
+        response.send(http, fd)
+    }
+
+    /// Turn a failed historical state query into an error response
+    fn historical_query_error(response_metadata: HttpResponseMetadata, req: &HttpRequestType, index_block_hash: &BlockHeaderHash, e: chain_error) -> HttpResponseType {
+        match e {
+            chain_error::NoSuchBlockError => {
+                HttpResponseType::NotFound(response_metadata, format!("No such processed block {}", index_block_hash.to_hex()))
+            },
+            chain_error::PrunedBlockError => {
+                HttpResponseType::Error(response_metadata, 410, format!("State as of block {} has been pruned", index_block_hash.to_hex()))
+            },
+            chain_error::ClarityError(ref clarity_e) => {
+                HttpResponseType::BadRequest(response_metadata, format!("Clarity error: {}", clarity_e))
+            },
+            _ => {
+                warn!("Failed to query historical state {:?}: {:?}", req, &e);
+                HttpResponseType::ServerError(response_metadata, format!("Failed to query state as of block {}", index_block_hash.to_hex()))
+            }
+        }
+    }
+
+    /// Send back a Clarity value read or computed as of a past block
+    fn send_historical_value<W: Write>(http: &mut StacksHttp, fd: &mut W, req: &HttpRequestType, index_block_hash: &BlockHeaderHash, result: Result<Value, chain_error>) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::new(req.metadata().version, HttpResponseMetadata::make_request_id(), None, req.metadata().keep_alive);
+        let response = match result {
+            Ok(value) => HttpResponseType::HistoricalValue(response_metadata, HistoricalValueData::from_value(index_block_hash, &value)),
+            Err(e) => ConversationHttp::historical_query_error(response_metadata, req, index_block_hash, e)
+        };
+        response.send(http, fd)
+    }
+
+    /// Handle a GET historical account.
+    /// The response will be synchronously written to the given fd (so use a fd that can buffer!)
+    fn handle_gethistorical_account<W: Write>(http: &mut StacksHttp, fd: &mut W, req: &HttpRequestType, index_block_hash: &BlockHeaderHash, principal: &PrincipalData, chainstate: &mut StacksChainState) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::new(req.metadata().version, HttpResponseMetadata::make_request_id(), None, req.metadata().keep_alive);
+        let response = match chainstate.get_account_at(index_block_hash, principal) {
+            Ok(account) => HttpResponseType::HistoricalAccount(response_metadata, HistoricalAccountData::from_account(index_block_hash, &account)),
+            Err(e) => ConversationHttp::historical_query_error(response_metadata, req, index_block_hash, e)
+        };
+        response.send(http, fd)
+    }
+
+    /// Handle a GET historical data var.
+    /// The response will be synchronously written to the given fd (so use a fd that can buffer!)
+    fn handle_gethistorical_data_var<W: Write>(http: &mut StacksHttp, fd: &mut W, req: &HttpRequestType, index_block_hash: &BlockHeaderHash, contract_id: &QualifiedContractIdentifier, var_name: &ClarityName, chainstate: &mut StacksChainState) -> Result<(), net_error> {
+        let result = chainstate.get_data_var_at(index_block_hash, contract_id, var_name.as_str());
+        ConversationHttp::send_historical_value(http, fd, req, index_block_hash, result)
+    }
+
+    /// Handle a GET historical map entry.
+    /// The response will be synchronously written to the given fd (so use a fd that can buffer!)
+    fn handle_gethistorical_map_entry<W: Write>(http: &mut StacksHttp, fd: &mut W, req: &HttpRequestType, index_block_hash: &BlockHeaderHash, contract_id: &QualifiedContractIdentifier, map_name: &ClarityName, key: &Value, chainstate: &mut StacksChainState) -> Result<(), net_error> {
+        let result = chainstate.get_map_entry_at(index_block_hash, contract_id, map_name.as_str(), key);
+        ConversationHttp::send_historical_value(http, fd, req, index_block_hash, result)
+    }
+
+    /// Handle a POST historical read-only evaluation.
+    /// The response will be synchronously written to the given fd (so use a fd that can buffer!)
+    fn handle_posthistorical_eval<W: Write>(http: &mut StacksHttp, fd: &mut W, req: &HttpRequestType, index_block_hash: &BlockHeaderHash, contract_id: &QualifiedContractIdentifier, code: &str, chainstate: &mut StacksChainState) -> Result<(), net_error> {
+        let result = chainstate.eval_read_only_at(index_block_hash, contract_id, code);
+        ConversationHttp::send_historical_value(http, fd, req, index_block_hash, result)
+    }
+
+    /// Handle a POST fee estimate, by dry-running the (possibly unsigned) transaction on top of
+    /// the given block.
+    /// The response will be synchronously written to the given fd (so use a fd that can buffer!)
+    fn handle_postfee_estimate<W: Write>(http: &mut StacksHttp, fd: &mut W, req: &HttpRequestType, index_block_hash: &BlockHeaderHash, tx: &StacksTransaction, chainstate: &mut StacksChainState) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::new(req.metadata().version, HttpResponseMetadata::make_request_id(), None, req.metadata().keep_alive);
+        let response = match chainstate.estimate_transaction_fee(index_block_hash, tx) {
+            Ok((fee, execution_cost)) => HttpResponseType::FeeEstimate(response_metadata, FeeEstimateData::from_estimate(index_block_hash, tx, fee, execution_cost)),
+            Err(chain_error::InvalidFee) => HttpResponseType::BadRequest(response_metadata, "Transaction payer cannot afford the fee".to_string()),
+            Err(chain_error::InvalidStacksTransaction(msg)) => HttpResponseType::BadRequest(response_metadata, format!("Invalid transaction: {}", msg)),
+            Err(e) => ConversationHttp::historical_query_error(response_metadata, req, index_block_hash, e)
+        };
+        response.send(http, fd)
+    }
+
+    /// Handle a POST transaction simulation, by dry-running the (possibly unsigned) transaction on
+    /// top of the given block and reporting everything it did.
+    /// The response will be synchronously written to the given fd (so use a fd that can buffer!)
+    fn handle_postsimulate_transaction<W: Write>(http: &mut StacksHttp, fd: &mut W, req: &HttpRequestType, index_block_hash: &BlockHeaderHash, tx: &StacksTransaction, chainstate: &mut StacksChainState) -> Result<(), net_error> {
+        let response_metadata = HttpResponseMetadata::new(req.metadata().version, HttpResponseMetadata::make_request_id(), None, req.metadata().keep_alive);
+        let response = match chainstate.simulate_transaction(index_block_hash, tx) {
+            Ok(outcome) => HttpResponseType::TransactionSimulation(response_metadata, TransactionSimulationData::from_outcome(index_block_hash, outcome)),
+            Err(chain_error::InvalidFee) => HttpResponseType::BadRequest(response_metadata, "Transaction payer cannot afford the fee".to_string()),
+            Err(chain_error::InvalidStacksTransaction(msg)) => HttpResponseType::BadRequest(response_metadata, format!("Invalid transaction: {}", msg)),
+            Err(e) => ConversationHttp::historical_query_error(response_metadata, req, index_block_hash, e)
+        };
+        response.send(http, fd)
+    }
+
     /// Handle a GET block.  Start streaming the reply.
     /// The response's preamble (but not the block data) will be synchronously written to the fd
     /// (so use a fd that can buffer!)
@@ -327,8 +676,40 @@ impl ConversationHttp {
             HttpRequestType::GetMicroblocksUnconfirmed(ref _md, ref index_anchor_block_hash, ref min_seq) => {
                 ConversationHttp::handle_getmicroblocks_unconfirmed(&mut self.connection.protocol, &mut reply, &req, index_anchor_block_hash, *min_seq, chainstate)?
             },
+            HttpRequestType::GetSortition(ref _md, ref burn_block_height) => {
+                ConversationHttp::handle_getsortition(&mut self.connection.protocol, &mut reply, &req, *burn_block_height, burndb)?;
+                None
+            },
+            HttpRequestType::GetSortitions(ref _md, ref start_height, ref end_height) => {
+                ConversationHttp::handle_getsortitions(&mut self.connection.protocol, &mut reply, &req, *start_height, *end_height, burndb)?;
+                None
+            },
+            HttpRequestType::GetHistoricalAccount(ref _md, ref index_block_hash, ref principal) => {
+                ConversationHttp::handle_gethistorical_account(&mut self.connection.protocol, &mut reply, &req, index_block_hash, principal, chainstate)?;
+                None
+            },
+            HttpRequestType::GetHistoricalDataVar(ref _md, ref index_block_hash, ref contract_id, ref var_name) => {
+                ConversationHttp::handle_gethistorical_data_var(&mut self.connection.protocol, &mut reply, &req, index_block_hash, contract_id, var_name, chainstate)?;
+                None
+            },
+            HttpRequestType::GetHistoricalMapEntry(ref _md, ref index_block_hash, ref contract_id, ref map_name, ref key) => {
+                ConversationHttp::handle_gethistorical_map_entry(&mut self.connection.protocol, &mut reply, &req, index_block_hash, contract_id, map_name, key, chainstate)?;
+                None
+            },
+            HttpRequestType::PostHistoricalEval(ref _md, ref index_block_hash, ref contract_id, ref code) => {
+                ConversationHttp::handle_posthistorical_eval(&mut self.connection.protocol, &mut reply, &req, index_block_hash, contract_id, code, chainstate)?;
+                None
+            },
             HttpRequestType::PostTransaction(_md, _tx) => {
                 panic!("Not implemented");
+            },
+            HttpRequestType::PostFeeEstimate(ref _md, ref index_block_hash, ref tx) => {
+                ConversationHttp::handle_postfee_estimate(&mut self.connection.protocol, &mut reply, &req, index_block_hash, tx, chainstate)?;
+                None
+            },
+            HttpRequestType::PostSimulateTransaction(ref _md, ref index_block_hash, ref tx) => {
+                ConversationHttp::handle_postsimulate_transaction(&mut self.connection.protocol, &mut reply, &req, index_block_hash, tx, chainstate)?;
+                None
             }
         };
 
@@ -602,6 +983,36 @@ impl ConversationHttp {
     pub fn new_getmicroblocks_unconfirmed(&self, anchored_index_block_hash: BlockHeaderHash, min_seq: u16) -> HttpRequestType {
         HttpRequestType::GetMicroblocksUnconfirmed(HttpRequestMetadata::from_host(self.peer_host.clone()), anchored_index_block_hash, min_seq)
     }
+
+    /// Make a new get-sortition request to this endpoint
+    pub fn new_getsortition(&self, burn_block_height: u64) -> HttpRequestType {
+        HttpRequestType::GetSortition(HttpRequestMetadata::from_host(self.peer_host.clone()), burn_block_height)
+    }
+
+    /// Make a new get-sortitions request for an inclusive range of burn block heights
+    pub fn new_getsortitions(&self, start_height: u64, end_height: u64) -> HttpRequestType {
+        HttpRequestType::GetSortitions(HttpRequestMetadata::from_host(self.peer_host.clone()), start_height, end_height)
+    }
+
+    /// Make a new get-historical-account request to this endpoint
+    pub fn new_gethistorical_account(&self, index_block_hash: BlockHeaderHash, principal: PrincipalData) -> HttpRequestType {
+        HttpRequestType::GetHistoricalAccount(HttpRequestMetadata::from_host(self.peer_host.clone()), index_block_hash, principal)
+    }
+
+    /// Make a new get-historical-data-var request to this endpoint
+    pub fn new_gethistorical_data_var(&self, index_block_hash: BlockHeaderHash, contract_id: QualifiedContractIdentifier, var_name: ClarityName) -> HttpRequestType {
+        HttpRequestType::GetHistoricalDataVar(HttpRequestMetadata::from_host(self.peer_host.clone()), index_block_hash, contract_id, var_name)
+    }
+
+    /// Make a new get-historical-map-entry request to this endpoint
+    pub fn new_gethistorical_map_entry(&self, index_block_hash: BlockHeaderHash, contract_id: QualifiedContractIdentifier, map_name: ClarityName, key: Value) -> HttpRequestType {
+        HttpRequestType::GetHistoricalMapEntry(HttpRequestMetadata::from_host(self.peer_host.clone()), index_block_hash, contract_id, map_name, key)
+    }
+
+    /// Make a new post-historical-eval request to this endpoint
+    pub fn new_posthistorical_eval(&self, index_block_hash: BlockHeaderHash, contract_id: QualifiedContractIdentifier, code: String) -> HttpRequestType {
+        HttpRequestType::PostHistoricalEval(HttpRequestMetadata::from_host(self.peer_host.clone()), index_block_hash, contract_id, code)
+    }
 }
 
 #[cfg(test)]
@@ -629,6 +1040,14 @@ mod test {
    
     use util::pipe::*;
     use util::get_epoch_time_secs;
+    use util::uint::Uint256;
+
+    use burnchains::test::{TestMinerFactory, TestBurnchainFork};
+    use address::AddressHashMode;
+    use chainstate::stacks::db::snapshot::test::append_block_at;
+    use chainstate::stacks::db::{STACKS_BOOT_CODE_CONTRACT_ADDRESS, BOOT_CODE_MINER_CONTRACT_NAME};
+
+    use vm::types::StandardPrincipalData;
 
     fn convo_send_recv(sender: &mut ConversationHttp, sender_chainstate: &mut StacksChainState, receiver: &mut ConversationHttp, receiver_chainstate: &mut StacksChainState) -> () {
         let (mut pipe_read, mut pipe_write) = Pipe::new();
@@ -979,5 +1398,288 @@ mod test {
                     }
                 });
     }
-}
+    
+    #[test]
+    fn test_rpc_getsortition() {
+        let server_sortition_info = RefCell::new(None);
+        test_rpc("test_rpc_getsortition", 40090, 40091, 50090, 50091,
+                 |ref mut peer_client, ref mut convo_client, ref mut peer_server, ref mut convo_server| {
+                     let first_block_height = peer_server.config.burnchain.first_block_height;
+                     let sortition_info = SortitionInfoData::from_db(peer_server.burndb.as_mut().unwrap(), first_block_height).unwrap();
+                     assert!(sortition_info.is_some());
+                     *server_sortition_info.borrow_mut() = sortition_info;
+
+                     convo_client.new_getsortition(first_block_height)
+                 },
+                 |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                     let req_md = http_request.metadata().clone();
+                     match http_response {
+                        HttpResponseType::SortitionInfo(response_md, sortition_info) => {
+                            assert_eq!(Some((*sortition_info).clone()), *server_sortition_info.borrow());
+                            assert_eq!(sortition_info.burn_block_height, peer_server.config.burnchain.first_block_height);
+                            assert_eq!(sortition_info.distribution.len(), 0);
+                            assert!(sortition_info.winning_block_txid.is_none());
+                            true
+                        },
+                        _ => {
+                            error!("Invalid response: {:?}", &http_response);
+                            false
+                        }
+                    }
+                 });
+    }
+    
+    #[test]
+    fn test_rpc_getsortition_with_winner() {
+        let server_sortition_info = RefCell::new(None);
+        let expected_sortition = RefCell::new(None);
+        test_rpc("test_rpc_getsortition_with_winner", 40140, 40141, 50140, 50141,
+                 |ref mut peer_client, ref mut convo_client, ref mut peer_server, ref mut convo_server| {
+                     let burnchain = peer_server.config.burnchain.clone();
+
+                     // the test peer's burn chain is made of placeholder snapshots whose sortitions
+                     // have no block commits, so serve from a burn chain with only the first block
+                     let burndb_path = format!("/tmp/blockstack-test-peer-{}-{}/burn-with-winner", &peer_server.config.test_name, peer_server.config.server_port);
+                     peer_server.burndb = Some(BurnDB::connect(&burndb_path, burnchain.first_block_height, &burnchain.first_block_hash, get_epoch_time_secs(), true).unwrap());
+                     let burndb = peer_server.burndb.as_mut().unwrap();
+
+                     let mut miner_factory = TestMinerFactory::new();
+                     let mut miner_1 = miner_factory.next_miner(&burnchain, 1, 1, AddressHashMode::SerializeP2PKH);
+                     let mut miner_2 = miner_factory.next_miner(&burnchain, 1, 1, AddressHashMode::SerializeP2PKH);
+
+                     let first_snapshot = BurnDB::get_first_block_snapshot(burndb.conn()).unwrap();
+                     let mut fork = TestBurnchainFork::new(first_snapshot.block_height, &first_snapshot.burn_header_hash, &first_snapshot.index_root, 0);
+
+                     // both miners register a key...
+                     let mut burn_block = {
+                         let mut tx = burndb.tx_begin().unwrap();
+                         fork.next_block(&mut tx)
+                     };
+                     let key_1 = burn_block.add_leader_key_register(&mut miner_1);
+                     let key_2 = burn_block.add_leader_key_register(&mut miner_2);
+                     fork.append_block(burn_block);
+                     fork.mine_pending_blocks(burndb, &burnchain);
+
+                     // ...and then commit to a block, burning different amounts
+                     let mut burn_block = {
+                         let mut tx = burndb.tx_begin().unwrap();
+                         fork.next_block(&mut tx)
+                     };
+                     let (commit_1, commit_2) = {
+                         let mut tx = burndb.tx_begin().unwrap();
+                         let commit_1 = burn_block.add_leader_block_commit(&mut tx, &mut miner_1, &BlockHeaderHash([0x11; 32]), 1000, &key_1, None, None);
+                         let commit_2 = burn_block.add_leader_block_commit(&mut tx, &mut miner_2, &BlockHeaderHash([0x22; 32]), 3000, &key_2, None, None);
+                         (commit_1, commit_2)
+                     };
+                     fork.append_block(burn_block);
+                     let sortition_snapshot = fork.mine_pending_blocks(burndb, &burnchain);
+
+                     assert!(sortition_snapshot.sortition);
+                     assert!(sortition_snapshot.winning_block_txid == commit_1.txid || sortition_snapshot.winning_block_txid == commit_2.txid);
+
+                     // this is the first sortition, so the VRF seed is the initial one
+                     let parent_snapshot = BurnDB::get_block_snapshot(burndb.conn(), &sortition_snapshot.parent_burn_header_hash).unwrap().unwrap();
+                     let sortition_index = parent_snapshot.sortition_hash.mix_burn_header(&sortition_snapshot.burn_header_hash).mix_VRF_seed(&VRFSeed::initial()).to_uint256();
+
+                     *expected_sortition.borrow_mut() = Some((sortition_snapshot.clone(), format!("{}", sortition_index)));
+                     *server_sortition_info.borrow_mut() = SortitionInfoData::from_db(burndb, sortition_snapshot.block_height).unwrap();
+
+                     convo_client.new_getsortition(sortition_snapshot.block_height)
+                 },
+                 |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                     let (sortition_snapshot, sortition_index) = expected_sortition.borrow().clone().unwrap();
+                     match http_response {
+                        HttpResponseType::SortitionInfo(response_md, sortition_info) => {
+                            assert_eq!(Some((*sortition_info).clone()), *server_sortition_info.borrow());
+                            assert_eq!(sortition_info.burn_block_height, sortition_snapshot.block_height);
+                            assert_eq!(sortition_info.winning_block_txid, Some(sortition_snapshot.winning_block_txid.to_hex()));
+                            assert_eq!(sortition_info.sortition_index, Some(sortition_index.clone()));
+                            assert_eq!(sortition_info.distribution.len(), 2);
+
+                            let mut total_burns : Vec<u128> = sortition_info.distribution.iter().map(|sample| sample.total_burns.parse::<u128>().unwrap()).collect();
+                            total_burns.sort();
+                            assert_eq!(total_burns, vec![1000, 3000]);
+
+                            // the sortition index falls in the winner's range, and only the winner's
+                            let winners : Vec<&BurnSampleData> = sortition_info.distribution.iter().filter(|sample| sample.winner).collect();
+                            assert_eq!(winners.len(), 1);
+                            assert_eq!(winners[0].txid, sortition_snapshot.winning_block_txid.to_hex());
+                            let sortition_index = parse_uint256(&sortition_index);
+                            for sample in sortition_info.distribution.iter() {
+                                let in_range = parse_uint256(&sample.range_start) <= sortition_index && sortition_index < parse_uint256(&sample.range_end);
+                                assert_eq!(in_range, sample.winner);
+                            }
+                            true
+                        },
+                        _ => {
+                            error!("Invalid response: {:?}", &http_response);
+                            false
+                        }
+                    }
+                 });
+    }
+    
+    /// Parse a 256-bit number in the "0x"-prefixed, big-endian hex form that Uint256 prints
+    fn parse_uint256(s: &str) -> Uint256 {
+        assert!(s.starts_with("0x") && s.len() == 66);
+        let mut words = [0u64; 4];
+        for i in 0..4 {
+            words[3 - i] = u64::from_str_radix(&s[(2 + 16 * i)..(2 + 16 * (i + 1))], 16).unwrap();
+        }
+        Uint256(words)
+    }
+
+    #[test]
+    fn test_rpc_getsortitions() {
+        let server_sortition_infos = RefCell::new(vec![]);
+        test_rpc("test_rpc_getsortitions", 40150, 40151, 50150, 50151,
+                 |ref mut peer_client, ref mut convo_client, ref mut peer_server, ref mut convo_server| {
+                     // the range runs past the burn chain tip
+                     let burndb = peer_server.burndb.as_mut().unwrap();
+                     let tip_height = BurnDB::get_canonical_burn_chain_tip(burndb.conn()).unwrap().block_height;
+                     for burn_block_height in (tip_height - 5)..(tip_height + 1) {
+                         let sortition_info = SortitionInfoData::from_db(burndb, burn_block_height).unwrap().unwrap();
+                         server_sortition_infos.borrow_mut().push(sortition_info);
+                     }
+
+                     convo_client.new_getsortitions(tip_height - 5, tip_height + 5)
+                 },
+                 |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                     let req_md = http_request.metadata().clone();
+                     match http_response {
+                        HttpResponseType::SortitionInfoList(response_md, sortition_infos) => {
+                            assert_eq!(*sortition_infos, *server_sortition_infos.borrow());
+                            assert_eq!(sortition_infos.len(), 6);
+                            for i in 1..sortition_infos.len() {
+                                assert_eq!(sortition_infos[i].burn_block_height, sortition_infos[i - 1].burn_block_height + 1);
+                            }
+                            true
+                        },
+                        _ => {
+                            error!("Invalid response: {:?}", &http_response);
+                            false
+                        }
+                    }
+                 });
+    }
 
+    #[test]
+    fn test_rpc_missing_getsortitions() {
+        test_rpc("test_rpc_missing_getsortitions", 40160, 40161, 50160, 50161,
+                 |ref mut peer_client, ref mut convo_client, ref mut peer_server, ref mut convo_server| {
+                     // no such burn blocks yet
+                     let start_height = peer_server.config.burnchain.first_block_height + 1000;
+                     convo_client.new_getsortitions(start_height, start_height + 10)
+                 },
+                 |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                     let req_md = http_request.metadata().clone();
+                     match http_response {
+                        HttpResponseType::NotFound(response_md, msg) => true,
+                        _ => {
+                           error!("Invalid response: {:?}", &http_response);
+                           false
+                        }
+                    }
+                });
+    }
+
+    #[test]
+    fn test_rpc_missing_getsortition() {
+        test_rpc("test_rpc_missing_getsortition", 40100, 40101, 50100, 50101,
+                 |ref mut peer_client, ref mut convo_client, ref mut peer_server, ref mut convo_server| {
+                     // no such burn block yet
+                     let burn_block_height = peer_server.config.burnchain.first_block_height + 1000;
+                     convo_client.new_getsortition(burn_block_height)
+                 },
+                 |ref http_request, ref http_response, ref mut peer_client, ref mut peer_server| {
+                     let req_md = http_request.metadata().clone();
+                     match http_response {
+                        HttpResponseType::NotFound(response_md, msg) => true,
+                        _ => {
+                           error!("Invalid response: {:?}", &http_response);
+                           false
+                        }
+                    }
+                });
+    }
+
+    #[test]
+    fn test_rpc_missing_gethistorical_account() {
+        test_rpc("test_rpc_missing_gethistorical_account", 40110, 40111, 50110, 50111,
+                 |_, ref mut convo_client, _, _| {
+                     // no such processed block
+                     let principal = PrincipalData::Standard(StandardPrincipalData(0x1a, [0x22; 20]));
+                     convo_client.new_gethistorical_account(BlockHeaderHash([0x11; 32]), principal)
+                 },
+                 |_, ref http_response, _, _| {
+                     match http_response {
+                        HttpResponseType::NotFound(_, _) => true,
+                        _ => {
+                           error!("Invalid response: {:?}", &http_response);
+                           false
+                        }
+                    }
+                });
+    }
+
+    #[test]
+    fn test_rpc_gethistorical_account() {
+        let index_block_hash_cell = RefCell::new(None);
+
+        test_rpc("test_rpc_gethistorical_account", 40120, 40121, 50120, 50121,
+                 |_, ref mut convo_client, ref mut peer_server, _| {
+                     // have the "server" peer process two blocks that credit the same account
+                     let chainstate = peer_server.chainstate.as_mut().unwrap();
+                     let first = append_block_at(chainstate, None, &BurnchainHeaderHash([0x21; 32]), 55);
+                     let second = append_block_at(chainstate, Some(&first), &BurnchainHeaderHash([0x22; 32]), 55);
+                     assert_eq!(chainstate.get_account_at(&second.index_block_hash(), &PrincipalData::Standard(StandardPrincipalData(0x16, [55; 20]))).unwrap().stx_balance, 110);
+
+                     *index_block_hash_cell.borrow_mut() = Some(first.index_block_hash());
+
+                     // ask for the balance as of the first block
+                     let principal = PrincipalData::Standard(StandardPrincipalData(0x16, [55; 20]));
+                     convo_client.new_gethistorical_account(first.index_block_hash(), principal)
+                 },
+                 |_, ref http_response, _, _| {
+                     match http_response {
+                        HttpResponseType::HistoricalAccount(_, account) => {
+                            assert_eq!(account.index_block_hash, index_block_hash_cell.borrow().as_ref().unwrap().to_hex());
+                            assert_eq!(account.balance, "55");
+                            assert_eq!(account.nonce, 0);
+                            true
+                        },
+                        _ => {
+                           error!("Invalid response: {:?}", &http_response);
+                           false
+                        }
+                    }
+                });
+    }
+
+    #[test]
+    fn test_rpc_posthistorical_eval() {
+        test_rpc("test_rpc_posthistorical_eval", 40130, 40131, 50130, 50131,
+                 |_, ref mut convo_client, ref mut peer_server, _| {
+                     let chainstate = peer_server.chainstate.as_mut().unwrap();
+                     let first = append_block_at(chainstate, None, &BurnchainHeaderHash([0x31; 32]), 77);
+
+                     // the miner-rewards map has no entry for this principal as of the block
+                     let boot_contract = QualifiedContractIdentifier::parse(&format!("{}.{}", STACKS_BOOT_CODE_CONTRACT_ADDRESS, BOOT_CODE_MINER_CONTRACT_NAME)).unwrap();
+                     let principal = PrincipalData::Standard(StandardPrincipalData(0x16, [77; 20]));
+                     let code = format!("(get-participant-info '{})", &principal);
+                     convo_client.new_posthistorical_eval(first.index_block_hash(), boot_contract, code)
+                 },
+                 |_, ref http_response, _, _| {
+                     match http_response {
+                        HttpResponseType::HistoricalValue(_, value) => {
+                            assert_eq!(value.repr, "(tuple (authorized false) (available u0))");
+                            true
+                        },
+                        _ => {
+                           error!("Invalid response: {:?}", &http_response);
+                           false
+                        }
+                    }
+                });
+    }
+}
@@ -1,5 +1,6 @@
 use util::hash::{to_hex};
 use burnchains::Address;
+use chainstate::stacks::index::storage::TrieStorageLayout;
 use vm::types::{PrincipalData, QualifiedContractIdentifier, AssetIdentifier} ;
 use rand::RngCore;
 use std::convert::TryInto;
@@ -51,6 +52,10 @@ impl Config {
                 NodeConfig {
                     name: node.name.unwrap_or(default_node_config.name),
                     working_dir: node.working_dir.unwrap_or(default_node_config.working_dir),
+                    prune_depth: node.prune_depth.or(default_node_config.prune_depth),
+                    archive: node.archive.unwrap_or(default_node_config.archive),
+                    packed_storage: node.packed_storage.unwrap_or(default_node_config.packed_storage),
+                    microblock_frequency: node.microblock_frequency.unwrap_or(default_node_config.microblock_frequency),
                 }
             },
             None => default_node_config
@@ -176,6 +181,17 @@ pub struct BurnchainConfigFile {
 pub struct NodeConfig {
     pub name: String,
     pub working_dir: String,
+    /// If set, prune forks that branch off more than this many blocks below the chain tip
+    pub prune_depth: Option<u64>,
+    /// If set, keep the state of every processed block (and never prune)
+    pub archive: bool,
+    /// If set, new MARFs append their tries into a few large blob files instead of writing one
+    /// file per block
+    pub packed_storage: bool,
+    /// How often (in milliseconds) a leader cuts a microblock during its tenure.  0 (the default)
+    /// disables microblock mining; transactions that can only be streamed then wait in the
+    /// mempool.
+    pub microblock_frequency: u64,
 }
 
 impl NodeConfig {
@@ -190,18 +206,35 @@ impl NodeConfig {
         NodeConfig {
             name: name.to_string(),
             working_dir: format!("/tmp/{}", testnet_id),
+            prune_depth: None,
+            archive: false,
+            packed_storage: false,
+            microblock_frequency: 0,
         }
     }
 
     pub fn get_default_mempool_path(&self) -> String {
         format!("{}/mempool/", self.working_dir)
     }
+
+    pub fn get_storage_layout(&self) -> TrieStorageLayout {
+        if self.packed_storage {
+            TrieStorageLayout::Packed
+        }
+        else {
+            TrieStorageLayout::FilePerBlock
+        }
+    }
 }
 
 #[derive(Clone, Default, Deserialize)]
 pub struct NodeConfigFile {
     pub name: Option<String>,
     pub working_dir: Option<String>,
+    pub prune_depth: Option<u64>,
+    pub archive: Option<bool>,
+    pub packed_storage: Option<bool>,
+    pub microblock_frequency: Option<u64>,
 }
 
 #[derive(Clone, Default, Deserialize)]
@@ -5,6 +5,8 @@ use std::io;
 use std::io::Read;
 use std::io::BufReader;
 use std::io::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use rand::RngCore;
 use util::hash::{to_hex};
 
@@ -21,9 +23,15 @@ pub trait MemPool {
     fn archive_tx(&mut self, tx: StacksTransaction);
 }
 
+/// How many times a transaction that couldn't be mined goes back into the mempool before it's
+/// dropped -- e.g. one stuck behind a nonce gap that never gets filled.
+pub const MAX_TX_REQUEUES: u32 = 10;
+
 #[derive(Clone)]
 pub struct MemPoolFS {
     path: String,
+    // how many times each transaction has been requeued, shared by every tenure's copy
+    requeues: Arc<Mutex<HashMap<Txid, u32>>>,
 }
 
 impl MemPoolFS {
@@ -35,6 +43,41 @@ impl MemPoolFS {
 
         Self {
             path: path.to_string(),
+            requeues: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Put a transaction that couldn't be mined back in the mempool, unless it has already been
+    /// put back MAX_TX_REQUEUES times.  Returns whether or not it was requeued.
+    pub fn requeue(&self, tx: &StacksTransaction) -> bool {
+        let txid = tx.txid();
+        {
+            let mut requeues = self.requeues.lock().unwrap();
+            let count = requeues.entry(txid.clone()).or_insert(0);
+            if *count >= MAX_TX_REQUEUES {
+                requeues.remove(&txid);
+                return false;
+            }
+            *count += 1;
+        }
+
+        self.put_back(tx);
+        true
+    }
+
+    /// Put a transaction back in the mempool as-is, without counting it as a requeue -- e.g. one
+    /// that this node can't mine at all, but that shouldn't be lost.
+    pub fn put_back(&self, tx: &StacksTransaction) {
+        let mut tx_bytes = vec![];
+        tx.consensus_serialize(&mut tx_bytes).expect("FATAL: failed to serialize transaction");
+        self.submit(tx_bytes);
+    }
+
+    /// Stop counting requeues of transactions that got mined.
+    pub fn forget_requeues(&self, txs: &Vec<StacksTransaction>) {
+        let mut requeues = self.requeues.lock().unwrap();
+        for tx in txs.iter() {
+            requeues.remove(&tx.txid());
         }
     }
 }
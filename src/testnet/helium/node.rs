@@ -9,7 +9,7 @@ use burnchains::{Burnchain, BurnchainHeaderHash, Txid};
 use chainstate::burn::db::burndb::{BurnDB};
 use chainstate::stacks::db::{StacksChainState, StacksHeaderInfo, ClarityTx};
 use chainstate::stacks::events::StacksTransactionReceipt;
-use chainstate::stacks::{StacksPrivateKey, StacksBlock, TransactionPayload, StacksWorkScore, StacksAddress, StacksTransactionSigner, StacksTransaction, TransactionVersion, StacksMicroblock, CoinbasePayload, StacksBlockBuilder, TransactionAnchorMode};
+use chainstate::stacks::{StacksPrivateKey, StacksBlock, TransactionPayload, StacksWorkScore, StacksAddress, StacksTransactionSigner, StacksTransaction, TransactionVersion, CoinbasePayload, StacksBlockBuilder, TransactionAnchorMode};
 use chainstate::burn::operations::{BlockstackOperationType, LeaderKeyRegisterOp, LeaderBlockCommitOp};
 use chainstate::burn::{ConsensusHash, SortitionHash, BlockSnapshot, VRFSeed, BlockHeaderHash};
 use net::{StacksMessageType, StacksMessageCodec};
@@ -83,15 +83,17 @@ impl Node {
 
         let initial_balances = config.initial_balances.iter().map(|e| (e.address.clone(), e.amount)).collect();
 
-        let chain_state = match StacksChainState::open_and_exec(
+        let mut chain_state = match StacksChainState::open_and_exec(
             false, 
             TESTNET_CHAIN_ID, 
             &config.get_chainstate_path(), 
+            config.node.get_storage_layout(),
             Some(initial_balances), 
             boot_block_exec) {
             Ok(res) => res,
             Err(_) => panic!("Error while opening chain state at path {:?}", config.get_chainstate_path())
         };
+        chain_state.set_archive_mode(config.node.archive);
 
         let mem_pool = MemPoolFS::new(&config.mempool.path);
 
@@ -168,6 +170,9 @@ impl Node {
                 },
                 BlockstackOperationType::UserBurnSupport(_) => {
                     // no-op, UserBurnSupport ops are not supported / produced at this point.
+                },
+                BlockstackOperationType::TransferStx(_) | BlockstackOperationType::PreStx(_) => {
+                    // no-op, STX operations are applied by the Stacks chain state.
                 }
             }
         }
@@ -300,7 +305,6 @@ impl Node {
         anchored_block: &StacksBlock, 
         burn_header_hash: &BurnchainHeaderHash, 
         parent_burn_header_hash: &BurnchainHeaderHash, 
-        microblocks: Vec<StacksMicroblock>, 
         db: &mut BurnDB) -> (StacksBlock, StacksHeaderInfo, Vec<StacksTransactionReceipt>) {
 
         {
@@ -314,17 +318,6 @@ impl Node {
                 get_epoch_time_secs(),
                 &anchored_block, 
                 &parent_burn_header_hash).unwrap();
-
-            // Preprocess the microblocks
-            for microblock in microblocks.iter() {
-                let res = self.chain_state.preprocess_streamed_microblock(
-                    &burn_header_hash, 
-                    &anchored_block.block_hash(), 
-                    microblock).unwrap();
-                if !res {
-                    warn!("Unhandled error while pre-processing microblock {}", microblock.header.block_hash());
-                }
-            }
         }
 
         let mut processed_blocks = vec![];
@@ -360,6 +353,13 @@ impl Node {
 
         self.chain_tip = Some(chain_tip_info.clone());
 
+        if let Some(prune_depth) = self.config.node.prune_depth {
+            match self.chain_state.prune_dead_forks(&chain_tip_info.burn_header_hash, &chain_tip_info.anchored_header.block_hash(), prune_depth) {
+                Ok(stats) => debug!("Pruned dead forks: {:?}", &stats),
+                Err(e) => warn!("Failed to prune dead forks: {:?}", &e)
+            }
+        }
+
         // Unset the `bootstraping_chain` flag.
         if self.bootstraping_chain {
             self.bootstraping_chain = false;
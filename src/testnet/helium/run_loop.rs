@@ -100,12 +100,12 @@ impl RunLoop {
         RunLoop::handle_new_tenure_cb(&self.new_tenure_callback, round_index, &first_tenure);
 
         // Run the tenure, keep the artifacts
-        let artifacts_from_1st_tenure = match first_tenure.run() {
+        let artifacts_from_1st_tenure = match first_tenure.run(burnchain.burndb_mut()) {
             Some(res) => res,
             None => panic!("Error while running 1st tenure")
         };
 
-        let (anchored_block_1, microblocks, parent_block_1) = artifacts_from_1st_tenure;
+        let (anchored_block_1, parent_block_1) = artifacts_from_1st_tenure;
 
         // Tenures are instantiating their own chainstate, so that nodes can keep their chainstate clean,
         // while having the option of running multiple tenures concurrently and try different strategies.
@@ -132,10 +132,12 @@ impl RunLoop {
             &anchored_block_1, 
             &last_sortitioned_block.burn_header_hash, 
             &last_sortitioned_block.parent_burn_header_hash, 
-            microblocks.clone(),
             burnchain.burndb_mut());
 
-        RunLoop::handle_new_chain_state_cb(&self.new_chain_state_callback, round_index, &mut self.node.chain_state, chain_tip, chain_tip_info, receipts);
+        RunLoop::handle_new_chain_state_cb(&self.new_chain_state_callback, round_index, &mut self.node.chain_state, chain_tip, chain_tip_info.clone(), receipts);
+
+        // Stream microblocks off of the block we just processed
+        first_tenure.handle_microblocks(&mut self.node.chain_state, &chain_tip_info);
 
         // If the node we're looping on won the sortition, initialize and configure the next tenure
         if won_sortition {
@@ -151,9 +153,9 @@ impl RunLoop {
 
             // Run the last initialized tenure
             let artifacts_from_tenure = match leader_tenure {
-                Some(mut tenure) => {
+                Some(ref mut tenure) => {
                     RunLoop::handle_new_tenure_cb(&self.new_tenure_callback, round_index, &tenure);
-                    tenure.run()
+                    tenure.run(burnchain.burndb_mut())
                 },
                 None => None
             };
@@ -162,7 +164,7 @@ impl RunLoop {
             match artifacts_from_tenure {
                 Some(ref artifacts) => {
                     // Have each node receive artifacts from the current tenure
-                    let (anchored_block, _, parent_block) = artifacts;
+                    let (anchored_block, parent_block) = artifacts;
                     let mut ops = self.node.receive_tenure_artifacts(&anchored_block, &parent_block);
                     next_burn_ops.append(&mut ops);
                 },
@@ -171,8 +173,6 @@ impl RunLoop {
 
             burnchain_state = burnchain.make_next_block(next_burn_ops);
             RunLoop::handle_burnchain_state_cb(&self.new_burnchain_state_callback, round_index, &burnchain_state);
-    
-            leader_tenure = None;
 
             // Have each node process the new block, that can include, or not, a sortition.
             let (last_sortitioned_block, won_sortition) = match self.node.process_burnchain_state(&burnchain_state) {
@@ -186,13 +186,12 @@ impl RunLoop {
                 Some(ref artifacts) => {
                     // Have each node process the previous tenure.
                     // We should have some additional checks here, and ensure that the previous artifacts are legit.
-                    let (anchored_block, microblocks, _) = artifacts;
+                    let (anchored_block, _) = artifacts;
 
                     let (chain_tip, chain_tip_info, events) = self.node.process_tenure(
                         &anchored_block, 
                         &burnchain_state.chain_tip.burn_header_hash, 
                         &burnchain_state.chain_tip.parent_burn_header_hash,             
-                        microblocks.to_vec(),
                         burnchain.burndb_mut());
 
                     RunLoop::handle_new_chain_state_cb(
@@ -200,20 +199,29 @@ impl RunLoop {
                         round_index,
                         &mut self.node.chain_state,
                         chain_tip,
-                        chain_tip_info,
+                        chain_tip_info.clone(),
                         events
                     );
+
+                    // Have the leader stream microblocks off of the block we just processed
+                    if let Some(ref mut tenure) = leader_tenure {
+                        tenure.handle_microblocks(&mut self.node.chain_state, &chain_tip_info);
+                    }
                 },
             };
             
             // If the node we're looping on won the sortition, initialize and configure the next tenure
+            leader_tenure = None;
             if won_sortition {
                 leader_tenure = self.node.initiate_new_tenure(&last_sortitioned_block);
             } 
             
             round_index += 1;
 
-            sleep_ms(self.config.burnchain.block_time);
+            // a tenure that streams microblocks spends the block time doing so
+            if self.config.node.microblock_frequency == 0 {
+                sleep_ms(self.config.burnchain.block_time);
+            }
         }
     }
 
@@ -1,14 +1,17 @@
 use super::{MemPool, MemPoolFS, Config};
+use super::mem_pool::MAX_TX_REQUEUES;
 use super::node::{SortitionedBlock, TESTNET_CHAIN_ID};
 
 use std::thread;
 use std::time;
+use std::mem;
 
 use burnchains::{BurnchainHeaderHash, Txid};
 use chainstate::stacks::db::{StacksChainState, StacksHeaderInfo, ClarityTx};
 use chainstate::stacks::{StacksPrivateKey, StacksBlock, StacksWorkScore, StacksAddress, StacksTransactionSigner, StacksTransaction, TransactionVersion, StacksMicroblock, CoinbasePayload, StacksBlockBuilder, TransactionAnchorMode};
-use chainstate::stacks::{MINER_BLOCK_BURN_HEADER_HASH, MINER_BLOCK_HEADER_HASH};
+use chainstate::stacks::{MINER_BLOCK_BURN_HEADER_HASH, MINER_BLOCK_HEADER_HASH, MICROBLOCK_STREAM_COST_LIMIT};
 use chainstate::burn::{VRFSeed, BlockHeaderHash};
+use chainstate::burn::db::burndb::BurnDB;
 use util::vrf::{VRFProof};
 
 pub struct LeaderTenure {
@@ -19,6 +22,7 @@ pub struct LeaderTenure {
     last_sortitioned_block: SortitionedBlock,
     pub mem_pool: MemPoolFS,
     parent_block: StacksHeaderInfo,
+    pending_txs: Vec<StacksTransaction>,
     started_at: std::time::Instant,
     pub vrf_seed: VRFSeed,
 }
@@ -54,39 +58,156 @@ impl <'a> LeaderTenure {
             last_sortitioned_block,
             mem_pool,
             parent_block,
+            pending_txs: vec![],
             started_at: now,
             vrf_seed: VRFSeed::from_proof(&vrf_proof),
         }
     }
 
-    pub fn handle_txs(&mut self, clarity_tx: &mut ClarityTx<'a>, txs: Vec<StacksTransaction>) {
+    /// Mine the given transactions, and return how many of them were mined.
+    pub fn handle_txs(&mut self, clarity_tx: &mut ClarityTx<'a>, txs: Vec<StacksTransaction>) -> usize {
+        let mut num_mined = 0;
         for tx in txs {
             let res = self.block_builder.try_mine_tx(clarity_tx, &tx);
             match res {
                 Err(e) => error!("Failed mining transaction - {}", e),
-                Ok(_) => {},
+                Ok(_) => num_mined += 1,
             };
         }
+        num_mined
     }
 
-    pub fn run(&mut self) -> Option<(StacksBlock, Vec<StacksMicroblock>, SortitionedBlock)> {
+    /// Put transactions that couldn't be mined yet back in the mempool.  Transactions that have
+    /// already gone back too many times are dropped (see MemPoolFS::requeue()).
+    fn requeue_txs(&mut self, txs: Vec<StacksTransaction>) {
+        for tx in txs {
+            if !self.mem_pool.requeue(&tx) {
+                warn!("Dropping transaction {}: it could not be mined after {} tries", tx.txid(), MAX_TX_REQUEUES);
+            }
+        }
+    }
+
+    /// Stream microblocks off of the anchored block this tenure mined, once the node has
+    /// processed it, cutting one every `microblock_frequency` milliseconds until the tenure's block
+    /// time is up.  Each microblock is stored in the node's chain state as soon as it is cut, so the
+    /// next leader can confirm it.  Each microblock packs the streamable transactions that arrived
+    /// since the last one, along with any that couldn't be mined into the previous one (e.g.
+    /// because of a nonce gap that a newly-arrived transaction may fill).  Whatever is left at the
+    /// end goes back in the mempool.
+    pub fn handle_microblocks(&mut self, chain_state: &mut StacksChainState, chain_tip: &StacksHeaderInfo) -> Vec<StacksMicroblock> {
+        let mut microblocks = vec![];
+        let pending_txs = mem::replace(&mut self.pending_txs, vec![]);
+        let microblock_frequency = self.config.node.microblock_frequency;
+        if microblock_frequency == 0 {
+            let (streamed_only, anchorable) : (Vec<_>, Vec<_>) = pending_txs
+                .into_iter()
+                .partition(|tx| tx.anchor_mode == TransactionAnchorMode::OffChainOnly);
+
+            // transactions that can only be streamed stay in the mempool until microblock
+            // mining is turned on
+            if streamed_only.len() > 0 {
+                debug!("Microblock mining is disabled; leaving {} transaction(s) that can only be streamed in the mempool", streamed_only.len());
+            }
+            for tx in streamed_only.iter() {
+                self.mem_pool.put_back(tx);
+            }
+            self.requeue_txs(anchorable);
+            return microblocks;
+        }
 
-        let mut chain_state = StacksChainState::open(
+        let anchored_block_hash = chain_tip.anchored_header.block_hash();
+        if anchored_block_hash != self.block_builder.header.block_hash() {
+            // our block isn't the chain tip, so there's nothing to stream off of
+            self.requeue_txs(pending_txs);
+            return microblocks;
+        }
+
+        let mut miner_chain_state = StacksChainState::open_with_layout(
             false, 
             TESTNET_CHAIN_ID, 
-            &self.config.get_chainstate_path()).unwrap();
+            &self.config.get_chainstate_path(),
+            self.config.node.get_storage_layout()).unwrap();
+
+        let mut clarity_tx = miner_chain_state.block_begin(
+            &chain_tip.burn_header_hash, 
+            &anchored_block_hash, 
+            &MINER_BLOCK_BURN_HEADER_HASH, 
+            &MINER_BLOCK_HEADER_HASH);
+        clarity_tx.reset_cost(MICROBLOCK_STREAM_COST_LIMIT);
+
+        let tenure_end = time::Instant::now() + time::Duration::from_millis(self.average_block_time);
+        let mut next_txs = pending_txs;
+        loop {
+            let (mined, requeued) = self.block_builder.mine_txs_by_fee_rate(&mut clarity_tx, next_txs);
+            next_txs = requeued;
+            self.mem_pool.forget_requeues(&mined);
+
+            if mined.len() > 0 {
+                match self.block_builder.mine_next_microblock() {
+                    Ok(microblock) => {
+                        match chain_state.preprocess_streamed_microblock(&chain_tip.burn_header_hash, &anchored_block_hash, &microblock) {
+                            Ok(true) => {},
+                            Ok(false) => warn!("Failed to store microblock {}", microblock.block_hash()),
+                            Err(e) => error!("Failed storing microblock {} - {:?}", microblock.block_hash(), e)
+                        };
+                        microblocks.push(microblock);
+                    },
+                    Err(e) => {
+                        error!("Failed mining microblock - {}", e);
+                        break;
+                    }
+                }
+            }
+
+            let now = time::Instant::now();
+            if now >= tenure_end {
+                break;
+            }
+            thread::sleep(std::cmp::min(time::Duration::from_millis(microblock_frequency), tenure_end - now));
+
+            next_txs.extend(self.mem_pool.poll());
+        }
 
-        let mut clarity_tx = self.block_builder.epoch_begin(&mut chain_state).unwrap();
+        clarity_tx.rollback_block();
+
+        self.requeue_txs(next_txs);
+        microblocks
+    }
+
+    /// Mine this tenure's anchored block.  Transactions that can't go in it are held for the
+    /// microblock stream (see handle_microblocks()).
+    pub fn run(&mut self, burn_db: &mut BurnDB) -> Option<(StacksBlock, SortitionedBlock)> {
+
+        let mut chain_state = StacksChainState::open_with_layout(
+            false, 
+            TESTNET_CHAIN_ID, 
+            &self.config.get_chainstate_path(),
+            self.config.node.get_storage_layout()).unwrap();
+
+        // we're building on the burnchain block of the last sortition
+        let mut clarity_tx = {
+            let mut burn_tx = burn_db.tx_begin().unwrap();
+            self.block_builder.epoch_begin(&mut chain_state, &mut burn_tx, &self.last_sortitioned_block.burn_header_hash).unwrap()
+        };
 
         self.handle_txs(&mut clarity_tx, vec![self.coinbase_tx.clone()]);
 
-        let txs = self.mem_pool.poll();
-        self.handle_txs(&mut clarity_tx, txs);
+        // transactions that can only be streamed wait for the microblock stream
+        let (streamed_txs, anchored_txs) : (Vec<_>, Vec<_>) = self.mem_pool.poll()
+            .into_iter()
+            .partition(|tx| tx.anchor_mode == TransactionAnchorMode::OffChainOnly);
+
+        // whatever doesn't make it into the anchored block gets another chance in a microblock
+        let (mined_txs, mut requeued_txs) = self.block_builder.mine_txs_by_fee_rate(&mut clarity_tx, anchored_txs);
+        self.mem_pool.forget_requeues(&mined_txs);
 
         let anchored_block = self.block_builder.mine_anchored_block(&mut clarity_tx);
 
         clarity_tx.rollback_block();
 
-        Some((anchored_block, vec![], self.last_sortitioned_block.clone()))
+        requeued_txs.extend(streamed_txs);
+        self.pending_txs = requeued_txs;
+
+        Some((anchored_block, self.last_sortitioned_block.clone()))
     }
 }
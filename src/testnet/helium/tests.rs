@@ -1,13 +1,18 @@
 use testnet;
 use rand::RngCore;
 use util::hash::{to_hex, hex_bytes};
-use testnet::helium::mem_pool::MemPool;
+use std::fs;
+use testnet::helium::mem_pool::{MemPool, MemPoolFS, MAX_TX_REQUEUES};
 use chainstate::stacks::db::{StacksChainState};
 use chainstate::stacks::events::{StacksTransactionEvent};
 use super::node::{TESTNET_CHAIN_ID};
 use super::config::{InitialBalance};
 
-use chainstate::stacks::{TransactionPayload, CoinbasePayload};
+use chainstate::stacks::{TransactionPayload, CoinbasePayload, StacksTransaction, StacksTransactionSigner, StacksPrivateKey,
+                         StacksAddress, TransactionAuth, TransactionVersion, TransactionAnchorMode, TokenTransferMemo};
+use core::EMPTY_MICROBLOCK_PARENT_HASH;
+use burnchains::Address;
+use net::StacksMessageCodec;
 use vm::types::PrincipalData;
 
 pub fn new_test_conf() -> testnet::helium::Config {
@@ -399,3 +404,142 @@ fn should_succeed_handling_malformed_and_valid_txs() {
     });
     run_loop.start(num_rounds);
 }
+
+#[test]
+fn should_succeed_mining_microblocks() {
+    let mut conf = new_test_conf();
+    conf.burnchain.block_time = 2000;
+    conf.node.microblock_frequency = 500;
+
+    let num_rounds = 3;
+    let mut run_loop = testnet::helium::RunLoop::new(conf);
+
+    run_loop.apply_on_new_tenures(|round, tenure| {
+        if round == 1 {
+            // On round 1, send a STX transfer that can only be mined in a microblock
+            let sk = StacksPrivateKey::from_hex("b1cf9cee5083f421c84d7cb53be5edf2801c3c78d63d53917aee0bdc8bd160ee01").unwrap();
+            let recipient = StacksAddress::from_string(&"ST195Q2HPXY576N4CT2A0R94D7DRYSX54A5X3YZTH".to_string()).unwrap();
+            let mut tx = StacksTransaction::new(TransactionVersion::Testnet,
+                                                TransactionAuth::from_p2pkh(&sk).unwrap(),
+                                                TransactionPayload::TokenTransfer(recipient, 1000, TokenTransferMemo([0u8; 34])));
+            tx.chain_id = TESTNET_CHAIN_ID;
+            tx.set_anchor_mode(TransactionAnchorMode::OffChainOnly);
+
+            let mut tx_signer = StacksTransactionSigner::new(&tx);
+            tx_signer.sign_origin(&sk).unwrap();
+
+            let mut tx_bytes = vec![];
+            tx_signer.get_tx().unwrap().consensus_serialize(&mut tx_bytes).unwrap();
+            tenure.mem_pool.submit(tx_bytes);
+        }
+    });
+
+    run_loop.apply_on_new_chain_states(|round, chain_state, block, chain_tip_info, receipts| {
+        match round {
+            1 => {
+                // The transfer can't be anchored, so block #2 only has the coinbase.  It gets
+                // streamed in a microblock once this block is processed.
+                assert!(chain_tip_info.block_height == 2);
+                assert!(block.txs.len() == 1);
+                assert!(!chain_state.has_any_staging_microblock_indexed(&chain_tip_info.index_block_hash(), 0).unwrap());
+            },
+            2 => {
+                // Block #3 confirms block #2's microblock stream, including the transfer, which
+                // was stored as it was streamed
+                assert!(chain_tip_info.block_height == 3);
+                assert!(block.header.parent_microblock != EMPTY_MICROBLOCK_PARENT_HASH);
+                assert!(block.header.parent_microblock_sequence == 0);
+
+                let events: Vec<StacksTransactionEvent> = receipts.iter().flat_map(|a| a.events.clone()).collect();
+                assert!(events.len() == 1);
+            },
+            _ => {}
+        }
+    });
+    run_loop.start(num_rounds);
+}
+
+#[test]
+fn should_keep_streamed_only_txs_when_not_mining_microblocks() {
+    let conf = new_test_conf();
+    assert_eq!(conf.node.microblock_frequency, 0);
+    let mempool_path = conf.mempool.path.clone();
+
+    let num_rounds = 3;
+    let mut run_loop = testnet::helium::RunLoop::new(conf);
+
+    run_loop.apply_on_new_tenures(|round, tenure| {
+        if round == 1 {
+            // On round 1, send a STX transfer that can only be mined in a microblock
+            let sk = StacksPrivateKey::from_hex("b1cf9cee5083f421c84d7cb53be5edf2801c3c78d63d53917aee0bdc8bd160ee01").unwrap();
+            let recipient = StacksAddress::from_string(&"ST195Q2HPXY576N4CT2A0R94D7DRYSX54A5X3YZTH".to_string()).unwrap();
+            let mut tx = StacksTransaction::new(TransactionVersion::Testnet,
+                                                TransactionAuth::from_p2pkh(&sk).unwrap(),
+                                                TransactionPayload::TokenTransfer(recipient, 1000, TokenTransferMemo([0u8; 34])));
+            tx.chain_id = TESTNET_CHAIN_ID;
+            tx.set_anchor_mode(TransactionAnchorMode::OffChainOnly);
+
+            let mut tx_signer = StacksTransactionSigner::new(&tx);
+            tx_signer.sign_origin(&sk).unwrap();
+
+            let mut tx_bytes = vec![];
+            tx_signer.get_tx().unwrap().consensus_serialize(&mut tx_bytes).unwrap();
+            tenure.mem_pool.submit(tx_bytes);
+        }
+    });
+
+    run_loop.apply_on_new_chain_states(|_round, _chain_state, block, _chain_tip_info, _receipts| {
+        // The transfer is never mined, since nothing streams microblocks
+        assert!(block.txs.len() == 1);
+        assert!(block.header.parent_microblock == EMPTY_MICROBLOCK_PARENT_HASH);
+    });
+    run_loop.start(num_rounds);
+
+    // ...but it's still in the mempool, after going through two tenures
+    let mut mem_pool = MemPoolFS::new(&mempool_path);
+    let txs = mem_pool.poll();
+    assert_eq!(txs.len(), 1);
+    assert_eq!(txs[0].anchor_mode, TransactionAnchorMode::OffChainOnly);
+}
+
+#[test]
+fn should_drop_txs_requeued_too_many_times() {
+    let path = "/tmp/blockstack-test-mempool-requeues";
+    if fs::metadata(path).is_ok() {
+        fs::remove_dir_all(path).unwrap();
+    }
+    let mut mem_pool = MemPoolFS::new(path);
+
+    let sk = StacksPrivateKey::from_hex("b1cf9cee5083f421c84d7cb53be5edf2801c3c78d63d53917aee0bdc8bd160ee01").unwrap();
+    let recipient = StacksAddress::from_string(&"ST195Q2HPXY576N4CT2A0R94D7DRYSX54A5X3YZTH".to_string()).unwrap();
+    let mut tx = StacksTransaction::new(TransactionVersion::Testnet,
+                                        TransactionAuth::from_p2pkh(&sk).unwrap(),
+                                        TransactionPayload::TokenTransfer(recipient, 1000, TokenTransferMemo([0u8; 34])));
+    tx.chain_id = TESTNET_CHAIN_ID;
+    // stuck behind a nonce gap
+    tx.set_origin_nonce(100);
+
+    let mut tx_signer = StacksTransactionSigner::new(&tx);
+    tx_signer.sign_origin(&sk).unwrap();
+    let tx = tx_signer.get_tx().unwrap();
+
+    for _ in 0..MAX_TX_REQUEUES {
+        assert!(mem_pool.requeue(&tx));
+        assert_eq!(mem_pool.poll(), vec![tx.clone()]);
+    }
+
+    // dropped once it's been requeued too many times
+    assert!(!mem_pool.requeue(&tx));
+    assert_eq!(mem_pool.poll(), vec![]);
+
+    // a dropped transaction starts over, and so does one that got mined
+    assert!(mem_pool.requeue(&tx));
+    assert_eq!(mem_pool.poll(), vec![tx.clone()]);
+    mem_pool.forget_requeues(&vec![tx.clone()]);
+    for _ in 0..MAX_TX_REQUEUES {
+        assert!(mem_pool.requeue(&tx));
+        assert_eq!(mem_pool.poll(), vec![tx.clone()]);
+    }
+
+    fs::remove_dir_all(path).unwrap();
+}
@@ -142,9 +142,9 @@ fn test_names_tokens_contracts_interface() {
 
     let contract_analysis = mem_type_check(INTERFACE_TEST_CONTRACT).unwrap().1;
     let test_contract_json_str = build_contract_interface(&contract_analysis).serialize();
-    let test_contract_json = serde_json::from_str(&test_contract_json_str).unwrap();
+    let test_contract_json : serde_json::Value = serde_json::from_str(&test_contract_json_str).unwrap();
 
-    let test_contract_json_expected = serde_json::from_str(r#"{
+    let test_contract_json_expected : serde_json::Value = serde_json::from_str(r#"{
         "functions": [
             { "name": "f00",
                 "access": "private",
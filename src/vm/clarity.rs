@@ -4,7 +4,7 @@ use vm::contexts::{OwnedEnvironment, AssetMap};
 use vm::database::{MarfedKV, ClarityDatabase, SqliteConnection, HeadersDB};
 use vm::analysis::{AnalysisDatabase};
 use vm::errors::{Error as InterpreterError};
-use vm::ast::{ContractAST, errors::ParseError};
+use vm::ast::{ContractAST, errors::ParseError, errors::ParseErrors};
 use vm::analysis::{ContractAnalysis, errors::CheckError, errors::CheckErrors};
 use vm::ast;
 use vm::analysis;
@@ -42,7 +42,9 @@ pub struct ClarityBlockConnection<'a> {
     datastore: MarfedKV,
     parent: &'a mut ClarityInstance,
     header_db: &'a dyn HeadersDB,
-    cost_track: Option<LimitedCostTracker>
+    cost_track: Option<LimitedCostTracker>,
+    // cost so far when writes began to be held (see hold_writes())
+    held_cost: Option<ExecutionCost>
 }
 
 pub struct ClarityReadOnlyConnection<'a> {
@@ -62,7 +64,11 @@ pub enum Error {
 
 impl From<CheckError> for Error {
     fn from(e: CheckError) -> Self {
-        Error::Analysis(e)
+        match &e.err {
+            CheckErrors::CostBalanceExceeded(a, b) => Error::CostError(a.clone(), b.clone()),
+            CheckErrors::CostOverflow => Error::CostError(ExecutionCost::max_value(), ExecutionCost::max_value()),
+            _ => Error::Analysis(e)
+        }
     }
 }
 
@@ -78,7 +84,11 @@ impl From<InterpreterError> for Error {
 
 impl From<ParseError> for Error {
     fn from(e: ParseError) -> Self {
-        Error::Parse(e)
+        match &e.err {
+            ParseErrors::CostBalanceExceeded(a, b) => Error::CostError(a.clone(), b.clone()),
+            ParseErrors::CostOverflow => Error::CostError(ExecutionCost::max_value(), ExecutionCost::max_value()),
+            _ => Error::Parse(e)
+        }
     }
 }
 
@@ -124,7 +134,8 @@ impl ClarityInstance {
             datastore,
             header_db,
             parent: self,
-            cost_track: Some(LimitedCostTracker::new_max_limit())
+            cost_track: Some(LimitedCostTracker::new_max_limit()),
+            held_cost: None
         }
     }
 
@@ -141,7 +152,8 @@ impl ClarityInstance {
             datastore,
             header_db,
             parent: self,
-            cost_track: Some(LimitedCostTracker::new(limit))
+            cost_track: Some(LimitedCostTracker::new(limit)),
+            held_cost: None
         }
     }
 
@@ -174,6 +186,13 @@ impl ClarityInstance {
             .map_err(Error::from)
     }
 
+    /// Drop the state of blocks that will never be built on again.  See MarfedKV::drop_blocks().
+    pub fn drop_blocks(&mut self, blocks: &[BlockHeaderHash]) -> Result<usize, InterpreterError> {
+        self.datastore.as_mut()
+            .expect("FAIL: use of drop_blocks while prior block neither committed nor rolled back.")
+            .drop_blocks(blocks)
+    }
+
     pub fn destroy(mut self) -> MarfedKV {
         let datastore = self.datastore.take()
             .expect("FAIL: attempt to recover database connection from clarity instance which is still open");
@@ -235,6 +254,16 @@ impl <'a> ClarityReadOnlyConnection <'a> {
     pub fn done(self) {
         self.parent.datastore.replace(self.datastore);
     }
+
+    /// Evaluate a read-only Clarity snippit in the context of a contract, at this connection's
+    ///   block.  Nothing is written to the datastore.
+    pub fn eval_read_only(&mut self, contract: &QualifiedContractIdentifier, code: &str) -> Result<Value, Error> {
+        let clarity_db = ClarityDatabase::new(&mut self.datastore, &self.header_db);
+        let mut env = OwnedEnvironment::new(clarity_db);
+        env.eval_read_only(contract, code)
+            .map(|(x, _, _)| x)
+            .map_err(Error::from)
+    }
 }
 
 impl <'a> ClarityBlockConnection <'a> {
@@ -298,6 +327,50 @@ impl <'a> ClarityBlockConnection <'a> {
         self.datastore.get_root_hash()
     }
 
+    /// Get the total execution cost of everything run in this block so far
+    pub fn cost_so_far(&self) -> ExecutionCost {
+        self.cost_track.as_ref()
+            .expect("Failed to get ownership of cost tracker in ClarityBlockConnection")
+            .get_total()
+    }
+
+    pub fn get_cost_limit(&self) -> ExecutionCost {
+        self.cost_track.as_ref()
+            .expect("Failed to get ownership of cost tracker in ClarityBlockConnection")
+            .get_limit()
+    }
+
+    /// Start counting execution cost from `cost` against a new budget.  Used to give an anchored
+    ///   block and a microblock stream processed in the same block their own budgets.
+    pub fn reset_cost(&mut self, cost: ExecutionCost, limit: ExecutionCost) {
+        let cost_track = self.cost_track.as_mut()
+            .expect("Failed to get ownership of cost tracker in ClarityBlockConnection");
+        cost_track.set_total(cost);
+        cost_track.set_limit(limit);
+    }
+
+    /// Hold back all subsequent writes (and remember the cost so far), so that everything done
+    ///   since can be undone with discard_writes().  See MarfedKV::hold_writes().
+    pub fn hold_writes(&mut self) {
+        self.held_cost = Some(self.cost_so_far());
+        self.datastore.hold_writes();
+    }
+
+    /// Write out everything held since hold_writes()
+    pub fn release_writes(&mut self) {
+        self.held_cost = None;
+        self.datastore.release_writes();
+    }
+
+    /// Drop everything held since hold_writes(), and forget its execution cost
+    pub fn discard_writes(&mut self) {
+        let held_cost = self.held_cost.take()
+            .expect("FAIL: discarded writes that were not held");
+        let limit = self.get_cost_limit();
+        self.reset_cost(held_cost, limit);
+        self.datastore.discard_writes();
+    }
+
     /// Get the inner MARF
     pub fn get_marf(&mut self) -> &mut MARF {
         self.datastore.get_marf()
@@ -477,9 +550,8 @@ mod tests {
     use super::*;
     use vm::analysis::errors::CheckErrors;
     use vm::types::{Value, StandardPrincipalData};
-    use vm::database::{NULL_HEADER_DB, ClarityBackingStore, MarfedKV};
+    use vm::database::{NULL_HEADER_DB, ClarityBackingStore, MarfedKV, SideStoreType};
     use chainstate::stacks::index::storage::{TrieFileStorage};
-    use rusqlite::NO_PARAMS;
 
     #[test]
     pub fn bad_syntax_test() {
@@ -538,9 +610,56 @@ mod tests {
     }
 
     #[test]
-    pub fn test_block_roll_back() {
+    pub fn test_discard_held_writes() {
         let marf = MarfedKV::temporary();
         let mut clarity_instance = ClarityInstance::new(marf);
+
+        let contract_identifier = QualifiedContractIdentifier::local("foo").unwrap();
+        let sender = StandardPrincipalData::transient().into();
+
+        {
+            let mut conn = clarity_instance.begin_block(&TrieFileStorage::block_sentinel(),
+                                                        &BlockHeaderHash::from_bytes(&[0 as u8; 32]).unwrap(),
+                                                        &NULL_HEADER_DB);
+
+            let contract = "(define-data-var bar int 0)
+                            (define-public (set-bar (x int)) (begin (var-set bar x) (ok x)))
+                            (define-read-only (get-bar) (var-get bar))";
+
+            let (ct_ast, ct_analysis) = conn.analyze_smart_contract(&contract_identifier, &contract).unwrap();
+            conn.initialize_smart_contract(
+                &contract_identifier, &ct_ast, &contract, |_,_| false).unwrap();
+            conn.save_analysis(&contract_identifier, &ct_analysis).unwrap();
+
+            let cost_before = conn.cost_so_far();
+            let root_before = conn.get_root_hash();
+
+            conn.hold_writes();
+            conn.run_contract_call(&sender, &contract_identifier, "set-bar", &[Value::Int(1)],
+                                   |_, _| false).unwrap();
+            assert!(conn.cost_so_far() != cost_before);
+            assert_eq!(conn.eval_read_only(&contract_identifier, "(get-bar)").unwrap(), Value::Int(1));
+
+            // discarded writes and their cost are gone
+            conn.discard_writes();
+            assert_eq!(conn.cost_so_far(), cost_before);
+            assert_eq!(conn.get_root_hash(), root_before);
+            assert_eq!(conn.eval_read_only(&contract_identifier, "(get-bar)").unwrap(), Value::Int(0));
+
+            // released writes are kept
+            conn.hold_writes();
+            conn.run_contract_call(&sender, &contract_identifier, "set-bar", &[Value::Int(2)],
+                                   |_, _| false).unwrap();
+            conn.release_writes();
+            assert!(conn.get_root_hash() != root_before);
+            assert_eq!(conn.eval_read_only(&contract_identifier, "(get-bar)").unwrap(), Value::Int(2));
+
+            conn.commit_block();
+        }
+    }
+
+    fn check_block_roll_back(marf: MarfedKV) {
+        let mut clarity_instance = ClarityInstance::new(marf);
         let contract_identifier = QualifiedContractIdentifier::local("foo").unwrap();
 
         {
@@ -562,11 +681,18 @@ mod tests {
         // should not be in the marf.
         assert_eq!(marf.get_contract_hash(&contract_identifier).unwrap_err(),
                    CheckErrors::NoSuchContract(contract_identifier.to_string()).into());
-        let sql = marf.get_side_store();
-        // sqlite should not have any entries
-        assert_eq!(0,
-                   sql.mut_conn()
-                   .query_row::<u32,_,_>("SELECT COUNT(value) FROM data_table", NO_PARAMS, |row| row.get(0)).unwrap());
+        // the side store should not have any entries
+        assert_eq!(0, marf.get_side_store().num_entries());
+    }
+
+    #[test]
+    pub fn test_block_roll_back() {
+        check_block_roll_back(MarfedKV::temporary());
+    }
+
+    #[test]
+    pub fn test_block_roll_back_log_side_store() {
+        check_block_roll_back(MarfedKV::temporary_with_side_store(SideStoreType::Log));
     }
 
     #[test]
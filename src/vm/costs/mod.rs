@@ -107,6 +107,16 @@ impl LimitedCostTracker {
     pub fn get_total(&self) -> ExecutionCost {
         self.total.clone()
     }
+    pub fn get_limit(&self) -> ExecutionCost {
+        self.limit.clone()
+    }
+    /// Overwrite the running total, e.g. to forget the cost of a transaction that was discarded
+    pub fn set_total(&mut self, total: ExecutionCost) {
+        self.total = total;
+    }
+    pub fn set_limit(&mut self, limit: ExecutionCost) {
+        self.limit = limit;
+    }
 }
 
 fn add_cost(s: &mut LimitedCostTracker, cost: ExecutionCost) -> std::result::Result<(), CostErrors> {
@@ -205,6 +215,7 @@ impl fmt::Display for ExecutionCost {
 pub trait CostOverflowingMath <T> {
     fn cost_overflow_mul(self, other: T) -> Result<T>;
     fn cost_overflow_add(self, other: T) -> Result<T>;
+    fn cost_overflow_sub(self, other: T) -> Result<T>;
 }
 
 impl CostOverflowingMath <u64> for u64 {
@@ -216,6 +227,10 @@ impl CostOverflowingMath <u64> for u64 {
         self.checked_add(other)
             .ok_or_else(|| CostErrors::CostOverflow)
     }
+    fn cost_overflow_sub(self, other: u64) -> Result<u64> {
+        self.checked_sub(other)
+            .ok_or_else(|| CostErrors::CostOverflow)
+    }
 }
 
 impl ExecutionCost {
@@ -245,6 +260,15 @@ impl ExecutionCost {
         Ok(())
     }
 
+    pub fn sub(&mut self, other: &ExecutionCost) -> Result<()> {
+        self.runtime = self.runtime.cost_overflow_sub(other.runtime)?;
+        self.read_count   = self.read_count.cost_overflow_sub(other.read_count)?;
+        self.read_length  = self.read_length.cost_overflow_sub(other.read_length)?;
+        self.write_length = self.write_length.cost_overflow_sub(other.write_length)?;
+        self.write_count  = self.write_count.cost_overflow_sub(other.write_count)?;
+        Ok(())
+    }
+
     pub fn multiply(&mut self, times: u64) -> Result<()> {
         self.runtime = self.runtime.cost_overflow_mul(times)?;
         self.read_count   = self.read_count.cost_overflow_mul(times)?;
@@ -166,7 +166,7 @@ impl <'a> ClarityDatabase <'a> {
     }
 
     pub fn put <T: ClaritySerializable> (&mut self, key: &str, value: &T) {
-        self.store.put(&key, &value.serialize());
+        self.store.put(&key, value);
     }
 
     fn get <T> (&mut self, key: &str) -> Option<T> where T: ClarityDeserializable<T> {
@@ -1,4 +1,4 @@
-use super::{MarfedKV, ClarityBackingStore, ClarityDeserializable};
+use super::{MarfedKV, ClarityBackingStore, ClaritySerializable, ClarityDeserializable};
 use vm::Value;
 use vm::errors::{ InterpreterResult as Result };
 use chainstate::burn::BlockHeaderHash;
@@ -8,15 +8,15 @@ use vm::types::{QualifiedContractIdentifier, TypeSignature};
 use std::{cmp::Eq, hash::Hash, clone::Clone};
 
 #[cfg(rollback_value_check)]
-type RollbackValueCheck = String;
+type RollbackValueCheck = Vec<u8>;
 #[cfg(not(rollback_value_check))]
 type RollbackValueCheck = ();
 
 #[cfg(not(rollback_value_check))]
-fn rollback_value_check(_value: &String, _check: &RollbackValueCheck) {}
+fn rollback_value_check(_value: &Vec<u8>, _check: &RollbackValueCheck) {}
 
 #[cfg(not(rollback_value_check))]
-fn rollback_edits_push<T>(edits: &mut Vec<(T, RollbackValueCheck)>, key: T, _value: &String) {
+fn rollback_edits_push<T>(edits: &mut Vec<(T, RollbackValueCheck)>, key: T, _value: &Vec<u8>) {
     edits.push((key, ()));
 }
 // this function is used to check the lookup map when committing at the "bottom" of the
@@ -24,7 +24,7 @@ fn rollback_edits_push<T>(edits: &mut Vec<(T, RollbackValueCheck)>, key: T, _val
 //   this is used to get the edit _value_ out of the lookupmap, for used in the subsequent `put_all`
 //   command.
 #[cfg(not(rollback_value_check))]
-fn rollback_check_pre_bottom_commit<T>(edits: Vec<(T, RollbackValueCheck)>, lookup_map: &mut HashMap<T, Vec<String>>) -> Vec<(T, String)>
+fn rollback_check_pre_bottom_commit<T>(edits: Vec<(T, RollbackValueCheck)>, lookup_map: &mut HashMap<T, Vec<Vec<u8>>>) -> Vec<(T, Vec<u8>)>
 where T: Eq + Hash + Clone {
     for (_, edit_history) in lookup_map.iter_mut() {
         edit_history.reverse();
@@ -40,18 +40,18 @@ where T: Eq + Hash + Clone {
 }
 
 #[cfg(rollback_value_check)]
-fn rollback_value_check(value: &String, check: &RollbackValueCheck) {
+fn rollback_value_check(value: &Vec<u8>, check: &RollbackValueCheck) {
     assert_eq!(value, check)
 }
 #[cfg(rollback_value_check)]
-fn rollback_edits_push<T>(edits: &mut Vec<(T, RollbackValueCheck)>, key: T, value: &String)
+fn rollback_edits_push<T>(edits: &mut Vec<(T, RollbackValueCheck)>, key: T, value: &Vec<u8>)
 where T: Eq + Hash + Clone {
     edits.push((key, value.clone()));
 }
 // this function is used to check the lookup map when committing at the "bottom" of the
 //   wrapper -- i.e., when committing to the underlying store.
 #[cfg(rollback_value_check)]
-fn rollback_check_pre_bottom_commit<T>(edits: Vec<(T, RollbackValueCheck)>, lookup_map: &mut HashMap<T, Vec<String>>) -> Vec<(T, String)>
+fn rollback_check_pre_bottom_commit<T>(edits: Vec<(T, RollbackValueCheck)>, lookup_map: &mut HashMap<T, Vec<Vec<u8>>>) -> Vec<(T, Vec<u8>)>
 where T: Eq + Hash + Clone {
     for (_, edit_history) in lookup_map.iter_mut() {
         edit_history.reverse();
@@ -74,8 +74,10 @@ pub struct RollbackWrapper <'a> {
     // lookup_map is a history of edits for a given key.
     //   in order of least-recent to most-recent at the tail.
     //   this allows ~ O(1) lookups, and ~ O(1) commits, roll-backs (amortized by # of PUTs).
-    lookup_map: HashMap<String, Vec<String>>,
-    metadata_lookup_map: HashMap<(QualifiedContractIdentifier, String), Vec<String>>,
+    //   values are kept as they will be stored (see ClaritySerializable::serialize_to_store),
+    //   and metadata is kept as the bytes of its text.
+    lookup_map: HashMap<String, Vec<Vec<u8>>>,
+    metadata_lookup_map: HashMap<(QualifiedContractIdentifier, String), Vec<Vec<u8>>>,
     // stack keeps track of the most recent rollback context, which tells us which
     //   edits were performed by which context. at the moment, each context's edit history
     //   is a separate Vec which must be drained into the parent on commits, meaning that
@@ -86,7 +88,7 @@ pub struct RollbackWrapper <'a> {
     stack: Vec<RollbackContext>
 }
 
-fn rollback_lookup_map<T>(key: &T, value: &RollbackValueCheck, lookup_map: &mut HashMap<T, Vec<String>>) -> String
+fn rollback_lookup_map<T>(key: &T, value: &RollbackValueCheck, lookup_map: &mut HashMap<T, Vec<Vec<u8>>>) -> Vec<u8>
 where T: Eq + Hash + Clone {
     let popped_value;
     let remove_edit_deque = {
@@ -151,6 +153,9 @@ impl <'a> RollbackWrapper <'a> {
             let metadata_edits = rollback_check_pre_bottom_commit(
                 last_item.metadata_edits, &mut self.metadata_lookup_map);
             if metadata_edits.len() > 0 {
+                let metadata_edits = metadata_edits.into_iter()
+                    .map(|(key, value)| (key, String::from_utf8(value).expect("ERROR: Clarity VM stored non-UTF-8 metadata")))
+                    .collect();
                 self.store.put_all_metadata(metadata_edits);
             }
         } else {
@@ -166,7 +171,7 @@ impl <'a> RollbackWrapper <'a> {
     }
 }
 
-fn inner_put<T>(lookup_map: &mut HashMap<T, Vec<String>>, edits: &mut Vec<(T, RollbackValueCheck)>, key: T, value: String)
+fn inner_put<T>(lookup_map: &mut HashMap<T, Vec<Vec<u8>>>, edits: &mut Vec<(T, RollbackValueCheck)>, key: T, value: Vec<u8>)
 where T: Eq + Hash + Clone {
     if !lookup_map.contains_key(&key) {
         lookup_map.insert(key.clone(), Vec::new());
@@ -177,11 +182,11 @@ where T: Eq + Hash + Clone {
 }
 
 impl <'a> RollbackWrapper <'a> {
-    pub fn put(&mut self, key: &str, value: &str) {
+    pub fn put<T: ClaritySerializable>(&mut self, key: &str, value: &T) {
         let current = self.stack.last_mut()
             .expect("ERROR: Clarity VM attempted PUT on non-nested context.");
 
-        inner_put(&mut self.lookup_map, &mut current.edits, key.to_string(), value.to_string())
+        inner_put(&mut self.lookup_map, &mut current.edits, key.to_string(), value.serialize_to_store())
     }
 
     pub fn set_block_hash(&mut self, bhh: BlockHeaderHash) -> Result<BlockHeaderHash> {
@@ -194,10 +199,10 @@ impl <'a> RollbackWrapper <'a> {
 
         let lookup_result = self.lookup_map.get(key)
             .and_then(|x| x.last())
-            .map(|x| T::deserialize(x));
+            .map(|x| T::deserialize_from_store(x));
 
         lookup_result
-            .or_else(|| self.store.get(key).map(|x| T::deserialize(&x)))
+            .or_else(|| self.store.get(key).map(|x| T::deserialize_from_store(&x)))
     }
 
     pub fn get_value(&mut self, key: &str, expected: &TypeSignature) -> Option<Value> {
@@ -206,10 +211,10 @@ impl <'a> RollbackWrapper <'a> {
 
         let lookup_result = self.lookup_map.get(key)
             .and_then(|x| x.last())
-            .map(|x| Value::deserialize(x, expected));
+            .map(|x| Value::deserialize_from_store(x, expected));
 
         lookup_result
-            .or_else(|| self.store.get(key).map(|x| Value::deserialize(&x, expected)))
+            .or_else(|| self.store.get(key).map(|x| Value::deserialize_from_store(&x, expected)))
     }
 
     pub fn get_current_block_height(&mut self) -> u32 {
@@ -232,7 +237,7 @@ impl <'a> RollbackWrapper <'a> {
 
         let metadata_key = (contract.clone(), key.to_string());
 
-        inner_put(&mut self.metadata_lookup_map, &mut current.metadata_edits, metadata_key, value.to_string())
+        inner_put(&mut self.metadata_lookup_map, &mut current.metadata_edits, metadata_key, value.as_bytes().to_vec())
     }
 
     // Throws a NoSuchContract error if contract doesn't exist,
@@ -245,10 +250,10 @@ impl <'a> RollbackWrapper <'a> {
         //  (&A, &B) into &(A, B).
         let metadata_key = (contract.clone(), key.to_string());
         let lookup_result = self.metadata_lookup_map.get(&metadata_key)
-            .and_then(|x| x.last().cloned());
+            .and_then(|x| x.last());
 
         match lookup_result {
-            Some(x) => Ok(Some(x)),
+            Some(x) => Ok(Some(String::from_utf8(x.clone()).expect("ERROR: Clarity VM stored non-UTF-8 metadata"))),
             None => {
                 self.store.get_metadata(contract, key)
             }
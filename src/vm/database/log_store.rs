@@ -0,0 +1,772 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufReader, Read, Write, Seek, SeekFrom};
+
+use chainstate::burn::BlockHeaderHash;
+
+use vm::database::SideStore;
+use vm::errors::{InterpreterError, InterpreterResult as Result, IncomparableError};
+
+const LOG_FAIL_MESSAGE: &str = "PANIC: Log store failure in Smart Contract VM.";
+
+/// Buffered records are written out once there are this many bytes of them, even if no save
+///   point has been committed yet.
+const MAX_WRITE_BUFFER: usize = 16 * 1024 * 1024;
+
+/// The log is compacted once it holds at least this many bytes of dead records (and they
+///   outnumber the live ones).
+const COMPACTION_MIN_DEAD_BYTES: u64 = 64 * 1024 * 1024;
+
+// record tags.  Each record is a tag byte, followed by a fixed number of fields (depending on the
+//   tag), each of which is a 4-byte big-endian length and then that many bytes.
+const RECORD_PUT: u8 = 1;                       // key, value
+const RECORD_METADATA_PUT: u8 = 2;              // block, key, value
+const RECORD_METADATA_MOVE: u8 = 3;             // from-block, to-block
+const RECORD_METADATA_DELETE: u8 = 4;           // block
+const RECORD_METADATA_DELETE_MOVED: u8 = 5;     // (no fields)
+const RECORD_COMMIT: u8 = 6;                    // (no fields)
+
+/// Where a value lives in the log
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ValueLocation {
+    /// offset of the value's bytes
+    offset: u64,
+    /// length of the value
+    len: u32,
+    /// length of the whole record that holds the value
+    record_len: u64
+}
+
+/// An operation on the index, as read back from (or just appended to) the log
+enum LogOp {
+    Put(String, ValueLocation),
+    MetadataPut(String, String, ValueLocation),
+    MetadataMove(String, String),
+    MetadataDelete(String),
+    MetadataDeleteMoved
+}
+
+/// The prior state of part of the index, to be restored on rollback
+enum UndoEntry {
+    Data(String, Option<ValueLocation>),
+    Metadata(String, String, Option<ValueLocation>),
+    MetadataBlock(String, Option<HashMap<String, ValueLocation>>)
+}
+
+struct SavePoint {
+    key: BlockHeaderHash,
+    log_len: u64,
+    undo: Vec<UndoEntry>
+}
+
+/// A side store kept as an append-only log of records, with an in-memory index from each key
+///   to where its value sits in the log.
+/// Writes only ever append to the log, and are buffered in memory until the outermost save point
+///   is committed.  At that point, a commit record is appended and the log is synced.  On open,
+///   the log is replayed to rebuild the index, and anything after the last commit record (i.e.
+///   the writes of a block that was being processed when the node stopped) is truncated away.
+/// Rolling back a save point truncates the log back to where it was when the save point began,
+///   and undoes the index changes made since then.
+/// Overwritten and deleted records stay in the log until it is compacted, which happens on
+///   commit once they make up most of it.
+pub struct LogStore {
+    path: String,
+    file: File,
+    /// number of bytes of the log that are on disk
+    flushed_len: u64,
+    /// records appended since the last flush
+    write_buf: Vec<u8>,
+    data: HashMap<String, ValueLocation>,
+    /// block hash (or the name it was moved to) --> metadata key --> value
+    metadata: HashMap<String, HashMap<String, ValueLocation>>,
+    /// number of bytes of the log taken up by records in the index
+    live_bytes: u64,
+    savepoints: Vec<SavePoint>
+}
+
+fn io_error(err: io::Error) -> InterpreterError {
+    InterpreterError::SideStoreIOError(IncomparableError { err })
+}
+
+fn read_len<R: Read>(fd: &mut R) -> io::Result<u32> {
+    let mut len_bytes = [0u8; 4];
+    fd.read_exact(&mut len_bytes)?;
+    Ok(u32::from_be_bytes(len_bytes))
+}
+
+fn read_string<R: Read>(fd: &mut R) -> io::Result<String> {
+    let len = read_len(fd)?;
+    let mut buf = vec![0u8; len as usize];
+    fd.read_exact(&mut buf)?;
+    String::from_utf8(buf)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Non-UTF-8 string in log"))
+}
+
+/// Skip over a value, returning its length
+fn skip_value<R: Read>(fd: &mut R) -> io::Result<u32> {
+    let len = read_len(fd)?;
+    let skipped = io::copy(&mut fd.take(len as u64), &mut io::sink())?;
+    if skipped != len as u64 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated value in log"));
+    }
+    Ok(len)
+}
+
+fn metadata_key(contract_hash: &str, key: &str) -> String {
+    format!("clr-meta::{}::{}", contract_hash, key)
+}
+
+impl LogStore {
+    /// Open the log at the given path, creating it if it does not exist, and rebuild the index
+    ///   from it.
+    pub fn open(path: &str) -> Result<LogStore> {
+        let file = OpenOptions::new().read(true).append(true).create(true).open(path)
+            .map_err(io_error)?;
+
+        let mut store = LogStore {
+            path: path.to_string(),
+            file: file,
+            flushed_len: 0,
+            write_buf: vec![],
+            data: HashMap::new(),
+            metadata: HashMap::new(),
+            live_bytes: 0,
+            savepoints: vec![]
+        };
+
+        let file_len = store.file.metadata().map_err(io_error)?.len();
+        let committed_len = store.replay()?;
+        if committed_len < file_len {
+            warn!("Dropping {} uncommitted bytes from the end of {}", file_len - committed_len, path);
+            store.file.set_len(committed_len).map_err(io_error)?;
+        }
+        store.flushed_len = committed_len;
+
+        Ok(store)
+    }
+
+    /// Apply every committed record in the log to the index.  Returns the length of the log up
+    ///   to and including the last commit record.
+    fn replay(&mut self) -> Result<u64> {
+        let mut fd = BufReader::new(File::open(&self.path).map_err(io_error)?);
+        let mut offset = 0;
+        let mut committed_len = 0;
+        let mut pending = vec![];
+
+        loop {
+            let mut tag = [0u8; 1];
+            match fd.read(&mut tag) {
+                Ok(0) => break,
+                Ok(_) => {},
+                Err(e) => return Err(io_error(e).into())
+            }
+
+            match LogStore::read_op(&mut fd, tag[0], offset) {
+                Ok((Some(op), record_len)) => {
+                    pending.push(op);
+                    offset += record_len;
+                },
+                Ok((None, record_len)) => {
+                    for op in pending.drain(..) {
+                        self.apply(op);
+                    }
+                    offset += record_len;
+                    committed_len = offset;
+                },
+                Err(e) => {
+                    // most likely a write that was cut short
+                    warn!("Unreadable record at offset {} of {}: {:?}", offset, &self.path, &e);
+                    break;
+                }
+            }
+        }
+
+        Ok(committed_len)
+    }
+
+    /// Read the rest of a record whose tag has been read.  Returns the operation it encodes
+    ///   (None for a commit record) and the length of the whole record.
+    fn read_op<R: Read>(fd: &mut R, tag: u8, offset: u64) -> io::Result<(Option<LogOp>, u64)> {
+        match tag {
+            RECORD_PUT => {
+                let key = read_string(fd)?;
+                let value_offset = offset + 1 + 4 + (key.len() as u64) + 4;
+                let len = skip_value(fd)?;
+                let record_len = value_offset + (len as u64) - offset;
+                Ok((Some(LogOp::Put(key, ValueLocation { offset: value_offset, len, record_len })), record_len))
+            },
+            RECORD_METADATA_PUT => {
+                let block = read_string(fd)?;
+                let key = read_string(fd)?;
+                let value_offset = offset + 1 + 4 + (block.len() as u64) + 4 + (key.len() as u64) + 4;
+                let len = skip_value(fd)?;
+                let record_len = value_offset + (len as u64) - offset;
+                Ok((Some(LogOp::MetadataPut(block, key, ValueLocation { offset: value_offset, len, record_len })), record_len))
+            },
+            RECORD_METADATA_MOVE => {
+                let from = read_string(fd)?;
+                let to = read_string(fd)?;
+                let record_len = 1 + 4 + (from.len() as u64) + 4 + (to.len() as u64);
+                Ok((Some(LogOp::MetadataMove(from, to)), record_len))
+            },
+            RECORD_METADATA_DELETE => {
+                let block = read_string(fd)?;
+                let record_len = 1 + 4 + (block.len() as u64);
+                Ok((Some(LogOp::MetadataDelete(block)), record_len))
+            },
+            RECORD_METADATA_DELETE_MOVED => {
+                Ok((Some(LogOp::MetadataDeleteMoved), 1))
+            },
+            RECORD_COMMIT => {
+                Ok((None, 1))
+            },
+            _ => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unknown record tag {}", tag)))
+            }
+        }
+    }
+
+    /// Length of the log, including buffered records
+    pub fn log_len(&self) -> u64 {
+        self.flushed_len + (self.write_buf.len() as u64)
+    }
+
+    /// Number of bytes of the log taken up by records that have been overwritten or deleted
+    pub fn dead_bytes(&self) -> u64 {
+        self.log_len() - self.live_bytes
+    }
+
+    /// Append a record.  Returns the location of its last field, which is where the value is for
+    ///   records that have one.
+    fn append_record(&mut self, tag: u8, fields: &[&[u8]]) -> ValueLocation {
+        let start = self.log_len();
+        self.write_buf.push(tag);
+        for field in fields.iter() {
+            self.write_buf.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            self.write_buf.extend_from_slice(field);
+        }
+        let end = self.log_len();
+        let len = fields.last().map(|field| field.len()).unwrap_or(0);
+
+        if self.write_buf.len() >= MAX_WRITE_BUFFER {
+            self.flush();
+        }
+
+        ValueLocation { offset: end - (len as u64), len: len as u32, record_len: end - start }
+    }
+
+    fn flush(&mut self) {
+        if self.write_buf.len() == 0 {
+            return;
+        }
+        self.file.write_all(&self.write_buf).expect(LOG_FAIL_MESSAGE);
+        self.flushed_len += self.write_buf.len() as u64;
+        self.write_buf.clear();
+    }
+
+    /// Drop everything appended after the given log length
+    fn truncate(&mut self, log_len: u64) {
+        if log_len >= self.flushed_len {
+            self.write_buf.truncate((log_len - self.flushed_len) as usize);
+        }
+        else {
+            self.write_buf.clear();
+            self.file.set_len(log_len).expect(LOG_FAIL_MESSAGE);
+            self.flushed_len = log_len;
+        }
+    }
+
+    /// Make everything appended so far durable
+    fn commit_log(&mut self) {
+        self.append_record(RECORD_COMMIT, &[]);
+        self.flush();
+        self.file.sync_data().expect(LOG_FAIL_MESSAGE);
+    }
+
+    /// Writes made outside of any save point are committed right away
+    fn autocommit(&mut self) {
+        if self.savepoints.len() == 0 {
+            self.commit_log();
+        }
+    }
+
+    fn read_value(&mut self, loc: &ValueLocation) -> Vec<u8> {
+        let mut buf = vec![0u8; loc.len as usize];
+        if loc.offset >= self.flushed_len {
+            let start = (loc.offset - self.flushed_len) as usize;
+            buf.copy_from_slice(&self.write_buf[start..(start + loc.len as usize)]);
+        }
+        else {
+            self.file.seek(SeekFrom::Start(loc.offset)).expect(LOG_FAIL_MESSAGE);
+            self.file.read_exact(&mut buf).expect(LOG_FAIL_MESSAGE);
+        }
+        buf
+    }
+
+    fn set_data(&mut self, key: String, loc: Option<ValueLocation>) -> Option<ValueLocation> {
+        let prior = match loc {
+            Some(loc) => {
+                self.live_bytes += loc.record_len;
+                self.data.insert(key, loc)
+            },
+            None => self.data.remove(&key)
+        };
+        if let Some(ref prior) = prior {
+            self.live_bytes -= prior.record_len;
+        }
+        prior
+    }
+
+    fn set_metadata(&mut self, block: String, key: String, loc: Option<ValueLocation>) -> Option<ValueLocation> {
+        let prior = match loc {
+            Some(loc) => {
+                self.live_bytes += loc.record_len;
+                self.metadata.entry(block).or_insert_with(HashMap::new).insert(key, loc)
+            },
+            None => {
+                let (prior, now_empty) = match self.metadata.get_mut(&block) {
+                    Some(entries) => (entries.remove(&key), entries.len() == 0),
+                    None => (None, false)
+                };
+                if now_empty {
+                    self.metadata.remove(&block);
+                }
+                prior
+            }
+        };
+        if let Some(ref prior) = prior {
+            self.live_bytes -= prior.record_len;
+        }
+        prior
+    }
+
+    fn set_metadata_block(&mut self, block: String, entries: Option<HashMap<String, ValueLocation>>) -> Option<HashMap<String, ValueLocation>> {
+        let prior = match entries {
+            Some(entries) => {
+                self.live_bytes += entries.values().map(|loc| loc.record_len).sum::<u64>();
+                self.metadata.insert(block, entries)
+            },
+            None => self.metadata.remove(&block)
+        };
+        if let Some(ref prior) = prior {
+            self.live_bytes -= prior.values().map(|loc| loc.record_len).sum::<u64>();
+        }
+        prior
+    }
+
+    fn journal(&mut self, entry: UndoEntry) {
+        if let Some(savepoint) = self.savepoints.last_mut() {
+            savepoint.undo.push(entry);
+        }
+    }
+
+    fn undo(&mut self, entry: UndoEntry) {
+        match entry {
+            UndoEntry::Data(key, loc) => {
+                self.set_data(key, loc);
+            },
+            UndoEntry::Metadata(block, key, loc) => {
+                self.set_metadata(block, key, loc);
+            },
+            UndoEntry::MetadataBlock(block, entries) => {
+                self.set_metadata_block(block, entries);
+            }
+        }
+    }
+
+    /// Apply an operation to the index.  Returns the number of metadata entries deleted.
+    fn apply(&mut self, op: LogOp) -> usize {
+        match op {
+            LogOp::Put(key, loc) => {
+                let prior = self.set_data(key.clone(), Some(loc));
+                self.journal(UndoEntry::Data(key, prior));
+                0
+            },
+            LogOp::MetadataPut(block, key, loc) => {
+                let prior = self.set_metadata(block.clone(), key.clone(), Some(loc));
+                self.journal(UndoEntry::Metadata(block, key, prior));
+                0
+            },
+            LogOp::MetadataMove(from, to) => {
+                let moved = self.set_metadata_block(from.clone(), None);
+                self.journal(UndoEntry::MetadataBlock(from, moved.clone()));
+                for (key, loc) in moved.unwrap_or_default().into_iter() {
+                    let prior = self.set_metadata(to.clone(), key.clone(), Some(loc));
+                    self.journal(UndoEntry::Metadata(to.clone(), key, prior));
+                }
+                0
+            },
+            LogOp::MetadataDelete(block) => {
+                let deleted = self.set_metadata_block(block.clone(), None);
+                let num_deleted = deleted.as_ref().map(|entries| entries.len()).unwrap_or(0);
+                self.journal(UndoEntry::MetadataBlock(block, deleted));
+                num_deleted
+            },
+            LogOp::MetadataDeleteMoved => {
+                let moved_blocks : Vec<String> = self.metadata.keys().filter(|block| block.len() != 64).cloned().collect();
+                let mut num_deleted = 0;
+                for block in moved_blocks.into_iter() {
+                    num_deleted += self.apply(LogOp::MetadataDelete(block));
+                }
+                num_deleted
+            }
+        }
+    }
+
+    fn write_put(&mut self, key: &str, value: &[u8]) {
+        let loc = self.append_record(RECORD_PUT, &[key.as_bytes(), value]);
+        self.apply(LogOp::Put(key.to_string(), loc));
+    }
+
+    fn write_metadata_put(&mut self, block: &str, key: &str, value: &[u8]) {
+        let loc = self.append_record(RECORD_METADATA_PUT, &[block.as_bytes(), key.as_bytes(), value]);
+        self.apply(LogOp::MetadataPut(block.to_string(), key.to_string(), loc));
+    }
+
+    fn write_metadata_move(&mut self, from: &str, to: &str) {
+        if !self.metadata.contains_key(from) {
+            return;
+        }
+        self.append_record(RECORD_METADATA_MOVE, &[from.as_bytes(), to.as_bytes()]);
+        self.apply(LogOp::MetadataMove(from.to_string(), to.to_string()));
+        self.autocommit();
+    }
+
+    /// Rewrite the log so it only holds the records in the index.  Must not be called while a
+    ///   save point is open.
+    pub fn compact(&mut self) -> Result<()> {
+        assert!(self.savepoints.len() == 0, "BUG: tried to compact the log store with an open save point");
+
+        let compact_path = format!("{}.compact", &self.path);
+        if fs::metadata(&compact_path).is_ok() {
+            fs::remove_file(&compact_path).map_err(io_error)?;
+        }
+
+        let mut compacted = LogStore::open(&compact_path)?;
+
+        let data : Vec<(String, ValueLocation)> = self.data.iter().map(|(key, loc)| (key.clone(), loc.clone())).collect();
+        for (key, loc) in data.into_iter() {
+            let value = self.read_value(&loc);
+            compacted.write_put(&key, &value);
+        }
+
+        let metadata : Vec<(String, String, ValueLocation)> = self.metadata.iter()
+            .flat_map(|(block, entries)| entries.iter().map(move |(key, loc)| (block.clone(), key.clone(), loc.clone())))
+            .collect();
+        for (block, key, loc) in metadata.into_iter() {
+            let value = self.read_value(&loc);
+            compacted.write_metadata_put(&block, &key, &value);
+        }
+
+        compacted.commit_log();
+        fs::rename(&compact_path, &self.path).map_err(io_error)?;
+
+        debug!("Compacted {} from {} to {} bytes", &self.path, self.log_len(), compacted.log_len());
+
+        let LogStore { file, flushed_len, write_buf, data, metadata, live_bytes, .. } = compacted;
+        self.file = file;
+        self.flushed_len = flushed_len;
+        self.write_buf = write_buf;
+        self.data = data;
+        self.metadata = metadata;
+        self.live_bytes = live_bytes;
+        Ok(())
+    }
+
+    fn find_savepoint(&self, key: &BlockHeaderHash) -> Option<usize> {
+        self.savepoints.iter().rposition(|savepoint| savepoint.key == *key)
+    }
+}
+
+impl SideStore for LogStore {
+    fn put(&mut self, key: &str, value: &[u8]) {
+        // values are keyed by their hash, so the same value gets written over and over again
+        if let Some(loc) = self.data.get(key).cloned() {
+            if loc.len as usize == value.len() && self.read_value(&loc) == value {
+                return;
+            }
+        }
+        self.write_put(key, value);
+        self.autocommit();
+    }
+
+    fn get(&mut self, key: &str) -> Option<Vec<u8>> {
+        match self.data.get(key).cloned() {
+            Some(loc) => Some(self.read_value(&loc)),
+            None => None
+        }
+    }
+
+    fn has_entry(&mut self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+
+    fn num_entries(&mut self) -> u64 {
+        self.data.len() as u64
+    }
+
+    fn insert_metadata(&mut self, bhh: &BlockHeaderHash, contract_hash: &str, key: &str, value: &str) {
+        self.write_metadata_put(&bhh.to_hex(), &metadata_key(contract_hash, key), value.as_bytes());
+        self.autocommit();
+    }
+
+    fn get_metadata(&mut self, bhh: &BlockHeaderHash, contract_hash: &str, key: &str) -> Option<String> {
+        let loc_opt = self.metadata.get(&bhh.to_hex())
+            .and_then(|entries| entries.get(&metadata_key(contract_hash, key)))
+            .cloned();
+        match loc_opt {
+            Some(loc) => Some(String::from_utf8(self.read_value(&loc)).expect(LOG_FAIL_MESSAGE)),
+            None => None
+        }
+    }
+
+    fn commit_metadata_to(&mut self, from: &BlockHeaderHash, to: &BlockHeaderHash) {
+        self.write_metadata_move(&from.to_hex(), &to.to_hex());
+    }
+
+    fn move_metadata_to(&mut self, from: &BlockHeaderHash, to: &str) {
+        self.write_metadata_move(&from.to_hex(), to);
+    }
+
+    fn delete_metadata_at(&mut self, bhh: &BlockHeaderHash) -> usize {
+        let block = bhh.to_hex();
+        if !self.metadata.contains_key(&block) {
+            return 0;
+        }
+        self.append_record(RECORD_METADATA_DELETE, &[block.as_bytes()]);
+        let num_deleted = self.apply(LogOp::MetadataDelete(block));
+        self.autocommit();
+        num_deleted
+    }
+
+    fn delete_moved_metadata(&mut self) -> usize {
+        if !self.metadata.keys().any(|block| block.len() != 64) {
+            return 0;
+        }
+        self.append_record(RECORD_METADATA_DELETE_MOVED, &[]);
+        let num_deleted = self.apply(LogOp::MetadataDeleteMoved);
+        self.autocommit();
+        num_deleted
+    }
+
+    fn begin(&mut self, key: &BlockHeaderHash) {
+        let log_len = self.log_len();
+        self.savepoints.push(SavePoint { key: key.clone(), log_len, undo: vec![] });
+    }
+
+    fn rollback(&mut self, key: &BlockHeaderHash) {
+        let idx = self.find_savepoint(key)
+            .unwrap_or_else(|| panic!("PANIC: Failed to roll back log store save point {} in Smart Contract VM.", key));
+
+        let log_len = self.savepoints[idx].log_len;
+        while self.savepoints.len() > idx {
+            let savepoint = self.savepoints.pop().expect("BUG: save point stack shrank");
+            for entry in savepoint.undo.into_iter().rev() {
+                self.undo(entry);
+            }
+        }
+        self.truncate(log_len);
+    }
+
+    fn commit(&mut self, key: &BlockHeaderHash) {
+        let idx = self.find_savepoint(key)
+            .unwrap_or_else(|| panic!("PANIC: Failed to commit log store save point {} in Smart Contract VM.", key));
+
+        let released = self.savepoints.split_off(idx);
+        match self.savepoints.last_mut() {
+            Some(parent) => {
+                // the enclosing save point can still be rolled back
+                for savepoint in released.into_iter() {
+                    parent.undo.extend(savepoint.undo);
+                }
+            },
+            None => {
+                self.commit_log();
+                let dead_bytes = self.dead_bytes();
+                if dead_bytes >= COMPACTION_MIN_DEAD_BYTES && dead_bytes > self.live_bytes {
+                    if let Err(e) = self.compact() {
+                        warn!("Failed to compact {}: {:?}", &self.path, &e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::env;
+    use rand::Rng;
+
+    use util::hash::to_hex;
+
+    fn temp_log_path(name: &str) -> String {
+        let mut path = env::temp_dir();
+        let random_bytes = rand::thread_rng().gen::<[u8; 16]>();
+        path.push(format!("{}-{}.log", name, to_hex(&random_bytes)));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn log_store_put_get_reopen() {
+        let path = temp_log_path("log_store_put_get_reopen");
+        let bhh = BlockHeaderHash([1; 32]);
+        {
+            let mut store = LogStore::open(&path).unwrap();
+            store.put("a", b"alpha");
+            store.put("b", b"beta");
+            store.insert_metadata(&bhh, "contract", "k", "meta");
+
+            assert_eq!(store.get("a"), Some(b"alpha".to_vec()));
+            assert_eq!(store.get("c"), None);
+            assert_eq!(store.get_metadata(&bhh, "contract", "k"), Some("meta".to_string()));
+            assert_eq!(store.get_metadata(&bhh, "contract", "j"), None);
+            assert_eq!(store.num_entries(), 2);
+
+            // same value again is not re-appended
+            let log_len = store.log_len();
+            store.put("a", b"alpha");
+            assert_eq!(store.log_len(), log_len);
+        }
+
+        let mut store = LogStore::open(&path).unwrap();
+        assert_eq!(store.get("a"), Some(b"alpha".to_vec()));
+        assert_eq!(store.get("b"), Some(b"beta".to_vec()));
+        assert_eq!(store.get_metadata(&bhh, "contract", "k"), Some("meta".to_string()));
+        assert_eq!(store.num_entries(), 2);
+    }
+
+    #[test]
+    fn log_store_savepoints() {
+        let path = temp_log_path("log_store_savepoints");
+        let mut store = LogStore::open(&path).unwrap();
+        let b1 = BlockHeaderHash([1; 32]);
+        let b2 = BlockHeaderHash([2; 32]);
+        let b3 = BlockHeaderHash([3; 32]);
+
+        store.begin(&b1);
+        store.put("a", b"alpha");
+        store.insert_metadata(&b1, "contract", "k", "one");
+
+        store.begin(&b2);
+        store.put("a", b"aleph");
+        store.put("b", b"beta");
+        store.insert_metadata(&b2, "contract", "k", "two");
+        store.commit_metadata_to(&b2, &b3);
+        assert_eq!(store.get_metadata(&b2, "contract", "k"), None);
+        assert_eq!(store.get_metadata(&b3, "contract", "k"), Some("two".to_string()));
+
+        store.rollback(&b2);
+        assert_eq!(store.get("a"), Some(b"alpha".to_vec()));
+        assert_eq!(store.get("b"), None);
+        assert_eq!(store.get_metadata(&b3, "contract", "k"), None);
+        assert_eq!(store.get_metadata(&b1, "contract", "k"), Some("one".to_string()));
+
+        // inner commits can still be rolled back by the outer save point
+        store.begin(&b2);
+        store.put("c", b"gamma");
+        store.commit(&b2);
+        assert_eq!(store.get("c"), Some(b"gamma".to_vec()));
+
+        store.commit_metadata_to(&b1, &b3);
+        store.commit(&b1);
+        assert_eq!(store.get_metadata(&b3, "contract", "k"), Some("one".to_string()));
+
+        store.begin(&b2);
+        store.put("d", b"delta");
+        assert_eq!(store.delete_metadata_at(&b3), 1);
+        store.rollback(&b2);
+        assert_eq!(store.get("d"), None);
+        assert_eq!(store.get_metadata(&b3, "contract", "k"), Some("one".to_string()));
+
+        // an open save point is lost on reopen
+        store.begin(&b2);
+        store.put("e", b"epsilon");
+        store.flush();
+        drop(store);
+
+        let mut store = LogStore::open(&path).unwrap();
+        assert_eq!(store.get("a"), Some(b"alpha".to_vec()));
+        assert_eq!(store.get("c"), Some(b"gamma".to_vec()));
+        assert_eq!(store.get("e"), None);
+        assert_eq!(store.get_metadata(&b3, "contract", "k"), Some("one".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to commit log store save point")]
+    fn log_store_commit_unknown_savepoint() {
+        let path = temp_log_path("log_store_commit_unknown_savepoint");
+        let mut store = LogStore::open(&path).unwrap();
+        let bhh = BlockHeaderHash([1; 32]);
+        store.begin(&bhh);
+        store.rollback(&bhh);
+        store.commit(&bhh); // shouldn't be on the stack!
+    }
+
+    #[test]
+    fn log_store_torn_write() {
+        let path = temp_log_path("log_store_torn_write");
+        {
+            let mut store = LogStore::open(&path).unwrap();
+            store.put("a", b"alpha");
+            store.put("b", b"beta");
+        }
+
+        // chop off part of the last commit
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+        let mut store = LogStore::open(&path).unwrap();
+        assert_eq!(store.get("a"), Some(b"alpha".to_vec()));
+        assert_eq!(store.get("b"), None);
+
+        // and the log is usable afterwards
+        store.put("b", b"beta");
+        drop(store);
+
+        let mut store = LogStore::open(&path).unwrap();
+        assert_eq!(store.get("b"), Some(b"beta".to_vec()));
+    }
+
+    #[test]
+    fn log_store_moved_metadata_and_compaction() {
+        let path = temp_log_path("log_store_moved_metadata_and_compaction");
+        let mut store = LogStore::open(&path).unwrap();
+        let b1 = BlockHeaderHash([1; 32]);
+        let b2 = BlockHeaderHash([2; 32]);
+
+        for i in 0..10 {
+            store.insert_metadata(&b1, "contract", &format!("k{}", i), &format!("v{}", i));
+            store.insert_metadata(&b2, "contract", &format!("k{}", i), &format!("v{}", i));
+        }
+        store.move_metadata_to(&b1, "moved");
+        assert_eq!(store.get_metadata(&b1, "contract", "k0"), None);
+
+        store.put("a", b"alpha");
+        store.put("a", b"aleph");
+
+        assert_eq!(store.delete_moved_metadata(), 10);
+        assert_eq!(store.delete_moved_metadata(), 0);
+        assert!(store.dead_bytes() > 0);
+
+        let live_len = store.log_len() - store.dead_bytes();
+        store.compact().unwrap();
+        assert_eq!(store.dead_bytes(), 1);      // just the commit record
+        assert_eq!(store.log_len(), live_len + 1);
+
+        assert_eq!(store.get("a"), Some(b"aleph".to_vec()));
+        for i in 0..10 {
+            assert_eq!(store.get_metadata(&b2, "contract", &format!("k{}", i)), Some(format!("v{}", i)));
+        }
+        drop(store);
+
+        let mut store = LogStore::open(&path).unwrap();
+        assert_eq!(store.get("a"), Some(b"aleph".to_vec()));
+        assert_eq!(store.num_entries(), 1);
+        assert_eq!(store.get_metadata(&b2, "contract", "k9"), Some("v9".to_string()));
+    }
+}
@@ -2,11 +2,13 @@ use std::path::PathBuf;
 
 use vm::types::{QualifiedContractIdentifier};
 use vm::errors::{InterpreterError, CheckErrors, InterpreterResult as Result, IncomparableError, RuntimeErrorType};
-use vm::database::{SqliteConnection, ClarityDatabase, HeadersDB, NULL_HEADER_DB};
+use vm::database::{SqliteConnection, LogStore, SideStore, SideStoreType, ClarityDatabase, HeadersDB, NULL_HEADER_DB,
+                   ClaritySerializable, ClarityDeserializable};
+use vm::database::structures::{is_binary_stored_value, tagged_text_form};
 use vm::analysis::{AnalysisDatabase};
 use chainstate::stacks::index::marf::MARF;
 use chainstate::stacks::index::{MARFValue, Error as MarfError, TrieHash};
-use chainstate::stacks::index::storage::{TrieFileStorage};
+use chainstate::stacks::index::storage::{TrieFileStorage, TrieStorageLayout};
 use chainstate::burn::{VRFSeed, BlockHeaderHash};
 use burnchains::BurnchainHeaderHash;
 use std::convert::TryInto;
@@ -24,7 +26,9 @@ pub struct MarfedKV {
     marf: MARF,
     // Since the MARF only stores 32 bytes of value,
     //   we need another storage
-    side_store: SqliteConnection
+    side_store: Box<dyn SideStore>,
+    // writes held back from the MARF until release_writes() (see hold_writes())
+    held_writes: Option<Vec<(String, Vec<u8>)>>
 }
 
 pub struct MemoryBackingStore {
@@ -35,10 +39,11 @@ pub struct MemoryBackingStore {
 //    will _panic_. The rationale for this is that under no condition should the interpreter
 //    attempt to continue processing in the event of an unexpected storage error.
 pub trait ClarityBackingStore {
-    /// put K-V data into the committed datastore
-    fn put_all(&mut self, items: Vec<(String, String)>);
+    /// put K-V data into the committed datastore.  values are stored as given by
+    ///   ClaritySerializable::serialize_to_store()
+    fn put_all(&mut self, items: Vec<(String, Vec<u8>)>);
     /// fetch K-V out of the committed datastore
-    fn get(&mut self, key: &str) -> Option<String>;
+    fn get(&mut self, key: &str) -> Option<Vec<u8>>;
     fn has_entry(&mut self, key: &str) -> bool {
         self.get(key).is_some()
     }
@@ -57,14 +62,13 @@ pub trait ClarityBackingStore {
 
     fn get_open_chain_tip_height(&mut self) -> u32;
     fn get_open_chain_tip(&mut self) -> BlockHeaderHash;
-    fn get_side_store(&mut self) -> &mut SqliteConnection;
+    fn get_side_store(&mut self) -> &mut dyn SideStore;
 
     /// The contract commitment is the hash of the contract, plus the block height in
     ///   which the contract was initialized.
-    fn make_contract_commitment(&mut self, contract_hash: Sha512Trunc256Sum) -> String {
+    fn make_contract_commitment(&mut self, contract_hash: Sha512Trunc256Sum) -> ContractCommitment {
         let block_height = self.get_open_chain_tip_height();
-        let cc = ContractCommitment { hash: contract_hash, block_height };
-        cc.serialize()
+        ContractCommitment { hash: contract_hash, block_height }
     }
 
     /// This function is used to obtain a committed contract hash, and the block header hash of the block
@@ -72,7 +76,7 @@ pub trait ClarityBackingStore {
     ///   store.
     fn get_contract_hash(&mut self, contract: &QualifiedContractIdentifier) -> Result<(BlockHeaderHash, Sha512Trunc256Sum)> {
         let key = MarfedKV::make_contract_hash_key(contract);
-        let contract_commitment = self.get(&key).map(|x| ContractCommitment::deserialize(&x))
+        let contract_commitment = self.get(&key).map(|x| ContractCommitment::deserialize_from_store(&x))
             .ok_or_else(|| { CheckErrors::NoSuchContract(contract.to_string()) })?;
         let ContractCommitment { block_height, hash: contract_hash } = contract_commitment;
         let bhh = self.get_block_at_height(block_height)
@@ -97,16 +101,19 @@ pub trait ClarityBackingStore {
     }
 }
 
-struct ContractCommitment {
+pub struct ContractCommitment {
     pub hash: Sha512Trunc256Sum,
     pub block_height: u32
 }
 
-impl ContractCommitment {
-    pub fn serialize(&self) -> String {
+impl ClaritySerializable for ContractCommitment {
+    fn serialize(&self) -> String {
         format!("{}{}", self.hash, to_hex(&self.block_height.to_be_bytes()))
     }
-    pub fn deserialize(input: &str) -> ContractCommitment {
+}
+
+impl ClarityDeserializable<ContractCommitment> for ContractCommitment {
+    fn deserialize(input: &str) -> ContractCommitment {
         assert_eq!(input.len(), 72);
         let hash = Sha512Trunc256Sum::from_hex(&input[0..64]).expect("Hex decode fail.");
         let height_bytes = hex_bytes(&input[64..72]).expect("Hex decode fail.");
@@ -115,8 +122,38 @@ impl ContractCommitment {
     }
 }
 
+impl ContractCommitment {
+    /// Like deserialize(), but returns None instead of panicking on malformed input
+    pub fn try_deserialize(input: &str) -> Option<ContractCommitment> {
+        if input.len() != 72 || !input.is_ascii() {
+            return None;
+        }
+        let hash = Sha512Trunc256Sum::from_hex(&input[0..64]).ok()?;
+        let height_bytes = hex_bytes(&input[64..72]).ok()?;
+        let block_height = u32::from_be_bytes(height_bytes.as_slice().try_into().ok()?);
+        Some(ContractCommitment { hash, block_height })
+    }
+}
+
 impl MarfedKV {
-    pub fn open(path_str: &str, miner_tip: Option<&BlockHeaderHash>) -> Result<MarfedKV> {
+    /// Open a MarfedKV, using whichever kind of side store is already in the directory
+    ///   (SQLite, if there is none yet).  A new MARF is given the requested storage layout.
+    pub fn open(path_str: &str, miner_tip: Option<&BlockHeaderHash>, layout: TrieStorageLayout) -> Result<MarfedKV> {
+        let mut log_path = PathBuf::from(path_str);
+        log_path.push(SideStoreType::Log.file_name());
+
+        let side_store_type =
+            if log_path.exists() {
+                SideStoreType::Log
+            }
+            else {
+                SideStoreType::Sqlite
+            };
+
+        MarfedKV::open_with_side_store(path_str, miner_tip, side_store_type, layout)
+    }
+
+    pub fn open_with_side_store(path_str: &str, miner_tip: Option<&BlockHeaderHash>, side_store_type: SideStoreType, layout: TrieStorageLayout) -> Result<MarfedKV> {
         let mut path = PathBuf::from(path_str);
 
         std::fs::create_dir_all(&path)
@@ -127,14 +164,29 @@ impl MarfedKV {
             .ok_or_else(|| InterpreterError::BadFileName)?
             .to_string();
 
+        // don't start a second side store next to an existing one
+        let other_type = match side_store_type {
+            SideStoreType::Sqlite => SideStoreType::Log,
+            SideStoreType::Log => SideStoreType::Sqlite
+        };
         path.pop();
-        path.push("data.sqlite");
+        path.push(other_type.file_name());
+        if path.exists() {
+            return Err(InterpreterError::InterpreterError(
+                format!("{} already has a {:?} side store", path_str, other_type)).into());
+        }
+
+        path.pop();
+        path.push(side_store_type.file_name());
         let data_path = path.to_str()
             .ok_or_else(|| InterpreterError::BadFileName)?
             .to_string();
 
-        let side_store = SqliteConnection::initialize(&data_path)?;
-        let marf = MARF::from_path(&marf_path, miner_tip)
+        let side_store : Box<dyn SideStore> = match side_store_type {
+            SideStoreType::Sqlite => Box::new(SqliteConnection::initialize(&data_path)?),
+            SideStoreType::Log => Box::new(LogStore::open(&data_path)?)
+        };
+        let marf = MARF::from_path(&marf_path, miner_tip, layout)
             .map_err(|err| InterpreterError::MarfFailure(IncomparableError{ err }))?;
 
         let chain_tip = match miner_tip {
@@ -142,7 +194,7 @@ impl MarfedKV {
             None => TrieFileStorage::block_sentinel()
         };
 
-        Ok( MarfedKV { marf, chain_tip, side_store } )
+        Ok( MarfedKV { marf, chain_tip, side_store, held_writes: None } )
     }
 
     #[cfg(test)]
@@ -154,13 +206,26 @@ impl MarfedKV {
         let random_bytes = rand::thread_rng().gen::<[u8; 32]>();
         path.push(to_hex(&random_bytes));
 
-        let marf = MARF::from_path(path.to_str().expect("Inexplicably non-UTF-8 character in filename"), None)
+        let marf = MARF::from_path(path.to_str().expect("Inexplicably non-UTF-8 character in filename"), None, TrieStorageLayout::FilePerBlock)
             .unwrap();
-        let side_store = SqliteConnection::memory().unwrap();
+        let side_store = Box::new(SqliteConnection::memory().unwrap());
 
         let chain_tip = TrieFileStorage::block_sentinel();
 
-        MarfedKV { marf, chain_tip, side_store }
+        MarfedKV { marf, chain_tip, side_store, held_writes: None }
+    }
+
+    #[cfg(test)]
+    pub fn temporary_with_side_store(side_store_type: SideStoreType) -> MarfedKV {
+        use std::env;
+        use rand::Rng;
+
+        let mut path = env::temp_dir();
+        let random_bytes = rand::thread_rng().gen::<[u8; 32]>();
+        path.push(to_hex(&random_bytes));
+
+        MarfedKV::open_with_side_store(path.to_str().expect("Inexplicably non-UTF-8 character in filename"), None, side_store_type, TrieStorageLayout::FilePerBlock)
+            .unwrap()
     }
 
     pub fn as_clarity_db<'a>(&'a mut self, headers_db: &'a dyn HeadersDB) -> ClarityDatabase<'a> {
@@ -189,6 +254,7 @@ impl MarfedKV {
         self.side_store.begin(&self.chain_tip);
     }
     pub fn rollback(&mut self) {
+        self.held_writes = None;
         self.marf.drop_current();
         self.side_store.rollback(&self.chain_tip);
         self.chain_tip = TrieFileStorage::block_sentinel();
@@ -215,6 +281,46 @@ impl MarfedKV {
         self.marf.commit_to(final_bhh)
             .expect("ERROR: Failed to commit MARF block");
     }
+    /// Drop the tries and contract metadata of blocks that will never be built on again.  No
+    /// remaining block may descend from them.  Tries that are already gone are skipped, so an
+    /// interrupted drop can be retried.  Returns the number of metadata rows deleted, including
+    /// any left behind by commit_for_move().
+    pub fn drop_blocks(&mut self, blocks: &[BlockHeaderHash]) -> Result<usize> {
+        let mut num_deleted = 0;
+        for bhh in blocks.iter() {
+            match self.marf.delete_block(bhh) {
+                Ok(_) | Err(MarfError::NotFoundError) => {},
+                Err(err) => {
+                    return Err(InterpreterError::MarfFailure(IncomparableError{ err }).into());
+                }
+            }
+            num_deleted += self.side_store.delete_metadata_at(bhh);
+        }
+        num_deleted += self.side_store.delete_moved_metadata();
+        Ok(num_deleted)
+    }
+
+    /// Hold back all subsequent data writes from the MARF, so that a whole transaction's worth of
+    ///   writes (e.g. its fee debit _and_ its payload) can be discarded together.  Reads see the
+    ///   held writes.  Releasing them inserts them into the MARF in the order they were made, so
+    ///   the state root is the same as if they had never been held.  Contract metadata is not
+    ///   held; it is only written once a contract has been successfully initialized.
+    pub fn hold_writes(&mut self) {
+        assert!(self.held_writes.is_none(), "FAIL: writes are already being held");
+        self.held_writes = Some(vec![]);
+    }
+    pub fn release_writes(&mut self) {
+        let held_writes = self.held_writes.take()
+            .expect("FAIL: released writes that were not held");
+        if held_writes.len() > 0 {
+            self.insert_all(held_writes);
+        }
+    }
+    pub fn discard_writes(&mut self) {
+        self.held_writes.take()
+            .expect("FAIL: discarded writes that were not held");
+    }
+
     pub fn get_chain_tip(&self) -> &BlockHeaderHash {
         &self.chain_tip
     }
@@ -235,8 +341,20 @@ impl MarfedKV {
         &mut self.marf
     }
 
-    pub fn put(&mut self, key: &str, value: &str) {
-        let marf_value = MARFValue::from_value(value);
+    /// The MARF commits to the hash of a stored value's text form (see vm::database::structures),
+    ///   so that storing values as bytes did not change any state roots.
+    pub fn make_marf_value(value: &[u8]) -> MARFValue {
+        if let Some(text) = tagged_text_form(value) {
+            MARFValue::from_value_bytes(text)
+        } else if is_binary_stored_value(value) {
+            MARFValue::from_value_hex(value)
+        } else {
+            MARFValue::from_value_bytes(value)
+        }
+    }
+
+    pub fn put(&mut self, key: &str, value: &[u8]) {
+        let marf_value = MarfedKV::make_marf_value(value);
         self.side_store.put(&marf_value.to_hex(), value);
 
         self.marf.insert(key, marf_value)
@@ -246,11 +364,24 @@ impl MarfedKV {
     pub fn make_contract_hash_key(contract: &QualifiedContractIdentifier) -> String {
         format!("clarity-contract::{}", contract)
     }
+
+    fn insert_all(&mut self, mut items: Vec<(String, Vec<u8>)>) {
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        for (key, value) in items.drain(..) {
+            let marf_value = MarfedKV::make_marf_value(&value);
+            self.side_store.put(&marf_value.to_hex(), &value);
+            keys.push(key);
+            values.push(marf_value);
+        }
+        self.marf.insert_batch(&keys, values)
+            .expect("ERROR: Unexpected MARF Failure");
+    }
 }
 
 impl ClarityBackingStore for MarfedKV {
-    fn get_side_store(&mut self) -> &mut SqliteConnection {
-        &mut self.side_store
+    fn get_side_store(&mut self) -> &mut dyn SideStore {
+        &mut *self.side_store
     }
 
     fn set_block_hash(&mut self, bhh: BlockHeaderHash) -> Result<BlockHeaderHash> {
@@ -290,7 +421,12 @@ impl ClarityBackingStore for MarfedKV {
             .expect("Attempted to get the open chain tip from an unopened context.")
     }
 
-    fn get(&mut self, key: &str) -> Option<String> {
+    fn get(&mut self, key: &str) -> Option<Vec<u8>> {
+        if let Some(ref held_writes) = self.held_writes {
+            if let Some((_, value)) = held_writes.iter().rev().find(|(held_key, _)| held_key == key) {
+                return Some(value.clone());
+            }
+        }
         self.marf.get(&self.chain_tip, key)
             .or_else(|e| {
                 match e {
@@ -307,17 +443,11 @@ impl ClarityBackingStore for MarfedKV {
             })
     }
 
-    fn put_all(&mut self, mut items: Vec<(String, String)>) {
-        let mut keys = Vec::new();
-        let mut values = Vec::new();
-        for (key, value) in items.drain(..) {
-            let marf_value = MARFValue::from_value(&value);
-            self.side_store.put(&marf_value.to_hex(), &value);
-            keys.push(key);
-            values.push(marf_value);
+    fn put_all(&mut self, mut items: Vec<(String, Vec<u8>)>) {
+        match self.held_writes {
+            Some(ref mut held_writes) => held_writes.append(&mut items),
+            None => self.insert_all(items)
         }
-        self.marf.insert_batch(&keys, values)
-            .expect("ERROR: Unexpected MARF Failure");
     }
 }
 
@@ -346,11 +476,11 @@ impl ClarityBackingStore for MemoryBackingStore {
         Err(RuntimeErrorType::UnknownBlockHeaderHash(bhh).into())
     }
 
-    fn get(&mut self, key: &str) -> Option<String> {
+    fn get(&mut self, key: &str) -> Option<Vec<u8>> {
         self.side_store.get(key)
     }
 
-    fn get_side_store(&mut self) -> &mut SqliteConnection {
+    fn get_side_store(&mut self) -> &mut dyn SideStore {
         &mut self.side_store
     }
 
@@ -374,7 +504,7 @@ impl ClarityBackingStore for MemoryBackingStore {
         0
     }
 
-    fn put_all(&mut self, mut items: Vec<(String, String)>) {
+    fn put_all(&mut self, mut items: Vec<(String, Vec<u8>)>) {
         for (key, value) in items.drain(..) {
             self.side_store.put(&key, &value);
         }
@@ -1,5 +1,7 @@
 pub mod marf;
 mod sqlite;
+mod side_store;
+mod log_store;
 mod structures;
 mod clarity_db;
 mod key_value_wrapper;
@@ -7,7 +9,9 @@ mod key_value_wrapper;
 use std::collections::HashMap;
 
 pub use self::key_value_wrapper::{RollbackWrapper};
-pub use self::clarity_db::{ClarityDatabase, HeadersDB, NULL_HEADER_DB};
-pub use self::structures::{ClaritySerializable, ClarityDeserializable};
+pub use self::clarity_db::{ClarityDatabase, HeadersDB, NULL_HEADER_DB, StoreType};
+pub use self::structures::{ClaritySerializable, ClarityDeserializable, stored_value_text, try_stored_value_text, stored_value_from_text, tagged_text_form};
 pub use self::sqlite::{SqliteConnection};
-pub use self::marf::{MemoryBackingStore, MarfedKV, ClarityBackingStore};
+pub use self::side_store::{SideStore, SideStoreType};
+pub use self::log_store::{LogStore};
+pub use self::marf::{MemoryBackingStore, MarfedKV, ClarityBackingStore, ContractCommitment};
@@ -0,0 +1,60 @@
+use chainstate::burn::BlockHeaderHash;
+
+/// The side store holds everything the MARF cannot: the values whose hashes are committed to
+///   the MARF (keyed by that hash), and the contract metadata written while processing a block
+///   (keyed by block hash, contract, and metadata key).
+/// As with ClarityBackingStore, implementations do not return errors: any failure in the
+///   underlying storage panics, since the interpreter must not continue past it.
+/// Side stores are Send, so that a chainstate can be handed to another thread (e.g. the
+///   RPC server's).
+pub trait SideStore: Send {
+    /// put a value, keyed by its MARF value hash
+    fn put(&mut self, key: &str, value: &[u8]);
+    fn get(&mut self, key: &str) -> Option<Vec<u8>>;
+    fn has_entry(&mut self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+    /// number of (committed or pending) values in the store
+    fn num_entries(&mut self) -> u64;
+
+    fn insert_metadata(&mut self, bhh: &BlockHeaderHash, contract_hash: &str, key: &str, value: &str);
+    fn get_metadata(&mut self, bhh: &BlockHeaderHash, contract_hash: &str, key: &str) -> Option<String>;
+
+    /// re-key all metadata written at block `from` so it is stored at block `to`
+    fn commit_metadata_to(&mut self, from: &BlockHeaderHash, to: &BlockHeaderHash);
+
+    /// set aside all metadata written at block `from` under a name that is not a block hash
+    fn move_metadata_to(&mut self, from: &BlockHeaderHash, to: &str);
+
+    /// Delete all metadata stored at a block.  Returns the number of entries deleted.
+    fn delete_metadata_at(&mut self, bhh: &BlockHeaderHash) -> usize;
+
+    /// Delete all metadata set aside by move_metadata_to().  Returns the number of entries deleted.
+    fn delete_moved_metadata(&mut self) -> usize;
+
+    /// begin, commit, rollback a save point identified by key.
+    ///    Save points nest, and are used to clean up any data from aborted blocks.
+    ///    See MarfedKV::begin() for how these are used.
+    fn begin(&mut self, key: &BlockHeaderHash);
+    fn rollback(&mut self, key: &BlockHeaderHash);
+    fn commit(&mut self, key: &BlockHeaderHash);
+}
+
+/// Which side store implementation to use when creating a new MarfedKV
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SideStoreType {
+    /// rows in a SQLite database (the default)
+    Sqlite,
+    /// an append-only log with an in-memory index (see vm::database::log_store)
+    Log
+}
+
+impl SideStoreType {
+    /// name of the side store's file within the MarfedKV's directory
+    pub fn file_name(&self) -> &'static str {
+        match *self {
+            SideStoreType::Sqlite => "data.sqlite",
+            SideStoreType::Log => "data.log"
+        }
+    }
+}
@@ -4,22 +4,27 @@ use rusqlite::types::{ToSql, FromSql};
 use chainstate::burn::BlockHeaderHash;
 
 use vm::contracts::Contract;
+use vm::database::{SideStore, stored_value_from_text};
 use vm::errors::{Error, InterpreterError, RuntimeErrorType, InterpreterResult as Result, IncomparableError};
 
 const SQL_FAIL_MESSAGE: &str = "PANIC: SQL Failure in Smart Contract VM.";
 
+// stored in the database's user_version.
+//   version 0 stored each value's text form, version 1 stores values as bytes.
+const SIDE_STORE_SCHEMA_VERSION: i64 = 1;
+
 pub struct SqliteConnection {
     conn: Connection
 }
 
-fn sqlite_put(conn: &Connection, key: &str, value: &str) {
+fn sqlite_put(conn: &Connection, key: &str, value: &[u8]) {
     let params: [&dyn ToSql; 2] = [&key, &value];
     conn.execute("REPLACE INTO data_table (key, value) VALUES (?, ?)",
                       &params)
         .expect(SQL_FAIL_MESSAGE);
 }
 
-fn sqlite_get(conn: &Connection, key: &str) -> Option<String> {
+fn sqlite_get(conn: &Connection, key: &str) -> Option<Vec<u8>> {
     let params: [&dyn ToSql; 1] = [&key];
     conn.query_row(
         "SELECT value FROM data_table WHERE key = ?",
@@ -32,16 +37,24 @@ fn sqlite_has_entry(conn: &Connection, key: &str) -> bool {
     sqlite_get(conn, key).is_some()
 }
 
-impl SqliteConnection {
-    pub fn put(&mut self, key: &str, value: &str) {
+impl SideStore for SqliteConnection {
+    fn put(&mut self, key: &str, value: &[u8]) {
         sqlite_put(&self.conn, key, value)
     }
 
-    pub fn get(&mut self, key: &str) -> Option<String> {
+    fn get(&mut self, key: &str) -> Option<Vec<u8>> {
         sqlite_get(&self.conn, key)
     }
 
-    pub fn insert_metadata(&mut self, bhh: &BlockHeaderHash, contract_hash: &str, key: &str, value: &str) {
+    fn num_entries(&mut self) -> u64 {
+        self.conn.query_row::<i64,_,_>(
+            "SELECT COUNT(value) FROM data_table",
+            NO_PARAMS,
+            |row| row.get(0))
+            .expect(SQL_FAIL_MESSAGE) as u64
+    }
+
+    fn insert_metadata(&mut self, bhh: &BlockHeaderHash, contract_hash: &str, key: &str, value: &str) {
         let key = format!("clr-meta::{}::{}", contract_hash, key);
         let params: [&dyn ToSql; 3] = [&bhh, &key, &value.to_string()];
 
@@ -49,7 +62,7 @@ impl SqliteConnection {
             .expect(SQL_FAIL_MESSAGE);
     }
 
-    pub fn commit_metadata_to(&mut self, from: &BlockHeaderHash, to: &BlockHeaderHash) {
+    fn commit_metadata_to(&mut self, from: &BlockHeaderHash, to: &BlockHeaderHash) {
         let params = [to, from];
         self.conn.execute(
             "UPDATE metadata_table SET blockhash = ? WHERE blockhash = ?",
@@ -57,7 +70,7 @@ impl SqliteConnection {
             .expect(SQL_FAIL_MESSAGE);
     }
 
-    pub fn move_metadata_to(&mut self, from: &BlockHeaderHash, to: &str) {
+    fn move_metadata_to(&mut self, from: &BlockHeaderHash, to: &str) {
         let params: [&dyn ToSql; 2] = [&to.to_string(), from];
         self.conn.execute(
             "UPDATE metadata_table SET blockhash = ? WHERE blockhash = ?",
@@ -65,7 +78,24 @@ impl SqliteConnection {
             .expect(SQL_FAIL_MESSAGE);
     }
 
-    pub fn get_metadata(&mut self, bhh: &BlockHeaderHash, contract_hash: &str, key: &str) -> Option<String> {
+    fn delete_metadata_at(&mut self, bhh: &BlockHeaderHash) -> usize {
+        let params = [bhh];
+        self.conn.execute(
+            "DELETE FROM metadata_table WHERE blockhash = ?",
+            &params)
+            .expect(SQL_FAIL_MESSAGE)
+    }
+
+    // Nothing reads moved metadata back; it only exists so a miner's metadata isn't stepped on
+    //   when the mined block is processed.
+    fn delete_moved_metadata(&mut self) -> usize {
+        self.conn.execute(
+            "DELETE FROM metadata_table WHERE length(blockhash) != 64",
+            NO_PARAMS)
+            .expect(SQL_FAIL_MESSAGE)
+    }
+
+    fn get_metadata(&mut self, bhh: &BlockHeaderHash, contract_hash: &str, key: &str) -> Option<String> {
         let key = format!("clr-meta::{}::{}", contract_hash, key);
         let params: [&dyn ToSql; 2] = [&bhh, &key];
 
@@ -77,7 +107,7 @@ impl SqliteConnection {
             .expect(SQL_FAIL_MESSAGE)
     }
 
-    pub fn has_entry(&mut self, key: &str) -> bool {
+    fn has_entry(&mut self, key: &str) -> bool {
         sqlite_has_entry(&self.conn, key)
     }
 
@@ -90,17 +120,17 @@ impl SqliteConnection {
     /// this is a "lower-level" rollback than the roll backs performed in
     ///   ClarityDatabase or AnalysisDatabase -- this is done at the backing store level.
 
-    pub fn begin(&mut self, key: &BlockHeaderHash) {
+    fn begin(&mut self, key: &BlockHeaderHash) {
         self.conn.execute(&format!("SAVEPOINT SP{}", key), NO_PARAMS)
             .expect(SQL_FAIL_MESSAGE);
     }
 
-    pub fn rollback(&mut self, key: &BlockHeaderHash) {
+    fn rollback(&mut self, key: &BlockHeaderHash) {
         self.conn.execute_batch(&format!("ROLLBACK TO SAVEPOINT SP{}; RELEASE SAVEPOINT SP{}", key, key))
             .expect(SQL_FAIL_MESSAGE);
     }
 
-    pub fn commit(&mut self, key: &BlockHeaderHash) {
+    fn commit(&mut self, key: &BlockHeaderHash) {
         self.conn.execute(&format!("RELEASE SAVEPOINT SP{}", key), NO_PARAMS)
             .expect("PANIC: Failed to SQL commit in Smart Contract VM.");
     }
@@ -109,9 +139,9 @@ impl SqliteConnection {
 
 impl SqliteConnection {
     pub fn initialize(filename: &str) -> Result<Self> {
-        let contract_db = Self::inner_open(filename)?;
+        let mut contract_db = Self::inner_open(filename)?;
         contract_db.conn.execute("CREATE TABLE IF NOT EXISTS data_table
-                      (key TEXT PRIMARY KEY, value TEXT)", NO_PARAMS)
+                      (key TEXT PRIMARY KEY, value BLOB)", NO_PARAMS)
             .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
 
         contract_db.conn.execute("CREATE TABLE IF NOT EXISTS metadata_table
@@ -120,6 +150,7 @@ impl SqliteConnection {
             .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
 
         contract_db.check_schema()?;
+        contract_db.migrate_values_to_bytes()?;
 
         Ok(contract_db)
     }
@@ -127,9 +158,10 @@ impl SqliteConnection {
         Self::initialize(":memory:")
     }
     pub fn open(filename: &str) -> Result<Self> {
-        let contract_db = Self::inner_open(filename)?;
+        let mut contract_db = Self::inner_open(filename)?;
 
         contract_db.check_schema()?;
+        contract_db.migrate_values_to_bytes()?;
         Ok(contract_db)
     }
     pub fn check_schema(&self) -> Result<()> {
@@ -143,6 +175,48 @@ impl SqliteConnection {
         Ok(())
     }
 
+    /// Databases created before values were stored as bytes hold each value's text form
+    ///   (see vm::database::structures).  Convert them once, in place, by tagging each text form.
+    ///   The keys are the hashes of the text forms, and so do not change.
+    fn migrate_values_to_bytes(&mut self) -> Result<()> {
+        let version: i64 = self.conn.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        if version >= SIDE_STORE_SCHEMA_VERSION {
+            return Ok(());
+        }
+
+        let tx = self.conn.transaction()
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        let mut num_migrated = 0;
+        {
+            let mut stmt = tx.prepare("SELECT key, value FROM data_table WHERE typeof(value) = 'text'")
+                .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+            let rows = stmt.query_map(NO_PARAMS, |row| {
+                let key: String = row.get(0);
+                let value: String = row.get(1);
+                (key, value)
+            }).map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+
+            for row in rows {
+                let (key, value) = row.map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+                let stored = stored_value_from_text(&value);
+                let params: [&dyn ToSql; 2] = [&stored, &key];
+                tx.execute("UPDATE data_table SET value = ? WHERE key = ?", &params)
+                    .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+                num_migrated += 1;
+            }
+        }
+        tx.execute_batch(&format!("PRAGMA user_version = {}", SIDE_STORE_SCHEMA_VERSION))
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        tx.commit()
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+
+        if num_migrated > 0 {
+            info!("Converted {} Clarity side-store values to bytes", num_migrated);
+        }
+        Ok(())
+    }
+
     pub fn inner_open(filename: &str) -> Result<Self> {
         let conn = Connection::open(filename)
             .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
@@ -166,3 +240,83 @@ fn test_rollback() {
     conn.rollback(&bhh);
     conn.commit(&bhh); // shouldn't be on the stack!
 }
+
+#[cfg(test)]
+#[test]
+fn test_migrate_values_to_bytes() {
+    use chainstate::stacks::index::MARFValue;
+    use vm::database::{ClaritySerializable, ClarityDeserializable, ContractCommitment, MarfedKV, stored_value_text};
+    use vm::database::structures::STORED_TEXT_FORM_TAG;
+    use vm::types::{Value, TypeSignature, BufferLength};
+    use util::hash::to_hex;
+    use std::convert::TryFrom;
+
+    let mut conn = SqliteConnection::inner_open(":memory:").unwrap();
+    conn.conn.execute("CREATE TABLE data_table (key TEXT PRIMARY KEY, value TEXT)", NO_PARAMS).unwrap();
+    conn.conn.execute("CREATE TABLE metadata_table (key TEXT NOT NULL, blockhash TEXT, value TEXT, UNIQUE (key, blockhash))", NO_PARAMS).unwrap();
+
+    let buffer = Value::buff_from(vec![0xab; 600]).unwrap();
+    let texts = vec![
+        Value::UInt(5).serialize(),
+        buffer.serialize(),
+        // a u128, whose text form is also valid hex
+        "1234".to_string(),
+        // a contract commitment, whose text form is hex that decodes to a "binary" value
+        format!("{}{}", "00".repeat(32), "00000005"),
+        "{\"total_supply\":null}".to_string(),
+    ];
+    for text in texts.iter() {
+        sqlite_put(&conn.conn, &MARFValue::from_value(text).to_hex(), text.as_bytes());
+    }
+    conn.conn.execute("UPDATE data_table SET value = CAST(value AS TEXT)", NO_PARAMS).unwrap();
+
+    conn.migrate_values_to_bytes().unwrap();
+    for text in texts.iter() {
+        let key = MARFValue::from_value(text).to_hex();
+        let stored = conn.get(&key).unwrap();
+        assert_eq!(&stored_value_text(&stored), text);
+        assert_eq!(MarfedKV::make_marf_value(&stored).to_hex(), key);
+    }
+
+    // hex-encoded Clarity values are converted to their bytes, which take half the space...
+    for text in texts[0..2].iter() {
+        let key = MARFValue::from_value(text).to_hex();
+        let stored = conn.get(&key).unwrap();
+        assert_eq!(to_hex(&stored), *text);
+        assert!(stored.len() < text.len());
+        assert_eq!(stored.len() * 2, text.len());
+    }
+    let stored_len: i64 = conn.conn.query_row("SELECT SUM(length(value)) FROM data_table WHERE key = ?", &[&MARFValue::from_value(&texts[1]).to_hex()], |row| row.get(0)).unwrap();
+    assert_eq!(stored_len as usize, texts[1].len() / 2);
+
+    // ...and everything else is tagged and keeps its text form, even if it looks like hex
+    for text in texts[2..].iter() {
+        let key = MARFValue::from_value(text).to_hex();
+        let stored = conn.get(&key).unwrap();
+        assert_eq!(stored[0], STORED_TEXT_FORM_TAG);
+        assert_eq!(&stored[1..], text.as_bytes());
+    }
+
+    let key = MARFValue::from_value(&texts[0]).to_hex();
+    assert_eq!(Value::deserialize_from_store(&conn.get(&key).unwrap(), &TypeSignature::UIntType), Value::UInt(5));
+    let key = MARFValue::from_value(&texts[1]).to_hex();
+    let buffer_type = TypeSignature::BufferType(BufferLength::try_from(600u32).unwrap());
+    assert_eq!(Value::deserialize_from_store(&conn.get(&key).unwrap(), &buffer_type), buffer);
+
+    let key = MARFValue::from_value(&texts[3]).to_hex();
+    let commitment = ContractCommitment::deserialize_from_store(&conn.get(&key).unwrap());
+    assert_eq!(commitment.block_height, 5);
+
+    // values stored after the conversion are untagged
+    let value = Value::UInt(6).serialize_to_store();
+    sqlite_put(&conn.conn, &MarfedKV::make_marf_value(&value).to_hex(), &value);
+    assert_eq!(Value::deserialize_from_store(&conn.get(&MarfedKV::make_marf_value(&value).to_hex()).unwrap(), &TypeSignature::UIntType), Value::UInt(6));
+
+    // only done once
+    let version: i64 = conn.conn.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0)).unwrap();
+    assert_eq!(version, SIDE_STORE_SCHEMA_VERSION);
+    conn.conn.execute("UPDATE data_table SET value = CAST(value AS TEXT)", NO_PARAMS).unwrap();
+    conn.migrate_values_to_bytes().unwrap();
+    let num_text: i64 = conn.conn.query_row("SELECT COUNT(*) FROM data_table WHERE typeof(value) = 'text'", NO_PARAMS, |row| row.get(0)).unwrap();
+    assert_eq!(num_text, texts.len() as i64 + 1);
+}
@@ -1,13 +1,82 @@
 use vm::contracts::Contract;
+use util::hash::to_hex;
 use vm::errors::{Error, InterpreterError, RuntimeErrorType, InterpreterResult as Result, IncomparableError};
 use vm::types::{Value, OptionalData, TypeSignature, TupleTypeSignature, PrincipalData, NONE};
 
 pub trait ClaritySerializable {
     fn serialize(&self) -> String;
+    /// the bytes kept in the data store for this value (see `stored_value_text`)
+    fn serialize_to_store(&self) -> Vec<u8> {
+        self.serialize().into_bytes()
+    }
 }
 
 pub trait ClarityDeserializable<T> {
     fn deserialize(json: &str) -> T;
+    fn deserialize_from_store(bytes: &[u8]) -> T {
+        Self::deserialize(&stored_value_text(bytes))
+    }
+}
+
+/// Values are kept in the data store as bytes.  A Clarity value is stored as its consensus
+///   serialization, which always starts with a type prefix byte (below 0x20).  Everything else
+///   is stored as text, which never starts with a control character.
+/// Either way, a stored value has a text form -- the hex encoding of a Clarity value, or the
+///   text itself -- which is what the data store held before values were kept as bytes, and
+///   which is what the MARF commits to.
+/// When a data store that held text forms is converted, a text form that is exactly the hex
+///   encoding of a Clarity value is stored as that value's bytes.  Since the text form of those
+///   bytes is their hex, this is lossless even for text that only happens to look like a Clarity
+///   value (like a contract commitment).  Any other text form is stored as this tag byte followed
+///   by the text.  The tag is neither a Clarity type prefix nor a valid first byte of UTF-8 text.
+pub const STORED_TEXT_FORM_TAG: u8 = 0xff;
+
+pub fn is_binary_stored_value(bytes: &[u8]) -> bool {
+    match bytes.first() {
+        Some(b) => *b < 0x20,
+        None => false
+    }
+}
+
+/// The text form of a converted value, if this is one
+pub fn tagged_text_form(bytes: &[u8]) -> Option<&[u8]> {
+    match bytes.first() {
+        Some(&STORED_TEXT_FORM_TAG) => Some(&bytes[1..]),
+        _ => None
+    }
+}
+
+/// The text form of a stored value
+pub fn stored_value_text(bytes: &[u8]) -> String {
+    try_stored_value_text(bytes)
+        .expect("ERROR: stored value is neither a Clarity value nor UTF-8 text")
+}
+
+/// Like stored_value_text(), but returns None instead of panicking on malformed input
+pub fn try_stored_value_text(bytes: &[u8]) -> Option<String> {
+    if let Some(text) = tagged_text_form(bytes) {
+        String::from_utf8(text.to_vec()).ok()
+    } else if is_binary_stored_value(bytes) {
+        Some(to_hex(bytes))
+    } else {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+/// The stored value for a text form held by a data store from before values were kept as bytes.
+///   This is the inverse of `stored_value_text`.
+pub fn stored_value_from_text(text: &str) -> Vec<u8> {
+    if let Ok(value) = Value::try_deserialize_hex_untyped(text) {
+        let bytes = value.serialize_to_store();
+        if to_hex(&bytes) == text {
+            return bytes;
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(text.len() + 1);
+    bytes.push(STORED_TEXT_FORM_TAG);
+    bytes.extend_from_slice(text.as_bytes());
+    bytes
 }
 
 macro_rules! clarity_serializable {
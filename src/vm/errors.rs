@@ -1,5 +1,6 @@
 use std::fmt;
 use std::error;
+use std::io;
 use vm::ast::errors::ParseError;
 pub use vm::analysis::errors::{CheckErrors};
 pub use vm::analysis::errors::{check_argument_count, check_arguments_at_least};
@@ -41,6 +42,7 @@ pub enum InterpreterError {
     BadFileName,
     FailedToCreateDataDirectory,
     MarfFailure(IncomparableError<MarfError>),
+    SideStoreIOError(IncomparableError<io::Error>),
     FailureConstructingTupleWithType,
     FailureConstructingListWithType,
 }
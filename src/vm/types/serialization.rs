@@ -2,7 +2,7 @@ use vm::errors::{RuntimeErrorType, InterpreterResult, InterpreterError,
                  IncomparableError, Error as ClarityError, CheckErrors};
 use vm::types::{Value, StandardPrincipalData, OptionalData, PrincipalData, BufferLength, MAX_VALUE_SIZE,
                 TypeSignature, TupleData, QualifiedContractIdentifier, ResponseData};
-use vm::database::{ClaritySerializable, ClarityDeserializable};
+use vm::database::{ClaritySerializable, ClarityDeserializable, tagged_text_form};
 use vm::representations::{ClarityName, ContractName, MAX_STRING_LEN};
 
 use std::borrow::Borrow;
@@ -483,14 +483,30 @@ impl Value {
         Value::try_deserialize_hex(hex, expected)
             .expect("ERROR: Failed to parse Clarity hex string")
     }
+
+    /// Deserialize a value as kept in the data store (see `ClaritySerializable::serialize_to_store`).
+    ///   Values converted from a store that held text forms are hex.
+    pub fn deserialize_from_store(bytes: &[u8], expected: &TypeSignature) -> Self {
+        if let Some(text) = tagged_text_form(bytes) {
+            let hex = std::str::from_utf8(text)
+                .expect("ERROR: Failed to parse stored Clarity value");
+            return Value::deserialize(hex, expected);
+        }
+        Value::deserialize_read(&mut &bytes[..], Some(expected))
+            .expect("ERROR: Failed to parse stored Clarity value")
+    }
 }
 
 impl ClaritySerializable for Value {
     fn serialize(&self) -> String {
+        to_hex(self.serialize_to_store().as_slice())
+    }
+
+    fn serialize_to_store(&self) -> Vec<u8> {
         let mut byte_serialization = Vec::new();
         self.serialize_write(&mut byte_serialization)
             .expect("IOError filling byte buffer.");
-        to_hex(byte_serialization.as_slice())
+        byte_serialization
     }
 }
 